@@ -46,6 +46,30 @@ if [[ ! -z ${BTC_RPC_PORT} ]]; then
     START_COMMAND="$START_COMMAND --btcrpcport $BTC_RPC_PORT"
 fi
 
+# Set the Bitcoin ZMQ block notifications endpoint
+if [[ ! -z ${BTC_ZMQ_BLOCK} ]]; then
+    START_COMMAND="$START_COMMAND --btczmqblock $BTC_ZMQ_BLOCK"
+fi
+
+# Set how long to wait for bitcoind to answer, and how many times to retry if it does not
+if [[ ! -z ${BTC_RPC_TIMEOUT} ]]; then
+    START_COMMAND="$START_COMMAND --btcrpctimeout $BTC_RPC_TIMEOUT"
+fi
+
+if [[ ! -z ${BTC_RPC_RETRIES} ]]; then
+    START_COMMAND="$START_COMMAND --btcrpcretries $BTC_RPC_RETRIES"
+fi
+
+# Set the API penalties are broadcast through while bitcoind is unreachable
+if [[ ! -z ${BTC_BROADCAST_FALLBACK} ]]; then
+    START_COMMAND="$START_COMMAND --btcbroadcastfallback $BTC_BROADCAST_FALLBACK"
+fi
+
+# Set the time between polls for new blocks
+if [[ ! -z ${POLLING_DELTA} ]]; then
+    START_COMMAND="$START_COMMAND --pollingdelta $POLLING_DELTA"
+fi
+
 if [ "${DEBUG}" == "true" ]; then
     START_COMMAND="$START_COMMAND --debug"
 fi
@@ -62,5 +86,25 @@ if [ "${FORCE_UPDATE}" == "true" ]; then
     START_COMMAND="$START_COMMAND --forceupdate"
 fi
 
+if [ "${ANCHOR_CPFP}" == "true" ]; then
+    START_COMMAND="$START_COMMAND --anchorcpfp"
+fi
+
+if [ "${MAINNET_PENALTY_TRIGGERS}" == "true" ]; then
+    START_COMMAND="$START_COMMAND --mainnetpenaltytriggers"
+fi
+
+if [ "${DECLINE_LAPSED_PENALTIES}" == "true" ]; then
+    START_COMMAND="$START_COMMAND --declinelapsedpenalties"
+fi
+
+if [ "${LOCATOR_FILTER}" == "true" ]; then
+    START_COMMAND="$START_COMMAND --locatorfilter"
+fi
+
+if [ "${REFUND_DELETED_APPOINTMENTS}" == "true" ]; then
+    START_COMMAND="$START_COMMAND --refunddeletedappointments"
+fi
+
 # Start the TEOS daemon
 $START_COMMAND
@@ -5,6 +5,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .field_attribute("AppointmentData.appointment_data", "#[serde(flatten)]")
         .field_attribute("appointment_data", "#[serde(rename = \"appointment\")]")
         .field_attribute("user_id", "#[serde(with = \"hex::serde\")]")
+        .field_attribute("new_user_id", "#[serde(with = \"hex::serde\")]")
+        .field_attribute("old_user_id", "#[serde(with = \"hex::serde\")]")
+        .field_attribute(
+            "RegisterRequest.payment_preimage",
+            "#[serde(with = \"hex::serde\", default, skip_serializing_if = \"Vec::is_empty\")]",
+        )
+        .field_attribute(
+            "RegisterRequest.signature_versions",
+            "#[serde(default, skip_serializing_if = \"Vec::is_empty\")]",
+        )
+        .field_attribute("RegisterResponse.signature_version", "#[serde(default)]")
         .field_attribute("locator", "#[serde(with = \"hex::serde\")]")
         .field_attribute(
             "locators",
@@ -14,6 +25,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .field_attribute("dispute_txid", "#[serde(with = \"crate::ser::serde_be\")]")
         .field_attribute("penalty_txid", "#[serde(with = \"crate::ser::serde_be\")]")
         .field_attribute("penalty_rawtx", "#[serde(with = \"hex::serde\")]")
+        .field_attribute(
+            "AddAppointmentRequest.ttl",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
         .field_attribute(
             "GetAppointmentResponse.status",
             "#[serde(with = \"crate::ser::serde_status\")]",
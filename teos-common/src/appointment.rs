@@ -9,6 +9,10 @@ use bitcoin::Txid;
 use crate::protos as msgs;
 
 pub const LOCATOR_LEN: usize = 16;
+/// Domain separation tag prepended to appointments signed using [SignatureVersion::V2].
+pub const SIGNATURE_V2_TAG: &[u8] = b"teos:appointment:v2";
+/// Domain separation tag prepended to appointments signed using [SignatureVersion::V3].
+pub const SIGNATURE_V3_TAG: &[u8] = b"teos:appointment:v3";
 
 /// User identifier for appointments.
 #[derive(Debug, Eq, PartialEq, Copy, Clone, Hash, Serialize, Deserialize)]
@@ -69,6 +73,78 @@ pub struct Appointment {
     pub to_self_delay: u32,
 }
 
+/// Version of the serialization appointments are signed over.
+///
+/// Users and towers agree on a version when registering, so the appointment serialization can evolve without
+/// signatures made over an older format silently failing to verify.
+#[derive(Debug, Default, Eq, PartialEq, Copy, Clone, Hash, Serialize, Deserialize)]
+#[serde(into = "u32", try_from = "u32")]
+pub enum SignatureVersion {
+    /// The bare appointment serialization (see [Appointment::to_vec]).
+    #[default]
+    V1 = 1,
+    /// The appointment serialization prefixed by [SIGNATURE_V2_TAG].
+    V2 = 2,
+    /// The appointment serialization, followed by the reward the tower is entitled to if the appointment is triggered,
+    /// prefixed by [SIGNATURE_V3_TAG].
+    V3 = 3,
+}
+
+impl SignatureVersion {
+    /// Signature versions supported by this implementation, from oldest to newest.
+    pub const SUPPORTED: [SignatureVersion; 3] = [
+        SignatureVersion::V1,
+        SignatureVersion::V2,
+        SignatureVersion::V3,
+    ];
+
+    /// Gets the wire representation of all the supported versions.
+    pub fn supported() -> Vec<u32> {
+        Self::SUPPORTED.iter().map(|v| u32::from(*v)).collect()
+    }
+
+    /// Picks the newest version supported by both ends.
+    ///
+    /// Peers that do not advertise any known version (e.g. legacy peers) fall back to [SignatureVersion::V1].
+    pub fn negotiate(peer_versions: &[u32]) -> Self {
+        Self::SUPPORTED
+            .into_iter()
+            .rev()
+            .find(|v| peer_versions.contains(&u32::from(*v)))
+            .unwrap_or_default()
+    }
+
+    /// Whether signatures following this version commit to the reward of the appointment.
+    pub fn commits_to_reward(&self) -> bool {
+        matches!(self, SignatureVersion::V3)
+    }
+}
+
+impl From<SignatureVersion> for u32 {
+    fn from(v: SignatureVersion) -> Self {
+        v as u32
+    }
+}
+
+impl TryFrom<u32> for SignatureVersion {
+    type Error = String;
+
+    fn try_from(x: u32) -> Result<Self, Self::Error> {
+        match x {
+            1 => Ok(SignatureVersion::V1),
+            2 => Ok(SignatureVersion::V2),
+            3 => Ok(SignatureVersion::V3),
+            _ => Err(format!("Unknown signature version: {x}")),
+        }
+    }
+}
+
+impl fmt::Display for SignatureVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}", u32::from(*self))
+    }
+}
+
 /// Represents all the possible states of an appointment in the tower, or in a response to a client request.
 #[derive(Serialize, Deserialize, Debug)]
 pub enum AppointmentStatus {
@@ -133,6 +209,37 @@ impl Appointment {
         result.extend(self.to_self_delay.to_be_bytes().to_vec());
         result
     }
+
+    /// Serializes an appointment with no reward to be signed following the given [SignatureVersion].
+    pub fn to_signable_vec(&self, version: SignatureVersion) -> Vec<u8> {
+        self.to_signable_vec_with_reward(version, None)
+    }
+
+    /// Serializes an appointment, alongside the reward (in sats) the tower is entitled to if it is triggered, to be
+    /// signed following the given [SignatureVersion].
+    ///
+    /// Only versions that [commit to the reward](SignatureVersion::commits_to_reward) serialize it (as a big endian
+    /// `u64`, zero meaning no reward), so it is ignored otherwise.
+    pub fn to_signable_vec_with_reward(
+        &self,
+        version: SignatureVersion,
+        reward: Option<u64>,
+    ) -> Vec<u8> {
+        match version {
+            SignatureVersion::V1 => self.to_vec(),
+            SignatureVersion::V2 => {
+                let mut result = SIGNATURE_V2_TAG.to_vec();
+                result.extend(self.to_vec());
+                result
+            }
+            SignatureVersion::V3 => {
+                let mut result = SIGNATURE_V3_TAG.to_vec();
+                result.extend(self.to_vec());
+                result.extend(reward.unwrap_or(0).to_be_bytes());
+                result
+            }
+        }
+    }
 }
 
 impl From<Appointment> for msgs::Appointment {
@@ -151,3 +258,61 @@ impl From<Appointment> for msgs::Appointment {
 pub fn compute_appointment_slots(blob_size: usize, blob_max_size: usize) -> u32 {
     (blob_size as f32 / blob_max_size as f32).ceil() as u32
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature_version_negotiate() {
+        // Legacy peers do not advertise any version
+        assert_eq!(SignatureVersion::negotiate(&[]), SignatureVersion::V1);
+        assert_eq!(SignatureVersion::negotiate(&[1]), SignatureVersion::V1);
+        // The newest common version is picked, unknown versions are ignored
+        assert_eq!(SignatureVersion::negotiate(&[1, 2]), SignatureVersion::V2);
+        assert_eq!(
+            SignatureVersion::negotiate(&[2, 1, 7]),
+            SignatureVersion::V2
+        );
+        assert_eq!(
+            SignatureVersion::negotiate(&SignatureVersion::supported()),
+            SignatureVersion::V3
+        );
+        assert_eq!(SignatureVersion::negotiate(&[7]), SignatureVersion::V1);
+    }
+
+    #[test]
+    fn test_to_signable_vec() {
+        let appointment = Appointment::new(Locator([1; LOCATOR_LEN]), vec![2; 32], 42);
+
+        assert_eq!(
+            appointment.to_signable_vec(SignatureVersion::V1),
+            appointment.to_vec()
+        );
+        assert_eq!(
+            appointment.to_signable_vec(SignatureVersion::V2),
+            [SIGNATURE_V2_TAG, &appointment.to_vec()].concat()
+        );
+        assert_eq!(
+            appointment.to_signable_vec(SignatureVersion::V3),
+            [SIGNATURE_V3_TAG, &appointment.to_vec(), &[0; 8]].concat()
+        );
+
+        // The reward is only committed to from V3 onwards
+        for version in [SignatureVersion::V1, SignatureVersion::V2] {
+            assert_eq!(
+                appointment.to_signable_vec_with_reward(version, Some(1000)),
+                appointment.to_signable_vec(version)
+            );
+        }
+        assert_eq!(
+            appointment.to_signable_vec_with_reward(SignatureVersion::V3, Some(1000)),
+            [
+                SIGNATURE_V3_TAG,
+                &appointment.to_vec(),
+                &1000u64.to_be_bytes()
+            ]
+            .concat()
+        );
+    }
+}
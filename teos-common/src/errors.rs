@@ -16,6 +16,15 @@ pub const APPOINTMENT_NOT_FOUND: u8 = 36;
 
 /// Registration errors [65, 96]
 pub const REGISTRATION_RESOURCE_EXHAUSTED: u8 = 65;
+pub const REGISTRATION_RENEWAL_TOO_EARLY: u8 = 66;
+pub const REGISTRATION_EXPIRY_TOO_FAR: u8 = 67;
+pub const REGISTRATION_PAYMENT_REQUIRED: u8 = 68;
+pub const REGISTRATION_INVALID_PAYMENT: u8 = 69;
+pub const REGISTRATION_USER_NOT_ALLOWED: u8 = 70;
+
+/// Subscription transfer errors [97, 128]
+pub const TRANSFER_USER_ALREADY_REGISTERED: u8 = 97;
+pub const TRANSFER_ALREADY_USED: u8 = 98;
 
 /// UNHANDLED
 pub const UNEXPECTED_ERROR: u8 = 255;
@@ -26,6 +26,8 @@ use serde_json::json;
 
 use bitcoin::secp256k1::{Error, PublicKey};
 
+use appointment::Locator;
+
 pub const USER_ID_LEN: usize = 33;
 pub use UserId as TowerId;
 
@@ -99,6 +101,33 @@ impl TryFrom<serde_json::Value> for UserId {
     }
 }
 
+/// Builds the message a user has to sign in order to have the appointment identified by `locator`, accepted by the
+/// tower at `start_block`, deleted by the tower identified by `tower_id`. Binding both means the request cannot be
+/// replayed against a different tower, nor against an appointment sent later on under the same locator.
+pub fn delete_appointment_message(
+    tower_id: TowerId,
+    locator: Locator,
+    start_block: u32,
+) -> Vec<u8> {
+    format!("delete appointment {locator} start_block {start_block} tower {tower_id}").into_bytes()
+}
+
+/// Builds the message a user has to sign, with both their current and their new key, in order to transfer their
+/// subscription from `old_user_id` to `new_user_id` within the tower identified by `tower_id`. The `nonce` makes every
+/// transfer request unique, so it can only be used once, while binding the tower means it cannot be replayed against
+/// a different one.
+pub fn transfer_subscription_message(
+    tower_id: TowerId,
+    old_user_id: UserId,
+    new_user_id: UserId,
+    nonce: u64,
+) -> Vec<u8> {
+    format!(
+        "transfer subscription from {old_user_id} to {new_user_id} nonce {nonce} tower {tower_id}"
+    )
+    .into_bytes()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
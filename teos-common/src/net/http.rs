@@ -1,8 +1,18 @@
+/// Content type of the compact binary (protobuf) encoding of `add_appointment` requests and responses.
+///
+/// JSON is used unless requests are sent with this `Content-Type`, and responses are only encoded this way if it is
+/// the `Accept`ed one. Errors are always sent as JSON.
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
+
 pub enum Endpoint {
     Register,
     AddAppointment,
     GetAppointment,
+    DeleteAppointment,
     GetSubscriptionInfo,
+    TransferSubscription,
+    GetTowerInfo,
+    GetFeeEstimate,
     Ping,
 }
 
@@ -15,7 +25,11 @@ impl std::fmt::Display for Endpoint {
                 Endpoint::Register => "register",
                 Endpoint::AddAppointment => "add_appointment",
                 Endpoint::GetAppointment => "get_appointment",
+                Endpoint::DeleteAppointment => "delete_appointment",
                 Endpoint::GetSubscriptionInfo => "get_subscription_info",
+                Endpoint::TransferSubscription => "transfer_subscription",
+                Endpoint::GetTowerInfo => "get_tower_info",
+                Endpoint::GetFeeEstimate => "get_fee_estimate",
                 Endpoint::Ping => "ping",
             }
         )
@@ -165,3 +165,64 @@ impl AppointmentReceipt {
         }
     }
 }
+
+/// Proof that a tower deleted an appointment at the request of its owner.
+///
+/// The receipt commits to the signature of the user over the deletion request, which in turn commits to the appointment
+/// locator, and to the block height at which the appointment was deleted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeletionReceipt {
+    user_signature: String,
+    deletion_block: u32,
+    signature: Option<String>,
+}
+
+impl DeletionReceipt {
+    pub fn new(user_signature: String, deletion_block: u32) -> Self {
+        DeletionReceipt {
+            user_signature,
+            deletion_block,
+            signature: None,
+        }
+    }
+
+    pub fn with_signature(user_signature: String, deletion_block: u32, signature: String) -> Self {
+        DeletionReceipt {
+            user_signature,
+            deletion_block,
+            signature: Some(signature),
+        }
+    }
+
+    pub fn user_signature(&self) -> &str {
+        &self.user_signature
+    }
+
+    pub fn deletion_block(&self) -> u32 {
+        self.deletion_block
+    }
+
+    pub fn signature(&self) -> Option<String> {
+        self.signature.clone()
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut ser = Vec::new();
+        ser.extend_from_slice(self.user_signature.as_bytes());
+        ser.extend_from_slice(&self.deletion_block.to_be_bytes());
+
+        ser
+    }
+
+    pub fn sign(&mut self, sk: &SecretKey) {
+        self.signature = Some(cryptography::sign(&self.to_vec(), sk).unwrap());
+    }
+
+    pub fn verify(&self, id: &UserId) -> bool {
+        if let Some(signature) = self.signature() {
+            cryptography::verify(&self.to_vec(), &signature, &id.0)
+        } else {
+            false
+        }
+    }
+}
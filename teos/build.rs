@@ -12,6 +12,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "GetUserResponse.appointments",
             "#[serde(serialize_with = \"teos_common::ser::serde_vec_bytes::serialize\")]",
         )
+        .field_attribute(
+            "RebroadcastAllResponse.rebroadcast",
+            "#[serde(serialize_with = \"teos_common::ser::serde_vec_bytes::serialize\")]",
+        )
+        .field_attribute(
+            "RebroadcastAllResponse.rejected",
+            "#[serde(serialize_with = \"teos_common::ser::serde_vec_bytes::serialize\")]",
+        )
+        .field_attribute("PenaltyRecord.uuid", "#[serde(with = \"hex::serde\")]")
+        .field_attribute(
+            "PenaltyRecord.dispute_txid",
+            "#[serde(with = \"teos_common::ser::serde_be\")]",
+        )
+        .field_attribute(
+            "PenaltyRecord.penalty_txid",
+            "#[serde(with = \"teos_common::ser::serde_be\")]",
+        )
+        .field_attribute(
+            "FeeBump.txid",
+            "#[serde(with = \"teos_common::ser::serde_be\")]",
+        )
         .field_attribute(
             "NetworkAddress.address_type",
             "#[serde(rename = \"type\", with = \"crate::api::serde::serde_address_type\")]",
@@ -0,0 +1,241 @@
+//! Logic related to fee-bumping penalty transactions via CPFP (child pays for parent) on their anchor outputs.
+//!
+//! A penalty transaction is considered to have an anchor if one of its outputs pays (P2WPKH) to the key held by the
+//! [AnchorMaterial]. When such a penalty gets stuck in the mempool, the [Responder](crate::responder::Responder) can
+//! spend that output in a child transaction that pays enough fees to bring the whole package to a target feerate.
+
+use bitcoin::blockdata::script::Script;
+use bitcoin::secp256k1::{Message, PublicKey, Secp256k1, SecretKey};
+use bitcoin::util::sighash::SighashCache;
+use bitcoin::{EcdsaSighashType, OutPoint, Transaction, TxIn, TxOut, Witness};
+
+/// Virtual size (in vbytes) of an anchor spending child (1 P2WPKH input, 1 P2WPKH output).
+pub const ANCHOR_CHILD_VSIZE: u64 = 110;
+/// Dust limit for P2WPKH outputs. The child change output will never go below this.
+pub const P2WPKH_DUST_LIMIT: u64 = 294;
+
+/// Returns the virtual size (in vbytes) of a given transaction.
+pub(crate) fn vsize(tx: &Transaction) -> u64 {
+    (tx.weight() as u64).div_ceil(4)
+}
+
+/// Key material used by the tower to spend the anchor outputs of penalty transactions.
+#[derive(Debug, Clone)]
+pub struct AnchorMaterial {
+    /// The key used to sign anchor spends.
+    sk: SecretKey,
+    /// The public counterpart of `sk`.
+    pk: bitcoin::PublicKey,
+    /// The P2WPKH script anchor outputs are expected to pay to.
+    script_pubkey: Script,
+}
+
+impl AnchorMaterial {
+    /// Creates a new [AnchorMaterial] instance from a given secret key.
+    pub fn new(sk: SecretKey) -> Self {
+        let pk = bitcoin::PublicKey::new(PublicKey::from_secret_key(&Secp256k1::new(), &sk));
+        let script_pubkey = Script::new_v0_p2wpkh(&pk.wpubkey_hash().unwrap());
+
+        AnchorMaterial {
+            sk,
+            pk,
+            script_pubkey,
+        }
+    }
+
+    /// The script anchor outputs are expected to pay to.
+    pub fn script_pubkey(&self) -> &Script {
+        &self.script_pubkey
+    }
+
+    /// Finds the anchor output of a given transaction, if any.
+    ///
+    /// Returns the anchor outpoint alongside its value.
+    pub fn find_anchor(&self, tx: &Transaction) -> Option<(OutPoint, u64)> {
+        tx.output
+            .iter()
+            .enumerate()
+            .find(|(_, o)| o.script_pubkey == self.script_pubkey)
+            .map(|(vout, o)| (OutPoint::new(tx.txid(), vout as u32), o.value))
+    }
+
+    /// Builds a child transaction spending the anchor of `parent` so the package feerate gets to `target_feerate` (sat/vB).
+    ///
+    /// `parent_fee` is the fee (in sats) paid by `parent`. The child fee is bounded by the anchor value, so the child may
+    /// only partially bump the package if the anchor is not big enough.
+    ///
+    /// Returns [None] if `parent` has no anchor or if the child would not raise the package feerate.
+    pub fn build_cpfp(
+        &self,
+        parent: &Transaction,
+        parent_fee: u64,
+        target_feerate: u64,
+    ) -> Option<Transaction> {
+        let (outpoint, anchor_value) = self.find_anchor(parent)?;
+        let parent_vsize = vsize(parent);
+
+        let required_fee = target_feerate * (parent_vsize + ANCHOR_CHILD_VSIZE);
+        let child_fee = required_fee
+            .saturating_sub(parent_fee)
+            .min(anchor_value.saturating_sub(P2WPKH_DUST_LIMIT));
+
+        // The package feerate only goes up if the child pays a higher feerate than its parent.
+        if child_fee * parent_vsize <= parent_fee * ANCHOR_CHILD_VSIZE {
+            return None;
+        }
+
+        let mut child = Transaction {
+            version: 2,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: outpoint,
+                script_sig: Script::new(),
+                sequence: 0xFFFFFFFD,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: anchor_value - child_fee,
+                script_pubkey: self.script_pubkey.clone(),
+            }],
+        };
+
+        let sighash = SighashCache::new(&child)
+            .segwit_signature_hash(
+                0,
+                &Script::new_p2pkh(&self.pk.pubkey_hash()),
+                anchor_value,
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let sig = Secp256k1::signing_only()
+            .sign_ecdsa(&Message::from_slice(&sighash[..]).unwrap(), &self.sk);
+
+        let mut ser_sig = sig.serialize_der().to_vec();
+        ser_sig.push(EcdsaSighashType::All as u8);
+        child.input[0].witness.push(ser_sig);
+        child.input[0].witness.push(self.pk.to_bytes());
+
+        Some(child)
+    }
+}
+
+/// Computes the fee paid by a penalty transaction given the dispute transaction it spends from.
+///
+/// Inputs not spending from `dispute_tx` cannot be valued, so the fee will be underestimated if there are any.
+pub(crate) fn penalty_fee(dispute_tx: &Transaction, penalty_tx: &Transaction) -> u64 {
+    let dispute_txid = dispute_tx.txid();
+    let input_value: u64 = penalty_tx
+        .input
+        .iter()
+        .filter(|i| i.previous_output.txid == dispute_txid)
+        .filter_map(|i| dispute_tx.output.get(i.previous_output.vout as usize))
+        .map(|o| o.value)
+        .sum();
+    let output_value: u64 = penalty_tx.output.iter().map(|o| o.value).sum();
+
+    input_value.saturating_sub(output_value)
+}
+
+/// Computes the feerate (sat/vB) of a package given its total fee and its transactions.
+pub(crate) fn package_feerate(fee: u64, txs: &[&Transaction]) -> f64 {
+    fee as f64 / txs.iter().map(|tx| vsize(tx)).sum::<u64>() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{get_anchor_penalty, get_random_tx};
+    use bitcoin::secp256k1::ecdsa::Signature;
+    use teos_common::cryptography::get_random_keypair;
+
+    #[test]
+    fn test_find_anchor() {
+        let anchor = AnchorMaterial::new(get_random_keypair().0);
+        let (_, penalty_tx) = get_anchor_penalty(&anchor, 100, 10_000);
+
+        assert_eq!(
+            anchor.find_anchor(&penalty_tx),
+            Some((OutPoint::new(penalty_tx.txid(), 1), 10_000))
+        );
+        assert_eq!(anchor.find_anchor(&get_random_tx()), None);
+    }
+
+    #[test]
+    fn test_penalty_fee() {
+        let anchor = AnchorMaterial::new(get_random_keypair().0);
+        let (dispute_tx, penalty_tx) = get_anchor_penalty(&anchor, 150, 10_000);
+        assert_eq!(penalty_fee(&dispute_tx, &penalty_tx), 150);
+
+        // Inputs that do not come from the dispute transaction cannot be valued.
+        assert_eq!(penalty_fee(&get_random_tx(), &penalty_tx), 0);
+    }
+
+    #[test]
+    fn test_build_cpfp() {
+        let anchor = AnchorMaterial::new(get_random_keypair().0);
+        let parent_fee = 100;
+        let (dispute_tx, penalty_tx) = get_anchor_penalty(&anchor, parent_fee, 10_000);
+        let target_feerate = 20;
+
+        let child = anchor
+            .build_cpfp(&penalty_tx, parent_fee, target_feerate)
+            .unwrap();
+        let child_fee = 10_000 - child.output[0].value;
+
+        // The child spends the anchor and pays back to the anchor key.
+        assert_eq!(
+            child.input[0].previous_output,
+            OutPoint::new(penalty_tx.txid(), 1)
+        );
+        assert_eq!(child.output[0].script_pubkey, *anchor.script_pubkey());
+
+        // The package feerate is raised up to the target.
+        let parent_feerate = package_feerate(penalty_fee(&dispute_tx, &penalty_tx), &[&penalty_tx]);
+        let package_feerate = package_feerate(parent_fee + child_fee, &[&penalty_tx, &child]);
+        assert!(package_feerate > parent_feerate);
+        assert!(package_feerate >= target_feerate as f64);
+
+        // The witness signature is valid for the anchor key.
+        let witness = child.input[0].witness.to_vec();
+        let sighash = SighashCache::new(&child)
+            .segwit_signature_hash(
+                0,
+                &Script::new_p2pkh(&anchor.pk.pubkey_hash()),
+                10_000,
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let sig = Signature::from_der(&witness[0][..witness[0].len() - 1]).unwrap();
+        assert!(Secp256k1::verification_only()
+            .verify_ecdsa(
+                &Message::from_slice(&sighash[..]).unwrap(),
+                &sig,
+                &anchor.pk.inner
+            )
+            .is_ok());
+        assert_eq!(witness[1], anchor.pk.to_bytes());
+    }
+
+    #[test]
+    fn test_build_cpfp_bounded_by_anchor() {
+        let anchor = AnchorMaterial::new(get_random_keypair().0);
+        let anchor_value = 2_000;
+        let (_, penalty_tx) = get_anchor_penalty(&anchor, 100, anchor_value);
+
+        // The child cannot spend more than the anchor (minus dust).
+        let child = anchor.build_cpfp(&penalty_tx, 100, 1_000).unwrap();
+        assert_eq!(child.output[0].value, P2WPKH_DUST_LIMIT);
+    }
+
+    #[test]
+    fn test_build_cpfp_no_bump() {
+        let anchor = AnchorMaterial::new(get_random_keypair().0);
+        let (_, penalty_tx) = get_anchor_penalty(&anchor, 10_000, 10_000);
+
+        // The parent already pays above the target.
+        assert!(anchor.build_cpfp(&penalty_tx, 10_000, 1).is_none());
+        // No anchor, no child.
+        assert!(anchor.build_cpfp(&get_random_tx(), 0, 20).is_none());
+    }
+}
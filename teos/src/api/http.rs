@@ -1,3 +1,4 @@
+use prost::Message;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
 use std::error::Error;
@@ -5,21 +6,26 @@ use std::net::SocketAddr;
 use tokio::time::Duration;
 use tonic::transport::Channel;
 use triggered::{Listener, Trigger};
+use warp::hyper::body::Bytes;
 use warp::{http::StatusCode, reject, reply, Filter, Rejection, Reply};
 
 use teos_common::appointment::LOCATOR_LEN;
-use teos_common::net::http::Endpoint;
+use teos_common::net::http::{Endpoint, PROTOBUF_CONTENT_TYPE};
 use teos_common::protos as common_msgs;
 use teos_common::{errors, USER_ID_LEN};
 
+use crate::api::internal::ERROR_CODE_KEY;
 use crate::protos::public_tower_services_client::PublicTowerServicesClient;
 
 // TODO: Limit the body length for /add_appointment should not be needed, since slots are consumed proportionally to it.
 // Setting a limit for now just to prevent spam to some extend, but this is likely to be lifted.
-const REGISTER_BODY_LEN: u64 = 87;
+const REGISTER_BODY_LEN: u64 = 200;
 const ADD_APPOINTMENT_BODY_LEN: u64 = 2048;
 const GET_APPOINTMENT_BODY_LEN: u64 = 178;
+const DELETE_APPOINTMENT_BODY_LEN: u64 = 204;
 const GET_SUBSCRIPTION_INFO_BODY_LEN: u64 = 127;
+const TRANSFER_SUBSCRIPTION_BODY_LEN: u64 = 500;
+const GET_FEE_ESTIMATE_BODY_LEN: u64 = 32;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub(crate) struct ApiError {
@@ -64,7 +70,40 @@ fn with_grpc(
     warp::any().map(move || grpc_endpoint.clone())
 }
 
+/// Extracts the body of an `add_appointment` request, which can be either JSON or compact binary (protobuf) encoded,
+/// depending on its `Content-Type`.
+fn with_add_appointment_body(
+) -> impl Filter<Extract = (common_msgs::AddAppointmentRequest,), Error = Rejection> + Clone {
+    let protobuf = warp::header::exact_ignore_case("content-type", PROTOBUF_CONTENT_TYPE)
+        .and(warp::body::bytes())
+        .and_then(|body: Bytes| async move {
+            common_msgs::AddAppointmentRequest::decode(body).map_err(|e| {
+                reject::custom(ApiError::new(
+                    format!("Invalid protobuf body: {e}"),
+                    errors::INVALID_REQUEST_FORMAT,
+                ))
+            })
+        });
+
+    warp::body::content_length_limit(ADD_APPOINTMENT_BODY_LEN)
+        .and(protobuf.or(warp::body::json()).unify())
+}
+
 fn match_status(s: &tonic::Status) -> (StatusCode, u8) {
+    // Errors with no gRPC counterpart carry their own error code
+    if let Some(error_code) = s
+        .metadata()
+        .get(ERROR_CODE_KEY)
+        .and_then(|v| v.to_str().ok()?.parse().ok())
+    {
+        let status_code = match error_code {
+            errors::REGISTRATION_PAYMENT_REQUIRED => StatusCode::PAYMENT_REQUIRED,
+            errors::REGISTRATION_USER_NOT_ALLOWED => StatusCode::FORBIDDEN,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        return (status_code, error_code);
+    }
+
     let mut status_code = StatusCode::BAD_REQUEST;
     let error_code = match s.code() {
         tonic::Code::InvalidArgument => errors::WRONG_FIELD_FORMAT,
@@ -141,6 +180,7 @@ async fn register(
 
 async fn add_appointment(
     req: common_msgs::AddAppointmentRequest,
+    accept: Option<String>,
     addr: Option<std::net::SocketAddr>,
     mut grpc_conn: PublicTowerServicesClient<Channel>,
 ) -> std::result::Result<impl Reply, Rejection> {
@@ -167,8 +207,23 @@ async fn add_appointment(
         return Err(ApiError::empty_field("signature"));
     }
 
-    let (body, status) = parse_grpc_response(grpc_conn.add_appointment(req).await);
-    Ok(reply::with_status(body, status))
+    // Only successful responses are binary encoded, errors are always sent as JSON
+    let accepts_protobuf = accept.is_some_and(|accept| accept.contains(PROTOBUF_CONTENT_TYPE));
+    match grpc_conn.add_appointment(req).await {
+        Ok(r) if accepts_protobuf => {
+            log::debug!("Request succeeded");
+            Ok(reply::with_header(
+                r.into_inner().encode_to_vec(),
+                "content-type",
+                PROTOBUF_CONTENT_TYPE,
+            )
+            .into_response())
+        }
+        result => {
+            let (body, status) = parse_grpc_response(result);
+            Ok(reply::with_status(body, status).into_response())
+        }
+    }
 }
 
 async fn get_appointment(
@@ -199,6 +254,34 @@ async fn get_appointment(
     Ok(reply::with_status(body, status))
 }
 
+async fn delete_appointment(
+    req: common_msgs::DeleteAppointmentRequest,
+    addr: Option<std::net::SocketAddr>,
+    mut grpc_conn: PublicTowerServicesClient<Channel>,
+) -> std::result::Result<impl Reply, Rejection> {
+    log::debug!(
+        "Received a delete_appointment request from {}",
+        addr.map_or("an unknown address".to_owned(), |a| a.to_string())
+    );
+
+    if req.locator.is_empty() {
+        return Err(ApiError::empty_field("locator"));
+    }
+    if req.locator.len() != LOCATOR_LEN {
+        return Err(ApiError::wrong_field_length(
+            "locator",
+            req.locator.len(),
+            LOCATOR_LEN,
+        ));
+    }
+    if req.signature.is_empty() {
+        return Err(ApiError::empty_field("signature"));
+    }
+
+    let (body, status) = parse_grpc_response(grpc_conn.delete_appointment(req).await);
+    Ok(reply::with_status(body, status))
+}
+
 async fn get_subscription_info(
     req: common_msgs::GetSubscriptionInfoRequest,
     addr: Option<std::net::SocketAddr>,
@@ -217,6 +300,61 @@ async fn get_subscription_info(
     Ok(reply::with_status(body, status))
 }
 
+async fn transfer_subscription(
+    req: common_msgs::TransferSubscriptionRequest,
+    addr: Option<std::net::SocketAddr>,
+    mut grpc_conn: PublicTowerServicesClient<Channel>,
+) -> std::result::Result<impl Reply, Rejection> {
+    log::debug!(
+        "Received a transfer_subscription request from {}",
+        addr.map_or("an unknown address".to_owned(), |a| a.to_string())
+    );
+
+    if req.old_user_id.is_empty() {
+        return Err(ApiError::empty_field("old_user_id"));
+    }
+    if req.old_user_id.len() != USER_ID_LEN {
+        return Err(ApiError::wrong_field_length(
+            "old_user_id",
+            req.old_user_id.len(),
+            USER_ID_LEN,
+        ));
+    }
+    if req.new_user_id.is_empty() {
+        return Err(ApiError::empty_field("new_user_id"));
+    }
+    if req.new_user_id.len() != USER_ID_LEN {
+        return Err(ApiError::wrong_field_length(
+            "new_user_id",
+            req.new_user_id.len(),
+            USER_ID_LEN,
+        ));
+    }
+    if req.old_user_signature.is_empty() {
+        return Err(ApiError::empty_field("old_user_signature"));
+    }
+    if req.new_user_signature.is_empty() {
+        return Err(ApiError::empty_field("new_user_signature"));
+    }
+
+    let (body, status) = parse_grpc_response(grpc_conn.transfer_subscription(req).await);
+    Ok(reply::with_status(body, status))
+}
+
+async fn get_fee_estimate(
+    req: common_msgs::GetFeeEstimateRequest,
+    addr: Option<std::net::SocketAddr>,
+    mut grpc_conn: PublicTowerServicesClient<Channel>,
+) -> std::result::Result<impl Reply, Rejection> {
+    log::debug!(
+        "Received a get_fee_estimate request from {}",
+        addr.map_or("an unknown address".to_owned(), |a| a.to_string())
+    );
+
+    let (body, status) = parse_grpc_response(grpc_conn.get_fee_estimate(req).await);
+    Ok(reply::with_status(body, status))
+}
+
 async fn ping(addr: Option<SocketAddr>) -> Result<impl Reply, Rejection> {
     log::debug!(
         "Received a ping request from {}",
@@ -237,7 +375,8 @@ fn router(
 
     let add_appointment = warp::post()
         .and(warp::path(Endpoint::AddAppointment.to_string()))
-        .and(warp::body::content_length_limit(ADD_APPOINTMENT_BODY_LEN).and(warp::body::json()))
+        .and(with_add_appointment_body())
+        .and(warp::header::optional::<String>("accept"))
         .and(warp::addr::remote())
         .and(with_grpc(grpc_conn.clone()))
         .and_then(add_appointment);
@@ -249,6 +388,13 @@ fn router(
         .and(with_grpc(grpc_conn.clone()))
         .and_then(get_appointment);
 
+    let delete_appointment = warp::post()
+        .and(warp::path(Endpoint::DeleteAppointment.to_string()))
+        .and(warp::body::content_length_limit(DELETE_APPOINTMENT_BODY_LEN).and(warp::body::json()))
+        .and(warp::addr::remote())
+        .and(with_grpc(grpc_conn.clone()))
+        .and_then(delete_appointment);
+
     let get_subscription_info = warp::post()
         .and(warp::path(Endpoint::GetSubscriptionInfo.to_string()))
         .and(
@@ -256,9 +402,26 @@ fn router(
                 .and(warp::body::json()),
         )
         .and(warp::addr::remote())
-        .and(with_grpc(grpc_conn))
+        .and(with_grpc(grpc_conn.clone()))
         .and_then(get_subscription_info);
 
+    let transfer_subscription = warp::post()
+        .and(warp::path(Endpoint::TransferSubscription.to_string()))
+        .and(
+            warp::body::content_length_limit(TRANSFER_SUBSCRIPTION_BODY_LEN)
+                .and(warp::body::json()),
+        )
+        .and(warp::addr::remote())
+        .and(with_grpc(grpc_conn.clone()))
+        .and_then(transfer_subscription);
+
+    let get_fee_estimate = warp::post()
+        .and(warp::path(Endpoint::GetFeeEstimate.to_string()))
+        .and(warp::body::content_length_limit(GET_FEE_ESTIMATE_BODY_LEN).and(warp::body::json()))
+        .and(warp::addr::remote())
+        .and(with_grpc(grpc_conn))
+        .and_then(get_fee_estimate);
+
     let ping = warp::get()
         .and(warp::path(Endpoint::Ping.to_string()))
         .and(warp::addr::remote())
@@ -267,7 +430,10 @@ fn router(
     register
         .or(add_appointment)
         .or(get_appointment)
+        .or(delete_appointment)
         .or(get_subscription_info)
+        .or(transfer_subscription)
+        .or(get_fee_estimate)
         .or(ping)
         .recover(handle_rejection)
 }
@@ -605,7 +771,13 @@ mod tests_failures {
         let res = warp::test::request()
             .method("POST")
             .path(&Endpoint::Register.path())
-            .json(&format!("{}{}", get_random_user_id(), get_random_user_id()))
+            .json(&format!(
+                "{}{}{}{}",
+                get_random_user_id(),
+                get_random_user_id(),
+                get_random_user_id(),
+                get_random_user_id()
+            ))
             .reply(&router(grpc_conn))
             .await;
 
@@ -662,12 +834,13 @@ mod tests_methods {
 
     use crate::responder::{ConfirmationStatus, TransactionTracker};
     use crate::test_utils::{
-        generate_dummy_appointment, get_random_tx, ApiConfig, DURATION, SLOTS,
+        generate_dummy_appointment, get_random_tx, ApiConfig, MockedPaymentVerifier, DURATION,
+        MOCKED_FEERATE, SLOTS,
     };
     use crate::watcher::Breach;
 
     use teos_common::test_utils::get_random_user_id;
-    use teos_common::{cryptography, UserId};
+    use teos_common::{appointment::SignatureVersion, cryptography, UserId};
 
     #[tokio::test]
     async fn test_register() {
@@ -677,11 +850,19 @@ mod tests_methods {
                 Endpoint::Register,
                 common_msgs::RegisterRequest {
                     user_id: get_random_user_id().to_vec(),
+                    payment_preimage: Vec::new(),
+                    signature_versions: SignatureVersion::supported(),
                 },
                 server_addr,
             )
             .await;
-        assert!(matches!(response, Ok(common_msgs::RegisterResponse { .. })));
+        assert!(matches!(
+            response,
+            Ok(common_msgs::RegisterResponse {
+                signature_version: 3,
+                ..
+            })
+        ));
     }
 
     #[tokio::test]
@@ -695,6 +876,8 @@ mod tests_methods {
             Endpoint::Register,
             common_msgs::RegisterRequest {
                 user_id: user_id.to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
             },
             server_addr,
         )
@@ -707,6 +890,8 @@ mod tests_methods {
                 Endpoint::Register,
                 RequestBody::Json(serde_json::json!(common_msgs::RegisterRequest {
                     user_id: user_id.to_vec(),
+                    payment_preimage: Vec::new(),
+                    signature_versions: Vec::new(),
                 })),
                 server_addr,
             )
@@ -721,6 +906,105 @@ mod tests_methods {
         );
     }
 
+    #[tokio::test]
+    async fn test_register_with_payment() {
+        let verifier = std::sync::Arc::new(MockedPaymentVerifier::default());
+        let (server_addr, _, _s) = run_tower_in_background_with_config(
+            ApiConfig::new(SLOTS, DURATION).with_payment_verifier(verifier.clone()),
+        )
+        .await;
+        let user_id = get_random_user_id();
+
+        // Registering without a payment is refused with an invoice to be paid
+        let (api_error, status) = check_api_error(
+            Endpoint::Register,
+            RequestBody::Json(serde_json::json!(common_msgs::RegisterRequest {
+                user_id: user_id.to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            })),
+            server_addr,
+        )
+        .await;
+        assert!(api_error.error.contains("lnbcrt1invoice"));
+        assert_eq!(api_error.error_code, errors::REGISTRATION_PAYMENT_REQUIRED);
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+
+        // Presenting the preimage of the paid invoice grants the subscription
+        let preimage = cryptography::get_random_bytes(32);
+        verifier.pay(user_id, &preimage);
+        let response =
+            request_to_api::<common_msgs::RegisterRequest, common_msgs::RegisterResponse>(
+                Endpoint::Register,
+                common_msgs::RegisterRequest {
+                    user_id: user_id.to_vec(),
+                    payment_preimage: preimage.clone(),
+                    signature_versions: Vec::new(),
+                },
+                server_addr,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.available_slots, SLOTS);
+
+        // But only once
+        let (api_error, status) = check_api_error(
+            Endpoint::Register,
+            RequestBody::Json(serde_json::json!(common_msgs::RegisterRequest {
+                user_id: user_id.to_vec(),
+                payment_preimage: preimage,
+                signature_versions: Vec::new(),
+            })),
+            server_addr,
+        )
+        .await;
+        assert_eq!(api_error.error_code, errors::REGISTRATION_INVALID_PAYMENT);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_register_renewal_too_early() {
+        let (server_addr, _, _s) =
+            run_tower_in_background_with_config(ApiConfig::new(SLOTS, DURATION)).await;
+        let user_id = get_random_user_id();
+
+        // Register and renew right away, this should go through given the subscription expires within the renewal window
+        for _ in 0..2 {
+            request_to_api::<common_msgs::RegisterRequest, common_msgs::RegisterResponse>(
+                Endpoint::Register,
+                common_msgs::RegisterRequest {
+                    user_id: user_id.to_vec(),
+                    payment_preimage: Vec::new(),
+                    signature_versions: Vec::new(),
+                },
+                server_addr,
+            )
+            .await
+            .unwrap();
+        }
+
+        // Renew again, the subscription is now too far from expiring to be renewed
+        assert_eq!(
+            check_api_error(
+                Endpoint::Register,
+                RequestBody::Json(serde_json::json!(common_msgs::RegisterRequest {
+                    user_id: user_id.to_vec(),
+                    payment_preimage: Vec::new(),
+                    signature_versions: Vec::new(),
+                })),
+                server_addr,
+            )
+            .await,
+            (
+                ApiError::new(
+                    "Subscription cannot be renewed yet. Try again closer to its expiry".into(),
+                    errors::REGISTRATION_RENEWAL_TOO_EARLY
+                ),
+                StatusCode::BAD_REQUEST
+            )
+        );
+    }
+
     #[tokio::test]
     async fn test_register_service_unavailable() {
         let (server_addr, _, _s) = run_tower_in_background_with_config(
@@ -735,6 +1019,8 @@ mod tests_methods {
                 Endpoint::Register,
                 RequestBody::Json(serde_json::json!(common_msgs::RegisterRequest {
                     user_id: user_id.to_vec(),
+                    payment_preimage: Vec::new(),
+                    signature_versions: Vec::new(),
                 })),
                 server_addr,
             )
@@ -759,6 +1045,8 @@ mod tests_methods {
             Endpoint::Register,
             common_msgs::RegisterRequest {
                 user_id: user_pk.serialize().to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
             },
             server_addr,
         )
@@ -777,6 +1065,9 @@ mod tests_methods {
             common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             },
             server_addr,
         )
@@ -788,6 +1079,93 @@ mod tests_methods {
         ));
     }
 
+    #[tokio::test]
+    async fn test_add_appointment_protobuf() {
+        let (server_addr, _s) = run_tower_in_background().await;
+        let grpc_conn = PublicTowerServicesClient::connect(format!("http://{server_addr}"))
+            .await
+            .unwrap();
+
+        // Register first
+        let (user_sk, user_pk) = cryptography::get_random_keypair();
+        request_to_api::<common_msgs::RegisterRequest, common_msgs::RegisterResponse>(
+            Endpoint::Register,
+            common_msgs::RegisterRequest {
+                user_id: user_pk.serialize().to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            },
+            server_addr,
+        )
+        .await
+        .unwrap();
+
+        let new_request = || {
+            let appointment = generate_dummy_appointment(None).inner;
+            let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+            common_msgs::AddAppointmentRequest {
+                appointment: Some(appointment.into()),
+                signature,
+                ttl: None,
+                priority: None,
+                reward: None,
+            }
+        };
+
+        // The request and the response round-trip using the binary encoding, which is more compact than JSON
+        let request = new_request();
+        assert!(request.encoded_len() < serde_json::to_vec(&request).unwrap().len());
+        let res = warp::test::request()
+            .method("POST")
+            .path(&Endpoint::AddAppointment.path())
+            .header("content-type", PROTOBUF_CONTENT_TYPE)
+            .header("accept", PROTOBUF_CONTENT_TYPE)
+            .body(request.encode_to_vec())
+            .reply(&router(grpc_conn.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()["content-type"], PROTOBUF_CONTENT_TYPE);
+        let response = common_msgs::AddAppointmentResponse::decode(res.body().clone()).unwrap();
+        assert_eq!(response.locator, request.appointment.unwrap().locator);
+
+        // Responses are JSON encoded unless the binary encoding is accepted
+        let request = new_request();
+        let res = warp::test::request()
+            .method("POST")
+            .path(&Endpoint::AddAppointment.path())
+            .header("content-type", PROTOBUF_CONTENT_TYPE)
+            .body(request.encode_to_vec())
+            .reply(&router(grpc_conn.clone()))
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let response =
+            serde_json::from_slice::<common_msgs::AddAppointmentResponse>(res.body()).unwrap();
+        assert_eq!(response.locator, request.appointment.unwrap().locator);
+
+        // The JSON path still works
+        let request = new_request();
+        let response = request_to_api::<
+            common_msgs::AddAppointmentRequest,
+            common_msgs::AddAppointmentResponse,
+        >(Endpoint::AddAppointment, request.clone(), server_addr)
+        .await
+        .unwrap();
+        assert_eq!(response.locator, request.appointment.unwrap().locator);
+
+        // Errors are always JSON encoded
+        let res = warp::test::request()
+            .method("POST")
+            .path(&Endpoint::AddAppointment.path())
+            .header("content-type", PROTOBUF_CONTENT_TYPE)
+            .header("accept", PROTOBUF_CONTENT_TYPE)
+            .body(vec![0xff; 8])
+            .reply(&router(grpc_conn))
+            .await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let api_error = serde_json::from_slice::<ApiError>(res.body()).unwrap();
+        assert_eq!(api_error.error_code, errors::INVALID_REQUEST_FORMAT);
+    }
+
     #[tokio::test]
     async fn test_add_appointment_non_registered() {
         let (server_addr, _s) = run_tower_in_background().await;
@@ -801,6 +1179,9 @@ mod tests_methods {
                 RequestBody::Json(serde_json::json!(common_msgs::AddAppointmentRequest {
                     appointment: Some(appointment.into()),
                     signature,
+                    ttl: None,
+                    priority: None,
+                    reward: None,
                 })),
                 server_addr,
             )
@@ -827,6 +1208,8 @@ mod tests_methods {
             Endpoint::Register,
             common_msgs::RegisterRequest {
                 user_id: user_pk.serialize().to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
             },
             server_addr,
         )
@@ -853,6 +1236,9 @@ mod tests_methods {
                 RequestBody::Json(serde_json::json!(common_msgs::AddAppointmentRequest {
                     appointment: Some(appointment.into()),
                     signature,
+                    ttl: None,
+                    priority: None,
+                    reward: None,
                 })),
                 server_addr,
             )
@@ -883,6 +1269,9 @@ mod tests_methods {
                 RequestBody::Json(serde_json::json!(common_msgs::AddAppointmentRequest {
                     appointment: Some(appointment.into()),
                     signature,
+                    ttl: None,
+                    priority: None,
+                    reward: None,
                 })),
                 server_addr,
             )
@@ -907,6 +1296,8 @@ mod tests_methods {
             Endpoint::Register,
             common_msgs::RegisterRequest {
                 user_id: user_pk.serialize().to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
             },
             server_addr,
         )
@@ -922,6 +1313,9 @@ mod tests_methods {
             common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.clone().into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             },
             server_addr,
         )
@@ -995,6 +1389,8 @@ mod tests_methods {
             Endpoint::Register,
             common_msgs::RegisterRequest {
                 user_id: user_pk.serialize().to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
             },
             server_addr,
         )
@@ -1063,6 +1459,89 @@ mod tests_methods {
         );
     }
 
+    #[tokio::test]
+    async fn test_delete_appointment() {
+        let (server_addr, internal_api, _s) =
+            run_tower_in_background_with_config(ApiConfig::default()).await;
+
+        // Register first
+        let (user_sk, user_pk) = cryptography::get_random_keypair();
+        request_to_api::<common_msgs::RegisterRequest, common_msgs::RegisterResponse>(
+            Endpoint::Register,
+            common_msgs::RegisterRequest {
+                user_id: user_pk.serialize().to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            },
+            server_addr,
+        )
+        .await
+        .unwrap();
+
+        // Add an appointment
+        let appointment = generate_dummy_appointment(None).inner;
+        let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+
+        let add_response = request_to_api::<
+            common_msgs::AddAppointmentRequest,
+            common_msgs::AddAppointmentResponse,
+        >(
+            Endpoint::AddAppointment,
+            common_msgs::AddAppointmentRequest {
+                appointment: Some(appointment.clone().into()),
+                signature,
+                ttl: None,
+                priority: None,
+                reward: None,
+            },
+            server_addr,
+        )
+        .await
+        .unwrap();
+
+        // Delete it
+        let request = common_msgs::DeleteAppointmentRequest {
+            locator: appointment.locator.to_vec(),
+            signature: cryptography::sign(
+                &teos_common::delete_appointment_message(
+                    internal_api.get_watcher().tower_id,
+                    appointment.locator,
+                    add_response.start_block,
+                ),
+                &user_sk,
+            )
+            .unwrap(),
+            start_block: add_response.start_block,
+        };
+        let response = request_to_api::<
+            common_msgs::DeleteAppointmentRequest,
+            common_msgs::DeleteAppointmentResponse,
+        >(Endpoint::DeleteAppointment, request.clone(), server_addr)
+        .await;
+
+        assert!(matches!(
+            response,
+            Ok(common_msgs::DeleteAppointmentResponse { .. })
+        ));
+
+        // Deleting it again fails
+        assert_eq!(
+            check_api_error(
+                Endpoint::DeleteAppointment,
+                RequestBody::Json(serde_json::json!(request)),
+                server_addr,
+            )
+            .await,
+            (
+                ApiError::new(
+                    "Appointment not found".into(),
+                    errors::APPOINTMENT_NOT_FOUND
+                ),
+                StatusCode::NOT_FOUND
+            )
+        );
+    }
+
     #[tokio::test]
     async fn test_get_subscription_info() {
         let (server_addr, _s) = run_tower_in_background().await;
@@ -1073,6 +1552,8 @@ mod tests_methods {
             Endpoint::Register,
             common_msgs::RegisterRequest {
                 user_id: user_pk.serialize().to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
             },
             server_addr,
         )
@@ -1099,6 +1580,105 @@ mod tests_methods {
         ));
     }
 
+    #[tokio::test]
+    async fn test_get_fee_estimate() {
+        let (server_addr, _s) = run_tower_in_background().await;
+
+        // The estimate comes from the (mocked) bitcoind backing the tower
+        let response = request_to_api::<
+            common_msgs::GetFeeEstimateRequest,
+            common_msgs::GetFeeEstimateResponse,
+        >(
+            Endpoint::GetFeeEstimate,
+            common_msgs::GetFeeEstimateRequest { conf_target: 6 },
+            server_addr,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            response,
+            common_msgs::GetFeeEstimateResponse {
+                conf_target: 6,
+                feerate: MOCKED_FEERATE as f64,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_subscription() {
+        let (server_addr, internal_api, _s) =
+            run_tower_in_background_with_config(ApiConfig::default()).await;
+
+        // Register first
+        let (old_sk, old_pk) = cryptography::get_random_keypair();
+        let register_request = common_msgs::RegisterRequest {
+            user_id: old_pk.serialize().to_vec(),
+            payment_preimage: Vec::new(),
+            signature_versions: SignatureVersion::supported(),
+        };
+        request_to_api::<common_msgs::RegisterRequest, common_msgs::RegisterResponse>(
+            Endpoint::Register,
+            register_request.clone(),
+            server_addr,
+        )
+        .await
+        .unwrap();
+
+        // Transfer the subscription to a new key
+        let (new_sk, new_pk) = cryptography::get_random_keypair();
+        let message = teos_common::transfer_subscription_message(
+            internal_api.get_watcher().tower_id,
+            UserId(old_pk),
+            UserId(new_pk),
+            u64::MAX,
+        );
+        let transfer_request = common_msgs::TransferSubscriptionRequest {
+            old_user_id: old_pk.serialize().to_vec(),
+            new_user_id: new_pk.serialize().to_vec(),
+            nonce: u64::MAX,
+            old_user_signature: cryptography::sign(&message, &old_sk).unwrap(),
+            new_user_signature: cryptography::sign(&message, &new_sk).unwrap(),
+        };
+        let response = request_to_api::<
+            common_msgs::TransferSubscriptionRequest,
+            common_msgs::RegisterResponse,
+        >(
+            Endpoint::TransferSubscription,
+            transfer_request.clone(),
+            server_addr,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.user_id, new_pk.serialize().to_vec());
+        // The new user signs the way agreed with the old one
+        assert_eq!(response.signature_version, u32::from(SignatureVersion::V3));
+
+        // Subscriptions cannot be transferred to users that are already registered
+        request_to_api::<common_msgs::RegisterRequest, common_msgs::RegisterResponse>(
+            Endpoint::Register,
+            register_request,
+            server_addr,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            check_api_error(
+                Endpoint::TransferSubscription,
+                RequestBody::Json(serde_json::json!(transfer_request)),
+                server_addr,
+            )
+            .await,
+            (
+                ApiError::new(
+                    "The new user id is already registered with the tower".into(),
+                    errors::TRANSFER_USER_ALREADY_REGISTERED
+                ),
+                StatusCode::BAD_REQUEST
+            )
+        );
+    }
+
     #[tokio::test]
     async fn test_get_subscription_info_non_registered() {
         let (server_addr, _s) = run_tower_in_background().await;
@@ -1,19 +1,62 @@
 use std::sync::{Arc, Condvar, Mutex};
+use tonic::metadata::MetadataMap;
 use tonic::{Code, Request, Response, Status};
 use triggered::Trigger;
 
+use bitcoin::{consensus, Transaction};
+
 use crate::extended_appointment::UUID;
+use crate::gatekeeper::RenewalFailure;
 use crate::protos as msgs;
 use crate::protos::private_tower_services_server::PrivateTowerServices;
 use crate::protos::public_tower_services_server::PublicTowerServices;
 use crate::watcher::{
-    AddAppointmentFailure, AppointmentInfo, GetAppointmentFailure, GetSubscriptionInfoFailure,
-    Watcher,
+    AddAppointmentFailure, AppointmentInfo, AppointmentTerms, DeleteAppointmentFailure,
+    GetAppointmentFailure, GetSubscriptionInfoFailure, TransferSubscriptionFailure,
+    TriggerPenaltyFailure, Watcher,
 };
 
-use teos_common::appointment::{Appointment, AppointmentStatus, Locator};
+use teos_common::appointment::{
+    Appointment, AppointmentStatus, Locator, SignatureVersion, LOCATOR_LEN,
+};
 use teos_common::protos as common_msgs;
-use teos_common::UserId;
+use teos_common::{errors, UserId};
+
+/// Metadata key carrying the API error code of errors with no gRPC counterpart, so the HTTP API can tell them apart.
+pub(crate) const ERROR_CODE_KEY: &str = "teos-error-code";
+
+/// Maximum confirmation target fee estimates can be requested for (the highest `estimatesmartfee` supports).
+const MAX_FEE_ESTIMATE_TARGET: u32 = 1008;
+
+/// Builds a [Status] that carries the given API error code alongside the gRPC one.
+fn status_with_error_code(code: Code, message: String, error_code: u8) -> Status {
+    let mut metadata = MetadataMap::new();
+    metadata.insert(ERROR_CODE_KEY, error_code.to_string().parse().unwrap());
+    Status::with_metadata(code, message, metadata)
+}
+
+/// Reasons why the tower cannot take data in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NotReady {
+    /// bitcoind cannot be reached.
+    BitcoindUnreachable,
+    /// bitcoind is on its initial block download.
+    BitcoindSyncing,
+}
+
+impl From<NotReady> for Status {
+    fn from(e: NotReady) -> Self {
+        match e {
+            NotReady::BitcoindUnreachable => {
+                Status::new(Code::Unavailable, "Service currently unavailable")
+            }
+            NotReady::BitcoindSyncing => Status::new(
+                Code::Unavailable,
+                "Service currently unavailable (bitcoind is syncing)",
+            ),
+        }
+    }
+}
 
 /// Internal API of the tower.
 /// Holds the [Watcher] (which is the single entry point of the tower's core) and offers interfaces
@@ -26,8 +69,12 @@ pub struct InternalAPI {
     addresses: Vec<msgs::NetworkAddress>,
     /// A flag that indicates wether bitcoind is reachable or not.
     bitcoind_reachable: Arc<(Mutex<bool>, Condvar)>,
+    /// A flag that indicates whether bitcoind is still on its initial block download or not.
+    bitcoind_syncing: Arc<Mutex<bool>>,
     /// A signal indicating the tower is shuting down.
     shutdown_trigger: Trigger,
+    /// Whether penalties can be manually triggered.
+    penalty_triggers: bool,
 }
 
 impl InternalAPI {
@@ -42,10 +89,28 @@ impl InternalAPI {
             watcher,
             addresses,
             bitcoind_reachable,
+            bitcoind_syncing: Arc::new(Mutex::new(false)),
             shutdown_trigger,
+            penalty_triggers: false,
         }
     }
 
+    /// Sets the flag that indicates whether bitcoind is still on its initial block download (as set by the [ChainMonitor]).
+    ///
+    /// [ChainMonitor]: crate::chain_monitor::ChainMonitor
+    pub fn with_bitcoind_syncing(mut self, bitcoind_syncing: Arc<Mutex<bool>>) -> Self {
+        self.bitcoind_syncing = bitcoind_syncing;
+        self
+    }
+
+    /// Allows penalties to be manually triggered via [PrivateTowerServices::trigger_penalty].
+    ///
+    /// This should only be enabled for networks where funds are worthless (or under an explicit user override).
+    pub fn with_penalty_triggers(mut self, enabled: bool) -> Self {
+        self.penalty_triggers = enabled;
+        self
+    }
+
     pub fn get_addresses(&self) -> &Vec<msgs::NetworkAddress> {
         &self.addresses
     }
@@ -62,6 +127,21 @@ impl InternalAPI {
             ))
         }
     }
+
+    /// Checks whether bitcoind is reachable and done with its initial block download.
+    ///
+    /// Breaches cannot be spotted while bitcoind is syncing, so no data is accepted until then.
+    fn check_service_ready(&self) -> Result<(), NotReady> {
+        if !*self.bitcoind_reachable.0.lock().unwrap() {
+            log::error!("Bitcoind not reachable");
+            Err(NotReady::BitcoindUnreachable)
+        } else if *self.bitcoind_syncing.lock().unwrap() {
+            log::warn!("Bitcoind is syncing");
+            Err(NotReady::BitcoindSyncing)
+        } else {
+            Ok(())
+        }
+    }
 }
 
 /// Public tower API. Accessible by users.
@@ -72,7 +152,7 @@ impl PublicTowerServices for Arc<InternalAPI> {
         &self,
         request: Request<common_msgs::RegisterRequest>,
     ) -> Result<Response<common_msgs::RegisterResponse>, Status> {
-        self.check_service_unavailable()?;
+        self.check_service_ready()?;
         let req_data = request.into_inner();
 
         let user_id = UserId::from_slice(&req_data.user_id).map_err(|_| {
@@ -82,18 +162,69 @@ impl PublicTowerServices for Arc<InternalAPI> {
             )
         })?;
 
-        match self.watcher.register(user_id) {
-            Ok(receipt) => Ok(Response::new(common_msgs::RegisterResponse {
-                user_id: req_data.user_id,
-                available_slots: receipt.available_slots(),
-                subscription_start: receipt.subscription_start(),
-                subscription_expiry: receipt.subscription_expiry(),
-                subscription_signature: receipt.signature().unwrap(),
-            })),
-            Err(_) => Err(Status::new(
-                Code::ResourceExhausted,
-                "Subscription maximum slots count reached",
-            )),
+        // Registrations may need to query the Lightning node payments are verified against, so they are handled on a
+        // thread where blocking is allowed
+        let watcher = self.watcher.clone();
+        let payment_preimage = req_data.payment_preimage.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            if payment_preimage.is_empty() {
+                watcher.register(user_id)
+            } else {
+                watcher.register_with_payment(user_id, &payment_preimage)
+            }
+        })
+        .await
+        .map_err(|e| Status::new(Code::Internal, e.to_string()))?;
+
+        match result {
+            Ok(receipt) => {
+                let signature_version = SignatureVersion::negotiate(&req_data.signature_versions);
+                self.watcher
+                    .set_signature_version(user_id, signature_version);
+                Ok(Response::new(common_msgs::RegisterResponse {
+                    user_id: req_data.user_id,
+                    available_slots: receipt.available_slots(),
+                    subscription_start: receipt.subscription_start(),
+                    subscription_expiry: receipt.subscription_expiry(),
+                    subscription_signature: receipt.signature().unwrap(),
+                    signature_version: signature_version.into(),
+                }))
+            }
+            Err(e) => Err(match e {
+                RenewalFailure::MaxSlotsReached => Status::new(
+                    Code::ResourceExhausted,
+                    "Subscription maximum slots count reached",
+                ),
+                RenewalFailure::TooEarly => status_with_error_code(
+                    Code::FailedPrecondition,
+                    "Subscription cannot be renewed yet. Try again closer to its expiry".to_owned(),
+                    errors::REGISTRATION_RENEWAL_TOO_EARLY,
+                ),
+                RenewalFailure::ExpiryTooFar => status_with_error_code(
+                    Code::OutOfRange,
+                    "Renewed subscription would expire too far into the future".to_owned(),
+                    errors::REGISTRATION_EXPIRY_TOO_FAR,
+                ),
+                RenewalFailure::PaymentRequired(invoice) => status_with_error_code(
+                    Code::FailedPrecondition,
+                    format!("Registration requires a payment. Pay the following invoice and register again providing its preimage: {invoice}"),
+                    errors::REGISTRATION_PAYMENT_REQUIRED,
+                ),
+                RenewalFailure::InvalidPayment(reason) => status_with_error_code(
+                    Code::PermissionDenied,
+                    format!("Invalid payment. {reason}"),
+                    errors::REGISTRATION_INVALID_PAYMENT,
+                ),
+                RenewalFailure::PaymentUnavailable => Status::new(
+                    Code::Unavailable,
+                    "Payments cannot be verified right now. Try again later",
+                ),
+                RenewalFailure::NotAllowed => status_with_error_code(
+                    Code::PermissionDenied,
+                    "This user is not allowed to register with the tower".to_owned(),
+                    errors::REGISTRATION_USER_NOT_ALLOWED,
+                ),
+            }),
         }
     }
 
@@ -102,21 +233,40 @@ impl PublicTowerServices for Arc<InternalAPI> {
         &self,
         request: Request<common_msgs::AddAppointmentRequest>,
     ) -> Result<Response<common_msgs::AddAppointmentResponse>, Status> {
-        self.check_service_unavailable()?;
+        self.check_service_ready()?;
         let req_data = request.into_inner();
-        let app_data = req_data.appointment.unwrap();
+        let app_data = req_data
+            .appointment
+            .ok_or_else(|| Status::new(Code::InvalidArgument, "Missing appointment"))?;
 
-        let appointment = Appointment::new(
-            Locator::from_slice(&app_data.locator).unwrap(),
-            app_data.encrypted_blob,
-            app_data.to_self_delay,
-        );
-        let locator = appointment.locator;
+        // Locators cannot be fully validated (they are truncated txids), but structurally invalid ones can never
+        // match a breach, so there is no point on storing them.
+        let locator = Locator::from_slice(&app_data.locator).map_err(|_| {
+            Status::new(
+                Code::InvalidArgument,
+                format!("Invalid locator. Expected {LOCATOR_LEN} bytes"),
+            )
+        })?;
+        let appointment =
+            Appointment::new(locator, app_data.encrypted_blob, app_data.to_self_delay);
 
-        match self
-            .watcher
-            .add_appointment(appointment, req_data.signature)
-        {
+        // An appointment with a zero TTL would be pruned before it is ever watched.
+        if req_data.ttl == Some(0) {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                "Invalid TTL. Appointments must be watched for at least one block",
+            ));
+        }
+
+        match self.watcher.add_appointment_with_terms(
+            appointment,
+            req_data.signature,
+            AppointmentTerms {
+                ttl: req_data.ttl,
+                priority: req_data.priority,
+                reward: req_data.reward,
+            },
+        ) {
             Ok((receipt, available_slots, subscription_expiry)) => {
                 Ok(Response::new(common_msgs::AddAppointmentResponse {
                     locator: locator.to_vec(),
@@ -140,6 +290,10 @@ impl PublicTowerServices for Arc<InternalAPI> {
                     Code::AlreadyExists,
                     "The provided appointment has already been triggered",
                 )),
+                AddAppointmentFailure::StorageFull => Err(Status::new(
+                    Code::Unavailable,
+                    "The tower is running out of storage. Try again later",
+                )),
             },
         }
     }
@@ -198,6 +352,46 @@ impl PublicTowerServices for Arc<InternalAPI> {
         }
     }
 
+    /// Delete appointment endpoint. Part of the public API. Internally calls [Watcher::delete_appointment].
+    async fn delete_appointment(
+        &self,
+        request: Request<common_msgs::DeleteAppointmentRequest>,
+    ) -> Result<Response<common_msgs::DeleteAppointmentResponse>, Status> {
+        self.check_service_unavailable()?;
+        let req_data = request.into_inner();
+        let locator = Locator::from_slice(&req_data.locator).unwrap();
+
+        match self
+            .watcher
+            .delete_appointment(locator, req_data.start_block, &req_data.signature)
+        {
+            Ok((receipt, available_slots)) => {
+                Ok(Response::new(common_msgs::DeleteAppointmentResponse {
+                    locator: req_data.locator,
+                    deletion_block: receipt.deletion_block(),
+                    signature: receipt.signature().unwrap(),
+                    available_slots,
+                }))
+            }
+            Err(e) => Err(match e {
+                DeleteAppointmentFailure::NotFound => {
+                    Status::new(Code::NotFound, "Appointment not found")
+                }
+                DeleteAppointmentFailure::AuthenticationFailure => {
+                    Status::new(Code::Unauthenticated, "User cannot be authenticated")
+                }
+                DeleteAppointmentFailure::SubscriptionExpired(x) => Status::new(
+                    Code::Unauthenticated,
+                    format!("Your subscription expired at {x}"),
+                ),
+                DeleteAppointmentFailure::AlreadyTriggered => Status::new(
+                    Code::AlreadyExists,
+                    "The appointment has already been triggered",
+                ),
+            }),
+        }
+    }
+
     /// Get subscription info endpoint. Part of the public API. Internally calls [Watcher::get_subscription_info].
     async fn get_subscription_info(
         &self,
@@ -224,6 +418,98 @@ impl PublicTowerServices for Arc<InternalAPI> {
             locators: locators.iter().map(|x| x.to_vec()).collect(),
         }))
     }
+
+    /// Transfer subscription endpoint. Part of the public API. Internally calls [Watcher::transfer_subscription].
+    async fn transfer_subscription(
+        &self,
+        request: Request<common_msgs::TransferSubscriptionRequest>,
+    ) -> Result<Response<common_msgs::RegisterResponse>, Status> {
+        self.check_service_unavailable()?;
+        let req_data = request.into_inner();
+
+        let invalid_user_id = |_| {
+            Status::new(
+                Code::InvalidArgument,
+                "Provided public key does not match expected format (33-byte compressed key)",
+            )
+        };
+        let old_user_id = UserId::from_slice(&req_data.old_user_id).map_err(invalid_user_id)?;
+        let new_user_id = UserId::from_slice(&req_data.new_user_id).map_err(invalid_user_id)?;
+
+        match self.watcher.transfer_subscription(
+            old_user_id,
+            new_user_id,
+            req_data.nonce,
+            &req_data.old_user_signature,
+            &req_data.new_user_signature,
+        ) {
+            Ok(receipt) => Ok(Response::new(common_msgs::RegisterResponse {
+                user_id: req_data.new_user_id,
+                available_slots: receipt.available_slots(),
+                subscription_start: receipt.subscription_start(),
+                subscription_expiry: receipt.subscription_expiry(),
+                subscription_signature: receipt.signature().unwrap(),
+                // The new user keeps signing the way the old one agreed to
+                signature_version: self
+                    .watcher
+                    .get_signature_version(new_user_id)
+                    .unwrap_or_default()
+                    .into(),
+            })),
+            Err(e) => Err(match e {
+                TransferSubscriptionFailure::AuthenticationFailure => Status::new(
+                    Code::Unauthenticated,
+                    "Invalid signature or user not found. Have you registered?",
+                ),
+                TransferSubscriptionFailure::SubscriptionExpired(x) => Status::new(
+                    Code::Unauthenticated,
+                    format!("Your subscription expired at {x}"),
+                ),
+                TransferSubscriptionFailure::AlreadyRegistered => status_with_error_code(
+                    Code::Aborted,
+                    "The new user id is already registered with the tower".to_owned(),
+                    errors::TRANSFER_USER_ALREADY_REGISTERED,
+                ),
+                TransferSubscriptionFailure::Replayed => status_with_error_code(
+                    Code::PermissionDenied,
+                    "This transfer has already been performed".to_owned(),
+                    errors::TRANSFER_ALREADY_USED,
+                ),
+                TransferSubscriptionFailure::NotAllowed => status_with_error_code(
+                    Code::PermissionDenied,
+                    "The new user id is not allowed to register with the tower".to_owned(),
+                    errors::REGISTRATION_USER_NOT_ALLOWED,
+                ),
+            }),
+        }
+    }
+
+    /// Get fee estimate endpoint. Part of the public API. Internally calls [Watcher::estimate_feerate].
+    async fn get_fee_estimate(
+        &self,
+        request: Request<common_msgs::GetFeeEstimateRequest>,
+    ) -> Result<Response<common_msgs::GetFeeEstimateResponse>, Status> {
+        self.check_service_unavailable()?;
+        let conf_target = request.into_inner().conf_target;
+
+        if !(1..=MAX_FEE_ESTIMATE_TARGET).contains(&conf_target) {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                format!("conf_target must be between 1 and {MAX_FEE_ESTIMATE_TARGET}"),
+            ));
+        }
+
+        match self.watcher.estimate_feerate(conf_target as u16) {
+            Some(feerate) => Ok(Response::new(common_msgs::GetFeeEstimateResponse {
+                conf_target,
+                feerate,
+            })),
+            None => Err(Status::new(
+                Code::Unavailable,
+                "No fee estimate is currently available",
+            )),
+        }
+    }
 }
 
 /// Private tower API. Only accessible by the tower admin via RPC.
@@ -333,6 +619,8 @@ impl PrivateTowerServices for Arc<InternalAPI> {
                 .map_or("an unknown address".to_owned(), |a| a.to_string())
         );
 
+        let (db_size, max_db_size) = self.watcher.get_db_size();
+        let reorg_exposure = self.watcher.get_reorg_exposure();
         Ok(Response::new(msgs::GetTowerInfoResponse {
             tower_id: self.watcher.tower_id.to_vec(),
             addresses: self.get_addresses().clone(),
@@ -340,6 +628,12 @@ impl PrivateTowerServices for Arc<InternalAPI> {
             n_watcher_appointments: self.watcher.get_appointments_count() as u32,
             n_responder_trackers: self.watcher.get_trackers_count() as u32,
             bitcoind_reachable: self.check_service_unavailable().is_ok(),
+            db_size,
+            max_db_size,
+            n_unresolved_penalties: reorg_exposure.unresolved_penalties as u32,
+            min_penalty_confirmations: reorg_exposure.min_confirmations,
+            bitcoind_syncing: *self.bitcoind_syncing.lock().unwrap(),
+            n_expired_users: self.watcher.get_expired_users_count() as u32,
         }))
     }
 
@@ -366,6 +660,33 @@ impl PrivateTowerServices for Arc<InternalAPI> {
         Ok(Response::new(msgs::GetUsersResponse { user_ids }))
     }
 
+    /// List penalties endpoint. Gets the penalties the tower has responded with, optionally filtered by time range.
+    /// Part of the private API. Internally calls [Watcher::get_penalties].
+    async fn list_penalties(
+        &self,
+        request: Request<msgs::ListPenaltiesRequest>,
+    ) -> Result<Response<msgs::ListPenaltiesResponse>, Status> {
+        log::debug!(
+            "Received a list_penalties request from {}",
+            request
+                .remote_addr()
+                .map_or("an unknown address".to_owned(), |a| a.to_string())
+        );
+
+        // A zero bound means the range is open on that end
+        let req_data = request.into_inner();
+        let bound = |x: u64| (x != 0).then_some(x);
+
+        let penalties = self
+            .watcher
+            .get_penalties(bound(req_data.start_time), bound(req_data.end_time))
+            .into_iter()
+            .map(|r| r.into())
+            .collect();
+
+        Ok(Response::new(msgs::ListPenaltiesResponse { penalties }))
+    }
+
     /// Get user endpoint. Gets information about a given user. Part of the private API.
     /// Internally calls [Watcher::get_user].
     async fn get_user(
@@ -400,6 +721,142 @@ impl PrivateTowerServices for Arc<InternalAPI> {
         }
     }
 
+    /// Trigger penalty endpoint. Broadcasts the penalty of a given appointment as if its dispute transaction had
+    /// been seen on chain. Part of the private API. Internally calls [Watcher::trigger_penalty].
+    ///
+    /// Meant for disaster recovery drills, so it is only available if penalty triggers have been enabled.
+    async fn trigger_penalty(
+        &self,
+        request: Request<msgs::TriggerPenaltyRequest>,
+    ) -> Result<Response<msgs::TriggerPenaltyResponse>, Status> {
+        log::debug!(
+            "Received a trigger_penalty request from {}",
+            request
+                .remote_addr()
+                .map_or("an unknown address".to_owned(), |a| a.to_string())
+        );
+
+        if !self.penalty_triggers {
+            return Err(Status::new(
+                Code::PermissionDenied,
+                "Penalties cannot be manually triggered on this network",
+            ));
+        }
+        self.check_service_ready()?;
+        let req_data = request.into_inner();
+
+        let locator = Locator::from_slice(&req_data.locator).map_err(|_| {
+            Status::new(
+                Code::InvalidArgument,
+                "The provided locator does not match the expected format (16-byte hexadecimal string)",
+            )
+        })?;
+        let user_id = UserId::from_slice(&req_data.user_id).map_err(|_| {
+            Status::new(
+                Code::InvalidArgument,
+                "Provided public key does not match expected format (33-byte compressed key)",
+            )
+        })?;
+        let dispute_tx: Transaction = consensus::deserialize(&req_data.dispute_tx)
+            .map_err(|_| Status::new(Code::InvalidArgument, "Malformed dispute transaction"))?;
+        if Locator::new(dispute_tx.txid()) != locator {
+            return Err(Status::new(
+                Code::InvalidArgument,
+                "The provided dispute transaction does not match the locator",
+            ));
+        }
+
+        match self.watcher.trigger_penalty(locator, user_id, dispute_tx) {
+            Ok(tracker) => Ok(Response::new(msgs::TriggerPenaltyResponse {
+                tracker: Some(tracker.into()),
+            })),
+            Err(e) => Err(match e {
+                TriggerPenaltyFailure::NotFound => {
+                    Status::new(Code::NotFound, "Appointment not found")
+                }
+                TriggerPenaltyFailure::AlreadyTriggered => Status::new(
+                    Code::AlreadyExists,
+                    "The appointment has already been triggered",
+                ),
+                TriggerPenaltyFailure::DecryptionFailed => Status::new(
+                    Code::FailedPrecondition,
+                    "The penalty cannot be decrypted using the provided dispute transaction",
+                ),
+                TriggerPenaltyFailure::Rejected(reason) => Status::new(
+                    Code::Aborted,
+                    format!("The penalty was rejected by the network (rpc error code: {reason})"),
+                ),
+            }),
+        }
+    }
+
+    /// Rebroadcast all endpoint. Rebroadcasts, right away, all the penalties that have not been confirmed yet.
+    /// Part of the private API. Internally calls [Watcher::rebroadcast_penalties].
+    async fn rebroadcast_all(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<msgs::RebroadcastAllResponse>, Status> {
+        log::debug!(
+            "Received a rebroadcast_all request from {}",
+            request
+                .remote_addr()
+                .map_or("an unknown address".to_owned(), |a| a.to_string())
+        );
+        self.check_service_ready()?;
+
+        let (rebroadcast, rejected) = self.watcher.rebroadcast_penalties();
+        Ok(Response::new(msgs::RebroadcastAllResponse {
+            rebroadcast: rebroadcast.iter().map(|uuid| uuid.to_vec()).collect(),
+            rejected: rejected.iter().map(|uuid| uuid.to_vec()).collect(),
+        }))
+    }
+
+    /// Reload user lists endpoint. Reloads the lists of users allowed to, or blocked from, registering with the tower.
+    /// Part of the private API. Internally calls [Watcher::reload_access_lists].
+    async fn reload_user_lists(
+        &self,
+        request: Request<()>,
+    ) -> Result<Response<msgs::ReloadUserListsResponse>, Status> {
+        log::debug!(
+            "Received a reload_user_lists request from {}",
+            request
+                .remote_addr()
+                .map_or("an unknown address".to_owned(), |a| a.to_string())
+        );
+
+        let (allowed_users, blocked_users) = self.watcher.reload_access_lists().map_err(|e| {
+            Status::new(
+                Code::FailedPrecondition,
+                format!("User lists cannot be reloaded. {e}"),
+            )
+        })?;
+        Ok(Response::new(msgs::ReloadUserListsResponse {
+            allowed_users: allowed_users.map(|n| n as u32),
+            blocked_users: blocked_users as u32,
+        }))
+    }
+
+    /// Check integrity endpoint. Checks the data held by the tower is consistent, optionally repairing it.
+    /// Part of the private API. Internally calls [Watcher::check_integrity].
+    async fn check_integrity(
+        &self,
+        request: Request<msgs::CheckIntegrityRequest>,
+    ) -> Result<Response<msgs::CheckIntegrityResponse>, Status> {
+        log::debug!(
+            "Received a check_integrity request from {}",
+            request
+                .remote_addr()
+                .map_or("an unknown address".to_owned(), |a| a.to_string())
+        );
+
+        let repair = request.into_inner().repair;
+        let issues = self.watcher.check_integrity(repair);
+        Ok(Response::new(msgs::CheckIntegrityResponse {
+            repaired: repair && !issues.is_empty(),
+            issues: issues.iter().map(|issue| issue.to_string()).collect(),
+        }))
+    }
+
     /// Stop endpoint. Stops the tower daemon. Part of the private API.
     async fn stop(&self, request: Request<()>) -> Result<Response<()>, Status> {
         self.shutdown_trigger.trigger();
@@ -434,13 +891,16 @@ mod tests_private_api {
     use bitcoin::hashes::Hash;
     use bitcoin::Txid;
 
+    use crate::gatekeeper::UserAccessLists;
     use crate::responder::{ConfirmationStatus, TransactionTracker};
     use crate::test_utils::{
-        create_api, generate_dummy_appointment, generate_dummy_appointment_with_user,
-        get_random_tx, DURATION, SLOTS, START_HEIGHT,
+        create_api, create_api_with_config, generate_dummy_appointment,
+        generate_dummy_appointment_with_user, get_random_tx, ApiConfig, DURATION, SLOTS,
+        START_HEIGHT,
     };
     use crate::watcher::Breach;
 
+    use tempdir::TempDir;
     use teos_common::cryptography::{self, get_random_keypair};
     use teos_common::test_utils::get_random_user_id;
 
@@ -632,8 +1092,14 @@ mod tests_private_api {
 
         assert_eq!(response.tower_id, internal_api.watcher.tower_id.to_vec());
         assert_eq!(response.n_registered_users, 0);
+        assert_eq!(response.n_expired_users, 0);
         assert_eq!(response.n_watcher_appointments, 0);
         assert_eq!(response.n_responder_trackers, 0);
+        assert!(response.db_size > 0);
+        assert_eq!(response.max_db_size, 0);
+        assert_eq!(response.n_unresolved_penalties, 0);
+        assert!(response.min_penalty_confirmations.is_none());
+        assert!(!response.bitcoind_syncing);
     }
 
     #[tokio::test]
@@ -669,8 +1135,12 @@ mod tests_private_api {
         // Given get_tower_info checks data in memory, the data added to the Responder in the test won't be added to the Watcher too.
         assert_eq!(response.tower_id, internal_api.watcher.tower_id.to_vec());
         assert_eq!(response.n_registered_users, 1);
+        assert_eq!(response.n_expired_users, 0);
         assert_eq!(response.n_watcher_appointments, 2);
         assert_eq!(response.n_responder_trackers, 3);
+        // All the trackers are confirmed in the same block
+        assert_eq!(response.n_unresolved_penalties, 3);
+        assert!(response.min_penalty_confirmations.is_some());
     }
 
     #[tokio::test]
@@ -772,50 +1242,317 @@ mod tests_private_api {
     }
 
     #[tokio::test]
-    async fn test_stop() {
-        let (internal_api, _s) = create_api().await;
-
-        assert!(!internal_api.shutdown_trigger.is_triggered());
-        internal_api.stop(Request::new(())).await.unwrap();
-        assert!(internal_api.shutdown_trigger.is_triggered());
-    }
-}
-
-#[cfg(test)]
-mod tests_public_api {
-    use super::*;
-
-    use crate::responder::{ConfirmationStatus, TransactionTracker};
-    use crate::test_utils::{
-        create_api, create_api_with_config, generate_dummy_appointment, get_random_tx, ApiConfig,
-        DURATION, SLOTS,
-    };
-    use crate::watcher::Breach;
-    use teos_common::cryptography::{self, get_random_keypair};
+    async fn test_trigger_penalty() {
+        let (internal_api, _s) =
+            create_api_with_config(ApiConfig::new(SLOTS, DURATION).penalty_triggers()).await;
 
-    #[tokio::test]
-    async fn test_register() {
-        let (internal_api, _s) = create_api().await;
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        internal_api.watcher.register(user_id).unwrap();
 
-        let (_, user_pk) = get_random_keypair();
+        let dispute_tx = get_random_tx();
+        let appointment = generate_dummy_appointment(Some(&dispute_tx.txid())).inner;
+        let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        internal_api
+            .watcher
+            .add_appointment(appointment.clone(), signature)
+            .unwrap();
 
-        // Registering (even multiple times) should work
-        for _ in 0..2 {
-            let response = internal_api
-                .register(Request::new(common_msgs::RegisterRequest {
-                    user_id: UserId(user_pk).to_vec(),
-                }))
-                .await
-                .unwrap()
-                .into_inner();
+        // The penalty is broadcast and tracked just as if the dispute transaction had been seen on chain
+        let response = internal_api
+            .trigger_penalty(Request::new(msgs::TriggerPenaltyRequest {
+                locator: appointment.locator.to_vec(),
+                user_id: user_id.to_vec(),
+                dispute_tx: consensus::serialize(&dispute_tx),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
 
-            assert!(matches!(response, common_msgs::RegisterResponse { .. }))
-        }
+        let penalty_tx =
+            cryptography::decrypt(&appointment.encrypted_blob, &dispute_tx.txid()).unwrap();
+        let tracker = response.tracker.unwrap();
+        assert_eq!(tracker.dispute_txid, dispute_tx.txid().to_vec());
+        assert_eq!(tracker.penalty_txid, penalty_tx.txid().to_vec());
+        assert_eq!(
+            internal_api
+                .watcher
+                .get_responder_trackers_with_locator(appointment.locator)
+                .len(),
+            1
+        );
     }
 
     #[tokio::test]
-    async fn test_register_wrong_user_id() {
-        let (internal_api, _s) = create_api().await;
+    async fn test_list_penalties() {
+        let (internal_api, _s) =
+            create_api_with_config(ApiConfig::new(SLOTS, DURATION).penalty_triggers()).await;
+
+        // No penalties yet
+        let response = internal_api
+            .list_penalties(Request::new(msgs::ListPenaltiesRequest::default()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.penalties.is_empty());
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        internal_api.watcher.register(user_id).unwrap();
+
+        let dispute_tx = get_random_tx();
+        let appointment = generate_dummy_appointment(Some(&dispute_tx.txid())).inner;
+        let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        internal_api
+            .watcher
+            .add_appointment(appointment.clone(), signature)
+            .unwrap();
+        internal_api
+            .trigger_penalty(Request::new(msgs::TriggerPenaltyRequest {
+                locator: appointment.locator.to_vec(),
+                user_id: user_id.to_vec(),
+                dispute_tx: consensus::serialize(&dispute_tx),
+            }))
+            .await
+            .unwrap();
+
+        let response = internal_api
+            .list_penalties(Request::new(msgs::ListPenaltiesRequest::default()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.penalties.len(), 1);
+        let penalty = &response.penalties[0];
+        let penalty_tx =
+            cryptography::decrypt(&appointment.encrypted_blob, &dispute_tx.txid()).unwrap();
+        assert_eq!(penalty.user_id, user_id.to_vec());
+        assert_eq!(penalty.dispute_txid, dispute_tx.txid().to_vec());
+        assert_eq!(penalty.penalty_txid, penalty_tx.txid().to_vec());
+        assert_eq!(penalty.status, "broadcast");
+
+        // Filtering by time
+        let response = internal_api
+            .list_penalties(Request::new(msgs::ListPenaltiesRequest {
+                start_time: penalty.timestamp + 1,
+                end_time: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.penalties.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_trigger_penalty_errors() {
+        let (internal_api, _s) =
+            create_api_with_config(ApiConfig::new(SLOTS, DURATION).penalty_triggers()).await;
+
+        let dispute_tx = get_random_tx();
+        let request = msgs::TriggerPenaltyRequest {
+            locator: Locator::new(dispute_tx.txid()).to_vec(),
+            user_id: get_random_user_id().to_vec(),
+            dispute_tx: consensus::serialize(&dispute_tx),
+        };
+
+        // Unknown appointment
+        let status = internal_api
+            .trigger_penalty(Request::new(request.clone()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        // Dispute transaction not matching the locator
+        let status = internal_api
+            .trigger_penalty(Request::new(msgs::TriggerPenaltyRequest {
+                dispute_tx: consensus::serialize(&get_random_tx()),
+                ..request.clone()
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+
+        // Malformed dispute transaction
+        let status = internal_api
+            .trigger_penalty(Request::new(msgs::TriggerPenaltyRequest {
+                dispute_tx: vec![0; 10],
+                ..request
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_trigger_penalty_not_allowed() {
+        let (internal_api, _s) = create_api().await;
+
+        let dispute_tx = get_random_tx();
+        let status = internal_api
+            .trigger_penalty(Request::new(msgs::TriggerPenaltyRequest {
+                locator: Locator::new(dispute_tx.txid()).to_vec(),
+                user_id: get_random_user_id().to_vec(),
+                dispute_tx: consensus::serialize(&dispute_tx),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_all() {
+        let (internal_api, _s) = create_api().await;
+
+        // Nothing to rebroadcast
+        let response = internal_api
+            .rebroadcast_all(Request::new(()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.rebroadcast.is_empty());
+        assert!(response.rejected.is_empty());
+
+        // Penalties cannot be rebroadcast while bitcoind is unreachable
+        let (internal_api, _s) =
+            create_api_with_config(ApiConfig::new(SLOTS, DURATION).bitcoind_unreachable()).await;
+        match internal_api.rebroadcast_all(Request::new(())).await {
+            Err(status) => {
+                assert_eq!(status.code(), Code::Unavailable);
+                assert_eq!(status.message(), "Service currently unavailable")
+            }
+            _ => panic!("Test should have returned Err"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reload_user_lists() {
+        // With no lists, reloading is a no-op
+        let (internal_api, _s) = create_api().await;
+        let response = internal_api
+            .reload_user_lists(Request::new(()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.allowed_users, None);
+        assert_eq!(response.blocked_users, 0);
+
+        let tmp_dir = TempDir::new("teos_api_allowlist").unwrap();
+        let allowlist_path = tmp_dir.path().join("allowlist");
+        std::fs::write(&allowlist_path, "").unwrap();
+        let (internal_api, _s) =
+            create_api_with_config(ApiConfig::new(SLOTS, DURATION).with_access_lists(
+                UserAccessLists::new(Some(allowlist_path.clone()), None).unwrap(),
+            ))
+            .await;
+
+        // Changes to the lists are picked up on reload
+        let users = (0..3)
+            .map(|_| UserId(get_random_keypair().1).to_string())
+            .collect::<Vec<_>>();
+        std::fs::write(&allowlist_path, users.join("\n")).unwrap();
+        let response = internal_api
+            .reload_user_lists(Request::new(()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.allowed_users, Some(3));
+        assert_eq!(response.blocked_users, 0);
+
+        // Lists that cannot be loaded are reported
+        std::fs::remove_file(&allowlist_path).unwrap();
+        let status = internal_api
+            .reload_user_lists(Request::new(()))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_check_integrity() {
+        let (internal_api, _s) = create_api().await;
+
+        // A healthy tower has nothing to report
+        let response = internal_api
+            .check_integrity(Request::new(msgs::CheckIntegrityRequest { repair: true }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.issues.is_empty());
+        assert!(!response.repaired);
+    }
+
+    #[tokio::test]
+    async fn test_stop() {
+        let (internal_api, _s) = create_api().await;
+
+        assert!(!internal_api.shutdown_trigger.is_triggered());
+        internal_api.stop(Request::new(())).await.unwrap();
+        assert!(internal_api.shutdown_trigger.is_triggered());
+    }
+}
+
+#[cfg(test)]
+mod tests_public_api {
+    use super::*;
+
+    use crate::gatekeeper::UserAccessLists;
+    use crate::responder::{ConfirmationStatus, TransactionTracker};
+    use crate::test_utils::{
+        create_api, create_api_with_config, generate_dummy_appointment, get_random_tx, ApiConfig,
+        MockedPaymentVerifier, DURATION, MOCKED_FEERATE, SLOTS,
+    };
+    use crate::watcher::Breach;
+    use tempdir::TempDir;
+    use teos_common::cryptography::{self, get_random_bytes, get_random_keypair};
+
+    #[tokio::test]
+    async fn test_register() {
+        let (internal_api, _s) = create_api().await;
+
+        let (_, user_pk) = get_random_keypair();
+
+        // Registering (even multiple times) should work
+        for _ in 0..2 {
+            let response = internal_api
+                .register(Request::new(common_msgs::RegisterRequest {
+                    user_id: UserId(user_pk).to_vec(),
+                    payment_preimage: Vec::new(),
+                    signature_versions: Vec::new(),
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+
+            assert!(matches!(response, common_msgs::RegisterResponse { .. }));
+            // Legacy users do not advertise any signature version, so they are kept on the first one
+            assert_eq!(response.signature_version, u32::from(SignatureVersion::V1));
+        }
+
+        // Users advertising the signature versions they support get the newest common one, which is kept by the tower
+        let user_id = UserId(get_random_keypair().1);
+        let response = internal_api
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id: user_id.to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: SignatureVersion::supported(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.signature_version, u32::from(SignatureVersion::V3));
+        assert_eq!(
+            internal_api
+                .watcher
+                .get_user_info(user_id)
+                .unwrap()
+                .0
+                .signature_version,
+            SignatureVersion::V3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_wrong_user_id() {
+        let (internal_api, _s) = create_api().await;
 
         let mut user_ids = Vec::new();
 
@@ -832,7 +1569,11 @@ mod tests_public_api {
 
         for user_id in user_ids {
             match internal_api
-                .register(Request::new(common_msgs::RegisterRequest { user_id }))
+                .register(Request::new(common_msgs::RegisterRequest {
+                    user_id,
+                    payment_preimage: Vec::new(),
+                    signature_versions: Vec::new(),
+                }))
                 .await
             {
                 Err(status) => {
@@ -855,13 +1596,19 @@ mod tests_public_api {
         internal_api
             .register(Request::new(common_msgs::RegisterRequest {
                 user_id: user_id.clone(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
             }))
             .await
             .unwrap();
 
         // Trying to add more slots (re-register) must fail
         match internal_api
-            .register(Request::new(common_msgs::RegisterRequest { user_id }))
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id,
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            }))
             .await
         {
             Err(status) => {
@@ -872,6 +1619,34 @@ mod tests_public_api {
         }
     }
 
+    #[tokio::test]
+    async fn test_register_renewal_too_early() {
+        let (internal_api, _s) = create_api().await;
+        let (_, user_pk) = get_random_keypair();
+        let request = common_msgs::RegisterRequest {
+            user_id: UserId(user_pk).to_vec(),
+            payment_preimage: Vec::new(),
+            signature_versions: Vec::new(),
+        };
+
+        // A fresh subscription can be renewed right away once, but not twice
+        for _ in 0..2 {
+            internal_api
+                .register(Request::new(request.clone()))
+                .await
+                .unwrap();
+        }
+        let status = internal_api
+            .register(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(
+            status.metadata().get(ERROR_CODE_KEY).unwrap(),
+            &errors::REGISTRATION_RENEWAL_TOO_EARLY.to_string()
+        );
+    }
+
     #[tokio::test]
     async fn test_register_service_unavailable() {
         let (internal_api, _s) =
@@ -881,7 +1656,11 @@ mod tests_public_api {
         let user_id = UserId(user_pk).to_vec();
 
         match internal_api
-            .register(Request::new(common_msgs::RegisterRequest { user_id }))
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id,
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            }))
             .await
         {
             Err(status) => {
@@ -892,6 +1671,138 @@ mod tests_public_api {
         }
     }
 
+    #[tokio::test]
+    async fn test_register_bitcoind_syncing() {
+        let (internal_api, _s) =
+            create_api_with_config(ApiConfig::new(u32::MAX, DURATION).bitcoind_syncing()).await;
+
+        let (_, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk).to_vec();
+
+        match internal_api
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id,
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            }))
+            .await
+        {
+            Err(status) => {
+                assert_eq!(status.code(), Code::Unavailable);
+                assert_eq!(
+                    status.message(),
+                    "Service currently unavailable (bitcoind is syncing)"
+                )
+            }
+            _ => panic!("Test should have returned Err"),
+        }
+
+        // The syncing status is reported by get_tower_info
+        let response = internal_api
+            .get_tower_info(Request::new(()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.bitcoind_reachable);
+        assert!(response.bitcoind_syncing);
+    }
+
+    #[tokio::test]
+    async fn test_register_not_allowed() {
+        let tmp_dir = TempDir::new("teos_api_blocklist").unwrap();
+        let blocklist_path = tmp_dir.path().join("blocklist");
+        let (_, user_pk) = get_random_keypair();
+        std::fs::write(&blocklist_path, UserId(user_pk).to_string()).unwrap();
+
+        let (internal_api, _s) = create_api_with_config(
+            ApiConfig::new(SLOTS, DURATION)
+                .with_access_lists(UserAccessLists::new(None, Some(blocklist_path)).unwrap()),
+        )
+        .await;
+
+        let status = internal_api
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id: UserId(user_pk).to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(
+            status.metadata().get(ERROR_CODE_KEY).unwrap(),
+            &errors::REGISTRATION_USER_NOT_ALLOWED.to_string()
+        );
+
+        // Users not in the blocklist can still register
+        let (_, other_user_pk) = get_random_keypair();
+        internal_api
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id: UserId(other_user_pk).to_vec(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            }))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_register_with_payment() {
+        let verifier = Arc::new(MockedPaymentVerifier::default());
+        let (internal_api, _s) = create_api_with_config(
+            ApiConfig::new(SLOTS, DURATION).with_payment_verifier(verifier.clone()),
+        )
+        .await;
+        let (_, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk).to_vec();
+
+        // Registering without a payment returns the invoice to be paid
+        let status = internal_api
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id: user_id.clone(),
+                payment_preimage: Vec::new(),
+                signature_versions: Vec::new(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert!(status.message().contains("lnbcrt1invoice"));
+        assert_eq!(
+            status.metadata().get(ERROR_CODE_KEY).unwrap(),
+            &errors::REGISTRATION_PAYMENT_REQUIRED.to_string()
+        );
+
+        // The preimage of an unpaid invoice is not accepted
+        let preimage = get_random_bytes(32);
+        let status = internal_api
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id: user_id.clone(),
+                payment_preimage: preimage.clone(),
+                signature_versions: Vec::new(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+        assert_eq!(
+            status.metadata().get(ERROR_CODE_KEY).unwrap(),
+            &errors::REGISTRATION_INVALID_PAYMENT.to_string()
+        );
+
+        // Once paid, the subscription is granted
+        verifier.pay(UserId(user_pk), &preimage);
+        let response = internal_api
+            .register(Request::new(common_msgs::RegisterRequest {
+                user_id: user_id.clone(),
+                payment_preimage: preimage,
+                signature_versions: Vec::new(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.user_id, user_id);
+        assert_eq!(response.available_slots, SLOTS);
+    }
+
     #[tokio::test]
     async fn test_add_appointment() {
         let (internal_api, _s) = create_api().await;
@@ -907,6 +1818,9 @@ mod tests_public_api {
             .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.clone().into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             }))
             .await
             .unwrap()
@@ -918,6 +1832,128 @@ mod tests_public_api {
         ));
     }
 
+    #[tokio::test]
+    async fn test_add_appointment_with_ttl() {
+        let (internal_api, _s) = create_api().await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        internal_api.watcher.register(UserId(user_pk)).unwrap();
+
+        let appointment = generate_dummy_appointment(None).inner;
+        let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+
+        // A zero TTL is rejected
+        match internal_api
+            .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
+                appointment: Some(appointment.clone().into()),
+                signature: signature.clone(),
+                ttl: Some(0),
+                priority: None,
+                reward: None,
+            }))
+            .await
+        {
+            Err(status) => {
+                assert_eq!(status.code(), Code::InvalidArgument);
+                assert_eq!(
+                    status.message(),
+                    "Invalid TTL. Appointments must be watched for at least one block"
+                );
+            }
+            _ => panic!("Test should have returned Err"),
+        }
+        assert!(internal_api
+            .watcher
+            .get_all_watcher_appointments()
+            .is_empty());
+
+        // Any other is accepted
+        let response = internal_api
+            .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
+                appointment: Some(appointment.into()),
+                signature,
+                ttl: Some(10),
+                priority: None,
+                reward: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.available_slots, SLOTS - 1);
+    }
+
+    #[tokio::test]
+    async fn test_add_appointment_invalid_locator() {
+        let (internal_api, _s) = create_api().await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        internal_api.watcher.register(UserId(user_pk)).unwrap();
+
+        let appointment = generate_dummy_appointment(None).inner;
+        for locator in [
+            Vec::new(),
+            appointment.locator.to_vec()[1..].to_vec(),
+            [appointment.locator.to_vec(), vec![0]].concat(),
+        ] {
+            let mut appointment_data: common_msgs::Appointment = appointment.clone().into();
+            appointment_data.locator = locator;
+            let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+
+            match internal_api
+                .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
+                    appointment: Some(appointment_data),
+                    signature,
+                    ttl: None,
+                    priority: None,
+                    reward: None,
+                }))
+                .await
+            {
+                Err(status) => {
+                    assert_eq!(status.code(), Code::InvalidArgument);
+                    assert_eq!(
+                        status.message(),
+                        format!("Invalid locator. Expected {LOCATOR_LEN} bytes")
+                    );
+                }
+                _ => panic!("Test should have returned Err"),
+            }
+        }
+
+        // Requests with no appointment are rejected too
+        match internal_api
+            .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
+                appointment: None,
+                signature: cryptography::sign(&appointment.to_vec(), &user_sk).unwrap(),
+                ttl: None,
+                priority: None,
+                reward: None,
+            }))
+            .await
+        {
+            Err(status) => {
+                assert_eq!(status.code(), Code::InvalidArgument);
+                assert_eq!(status.message(), "Missing appointment");
+            }
+            _ => panic!("Test should have returned Err"),
+        }
+
+        // Nothing was stored, and no slots were consumed
+        assert!(internal_api
+            .watcher
+            .get_all_watcher_appointments()
+            .is_empty());
+        assert_eq!(
+            internal_api
+                .watcher
+                .get_user_info(UserId(user_pk))
+                .unwrap()
+                .0
+                .available_slots,
+            SLOTS
+        );
+    }
+
     #[tokio::test]
     async fn test_add_appointment_non_registered() {
         let (internal_api, _s) = create_api().await;
@@ -932,6 +1968,9 @@ mod tests_public_api {
             .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.clone().into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             }))
             .await
         {
@@ -961,6 +2000,9 @@ mod tests_public_api {
             .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.clone().into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             }))
             .await
         {
@@ -990,6 +2032,9 @@ mod tests_public_api {
             .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.clone().into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             }))
             .await
         {
@@ -1027,6 +2072,9 @@ mod tests_public_api {
             .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             }))
             .await
         {
@@ -1053,6 +2101,9 @@ mod tests_public_api {
             .add_appointment(Request::new(common_msgs::AddAppointmentRequest {
                 appointment: Some(appointment.clone().into()),
                 signature,
+                ttl: None,
+                priority: None,
+                reward: None,
             }))
             .await
         {
@@ -1097,6 +2148,52 @@ mod tests_public_api {
         ));
     }
 
+    #[tokio::test]
+    async fn test_delete_appointment() {
+        let (internal_api, _s) = create_api().await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        internal_api.watcher.register(UserId(user_pk)).unwrap();
+
+        let appointment = generate_dummy_appointment(None).inner;
+        let user_signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        let (receipt, _, _) = internal_api
+            .watcher
+            .add_appointment(appointment.clone(), user_signature)
+            .unwrap();
+
+        // Delete the appointment through the API
+        let request = common_msgs::DeleteAppointmentRequest {
+            locator: appointment.locator.to_vec(),
+            signature: cryptography::sign(
+                &teos_common::delete_appointment_message(
+                    internal_api.watcher.tower_id,
+                    appointment.locator,
+                    receipt.start_block(),
+                ),
+                &user_sk,
+            )
+            .unwrap(),
+            start_block: receipt.start_block(),
+        };
+        let response = internal_api
+            .delete_appointment(Request::new(request.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.locator, appointment.locator.to_vec());
+        assert_eq!(response.available_slots, SLOTS - 1);
+
+        // Once deleted, it cannot be found anymore
+        match internal_api.delete_appointment(Request::new(request)).await {
+            Err(status) => {
+                assert_eq!(status.code(), Code::NotFound);
+                assert_eq!(status.message(), "Appointment not found");
+            }
+            _ => panic!("Test should have returned Err"),
+        }
+    }
+
     #[tokio::test]
     async fn test_get_appointment_non_registered() {
         let (internal_api, _s) = create_api().await;
@@ -1294,4 +2391,117 @@ mod tests_public_api {
             _ => panic!("Test should have returned Err"),
         }
     }
+
+    #[tokio::test]
+    async fn test_get_fee_estimate() {
+        let (internal_api, _s) = create_api().await;
+
+        let response = internal_api
+            .get_fee_estimate(Request::new(common_msgs::GetFeeEstimateRequest {
+                conf_target: 6,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            response,
+            common_msgs::GetFeeEstimateResponse {
+                conf_target: 6,
+                feerate: MOCKED_FEERATE as f64,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_fee_estimate_invalid_target() {
+        let (internal_api, _s) = create_api().await;
+
+        for conf_target in [0, MAX_FEE_ESTIMATE_TARGET + 1] {
+            match internal_api
+                .get_fee_estimate(Request::new(common_msgs::GetFeeEstimateRequest {
+                    conf_target,
+                }))
+                .await
+            {
+                Err(status) => assert_eq!(status.code(), Code::InvalidArgument),
+                _ => panic!("Test should have returned Err"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfer_subscription() {
+        let (internal_api, _s) = create_api().await;
+
+        let (old_sk, old_pk) = get_random_keypair();
+        let (new_sk, new_pk) = get_random_keypair();
+        internal_api.watcher.register(UserId(old_pk)).unwrap();
+
+        let message = teos_common::transfer_subscription_message(
+            internal_api.watcher.tower_id,
+            UserId(old_pk),
+            UserId(new_pk),
+            0,
+        );
+        let request = common_msgs::TransferSubscriptionRequest {
+            old_user_id: old_pk.serialize().to_vec(),
+            new_user_id: new_pk.serialize().to_vec(),
+            nonce: 0,
+            old_user_signature: cryptography::sign(&message, &old_sk).unwrap(),
+            new_user_signature: cryptography::sign(&message, &new_sk).unwrap(),
+        };
+        let response = internal_api
+            .transfer_subscription(Request::new(request.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.user_id, new_pk.serialize().to_vec());
+        assert_eq!(response.available_slots, SLOTS);
+
+        // Once done, the old user is no longer registered
+        match internal_api
+            .transfer_subscription(Request::new(request))
+            .await
+        {
+            Err(status) => {
+                assert_eq!(status.code(), Code::Unauthenticated);
+                assert_eq!(
+                    status.message(),
+                    "Invalid signature or user not found. Have you registered?"
+                );
+            }
+            _ => panic!("Test should have returned Err"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transfer_subscription_service_unavailable() {
+        let (internal_api, _s) =
+            create_api_with_config(ApiConfig::new(SLOTS, DURATION).bitcoind_unreachable()).await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let message = teos_common::transfer_subscription_message(
+            internal_api.watcher.tower_id,
+            UserId(user_pk),
+            UserId(user_pk),
+            0,
+        );
+        let signature = cryptography::sign(&message, &user_sk).unwrap();
+        match internal_api
+            .transfer_subscription(Request::new(common_msgs::TransferSubscriptionRequest {
+                old_user_id: user_pk.serialize().to_vec(),
+                new_user_id: user_pk.serialize().to_vec(),
+                nonce: 0,
+                old_user_signature: signature.clone(),
+                new_user_signature: signature,
+            }))
+            .await
+        {
+            Err(status) => {
+                assert_eq!(status.code(), Code::Unavailable);
+                assert_eq!(status.message(), "Service currently unavailable");
+            }
+            _ => panic!("Test should have returned Err"),
+        }
+    }
 }
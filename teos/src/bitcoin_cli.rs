@@ -10,9 +10,12 @@
 */
 
 use std::convert::TryInto;
+use std::future::Future;
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio::time::timeout;
 
 use bitcoin::base64;
 use bitcoin::hash_types::{BlockHash, Txid};
@@ -22,7 +25,14 @@ use bitcoincore_rpc::Auth;
 use lightning::util::ser::Writeable;
 use lightning_block_sync::http::{HttpEndpoint, JsonResponse};
 use lightning_block_sync::rpc::RpcClient;
-use lightning_block_sync::{AsyncBlockSourceResult, BlockHeaderData, BlockSource};
+use lightning_block_sync::{
+    AsyncBlockSourceResult, BlockHeaderData, BlockSource, BlockSourceError, BlockSourceResult,
+};
+
+use crate::chain_monitor::{is_pruned_block_error, SyncSource};
+
+/// How long to wait for `bitcoind` to answer a call by default. Matches the response timeout of the underlying [RpcClient].
+const DEFAULT_RPC_TIMEOUT: Duration = Duration::from_secs(300);
 
 /// A simple implementation of a bitcoind client (`bitcoin-cli`) with the minimal functionality required by the tower.
 pub struct BitcoindClient<'a> {
@@ -36,6 +46,10 @@ pub struct BitcoindClient<'a> {
     rpc_user: String,
     /// The RPC password for the given user.
     rpc_password: String,
+    /// How long to wait for `bitcoind` to answer a call before giving up on it.
+    rpc_timeout: Duration,
+    /// How many times calls are retried if `bitcoind` is too slow to answer them.
+    rpc_retries: u8,
 }
 
 impl BlockSource for &BitcoindClient<'_> {
@@ -46,24 +60,55 @@ impl BlockSource for &BitcoindClient<'_> {
         height_hint: Option<u32>,
     ) -> AsyncBlockSourceResult<'a, BlockHeaderData> {
         Box::pin(async move {
-            let rpc = self.bitcoind_rpc_client.lock().await;
-            rpc.get_header(header_hash, height_hint).await
+            self.call_with_retries("getblockheader", || async {
+                let rpc = self.bitcoind_rpc_client.lock().await;
+                rpc.get_header(header_hash, height_hint).await
+            })
+            .await
         })
     }
 
     /// Gets a block given its hash.
+    ///
+    /// Blocks pruned by `bitcoind` are reported as a persistent error, given they won't be available again.
     fn get_block<'a>(&'a self, header_hash: &'a BlockHash) -> AsyncBlockSourceResult<'a, Block> {
         Box::pin(async move {
-            let rpc = self.bitcoind_rpc_client.lock().await;
-            rpc.get_block(header_hash).await
+            self.call_with_retries("getblock", || async {
+                let rpc = self.bitcoind_rpc_client.lock().await;
+                rpc.get_block(header_hash).await.map_err(|e| {
+                    if is_pruned_block_error(&e) {
+                        BlockSourceError::persistent(e.into_inner())
+                    } else {
+                        e
+                    }
+                })
+            })
+            .await
         })
     }
 
     /// Get the best block known by our node.
     fn get_best_block(&self) -> AsyncBlockSourceResult<(BlockHash, Option<u32>)> {
         Box::pin(async move {
-            let rpc = self.bitcoind_rpc_client.lock().await;
-            rpc.get_best_block().await
+            self.call_with_retries("getblockchaininfo", || async {
+                let rpc = self.bitcoind_rpc_client.lock().await;
+                rpc.get_best_block().await
+            })
+            .await
+        })
+    }
+}
+
+impl SyncSource for &BitcoindClient<'_> {
+    /// Gets whether our node is still on its initial block download.
+    fn is_syncing(&self) -> AsyncBlockSourceResult<'_, bool> {
+        Box::pin(async move {
+            self.call_with_retries("getblockchaininfo", || async {
+                self.is_initial_block_download()
+                    .await
+                    .map_err(BlockSourceError::transient)
+            })
+            .await
         })
     }
 }
@@ -110,6 +155,8 @@ impl<'a> BitcoindClient<'a> {
             port,
             rpc_user,
             rpc_password,
+            rpc_timeout: DEFAULT_RPC_TIMEOUT,
+            rpc_retries: 0,
         };
 
         // Test that bitcoind is reachable.
@@ -126,6 +173,42 @@ impl<'a> BitcoindClient<'a> {
         }
     }
 
+    /// Sets how long to wait for `bitcoind` to answer a call, and how many times calls are retried if it does not.
+    ///
+    /// Only the calls made through [BlockSource] are retried, given they are all idempotent.
+    pub fn with_rpc_timeout(mut self, rpc_timeout: Duration, rpc_retries: u8) -> Self {
+        self.rpc_timeout = rpc_timeout;
+        self.rpc_retries = rpc_retries;
+        self
+    }
+
+    /// Runs an idempotent call, retrying it up to [rpc_retries](Self::rpc_retries) times if it does not complete within
+    /// [rpc_timeout](Self::rpc_timeout).
+    ///
+    /// Calls that fail (e.g. because the connection is refused) are not retried, so a node that is down can be told apart
+    /// from a node that is slow to answer.
+    async fn call_with_retries<T, F, Fut>(&self, method: &str, call: F) -> BlockSourceResult<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = BlockSourceResult<T>>,
+    {
+        for attempt in 1..=self.rpc_retries {
+            match timeout(self.rpc_timeout, call()).await {
+                Ok(result) => return result,
+                Err(_) => log::warn!(
+                    "bitcoind timed out when calling {method}. Retrying ({attempt}/{})",
+                    self.rpc_retries
+                ),
+            }
+        }
+
+        timeout(self.rpc_timeout, call()).await.unwrap_or_else(|_| {
+            Err(BlockSourceError::transient(format!(
+                "bitcoind timed out when calling {method}"
+            )))
+        })
+    }
+
     /// Gets a fresh RPC client.
     pub fn get_new_rpc_client(&self) -> std::io::Result<RpcClient> {
         let http_endpoint = HttpEndpoint::for_host(self.host.to_owned()).with_port(self.port);
@@ -179,4 +262,28 @@ impl<'a> BitcoindClient<'a> {
 
         Ok(btc_network.0)
     }
+
+    /// Gets whether bitcoind is still on its initial block download (IBD).
+    pub async fn is_initial_block_download(&self) -> std::io::Result<bool> {
+        // A wrapper type to extract "initialblockdownload" key from getblockchaininfo JsonResponse.
+        struct InitialBlockDownload(bool);
+        impl TryInto<InitialBlockDownload> for JsonResponse {
+            type Error = std::io::Error;
+            fn try_into(self) -> std::io::Result<InitialBlockDownload> {
+                self.0["initialblockdownload"]
+                    .as_bool()
+                    .map(InitialBlockDownload)
+                    .ok_or_else(|| {
+                        Error::new(ErrorKind::InvalidData, "missing initialblockdownload")
+                    })
+            }
+        }
+
+        let rpc = self.bitcoind_rpc_client.lock().await;
+        let ibd = rpc
+            .call_method::<InitialBlockDownload>("getblockchaininfo", &[])
+            .await?;
+
+        Ok(ibd.0)
+    }
 }
@@ -1,17 +1,66 @@
 //! Logic related to the Carrier, the component in charge or sending/requesting transaction data from/to `bitcoind`.
 
 use std::collections::HashMap;
+use std::io::ErrorKind;
 use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
 
+use crate::esplora::EsploraClient;
 use crate::responder::ConfirmationStatus;
 use crate::{errors, rpc_errors};
 
 use bitcoin::{Transaction, Txid};
 use bitcoincore_rpc::{
     jsonrpc::error::Error::Rpc as RpcError, jsonrpc::error::Error::Transport as TransportError,
-    Client as BitcoindClient, Error::JsonRpc as JsonRpcError, RpcApi,
+    jsonrpc::simple_http, Auth, Client as BitcoindClient, Error::JsonRpc as JsonRpcError, RpcApi,
 };
 
+/// Reasons bitcoind may refuse a transaction for that do not make it invalid. For instance, it may be paying too little
+/// fees to get into the mempool right now, or it may already be known.
+const NON_DEFINITIVE_REJECTIONS: [&str; 8] = [
+    "txn-already-in-mempool",
+    "txn-already-known",
+    "txn-mempool-conflict",
+    "min relay fee not met",
+    "mempool min fee not met",
+    "insufficient fee",
+    "mempool full",
+    "too-long-mempool-chain",
+];
+
+/// Creates a `bitcoind` RPC client whose calls give up after `timeout`.
+pub fn new_rpc_client(
+    url: &str,
+    auth: Auth,
+    timeout: Duration,
+) -> Result<BitcoindClient, bitcoincore_rpc::Error> {
+    let mut builder = simple_http::Builder::new()
+        .url(url)
+        .map_err(bitcoincore_rpc::jsonrpc::Error::from)?
+        .timeout(timeout);
+    if let (Some(user), pass) = auth.get_user_pass()? {
+        builder = builder.auth(user, pass);
+    }
+
+    Ok(BitcoindClient::from_jsonrpc(
+        bitcoincore_rpc::jsonrpc::Client::with_transport(builder.build()),
+    ))
+}
+
+/// Whether an RPC call failed because `bitcoind` took too long to answer, as opposed to not being reachable at all.
+fn is_timeout(error: &bitcoincore_rpc::Error) -> bool {
+    match error {
+        JsonRpcError(TransportError(e)) => match e.downcast_ref::<simple_http::Error>() {
+            Some(simple_http::Error::Timeout) => true,
+            Some(simple_http::Error::SocketError(e)) => {
+                matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 /// Component in charge of the interaction with Bitcoind by sending / querying transactions via RPC.
 #[derive(Debug)]
 pub struct Carrier {
@@ -24,6 +73,12 @@ pub struct Carrier {
     issued_receipts: HashMap<Txid, ConfirmationStatus>,
     /// The last known block height.
     block_height: u32,
+    /// How many times idempotent RPC calls are retried if `bitcoind` is too slow to answer before flagging it as unreachable.
+    rpc_retries: u8,
+    /// Esplora-compatible API transactions are broadcast through while `bitcoind` is unreachable, if any.
+    broadcast_fallback: Option<EsploraClient>,
+    /// Transactions broadcast through the fallback that `bitcoind` has not been handed yet.
+    fallback_broadcasts: HashMap<Txid, Transaction>,
 }
 
 impl Carrier {
@@ -38,9 +93,25 @@ impl Carrier {
             bitcoind_reachable,
             issued_receipts: HashMap::new(),
             block_height: last_known_block_height,
+            rpc_retries: 0,
+            broadcast_fallback: None,
+            fallback_broadcasts: HashMap::new(),
         }
     }
 
+    /// Sets how many times idempotent RPC calls are retried if `bitcoind` is too slow to answer.
+    pub fn with_rpc_retries(mut self, rpc_retries: u8) -> Self {
+        self.rpc_retries = rpc_retries;
+        self
+    }
+
+    /// Sets an Esplora-compatible API (e.g. `https://mempool.space/api`) to broadcast transactions through while
+    /// `bitcoind` is unreachable.
+    pub fn with_broadcast_fallback(mut self, esplora: Option<EsploraClient>) -> Self {
+        self.broadcast_fallback = esplora;
+        self
+    }
+
     /// The last known block height.
     pub(crate) fn block_height(&self) -> u32 {
         self.block_height
@@ -68,16 +139,56 @@ impl Carrier {
         }
     }
 
+    /// Whether bitcoind is currently flagged as reachable.
+    fn is_bitcoind_reachable(&self) -> bool {
+        *self.bitcoind_reachable.0.lock().unwrap()
+    }
+
+    /// Whether transactions are currently broadcast through the fallback, that is, there is one and bitcoind is flagged
+    /// as unreachable.
+    pub(crate) fn uses_broadcast_fallback(&self) -> bool {
+        self.broadcast_fallback.is_some() && !self.is_bitcoind_reachable()
+    }
+
     /// Flags bitcoind as unreachable.
     fn flag_bitcoind_unreachable(&self) {
         let (lock, _) = &*self.bitcoind_reachable;
         *lock.lock().unwrap() = false;
     }
 
+    /// Runs an idempotent RPC call, retrying it up to [rpc_retries](Self::rpc_retries) times if it times out.
+    ///
+    /// Calls that fail for any other reason (e.g. the connection being refused) are not retried. Non-idempotent calls,
+    /// such as `sendrawtransaction`, must not be run through here.
+    fn call_with_retries<T>(
+        &self,
+        method: &str,
+        call: impl Fn() -> Result<T, bitcoincore_rpc::Error>,
+    ) -> Result<T, bitcoincore_rpc::Error> {
+        let mut result = call();
+        for attempt in 1..=self.rpc_retries {
+            match &result {
+                Err(e) if is_timeout(e) => log::warn!(
+                    "bitcoind timed out when calling {method}. Retrying ({attempt}/{})",
+                    self.rpc_retries
+                ),
+                _ => break,
+            }
+            result = call();
+        }
+        result
+    }
+
     /// Sends a [Transaction] to the Bitcoin network.
     ///
     /// Returns a [ConfirmationStatus] indicating whether the transaction was accepted by the node or not.
     pub(crate) fn send_transaction(&mut self, tx: &Transaction) -> ConfirmationStatus {
+        // Penalties cannot wait for bitcoind to be back, so try the fallback (if any) first.
+        if !self.is_bitcoind_reachable() {
+            if let Some(receipt) = self.send_via_fallback(tx) {
+                return receipt;
+            }
+        }
         self.hang_until_bitcoind_reachable();
 
         if let Some(receipt) = self.issued_receipts.get(&tx.txid()) {
@@ -127,7 +238,8 @@ impl Carrier {
                 }
             },
             Err(JsonRpcError(TransportError(_))) => {
-                // Connection refused, bitcoind is down.
+                // Connection refused, bitcoind is down. Unlike idempotent calls, this is not retried on timeouts, but
+                // only once bitcoind is found to be reachable again.
                 log::error!("Connection lost with bitcoind, retrying request when possible");
                 self.flag_bitcoind_unreachable();
                 self.send_transaction(tx)
@@ -144,6 +256,123 @@ impl Carrier {
         receipt
     }
 
+    /// Sends a [Transaction] to the Bitcoin network through the broadcast fallback, if any.
+    ///
+    /// Returns [None] if there is no fallback or the transaction could not be broadcast through it, in which case the
+    /// transaction is left for `bitcoind` to broadcast once it is reachable again.
+    fn send_via_fallback(&mut self, tx: &Transaction) -> Option<ConfirmationStatus> {
+        let esplora = self.broadcast_fallback.as_ref()?;
+
+        if let Some(receipt) = self.issued_receipts.get(&tx.txid()) {
+            log::info!("Transaction already sent: {}", tx.txid());
+            return Some(*receipt);
+        }
+
+        log::warn!(
+            "bitcoind is unreachable. Pushing transaction to the network through the broadcast fallback: {}",
+            tx.txid()
+        );
+        match esplora.broadcast(tx) {
+            Ok(()) => {
+                log::info!(
+                    "Transaction successfully delivered through the broadcast fallback: {}",
+                    tx.txid()
+                );
+                let receipt = ConfirmationStatus::InMempoolSince(self.block_height);
+                self.issued_receipts.insert(tx.txid(), receipt);
+                self.fallback_broadcasts.insert(tx.txid(), tx.clone());
+                Some(receipt)
+            }
+            Err(e) => {
+                log::error!(
+                    "Transaction couldn't be broadcast through the broadcast fallback: {}. Waiting for bitcoind. Error: {e}",
+                    tx.txid()
+                );
+                None
+            }
+        }
+    }
+
+    /// Hands the transactions broadcast through the fallback to `bitcoind`, so it is aware of them. Does nothing if
+    /// `bitcoind` is still unreachable.
+    ///
+    /// The receipts issued when the transactions were broadcast through the fallback are left untouched, so they are
+    /// not accounted for twice.
+    pub(crate) fn reconcile_fallback_broadcasts(&mut self) {
+        if self.fallback_broadcasts.is_empty() || !self.is_bitcoind_reachable() {
+            return;
+        }
+
+        for (txid, tx) in std::mem::take(&mut self.fallback_broadcasts) {
+            match self.bitcoin_cli.send_raw_transaction(&tx) {
+                Ok(_) => log::info!("Transaction broadcast through the fallback handed to bitcoind: {txid}"),
+                Err(JsonRpcError(TransportError(_))) => {
+                    // Connection refused, bitcoind is down again. Keep the transaction to be handed over later on.
+                    log::error!("Connection lost with bitcoind, retrying request when possible");
+                    self.flag_bitcoind_unreachable();
+                    self.fallback_broadcasts.insert(txid, tx);
+                }
+                // Most likely, bitcoind already knows about the transaction.
+                Err(e) => log::info!(
+                    "bitcoind refused transaction broadcast through the fallback: {txid}. Error: {e:?}"
+                ),
+            }
+        }
+    }
+
+    /// Sends a [Transaction] to the Bitcoin network even if it has already been sent.
+    ///
+    /// Unlike [Carrier::send_transaction], the receipts cached by the [Carrier] are disregarded (and replaced), so the
+    /// transaction is always pushed to `bitcoind`.
+    pub(crate) fn resend_transaction(&mut self, tx: &Transaction) -> ConfirmationStatus {
+        self.issued_receipts.remove(&tx.txid());
+        self.send_transaction(tx)
+    }
+
+    /// Checks whether a [Transaction] would be accepted to the mempool, without broadcasting it.
+    ///
+    /// This uses `testmempoolaccept` under the hood. Returns the reason given by `bitcoind` if the transaction is
+    /// definitely invalid (e.g. it has a bad signature or its inputs have already been spent). Transactions that are
+    /// refused for transient reasons (see [NON_DEFINITIVE_REJECTIONS]), or that cannot be checked at all, are reported
+    /// as acceptable so it's up to [Carrier::send_transaction] to decide.
+    pub(crate) fn test_accept(&self, tx: &Transaction) -> Result<(), String> {
+        self.hang_until_bitcoind_reachable();
+
+        match self.call_with_retries("testmempoolaccept", || {
+            self.bitcoin_cli.test_mempool_accept(&[tx])
+        }) {
+            Ok(results) => match results.into_iter().next() {
+                Some(result) if !result.allowed => {
+                    let reason = result.reject_reason.unwrap_or_default();
+                    if NON_DEFINITIVE_REJECTIONS
+                        .iter()
+                        .any(|r| reason.starts_with(r))
+                    {
+                        log::info!(
+                            "Transaction not accepted for now: {}. Reason: {reason}",
+                            tx.txid()
+                        );
+                        Ok(())
+                    } else {
+                        log::error!("Transaction is invalid: {}. Reason: {reason}", tx.txid());
+                        Err(reason)
+                    }
+                }
+                _ => Ok(()),
+            },
+            Err(JsonRpcError(TransportError(_))) => {
+                // Connection refused, bitcoind is down.
+                log::error!("Connection lost with bitcoind, retrying request when possible");
+                self.flag_bitcoind_unreachable();
+                self.test_accept(tx)
+            }
+            Err(e) => {
+                log::error!("Unexpected error when calling testmempoolaccept: {e:?}");
+                Ok(())
+            }
+        }
+    }
+
     /// Checks whether a given transaction can be found in the mempool.
     ///
     /// This uses `getrawtransaction` under the hood and, therefore, its behavior depends on whether `txindex` is enabled in bitcoind.
@@ -152,7 +381,9 @@ impl Carrier {
     pub(crate) fn in_mempool(&self, txid: &Txid) -> bool {
         self.hang_until_bitcoind_reachable();
 
-        match self.bitcoin_cli.get_raw_transaction_info(txid, None) {
+        match self.call_with_retries("getrawtransaction", || {
+            self.bitcoin_cli.get_raw_transaction_info(txid, None)
+        }) {
             Ok(tx) => tx.blockhash.is_none(),
             Err(JsonRpcError(RpcError(rpcerr))) => match rpcerr.code {
                 rpc_errors::RPC_INVALID_ADDRESS_OR_KEY => {
@@ -179,6 +410,39 @@ impl Carrier {
             }
         }
     }
+
+    /// Estimates the feerate (in sat/vB) needed for a transaction to confirm within `conf_target` blocks, rounded down.
+    ///
+    /// Returns [None] if `bitcoind` does not have enough data to provide an estimate.
+    pub(crate) fn estimate_feerate(&self, conf_target: u16) -> Option<u64> {
+        self.estimate_exact_feerate(conf_target)
+            .map(|feerate| feerate as u64)
+    }
+
+    /// Estimates the feerate (in sat/vB) needed for a transaction to confirm within `conf_target` blocks, keeping its
+    /// fractional part.
+    ///
+    /// Returns [None] if `bitcoind` does not have enough data to provide an estimate.
+    pub(crate) fn estimate_exact_feerate(&self, conf_target: u16) -> Option<f64> {
+        self.hang_until_bitcoind_reachable();
+
+        match self.call_with_retries("estimatesmartfee", || {
+            self.bitcoin_cli.estimate_smart_fee(conf_target, None)
+        }) {
+            // bitcoind returns feerates in BTC/kvB.
+            Ok(estimate) => estimate.fee_rate.map(|rate| rate.as_sat() as f64 / 1000.0),
+            Err(JsonRpcError(TransportError(_))) => {
+                // Connection refused, bitcoind is down.
+                log::error!("Connection lost with bitcoind, retrying request when possible");
+                self.flag_bitcoind_unreachable();
+                self.estimate_exact_feerate(conf_target)
+            }
+            Err(e) => {
+                log::error!("Unexpected error when calling estimatesmartfee: {e:?}");
+                None
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -186,7 +450,9 @@ mod tests {
     use super::*;
     use std::thread;
 
-    use crate::test_utils::{get_random_tx, start_server, BitcoindMock, MockOptions, START_HEIGHT};
+    use crate::test_utils::{
+        get_random_tx, start_server, BitcoindMock, MockOptions, MOCKED_FEERATE, START_HEIGHT,
+    };
     use teos_common::test_utils::{TXID_HEX, TX_HEX};
 
     use bitcoin::consensus;
@@ -203,6 +469,11 @@ mod tests {
         pub(crate) fn get_height(&self) -> u32 {
             self.block_height
         }
+
+        // Helper function to flag bitcoind as unreachable in tests
+        pub(crate) fn set_bitcoind_unreachable(&self) {
+            self.flag_bitcoind_unreachable()
+        }
     }
 
     #[test]
@@ -380,6 +651,85 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_send_transaction_broadcast_fallback() {
+        let bitcoind_mock = BitcoindMock::new(MockOptions::default());
+        let bitcoind_reachable = Arc::new((Mutex::new(false), Condvar::new()));
+        let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+        let start_height = START_HEIGHT as u32;
+        start_server(bitcoind_mock.server);
+
+        let mut esplora = mockito::Server::new();
+        let esplora_mock = esplora
+            .mock("POST", "/tx")
+            .match_body(TX_HEX)
+            .with_status(200)
+            .with_body(TXID_HEX)
+            .expect(1)
+            .create();
+
+        let mut carrier = Carrier::new(bitcoin_cli, bitcoind_reachable.clone(), start_height)
+            .with_broadcast_fallback(Some(EsploraClient::new(&esplora.url()).unwrap()));
+        let tx: Transaction = consensus::deserialize(&Vec::from_hex(TX_HEX).unwrap()).unwrap();
+
+        // bitcoind is down, but the transaction does not wait for it
+        let r = carrier.send_transaction(&tx);
+        assert_eq!(r, ConfirmationStatus::InMempoolSince(start_height));
+        assert!(carrier.fallback_broadcasts.contains_key(&tx.txid()));
+
+        // Sending it again is not pushed through the fallback again
+        assert_eq!(carrier.send_transaction(&tx), r);
+        esplora_mock.assert();
+
+        // Nothing is reconciled while bitcoind is unreachable
+        carrier.reconcile_fallback_broadcasts();
+        assert!(carrier.fallback_broadcasts.contains_key(&tx.txid()));
+
+        // Once bitcoind is back, it is handed the transaction, but the receipt is not issued twice
+        *bitcoind_reachable.0.lock().unwrap() = true;
+        carrier.reconcile_fallback_broadcasts();
+        assert!(carrier.fallback_broadcasts.is_empty());
+        assert_eq!(carrier.issued_receipts.len(), 1);
+        assert_eq!(carrier.issued_receipts.get(&tx.txid()).unwrap(), &r);
+    }
+
+    #[test]
+    fn test_send_transaction_broadcast_fallback_rejected() {
+        let bitcoind_mock = BitcoindMock::new(MockOptions::default());
+        let bitcoind_reachable = Arc::new((Mutex::new(false), Condvar::new()));
+        let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+        let start_height = START_HEIGHT as u32;
+        start_server(bitcoind_mock.server);
+
+        let mut esplora = mockito::Server::new();
+        let esplora_mock = esplora
+            .mock("POST", "/tx")
+            .with_status(400)
+            .with_body("sendrawtransaction RPC error")
+            .expect(1)
+            .create();
+
+        let mut carrier = Carrier::new(bitcoin_cli, bitcoind_reachable.clone(), start_height)
+            .with_broadcast_fallback(Some(EsploraClient::new(&esplora.url()).unwrap()));
+        let tx: Transaction = consensus::deserialize(&Vec::from_hex(TX_HEX).unwrap()).unwrap();
+        let delay = std::time::Duration::new(1, 0);
+
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let (reachable, notifier) = &*bitcoind_reachable;
+            *reachable.lock().unwrap() = true;
+            notifier.notify_all();
+        });
+
+        // If the fallback cannot broadcast the transaction, it waits for bitcoind
+        let before = std::time::Instant::now();
+        let r = carrier.send_transaction(&tx);
+        assert!(std::time::Instant::now() - before >= delay);
+        assert_eq!(r, ConfirmationStatus::InMempoolSince(start_height));
+        assert!(carrier.fallback_broadcasts.is_empty());
+        esplora_mock.assert();
+    }
+
     #[test]
     fn test_in_mempool() {
         let bitcoind_mock = BitcoindMock::new(MockOptions::in_mempool());
@@ -463,4 +813,116 @@ mod tests {
             delay.as_secs()
         );
     }
+
+    #[test]
+    fn test_test_accept() {
+        let bitcoind_mock = BitcoindMock::new(MockOptions::default());
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+        let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+        start_server(bitcoind_mock.server);
+
+        let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, START_HEIGHT as u32);
+        assert_eq!(carrier.test_accept(&get_random_tx()), Ok(()));
+    }
+
+    #[test]
+    fn test_test_accept_rejected() {
+        // Transactions refused for transient reasons are not considered invalid
+        for reason in [
+            "txn-already-in-mempool",
+            "mempool min fee not met, 100 < 200",
+        ] {
+            let bitcoind_mock = BitcoindMock::new(MockOptions::with_mempool_rejection(reason));
+            let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+            let bitcoin_cli =
+                Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+            start_server(bitcoind_mock.server);
+
+            let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, START_HEIGHT as u32);
+            assert_eq!(carrier.test_accept(&get_random_tx()), Ok(()));
+        }
+
+        // While others are
+        for reason in [
+            "missing-inputs",
+            "mandatory-script-verify-flag-failed (Signature must be zero for failed CHECK(MULTI)SIG operation)",
+        ] {
+            let bitcoind_mock = BitcoindMock::new(MockOptions::with_mempool_rejection(reason));
+            let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+            let bitcoin_cli =
+                Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+            start_server(bitcoind_mock.server);
+
+            let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, START_HEIGHT as u32);
+            assert_eq!(
+                carrier.test_accept(&get_random_tx()),
+                Err(reason.to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn test_test_accept_unexpected_error() {
+        // If the transaction cannot be checked, it is given the benefit of the doubt
+        let bitcoind_mock =
+            BitcoindMock::new(MockOptions::with_error(rpc_errors::RPC_MISC_ERROR as i64));
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+        let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+        start_server(bitcoind_mock.server);
+
+        let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, START_HEIGHT as u32);
+        assert_eq!(carrier.test_accept(&get_random_tx()), Ok(()));
+    }
+
+    #[test]
+    fn test_estimate_feerate() {
+        let bitcoind_mock = BitcoindMock::new(MockOptions::default());
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+        let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+        start_server(bitcoind_mock.server);
+
+        let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, START_HEIGHT as u32);
+        assert_eq!(carrier.estimate_feerate(6), Some(MOCKED_FEERATE));
+    }
+
+    #[test]
+    fn test_estimate_exact_feerate() {
+        let bitcoind_mock = BitcoindMock::new(MockOptions::with_feerate(12.5));
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+        let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+        start_server(bitcoind_mock.server);
+
+        // The fractional part is only kept if asked for
+        let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, START_HEIGHT as u32);
+        assert_eq!(carrier.estimate_exact_feerate(6), Some(12.5));
+        assert_eq!(carrier.estimate_feerate(6), Some(12));
+    }
+
+    #[test]
+    fn test_estimate_feerate_slow_bitcoind() {
+        // If bitcoind is slow to answer, but does so within the retry budget, it is not flagged as unreachable
+        let bitcoind_mock = BitcoindMock::new(MockOptions::slow(Duration::from_secs(2), 1));
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+        let bitcoin_cli = Arc::new(
+            new_rpc_client(bitcoind_mock.url(), Auth::None, Duration::from_secs(1)).unwrap(),
+        );
+        start_server(bitcoind_mock.server);
+
+        let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable.clone(), START_HEIGHT as u32)
+            .with_rpc_retries(1);
+        assert_eq!(carrier.estimate_feerate(6), Some(MOCKED_FEERATE));
+        assert!(*bitcoind_reachable.0.lock().unwrap());
+    }
+
+    #[test]
+    fn test_estimate_feerate_unexpected_error() {
+        let bitcoind_mock =
+            BitcoindMock::new(MockOptions::with_error(rpc_errors::RPC_MISC_ERROR as i64));
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+        let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
+        start_server(bitcoind_mock.server);
+
+        let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, START_HEIGHT as u32);
+        assert_eq!(carrier.estimate_feerate(6), None);
+    }
 }
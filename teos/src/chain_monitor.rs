@@ -1,22 +1,96 @@
 //! Logic related to the ChainMonitor, the component in charge of querying block data from `bitcoind`.
 //!
 
-use std::ops::Deref;
+use std::future;
+use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Condvar, Mutex};
 use std::time;
-use tokio::time::timeout;
+use tokio::time::{sleep, timeout};
 use triggered::Listener;
+use zeromq::{Socket, SocketRecv, SubSocket, ZmqError, ZmqMessage};
 
 use lightning::chain;
-use lightning_block_sync::poll::{ChainTip, Poll, ValidatedBlockHeader};
-use lightning_block_sync::{BlockSourceErrorKind, Cache, SpvClient};
+use lightning_block_sync::poll::{
+    ChainPoller, ChainTip, Poll, ValidatedBlock, ValidatedBlockHeader,
+};
+use lightning_block_sync::{
+    AsyncBlockSourceResult, BlockSource, BlockSourceError, BlockSourceErrorKind, Cache, SpvClient,
+};
 
 use crate::dbm::DBM;
 
+/// The `bitcoind` ZMQ topics that notify about new blocks.
+const ZMQ_BLOCK_TOPICS: [&str; 2] = ["hashblock", "rawblock"];
+/// How long to wait for a ZMQ subscription to be set up before giving up (and falling back to polling).
+const ZMQ_CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(5);
+
+/// The error `bitcoind` answers `getblock` with when running in pruned mode and the block data is already gone.
+pub const PRUNED_BLOCK_ERROR: &str = "Block not available (pruned data)";
+
+/// Returns whether a block source error is due to the requested block having been pruned by `bitcoind`.
+pub fn is_pruned_block_error(e: &BlockSourceError) -> bool {
+    // The underlying error is only reachable by value, but its message is part of the debug representation.
+    format!("{e:?}").contains(PRUNED_BLOCK_ERROR)
+}
+
+/// Fetches the last `n` blocks of the chain, from `last_known_block` backwards.
+///
+/// If `bitcoind` is running in pruned mode, old blocks may not be available anymore. In that case, the blocks from the
+/// first pruned one backwards are skipped and only the available ones are returned, so callers must be ready to get
+/// less than `n` blocks (reorgs deeper than that cannot be handled). Any other error is returned.
+pub async fn get_last_n_blocks<B, T>(
+    poller: &mut ChainPoller<B, T>,
+    mut last_known_block: ValidatedBlockHeader,
+    n: usize,
+) -> Result<Vec<ValidatedBlock>, BlockSourceError>
+where
+    B: DerefMut<Target = T> + Sized + Send + Sync,
+    T: BlockSource,
+{
+    let mut last_n_blocks = Vec::with_capacity(n);
+    for _ in 0..n {
+        log::debug!("Fetching block #{}", last_known_block.height);
+        let block = match poller.fetch_block(&last_known_block).await {
+            Ok(block) => block,
+            Err(e) if is_pruned_block_error(&e) => {
+                log::warn!(
+                    "Block #{} has been pruned by bitcoind. Skipping it and any older block, only the last {} blocks are available",
+                    last_known_block.height,
+                    last_n_blocks.len()
+                );
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        last_known_block = poller.look_up_previous_header(&last_known_block).await?;
+        last_n_blocks.push(block);
+    }
+
+    Ok(last_n_blocks)
+}
+
+/// A source that can tell whether `bitcoind` is still on its initial block download (IBD).
+///
+/// The chain reported by `bitcoind` while syncing is not the actual tip, so it cannot be relied on to look for breaches.
+pub trait SyncSource {
+    /// Returns whether `bitcoind` is still syncing the chain.
+    fn is_syncing(&self) -> AsyncBlockSourceResult<'_, bool>;
+}
+
+/// Waits for a new block notification from `bitcoind`. Never returns if there is no active subscription.
+async fn wait_for_block(subscription: &mut Option<SubSocket>) -> Result<ZmqMessage, ZmqError> {
+    match subscription {
+        Some(socket) => socket.recv().await,
+        None => future::pending().await,
+    }
+}
+
 /// Component in charge of monitoring the chain for new blocks.
 ///
 /// Takes care of polling `bitcoind` for new tips and hand it to subscribers.
 /// It is mainly a wrapper around [chain::Listen] that provides some logging.
+///
+/// If `bitcoind` block notifications are set up (via ZMQ), the best tip is also polled as soon as a new block is notified.
 pub struct ChainMonitor<'a, P, C, L>
 where
     P: Poll,
@@ -36,6 +110,14 @@ where
     shutdown_signal: Listener,
     /// A flag that indicates wether bitcoind is reachable or not.
     bitcoind_reachable: Arc<(Mutex<bool>, Condvar)>,
+    /// The `bitcoind` ZMQ endpoint new blocks are notified to, if any.
+    zmq_endpoint: Option<String>,
+    /// The subscription to [zmq_endpoint](Self::zmq_endpoint). [None] means the [ChainMonitor] is only polling.
+    zmq_subscription: Option<SubSocket>,
+    /// A source to check whether `bitcoind` is still syncing before processing any block, if any.
+    sync_source: Option<Box<dyn SyncSource + 'a>>,
+    /// A flag that indicates whether bitcoind is still on its initial block download or not.
+    bitcoind_syncing: Arc<Mutex<bool>>,
 }
 
 impl<'a, P, C, L> ChainMonitor<'a, P, C, L>
@@ -50,7 +132,7 @@ where
         spv_client: SpvClient<'a, P, C, L>,
         last_known_block_header: ValidatedBlockHeader,
         dbm: Arc<Mutex<DBM>>,
-        polling_delta_sec: u16,
+        polling_delta: time::Duration,
         shutdown_signal: Listener,
         bitcoind_reachable: Arc<(Mutex<bool>, Condvar)>,
     ) -> ChainMonitor<'a, P, C, L> {
@@ -58,14 +140,106 @@ where
             spv_client,
             last_known_block_header,
             dbm,
-            polling_delta: time::Duration::from_secs(polling_delta_sec as u64),
+            polling_delta,
             shutdown_signal,
             bitcoind_reachable,
+            zmq_endpoint: None,
+            zmq_subscription: None,
+            sync_source: None,
+            bitcoind_syncing: Arc::new(Mutex::new(false)),
+        }
+    }
+
+    /// Sets a source to check whether `bitcoind` is on its initial block download (IBD) every time the tip is polled.
+    ///
+    /// No block is processed while `bitcoind` is syncing. `bitcoind_syncing` reflects whether it is so it can be reported.
+    pub fn with_sync_source<S: SyncSource + 'a>(
+        mut self,
+        sync_source: S,
+        bitcoind_syncing: Arc<Mutex<bool>>,
+    ) -> Self {
+        self.sync_source = Some(Box::new(sync_source));
+        self.bitcoind_syncing = bitcoind_syncing;
+        self
+    }
+
+    /// Checks whether `bitcoind` is still on its initial block download, updating [bitcoind_syncing](Self::bitcoind_syncing).
+    ///
+    /// If the check fails, the last known status is kept (connection errors are handled when polling the tip).
+    async fn check_syncing(&self) -> bool {
+        let sync_source = match &self.sync_source {
+            Some(sync_source) => sync_source,
+            None => return false,
+        };
+
+        let was_syncing = *self.bitcoind_syncing.lock().unwrap();
+        let syncing = match sync_source.is_syncing().await {
+            Ok(syncing) => syncing,
+            Err(e) => {
+                log::debug!("Cannot check whether bitcoind is syncing: {e:?}");
+                was_syncing
+            }
+        };
+
+        if syncing && !was_syncing {
+            log::warn!("bitcoind is syncing (initial block download). Blocks won't be processed until it is done");
+        } else if !syncing && was_syncing {
+            log::info!("bitcoind is done syncing. Resuming block processing");
         }
+        *self.bitcoind_syncing.lock().unwrap() = syncing;
+
+        syncing
+    }
+
+    /// Sets the `bitcoind` ZMQ endpoint to get block notifications from (`zmqpubhashblock` or `zmqpubrawblock`).
+    pub fn with_zmq(mut self, endpoint: String) -> Self {
+        self.zmq_endpoint = Some(endpoint);
+        self
+    }
+
+    /// Subscribes to `bitcoind` block notifications, if a ZMQ endpoint is set. Any previous subscription is replaced.
+    ///
+    /// If the subscription cannot be set up, the [ChainMonitor] falls back to polling.
+    async fn subscribe(&mut self) {
+        let endpoint = match &self.zmq_endpoint {
+            Some(endpoint) => endpoint.clone(),
+            None => return,
+        };
+
+        let mut socket = SubSocket::new();
+        let subscription = timeout(ZMQ_CONNECT_TIMEOUT, async {
+            socket.connect(&endpoint).await?;
+            for topic in ZMQ_BLOCK_TOPICS {
+                socket.subscribe(topic).await?;
+            }
+            Ok::<(), ZmqError>(())
+        })
+        .await;
+
+        self.zmq_subscription = match subscription {
+            Ok(Ok(())) => {
+                log::info!("Subscribed to block notifications on {endpoint}");
+                Some(socket)
+            }
+            Ok(Err(e)) => {
+                log::warn!("Cannot subscribe to block notifications on {endpoint} ({e}). Falling back to polling");
+                None
+            }
+            Err(_) => {
+                log::warn!("Timed out subscribing to block notifications on {endpoint}. Falling back to polling");
+                None
+            }
+        };
     }
 
     /// Polls the best chain tip from bitcoind. Serves the data to its listeners (through [chain::Listen]) and logs data about the polled tips.
+    ///
+    /// Nothing is polled while `bitcoind` is on its initial block download.
     pub async fn poll_best_tip(&mut self) {
+        if self.check_syncing().await {
+            return;
+        }
+
         let (reachable, notifier) = &*self.bitcoind_reachable;
         match self.spv_client.poll_best_tip().await {
             Ok((chain_tip, _)) => {
@@ -112,16 +286,47 @@ where
     }
 
     /// Monitors `bitcoind` polling the best chain tip every [polling_delta](Self::polling_delta).
+    ///
+    /// The first poll happens right away, so the [ChainMonitor] catches up with the chain as soon as it is started.
+    /// If subscribed to `bitcoind` block notifications, the best tip is also polled whenever a new block is notified.
+    /// A dropped subscription is renewed once `bitcoind` is found to be reachable, and polling is used in the meantime.
     pub async fn monitor_chain(&mut self) {
+        self.subscribe().await;
+        let mut was_reachable = true;
+
         loop {
             self.poll_best_tip().await;
-            // Sleep for self.polling_delta seconds or shutdown if the signal is received.
-            if timeout(self.polling_delta, self.shutdown_signal.clone())
-                .await
-                .is_ok()
+
+            // If bitcoind went unreachable, the ZMQ connection most likely went down with it, so renew it.
+            let reachable = *self.bitcoind_reachable.0.lock().unwrap();
+            if reachable
+                && self.zmq_endpoint.is_some()
+                && (!was_reachable || self.zmq_subscription.is_none())
             {
-                log::debug!("Received shutting down signal. Shutting down");
-                break;
+                self.subscribe().await;
+            }
+            was_reachable = reachable;
+
+            // Sleep for self.polling_delta seconds, wake up early if a new block is notified, or shutdown if the signal is received.
+            let notification = tokio::select! {
+                _ = self.shutdown_signal.clone() => {
+                    log::debug!("Received shutting down signal. Shutting down");
+                    break;
+                }
+                _ = sleep(self.polling_delta) => None,
+                notification = wait_for_block(&mut self.zmq_subscription) => Some(notification),
+            };
+
+            match notification {
+                Some(Ok(_)) => log::debug!("New block notified by bitcoind"),
+                Some(Err(e)) => {
+                    // Polling right away re-checks whether bitcoind is still reachable.
+                    log::warn!(
+                        "Block notifications subscription dropped ({e}). Falling back to polling"
+                    );
+                    self.zmq_subscription = None;
+                }
+                None => (),
             }
         }
     }
@@ -135,9 +340,11 @@ mod tests {
     use std::iter::FromIterator;
     use std::thread;
 
+    use bitcoin::hashes::Hash;
     use bitcoin::network::constants::Network;
     use bitcoin::BlockHash;
     use lightning_block_sync::{poll::ChainPoller, SpvClient, UnboundedCache};
+    use zeromq::{PubSocket, SocketSend};
 
     use crate::test_utils::{Blockchain, START_HEIGHT};
 
@@ -174,6 +381,83 @@ mod tests {
         }
     }
 
+    /// Mocks a bitcoind that can be set to be on its initial block download.
+    struct IBDMock(Arc<Mutex<bool>>);
+
+    impl SyncSource for IBDMock {
+        fn is_syncing(&self) -> AsyncBlockSourceResult<'_, bool> {
+            Box::pin(async move { Ok(*self.0.lock().unwrap()) })
+        }
+    }
+
+    #[test]
+    fn test_is_pruned_block_error() {
+        let pruned = std::io::Error::other(PRUNED_BLOCK_ERROR);
+        assert!(is_pruned_block_error(&BlockSourceError::transient(pruned)));
+        assert!(is_pruned_block_error(&BlockSourceError::persistent(
+            PRUNED_BLOCK_ERROR
+        )));
+        assert!(!is_pruned_block_error(&BlockSourceError::transient(
+            "Connection refused"
+        )));
+    }
+
+    #[tokio::test]
+    async fn test_get_last_n_blocks() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let tip = chain.tip();
+        let expected: Vec<BlockHash> = (START_HEIGHT - 9..=START_HEIGHT)
+            .rev()
+            .map(|h| chain.blocks[h].block_hash())
+            .collect();
+
+        let mut poller = ChainPoller::new(&mut chain, Network::Bitcoin);
+        let blocks = get_last_n_blocks(&mut poller, tip, 10).await.unwrap();
+        assert_eq!(
+            blocks.iter().map(|b| b.block_hash()).collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_last_n_blocks_pruned() {
+        // Blocks below the prune height are skipped, so only the available ones are returned
+        let prune_height = START_HEIGHT - 4;
+        let mut chain = Blockchain::default()
+            .with_height(START_HEIGHT)
+            .pruned_below(prune_height);
+        let tip = chain.tip();
+        let expected: Vec<BlockHash> = (prune_height..=START_HEIGHT)
+            .rev()
+            .map(|h| chain.blocks[h].block_hash())
+            .collect();
+
+        let mut poller = ChainPoller::new(&mut chain, Network::Bitcoin);
+        let blocks = get_last_n_blocks(&mut poller, tip, 10).await.unwrap();
+        assert_eq!(
+            blocks.iter().map(|b| b.block_hash()).collect::<Vec<_>>(),
+            expected
+        );
+
+        // Blocks are chained from the tip backwards, so they can still be used to build a TxIndex
+        for (block, prev) in blocks.iter().zip(blocks.iter().skip(1)) {
+            assert_eq!(block.header.prev_blockhash, prev.block_hash());
+        }
+
+        // If nothing is pruned within the requested range, everything is returned
+        let mut poller = ChainPoller::new(&mut chain, Network::Bitcoin);
+        let blocks = get_last_n_blocks(&mut poller, tip, 3).await.unwrap();
+        assert_eq!(blocks.len(), 3);
+
+        // Any other error is still returned
+        let mut chain = Blockchain::default()
+            .with_height(START_HEIGHT)
+            .without_blocks(START_HEIGHT - 4..);
+        let tip = chain.tip();
+        let mut poller = ChainPoller::new(&mut chain, Network::Bitcoin);
+        assert!(get_last_n_blocks(&mut poller, tip, 10).await.is_err());
+    }
+
     #[tokio::test]
     async fn test_poll_best_tip_common() {
         let mut chain = Blockchain::default().with_height(START_HEIGHT);
@@ -188,8 +472,15 @@ mod tests {
         let spv_client = SpvClient::new(tip, poller, cache, &listener);
         let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
 
-        let mut cm =
-            ChainMonitor::new(spv_client, tip, dbm, 1, shutdown_signal, bitcoind_reachable).await;
+        let mut cm = ChainMonitor::new(
+            spv_client,
+            tip,
+            dbm,
+            time::Duration::from_secs(1),
+            shutdown_signal,
+            bitcoind_reachable,
+        )
+        .await;
 
         // If there's no new block nothing gets connected nor disconnected
         cm.poll_best_tip().await;
@@ -216,7 +507,7 @@ mod tests {
             spv_client,
             old_tip,
             dbm,
-            1,
+            time::Duration::from_secs(1),
             shutdown_signal,
             bitcoind_reachable,
         )
@@ -236,6 +527,55 @@ mod tests {
         assert!(listener.disconnected_blocks.borrow().is_empty());
     }
 
+    #[tokio::test]
+    async fn test_poll_best_tip_syncing() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let new_tip = chain.tip();
+        let old_tip = chain.at_height(START_HEIGHT - 1);
+        let polls = chain.polls.clone();
+
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let (_, shutdown_signal) = triggered::trigger();
+        let listener = DummyListener::new();
+
+        let poller = ChainPoller::new(&mut chain, Network::Bitcoin);
+        let cache = &mut UnboundedCache::new();
+        let spv_client = SpvClient::new(old_tip, poller, cache, &listener);
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+        let ibd = Arc::new(Mutex::new(true));
+        let bitcoind_syncing = Arc::new(Mutex::new(false));
+
+        let mut cm = ChainMonitor::new(
+            spv_client,
+            old_tip,
+            dbm,
+            time::Duration::from_secs(1),
+            shutdown_signal,
+            bitcoind_reachable,
+        )
+        .await
+        .with_sync_source(IBDMock(ibd.clone()), bitcoind_syncing.clone());
+
+        // While bitcoind is on its initial block download the chain is not even polled, so nothing gets connected
+        for _ in 0..3 {
+            cm.poll_best_tip().await;
+            assert!(*bitcoind_syncing.lock().unwrap());
+            assert_eq!(cm.last_known_block_header, old_tip);
+            assert!(polls.lock().unwrap().is_empty());
+            assert!(listener.connected_blocks.borrow().is_empty());
+        }
+
+        // Once it is done syncing, the new tip is processed as usual
+        *ibd.lock().unwrap() = false;
+        cm.poll_best_tip().await;
+        assert!(!*bitcoind_syncing.lock().unwrap());
+        assert_eq!(cm.last_known_block_header, new_tip);
+        assert!(listener
+            .connected_blocks
+            .borrow()
+            .contains(&new_tip.deref().header.block_hash()));
+    }
+
     #[tokio::test]
     async fn test_poll_best_tip_worse() {
         let mut chain = Blockchain::default().with_height(START_HEIGHT);
@@ -255,7 +595,7 @@ mod tests {
             spv_client,
             best_tip,
             dbm,
-            1,
+            time::Duration::from_secs(1),
             shutdown_signal,
             bitcoind_reachable,
         )
@@ -294,7 +634,7 @@ mod tests {
             spv_client,
             old_best,
             dbm,
-            1,
+            time::Duration::from_secs(1),
             shutdown_signal,
             bitcoind_reachable,
         )
@@ -333,7 +673,7 @@ mod tests {
             spv_client,
             tip,
             dbm,
-            1,
+            time::Duration::from_secs(1),
             shutdown_signal,
             bitcoind_reachable.clone(),
         )
@@ -361,4 +701,114 @@ mod tests {
         // This would hang if the cm didn't notify their subscribers about the bitcoind status, so it serves as out assert.
         t.join().unwrap();
     }
+
+    #[tokio::test]
+    async fn test_monitor_chain_zmq() {
+        let mut chain = Blockchain::default()
+            .with_height(START_HEIGHT)
+            .unreachable();
+        let chain_offline = chain.unreachable.clone();
+        let new_tip = chain.tip();
+        let old_tip = chain.at_height(START_HEIGHT - 1);
+
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let (shutdown_trigger, shutdown_signal) = triggered::trigger();
+        let listener = DummyListener::new();
+
+        let poller = ChainPoller::new(&mut chain, Network::Bitcoin);
+        let cache = &mut UnboundedCache::new();
+        let spv_client = SpvClient::new(old_tip, poller, cache, &listener);
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+
+        // Mock bitcoind's ZMQ block notifications
+        let mut publisher = PubSocket::new();
+        let endpoint = publisher.bind("tcp://127.0.0.1:0").await.unwrap();
+
+        // The polling delta is big enough for the new tip to only be found if a block is notified
+        let mut cm = ChainMonitor::new(
+            spv_client,
+            old_tip,
+            dbm,
+            time::Duration::from_secs(u16::MAX as u64),
+            shutdown_signal,
+            bitcoind_reachable.clone(),
+        )
+        .await
+        .with_zmq(endpoint.to_string());
+
+        let notify_block = async {
+            // Wait for the first poll to fail and set bitcoind back online
+            while *bitcoind_reachable.0.lock().unwrap() {
+                sleep(time::Duration::from_millis(10)).await;
+            }
+            *chain_offline.lock().unwrap() = false;
+
+            // Notify the block until it is connected (notifications sent before the subscription is set are lost)
+            let block_hash = new_tip.deref().header.block_hash();
+            while !listener.connected_blocks.borrow().contains(&block_hash) {
+                let mut message = ZmqMessage::from("hashblock");
+                message.push_back(block_hash.into_inner().to_vec().into());
+                publisher.send(message).await.unwrap();
+                sleep(time::Duration::from_millis(100)).await;
+            }
+            shutdown_trigger.trigger();
+        };
+
+        timeout(time::Duration::from_secs(10), async {
+            tokio::join!(cm.monitor_chain(), notify_block)
+        })
+        .await
+        .unwrap();
+
+        // The block was connected and bitcoind flagged as reachable again.
+        assert_eq!(cm.last_known_block_header, new_tip);
+        assert!(*bitcoind_reachable.0.lock().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_monitor_chain_polling() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let polls = chain.polls.clone();
+        let tip = chain.tip();
+
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let (shutdown_trigger, shutdown_signal) = triggered::trigger();
+        let listener = DummyListener::new();
+
+        let poller = ChainPoller::new(&mut chain, Network::Bitcoin);
+        let cache = &mut UnboundedCache::new();
+        let spv_client = SpvClient::new(tip, poller, cache, &listener);
+        let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
+
+        let polling_delta = time::Duration::from_millis(200);
+        let mut cm = ChainMonitor::new(
+            spv_client,
+            tip,
+            dbm,
+            polling_delta,
+            shutdown_signal,
+            bitcoind_reachable,
+        )
+        .await;
+
+        let started_at = time::Instant::now();
+        let shutdown = async {
+            while polls.lock().unwrap().len() < 3 {
+                sleep(time::Duration::from_millis(10)).await;
+            }
+            shutdown_trigger.trigger();
+        };
+        timeout(time::Duration::from_secs(10), async {
+            tokio::join!(cm.monitor_chain(), shutdown)
+        })
+        .await
+        .unwrap();
+
+        // The first poll happens right away, whereas the following ones wait for the polling delta
+        let polls = polls.lock().unwrap();
+        assert!(polls[0] - started_at < polling_delta);
+        for window in polls.windows(2) {
+            assert!(window[1] - window[0] >= polling_delta);
+        }
+    }
 }
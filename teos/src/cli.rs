@@ -118,6 +118,63 @@ async fn main() {
                 Err(e) => handle_error(e),
             };
         }
+        Command::TriggerPenalty(data) => {
+            match (
+                Locator::from_hex(&data.locator),
+                UserId::from_str(&data.user_id),
+                Vec::from_hex(&data.dispute_tx),
+            ) {
+                (Ok(locator), Ok(user_id), Ok(dispute_tx)) => {
+                    match client
+                        .trigger_penalty(Request::new(msgs::TriggerPenaltyRequest {
+                            locator: locator.to_vec(),
+                            user_id: user_id.to_vec(),
+                            dispute_tx,
+                        }))
+                        .await
+                    {
+                        Ok(response) => {
+                            println!("{}", pretty_json(&response.into_inner()).unwrap())
+                        }
+                        Err(status) => handle_error(status.message()),
+                    }
+                }
+                (Err(e), _, _) => handle_error(e),
+                (_, Err(e), _) => handle_error(e),
+                (_, _, Err(e)) => handle_error(e),
+            };
+        }
+        Command::ListPenalties(data) => {
+            match client
+                .list_penalties(Request::new(msgs::ListPenaltiesRequest {
+                    start_time: data.start_time.unwrap_or_default(),
+                    end_time: data.end_time.unwrap_or_default(),
+                }))
+                .await
+            {
+                Ok(response) => println!("{}", pretty_json(&response.into_inner()).unwrap()),
+                Err(status) => handle_error(status.message()),
+            }
+        }
+        Command::RebroadcastAll => match client.rebroadcast_all(Request::new(())).await {
+            Ok(response) => println!("{}", pretty_json(&response.into_inner()).unwrap()),
+            Err(status) => handle_error(status.message()),
+        },
+        Command::ReloadUserLists => match client.reload_user_lists(Request::new(())).await {
+            Ok(response) => println!("{}", pretty_json(&response.into_inner()).unwrap()),
+            Err(status) => handle_error(status.message()),
+        },
+        Command::CheckIntegrity(data) => {
+            match client
+                .check_integrity(Request::new(msgs::CheckIntegrityRequest {
+                    repair: data.repair,
+                }))
+                .await
+            {
+                Ok(response) => println!("{}", pretty_json(&response.into_inner()).unwrap()),
+                Err(status) => handle_error(status.message()),
+            }
+        }
         Command::Stop => {
             println!("Shutting down tower");
             client.stop(Request::new(())).await.unwrap();
@@ -16,6 +16,16 @@ pub enum Command {
     GetUsers,
     /// Gets information about a specific user
     GetUser(GetUserData),
+    /// Broadcasts the penalty of a given appointment as if its dispute transaction had been seen on chain (disaster recovery drills only)
+    TriggerPenalty(TriggerPenaltyData),
+    /// Gets the penalties the tower has responded with, optionally within a time range
+    ListPenalties(ListPenaltiesData),
+    /// Rebroadcasts, right away, all the penalties that have not been confirmed yet
+    RebroadcastAll,
+    /// Reloads the lists of users allowed to, or blocked from, registering with the tower
+    ReloadUserLists,
+    /// Checks the data held by the tower is consistent, optionally repairing it
+    CheckIntegrity(CheckIntegrityData),
     /// Requests a graceful shutdown of the tower
     Stop,
 }
@@ -27,6 +37,36 @@ pub struct GetUserData {
     pub user_id: String,
 }
 
+#[derive(Debug, StructOpt, Clone)]
+#[structopt(rename_all = "snake_case")]
+pub struct TriggerPenaltyData {
+    /// The locator of the appointment (16-byte hexadecimal string).
+    pub locator: String,
+    /// The identifier of the user the appointment belongs to (33-byte compressed public key).
+    pub user_id: String,
+    /// The raw dispute transaction (hexadecimal string).
+    pub dispute_tx: String,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+#[structopt(rename_all = "snake_case")]
+pub struct ListPenaltiesData {
+    /// Only return penalties responded at or after this time (Unix time, in seconds).
+    #[structopt(long)]
+    pub start_time: Option<u64>,
+    /// Only return penalties responded at or before this time (Unix time, in seconds).
+    #[structopt(long)]
+    pub end_time: Option<u64>,
+}
+
+#[derive(Debug, StructOpt, Clone)]
+#[structopt(rename_all = "snake_case")]
+pub struct CheckIntegrityData {
+    /// Removes dangling rows and overwrites the stored users with the ones held in memory.
+    #[structopt(long)]
+    pub repair: bool,
+}
+
 #[derive(Debug, StructOpt, Clone)]
 pub struct GetAppointmentsData {
     /// The locator of the appointments (16-byte hexadecimal string).
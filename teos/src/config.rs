@@ -2,8 +2,14 @@
 
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::str::FromStr;
 use structopt::StructOpt;
 
+use bitcoin::network::constants::Network;
+use bitcoin::{Address, Script};
+
+use crate::dbm::MAX_SHARDS;
+
 pub fn data_dir_absolute_path(data_dir: String) -> PathBuf {
     if let Some(a) = data_dir.strip_prefix('~') {
         if let Some(b) = data_dir.strip_prefix("~/") {
@@ -91,6 +97,30 @@ pub struct Opt {
     #[structopt(long)]
     pub btc_rpc_port: Option<u16>,
 
+    /// bitcoind zmqpubhashblock (or zmqpubrawblock) endpoint. If unset, new blocks are only found by polling
+    #[structopt(long)]
+    pub btc_zmq_block: Option<String>,
+
+    /// Time (in seconds) to wait for bitcoind to answer an RPC call before giving up on it [default: 15]
+    #[structopt(long)]
+    pub btc_rpc_timeout: Option<u16>,
+
+    /// Times idempotent RPC calls are retried if bitcoind is too slow to answer, before flagging it as unreachable [default: 2]
+    #[structopt(long)]
+    pub btc_rpc_retries: Option<u8>,
+
+    /// Esplora-compatible API (e.g. https://mempool.space/api) used to broadcast penalties while bitcoind is unreachable. If unset, penalties wait for bitcoind
+    #[structopt(long)]
+    pub btc_broadcast_fallback: Option<String>,
+
+    /// Esplora-compatible API (e.g. https://mempool.space/api) penalty confirmations are corroborated against before finalizing trackers. If unset, bitcoind alone is trusted
+    #[structopt(long)]
+    pub btc_confirmation_source: Option<String>,
+
+    /// Time (in seconds) between polls to bitcoind for new blocks [default: 60]
+    #[structopt(long)]
+    pub polling_delta: Option<u16>,
+
     /// Specify data directory
     #[structopt(long, default_value = "~/.teos")]
     pub data_dir: String,
@@ -123,6 +153,36 @@ pub struct Opt {
     /// Port for the onion hidden service to listen on [default: 9814]
     #[structopt(long)]
     pub onion_hidden_service_port: Option<u16>,
+
+    /// If set, stuck penalties with an anchor output paying to the tower anchor key (logged on startup) are fee-bumped via CPFP
+    #[structopt(long)]
+    pub anchor_cpfp: bool,
+
+    /// Allows manually triggering penalties on mainnet. Meant for disaster recovery drills, THIS BROADCASTS REAL PENALTIES
+    #[structopt(long)]
+    pub mainnet_penalty_triggers: bool,
+
+    /// If set, breaches of users whose subscription has expired are not responded to (they are recorded as declined)
+    #[structopt(long)]
+    pub decline_lapsed_penalties: bool,
+
+    /// If set, breaches whose penalty cannot be decrypted are retried with the keys clients are known to mix up the
+    /// dispute txid with
+    #[structopt(long)]
+    pub recover_unconstructable_penalties: bool,
+
+    /// If set, trackers are finalized even if the secondary confirmation source does not agree (the disagreement is
+    /// only logged)
+    #[structopt(long)]
+    pub lenient_confirmation_source: bool,
+
+    /// If set, an in-memory filter over the stored locators is checked before looking for breaches in the database
+    #[structopt(long)]
+    pub locator_filter: bool,
+
+    /// If set, the slots of appointments deleted by their users are given back to them
+    #[structopt(long)]
+    pub refund_deleted_appointments: bool,
 }
 
 /// Holds all configuration options.
@@ -149,19 +209,46 @@ pub struct Config {
     pub btc_rpc_password: String,
     pub btc_rpc_connect: String,
     pub btc_rpc_port: u16,
+    pub btc_zmq_block: String,
+    pub btc_rpc_timeout: u16,
+    pub btc_rpc_retries: u8,
+    pub btc_broadcast_fallback: String,
+    pub btc_confirmation_source: String,
 
     // Flags
     pub debug: bool,
     pub deps_debug: bool,
     pub overwrite_key: bool,
     pub force_update: bool,
+    pub anchor_cpfp: bool,
+    pub mainnet_penalty_triggers: bool,
+    pub decline_lapsed_penalties: bool,
+    pub recover_unconstructable_penalties: bool,
+    pub lenient_confirmation_source: bool,
+    pub locator_filter: bool,
+    pub refund_deleted_appointments: bool,
 
     // General
     pub subscription_slots: u32,
     pub subscription_duration: u32,
     pub expiry_delta: u32,
+    pub renewal_window: u32,
+    pub max_expiry_horizon: u32,
     pub min_to_self_delay: u16,
     pub polling_delta: u16,
+    pub min_penalty_value: u64,
+    pub max_penalty_broadcast_delay: u32,
+    pub max_db_size: u64,
+    pub db_shards: u8,
+    pub reward_address: String,
+
+    // Access control
+    pub user_allowlist: String,
+    pub user_blocklist: String,
+
+    // Payments
+    pub cln_rpc_path: String,
+    pub subscription_price_msat: u64,
 
     // Internal API
     pub internal_api_bind: String,
@@ -224,6 +311,24 @@ impl Config {
         if options.btc_rpc_port.is_some() {
             self.btc_rpc_port = options.btc_rpc_port.unwrap();
         }
+        if let Some(btc_zmq_block) = options.btc_zmq_block {
+            self.btc_zmq_block = btc_zmq_block;
+        }
+        if let Some(btc_rpc_timeout) = options.btc_rpc_timeout {
+            self.btc_rpc_timeout = btc_rpc_timeout;
+        }
+        if let Some(btc_rpc_retries) = options.btc_rpc_retries {
+            self.btc_rpc_retries = btc_rpc_retries;
+        }
+        if let Some(btc_broadcast_fallback) = options.btc_broadcast_fallback {
+            self.btc_broadcast_fallback = btc_broadcast_fallback;
+        }
+        if let Some(btc_confirmation_source) = options.btc_confirmation_source {
+            self.btc_confirmation_source = btc_confirmation_source;
+        }
+        if let Some(polling_delta) = options.polling_delta {
+            self.polling_delta = polling_delta;
+        }
         if options.tor_control_port.is_some() {
             self.tor_control_port = options.tor_control_port.unwrap();
         }
@@ -234,10 +339,35 @@ impl Config {
         self.tor_support |= options.tor_support;
         self.debug |= options.debug;
         self.deps_debug |= options.deps_debug;
+        self.anchor_cpfp |= options.anchor_cpfp;
+        self.mainnet_penalty_triggers |= options.mainnet_penalty_triggers;
+        self.decline_lapsed_penalties |= options.decline_lapsed_penalties;
+        self.recover_unconstructable_penalties |= options.recover_unconstructable_penalties;
+        self.lenient_confirmation_source |= options.lenient_confirmation_source;
+        self.locator_filter |= options.locator_filter;
+        self.refund_deleted_appointments |= options.refund_deleted_appointments;
         self.overwrite_key = options.overwrite_key;
         self.force_update = options.force_update;
     }
 
+    /// Whether penalties can be manually triggered. This is only allowed outside mainnet, unless explicitly overridden.
+    ///
+    /// Must be called after [Config::verify], given it relies on the network name being normalized.
+    pub fn penalty_triggers_allowed(&self) -> bool {
+        self.btc_network != "main" || self.mainnet_penalty_triggers
+    }
+
+    /// The script the operator reward outputs pay to, if the tower takes rewards.
+    ///
+    /// Must be called after [Config::verify], given it relies on the reward address being valid.
+    pub fn reward_script(&self) -> Option<Script> {
+        (!self.reward_address.is_empty()).then(|| {
+            Address::from_str(&self.reward_address)
+                .unwrap()
+                .script_pubkey()
+        })
+    }
+
     /// Verifies that [Config] is properly built.
     ///
     /// This includes:
@@ -257,6 +387,31 @@ impl Config {
             ));
         }
 
+        if self.polling_delta == 0 {
+            return Err(ConfigError(
+                "polling_delta must be at least one second".to_owned(),
+            ));
+        }
+
+        if self.btc_rpc_timeout == 0 {
+            return Err(ConfigError(
+                "btc_rpc_timeout must be at least one second".to_owned(),
+            ));
+        }
+
+        if self.db_shards == 0 || self.db_shards as usize > MAX_SHARDS {
+            return Err(ConfigError(format!(
+                "db_shards must be between 1 and {MAX_SHARDS}"
+            )));
+        }
+
+        if !self.cln_rpc_path.is_empty() && self.subscription_price_msat == 0 {
+            return Err(ConfigError(
+                "subscription_price_msat must be set if registrations require a payment (cln_rpc_path is set)"
+                    .to_owned(),
+            ));
+        }
+
         // Normalize the network option to the ones used by bitcoind.
         if ["mainnet", "testnet"].contains(&self.btc_network.as_str()) {
             self.btc_network = self.btc_network.trim_end_matches("net").into();
@@ -276,6 +431,24 @@ impl Config {
             self.btc_rpc_port = default_rpc_port;
         }
 
+        if !self.reward_address.is_empty() {
+            let network = match self.btc_network.as_str() {
+                "main" => Network::Bitcoin,
+                "test" => Network::Testnet,
+                "regtest" => Network::Regtest,
+                _ => Network::Signet,
+            };
+            match Address::from_str(&self.reward_address) {
+                Ok(address) if address.is_valid_for_network(network) => (),
+                _ => {
+                    return Err(ConfigError(format!(
+                        "reward_address is not a valid {} address",
+                        self.btc_network
+                    )))
+                }
+            }
+        }
+
         Ok(())
     }
 
@@ -328,16 +501,39 @@ impl Default for Config {
             btc_rpc_cookie: String::new(),
             btc_rpc_connect: "localhost".into(),
             btc_rpc_port: 0,
+            btc_zmq_block: String::new(),
+            btc_rpc_timeout: 15,
+            btc_rpc_retries: 2,
+            btc_broadcast_fallback: String::new(),
+            btc_confirmation_source: String::new(),
 
             debug: false,
             deps_debug: false,
             overwrite_key: false,
             force_update: false,
+            anchor_cpfp: false,
+            mainnet_penalty_triggers: false,
+            decline_lapsed_penalties: false,
+            recover_unconstructable_penalties: false,
+            lenient_confirmation_source: false,
+            locator_filter: false,
+            refund_deleted_appointments: false,
             subscription_slots: 10000,
             subscription_duration: 4320,
             expiry_delta: 6,
+            renewal_window: 0,
+            max_expiry_horizon: 0,
             min_to_self_delay: 20,
             polling_delta: 60,
+            min_penalty_value: 0,
+            max_penalty_broadcast_delay: 0,
+            max_db_size: 0,
+            db_shards: 1,
+            reward_address: String::new(),
+            user_allowlist: String::new(),
+            user_blocklist: String::new(),
+            cln_rpc_path: String::new(),
+            subscription_price_msat: 0,
             internal_api_bind: "127.0.0.1".into(),
             internal_api_port: 50051,
         }
@@ -364,12 +560,25 @@ mod tests {
                 btc_rpc_cookie: None,
                 btc_rpc_connect: None,
                 btc_rpc_port: None,
+                btc_zmq_block: None,
+                btc_rpc_timeout: None,
+                btc_rpc_retries: None,
+                btc_broadcast_fallback: None,
+                btc_confirmation_source: None,
+                polling_delta: None,
                 data_dir: String::from("~/.teos"),
 
                 debug: false,
                 deps_debug: false,
                 overwrite_key: false,
                 force_update: false,
+                anchor_cpfp: false,
+                mainnet_penalty_triggers: false,
+                decline_lapsed_penalties: false,
+                recover_unconstructable_penalties: false,
+                lenient_confirmation_source: false,
+                locator_filter: false,
+                refund_deleted_appointments: false,
             }
         }
     }
@@ -428,6 +637,116 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_config_verify_subscription_price() {
+        // Paid registrations need a price
+        let mut config = Config {
+            btc_rpc_user: "user".to_owned(),
+            btc_rpc_password: "password".to_owned(),
+            cln_rpc_path: "~/.lightning/bitcoin/lightning-rpc".to_owned(),
+            ..Default::default()
+        };
+        assert!(
+            matches!(config.verify(), Err(ConfigError(e)) if e.contains("subscription_price_msat must be set"))
+        );
+
+        config.subscription_price_msat = 1000;
+        config.verify().unwrap();
+    }
+
+    #[test]
+    fn test_config_verify_polling_delta() {
+        // Polling bitcoind in a loop is not allowed
+        let mut config = Config {
+            btc_rpc_user: "user".to_owned(),
+            btc_rpc_password: "password".to_owned(),
+            polling_delta: 0,
+            ..Default::default()
+        };
+        assert!(
+            matches!(config.verify(), Err(ConfigError(e)) if e.contains("polling_delta must be at least one second"))
+        );
+
+        // It can be set from the command line
+        config.patch_with_options(Opt {
+            polling_delta: Some(1),
+            ..Default::default()
+        });
+        config.verify().unwrap();
+        assert_eq!(config.polling_delta, 1);
+    }
+
+    #[test]
+    fn test_config_verify_btc_rpc_timeout() {
+        // Calls to bitcoind must be given some time to complete
+        let mut config = Config {
+            btc_rpc_user: "user".to_owned(),
+            btc_rpc_password: "password".to_owned(),
+            btc_rpc_timeout: 0,
+            ..Default::default()
+        };
+        assert!(
+            matches!(config.verify(), Err(ConfigError(e)) if e.contains("btc_rpc_timeout must be at least one second"))
+        );
+
+        // It can be set from the command line
+        config.patch_with_options(Opt {
+            btc_rpc_timeout: Some(1),
+            btc_rpc_retries: Some(0),
+            ..Default::default()
+        });
+        config.verify().unwrap();
+        assert_eq!(config.btc_rpc_timeout, 1);
+        assert_eq!(config.btc_rpc_retries, 0);
+    }
+
+    #[test]
+    fn test_config_verify_db_shards() {
+        let mut config = Config {
+            btc_rpc_user: "user".to_owned(),
+            btc_rpc_password: "password".to_owned(),
+            db_shards: 0,
+            ..Default::default()
+        };
+        assert!(
+            matches!(config.verify(), Err(ConfigError(e)) if e.contains("db_shards must be between 1 and"))
+        );
+
+        config.db_shards = MAX_SHARDS as u8 + 1;
+        assert!(config.verify().is_err());
+
+        config.db_shards = MAX_SHARDS as u8;
+        config.verify().unwrap();
+    }
+
+    #[test]
+    fn test_config_verify_reward_address() {
+        let mut config = Config {
+            btc_rpc_user: "user".to_owned(),
+            btc_rpc_password: "password".to_owned(),
+            btc_network: "regtest".to_owned(),
+            reward_address: "not an address".to_owned(),
+            ..Default::default()
+        };
+        assert!(
+            matches!(config.verify(), Err(ConfigError(e)) if e.contains("reward_address is not a valid regtest address"))
+        );
+
+        // Addresses of a different network are rejected too
+        config.reward_address = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_owned();
+        assert!(config.verify().is_err());
+
+        let pk = bitcoin::PublicKey::new(teos_common::cryptography::get_random_keypair().1);
+        config.reward_address = Address::p2wpkh(&pk, Network::Regtest).unwrap().to_string();
+        config.verify().unwrap();
+        assert!(config.reward_script().unwrap().is_v0_p2wpkh());
+
+        // No reward is taken by default
+        config.reward_address = String::new();
+        config.verify().unwrap();
+        assert!(config.reward_script().is_none());
+    }
+
     #[test]
     fn test_config_verify_tor_set() {
         let mut config = Config {
@@ -439,4 +758,26 @@ mod tests {
 
         config.verify().unwrap()
     }
+
+    #[test]
+    fn test_config_penalty_triggers_allowed() {
+        let mut config = Config {
+            btc_rpc_user: "user".to_owned(),
+            btc_rpc_password: "password".to_owned(),
+            ..Default::default()
+        };
+
+        // Penalties cannot be manually triggered on mainnet unless explicitly allowed
+        config.verify().unwrap();
+        assert!(!config.penalty_triggers_allowed());
+        config.mainnet_penalty_triggers = true;
+        assert!(config.penalty_triggers_allowed());
+
+        for network in ["testnet", "signet", "regtest"] {
+            config.btc_network = network.to_owned();
+            config.mainnet_penalty_triggers = false;
+            config.verify().unwrap();
+            assert!(config.penalty_triggers_allowed());
+        }
+    }
 }
@@ -0,0 +1,288 @@
+//! Logic related to corroborating penalty confirmations against a source other than the tower's `bitcoind`.
+//!
+//! The [Responder](crate::responder::Responder) counts confirmations out of the blocks `bitcoind` feeds it. Operators
+//! running the tower away from their node can set a [Corroborator] so penalties are only considered irrevocably resolved
+//! once a secondary [Confirmer] agrees with the primary one (`bitcoind` itself by default, see [BitcoindConfirmer]).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use bitcoin::Txid;
+use bitcoincore_rpc::{
+    jsonrpc::error::Error::Rpc as RpcError, Client as BitcoindClient,
+    Error::JsonRpc as JsonRpcError, RpcApi,
+};
+use tokio::sync::Notify;
+
+use teos_common::constants::IRREVOCABLY_RESOLVED;
+
+use crate::rpc_errors;
+
+/// Error raised if a confirmation source cannot be queried.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ConfirmationError(pub String);
+
+impl fmt::Display for ConfirmationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Confirmation source error: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConfirmationError {}
+
+/// What the [Responder](crate::responder::Responder) does if the secondary [Confirmer] does not agree a penalty is
+/// irrevocably resolved (or cannot be reached).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CorroborationPolicy {
+    /// The tracker is kept until both sources agree.
+    #[default]
+    Require,
+    /// The disagreement is only logged, and the tracker is finalized anyway.
+    Warn,
+}
+
+/// A source penalty confirmations can be checked against.
+pub trait Confirmer: Send + Sync + fmt::Debug {
+    /// Gets how many confirmations a transaction has (one if it is in the chain tip), or [None] if it is not confirmed.
+    ///
+    /// `height` is the one of the block the tower saw the transaction confirmed in, which sources may use to look it up.
+    fn get_confirmations(&self, txid: &Txid, height: u32)
+        -> Result<Option<u32>, ConfirmationError>;
+}
+
+/// [Confirmer] backed by `bitcoind`.
+#[derive(Debug)]
+pub struct BitcoindConfirmer {
+    bitcoin_cli: Arc<BitcoindClient>,
+}
+
+impl BitcoindConfirmer {
+    /// Creates a new [BitcoindConfirmer] instance.
+    pub fn new(bitcoin_cli: Arc<BitcoindClient>) -> Self {
+        Self { bitcoin_cli }
+    }
+}
+
+impl Confirmer for BitcoindConfirmer {
+    /// Looks the transaction up in the block at the given height, so `bitcoind` does not need to be run with `txindex`.
+    fn get_confirmations(
+        &self,
+        txid: &Txid,
+        height: u32,
+    ) -> Result<Option<u32>, ConfirmationError> {
+        let block_hash = self
+            .bitcoin_cli
+            .get_block_hash(height as u64)
+            .map_err(|e| ConfirmationError(e.to_string()))?;
+
+        match self
+            .bitcoin_cli
+            .get_raw_transaction_info(txid, Some(&block_hash))
+        {
+            Ok(tx_info) => Ok(tx_info.confirmations),
+            // The transaction is not in the block at the given height (anymore)
+            Err(JsonRpcError(RpcError(rpcerr)))
+                if rpcerr.code == rpc_errors::RPC_INVALID_ADDRESS_OR_KEY =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(ConfirmationError(e.to_string())),
+        }
+    }
+}
+
+/// The latest outcome of corroborating a penalty is irrevocably resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    /// The sources are yet to be asked about the penalty, which was seen confirmed at the given height.
+    Pending(u32),
+    /// Both sources agree.
+    Agreed,
+    /// The primary source does not agree, for the given reason.
+    PrimaryDisagrees(String),
+    /// The secondary source does not agree, for the given reason.
+    SecondaryDisagrees(String),
+}
+
+/// Checks whether a [Confirmer] reports a penalty as irrevocably resolved, returning the reason why not otherwise.
+fn check(confirmer: &dyn Confirmer, txid: &Txid, height: u32) -> Result<(), String> {
+    match confirmer.get_confirmations(txid, height) {
+        Ok(Some(confirmations)) if confirmations >= IRREVOCABLY_RESOLVED => Ok(()),
+        Ok(confirmations) => Err(format!(
+            "confirmation count: {}",
+            confirmations.unwrap_or(0)
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Component in charge of corroborating penalties are irrevocably resolved against a primary and a secondary
+/// [Confirmer] before their trackers are finalized.
+///
+/// Sources are queried by [Corroborator::run], away from the block processing path, and their answers cached for the
+/// [Responder](crate::responder::Responder) to pick them up on the following blocks.
+#[derive(Debug)]
+pub struct Corroborator {
+    /// The source the tower trusts in the first place.
+    primary: Arc<dyn Confirmer>,
+    /// The source the primary one is corroborated against.
+    secondary: Arc<dyn Confirmer>,
+    /// What to do if the secondary source does not agree.
+    policy: CorroborationPolicy,
+    /// The latest outcome of corroborating each penalty.
+    outcomes: Mutex<HashMap<Txid, Outcome>>,
+    /// Notifies [Corroborator::run] that there are penalties pending to be corroborated.
+    requested: Notify,
+}
+
+impl Corroborator {
+    /// Creates a new [Corroborator] instance.
+    pub fn new(
+        primary: Arc<dyn Confirmer>,
+        secondary: Arc<dyn Confirmer>,
+        policy: CorroborationPolicy,
+    ) -> Self {
+        Self {
+            primary,
+            secondary,
+            policy,
+            outcomes: Mutex::new(HashMap::new()),
+            requested: Notify::new(),
+        }
+    }
+
+    /// Checks whether the sources agree a penalty (seen confirmed at `height`) is irrevocably resolved.
+    ///
+    /// This never waits for the sources. Penalties with no known outcome are queued for [Corroborator::run] to ask the
+    /// sources about them, and held back meanwhile. Disagreements (including the sources being unreachable) are always
+    /// logged, and the sources asked again, but those of the secondary source only keep the tracker from being finalized
+    /// if the [CorroborationPolicy] requires so.
+    pub(crate) fn is_corroborated(&self, txid: &Txid, height: u32) -> bool {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        match outcomes.remove(txid) {
+            Some(Outcome::Agreed) => return true,
+            Some(Outcome::PrimaryDisagrees(reason)) => {
+                log::warn!("Primary confirmation source does not agree {txid} is irrevocably resolved ({reason}). Keeping its tracker")
+            }
+            Some(Outcome::SecondaryDisagrees(reason)) => match self.policy {
+                CorroborationPolicy::Require => {
+                    log::warn!("Secondary confirmation source does not agree {txid} is irrevocably resolved ({reason}). Keeping its tracker")
+                }
+                CorroborationPolicy::Warn => {
+                    log::warn!("Secondary confirmation source does not agree {txid} is irrevocably resolved ({reason}). Finalizing its tracker anyway");
+                    return true;
+                }
+            },
+            Some(Outcome::Pending(_)) | None => (),
+        }
+
+        outcomes.insert(*txid, Outcome::Pending(height));
+        self.requested.notify_one();
+        false
+    }
+
+    /// Asks the sources about the penalties pending to be corroborated, caching their answers.
+    pub(crate) async fn corroborate_pending(&self) {
+        let pending: Vec<(Txid, u32)> = self
+            .outcomes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(txid, outcome)| match outcome {
+                Outcome::Pending(height) => Some((*txid, *height)),
+                _ => None,
+            })
+            .collect();
+
+        for (txid, height) in pending {
+            let primary = self.primary.clone();
+            let secondary = self.secondary.clone();
+            // Sources may take a while to answer, so they are queried on a thread where blocking is allowed
+            let outcome = tokio::task::spawn_blocking(move || {
+                if let Err(reason) = check(primary.as_ref(), &txid, height) {
+                    Outcome::PrimaryDisagrees(reason)
+                } else if let Err(reason) = check(secondary.as_ref(), &txid, height) {
+                    Outcome::SecondaryDisagrees(reason)
+                } else {
+                    Outcome::Agreed
+                }
+            })
+            .await
+            .unwrap_or_else(|e| Outcome::PrimaryDisagrees(e.to_string()));
+
+            self.outcomes.lock().unwrap().insert(txid, outcome);
+        }
+    }
+
+    /// Corroborates the penalties queued by [Corroborator::is_corroborated] as they come. Meant to be run as a task of its own.
+    pub async fn run(&self) {
+        loop {
+            self.requested.notified().await;
+            self.corroborate_pending().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{start_server, BitcoindMock, MockOptions};
+
+    use bitcoin::hashes::Hash;
+    use bitcoincore_rpc::Auth;
+
+    /// Confirmer reporting a fixed confirmation count for every transaction.
+    #[derive(Debug)]
+    struct MockConfirmer(Option<u32>);
+
+    impl Confirmer for MockConfirmer {
+        fn get_confirmations(&self, _: &Txid, _: u32) -> Result<Option<u32>, ConfirmationError> {
+            Ok(self.0)
+        }
+    }
+
+    #[test]
+    fn test_bitcoind_get_confirmations() {
+        let txid = Txid::from_inner([1; 32]);
+
+        let bitcoind_mock = BitcoindMock::new(MockOptions::with_confirmations(42));
+        let confirmer = BitcoindConfirmer::new(Arc::new(
+            BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap(),
+        ));
+        start_server(bitcoind_mock.server);
+        assert_eq!(confirmer.get_confirmations(&txid, 100), Ok(Some(42)));
+
+        // Transactions that cannot be found in the block at the given height are not confirmed
+        let bitcoind_mock = BitcoindMock::new(MockOptions::default());
+        let confirmer = BitcoindConfirmer::new(Arc::new(
+            BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap(),
+        ));
+        start_server(bitcoind_mock.server);
+        assert_eq!(confirmer.get_confirmations(&txid, 100), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_corroborator_run() {
+        let txid = Txid::from_inner([1; 32]);
+        let corroborator = Arc::new(Corroborator::new(
+            Arc::new(MockConfirmer(Some(IRREVOCABLY_RESOLVED))),
+            Arc::new(MockConfirmer(Some(IRREVOCABLY_RESOLVED))),
+            CorroborationPolicy::Require,
+        ));
+        let runner = corroborator.clone();
+        tokio::spawn(async move { runner.run().await });
+
+        // Penalties are queued and corroborated in the background
+        assert!(!corroborator.is_corroborated(&txid, 100));
+        let start = std::time::Instant::now();
+        while corroborator.outcomes.lock().unwrap().get(&txid) != Some(&Outcome::Agreed) {
+            assert!(start.elapsed() < std::time::Duration::from_secs(5));
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(corroborator.is_corroborated(&txid, 100));
+        // Outcomes are consumed once picked up
+        assert!(corroborator.outcomes.lock().unwrap().is_empty());
+    }
+}
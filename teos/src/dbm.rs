@@ -1,33 +1,72 @@
 //! Logic related to the tower database manager (DBM), component in charge of persisting data on disk.
 //!
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs;
 use std::iter::FromIterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use rusqlite::limits::Limit;
-use rusqlite::{params, params_from_iter, Connection, Error as SqliteError};
+use rusqlite::{params, params_from_iter, Connection, DatabaseName, Error as SqliteError};
 
 use bitcoin::consensus;
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::SecretKey;
-use bitcoin::BlockHash;
+use bitcoin::{BlockHash, Transaction, Txid};
 
-use teos_common::appointment::{Appointment, Locator};
+use teos_common::appointment::{Appointment, Locator, SignatureVersion};
 use teos_common::dbm::{DatabaseConnection, DatabaseManager, Error};
 use teos_common::UserId;
 
 use crate::extended_appointment::{ExtendedAppointment, UUID};
 use crate::gatekeeper::UserInfo;
-use crate::responder::{ConfirmationStatus, PenaltySummary, TransactionTracker};
+use crate::locator_filter::LocatorFilter;
+use crate::responder::{
+    ConfirmationStatus, FeeBump, PenaltyRecord, PenaltyReplacement, PenaltyStatus, PenaltySummary,
+    TransactionTracker,
+};
+
+/// Maximum number of shards the per-user data can be split across (bounded by how many databases SQLite can attach).
+pub const MAX_SHARDS: usize = 10;
+
+/// Tables (and indexes) holding per-user data, which are split across shards (see [DBM::with_shards]). The rest of
+/// them are only kept by the main database file.
+const SHARDED_TABLES: [&str; 9] = [
+    "users",
+    "user_signature_versions",
+    "appointments",
+    "trackers",
+    "locators_index",
+    "appointment_ttls",
+    "expiry_heights_index",
+    "appointment_priorities",
+    "appointment_rewards",
+];
+
+/// Sharded tables holding per-appointment data, keyed by the UUID of the appointment they belong to.
+const APPOINTMENT_DATA_TABLES: [&str; 4] = [
+    "trackers",
+    "appointment_ttls",
+    "appointment_priorities",
+    "appointment_rewards",
+];
 
-const TABLES: [&str; 6] = [
+const TABLES: [&str; 18] = [
     "CREATE TABLE IF NOT EXISTS users (
     user_id INT PRIMARY KEY,
     available_slots INT NOT NULL,
     subscription_start INT NOT NULL,
     subscription_expiry INT NOT NULL
+)",
+    "CREATE TABLE IF NOT EXISTS user_signature_versions (
+    user_id INT PRIMARY KEY,
+    version INT NOT NULL,
+    FOREIGN KEY(user_id)
+        REFERENCES users(user_id)
+        ON DELETE CASCADE
 )",
     "CREATE TABLE IF NOT EXISTS appointments (
     UUID INT PRIMARY KEY,
@@ -58,19 +97,112 @@ const TABLES: [&str; 6] = [
     "CREATE TABLE IF NOT EXISTS keys (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     key INT NOT NULL
+)",
+    "CREATE TABLE IF NOT EXISTS anchor_key (
+    id INT PRIMARY KEY,
+    key TEXT NOT NULL
+)",
+    "CREATE TABLE IF NOT EXISTS penalty_ledger (
+    UUID INT PRIMARY KEY,
+    user_id INT NOT NULL,
+    dispute_txid INT NOT NULL,
+    penalty_txid INT NOT NULL,
+    value INT NOT NULL,
+    timestamp INT NOT NULL,
+    status TEXT NOT NULL
+)",
+    "CREATE TABLE IF NOT EXISTS fee_bumps (
+    UUID INT NOT NULL,
+    txid INT NOT NULL,
+    feerate INT NOT NULL,
+    fee INT NOT NULL,
+    timestamp INT NOT NULL,
+    PRIMARY KEY (UUID, txid)
+)",
+    "CREATE TABLE IF NOT EXISTS penalty_replacements (
+    UUID INT NOT NULL,
+    old_penalty_txid INT NOT NULL,
+    new_penalty_txid INT NOT NULL,
+    timestamp INT NOT NULL,
+    PRIMARY KEY (UUID, new_penalty_txid)
+)",
+    "CREATE TABLE IF NOT EXISTS subscription_transfers (
+    transfer_id INT PRIMARY KEY,
+    old_user_id INT NOT NULL,
+    new_user_id INT NOT NULL
+)",
+    "CREATE TABLE IF NOT EXISTS redeemed_payments (
+    payment_hash INT PRIMARY KEY,
+    user_id INT NOT NULL
 )",
     "CREATE INDEX IF NOT EXISTS locators_index ON appointments (
         locator
+)",
+    "CREATE TABLE IF NOT EXISTS appointment_ttls (
+    UUID INT PRIMARY KEY,
+    expiry_height INT NOT NULL,
+    FOREIGN KEY(UUID)
+        REFERENCES appointments(UUID)
+        ON DELETE CASCADE
+)",
+    "CREATE INDEX IF NOT EXISTS expiry_heights_index ON appointment_ttls (
+        expiry_height
+)",
+    "CREATE TABLE IF NOT EXISTS appointment_priorities (
+    UUID INT PRIMARY KEY,
+    priority INT NOT NULL,
+    FOREIGN KEY(UUID)
+        REFERENCES appointments(UUID)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS appointment_rewards (
+    UUID INT PRIMARY KEY,
+    reward INT NOT NULL,
+    FOREIGN KEY(UUID)
+        REFERENCES appointments(UUID)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS shards (
+    id INT PRIMARY KEY,
+    count INT NOT NULL
 )",
 ];
 
+/// Gets the index of the shard the data of a given user belongs to, given the total number of shards.
+fn shard_index(user_id: &[u8], count: usize) -> usize {
+    let digest = sha256::Hash::hash(user_id).into_inner();
+    (u64::from_be_bytes(digest[..8].try_into().unwrap()) % count as u64) as usize
+}
+
+/// Gets the [SignatureVersion] of a user given the one stored in the database (if any).
+///
+/// Users registered before versions were negotiated do not have any, and are therefore using [SignatureVersion::V1].
+fn signature_version_from_db(version: Option<u32>) -> SignatureVersion {
+    version
+        .and_then(|v| SignatureVersion::try_from(v).ok())
+        .unwrap_or_default()
+}
+
+/// Gets the path of a given shard file, next to the main database file.
+fn shard_path(db_path: &Path, index: usize) -> PathBuf {
+    db_path.with_extension(format!("shard{index}.sql3"))
+}
+
 /// Component in charge of interacting with the underlying database.
 ///
 /// Currently works for `SQLite`. `PostgreSQL` should also be added in the future.
+///
+/// The per-user data (users, appointments, trackers and their metadata) can be split across several database files
+/// (shards), keyed by user id, so a single file does not have to hold the data of every user. Shards are attached to
+/// the main database connection, so transactions still span all of them.
 #[derive(Debug)]
 pub struct DBM {
     /// The underlying database connection.
     connection: Connection,
+    /// The schemas of the shards the per-user data is split across. The first one is always `main`.
+    shards: Vec<String>,
+    /// An optional filter over the stored locators, consulted before querying the database for breaches.
+    locator_filter: RefCell<Option<LocatorFilter>>,
 }
 
 impl DatabaseConnection for DBM {
@@ -86,21 +218,208 @@ impl DatabaseConnection for DBM {
 impl DBM {
     /// Creates a new [DBM] instance.
     pub fn new(db_path: PathBuf) -> Result<Self, SqliteError> {
-        let connection = Connection::open(db_path)?;
+        Self::with_shards(db_path, 1)
+    }
+
+    /// Creates a new [DBM] instance with the per-user data split across `shards` database files (the main one included).
+    ///
+    /// Shard files are placed next to the main one. If the database was previously split across a different number of
+    /// shards (a single file included), the data is moved to the shards it now belongs to before anything else. Shard
+    /// files left empty this way are removed.
+    pub fn with_shards(db_path: PathBuf, shards: usize) -> Result<Self, SqliteError> {
+        assert!(
+            (1..=MAX_SHARDS).contains(&shards),
+            "shards must be between 1 and {MAX_SHARDS}"
+        );
+
+        let connection = Connection::open(&db_path)?;
         connection.execute("PRAGMA foreign_keys=1;", [])?;
-        let mut dbm = Self { connection };
+        let mut dbm = Self {
+            connection,
+            shards: vec!["main".to_owned()],
+            locator_filter: RefCell::new(None),
+        };
         dbm.create_tables(Vec::from_iter(TABLES))?;
 
+        // Shards from a previous setup are attached too (even if they are not used anymore) so their data can be moved.
+        let previous_shards = dbm.load_shard_count();
+        for index in 1..shards.max(previous_shards) {
+            dbm.attach_shard(&shard_path(&db_path, index), index)?;
+        }
+
+        if shards != previous_shards {
+            log::info!(
+                "Splitting the database into {shards} shard(s) (previously {previous_shards})"
+            );
+            dbm.reshard(shards)?;
+
+            for index in (shards..previous_shards).rev() {
+                let schema = dbm.shards.pop().unwrap();
+                dbm.connection
+                    .execute(&format!("DETACH DATABASE {schema}"), [])?;
+                if let Err(e) = fs::remove_file(shard_path(&db_path, index)) {
+                    log::warn!("Couldn't remove unused shard {index}. Error: {e:?}");
+                }
+            }
+        }
+
         Ok(dbm)
     }
 
+    /// Attaches a shard file to the database connection, creating its tables if needed.
+    fn attach_shard(&mut self, path: &Path, index: usize) -> Result<(), SqliteError> {
+        let schema = format!("shard{index}");
+        self.connection.execute(
+            "ATTACH DATABASE ?1 AS ?2",
+            params![path.to_string_lossy(), schema],
+        )?;
+
+        let tx = self.connection.transaction()?;
+        for table in TABLES.iter() {
+            // The table (or index) name comes right after the IF NOT EXISTS clause.
+            let (_, name) = table.split_once("IF NOT EXISTS ").unwrap();
+            let name = name.split_whitespace().next().unwrap();
+            if SHARDED_TABLES.contains(&name) {
+                tx.execute(
+                    &table.replacen("IF NOT EXISTS ", &format!("IF NOT EXISTS {schema}."), 1),
+                    [],
+                )?;
+            }
+        }
+        tx.commit()?;
+
+        self.shards.push(schema);
+        Ok(())
+    }
+
+    /// Loads the number of shards the database was split across the last time it was opened. Defaults to one.
+    fn load_shard_count(&self) -> usize {
+        self.connection
+            .query_row("SELECT count FROM shards WHERE id=0", [], |row| row.get(0))
+            .unwrap_or(1)
+    }
+
+    /// Moves every user (alongside all its data) to the shard it belongs to given `count` shards, and records the new
+    /// shard count. Everything is done in a single transaction.
+    fn reshard(&mut self, count: usize) -> Result<(), SqliteError> {
+        let tx = self.connection.transaction()?;
+
+        for (index, source) in self.shards.iter().enumerate() {
+            let users = tx
+                .prepare(&format!("SELECT user_id FROM {source}.users"))?
+                .query_map([], |row| row.get::<_, Vec<u8>>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for user_id in users {
+                let target_index = shard_index(&user_id, count);
+                if target_index == index {
+                    continue;
+                }
+                let target = &self.shards[target_index];
+
+                for table in ["users", "user_signature_versions", "appointments"] {
+                    tx.execute(
+                        &format!("INSERT INTO {target}.{table} SELECT * FROM {source}.{table} WHERE user_id=(?)"),
+                        [&user_id],
+                    )?;
+                }
+                for table in APPOINTMENT_DATA_TABLES {
+                    tx.execute(
+                        &format!(
+                            "INSERT INTO {target}.{table} SELECT * FROM {source}.{table}
+                                WHERE UUID IN (SELECT UUID FROM {source}.appointments WHERE user_id=(?))"
+                        ),
+                        [&user_id],
+                    )?;
+                }
+                // The rest of the user data is removed on cascade.
+                tx.execute(
+                    &format!("DELETE FROM {source}.users WHERE user_id=(?)"),
+                    [&user_id],
+                )?;
+            }
+        }
+
+        tx.execute(
+            "INSERT OR REPLACE INTO shards (id, count) VALUES (0, ?)",
+            [count],
+        )?;
+        tx.commit()
+    }
+
+    /// Gets the schema of the shard the data of a given user is stored at.
+    fn user_shard(&self, user_id: UserId) -> &str {
+        &self.shards[shard_index(&user_id.to_vec(), self.shards.len())]
+    }
+
+    /// Gets the schema of the shard the appointment identified by `uuid` (and therefore its tracker, if any) is stored at.
+    ///
+    /// Defaults to the main shard if the appointment cannot be found.
+    fn uuid_shard(&self, uuid: UUID) -> &str {
+        if self.shards.len() > 1 {
+            for schema in self.shards.iter() {
+                if self
+                    .connection
+                    .prepare(&format!(
+                        "SELECT UUID FROM {schema}.appointments WHERE UUID=(?)"
+                    ))
+                    .unwrap()
+                    .exists([uuid.to_vec()])
+                    .unwrap()
+                {
+                    return schema;
+                }
+            }
+        }
+        &self.shards[0]
+    }
+
+    /// Enables the locator filter, building it from the appointments currently in the database.
+    ///
+    /// Once enabled, the filter is kept up to date as appointments are added and removed.
+    pub fn enable_locator_filter(&self) {
+        self.rebuild_locator_filter();
+    }
+
+    /// Rebuilds the locator filter from the appointments currently in the database.
+    fn rebuild_locator_filter(&self) {
+        let mut locators = Vec::new();
+        for schema in self.shards.iter() {
+            let mut stmt = self
+                .connection
+                .prepare(&format!("SELECT locator FROM {schema}.appointments"))
+                .unwrap();
+            locators.extend(
+                stmt.query_map([], |row| {
+                    let raw_locator: Vec<u8> = row.get(0).unwrap();
+                    Ok(Locator::from_slice(&raw_locator).unwrap())
+                })
+                .unwrap()
+                .map(|locator_res| locator_res.unwrap()),
+            );
+        }
+
+        log::debug!("Building locator filter ({} locators)", locators.len());
+        self.locator_filter
+            .replace(Some(LocatorFilter::from_locators(locators)));
+    }
+
+    /// Lets the locator filter (if enabled) know that some appointments have been removed.
+    fn locator_filter_mark_removed(&self, count: usize) {
+        if let Some(filter) = self.locator_filter.borrow_mut().as_mut() {
+            filter.mark_removed(count);
+        }
+    }
+
     /// Stores a user ([UserInfo]) into the database.
     pub(crate) fn store_user(&self, user_id: UserId, user_info: &UserInfo) -> Result<(), Error> {
-        let query =
-        "INSERT INTO users (user_id, available_slots, subscription_start, subscription_expiry) VALUES (?1, ?2, ?3, ?4)";
+        let query = format!(
+            "INSERT INTO {}.users (user_id, available_slots, subscription_start, subscription_expiry) VALUES (?1, ?2, ?3, ?4)",
+            self.user_shard(user_id)
+        );
 
         match self.store_data(
-            query,
+            &query,
             params![
                 user_id.to_vec(),
                 user_info.available_slots,
@@ -121,10 +440,12 @@ impl DBM {
 
     /// Updates an existing user ([UserInfo]) in the database.
     pub(crate) fn update_user(&self, user_id: UserId, user_info: &UserInfo) {
-        let query =
-        "UPDATE users SET available_slots=(?1), subscription_start=(?2), subscription_expiry=(?3) WHERE user_id=(?4)";
+        let query = format!(
+            "UPDATE {}.users SET available_slots=(?1), subscription_start=(?2), subscription_expiry=(?3) WHERE user_id=(?4)",
+            self.user_shard(user_id)
+        );
         match self.update_data(
-            query,
+            &query,
             params![
                 user_info.available_slots,
                 user_info.subscription_start,
@@ -141,11 +462,31 @@ impl DBM {
         }
     }
 
+    /// Stores the [SignatureVersion] agreed with a given user, replacing the previous one (if any).
+    ///
+    /// Users with no version stored are assumed to use [SignatureVersion::V1].
+    pub(crate) fn store_signature_version(
+        &self,
+        user_id: UserId,
+        version: SignatureVersion,
+    ) -> Result<(), Error> {
+        self.store_data(
+            &format!(
+                "INSERT OR REPLACE INTO {}.user_signature_versions (user_id, version) VALUES (?1, ?2)",
+                self.user_shard(user_id)
+            ),
+            params![user_id.to_vec(), u32::from(version)],
+        )
+    }
+
     /// Loads the associated locators ([Locator]) of a given user ([UserId]).
     pub(crate) fn load_user_locators(&self, user_id: UserId) -> Vec<Locator> {
         let mut stmt = self
             .connection
-            .prepare("SELECT locator FROM appointments WHERE user_id=(?)")
+            .prepare(&format!(
+                "SELECT locator FROM {}.appointments WHERE user_id=(?)",
+                self.user_shard(user_id)
+            ))
             .unwrap();
 
         stmt.query_map([user_id.to_vec()], |row| {
@@ -161,20 +502,28 @@ impl DBM {
     /// Loads all users from the database.
     pub(crate) fn load_all_users(&self) -> HashMap<UserId, UserInfo> {
         let mut users = HashMap::new();
-        let mut stmt = self
-            .connection
-            .prepare("SELECT user_id, available_slots, subscription_start, subscription_expiry FROM users")
-            .unwrap();
-        let mut rows = stmt.query([]).unwrap();
+        for schema in self.shards.iter() {
+            let mut stmt = self
+                .connection
+                .prepare(&format!("SELECT u.user_id, available_slots, subscription_start, subscription_expiry, version FROM {schema}.users as u
+                    LEFT JOIN {schema}.user_signature_versions as v ON u.user_id=v.user_id"))
+                .unwrap();
+            let mut rows = stmt.query([]).unwrap();
 
-        while let Ok(Some(row)) = rows.next() {
-            let raw_userid: Vec<u8> = row.get(0).unwrap();
-            let user_id = UserId::from_slice(&raw_userid).unwrap();
-            let slots = row.get(1).unwrap();
-            let start = row.get(2).unwrap();
-            let expiry = row.get(3).unwrap();
+            while let Ok(Some(row)) = rows.next() {
+                let raw_userid: Vec<u8> = row.get(0).unwrap();
+                let user_id = UserId::from_slice(&raw_userid).unwrap();
+                let slots = row.get(1).unwrap();
+                let start = row.get(2).unwrap();
+                let expiry = row.get(3).unwrap();
+                let version = row.get::<_, Option<u32>>(4).unwrap();
 
-            users.insert(user_id, UserInfo::new(slots, start, expiry));
+                users.insert(
+                    user_id,
+                    UserInfo::new(slots, start, expiry)
+                        .with_signature_version(signature_version_from_db(version)),
+                );
+            }
         }
 
         users
@@ -189,40 +538,250 @@ impl DBM {
             .map(|uuid| uuid.to_vec())
             .collect::<Vec<Vec<u8>>>();
 
+        let filter_enabled = self.locator_filter.borrow().is_some();
+        let mut removed_appointments = 0;
+
         for chunk in iter.chunks(limit) {
-            let query = "DELETE FROM users WHERE user_id IN ".to_owned();
             let placeholders = format!("(?{})", (", ?").repeat(chunk.len() - 1));
 
-            match tx.execute(&format!("{query}{placeholders}"), params_from_iter(chunk)) {
-                Ok(_) => log::debug!("Users deletion added to db transaction"),
-                Err(e) => log::error!("Couldn't add deletion query to transaction. Error: {e:?}"),
+            for schema in self.shards.iter() {
+                // Appointments are removed on cascade, so they need to be counted beforehand for the filter to know.
+                if filter_enabled {
+                    removed_appointments += tx
+                        .query_row(
+                            &format!(
+                                "SELECT COUNT(*) FROM {schema}.appointments WHERE user_id IN {placeholders}"
+                            ),
+                            params_from_iter(chunk),
+                            |row| row.get::<_, usize>(0),
+                        )
+                        .unwrap_or(0);
+                }
+
+                match tx.execute(
+                    &format!("DELETE FROM {schema}.users WHERE user_id IN {placeholders}"),
+                    params_from_iter(chunk),
+                ) {
+                    Ok(_) => log::debug!("Users deletion added to db transaction"),
+                    Err(e) => {
+                        log::error!("Couldn't add deletion query to transaction. Error: {e:?}")
+                    }
+                }
             }
         }
 
         match tx.commit() {
-            Ok(_) => log::debug!("Users successfully deleted"),
+            Ok(_) => {
+                log::debug!("Users successfully deleted");
+                self.locator_filter_mark_removed(removed_appointments);
+            }
             Err(e) => log::error!("Couldn't delete users. Error: {e:?}"),
         }
 
         (users.len() as f64 / limit as f64).ceil() as usize
     }
 
+    /// Records that the payment identified by `payment_hash` has been redeemed by a given user.
+    ///
+    /// Fails with [Error::AlreadyExists] if the payment has already been redeemed. Redeemed payments are kept even if the
+    /// user is deleted, so they cannot be redeemed again.
+    pub(crate) fn store_redeemed_payment(
+        &self,
+        payment_hash: &sha256::Hash,
+        user_id: UserId,
+    ) -> Result<(), Error> {
+        self.store_data(
+            "INSERT INTO redeemed_payments (payment_hash, user_id) VALUES (?1, ?2)",
+            params![payment_hash.to_vec(), user_id.to_vec()],
+        )
+    }
+
+    /// Removes a redeemed payment, so it can be redeemed again.
+    pub(crate) fn remove_redeemed_payment(&self, payment_hash: &sha256::Hash) {
+        if let Err(e) = self.remove_data(
+            "DELETE FROM redeemed_payments WHERE payment_hash=(?)",
+            params![payment_hash.to_vec()],
+        ) {
+            log::error!("Couldn't remove redeemed payment: {payment_hash}. Error: {e:?}");
+        }
+    }
+
+    /// Transfers the subscription of `old_user_id` (and all its appointments and trackers) to `new_user_id`.
+    ///
+    /// Appointment (and therefore tracker) [UUID]s depend on the user they belong to, so they are re-computed for
+    /// the new user. The `transfer_id` is recorded alongside so the same transfer cannot be performed twice. Everything
+    /// is done in a single transaction, even if both users belong to different shards.
+    pub(crate) fn transfer_user(
+        &mut self,
+        old_user_id: UserId,
+        new_user_id: UserId,
+        user_info: &UserInfo,
+        transfer_id: &[u8],
+    ) -> Result<(), Error> {
+        let old_shard = self.user_shard(old_user_id).to_owned();
+        let new_shard = self.user_shard(new_user_id).to_owned();
+        let tx = self.connection.transaction().map_err(Error::Unknown)?;
+
+        let used: bool = tx
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM subscription_transfers WHERE transfer_id=(?))",
+                [transfer_id],
+                |row| row.get(0),
+            )
+            .map_err(Error::Unknown)?;
+        if used {
+            return Err(Error::AlreadyExists);
+        }
+
+        tx.execute(
+            "INSERT INTO subscription_transfers (transfer_id, old_user_id, new_user_id) VALUES (?1, ?2, ?3)",
+            params![transfer_id, old_user_id.to_vec(), new_user_id.to_vec()],
+        )
+        .map_err(Error::Unknown)?;
+        tx.execute(
+            &format!("INSERT INTO {new_shard}.users (user_id, available_slots, subscription_start, subscription_expiry) VALUES (?1, ?2, ?3, ?4)"),
+            params![
+                new_user_id.to_vec(),
+                user_info.available_slots,
+                user_info.subscription_start,
+                user_info.subscription_expiry,
+            ],
+        )
+        .map_err(Error::Unknown)?;
+        // The signature version agreed with the old user is kept, given the new one has not negotiated any.
+        tx.execute(
+            &format!("INSERT INTO {new_shard}.user_signature_versions (user_id, version) VALUES (?1, ?2)"),
+            params![new_user_id.to_vec(), u32::from(user_info.signature_version)],
+        )
+        .map_err(Error::Unknown)?;
+
+        let uuids = {
+            let mut stmt = tx
+                .prepare(&format!(
+                    "SELECT UUID, locator FROM {old_shard}.appointments WHERE user_id=(?)"
+                ))
+                .map_err(Error::Unknown)?;
+            let uuids = stmt
+                .query_map([old_user_id.to_vec()], |row| {
+                    let raw_uuid: Vec<u8> = row.get(0)?;
+                    let raw_locator: Vec<u8> = row.get(1)?;
+                    Ok((
+                        UUID::from_slice(&raw_uuid).unwrap(),
+                        Locator::from_slice(&raw_locator).unwrap(),
+                    ))
+                })
+                .map_err(Error::Unknown)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Error::Unknown)?;
+            uuids
+        };
+
+        for (old_uuid, locator) in uuids.iter() {
+            let new_uuid = UUID::new(*locator, new_user_id);
+            // Trackers reference appointments, so the new appointment needs to exist before they can be moved over.
+            tx.execute(
+                &format!("INSERT INTO {new_shard}.appointments (UUID, locator, encrypted_blob, to_self_delay, user_signature, start_block, user_id)
+                SELECT (?1), locator, encrypted_blob, to_self_delay, user_signature, start_block, (?2) FROM {old_shard}.appointments WHERE UUID=(?3)"),
+                params![new_uuid.to_vec(), new_user_id.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+            // The old trackers are removed on cascade alongside the old appointments.
+            tx.execute(
+                &format!("INSERT INTO {new_shard}.trackers (UUID, dispute_tx, penalty_tx, height, confirmed)
+                SELECT (?1), dispute_tx, penalty_tx, height, confirmed FROM {old_shard}.trackers WHERE UUID=(?2)"),
+                params![new_uuid.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+            // So are the per-appointment terms (TTL hints, priorities and rewards), so they need to be moved over as well.
+            tx.execute(
+                &format!(
+                    "INSERT INTO {new_shard}.appointment_ttls (UUID, expiry_height)
+                SELECT (?1), expiry_height FROM {old_shard}.appointment_ttls WHERE UUID=(?2)"
+                ),
+                params![new_uuid.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+            tx.execute(
+                &format!(
+                    "INSERT INTO {new_shard}.appointment_priorities (UUID, priority)
+                SELECT (?1), priority FROM {old_shard}.appointment_priorities WHERE UUID=(?2)"
+                ),
+                params![new_uuid.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+            tx.execute(
+                &format!(
+                    "INSERT INTO {new_shard}.appointment_rewards (UUID, reward)
+                SELECT (?1), reward FROM {old_shard}.appointment_rewards WHERE UUID=(?2)"
+                ),
+                params![new_uuid.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+            tx.execute(
+                "UPDATE penalty_ledger SET UUID=(?1), user_id=(?2) WHERE UUID=(?3)",
+                params![new_uuid.to_vec(), new_user_id.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+            tx.execute(
+                "UPDATE fee_bumps SET UUID=(?1) WHERE UUID=(?2)",
+                params![new_uuid.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+            tx.execute(
+                "UPDATE penalty_replacements SET UUID=(?1) WHERE UUID=(?2)",
+                params![new_uuid.to_vec(), old_uuid.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+        }
+
+        // The old appointments are removed on cascade.
+        tx.execute(
+            &format!("DELETE FROM {old_shard}.users WHERE user_id=(?)"),
+            [old_user_id.to_vec()],
+        )
+        .map_err(Error::Unknown)?;
+
+        match tx.commit() {
+            Ok(_) => {
+                log::debug!(
+                    "Subscription successfully transferred: {old_user_id} -> {new_user_id} ({} appointments)",
+                    uuids.len()
+                );
+                Ok(())
+            }
+            Err(e) => {
+                log::error!("Couldn't transfer subscription: {old_user_id}. Error: {e:?}");
+                Err(Error::Unknown(e))
+            }
+        }
+    }
+
     /// Get the number of stored appointments.
     pub(crate) fn get_appointments_count(&self) -> usize {
-        let mut stmt = self
-            .connection
-            .prepare("SELECT COUNT(*) FROM appointments as a LEFT JOIN trackers as t ON a.UUID=t.UUID WHERE t.UUID IS NULL")
-            .unwrap();
-        stmt.query_row([], |row| row.get(0)).unwrap()
+        self.shards
+            .iter()
+            .map(|schema| {
+                let mut stmt = self
+                    .connection
+                    .prepare(&format!("SELECT COUNT(*) FROM {schema}.appointments as a LEFT JOIN {schema}.trackers as t ON a.UUID=t.UUID WHERE t.UUID IS NULL"))
+                    .unwrap();
+                stmt.query_row([], |row| row.get::<_, usize>(0)).unwrap()
+            })
+            .sum()
     }
 
     /// Get the number of stored trackers.
     pub(crate) fn get_trackers_count(&self) -> usize {
-        let mut stmt = self
-            .connection
-            .prepare("SELECT COUNT(*) FROM trackers")
-            .unwrap();
-        stmt.query_row([], |row| row.get(0)).unwrap()
+        self.shards
+            .iter()
+            .map(|schema| {
+                let mut stmt = self
+                    .connection
+                    .prepare(&format!("SELECT COUNT(*) FROM {schema}.trackers"))
+                    .unwrap();
+                stmt.query_row([], |row| row.get::<_, usize>(0)).unwrap()
+            })
+            .sum()
     }
 
     /// Stores an [Appointment] into the database.
@@ -231,9 +790,12 @@ impl DBM {
         uuid: UUID,
         appointment: &ExtendedAppointment,
     ) -> Result<(), Error> {
-        let query = "INSERT INTO appointments (UUID, locator, encrypted_blob, to_self_delay, user_signature, start_block, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+        let query = format!(
+            "INSERT INTO {}.appointments (UUID, locator, encrypted_blob, to_self_delay, user_signature, start_block, user_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            self.user_shard(appointment.user_id)
+        );
         match self.store_data(
-            query,
+            &query,
             params![
                 uuid.to_vec(),
                 appointment.locator().to_vec(),
@@ -246,6 +808,9 @@ impl DBM {
         ) {
             Ok(x) => {
                 log::debug!("Appointment successfully stored: {uuid}");
+                if let Some(filter) = self.locator_filter.borrow_mut().as_mut() {
+                    filter.insert(&appointment.locator());
+                }
                 Ok(x)
             }
             Err(e) => {
@@ -262,10 +827,12 @@ impl DBM {
         appointment: &ExtendedAppointment,
     ) -> Result<(), Error> {
         // DISCUSS: Check what fields we'd like to make updatable. e_blob and signature are the obvious, to_self_delay and start_block may not be necessary (or even risky)
-        let query =
-            "UPDATE appointments SET encrypted_blob=(?1), to_self_delay=(?2), user_signature=(?3), start_block=(?4) WHERE UUID=(?5)";
+        let query = format!(
+            "UPDATE {}.appointments SET encrypted_blob=(?1), to_self_delay=(?2), user_signature=(?3), start_block=(?4) WHERE UUID=(?5)",
+            self.uuid_shard(uuid)
+        );
         match self.update_data(
-            query,
+            &query,
             params![
                 appointment.encrypted_blob(),
                 appointment.to_self_delay(),
@@ -290,10 +857,11 @@ impl DBM {
         let key = uuid.to_vec();
         let mut stmt = self
             .connection
-            .prepare(
+            .prepare(&format!(
                 "SELECT locator, encrypted_blob, to_self_delay, user_signature, start_block, user_id
-                    FROM appointments WHERE UUID=(?)"
-            )
+                    FROM {}.appointments WHERE UUID=(?)",
+                self.uuid_shard(uuid)
+            ))
             .unwrap();
 
         stmt.query_row([key], |row| {
@@ -320,7 +888,10 @@ impl DBM {
     /// Check if an appointment with `uuid` exists.
     pub(crate) fn appointment_exists(&self, uuid: UUID) -> bool {
         self.connection
-            .prepare("SELECT UUID FROM appointments WHERE UUID=(?)")
+            .prepare(&format!(
+                "SELECT UUID FROM {}.appointments WHERE UUID=(?)",
+                self.uuid_shard(uuid)
+            ))
             .unwrap()
             .exists([uuid.to_vec()])
             .unwrap()
@@ -334,40 +905,44 @@ impl DBM {
     ) -> HashMap<UUID, ExtendedAppointment> {
         let mut appointments = HashMap::new();
 
-        let mut sql =
-            "SELECT a.UUID, a.locator, a.encrypted_blob, a.to_self_delay, a.user_signature, a.start_block, a.user_id
-                FROM appointments as a LEFT JOIN trackers as t ON a.UUID=t.UUID WHERE t.UUID IS NULL".to_string();
-        // If a locator was passed, filter based on it.
-        if locator.is_some() {
-            sql.push_str(" AND a.locator=(?)");
-        }
-        let mut stmt = self.connection.prepare(&sql).unwrap();
-
-        let mut rows = if let Some(locator) = locator {
-            stmt.query([locator.to_vec()]).unwrap()
-        } else {
-            stmt.query([]).unwrap()
-        };
-
-        while let Ok(Some(row)) = rows.next() {
-            let raw_uuid: Vec<u8> = row.get(0).unwrap();
-            let uuid = UUID::from_slice(&raw_uuid[0..20]).unwrap();
-            let raw_locator: Vec<u8> = row.get(1).unwrap();
-            let locator = Locator::from_slice(&raw_locator).unwrap();
-            let raw_userid: Vec<u8> = row.get(6).unwrap();
-            let user_id = UserId::from_slice(&raw_userid).unwrap();
+        for schema in self.shards.iter() {
+            let mut sql = format!(
+                "SELECT a.UUID, a.locator, a.encrypted_blob, a.to_self_delay, a.user_signature, a.start_block, a.user_id
+                    FROM {schema}.appointments as a LEFT JOIN {schema}.trackers as t ON a.UUID=t.UUID WHERE t.UUID IS NULL"
+            );
+            // If a locator was passed, filter based on it.
+            if locator.is_some() {
+                sql.push_str(" AND a.locator=(?)");
+            }
+            let mut stmt = self.connection.prepare(&sql).unwrap();
 
-            let appointment = Appointment::new(locator, row.get(2).unwrap(), row.get(3).unwrap());
+            let mut rows = if let Some(locator) = locator {
+                stmt.query([locator.to_vec()]).unwrap()
+            } else {
+                stmt.query([]).unwrap()
+            };
 
-            appointments.insert(
-                uuid,
-                ExtendedAppointment::new(
-                    appointment,
-                    user_id,
-                    row.get(4).unwrap(),
-                    row.get(5).unwrap(),
-                ),
-            );
+            while let Ok(Some(row)) = rows.next() {
+                let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                let uuid = UUID::from_slice(&raw_uuid[0..20]).unwrap();
+                let raw_locator: Vec<u8> = row.get(1).unwrap();
+                let locator = Locator::from_slice(&raw_locator).unwrap();
+                let raw_userid: Vec<u8> = row.get(6).unwrap();
+                let user_id = UserId::from_slice(&raw_userid).unwrap();
+
+                let appointment =
+                    Appointment::new(locator, row.get(2).unwrap(), row.get(3).unwrap());
+
+                appointments.insert(
+                    uuid,
+                    ExtendedAppointment::new(
+                        appointment,
+                        user_id,
+                        row.get(4).unwrap(),
+                        row.get(5).unwrap(),
+                    ),
+                );
+            }
         }
 
         appointments
@@ -377,7 +952,10 @@ impl DBM {
     pub(crate) fn get_appointment_length(&self, uuid: UUID) -> Option<usize> {
         let mut stmt = self
             .connection
-            .prepare("SELECT length(encrypted_blob) FROM appointments WHERE UUID=(?)")
+            .prepare(&format!(
+                "SELECT length(encrypted_blob) FROM {}.appointments WHERE UUID=(?)",
+                self.uuid_shard(uuid)
+            ))
             .unwrap();
 
         stmt.query_row([uuid.to_vec()], |row| row.get(0)).ok()
@@ -388,7 +966,10 @@ impl DBM {
     pub(crate) fn get_appointment_user_and_length(&self, uuid: UUID) -> Option<(UserId, usize)> {
         let mut stmt = self
             .connection
-            .prepare("SELECT user_id, length(encrypted_blob) FROM appointments WHERE UUID=(?)")
+            .prepare(&format!(
+                "SELECT user_id, length(encrypted_blob) FROM {}.appointments WHERE UUID=(?)",
+                self.uuid_shard(uuid)
+            ))
             .unwrap();
 
         stmt.query_row([uuid.to_vec()], |row| {
@@ -401,10 +982,14 @@ impl DBM {
 
     /// Removes an [Appointment] from the database.
     pub(crate) fn remove_appointment(&self, uuid: UUID) {
-        let query = "DELETE FROM appointments WHERE UUID=(?)";
-        match self.remove_data(query, params![uuid.to_vec()]) {
+        let query = format!(
+            "DELETE FROM {}.appointments WHERE UUID=(?)",
+            self.uuid_shard(uuid)
+        );
+        match self.remove_data(&query, params![uuid.to_vec()]) {
             Ok(_) => {
                 log::debug!("Appointment successfully removed: {uuid}");
+                self.locator_filter_mark_removed(1);
             }
             Err(_) => {
                 log::error!("Appointment not found, data cannot be removed: {uuid}");
@@ -421,6 +1006,10 @@ impl DBM {
         updated_users: &HashMap<UserId, UserInfo>,
     ) -> usize {
         let limit = self.connection.limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER) as usize;
+        let user_shards = updated_users
+            .keys()
+            .map(|id| (*id, self.user_shard(*id).to_owned()))
+            .collect::<HashMap<_, _>>();
         let tx = self.connection.transaction().unwrap();
         let iter = appointments
             .iter()
@@ -428,71 +1017,302 @@ impl DBM {
             .collect::<Vec<Vec<u8>>>();
 
         for chunk in iter.chunks(limit) {
-            let query = "DELETE FROM appointments WHERE UUID IN ".to_owned();
             let placeholders = format!("(?{})", (", ?").repeat(chunk.len() - 1));
 
-            match tx.execute(&format!("{query}{placeholders}"), params_from_iter(chunk)) {
-                Ok(_) => log::debug!("Appointments deletion added to db transaction"),
-                Err(e) => log::error!("Couldn't add deletion query to transaction. Error: {e:?}"),
+            for schema in self.shards.iter() {
+                match tx.execute(
+                    &format!("DELETE FROM {schema}.appointments WHERE UUID IN {placeholders}"),
+                    params_from_iter(chunk),
+                ) {
+                    Ok(_) => log::debug!("Appointments deletion added to db transaction"),
+                    Err(e) => {
+                        log::error!("Couldn't add deletion query to transaction. Error: {e:?}")
+                    }
+                }
             }
         }
 
         for (id, info) in updated_users.iter() {
-            let query = "UPDATE users SET available_slots=(?1) WHERE user_id=(?2)";
-            match tx.execute(query, params![info.available_slots, id.to_vec(),]) {
+            let query = format!(
+                "UPDATE {}.users SET available_slots=(?1) WHERE user_id=(?2)",
+                user_shards[id]
+            );
+            match tx.execute(&query, params![info.available_slots, id.to_vec(),]) {
                 Ok(_) => log::debug!("User update added to db transaction"),
                 Err(e) => log::error!("Couldn't add update query to transaction. Error: {e:?}"),
             };
         }
 
         match tx.commit() {
-            Ok(_) => log::debug!("Appointments successfully deleted"),
+            Ok(_) => {
+                log::debug!("Appointments successfully deleted");
+                self.locator_filter_mark_removed(appointments.len());
+            }
             Err(e) => log::error!("Couldn't delete appointments. Error: {e:?}"),
         }
 
         (appointments.len() as f64 / limit as f64).ceil() as usize
     }
 
+    /// Sets the height at which an appointment expires (as hinted by its TTL), replacing any previous one.
+    ///
+    /// If no height is given, the appointment is set not to expire (it is kept as long as its owner's subscription is).
+    pub(crate) fn store_appointment_expiry(
+        &self,
+        uuid: UUID,
+        expiry_height: Option<u32>,
+    ) -> Result<(), Error> {
+        let schema = self.uuid_shard(uuid);
+        match expiry_height {
+            Some(height) => self.store_data(
+                &format!("INSERT OR REPLACE INTO {schema}.appointment_ttls (UUID, expiry_height) VALUES (?1, ?2)"),
+                params![uuid.to_vec(), height],
+            ),
+            None => self
+                .connection
+                .execute(
+                    &format!("DELETE FROM {schema}.appointment_ttls WHERE UUID=(?)"),
+                    params![uuid.to_vec()],
+                )
+                .map(|_| ())
+                .map_err(Error::Unknown),
+        }
+    }
+
+    /// Sets the priority of an appointment, replacing any previous one.
+    ///
+    /// If no priority is given, the appointment is set back to the default one (see [Self::load_appointment_priority]).
+    pub(crate) fn store_appointment_priority(
+        &self,
+        uuid: UUID,
+        priority: Option<u32>,
+    ) -> Result<(), Error> {
+        let schema = self.uuid_shard(uuid);
+        match priority {
+            Some(priority) => self.store_data(
+                &format!("INSERT OR REPLACE INTO {schema}.appointment_priorities (UUID, priority) VALUES (?1, ?2)"),
+                params![uuid.to_vec(), priority],
+            ),
+            None => self
+                .connection
+                .execute(
+                    &format!("DELETE FROM {schema}.appointment_priorities WHERE UUID=(?)"),
+                    params![uuid.to_vec()],
+                )
+                .map(|_| ())
+                .map_err(Error::Unknown),
+        }
+    }
+
+    /// Loads the priority of an appointment. Appointments with no priority set have the lowest one (zero).
+    pub(crate) fn load_appointment_priority(&self, uuid: UUID) -> u32 {
+        self.connection
+            .query_row(
+                &format!(
+                    "SELECT priority FROM {}.appointment_priorities WHERE UUID=(?)",
+                    self.uuid_shard(uuid)
+                ),
+                [uuid.to_vec()],
+                |row| row.get(0),
+            )
+            .unwrap_or(0)
+    }
+
+    /// Sets the reward (in sats) the tower is entitled to if an appointment is triggered, replacing any previous one.
+    ///
+    /// If no reward is given, the appointment is set back to carry no reward terms.
+    pub(crate) fn store_appointment_reward(
+        &self,
+        uuid: UUID,
+        reward: Option<u64>,
+    ) -> Result<(), Error> {
+        let schema = self.uuid_shard(uuid);
+        match reward {
+            Some(reward) => self.store_data(
+                &format!("INSERT OR REPLACE INTO {schema}.appointment_rewards (UUID, reward) VALUES (?1, ?2)"),
+                params![uuid.to_vec(), reward],
+            ),
+            None => self
+                .connection
+                .execute(
+                    &format!("DELETE FROM {schema}.appointment_rewards WHERE UUID=(?)"),
+                    params![uuid.to_vec()],
+                )
+                .map(|_| ())
+                .map_err(Error::Unknown),
+        }
+    }
+
+    /// Loads the reward (in sats) the tower is entitled to if an appointment is triggered, if any.
+    pub(crate) fn load_appointment_reward(&self, uuid: UUID) -> Option<u64> {
+        self.connection
+            .query_row(
+                &format!(
+                    "SELECT reward FROM {}.appointment_rewards WHERE UUID=(?)",
+                    self.uuid_shard(uuid)
+                ),
+                [uuid.to_vec()],
+                |row| row.get(0),
+            )
+            .ok()
+    }
+
+    /// Loads the [`UUID`]s of the appointments that expire at or before `height`.
+    ///
+    /// Appointments that have already been triggered (that is, that have a tracker) are not included, since they
+    /// are now handled by the [Responder](crate::responder::Responder).
+    pub(crate) fn load_expired_appointments(&self, height: u32) -> Vec<UUID> {
+        let mut uuids = Vec::new();
+        for schema in self.shards.iter() {
+            let mut stmt = self
+                .connection
+                .prepare(&format!(
+                    "SELECT UUID FROM {schema}.appointment_ttls WHERE expiry_height<=(?) AND UUID NOT IN (SELECT UUID FROM {schema}.trackers)",
+                ))
+                .unwrap();
+
+            uuids.extend(
+                stmt.query_map([height], |row| {
+                    let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                    let uuid = UUID::from_slice(&raw_uuid).unwrap();
+                    Ok(uuid)
+                })
+                .unwrap()
+                .map(|uuid_res| uuid_res.unwrap()),
+            );
+        }
+        uuids
+    }
+
+    /// Loads the [`UUID`]s of up to `limit` appointments whose owner's subscription has expired at or before `height`,
+    /// oldest first (by start block).
+    ///
+    /// Appointments that have already been triggered (that is, that have a tracker) are not included, since their
+    /// penalties may still be in flight.
+    pub(crate) fn load_evictable_appointments(&self, height: u32, limit: usize) -> Vec<UUID> {
+        // Each shard gives its oldest ones, and the oldest of them all are picked.
+        let mut evictable = Vec::new();
+        for schema in self.shards.iter() {
+            let mut stmt = self
+                .connection
+                .prepare(&format!(
+                    "SELECT a.UUID, a.start_block FROM {schema}.appointments as a JOIN {schema}.users as u ON a.user_id=u.user_id
+                        WHERE u.subscription_expiry<=(?1) AND a.UUID NOT IN (SELECT UUID FROM {schema}.trackers)
+                        ORDER BY a.start_block LIMIT (?2)",
+                ))
+                .unwrap();
+
+            evictable.extend(
+                stmt.query_map(params![height, limit], |row| {
+                    let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                    let uuid = UUID::from_slice(&raw_uuid).unwrap();
+                    Ok((row.get::<_, u32>(1).unwrap(), uuid))
+                })
+                .unwrap()
+                .map(|uuid_res| uuid_res.unwrap()),
+            );
+        }
+
+        evictable.sort_by_key(|(start_block, _)| *start_block);
+        evictable
+            .into_iter()
+            .take(limit)
+            .map(|(_, uuid)| uuid)
+            .collect()
+    }
+
+    /// Gets the size (in bytes) of the data held by the database.
+    ///
+    /// Pages freed by deleted data are not counted, given they are reused before the database grows any further. The size
+    /// of all shards is added up.
+    pub(crate) fn get_used_size(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|schema| {
+                let pragma = |name| {
+                    self.connection
+                        .pragma_query_value(Some(DatabaseName::Attached(schema)), name, |row| {
+                            row.get::<_, u64>(0)
+                        })
+                        .unwrap()
+                };
+                (pragma("page_count") - pragma("freelist_count")) * pragma("page_size")
+            })
+            .sum()
+    }
+
     /// Loads the [`UUID`]s of appointments triggered by `locator`.
     pub(crate) fn load_uuids(&self, locator: Locator) -> Vec<UUID> {
-        let mut stmt = self
-            .connection
-            .prepare("SELECT UUID from appointments WHERE locator=(?)")
-            .unwrap();
+        let mut uuids = Vec::new();
+        for schema in self.shards.iter() {
+            let mut stmt = self
+                .connection
+                .prepare(&format!(
+                    "SELECT UUID from {schema}.appointments WHERE locator=(?)"
+                ))
+                .unwrap();
 
-        stmt.query_map([locator.to_vec()], |row| {
-            let raw_uuid: Vec<u8> = row.get(0).unwrap();
-            let uuid = UUID::from_slice(&raw_uuid).unwrap();
-            Ok(uuid)
-        })
-        .unwrap()
-        .map(|uuid_res| uuid_res.unwrap())
-        .collect()
+            uuids.extend(
+                stmt.query_map([locator.to_vec()], |row| {
+                    let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                    let uuid = UUID::from_slice(&raw_uuid).unwrap();
+                    Ok(uuid)
+                })
+                .unwrap()
+                .map(|uuid_res| uuid_res.unwrap()),
+            );
+        }
+        uuids
     }
 
     /// Filters the given set of [`Locator`]s by including only the ones which trigger any of our stored appointments.
+    ///
+    /// If the locator filter is enabled, locators are checked against it first so only the ones that may be known are
+    /// looked up in the database.
     pub(crate) fn batch_check_locators_exist(&self, locators: Vec<&Locator>) -> Vec<Locator> {
+        if self
+            .locator_filter
+            .borrow()
+            .as_ref()
+            .is_some_and(|filter| filter.needs_rebuild())
+        {
+            self.rebuild_locator_filter();
+        }
+        let locators = match self.locator_filter.borrow().as_ref() {
+            Some(filter) => locators
+                .into_iter()
+                .filter(|l| filter.contains(l))
+                .collect(),
+            None => locators,
+        };
+        if locators.is_empty() {
+            return Vec::new();
+        }
+
         let mut registered_locators = Vec::new();
         let locators: Vec<Vec<u8>> = locators.iter().map(|l| l.to_vec()).collect();
         let limit = self.connection.limit(Limit::SQLITE_LIMIT_VARIABLE_NUMBER) as usize;
 
         for chunk in locators.chunks(limit) {
-            let query = "SELECT locator FROM appointments WHERE locator IN ".to_owned();
             let placeholders = format!("(?{})", (", ?").repeat(chunk.len() - 1));
 
-            let mut stmt = self
-                .connection
-                .prepare(&format!("{query}{placeholders}"))
-                .unwrap();
-            let known_locators = stmt
-                .query_map(params_from_iter(chunk), |row| {
-                    let raw_locator: Vec<u8> = row.get(0).unwrap();
-                    let locator = Locator::from_slice(&raw_locator).unwrap();
-                    Ok(locator)
-                })
-                .unwrap()
-                .map(|locator_res| locator_res.unwrap());
-            registered_locators.extend(known_locators);
+            for schema in self.shards.iter() {
+                let mut stmt = self
+                    .connection
+                    .prepare(&format!(
+                        "SELECT locator FROM {schema}.appointments WHERE locator IN {placeholders}"
+                    ))
+                    .unwrap();
+                let known_locators = stmt
+                    .query_map(params_from_iter(chunk), |row| {
+                        let raw_locator: Vec<u8> = row.get(0).unwrap();
+                        let locator = Locator::from_slice(&raw_locator).unwrap();
+                        Ok(locator)
+                    })
+                    .unwrap()
+                    .map(|locator_res| locator_res.unwrap());
+                registered_locators.extend(known_locators);
+            }
         }
 
         registered_locators
@@ -506,10 +1326,12 @@ impl DBM {
     ) -> Result<(), Error> {
         let (height, confirmed) = tracker.status.to_db_data().ok_or(Error::MissingField)?;
 
-        let query =
-            "INSERT INTO trackers (UUID, dispute_tx, penalty_tx, height, confirmed) VALUES (?1, ?2, ?3, ?4, ?5)";
+        let query = format!(
+            "INSERT INTO {}.trackers (UUID, dispute_tx, penalty_tx, height, confirmed) VALUES (?1, ?2, ?3, ?4, ?5)",
+            self.user_shard(tracker.user_id)
+        );
         match self.store_data(
-            query,
+            &query,
             params![
                 uuid.to_vec(),
                 consensus::serialize(&tracker.dispute_tx),
@@ -519,34 +1341,70 @@ impl DBM {
             ],
         ) {
             Ok(x) => {
-                log::debug!("Tracker successfully stored: {uuid}");
+                log::debug!("Tracker successfully stored: {uuid}");
+                Ok(x)
+            }
+            Err(e) => {
+                log::error!("Couldn't store tracker: {uuid}. Error: {e:?}");
+                Err(e)
+            }
+        }
+    }
+
+    /// Updates the tracker status in the database.
+    ///
+    /// The only updatable fields are `height` and `confirmed`.
+    pub(crate) fn update_tracker_status(
+        &self,
+        uuid: UUID,
+        status: &ConfirmationStatus,
+    ) -> Result<(), Error> {
+        let (height, confirmed) = status.to_db_data().ok_or(Error::MissingField)?;
+
+        let query = format!(
+            "UPDATE {}.trackers SET height=(?1), confirmed=(?2) WHERE UUID=(?3)",
+            self.uuid_shard(uuid)
+        );
+        match self.update_data(&query, params![height, confirmed, uuid.to_vec(),]) {
+            Ok(x) => {
+                log::debug!("Tracker successfully updated: {uuid}");
                 Ok(x)
             }
             Err(e) => {
-                log::error!("Couldn't store tracker: {uuid}. Error: {e:?}");
+                log::error!("Couldn't update tracker: {uuid}. Error: {e:?}");
                 Err(e)
             }
         }
     }
 
-    /// Updates the tracker status in the database.
-    ///
-    /// The only updatable fields are `height` and `confirmed`.
-    pub(crate) fn update_tracker_status(
+    /// Replaces the penalty of a tracker in the database, alongside its status.
+    pub(crate) fn update_tracker_penalty(
         &self,
         uuid: UUID,
+        penalty_tx: &Transaction,
         status: &ConfirmationStatus,
     ) -> Result<(), Error> {
         let (height, confirmed) = status.to_db_data().ok_or(Error::MissingField)?;
 
-        let query = "UPDATE trackers SET height=(?1), confirmed=(?2) WHERE UUID=(?3)";
-        match self.update_data(query, params![height, confirmed, uuid.to_vec(),]) {
+        let query = format!(
+            "UPDATE {}.trackers SET penalty_tx=(?1), height=(?2), confirmed=(?3) WHERE UUID=(?4)",
+            self.uuid_shard(uuid)
+        );
+        match self.update_data(
+            &query,
+            params![
+                consensus::serialize(penalty_tx),
+                height,
+                confirmed,
+                uuid.to_vec()
+            ],
+        ) {
             Ok(x) => {
-                log::debug!("Tracker successfully updated: {uuid}");
+                log::debug!("Tracker penalty successfully replaced: {uuid}");
                 Ok(x)
             }
             Err(e) => {
-                log::error!("Couldn't update tracker: {uuid}. Error: {e:?}");
+                log::error!("Couldn't replace tracker penalty: {uuid}. Error: {e:?}");
                 Err(e)
             }
         }
@@ -556,10 +1414,11 @@ impl DBM {
     pub(crate) fn load_tracker(&self, uuid: UUID) -> Option<TransactionTracker> {
         let key = uuid.to_vec();
         let mut stmt = self
-            .connection.prepare(
+            .connection.prepare(&format!(
                 "SELECT t.dispute_tx, t.penalty_tx, t.height, t.confirmed, a.user_id
-                    FROM trackers as t INNER JOIN appointments as a ON t.UUID=a.UUID WHERE t.UUID=(?)"
-            )
+                    FROM {schema}.trackers as t INNER JOIN {schema}.appointments as a ON t.UUID=a.UUID WHERE t.UUID=(?)",
+                schema = self.uuid_shard(uuid)
+            ))
             .unwrap();
 
         stmt.query_row([key], |row| {
@@ -586,7 +1445,10 @@ impl DBM {
     /// Check if a tracker with `uuid` exists.
     pub(crate) fn tracker_exists(&self, uuid: UUID) -> bool {
         self.connection
-            .prepare("SELECT UUID FROM trackers WHERE UUID=(?)")
+            .prepare(&format!(
+                "SELECT UUID FROM {}.trackers WHERE UUID=(?)",
+                self.uuid_shard(uuid)
+            ))
             .unwrap()
             .exists([uuid.to_vec()])
             .unwrap()
@@ -600,42 +1462,45 @@ impl DBM {
     ) -> HashMap<UUID, TransactionTracker> {
         let mut trackers = HashMap::new();
 
-        let mut sql = "SELECT t.UUID, t.dispute_tx, t.penalty_tx, t.height, t.confirmed, a.user_id
-            FROM trackers as t INNER JOIN appointments as a ON t.UUID=a.UUID"
-            .to_string();
-        // If a locator was passed, filter based on it.
-        if locator.is_some() {
-            sql.push_str(" WHERE a.locator=(?)");
-        }
-        let mut stmt = self.connection.prepare(&sql).unwrap();
-
-        let mut rows = if let Some(locator) = locator {
-            stmt.query([locator.to_vec()]).unwrap()
-        } else {
-            stmt.query([]).unwrap()
-        };
+        for schema in self.shards.iter() {
+            let mut sql = format!(
+                "SELECT t.UUID, t.dispute_tx, t.penalty_tx, t.height, t.confirmed, a.user_id
+                    FROM {schema}.trackers as t INNER JOIN {schema}.appointments as a ON t.UUID=a.UUID"
+            );
+            // If a locator was passed, filter based on it.
+            if locator.is_some() {
+                sql.push_str(" WHERE a.locator=(?)");
+            }
+            let mut stmt = self.connection.prepare(&sql).unwrap();
 
-        while let Ok(Some(row)) = rows.next() {
-            let raw_uuid: Vec<u8> = row.get(0).unwrap();
-            let uuid = UUID::from_slice(&raw_uuid[0..20]).unwrap();
-            let raw_dispute_tx: Vec<u8> = row.get(1).unwrap();
-            let dispute_tx = consensus::deserialize(&raw_dispute_tx).unwrap();
-            let raw_penalty_tx: Vec<u8> = row.get(2).unwrap();
-            let penalty_tx = consensus::deserialize(&raw_penalty_tx).unwrap();
-            let height: u32 = row.get(3).unwrap();
-            let confirmed: bool = row.get(4).unwrap();
-            let raw_userid: Vec<u8> = row.get(5).unwrap();
-            let user_id = UserId::from_slice(&raw_userid).unwrap();
+            let mut rows = if let Some(locator) = locator {
+                stmt.query([locator.to_vec()]).unwrap()
+            } else {
+                stmt.query([]).unwrap()
+            };
 
-            trackers.insert(
-                uuid,
-                TransactionTracker {
-                    dispute_tx,
-                    penalty_tx,
-                    status: ConfirmationStatus::from_db_data(height, confirmed),
-                    user_id,
-                },
-            );
+            while let Ok(Some(row)) = rows.next() {
+                let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                let uuid = UUID::from_slice(&raw_uuid[0..20]).unwrap();
+                let raw_dispute_tx: Vec<u8> = row.get(1).unwrap();
+                let dispute_tx = consensus::deserialize(&raw_dispute_tx).unwrap();
+                let raw_penalty_tx: Vec<u8> = row.get(2).unwrap();
+                let penalty_tx = consensus::deserialize(&raw_penalty_tx).unwrap();
+                let height: u32 = row.get(3).unwrap();
+                let confirmed: bool = row.get(4).unwrap();
+                let raw_userid: Vec<u8> = row.get(5).unwrap();
+                let user_id = UserId::from_slice(&raw_userid).unwrap();
+
+                trackers.insert(
+                    uuid,
+                    TransactionTracker {
+                        dispute_tx,
+                        penalty_tx,
+                        status: ConfirmationStatus::from_db_data(height, confirmed),
+                        user_id,
+                    },
+                );
+            }
         }
 
         trackers
@@ -650,55 +1515,312 @@ impl DBM {
         status: ConfirmationStatus,
     ) -> Result<Vec<UUID>, Error> {
         let (height, confirmed) = status.to_db_data().ok_or(Error::MissingField)?;
-        let sql = format!(
-            "SELECT UUID FROM trackers WHERE confirmed=(?1) AND height{}(?2)",
-            if confirmed { "=" } else { "<=" }
-        );
-        let mut stmt = self.connection.prepare(&sql).unwrap();
+        let mut uuids = Vec::new();
+        for schema in self.shards.iter() {
+            let sql = format!(
+                "SELECT UUID FROM {schema}.trackers WHERE confirmed=(?1) AND height{}(?2)",
+                if confirmed { "=" } else { "<=" }
+            );
+            let mut stmt = self.connection.prepare(&sql).unwrap();
 
-        Ok(stmt
-            .query_map(params![confirmed, height], |row| {
-                let raw_uuid: Vec<u8> = row.get(0).unwrap();
-                let uuid = UUID::from_slice(&raw_uuid).unwrap();
-                Ok(uuid)
-            })
-            .unwrap()
-            .map(|uuid_res| uuid_res.unwrap())
-            .collect())
+            uuids.extend(
+                stmt.query_map(params![confirmed, height], |row| {
+                    let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                    let uuid = UUID::from_slice(&raw_uuid).unwrap();
+                    Ok(uuid)
+                })
+                .unwrap()
+                .map(|uuid_res| uuid_res.unwrap()),
+            );
+        }
+
+        Ok(uuids)
     }
 
     /// Loads the transaction IDs of all the penalties and their status from the database.
     pub(crate) fn load_penalties_summaries(&self) -> HashMap<UUID, PenaltySummary> {
         let mut summaries = HashMap::new();
 
+        for schema in self.shards.iter() {
+            let mut stmt = self
+                .connection
+                .prepare(&format!(
+                    "SELECT t.UUID, t.penalty_tx, t.height, t.confirmed
+                        FROM {schema}.trackers as t INNER JOIN {schema}.appointments as a ON t.UUID=a.UUID",
+                ))
+                .unwrap();
+            let mut rows = stmt.query([]).unwrap();
+
+            while let Ok(Some(row)) = rows.next() {
+                let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                let raw_penalty_tx: Vec<u8> = row.get(1).unwrap();
+                let height: u32 = row.get(2).unwrap();
+                let confirmed: bool = row.get(3).unwrap();
+
+                // DISCUSS: Should we store the txids to avoid pulling raw txs and deserializing then hashing them.
+                let penalty_txid = consensus::deserialize::<bitcoin::Transaction>(&raw_penalty_tx)
+                    .unwrap()
+                    .txid();
+                summaries.insert(
+                    UUID::from_slice(&raw_uuid).unwrap(),
+                    PenaltySummary::new(
+                        penalty_txid,
+                        ConfirmationStatus::from_db_data(height, confirmed),
+                    ),
+                );
+            }
+        }
+        summaries
+    }
+
+    /// Stores a [PenaltyRecord] into the penalty ledger. If a record with the same `uuid` is found, it is replaced.
+    pub(crate) fn store_penalty_record(&self, record: &PenaltyRecord) -> Result<(), Error> {
+        let query = "INSERT OR REPLACE INTO penalty_ledger (UUID, user_id, dispute_txid, penalty_txid, value, timestamp, status)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+        match self.store_data(
+            query,
+            params![
+                record.uuid.to_vec(),
+                record.user_id.to_vec(),
+                record.dispute_txid.to_vec(),
+                record.penalty_txid.to_vec(),
+                record.value,
+                record.timestamp,
+                record.status.as_str(),
+            ],
+        ) {
+            Ok(x) => {
+                log::debug!("Penalty record successfully stored: {}", record.uuid);
+                Ok(x)
+            }
+            Err(e) => {
+                log::error!(
+                    "Couldn't store penalty record: {}. Error: {e:?}",
+                    record.uuid
+                );
+                Err(e)
+            }
+        }
+    }
+
+    /// Updates the status of a [PenaltyRecord] in the penalty ledger.
+    pub(crate) fn update_penalty_status(
+        &self,
+        uuid: UUID,
+        status: PenaltyStatus,
+    ) -> Result<(), Error> {
+        let query = "UPDATE penalty_ledger SET status=(?1) WHERE UUID=(?2)";
+        match self.update_data(query, params![status.as_str(), uuid.to_vec()]) {
+            Ok(x) => {
+                log::debug!("Penalty record successfully updated: {uuid}");
+                Ok(x)
+            }
+            Err(e) => {
+                log::error!("Couldn't update penalty record: {uuid}. Error: {e:?}");
+                Err(e)
+            }
+        }
+    }
+
+    /// Loads the [PenaltyRecord]s from the penalty ledger, sorted by timestamp.
+    ///
+    /// If `start` and/or `end` are given, only the records within that range (both ends included) are returned.
+    pub(crate) fn load_penalty_records(
+        &self,
+        start: Option<u64>,
+        end: Option<u64>,
+    ) -> Vec<PenaltyRecord> {
         let mut stmt = self
             .connection
             .prepare(
-                "SELECT t.UUID, t.penalty_tx, t.height, t.confirmed
-                    FROM trackers as t INNER JOIN appointments as a ON t.UUID=a.UUID",
+                "SELECT UUID, user_id, dispute_txid, penalty_txid, value, timestamp, status FROM penalty_ledger
+                    WHERE timestamp >= (?1) AND timestamp <= (?2) ORDER BY timestamp",
             )
             .unwrap();
-        let mut rows = stmt.query([]).unwrap();
+        let mut rows = stmt
+            .query(params![start.unwrap_or(0), end.unwrap_or(i64::MAX as u64)])
+            .unwrap();
 
+        let mut records = Vec::new();
         while let Ok(Some(row)) = rows.next() {
             let raw_uuid: Vec<u8> = row.get(0).unwrap();
-            let raw_penalty_tx: Vec<u8> = row.get(1).unwrap();
-            let height: u32 = row.get(2).unwrap();
-            let confirmed: bool = row.get(3).unwrap();
+            let raw_userid: Vec<u8> = row.get(1).unwrap();
+            let raw_dispute_txid: Vec<u8> = row.get(2).unwrap();
+            let raw_penalty_txid: Vec<u8> = row.get(3).unwrap();
+            let status: String = row.get(6).unwrap();
+
+            records.push(PenaltyRecord {
+                uuid: UUID::from_slice(&raw_uuid).unwrap(),
+                user_id: UserId::from_slice(&raw_userid).unwrap(),
+                dispute_txid: Txid::from_slice(&raw_dispute_txid).unwrap(),
+                penalty_txid: Txid::from_slice(&raw_penalty_txid).unwrap(),
+                value: row.get(4).unwrap(),
+                timestamp: row.get(5).unwrap(),
+                status: PenaltyStatus::from_str(&status).unwrap(),
+                fee_bumps: Vec::new(),
+                replacements: Vec::new(),
+            });
+        }
+        for record in records.iter_mut() {
+            record.fee_bumps = self.load_fee_bumps(record.uuid);
+            record.replacements = self.load_penalty_replacements(record.uuid);
+        }
+        records
+    }
 
-            // DISCUSS: Should we store the txids to avoid pulling raw txs and deserializing then hashing them.
-            let penalty_txid = consensus::deserialize::<bitcoin::Transaction>(&raw_penalty_tx)
-                .unwrap()
-                .txid();
-            summaries.insert(
-                UUID::from_slice(&raw_uuid).unwrap(),
-                PenaltySummary::new(
-                    penalty_txid,
-                    ConfirmationStatus::from_db_data(height, confirmed),
+    /// Stores a [FeeBump] of the penalty identified by `uuid`. Bumps that are already stored are ignored.
+    pub(crate) fn store_fee_bump(&self, uuid: UUID, bump: &FeeBump) -> Result<(), Error> {
+        let query = "INSERT OR IGNORE INTO fee_bumps (UUID, txid, feerate, fee, timestamp) VALUES (?1, ?2, ?3, ?4, ?5)";
+        self.store_data(
+            query,
+            params![
+                uuid.to_vec(),
+                bump.txid.to_vec(),
+                bump.feerate,
+                bump.fee,
+                bump.timestamp,
+            ],
+        )
+    }
+
+    /// Records the replacement of the penalty identified by `uuid` in the penalty ledger, which moves on to track the
+    /// new penalty (worth `value` sats).
+    pub(crate) fn store_penalty_replacement(
+        &self,
+        uuid: UUID,
+        value: u64,
+        replacement: &PenaltyReplacement,
+    ) -> Result<(), Error> {
+        self.update_data(
+            "UPDATE penalty_ledger SET penalty_txid=(?1), value=(?2) WHERE UUID=(?3)",
+            params![replacement.new_penalty_txid.to_vec(), value, uuid.to_vec()],
+        )?;
+        self.store_data(
+            "INSERT OR IGNORE INTO penalty_replacements (UUID, old_penalty_txid, new_penalty_txid, timestamp) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                uuid.to_vec(),
+                replacement.old_penalty_txid.to_vec(),
+                replacement.new_penalty_txid.to_vec(),
+                replacement.timestamp,
+            ],
+        )
+    }
+
+    /// Loads the [PenaltyReplacement]s of the penalty identified by `uuid`, oldest first.
+    pub(crate) fn load_penalty_replacements(&self, uuid: UUID) -> Vec<PenaltyReplacement> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT old_penalty_txid, new_penalty_txid, timestamp FROM penalty_replacements WHERE UUID=(?) ORDER BY rowid",
+            )
+            .unwrap();
+
+        stmt.query_map([uuid.to_vec()], |row| {
+            let raw_old_txid: Vec<u8> = row.get(0).unwrap();
+            let raw_new_txid: Vec<u8> = row.get(1).unwrap();
+            Ok(PenaltyReplacement {
+                old_penalty_txid: Txid::from_slice(&raw_old_txid).unwrap(),
+                new_penalty_txid: Txid::from_slice(&raw_new_txid).unwrap(),
+                timestamp: row.get(2).unwrap(),
+            })
+        })
+        .unwrap()
+        .map(|replacement| replacement.unwrap())
+        .collect()
+    }
+
+    /// Loads the [FeeBump]s of the penalty identified by `uuid`, oldest first.
+    pub(crate) fn load_fee_bumps(&self, uuid: UUID) -> Vec<FeeBump> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT txid, feerate, fee, timestamp FROM fee_bumps WHERE UUID=(?) ORDER BY rowid",
+            )
+            .unwrap();
+
+        stmt.query_map([uuid.to_vec()], |row| {
+            let raw_txid: Vec<u8> = row.get(0).unwrap();
+            Ok(FeeBump {
+                txid: Txid::from_slice(&raw_txid).unwrap(),
+                feerate: row.get(1).unwrap(),
+                fee: row.get(2).unwrap(),
+                timestamp: row.get(3).unwrap(),
+            })
+        })
+        .unwrap()
+        .map(|bump| bump.unwrap())
+        .collect()
+    }
+
+    /// Loads the rows referencing data that cannot be found in the database: appointments whose user is gone, and
+    /// appointment data (e.g. trackers) whose appointment is gone. Returns the table and UUID of every dangling row.
+    ///
+    /// These can only be found if the database has been modified from outside the tower (or is corrupted), given the data
+    /// is otherwise removed on cascade.
+    pub(crate) fn load_dangling_rows(&self) -> Vec<(&'static str, UUID)> {
+        let mut dangling = Vec::new();
+        for schema in self.shards.iter() {
+            let mut queries = vec![(
+                "appointments",
+                format!(
+                    "SELECT a.UUID FROM {schema}.appointments as a LEFT JOIN {schema}.users as u ON a.user_id=u.user_id
+                        WHERE u.user_id IS NULL"
                 ),
-            );
+            )];
+            queries.extend(APPOINTMENT_DATA_TABLES.iter().map(|table| {
+                (
+                    *table,
+                    format!(
+                        "SELECT d.UUID FROM {schema}.{table} as d LEFT JOIN {schema}.appointments as a ON d.UUID=a.UUID
+                            WHERE a.UUID IS NULL"
+                    ),
+                )
+            }));
+
+            for (table, query) in queries {
+                let mut stmt = self.connection.prepare(&query).unwrap();
+                dangling.extend(
+                    stmt.query_map([], |row| {
+                        let raw_uuid: Vec<u8> = row.get(0).unwrap();
+                        Ok((table, UUID::from_slice(&raw_uuid).unwrap()))
+                    })
+                    .unwrap()
+                    .map(|row| row.unwrap()),
+                );
+            }
         }
-        summaries
+
+        dangling
+    }
+
+    /// Removes the rows referencing data that cannot be found in the database (see [Self::load_dangling_rows]) in one
+    /// transaction. Returns the number of rows removed.
+    pub(crate) fn remove_dangling_rows(&mut self) -> Result<usize, Error> {
+        let tx = self.connection.transaction().map_err(Error::Unknown)?;
+        let mut removed_appointments = 0;
+        let mut removed_data = 0;
+
+        for schema in self.shards.iter() {
+            // Appointments go first, so the data of the ones removed is already gone (on cascade) afterwards.
+            removed_appointments += tx
+                .execute(
+                    &format!("DELETE FROM {schema}.appointments WHERE user_id NOT IN (SELECT user_id FROM {schema}.users)"),
+                    [],
+                )
+                .map_err(Error::Unknown)?;
+            for table in APPOINTMENT_DATA_TABLES {
+                removed_data += tx
+                    .execute(
+                        &format!("DELETE FROM {schema}.{table} WHERE UUID NOT IN (SELECT UUID FROM {schema}.appointments)"),
+                        [],
+                    )
+                    .map_err(Error::Unknown)?;
+            }
+        }
+
+        tx.commit().map_err(Error::Unknown)?;
+        self.locator_filter_mark_removed(removed_appointments);
+        Ok(removed_appointments + removed_data)
     }
 
     /// Stores the last known block into the database.
@@ -747,6 +1869,28 @@ impl DBM {
         })
         .ok()
     }
+
+    /// Stores the key used to spend the anchor outputs of penalties into the database.
+    ///
+    /// The anchor key is kept apart from the tower key, so on-chain outputs cannot be linked to the tower identity.
+    pub fn store_anchor_key(&self, sk: &SecretKey) -> Result<(), Error> {
+        let query = "INSERT OR REPLACE INTO anchor_key (id, key) VALUES (0, ?)";
+        self.store_data(query, params![sk.display_secret().to_string()])
+    }
+
+    /// Loads the key used to spend the anchor outputs of penalties from the database.
+    pub fn load_anchor_key(&self) -> Option<SecretKey> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT key FROM anchor_key WHERE id=0")
+            .unwrap();
+
+        stmt.query_row([], |row| {
+            let sk: String = row.get(0).unwrap();
+            Ok(SecretKey::from_str(&sk).unwrap())
+        })
+        .ok()
+    }
 }
 
 #[cfg(test)]
@@ -754,6 +1898,7 @@ mod tests {
     use super::*;
     use std::collections::HashSet;
     use std::iter::FromIterator;
+    use tempdir::TempDir;
 
     use teos_common::cryptography::{get_random_bytes, get_random_keypair};
     use teos_common::test_utils::{get_random_locator, get_random_user_id};
@@ -769,7 +1914,11 @@ mod tests {
         pub(crate) fn in_memory() -> Result<Self, SqliteError> {
             let connection = Connection::open_in_memory()?;
             connection.execute("PRAGMA foreign_keys=1;", [])?;
-            let mut dbm = Self { connection };
+            let mut dbm = Self {
+                connection,
+                shards: vec!["main".to_owned()],
+                locator_filter: RefCell::new(None),
+            };
             dbm.create_tables(Vec::from_iter(TABLES))?;
 
             Ok(dbm)
@@ -777,18 +1926,22 @@ mod tests {
 
         pub(crate) fn load_user(&self, user_id: UserId) -> Option<UserInfo> {
             let key = user_id.to_vec();
+            let schema = self.user_shard(user_id);
             let mut stmt = self
                 .connection
-                .prepare(
-                    "SELECT available_slots, subscription_start, subscription_expiry
-                        FROM users WHERE user_id=(?)",
-                )
+                .prepare(&format!(
+                    "SELECT available_slots, subscription_start, subscription_expiry, version
+                        FROM {schema}.users as u LEFT JOIN {schema}.user_signature_versions as v
+                        ON u.user_id=v.user_id WHERE u.user_id=(?)"
+                ))
                 .unwrap();
             stmt.query_row([&key], |row| {
                 let slots = row.get(0).unwrap();
                 let start = row.get(1).unwrap();
                 let expiry = row.get(2).unwrap();
-                Ok(UserInfo::new(slots, start, expiry))
+                let version = row.get::<_, Option<u32>>(3).unwrap();
+                Ok(UserInfo::new(slots, start, expiry)
+                    .with_signature_version(signature_version_from_db(version)))
             })
             .ok()
         }
@@ -797,7 +1950,11 @@ mod tests {
     #[test]
     fn test_create_tables() {
         let connection = Connection::open_in_memory().unwrap();
-        let mut dbm = DBM { connection };
+        let mut dbm = DBM {
+            connection,
+            shards: vec!["main".to_owned()],
+            locator_filter: RefCell::new(None),
+        };
         dbm.create_tables(Vec::from_iter(TABLES)).unwrap();
     }
 
@@ -866,6 +2023,40 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_store_load_signature_version() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+
+        // Users with no version stored sign using V1
+        assert_eq!(
+            dbm.load_user(user_id).unwrap().signature_version,
+            SignatureVersion::V1
+        );
+
+        // Once stored, the version is loaded alongside the rest of the user data
+        dbm.store_signature_version(user_id, SignatureVersion::V2)
+            .unwrap();
+        let user = user.with_signature_version(SignatureVersion::V2);
+        assert_eq!(dbm.load_user(user_id).unwrap(), user);
+        assert_eq!(dbm.load_all_users(), HashMap::from([(user_id, user)]));
+
+        // And it is removed alongside the user
+        dbm.batch_remove_users(&[user_id]);
+        dbm.store_user(user_id, &user).unwrap();
+        assert_eq!(
+            dbm.load_user(user_id).unwrap().signature_version,
+            SignatureVersion::V1
+        );
+
+        // The version of unregistered users cannot be stored
+        assert!(dbm
+            .store_signature_version(get_random_user_id(), SignatureVersion::V2)
+            .is_err());
+    }
+
     #[test]
     fn test_load_all_users() {
         let dbm = DBM::in_memory().unwrap();
@@ -962,6 +2153,110 @@ mod tests {
         dbm.batch_remove_users(&users);
     }
 
+    #[test]
+    fn test_transfer_user() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let old_user_id = get_random_user_id();
+        let new_user_id = get_random_user_id();
+        let info = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY)
+            .with_signature_version(SignatureVersion::V2);
+        dbm.store_user(old_user_id, &info).unwrap();
+        dbm.store_signature_version(old_user_id, info.signature_version)
+            .unwrap();
+
+        // Add some appointments, one of them triggered (and with a matching penalty record)
+        let mut appointments = HashMap::new();
+        for _ in 0..5 {
+            let (uuid, appointment) = generate_dummy_appointment_with_user(old_user_id, None);
+            dbm.store_appointment(uuid, &appointment).unwrap();
+            appointments.insert(uuid, appointment);
+        }
+        let triggered_uuid = *appointments.keys().next().unwrap();
+        let ttl_uuid = *appointments.keys().nth(1).unwrap();
+        dbm.store_appointment_expiry(ttl_uuid, Some(200)).unwrap();
+        dbm.store_appointment_priority(ttl_uuid, Some(7)).unwrap();
+        dbm.store_appointment_reward(ttl_uuid, Some(1_000)).unwrap();
+        let tracker = get_random_tracker(old_user_id, ConfirmationStatus::ConfirmedIn(100));
+        dbm.store_tracker(triggered_uuid, &tracker).unwrap();
+        dbm.store_penalty_record(&PenaltyRecord::new(triggered_uuid, &tracker, 1000))
+            .unwrap();
+
+        let transfer_id = get_random_bytes(32);
+        dbm.transfer_user(old_user_id, new_user_id, &info, &transfer_id)
+            .unwrap();
+
+        // The subscription and all its data now belong to the new user
+        assert!(dbm.load_user(old_user_id).is_none());
+        assert_eq!(dbm.load_user(new_user_id).unwrap(), info);
+        for (uuid, appointment) in appointments.iter() {
+            assert!(dbm.load_appointment(*uuid).is_none());
+
+            let new_uuid = UUID::new(appointment.locator(), new_user_id);
+            let mut moved = dbm.load_appointment(new_uuid).unwrap();
+            assert_eq!(moved.user_id, new_user_id);
+            moved.user_id = old_user_id;
+            assert_eq!(&moved, appointment);
+        }
+        let new_triggered_uuid = UUID::new(appointments[&triggered_uuid].locator(), new_user_id);
+        assert!(dbm.load_tracker(triggered_uuid).is_none());
+        assert_eq!(
+            dbm.load_tracker(new_triggered_uuid).unwrap().user_id,
+            new_user_id
+        );
+        let records = dbm.load_penalty_records(None, None);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].uuid, new_triggered_uuid);
+        assert_eq!(records[0].user_id, new_user_id);
+
+        // So are the per-appointment terms
+        assert_eq!(
+            dbm.load_expired_appointments(200),
+            vec![UUID::new(appointments[&ttl_uuid].locator(), new_user_id)]
+        );
+        assert_eq!(
+            dbm.load_appointment_priority(UUID::new(
+                appointments[&ttl_uuid].locator(),
+                new_user_id
+            )),
+            7
+        );
+        assert_eq!(
+            dbm.load_appointment_reward(UUID::new(appointments[&ttl_uuid].locator(), new_user_id)),
+            Some(1_000)
+        );
+
+        // The same transfer cannot be performed twice
+        dbm.store_user(old_user_id, &info).unwrap();
+        dbm.store_signature_version(old_user_id, info.signature_version)
+            .unwrap();
+        dbm.batch_remove_users(&[new_user_id]);
+        assert!(matches!(
+            dbm.transfer_user(old_user_id, new_user_id, &info, &transfer_id),
+            Err(Error::AlreadyExists)
+        ));
+        assert_eq!(dbm.load_user(old_user_id).unwrap(), info);
+        assert!(dbm.load_user(new_user_id).is_none());
+    }
+
+    #[test]
+    fn test_store_remove_redeemed_payment() {
+        let dbm = DBM::in_memory().unwrap();
+        let payment_hash = sha256::Hash::hash(&get_random_bytes(32));
+        let user_id = get_random_user_id();
+
+        dbm.store_redeemed_payment(&payment_hash, user_id).unwrap();
+
+        // The same payment cannot be redeemed twice, not even by a different user
+        assert!(matches!(
+            dbm.store_redeemed_payment(&payment_hash, get_random_user_id()),
+            Err(Error::AlreadyExists)
+        ));
+
+        // Unless it is removed first
+        dbm.remove_redeemed_payment(&payment_hash);
+        dbm.store_redeemed_payment(&payment_hash, user_id).unwrap();
+    }
+
     #[test]
     fn test_get_appointments_trackers_count() {
         let dbm = DBM::in_memory().unwrap();
@@ -1296,9 +2591,62 @@ mod tests {
         ));
         assert!(matches!(dbm.store_tracker(uuid, &tracker), Ok { .. }));
 
-        dbm.batch_remove_appointments(&[uuid], &HashMap::from_iter([(appointment.user_id, info)]));
-        assert!(dbm.load_appointment(uuid).is_none());
-        assert!(dbm.load_tracker(uuid).is_none());
+        dbm.batch_remove_appointments(&[uuid], &HashMap::from_iter([(appointment.user_id, info)]));
+        assert!(dbm.load_appointment(uuid).is_none());
+        assert!(dbm.load_tracker(uuid).is_none());
+    }
+
+    #[test]
+    fn test_load_remove_dangling_rows() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let info = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+
+        let mut uuids = Vec::new();
+        for _ in 0..3 {
+            let uuid = generate_uuid();
+            let appointment = generate_dummy_appointment(None);
+            dbm.store_user(appointment.user_id, &info).unwrap();
+            dbm.store_appointment(uuid, &appointment).unwrap();
+            dbm.store_appointment_expiry(uuid, Some(SUBSCRIPTION_EXPIRY))
+                .unwrap();
+            dbm.store_tracker(
+                uuid,
+                &get_random_tracker(appointment.user_id, ConfirmationStatus::ConfirmedIn(21)),
+            )
+            .unwrap();
+            uuids.push((uuid, appointment.user_id));
+        }
+        assert!(dbm.load_dangling_rows().is_empty());
+
+        // Remove the user of an appointment, and the appointment of a tracker, bypassing the foreign keys
+        dbm.connection.execute("PRAGMA foreign_keys=0", []).unwrap();
+        dbm.connection
+            .execute("DELETE FROM users WHERE user_id=(?)", [uuids[0].1.to_vec()])
+            .unwrap();
+        dbm.connection
+            .execute(
+                "DELETE FROM appointments WHERE UUID=(?)",
+                [uuids[1].0.to_vec()],
+            )
+            .unwrap();
+        dbm.connection.execute("PRAGMA foreign_keys=1", []).unwrap();
+
+        assert_eq!(
+            HashSet::from_iter(dbm.load_dangling_rows()),
+            HashSet::from([
+                ("appointments", uuids[0].0),
+                ("trackers", uuids[1].0),
+                ("appointment_ttls", uuids[1].0),
+            ])
+        );
+
+        // The dangling appointment data goes away alongside it
+        assert_eq!(dbm.remove_dangling_rows().unwrap(), 3);
+        assert!(dbm.load_dangling_rows().is_empty());
+        assert!(dbm.load_appointment(uuids[0].0).is_none());
+        assert!(dbm.load_tracker(uuids[0].0).is_none());
+        assert!(dbm.load_tracker(uuids[2].0).is_some());
+        assert_eq!(dbm.get_trackers_count(), 1);
     }
 
     #[test]
@@ -1348,6 +2696,194 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_store_load_appointment_priority() {
+        let dbm = DBM::in_memory().unwrap();
+
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+
+        let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+        dbm.store_appointment(uuid, &appointment).unwrap();
+        // Appointments have the lowest priority by default.
+        assert_eq!(dbm.load_appointment_priority(uuid), 0);
+
+        // Priorities can be set, updated and removed.
+        dbm.store_appointment_priority(uuid, Some(3)).unwrap();
+        assert_eq!(dbm.load_appointment_priority(uuid), 3);
+        dbm.store_appointment_priority(uuid, Some(7)).unwrap();
+        assert_eq!(dbm.load_appointment_priority(uuid), 7);
+        dbm.store_appointment_priority(uuid, None).unwrap();
+        assert_eq!(dbm.load_appointment_priority(uuid), 0);
+
+        // Priorities are removed alongside their appointments.
+        dbm.store_appointment_priority(uuid, Some(3)).unwrap();
+        dbm.remove_appointment(uuid);
+        let count: u32 = dbm
+            .connection
+            .query_row("SELECT COUNT(*) FROM appointment_priorities", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_store_load_appointment_reward() {
+        let dbm = DBM::in_memory().unwrap();
+
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+
+        let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+        dbm.store_appointment(uuid, &appointment).unwrap();
+        // Appointments carry no reward terms by default.
+        assert_eq!(dbm.load_appointment_reward(uuid), None);
+
+        // Rewards can be set, updated and removed.
+        dbm.store_appointment_reward(uuid, Some(1_000)).unwrap();
+        assert_eq!(dbm.load_appointment_reward(uuid), Some(1_000));
+        dbm.store_appointment_reward(uuid, Some(2_000)).unwrap();
+        assert_eq!(dbm.load_appointment_reward(uuid), Some(2_000));
+        dbm.store_appointment_reward(uuid, None).unwrap();
+        assert_eq!(dbm.load_appointment_reward(uuid), None);
+
+        // Rewards are removed alongside their appointments.
+        dbm.store_appointment_reward(uuid, Some(1_000)).unwrap();
+        dbm.remove_appointment(uuid);
+        let count: u32 = dbm
+            .connection
+            .query_row("SELECT COUNT(*) FROM appointment_rewards", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_load_expired_appointments() {
+        let dbm = DBM::in_memory().unwrap();
+
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+
+        // Add some appointments expiring at different heights, and one with no expiry at all.
+        let mut uuids = Vec::new();
+        for height in 1..=5 {
+            let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+            dbm.store_appointment(uuid, &appointment).unwrap();
+            dbm.store_appointment_expiry(uuid, Some(height)).unwrap();
+            uuids.push(uuid);
+        }
+        let (no_ttl_uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+        dbm.store_appointment(no_ttl_uuid, &appointment).unwrap();
+
+        assert!(dbm.load_expired_appointments(0).is_empty());
+        assert_eq!(
+            HashSet::<UUID>::from_iter(dbm.load_expired_appointments(3)),
+            HashSet::from_iter(uuids[..3].iter().cloned())
+        );
+
+        // Expiries can be updated and removed.
+        dbm.store_appointment_expiry(uuids[3], Some(2)).unwrap();
+        dbm.store_appointment_expiry(uuids[0], None).unwrap();
+        assert_eq!(
+            HashSet::<UUID>::from_iter(dbm.load_expired_appointments(3)),
+            HashSet::from_iter([uuids[1], uuids[2], uuids[3]])
+        );
+
+        // Triggered appointments are not reported, even if expired.
+        let tracker = get_random_tracker(user_id, ConfirmationStatus::ConfirmedIn(21));
+        dbm.store_tracker(uuids[1], &tracker).unwrap();
+        assert_eq!(
+            HashSet::<UUID>::from_iter(dbm.load_expired_appointments(5)),
+            HashSet::from_iter([uuids[2], uuids[3], uuids[4]])
+        );
+
+        // Expiries are removed alongside their appointments.
+        dbm.remove_appointment(uuids[2]);
+        assert_eq!(
+            HashSet::<UUID>::from_iter(dbm.load_expired_appointments(5)),
+            HashSet::from_iter([uuids[3], uuids[4]])
+        );
+    }
+
+    #[test]
+    fn test_load_evictable_appointments() {
+        let dbm = DBM::in_memory().unwrap();
+
+        let expired_user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(expired_user_id, &user).unwrap();
+        let active_user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY + 1);
+        dbm.store_user(active_user_id, &user).unwrap();
+
+        // Add some appointments for both users, started at different heights (newest first).
+        let mut uuids = Vec::new();
+        for start_block in (1..=5).rev() {
+            for user_id in [expired_user_id, active_user_id] {
+                let (uuid, mut appointment) = generate_dummy_appointment_with_user(user_id, None);
+                appointment.start_block = start_block;
+                dbm.store_appointment(uuid, &appointment).unwrap();
+                if user_id == expired_user_id {
+                    uuids.push(uuid);
+                }
+            }
+        }
+        uuids.reverse();
+
+        // Only the ones of the expired user are evictable, oldest first.
+        assert!(dbm
+            .load_evictable_appointments(SUBSCRIPTION_EXPIRY - 1, 10)
+            .is_empty());
+        assert_eq!(
+            dbm.load_evictable_appointments(SUBSCRIPTION_EXPIRY, 10),
+            uuids
+        );
+        assert_eq!(
+            dbm.load_evictable_appointments(SUBSCRIPTION_EXPIRY, 2),
+            uuids[..2]
+        );
+
+        // Triggered appointments are never evictable.
+        let tracker = get_random_tracker(expired_user_id, ConfirmationStatus::ConfirmedIn(21));
+        dbm.store_tracker(uuids[0], &tracker).unwrap();
+        assert_eq!(
+            dbm.load_evictable_appointments(SUBSCRIPTION_EXPIRY, 10),
+            uuids[1..]
+        );
+    }
+
+    #[test]
+    fn test_get_used_size() {
+        let dbm = DBM::in_memory().unwrap();
+        let empty_size = dbm.get_used_size();
+        assert!(empty_size > 0);
+
+        // The size grows as data is added...
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+        let mut uuids = Vec::new();
+        for _ in 0..100 {
+            let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+            dbm.store_appointment(uuid, &appointment).unwrap();
+            uuids.push(uuid);
+        }
+        let full_size = dbm.get_used_size();
+        assert!(full_size > empty_size);
+
+        // ...and shrinks back as it is removed, even though the freed pages are kept around.
+        for uuid in uuids {
+            dbm.remove_appointment(uuid);
+        }
+        assert!(dbm.get_used_size() < full_size);
+    }
+
     #[test]
     fn test_batch_check_locators_exist() {
         let dbm = DBM::in_memory().unwrap();
@@ -1388,6 +2924,131 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_batch_check_locators_exist_locator_filter() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+
+        // Appointments stored before enabling the filter are loaded from the database, the rest are added on the go.
+        let mut appointments = Vec::new();
+        for i in 0..100 {
+            if i == 50 {
+                dbm.enable_locator_filter();
+            }
+            let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+            dbm.store_appointment(uuid, &appointment).unwrap();
+            appointments.push((uuid, appointment));
+        }
+
+        let known_locators: HashSet<_> = appointments.iter().map(|(_, a)| a.locator()).collect();
+        let unknown_locators: HashSet<_> = (0..100).map(|_| get_random_locator()).collect();
+        assert_eq!(
+            HashSet::from_iter(
+                dbm.batch_check_locators_exist(
+                    known_locators
+                        .iter()
+                        .chain(unknown_locators.iter())
+                        .collect()
+                )
+            ),
+            known_locators
+        );
+
+        // Removed appointments stop being reported even if they are still in the filter
+        let (uuid, appointment) = appointments.pop().unwrap();
+        dbm.remove_appointment(uuid);
+        let removed: Vec<UUID> = appointments.drain(..20).map(|(uuid, _)| uuid).collect();
+        dbm.batch_remove_appointments(&removed, &HashMap::new());
+        assert!(dbm
+            .batch_check_locators_exist(vec![&appointment.locator()])
+            .is_empty());
+        assert_eq!(
+            dbm.batch_check_locators_exist(known_locators.iter().collect())
+                .len(),
+            appointments.len()
+        );
+        assert!(!dbm
+            .locator_filter
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .needs_rebuild());
+
+        // Once most of the appointments are gone the filter is rebuilt
+        dbm.batch_remove_users(&[user_id]);
+        assert!(dbm
+            .locator_filter
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .needs_rebuild());
+        assert!(dbm
+            .batch_check_locators_exist(known_locators.iter().collect())
+            .is_empty());
+        assert!(!dbm
+            .locator_filter
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .needs_rebuild());
+    }
+
+    // Benchmark for the per-block breach lookup with and without the locator filter.
+    // Run with `cargo test --release bench_batch_check_locators_exist -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_batch_check_locators_exist() {
+        use std::time::Instant;
+
+        let n_app = 50_000;
+        let txs_per_block = 3_000;
+        let n_blocks = 50;
+
+        let dbm = DBM::in_memory().unwrap();
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+
+        let mut known_locators = Vec::new();
+        for _ in 0..n_app {
+            let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+            dbm.store_appointment(uuid, &appointment).unwrap();
+            known_locators.push(appointment.locator());
+        }
+
+        // Every block triggers a single appointment, the rest of the transactions are unrelated.
+        let blocks: Vec<Vec<Locator>> = (0..n_blocks)
+            .map(|i| {
+                let mut block: Vec<Locator> =
+                    (1..txs_per_block).map(|_| get_random_locator()).collect();
+                block.push(known_locators[i]);
+                block
+            })
+            .collect();
+
+        let scan = |dbm: &DBM| {
+            let start = Instant::now();
+            for block in blocks.iter() {
+                assert_eq!(
+                    dbm.batch_check_locators_exist(block.iter().collect()).len(),
+                    1
+                );
+            }
+            start.elapsed() / n_blocks as u32
+        };
+
+        let without_filter = scan(&dbm);
+        dbm.enable_locator_filter();
+        let with_filter = scan(&dbm);
+
+        println!(
+            "{n_app} appointments, {txs_per_block} txs per block. Average scan time per block: {without_filter:?} (no filter), {with_filter:?} (filter)"
+        );
+        assert!(with_filter < without_filter);
+    }
+
     #[test]
     fn test_store_load_tracker() {
         let dbm = DBM::in_memory().unwrap();
@@ -1477,6 +3138,30 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_update_tracker_penalty() {
+        let dbm = DBM::in_memory().unwrap();
+
+        let user_id = get_random_user_id();
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+
+        let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+        dbm.store_appointment(uuid, &appointment).unwrap();
+
+        let tracker = get_random_tracker(user_id, ConfirmationStatus::InMempoolSince(42));
+        dbm.store_tracker(uuid, &tracker).unwrap();
+
+        // The penalty and the status are replaced, the rest is kept as is
+        let penalty_tx = get_random_tx();
+        dbm.update_tracker_penalty(uuid, &penalty_tx, &ConfirmationStatus::InMempoolSince(43))
+            .unwrap();
+        let updated = dbm.load_tracker(uuid).unwrap();
+        assert_eq!(updated.penalty_tx, penalty_tx);
+        assert_eq!(updated.status, ConfirmationStatus::InMempoolSince(43));
+        assert_eq!(updated.dispute_tx, tracker.dispute_tx);
+    }
+
     #[test]
     fn test_load_nonexistent_tracker() {
         let dbm = DBM::in_memory().unwrap();
@@ -1707,6 +3392,96 @@ mod tests {
         assert_eq!(dbm.load_penalties_summaries(), penalties_summaries);
     }
 
+    #[test]
+    fn test_store_load_penalty_records() {
+        let dbm = DBM::in_memory().unwrap();
+        let mut records = Vec::new();
+
+        for i in 0..10 {
+            let user_id = get_random_user_id();
+            let tracker = get_random_tracker(user_id, ConfirmationStatus::InMempoolSince(i));
+            let record = PenaltyRecord::new(generate_uuid(), &tracker, 1000 + i as u64);
+            dbm.store_penalty_record(&record).unwrap();
+            records.push(record);
+        }
+
+        // Records are kept even if there is no matching tracker / appointment
+        assert_eq!(dbm.load_penalty_records(None, None), records);
+        assert_eq!(dbm.load_penalty_records(Some(1003), None), records[3..]);
+        assert_eq!(dbm.load_penalty_records(None, Some(1003)), records[..4]);
+        assert_eq!(
+            dbm.load_penalty_records(Some(1002), Some(1005)),
+            records[2..6]
+        );
+        assert!(dbm.load_penalty_records(Some(2000), None).is_empty());
+
+        // Update the status of one of them
+        dbm.update_penalty_status(records[0].uuid, PenaltyStatus::Resolved)
+            .unwrap();
+        records[0].status = PenaltyStatus::Resolved;
+        assert_eq!(dbm.load_penalty_records(None, Some(1000)), records[..1]);
+    }
+
+    #[test]
+    fn test_store_load_fee_bumps() {
+        let dbm = DBM::in_memory().unwrap();
+
+        let tracker =
+            get_random_tracker(get_random_user_id(), ConfirmationStatus::InMempoolSince(1));
+        let mut record = PenaltyRecord::new(generate_uuid(), &tracker, 1000);
+        dbm.store_penalty_record(&record).unwrap();
+        assert!(dbm.load_fee_bumps(record.uuid).is_empty());
+
+        for i in 1..=3 {
+            let bump = FeeBump {
+                txid: get_random_tx().txid(),
+                feerate: 10 * i,
+                fee: 1000 * i,
+                timestamp: 1000 + i,
+            };
+            dbm.store_fee_bump(record.uuid, &bump).unwrap();
+            record.fee_bumps.push(bump);
+        }
+        // Storing the same bump twice is a no-op.
+        dbm.store_fee_bump(record.uuid, &record.fee_bumps[0])
+            .unwrap();
+
+        assert_eq!(dbm.load_fee_bumps(record.uuid), record.fee_bumps);
+        assert_eq!(dbm.load_penalty_records(None, None), vec![record]);
+    }
+
+    #[test]
+    fn test_store_load_penalty_replacements() {
+        let dbm = DBM::in_memory().unwrap();
+
+        let tracker =
+            get_random_tracker(get_random_user_id(), ConfirmationStatus::InMempoolSince(1));
+        let mut record = PenaltyRecord::new(generate_uuid(), &tracker, 1000);
+        dbm.store_penalty_record(&record).unwrap();
+        assert!(dbm.load_penalty_replacements(record.uuid).is_empty());
+
+        for i in 1..=2 {
+            let new_penalty_tx = get_random_tx();
+            let replacement = PenaltyReplacement {
+                old_penalty_txid: record.penalty_txid,
+                new_penalty_txid: new_penalty_tx.txid(),
+                timestamp: 1000 + i,
+            };
+            dbm.store_penalty_replacement(record.uuid, 42 * i, &replacement)
+                .unwrap();
+            record.penalty_txid = replacement.new_penalty_txid;
+            record.value = 42 * i;
+            record.replacements.push(replacement);
+        }
+
+        // The record tracks the latest penalty, alongside the history of replacements
+        assert_eq!(
+            dbm.load_penalty_replacements(record.uuid),
+            record.replacements
+        );
+        assert_eq!(dbm.load_penalty_records(None, None), vec![record]);
+    }
+
     #[test]
     fn test_store_load_last_known_block() {
         let dbm = DBM::in_memory().unwrap();
@@ -1739,4 +3514,197 @@ mod tests {
             assert_eq!(dbm.load_tower_key().unwrap(), sk);
         }
     }
+
+    #[test]
+    fn test_store_load_anchor_key() {
+        let dbm = DBM::in_memory().unwrap();
+
+        assert!(dbm.load_anchor_key().is_none());
+        let sk = get_random_keypair().0;
+        dbm.store_anchor_key(&sk).unwrap();
+        assert_eq!(dbm.load_anchor_key().unwrap(), sk);
+
+        // The anchor key is independent of the tower key
+        dbm.store_tower_key(&get_random_keypair().0).unwrap();
+        assert_eq!(dbm.load_anchor_key().unwrap(), sk);
+    }
+
+    /// Counts the users stored at a given shard.
+    fn count_shard_users(dbm: &DBM, schema: &str) -> usize {
+        dbm.connection
+            .query_row(&format!("SELECT COUNT(*) FROM {schema}.users"), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+    }
+
+    /// Stores a user alongside a few appointments (one of them triggered). Returns the [UUID]s of the appointments.
+    fn store_user_data(dbm: &DBM, user_id: UserId) -> Vec<UUID> {
+        let user = UserInfo::new(AVAILABLE_SLOTS, SUBSCRIPTION_START, SUBSCRIPTION_EXPIRY);
+        dbm.store_user(user_id, &user).unwrap();
+        dbm.store_signature_version(user_id, SignatureVersion::V2)
+            .unwrap();
+
+        let mut uuids = Vec::new();
+        for _ in 0..3 {
+            let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+            dbm.store_appointment(uuid, &appointment).unwrap();
+            dbm.store_appointment_expiry(uuid, Some(1000)).unwrap();
+            uuids.push(uuid);
+        }
+        let tracker = get_random_tracker(user_id, ConfirmationStatus::ConfirmedIn(21));
+        dbm.store_tracker(uuids[0], &tracker).unwrap();
+
+        uuids
+    }
+
+    #[test]
+    fn test_sharded_routing() {
+        let tmp_dir = TempDir::new("teos_dbm_shards").unwrap();
+        let db_path = tmp_dir.path().join("teos_db.sql3");
+        let mut dbm = DBM::with_shards(db_path.clone(), 4).unwrap();
+        assert_eq!(dbm.shards.len(), 4);
+        for index in 1..4 {
+            assert!(shard_path(&db_path, index).exists());
+        }
+
+        let mut data = HashMap::new();
+        for _ in 0..20 {
+            let user_id = get_random_user_id();
+            data.insert(user_id, store_user_data(&dbm, user_id));
+        }
+
+        // Users are spread across shards, and each of them is stored at the one it belongs to
+        assert!(
+            dbm.shards
+                .iter()
+                .filter(|schema| count_shard_users(&dbm, schema) > 0)
+                .count()
+                > 1
+        );
+        assert_eq!(
+            dbm.shards
+                .iter()
+                .map(|schema| count_shard_users(&dbm, schema))
+                .sum::<usize>(),
+            20
+        );
+
+        // And everything can be retrieved no matter what shard it is at
+        assert_eq!(dbm.load_all_users().len(), 20);
+        assert_eq!(dbm.get_appointments_count(), 40);
+        assert_eq!(dbm.get_trackers_count(), 20);
+        assert_eq!(dbm.load_appointments(None).len(), 40);
+        assert_eq!(dbm.load_trackers(None).len(), 20);
+        assert_eq!(dbm.load_penalties_summaries().len(), 20);
+        assert_eq!(dbm.load_expired_appointments(1000).len(), 40);
+        assert_eq!(dbm.load_evictable_appointments(u32::MAX, 5).len(), 5);
+        for (user_id, uuids) in data.iter() {
+            assert_eq!(
+                dbm.load_user(*user_id).unwrap().available_slots,
+                AVAILABLE_SLOTS
+            );
+            assert_eq!(dbm.load_user_locators(*user_id).len(), 3);
+            for uuid in uuids {
+                let appointment = dbm.load_appointment(*uuid).unwrap();
+                assert_eq!(appointment.user_id, *user_id);
+                assert!(dbm.appointment_exists(*uuid));
+                assert_eq!(dbm.load_uuids(appointment.locator()), vec![*uuid]);
+                assert_eq!(
+                    dbm.batch_check_locators_exist(vec![&appointment.locator()]),
+                    vec![appointment.locator()]
+                );
+            }
+            assert!(dbm.tracker_exists(uuids[0]));
+            assert_eq!(dbm.load_tracker(uuids[0]).unwrap().user_id, *user_id);
+        }
+
+        // Subscriptions can be transferred across shards
+        let (old_user_id, uuids) = data.iter().next().unwrap();
+        let new_user_id = loop {
+            let user_id = get_random_user_id();
+            if dbm.user_shard(user_id) != dbm.user_shard(*old_user_id) {
+                break user_id;
+            }
+        };
+        let info = dbm.load_user(*old_user_id).unwrap();
+        let locator = dbm.load_appointment(uuids[0]).unwrap().locator();
+        dbm.transfer_user(*old_user_id, new_user_id, &info, &get_random_bytes(32))
+            .unwrap();
+        assert!(dbm.load_user(*old_user_id).is_none());
+        assert_eq!(dbm.load_user(new_user_id).unwrap(), info);
+        let new_uuid = UUID::new(locator, new_user_id);
+        assert_eq!(dbm.load_tracker(new_uuid).unwrap().user_id, new_user_id);
+        assert_eq!(dbm.get_appointments_count(), 40);
+        assert_eq!(dbm.get_trackers_count(), 20);
+
+        // And removals reach every shard
+        let (uuids, users): (Vec<_>, Vec<_>) = data
+            .iter()
+            .filter(|(user_id, _)| *user_id != old_user_id)
+            .map(|(user_id, uuids)| (uuids[1], *user_id))
+            .unzip();
+        dbm.batch_remove_appointments(&uuids, &HashMap::new());
+        assert_eq!(dbm.get_appointments_count(), 40 - uuids.len());
+        dbm.batch_remove_users(&users);
+        assert_eq!(dbm.load_all_users().len(), 1);
+    }
+
+    #[test]
+    fn test_reshard() {
+        let tmp_dir = TempDir::new("teos_dbm_reshard").unwrap();
+        let db_path = tmp_dir.path().join("teos_db.sql3");
+
+        // Start off with a single file database
+        let mut data = HashMap::new();
+        {
+            let dbm = DBM::new(db_path.clone()).unwrap();
+            for _ in 0..20 {
+                let user_id = get_random_user_id();
+                data.insert(user_id, store_user_data(&dbm, user_id));
+            }
+        }
+
+        // Split it across shards. Data is moved to the shard it belongs to, and can be retrieved as usual
+        for shards in [3, 5, 2] {
+            let dbm = DBM::with_shards(db_path.clone(), shards).unwrap();
+            assert_eq!(dbm.load_shard_count(), shards);
+            for index in 1..MAX_SHARDS {
+                assert_eq!(shard_path(&db_path, index).exists(), index < shards);
+            }
+
+            for (user_id, uuids) in data.iter() {
+                let schema = dbm.user_shard(*user_id);
+                let stored = dbm
+                    .connection
+                    .prepare(&format!(
+                        "SELECT user_id FROM {schema}.users WHERE user_id=(?)"
+                    ))
+                    .unwrap()
+                    .exists([user_id.to_vec()])
+                    .unwrap();
+                assert!(stored);
+                assert_eq!(
+                    dbm.load_user(*user_id).unwrap().signature_version,
+                    SignatureVersion::V2
+                );
+
+                for uuid in uuids {
+                    assert_eq!(dbm.load_appointment(*uuid).unwrap().user_id, *user_id);
+                }
+                assert_eq!(dbm.load_tracker(uuids[0]).unwrap().user_id, *user_id);
+            }
+            assert_eq!(dbm.get_appointments_count(), 40);
+            assert_eq!(dbm.get_trackers_count(), 20);
+            assert_eq!(dbm.load_expired_appointments(1000).len(), 40);
+        }
+
+        // And back to a single file
+        let dbm = DBM::new(db_path.clone()).unwrap();
+        assert_eq!(count_shard_users(&dbm, "main"), 20);
+        assert_eq!(dbm.load_appointments(None).len(), 40);
+        for index in 1..MAX_SHARDS {
+            assert!(!shard_path(&db_path, index).exists());
+        }
+    }
 }
@@ -1,5 +1,7 @@
 // Custom RPC errors [255+]
 #[allow(dead_code)]
 pub(crate) const RPC_TX_REORGED_AFTER_BROADCAST: i32 = -256;
+pub(crate) const RPC_PENALTY_UNECONOMICAL: i32 = -258;
+pub(crate) const RPC_SUBSCRIPTION_LAPSED: i32 = -259;
 // UNHANDLED
 pub(crate) const UNKNOWN_JSON_RPC_EXCEPTION: i32 = -257;
@@ -0,0 +1,184 @@
+//! Logic related to the interaction with Esplora-compatible HTTP APIs (e.g. `https://mempool.space/api`), which the
+//! tower can optionally use to reach the Bitcoin network other than through `bitcoind`.
+
+use std::time::Duration;
+
+use bitcoin::consensus::encode::serialize_hex;
+use bitcoin::{Transaction, Txid};
+use serde::Deserialize;
+
+use crate::confirmations::{ConfirmationError, Confirmer};
+
+/// Time to wait for the API to answer before giving up on it.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The status of a transaction, as reported by the API.
+#[derive(Deserialize)]
+struct TxStatus {
+    confirmed: bool,
+    block_height: Option<u32>,
+}
+
+/// Runs `f` on a thread of its own, given blocking HTTP clients cannot be used (nor built) within an async context.
+fn off_runtime<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    std::thread::spawn(f)
+        .join()
+        .unwrap_or_else(|_| Err("Request thread panicked".to_owned()))
+}
+
+/// Client for an Esplora-compatible API. Cloning it is cheap, and clones share the underlying HTTP client.
+#[derive(Debug, Clone)]
+pub struct EsploraClient {
+    url: String,
+    client: reqwest::blocking::Client,
+}
+
+impl EsploraClient {
+    /// Creates a new [EsploraClient] instance.
+    pub fn new(url: &str) -> Result<Self, String> {
+        let client = off_runtime(|| {
+            reqwest::blocking::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .map_err(|e| e.to_string())
+        })?;
+
+        Ok(Self {
+            url: url.trim_end_matches('/').to_owned(),
+            client,
+        })
+    }
+
+    /// Creates a client for a different API that shares the underlying HTTP client with this one.
+    pub fn with_url(&self, url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_owned(),
+            client: self.client.clone(),
+        }
+    }
+
+    /// Broadcasts a transaction (`POST <url>/tx`).
+    ///
+    /// Returns the error reported by the API (or the reason why it could not be reached) if the transaction was not accepted.
+    pub(crate) fn broadcast(&self, tx: &Transaction) -> Result<(), String> {
+        let request = self
+            .client
+            .post(format!("{}/tx", self.url))
+            .body(serialize_hex(tx));
+
+        off_runtime(move || {
+            let response = request.send().map_err(|e| e.to_string())?;
+            let status = response.status();
+            if status.is_success() {
+                Ok(())
+            } else {
+                Err(format!("{status}: {}", response.text().unwrap_or_default()))
+            }
+        })
+    }
+}
+
+impl Confirmer for EsploraClient {
+    /// Gets the confirmations out of the height of the block the transaction is in and the one of the API tip. The
+    /// height the transaction was seen confirmed at is disregarded.
+    fn get_confirmations(&self, txid: &Txid, _: u32) -> Result<Option<u32>, ConfirmationError> {
+        let client = self.client.clone();
+        let status_endpoint = format!("{}/tx/{txid}/status", self.url);
+        let tip_endpoint = format!("{}/blocks/tip/height", self.url);
+
+        off_runtime(move || {
+            let get = |endpoint: String| {
+                client
+                    .get(endpoint)
+                    .send()
+                    .and_then(|r| r.error_for_status())
+                    .and_then(|r| r.text())
+                    .map_err(|e| e.to_string())
+            };
+
+            let status: TxStatus = serde_json::from_str(&get(status_endpoint)?)
+                .map_err(|e| format!("Unexpected tx status. Error: {e}"))?;
+            let block_height = match status.block_height {
+                Some(h) if status.confirmed => h,
+                _ => return Ok(None),
+            };
+            let tip = get(tip_endpoint)?
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| "Unexpected tip height".to_owned())?;
+
+            Ok(Some(tip.saturating_sub(block_height) + 1))
+        })
+        .map_err(ConfirmationError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::consensus;
+    use bitcoin::hashes::hex::FromHex;
+    use bitcoin::hashes::Hash;
+    use teos_common::test_utils::TX_HEX;
+
+    #[test]
+    fn test_broadcast() {
+        let mut esplora = mockito::Server::new();
+        let client = EsploraClient::new(&format!("{}/", esplora.url())).unwrap();
+        let tx: Transaction = consensus::deserialize(&Vec::from_hex(TX_HEX).unwrap()).unwrap();
+
+        let accepted = esplora
+            .mock("POST", "/tx")
+            .match_body(TX_HEX)
+            .with_status(200)
+            .create();
+        assert_eq!(client.broadcast(&tx), Ok(()));
+        accepted.assert();
+
+        // Clients created out of another one query their own API
+        let mut rejecting = mockito::Server::new();
+        let _rejected = rejecting
+            .mock("POST", "/tx")
+            .with_status(400)
+            .with_body("sendrawtransaction RPC error")
+            .create();
+        assert_eq!(
+            client.with_url(&rejecting.url()).broadcast(&tx),
+            Err("400 Bad Request: sendrawtransaction RPC error".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_get_confirmations() {
+        let mut esplora = mockito::Server::new();
+        let client = EsploraClient::new(&format!("{}/", esplora.url())).unwrap();
+        let confirmed = Txid::from_inner([1; 32]);
+        let unconfirmed = Txid::from_inner([2; 32]);
+        let unknown = Txid::from_inner([3; 32]);
+
+        let _tip = esplora
+            .mock("GET", "/blocks/tip/height")
+            .with_body("800100")
+            .create();
+        let _confirmed = esplora
+            .mock("GET", format!("/tx/{confirmed}/status").as_str())
+            .with_body(r#"{"confirmed": true, "block_height": 800000, "block_hash": "00"}"#)
+            .create();
+        let _unconfirmed = esplora
+            .mock("GET", format!("/tx/{unconfirmed}/status").as_str())
+            .with_body(r#"{"confirmed": false}"#)
+            .create();
+        let _unknown = esplora
+            .mock("GET", format!("/tx/{unknown}/status").as_str())
+            .with_status(404)
+            .create();
+
+        assert_eq!(client.get_confirmations(&confirmed, 800000), Ok(Some(101)));
+        assert_eq!(client.get_confirmations(&unconfirmed, 800000), Ok(None));
+        // Unknown transactions cannot be told apart from an API error
+        assert!(client.get_confirmations(&unknown, 800000).is_err());
+    }
+}
@@ -1,18 +1,26 @@
 //! Logic related to the Gatekeeper, the component in charge of managing access to the tower resources.
 
 use lightning::chain;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
-use teos_common::appointment::{compute_appointment_slots, Locator};
+use bitcoin::hashes::{sha256, Hash};
+
+use teos_common::appointment::{compute_appointment_slots, Appointment, Locator, SignatureVersion};
 use teos_common::constants::ENCRYPTED_BLOB_MAX_SIZE;
 use teos_common::cryptography;
 use teos_common::receipts::RegistrationReceipt;
 use teos_common::UserId;
 
+use teos_common::dbm::Error as DBError;
+
 use crate::dbm::DBM;
 use crate::extended_appointment::{ExtendedAppointment, UUID};
+use crate::payments::PaymentVerifier;
 
 /// Data regarding a user subscription with the tower.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -23,17 +31,28 @@ pub(crate) struct UserInfo {
     pub(crate) subscription_start: u32,
     /// Block height where the user subscription expires.
     pub(crate) subscription_expiry: u32,
+    /// Version of the serialization the user signs appointments over, as agreed when registering.
+    pub(crate) signature_version: SignatureVersion,
 }
 
 impl UserInfo {
     /// Creates a new [UserInfo] instance.
+    ///
+    /// Users sign using [SignatureVersion::V1] unless a different version is set (see [with_signature_version](Self::with_signature_version)).
     pub fn new(available_slots: u32, subscription_start: u32, subscription_expiry: u32) -> Self {
         UserInfo {
             available_slots,
             subscription_start,
             subscription_expiry,
+            signature_version: SignatureVersion::default(),
         }
     }
+
+    /// Sets the [SignatureVersion] the user signs appointments with.
+    pub fn with_signature_version(mut self, signature_version: SignatureVersion) -> Self {
+        self.signature_version = signature_version;
+        self
+    }
 }
 
 /// Error raised if the user cannot be authenticated.
@@ -44,11 +63,116 @@ pub(crate) struct AuthenticationFailure<'a>(&'a str);
 #[derive(Debug, PartialEq)]
 pub(crate) struct NotEnoughSlots;
 
-/// Error raised if the user subscription slots limit has been reached.
+/// Errors raised if a user subscription cannot be renewed.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum RenewalFailure {
+    /// The user subscription slots limit has been reached. This is currently set to [u32::MAX].
+    MaxSlotsReached,
+    /// The subscription is not close enough to its expiry to be renewed (see [Gatekeeper::renewal_window]).
+    TooEarly,
+    /// The renewed subscription would expire too far into the future (see [Gatekeeper::max_expiry_horizon]).
+    ExpiryTooFar,
+    /// The tower requires a payment to grant the subscription. Contains the invoice the user needs to pay.
+    PaymentRequired(String),
+    /// The provided payment proof is not valid (the invoice is unknown, unpaid, or has already been redeemed).
+    InvalidPayment(&'static str),
+    /// Payments cannot be verified or redeemed right now (e.g. the Lightning node they are verified against cannot be reached).
+    PaymentUnavailable,
+    /// The user is not allowed to register with the tower (see [UserAccessLists]).
+    NotAllowed,
+}
+
+/// Errors raised if a user subscription cannot be transferred to a new user.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum TransferFailure {
+    /// The user the subscription is transferred from is not registered.
+    UserNotFound,
+    /// The user the subscription is transferred to is already registered.
+    AlreadyRegistered,
+    /// The transfer has already been performed once.
+    Replayed,
+    /// The user the subscription is transferred to is not allowed to register with the tower (see [UserAccessLists]).
+    NotAllowed,
+}
+
+/// Users allowed to, or blocked from, registering with the tower.
 ///
-/// This is currently set to [u32::MAX].
-#[derive(Debug, PartialEq)]
-pub(crate) struct MaxSlotsReached;
+/// Both lists are loaded from files holding one user id per line. Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Default, Clone)]
+pub struct UserAccessLists {
+    /// Path to the allowlist file, if any.
+    allowlist_path: Option<PathBuf>,
+    /// Path to the blocklist file, if any.
+    blocklist_path: Option<PathBuf>,
+    /// Users allowed to register. Everyone is allowed if unset.
+    allowed: Option<HashSet<UserId>>,
+    /// Users that cannot register, even if they are allowlisted.
+    blocked: HashSet<UserId>,
+}
+
+impl UserAccessLists {
+    /// Creates a new [UserAccessLists] instance, loading the lists from the given files.
+    pub fn new(
+        allowlist_path: Option<PathBuf>,
+        blocklist_path: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        let mut access_lists = UserAccessLists {
+            allowlist_path,
+            blocklist_path,
+            ..Default::default()
+        };
+        access_lists.reload()?;
+        Ok(access_lists)
+    }
+
+    /// Reloads the lists from their files. The current lists are kept if any of the files cannot be loaded.
+    pub fn reload(&mut self) -> Result<(), String> {
+        let allowed = self
+            .allowlist_path
+            .as_deref()
+            .map(load_user_list)
+            .transpose()?;
+        let blocked = self
+            .blocklist_path
+            .as_deref()
+            .map(load_user_list)
+            .transpose()?
+            .unwrap_or_default();
+
+        self.allowed = allowed;
+        self.blocked = blocked;
+        Ok(())
+    }
+
+    /// Whether a given user is allowed to register.
+    pub fn is_allowed(&self, user_id: &UserId) -> bool {
+        !self.blocked.contains(user_id)
+            && self
+                .allowed
+                .as_ref()
+                .is_none_or(|allowed| allowed.contains(user_id))
+    }
+
+    /// Gets the number of allowlisted users (if there is an allowlist) and blocklisted users.
+    pub fn sizes(&self) -> (Option<usize>, usize) {
+        (self.allowed.as_ref().map(|a| a.len()), self.blocked.len())
+    }
+}
+
+/// Loads a list of user ids from a file (see [UserAccessLists]).
+fn load_user_list(path: &Path) -> Result<HashSet<UserId>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}. Error: {e}", path.display()))?;
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            UserId::from_str(line)
+                .map_err(|_| format!("Invalid user id found in {}: {line}", path.display()))
+        })
+        .collect()
+}
 
 /// Component in charge of managing access to the tower resources.
 ///
@@ -67,8 +191,20 @@ pub struct Gatekeeper {
     subscription_duration: u32,
     /// Grace period given to renew subscriptions, in blocks.
     expiry_delta: u32,
+    /// How close to its expiry a subscription needs to be in order to be renewed, in blocks. Zero disables the check.
+    renewal_window: u32,
+    /// How far into the future a renewed subscription can expire, in blocks (starting from the block the renewal is requested).
+    /// Zero disables the check.
+    max_expiry_horizon: u32,
     /// Map of users registered within the tower.
     registered_users: Mutex<HashMap<UserId, UserInfo>>,
+    /// Users whose subscription has expired (but are not outdated yet), as flagged by the expiry sweep (see
+    /// [sweep_expired_subscriptions](Self::sweep_expired_subscriptions)).
+    expired_users: Mutex<HashSet<UserId>>,
+    /// Verifies the payments required to get a subscription, if any. Subscriptions are free if unset.
+    payment_verifier: Option<Arc<dyn PaymentVerifier>>,
+    /// Users allowed to, or blocked from, registering with the tower.
+    access_lists: Mutex<UserAccessLists>,
     /// A [DBM] (database manager) instance. Used to persist appointment data into disk.
     dbm: Arc<Mutex<DBM>>,
 }
@@ -80,19 +216,58 @@ impl Gatekeeper {
         subscription_slots: u32,
         subscription_duration: u32,
         expiry_delta: u32,
+        renewal_window: u32,
+        max_expiry_horizon: u32,
         dbm: Arc<Mutex<DBM>>,
     ) -> Self {
         let registered_users = dbm.lock().unwrap().load_all_users();
+        // Subscriptions that expired while the tower was offline are flagged straightaway
+        let expired_users = registered_users
+            .iter()
+            .filter(|(_, info)| last_known_block_height >= info.subscription_expiry)
+            .map(|(user_id, _)| *user_id)
+            .collect();
         Gatekeeper {
             last_known_block_height: AtomicU32::new(last_known_block_height),
             subscription_slots,
             subscription_duration,
             expiry_delta,
+            renewal_window,
+            max_expiry_horizon,
             registered_users: Mutex::new(registered_users),
+            expired_users: Mutex::new(expired_users),
+            payment_verifier: None,
+            access_lists: Mutex::new(UserAccessLists::default()),
             dbm,
         }
     }
 
+    /// Requires users to pay (the invoices issued by the given verifier) in order to register or renew a subscription.
+    pub fn with_payment_verifier(mut self, payment_verifier: Arc<dyn PaymentVerifier>) -> Self {
+        self.payment_verifier = Some(payment_verifier);
+        self
+    }
+
+    /// Restricts which users can register with the tower.
+    pub fn with_access_lists(mut self, access_lists: UserAccessLists) -> Self {
+        self.access_lists = Mutex::new(access_lists);
+        self
+    }
+
+    /// Reloads the lists of allowed and blocked users from disk.
+    ///
+    /// Returns the size of the reloaded lists (see [UserAccessLists::sizes]).
+    pub(crate) fn reload_access_lists(&self) -> Result<(Option<usize>, usize), String> {
+        let mut access_lists = self.access_lists.lock().unwrap();
+        access_lists.reload()?;
+        Ok(access_lists.sizes())
+    }
+
+    /// Whether a given user is allowed to register with the tower.
+    fn is_user_allowed(&self, user_id: &UserId) -> bool {
+        self.access_lists.lock().unwrap().is_allowed(user_id)
+    }
+
     /// Returns whether the [Gatekeeper] has been created from scratch (fresh) or from backed-up data.
     pub fn is_fresh(&self) -> bool {
         self.registered_users.lock().unwrap().is_empty()
@@ -103,6 +278,11 @@ impl Gatekeeper {
         self.registered_users.lock().unwrap().len()
     }
 
+    /// Gets the number of users whose subscription has expired but are not outdated yet.
+    pub(crate) fn get_expired_users_count(&self) -> usize {
+        self.expired_users.lock().unwrap().len()
+    }
+
     /// Gets the list of all registered user ids.
     pub(crate) fn get_user_ids(&self) -> Vec<UserId> {
         self.registered_users
@@ -113,6 +293,44 @@ impl Gatekeeper {
             .collect()
     }
 
+    /// Compares the users held in memory against the ones stored in the database. Returns the users whose available slots
+    /// do not match, alongside the slots held in memory and the stored ones (if the user is stored at all).
+    pub(crate) fn get_slot_mismatches(&self) -> Vec<(UserId, u32, Option<u32>)> {
+        let stored_users = self.dbm.lock().unwrap().load_all_users();
+        self.registered_users
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(user_id, info)| {
+                let stored_slots = stored_users
+                    .get(user_id)
+                    .map(|stored| stored.available_slots);
+                (stored_slots != Some(info.available_slots)).then_some((
+                    *user_id,
+                    info.available_slots,
+                    stored_slots,
+                ))
+            })
+            .collect()
+    }
+
+    /// Overwrites the stored data of the given users with the one held in memory, storing the users if missing.
+    pub(crate) fn persist_users(&self, user_ids: &[UserId]) {
+        let registered_users = self.registered_users.lock().unwrap();
+        let dbm = self.dbm.lock().unwrap();
+        let stored_users = dbm.load_all_users();
+        for user_id in user_ids {
+            match registered_users.get(user_id) {
+                Some(info) if stored_users.contains_key(user_id) => dbm.update_user(*user_id, info),
+                Some(info) => {
+                    // Errors are already logged by the DBM
+                    dbm.store_user(*user_id, info).ok();
+                }
+                None => (),
+            }
+        }
+    }
+
     /// Gets the data held by the tower about a given user.
     pub(crate) fn get_user_info(&self, user_id: UserId) -> Option<(UserInfo, Vec<Locator>)> {
         let info = self.registered_users.lock().unwrap().get(&user_id).cloned();
@@ -140,11 +358,160 @@ impl Gatekeeper {
         }
     }
 
+    /// Authenticates a user by the signature of an appointment (and the reward it carries, if any).
+    ///
+    /// The user cannot be known before recovering it from the signature, so the signature is recovered over the
+    /// serialization of every supported [SignatureVersion]. However, it is only deemed valid if done over the version
+    /// agreed with the recovered user when registering. Appointments carrying a reward are only accepted from users
+    /// whose version commits to it, so the reward cannot be tampered with.
+    pub(crate) fn authenticate_appointment(
+        &self,
+        appointment: &Appointment,
+        reward: Option<u64>,
+        signature: &str,
+    ) -> Result<UserId, AuthenticationFailure<'_>> {
+        let mut result = Err(AuthenticationFailure("User not found."));
+        for version in SignatureVersion::SUPPORTED.into_iter().rev() {
+            let user_id = match cryptography::recover_pk(
+                &appointment.to_signable_vec_with_reward(version, reward),
+                signature,
+            ) {
+                Ok(pk) => UserId(pk),
+                Err(_) => return Err(AuthenticationFailure("Wrong message or signature.")),
+            };
+            match self.registered_users.lock().unwrap().get(&user_id) {
+                Some(user_info) if user_info.signature_version == version => {
+                    if reward.is_some() && !version.commits_to_reward() {
+                        return Err(AuthenticationFailure(
+                            "Rewards can only be set using a signature version that commits to them.",
+                        ));
+                    }
+                    return Ok(user_id);
+                }
+                Some(_) => {
+                    result = Err(AuthenticationFailure(
+                        "Wrong signature version. Sign using the one agreed when registering.",
+                    ))
+                }
+                None => {}
+            }
+        }
+        result
+    }
+
+    /// Gets the [SignatureVersion] agreed with a given user, if registered.
+    pub(crate) fn get_signature_version(&self, user_id: UserId) -> Option<SignatureVersion> {
+        self.registered_users
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(|user_info| user_info.signature_version)
+    }
+
+    /// Sets the [SignatureVersion] agreed with a given user (when registering or renewing its subscription).
+    pub(crate) fn set_signature_version(&self, user_id: UserId, version: SignatureVersion) {
+        if let Some(user_info) = self.registered_users.lock().unwrap().get_mut(&user_id) {
+            if user_info.signature_version != version {
+                if let Err(e) = self
+                    .dbm
+                    .lock()
+                    .unwrap()
+                    .store_signature_version(user_id, version)
+                {
+                    log::error!("Couldn't store the signature version of {user_id}. Error: {e:?}");
+                    return;
+                }
+                user_info.signature_version = version;
+            }
+        }
+    }
+
     /// Adds a new user to the tower (or updates its subscription if already registered).
+    ///
+    /// Renewals are only accepted if the current subscription expires within the [renewal_window](Self::renewal_window)
+    /// and the renewed one does not expire further than [max_expiry_horizon](Self::max_expiry_horizon) blocks from now.
+    ///
+    /// If the tower requires a payment, no subscription is granted. Instead, [RenewalFailure::PaymentRequired] is returned
+    /// with an invoice that needs to be paid and redeemed through [add_update_paid_user](Self::add_update_paid_user).
     pub(crate) fn add_update_user(
         &self,
         user_id: UserId,
-    ) -> Result<RegistrationReceipt, MaxSlotsReached> {
+    ) -> Result<RegistrationReceipt, RenewalFailure> {
+        if !self.is_user_allowed(&user_id) {
+            return Err(RenewalFailure::NotAllowed);
+        }
+        match &self.payment_verifier {
+            Some(verifier) => Err(verifier
+                .create_invoice(user_id)
+                .map(RenewalFailure::PaymentRequired)
+                .unwrap_or_else(|e| {
+                    log::error!("Cannot create invoice for {user_id}. {e}");
+                    RenewalFailure::PaymentUnavailable
+                })),
+            None => self.grant_subscription(user_id),
+        }
+    }
+
+    /// Adds a new user to the tower (or updates its subscription if already registered) given the preimage of a paid invoice.
+    ///
+    /// Every payment can only be redeemed once. If the tower does not require payments, the preimage is ignored.
+    pub(crate) fn add_update_paid_user(
+        &self,
+        user_id: UserId,
+        payment_preimage: &[u8],
+    ) -> Result<RegistrationReceipt, RenewalFailure> {
+        if !self.is_user_allowed(&user_id) {
+            return Err(RenewalFailure::NotAllowed);
+        }
+        let verifier = match &self.payment_verifier {
+            Some(verifier) => verifier,
+            None => return self.grant_subscription(user_id),
+        };
+
+        let payment_hash = sha256::Hash::hash(payment_preimage);
+        match verifier.is_paid(user_id, &payment_hash) {
+            Ok(true) => {}
+            Ok(false) => {
+                return Err(RenewalFailure::InvalidPayment(
+                    "The invoice is unknown, has not been paid, or was not issued to this user",
+                ))
+            }
+            Err(e) => {
+                log::error!("Cannot verify payment {payment_hash}. {e}");
+                return Err(RenewalFailure::PaymentUnavailable);
+            }
+        }
+
+        // The payment is redeemed before granting the subscription so concurrent requests cannot redeem it twice
+        match self
+            .dbm
+            .lock()
+            .unwrap()
+            .store_redeemed_payment(&payment_hash, user_id)
+        {
+            Ok(()) => {}
+            Err(DBError::AlreadyExists) => {
+                return Err(RenewalFailure::InvalidPayment(
+                    "The payment has already been redeemed",
+                ))
+            }
+            Err(e) => {
+                log::error!("Cannot redeem payment {payment_hash}. Error: {e:?}");
+                return Err(RenewalFailure::PaymentUnavailable);
+            }
+        }
+
+        // The payment can be used again if the subscription cannot be granted (e.g. it is too early to renew it)
+        self.grant_subscription(user_id).inspect_err(|_| {
+            self.dbm
+                .lock()
+                .unwrap()
+                .remove_redeemed_payment(&payment_hash)
+        })
+    }
+
+    /// Grants a new subscription to a given user, or renews the current one (see [add_update_user](Self::add_update_user)).
+    fn grant_subscription(&self, user_id: UserId) -> Result<RegistrationReceipt, RenewalFailure> {
         let block_count = self.last_known_block_height.load(Ordering::Acquire);
 
         // TODO: For now, new calls to `add_update_user` add subscription_slots to the current count and reset the expiry time
@@ -152,15 +519,31 @@ impl Gatekeeper {
         let user_info = match registered_users.get_mut(&user_id) {
             // User already exists, updating the info
             Some(user_info) => {
-                user_info.available_slots = user_info
+                let available_slots = user_info
                     .available_slots
                     .checked_add(self.subscription_slots)
-                    .ok_or(MaxSlotsReached)?;
-                user_info.subscription_expiry = user_info
+                    .ok_or(RenewalFailure::MaxSlotsReached)?;
+                if self.renewal_window != 0
+                    && user_info.subscription_expiry.saturating_sub(block_count)
+                        > self.renewal_window
+                {
+                    return Err(RenewalFailure::TooEarly);
+                }
+                let subscription_expiry = user_info
                     .subscription_expiry
                     .checked_add(self.subscription_duration)
                     .unwrap_or(u32::MAX);
+                if self.max_expiry_horizon != 0
+                    && subscription_expiry.saturating_sub(block_count) > self.max_expiry_horizon
+                {
+                    return Err(RenewalFailure::ExpiryTooFar);
+                }
+
+                user_info.available_slots = available_slots;
+                user_info.subscription_expiry = subscription_expiry;
                 self.dbm.lock().unwrap().update_user(user_id, user_info);
+                // Flagged again by the next sweep if the renewed subscription is still expired
+                self.expired_users.lock().unwrap().remove(&user_id);
 
                 user_info
             }
@@ -190,6 +573,53 @@ impl Gatekeeper {
         ))
     }
 
+    /// Transfers the subscription of `old_user_id` (slots, expiry and appointments) to `new_user_id`.
+    ///
+    /// Transfers are identified by `transfer_id` and can only be performed once. The new user must not be registered
+    /// with the tower, and must be allowed to register with it.
+    pub(crate) fn transfer_subscription(
+        &self,
+        old_user_id: UserId,
+        new_user_id: UserId,
+        transfer_id: &[u8],
+    ) -> Result<RegistrationReceipt, TransferFailure> {
+        if !self.is_user_allowed(&new_user_id) {
+            return Err(TransferFailure::NotAllowed);
+        }
+        let mut registered_users = self.registered_users.lock().unwrap();
+        if registered_users.contains_key(&new_user_id) {
+            return Err(TransferFailure::AlreadyRegistered);
+        }
+        let user_info = *registered_users
+            .get(&old_user_id)
+            .ok_or(TransferFailure::UserNotFound)?;
+
+        match self.dbm.lock().unwrap().transfer_user(
+            old_user_id,
+            new_user_id,
+            &user_info,
+            transfer_id,
+        ) {
+            Ok(()) => {}
+            Err(DBError::AlreadyExists) => return Err(TransferFailure::Replayed),
+            Err(e) => panic!("Couldn't transfer subscription of {old_user_id}. Error: {e:?}"),
+        }
+
+        registered_users.remove(&old_user_id);
+        registered_users.insert(new_user_id, user_info);
+        let mut expired_users = self.expired_users.lock().unwrap();
+        if expired_users.remove(&old_user_id) {
+            expired_users.insert(new_user_id);
+        }
+
+        Ok(RegistrationReceipt::new(
+            new_user_id,
+            user_info.available_slots,
+            user_info.subscription_start,
+            user_info.subscription_expiry,
+        ))
+    }
+
     /// Adds an appointment to a given user, or updates it if already present in the system (and belonging to the requester).
     pub(crate) fn add_update_appointment(
         &self,
@@ -226,6 +656,9 @@ impl Gatekeeper {
     }
 
     /// Checks whether a subscription has expired.
+    ///
+    /// Subscriptions flagged by the expiry sweep are known to be expired. The rest are checked against the last known
+    /// block height, given the sweep may not have caught up with it yet.
     pub(crate) fn has_subscription_expired(
         &self,
         user_id: UserId,
@@ -234,14 +667,42 @@ impl Gatekeeper {
             Err(AuthenticationFailure("User not found.")),
             |user_info| {
                 Ok((
-                    self.last_known_block_height.load(Ordering::Acquire)
-                        >= user_info.subscription_expiry,
+                    self.expired_users.lock().unwrap().contains(&user_id)
+                        || self.last_known_block_height.load(Ordering::Acquire)
+                            >= user_info.subscription_expiry,
                     user_info.subscription_expiry,
                 ))
             },
         )
     }
 
+    /// Flags the subscriptions that have expired at a given block height, so they are known upfront instead of being
+    /// found out when the user interacts with the tower.
+    ///
+    /// This is run on every block connection. Returns the users whose subscription has newly expired.
+    pub(crate) fn sweep_expired_subscriptions(&self, block_height: u32) -> Vec<UserId> {
+        let registered_users = self.registered_users.lock().unwrap();
+        let mut expired_users = self.expired_users.lock().unwrap();
+
+        let newly_expired: Vec<UserId> = registered_users
+            .iter()
+            .filter(|(user_id, info)| {
+                block_height >= info.subscription_expiry && !expired_users.contains(user_id)
+            })
+            .map(|(user_id, _)| *user_id)
+            .collect();
+        for user_id in newly_expired.iter() {
+            log::info!(
+                "Subscription of {user_id} expired at height {}. Renewal grace period ends at height {}",
+                registered_users[user_id].subscription_expiry,
+                registered_users[user_id].subscription_expiry + self.expiry_delta
+            );
+            expired_users.insert(*user_id);
+        }
+
+        newly_expired
+    }
+
     /// Gets a map of outdated users. Outdated users are those whose subscription has expired and the renewal grace period
     /// has already passed ([expiry_delta](Self::expiry_delta)).
     pub(crate) fn get_outdated_users(&self, block_height: u32) -> Vec<UserId> {
@@ -311,22 +772,41 @@ impl chain::Listen for Gatekeeper {
                 let mut registered_users = self.registered_users.lock().unwrap();
                 // Removing each outdated user in a loop is more efficient than retaining non-outdated users
                 // because retaining would loop over all the available users which is always more than the outdated ones.
+                let mut expired_users = self.expired_users.lock().unwrap();
                 for outdated_user in outdated_users.iter() {
                     registered_users.remove(outdated_user);
+                    expired_users.remove(outdated_user);
                 }
             }
             self.dbm.lock().unwrap().batch_remove_users(&outdated_users);
         }
 
+        let newly_expired = self.sweep_expired_subscriptions(height);
+        if !newly_expired.is_empty() {
+            log::info!(
+                "{} subscription(s) expired at height {height}. {} expired in total",
+                newly_expired.len(),
+                self.get_expired_users_count()
+            );
+        }
+
         // Update last known block height
         self.last_known_block_height
             .store(height, Ordering::Release);
     }
 
-    /// Handles reorgs in the [Gatekeeper]. Simply updates the last_known_block_height.
+    /// Handles reorgs in the [Gatekeeper]. Updates the last_known_block_height and un-flags the subscriptions that are
+    /// not expired anymore.
     fn block_disconnected(&self, header: &bitcoin::BlockHeader, height: u32) {
         log::warn!("Block disconnected: {}", header.block_hash());
-        // There's nothing to be done here but updating the last known block
+        {
+            let registered_users = self.registered_users.lock().unwrap();
+            self.expired_users.lock().unwrap().retain(|user_id| {
+                registered_users
+                    .get(user_id)
+                    .is_some_and(|info| height > info.subscription_expiry)
+            });
+        }
         self.last_known_block_height
             .store(height - 1, Ordering::Release);
     }
@@ -336,16 +816,23 @@ impl chain::Listen for Gatekeeper {
 mod tests {
     use super::*;
 
-    use crate::test_utils::{generate_dummy_appointment_with_user, get_random_tracker, Blockchain};
+    use crate::test_utils::{
+        generate_dummy_appointment, generate_dummy_appointment_with_user, get_random_tracker,
+        Blockchain, MockedPaymentVerifier,
+    };
     use lightning::chain::Listen;
     use teos_common::cryptography::{get_random_bytes, get_random_keypair};
     use teos_common::test_utils::get_random_user_id;
 
+    use tempdir::TempDir;
+
     use crate::responder::ConfirmationStatus;
 
     const SLOTS: u32 = 21;
     const DURATION: u32 = 500;
     const EXPIRY_DELTA: u32 = 42;
+    const RENEWAL_WINDOW: u32 = DURATION;
+    const MAX_EXPIRY_HORIZON: u32 = 2 * DURATION;
     const START_HEIGHT: usize = 100;
 
     impl PartialEq for Gatekeeper {
@@ -375,7 +862,15 @@ mod tests {
 
     fn init_gatekeeper(chain: &Blockchain) -> Gatekeeper {
         let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
-        Gatekeeper::new(chain.get_block_count(), SLOTS, DURATION, EXPIRY_DELTA, dbm)
+        Gatekeeper::new(
+            chain.get_block_count(),
+            SLOTS,
+            DURATION,
+            EXPIRY_DELTA,
+            RENEWAL_WINDOW,
+            MAX_EXPIRY_HORIZON,
+            dbm,
+        )
     }
 
     #[test]
@@ -389,6 +884,8 @@ mod tests {
             SLOTS,
             DURATION,
             EXPIRY_DELTA,
+            RENEWAL_WINDOW,
+            MAX_EXPIRY_HORIZON,
             dbm.clone(),
         );
         assert!(gatekeeper.is_fresh());
@@ -413,8 +910,15 @@ mod tests {
         }
 
         // Create a new GK reusing the same DB and check that the data is loaded
-        let another_gk =
-            Gatekeeper::new(chain.get_block_count(), SLOTS, DURATION, EXPIRY_DELTA, dbm);
+        let another_gk = Gatekeeper::new(
+            chain.get_block_count(),
+            SLOTS,
+            DURATION,
+            EXPIRY_DELTA,
+            RENEWAL_WINDOW,
+            MAX_EXPIRY_HORIZON,
+            dbm,
+        );
         assert!(!another_gk.is_fresh());
         assert_eq!(gatekeeper, another_gk);
     }
@@ -450,6 +954,120 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_authenticate_appointment() {
+        let gatekeeper = init_gatekeeper(&Blockchain::default().with_height(START_HEIGHT));
+        let appointment = generate_dummy_appointment(None).inner;
+
+        // Only signatures over the version agreed with the user are accepted once it is registered. Users sign using V1
+        // unless agreed otherwise
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        gatekeeper.add_update_user(user_id).unwrap();
+        let v1_signature =
+            cryptography::sign(&appointment.to_signable_vec(SignatureVersion::V1), &user_sk)
+                .unwrap();
+        let v2_signature =
+            cryptography::sign(&appointment.to_signable_vec(SignatureVersion::V2), &user_sk)
+                .unwrap();
+
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, None, &v1_signature),
+            Ok(user_id)
+        );
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, None, &v2_signature),
+            Err(AuthenticationFailure(
+                "Wrong signature version. Sign using the one agreed when registering."
+            ))
+        );
+
+        gatekeeper.set_signature_version(user_id, SignatureVersion::V2);
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, None, &v2_signature),
+            Ok(user_id)
+        );
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, None, &v1_signature),
+            Err(AuthenticationFailure(
+                "Wrong signature version. Sign using the one agreed when registering."
+            ))
+        );
+
+        // The version is persisted alongside the rest of the user data
+        assert_eq!(
+            gatekeeper
+                .dbm
+                .lock()
+                .unwrap()
+                .load_user(user_id)
+                .unwrap()
+                .signature_version,
+            SignatureVersion::V2
+        );
+
+        // Signatures over something else, or by unknown users, are not
+        let signature = cryptography::sign(&appointment.locator.to_vec(), &user_sk).unwrap();
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, None, &signature),
+            Err(AuthenticationFailure("User not found."))
+        );
+        let signature = cryptography::sign(
+            &appointment.to_signable_vec(SignatureVersion::V2),
+            &get_random_keypair().0,
+        )
+        .unwrap();
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, None, &signature),
+            Err(AuthenticationFailure("User not found."))
+        );
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, None, "signature"),
+            Err(AuthenticationFailure("Wrong message or signature."))
+        );
+    }
+
+    #[test]
+    fn test_authenticate_appointment_reward() {
+        let gatekeeper = init_gatekeeper(&Blockchain::default().with_height(START_HEIGHT));
+        let appointment = generate_dummy_appointment(None).inner;
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        gatekeeper.add_update_user(user_id).unwrap();
+
+        // Users whose signature version does not commit to the reward cannot set one
+        gatekeeper.set_signature_version(user_id, SignatureVersion::V2);
+        let signature = cryptography::sign(
+            &appointment.to_signable_vec_with_reward(SignatureVersion::V2, Some(1000)),
+            &user_sk,
+        )
+        .unwrap();
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, Some(1000), &signature),
+            Err(AuthenticationFailure(
+                "Rewards can only be set using a signature version that commits to them."
+            ))
+        );
+
+        // Otherwise, the reward is covered by the signature, so it cannot be changed
+        gatekeeper.set_signature_version(user_id, SignatureVersion::V3);
+        let signature = cryptography::sign(
+            &appointment.to_signable_vec_with_reward(SignatureVersion::V3, Some(1000)),
+            &user_sk,
+        )
+        .unwrap();
+        assert_eq!(
+            gatekeeper.authenticate_appointment(&appointment, Some(1000), &signature),
+            Ok(user_id)
+        );
+        for reward in [None, Some(2000)] {
+            assert_eq!(
+                gatekeeper.authenticate_appointment(&appointment, reward, &signature),
+                Err(AuthenticationFailure("User not found."))
+            );
+        }
+    }
+
     #[test]
     fn test_add_update_user() {
         let mut chain = Blockchain::default().with_height(START_HEIGHT);
@@ -505,7 +1123,7 @@ mod tests {
 
         assert!(matches!(
             gatekeeper.add_update_user(user_id),
-            Err(MaxSlotsReached)
+            Err(RenewalFailure::MaxSlotsReached)
         ));
 
         // Data in the database remains untouched
@@ -519,6 +1137,283 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_add_update_user_renewal_too_early() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let gatekeeper = init_gatekeeper(&chain);
+
+        // Renewals are only accepted if the subscription expires within the renewal window. A fresh subscription
+        // expires in DURATION blocks, so it can be renewed once right away.
+        let user_id = get_random_user_id();
+        gatekeeper.add_update_user(user_id).unwrap();
+        let receipt = gatekeeper.add_update_user(user_id).unwrap();
+        assert_eq!(
+            receipt.subscription_expiry(),
+            START_HEIGHT as u32 + DURATION * 2
+        );
+
+        // Now the subscription expires further than RENEWAL_WINDOW blocks from now, so it cannot be renewed
+        assert_eq!(
+            gatekeeper.add_update_user(user_id),
+            Err(RenewalFailure::TooEarly)
+        );
+
+        // Data in the database remains untouched
+        assert_eq!(
+            gatekeeper.dbm.lock().unwrap().load_user(user_id).unwrap(),
+            UserInfo::new(
+                receipt.available_slots(),
+                receipt.subscription_start(),
+                receipt.subscription_expiry()
+            )
+        );
+
+        // Once the chain moves forward the subscription can be renewed again
+        for _ in 0..DURATION {
+            chain.generate(None);
+        }
+        gatekeeper
+            .last_known_block_height
+            .store(chain.get_block_count(), Ordering::Relaxed);
+        assert!(gatekeeper.add_update_user(user_id).is_ok());
+    }
+
+    #[test]
+    fn test_add_update_user_renewal_unbounded() {
+        let chain = Blockchain::default().with_height(START_HEIGHT);
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        // Setting both the renewal window and the expiry horizon to zero disables the checks
+        let gatekeeper = Gatekeeper::new(
+            chain.get_block_count(),
+            SLOTS,
+            DURATION,
+            EXPIRY_DELTA,
+            0,
+            0,
+            dbm,
+        );
+
+        let user_id = get_random_user_id();
+        let mut receipt = gatekeeper.add_update_user(user_id).unwrap();
+        for _ in 0..3 {
+            receipt = gatekeeper.add_update_user(user_id).unwrap();
+        }
+        assert_eq!(
+            receipt.subscription_expiry(),
+            START_HEIGHT as u32 + DURATION * 4
+        );
+    }
+
+    #[test]
+    fn test_add_update_user_renewal_too_far() {
+        let chain = Blockchain::default().with_height(START_HEIGHT);
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        // Any subscription can be renewed, but it cannot expire further than MAX_EXPIRY_HORIZON blocks from now
+        let gatekeeper = Gatekeeper::new(
+            chain.get_block_count(),
+            SLOTS,
+            DURATION,
+            EXPIRY_DELTA,
+            0,
+            MAX_EXPIRY_HORIZON,
+            dbm,
+        );
+
+        let user_id = get_random_user_id();
+        gatekeeper.add_update_user(user_id).unwrap();
+        let receipt = gatekeeper.add_update_user(user_id).unwrap();
+        assert_eq!(
+            receipt.subscription_expiry(),
+            START_HEIGHT as u32 + MAX_EXPIRY_HORIZON
+        );
+
+        // Renewing again would set the expiry past the horizon
+        assert_eq!(
+            gatekeeper.add_update_user(user_id),
+            Err(RenewalFailure::ExpiryTooFar)
+        );
+
+        // Data in the database remains untouched
+        assert_eq!(
+            gatekeeper.dbm.lock().unwrap().load_user(user_id).unwrap(),
+            UserInfo::new(
+                receipt.available_slots(),
+                receipt.subscription_start(),
+                receipt.subscription_expiry()
+            )
+        );
+    }
+
+    #[test]
+    fn test_add_update_paid_user() {
+        let chain = Blockchain::default().with_height(START_HEIGHT);
+
+        // Towers that do not require payments ignore the preimage
+        let gatekeeper = init_gatekeeper(&chain);
+        let user_id = get_random_user_id();
+        gatekeeper
+            .add_update_paid_user(user_id, &get_random_bytes(32))
+            .unwrap();
+        assert!(gatekeeper
+            .registered_users
+            .lock()
+            .unwrap()
+            .contains_key(&user_id));
+
+        // Otherwise, registering without paying returns an invoice to be paid
+        let verifier = Arc::new(MockedPaymentVerifier::default());
+        let gatekeeper = init_gatekeeper(&chain).with_payment_verifier(verifier.clone());
+        let user_id = get_random_user_id();
+        assert!(matches!(
+            gatekeeper.add_update_user(user_id),
+            Err(RenewalFailure::PaymentRequired(invoice)) if invoice.contains(&user_id.to_string())
+        ));
+        assert!(!gatekeeper
+            .registered_users
+            .lock()
+            .unwrap()
+            .contains_key(&user_id));
+
+        // Preimages of unpaid invoices are rejected
+        let preimage = get_random_bytes(32);
+        assert!(matches!(
+            gatekeeper.add_update_paid_user(user_id, &preimage),
+            Err(RenewalFailure::InvalidPayment(..))
+        ));
+        assert!(!gatekeeper
+            .registered_users
+            .lock()
+            .unwrap()
+            .contains_key(&user_id));
+
+        // Payments made for someone else are rejected too
+        verifier.pay(get_random_user_id(), &preimage);
+        assert!(matches!(
+            gatekeeper.add_update_paid_user(user_id, &preimage),
+            Err(RenewalFailure::InvalidPayment(..))
+        ));
+
+        // Once paid, the subscription is granted
+        verifier.pay(user_id, &preimage);
+        let receipt = gatekeeper.add_update_paid_user(user_id, &preimage).unwrap();
+        assert_eq!(receipt.available_slots(), SLOTS);
+        assert_eq!(
+            gatekeeper.dbm.lock().unwrap().load_user(user_id).unwrap(),
+            UserInfo::new(
+                receipt.available_slots(),
+                receipt.subscription_start(),
+                receipt.subscription_expiry()
+            )
+        );
+
+        // The same payment cannot be redeemed twice
+        assert_eq!(
+            gatekeeper.add_update_paid_user(user_id, &preimage),
+            Err(RenewalFailure::InvalidPayment(
+                "The payment has already been redeemed"
+            ))
+        );
+
+        // Payments are not burnt if the subscription cannot be granted
+        let preimage = get_random_bytes(32);
+        verifier.pay(user_id, &preimage);
+        gatekeeper.add_update_paid_user(user_id, &preimage).unwrap();
+        assert_eq!(
+            gatekeeper.add_update_paid_user(user_id, &preimage),
+            Err(RenewalFailure::InvalidPayment(
+                "The payment has already been redeemed"
+            ))
+        );
+        let preimage = get_random_bytes(32);
+        verifier.pay(user_id, &preimage);
+        assert_eq!(
+            gatekeeper.add_update_paid_user(user_id, &preimage),
+            Err(RenewalFailure::TooEarly)
+        );
+        assert!(gatekeeper
+            .dbm
+            .lock()
+            .unwrap()
+            .store_redeemed_payment(&sha256::Hash::hash(&preimage), user_id)
+            .is_ok());
+
+        // Payments cannot be checked if the node is unreachable
+        verifier.set_unreachable(true);
+        assert_eq!(
+            gatekeeper.add_update_user(user_id),
+            Err(RenewalFailure::PaymentUnavailable)
+        );
+        assert_eq!(
+            gatekeeper.add_update_paid_user(user_id, &get_random_bytes(32)),
+            Err(RenewalFailure::PaymentUnavailable)
+        );
+    }
+
+    #[test]
+    fn test_access_lists() {
+        let tmp_dir = TempDir::new("teos_access_lists").unwrap();
+        let allowlist_path = tmp_dir.path().join("allowlist");
+        let blocklist_path = tmp_dir.path().join("blocklist");
+
+        let allowed_user_id = get_random_user_id();
+        let blocked_user_id = get_random_user_id();
+        fs::write(
+            &allowlist_path,
+            format!("# Club members\n{allowed_user_id}\n\n{blocked_user_id}\n"),
+        )
+        .unwrap();
+        fs::write(&blocklist_path, format!("{blocked_user_id}\n")).unwrap();
+
+        let access_lists =
+            UserAccessLists::new(Some(allowlist_path.clone()), Some(blocklist_path.clone()))
+                .unwrap();
+        assert_eq!(access_lists.sizes(), (Some(2), 1));
+        let gatekeeper = init_gatekeeper(&Blockchain::default().with_height(START_HEIGHT))
+            .with_access_lists(access_lists);
+
+        // Allowlisted users can register
+        gatekeeper.add_update_user(allowed_user_id).unwrap();
+
+        // Users not in the allowlist cannot, and neither can blocklisted ones (even if allowlisted)
+        let other_user_id = get_random_user_id();
+        for user_id in [other_user_id, blocked_user_id] {
+            assert_eq!(
+                gatekeeper.add_update_user(user_id),
+                Err(RenewalFailure::NotAllowed)
+            );
+            assert_eq!(
+                gatekeeper.add_update_paid_user(user_id, &get_random_bytes(32)),
+                Err(RenewalFailure::NotAllowed)
+            );
+            assert_eq!(
+                gatekeeper.transfer_subscription(allowed_user_id, user_id, &get_random_bytes(32)),
+                Err(TransferFailure::NotAllowed)
+            );
+            assert!(gatekeeper.get_user_info(user_id).is_none());
+        }
+
+        // The lists can be reloaded from disk
+        fs::write(&allowlist_path, format!("{other_user_id}\n")).unwrap();
+        fs::write(&blocklist_path, "").unwrap();
+        assert_eq!(gatekeeper.reload_access_lists(), Ok((Some(1), 0)));
+        gatekeeper.add_update_user(other_user_id).unwrap();
+        assert_eq!(
+            gatekeeper.add_update_user(blocked_user_id),
+            Err(RenewalFailure::NotAllowed)
+        );
+
+        // The current lists are kept if the new ones are not valid
+        fs::write(&blocklist_path, "not a user id\n").unwrap();
+        assert!(gatekeeper.reload_access_lists().is_err());
+        assert_eq!(
+            gatekeeper.access_lists.lock().unwrap().sizes(),
+            (Some(1), 0)
+        );
+        fs::remove_file(&allowlist_path).unwrap();
+        assert!(gatekeeper.reload_access_lists().is_err());
+        assert!(gatekeeper.is_user_allowed(&other_user_id));
+    }
+
     #[test]
     fn test_add_update_appointment() {
         let gatekeeper = init_gatekeeper(&Blockchain::default().with_height(START_HEIGHT));
@@ -650,6 +1545,81 @@ mod tests {
         assert_eq!(loaded_user.available_slots, updated_slot_count);
     }
 
+    #[test]
+    fn test_transfer_subscription() {
+        let gatekeeper = init_gatekeeper(&Blockchain::default().with_height(START_HEIGHT));
+        let old_user_id = get_random_user_id();
+        let new_user_id = get_random_user_id();
+        let transfer_id = get_random_bytes(32);
+
+        // The user transferring the subscription must be registered
+        assert_eq!(
+            gatekeeper.transfer_subscription(old_user_id, new_user_id, &transfer_id),
+            Err(TransferFailure::UserNotFound)
+        );
+
+        gatekeeper.add_update_user(old_user_id).unwrap();
+        let (uuid, appointment) = generate_dummy_appointment_with_user(old_user_id, None);
+        gatekeeper
+            .add_update_appointment(old_user_id, uuid, &appointment)
+            .unwrap();
+        gatekeeper
+            .dbm
+            .lock()
+            .unwrap()
+            .store_appointment(uuid, &appointment)
+            .unwrap();
+        let (user_info, locators) = gatekeeper.get_user_info(old_user_id).unwrap();
+
+        // The new user cannot be registered
+        let registered_user_id = get_random_user_id();
+        gatekeeper.add_update_user(registered_user_id).unwrap();
+        assert_eq!(
+            gatekeeper.transfer_subscription(old_user_id, registered_user_id, &transfer_id),
+            Err(TransferFailure::AlreadyRegistered)
+        );
+
+        let receipt = gatekeeper
+            .transfer_subscription(old_user_id, new_user_id, &transfer_id)
+            .unwrap();
+        assert_eq!(
+            receipt,
+            RegistrationReceipt::new(
+                new_user_id,
+                user_info.available_slots,
+                user_info.subscription_start,
+                user_info.subscription_expiry
+            )
+        );
+        assert!(gatekeeper.get_user_info(old_user_id).is_none());
+        assert_eq!(
+            gatekeeper.get_user_info(new_user_id),
+            Some((user_info, locators))
+        );
+        assert_eq!(
+            gatekeeper.dbm.lock().unwrap().load_user(new_user_id),
+            Some(user_info)
+        );
+
+        // Even if the old user registers again and the new one is gone, the transfer cannot be replayed
+        gatekeeper.add_update_user(old_user_id).unwrap();
+        gatekeeper
+            .registered_users
+            .lock()
+            .unwrap()
+            .remove(&new_user_id);
+        gatekeeper
+            .dbm
+            .lock()
+            .unwrap()
+            .batch_remove_users(&[new_user_id]);
+        assert_eq!(
+            gatekeeper.transfer_subscription(old_user_id, new_user_id, &transfer_id),
+            Err(TransferFailure::Replayed)
+        );
+        assert!(gatekeeper.get_user_info(new_user_id).is_none());
+    }
+
     #[test]
     fn test_has_subscription_expired() {
         let gatekeeper = init_gatekeeper(&Blockchain::default().with_height(START_HEIGHT));
@@ -683,6 +1653,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sweep_expired_subscriptions() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let gatekeeper = init_gatekeeper(&chain);
+
+        let user_id = get_random_user_id();
+        let another_user_id = get_random_user_id();
+        gatekeeper.add_update_user(user_id).unwrap();
+        gatekeeper.add_update_user(another_user_id).unwrap();
+        let expiry = START_HEIGHT as u32 + DURATION;
+
+        // Nothing is flagged before the subscriptions expire
+        assert!(gatekeeper
+            .sweep_expired_subscriptions(expiry - 1)
+            .is_empty());
+        assert_eq!(gatekeeper.get_expired_users_count(), 0);
+
+        // Once they do, they are flagged only once
+        let expired = gatekeeper.sweep_expired_subscriptions(expiry);
+        assert_eq!(
+            HashSet::<UserId>::from_iter(expired),
+            HashSet::from([user_id, another_user_id])
+        );
+        assert!(gatekeeper
+            .sweep_expired_subscriptions(expiry + 1)
+            .is_empty());
+        assert_eq!(gatekeeper.get_expired_users_count(), 2);
+
+        // Flagged subscriptions are reported as expired straightaway, even if the height has not been updated yet
+        assert_eq!(
+            gatekeeper.has_subscription_expired(user_id),
+            Ok((true, expiry))
+        );
+
+        // Renewing the subscription clears the flag
+        gatekeeper
+            .last_known_block_height
+            .store(expiry, Ordering::Release);
+        gatekeeper.add_update_user(user_id).unwrap();
+        assert_eq!(
+            gatekeeper.has_subscription_expired(user_id),
+            Ok((false, expiry + DURATION))
+        );
+        assert_eq!(gatekeeper.get_expired_users_count(), 1);
+
+        // Reorging back below the expiry un-flags the rest
+        gatekeeper.block_disconnected(&chain.generate(None).header, expiry);
+        assert_eq!(gatekeeper.get_expired_users_count(), 0);
+        assert_eq!(
+            gatekeeper.has_subscription_expired(another_user_id),
+            Ok((false, expiry))
+        );
+    }
+
     #[test]
     fn test_get_outdated_users() {
         let start_height = START_HEIGHT as u32 + EXPIRY_DELTA;
@@ -895,6 +1919,17 @@ mod tests {
             gatekeeper.last_known_block_height.load(Ordering::Relaxed),
             chain.get_block_count()
         );
+
+        // Subscriptions expiring at the new block's height are flagged, and unflagged once outdated
+        let user_id = get_random_user_id();
+        gatekeeper.add_outdated_user(user_id, chain.tip().height + 1 + EXPIRY_DELTA);
+        gatekeeper.block_connected(&chain.generate(None), chain.get_block_count());
+        assert!(gatekeeper.expired_users.lock().unwrap().contains(&user_id));
+
+        gatekeeper.add_outdated_user(user_id, chain.tip().height + 1);
+        gatekeeper.block_connected(&chain.generate(None), chain.get_block_count());
+        assert!(!gatekeeper.expired_users.lock().unwrap().contains(&user_id));
+        assert_eq!(gatekeeper.get_expired_users_count(), 0);
     }
 
     #[test]
@@ -7,17 +7,22 @@
 pub mod protos {
     tonic::include_proto!("teos.v2");
 }
+pub mod anchors;
 pub mod api;
 pub mod bitcoin_cli;
 pub mod carrier;
 pub mod chain_monitor;
 pub mod cli_config;
 pub mod config;
+pub mod confirmations;
 pub mod dbm;
 #[doc(hidden)]
 mod errors;
+pub mod esplora;
 mod extended_appointment;
 pub mod gatekeeper;
+mod locator_filter;
+pub mod payments;
 pub mod responder;
 #[doc(hidden)]
 mod rpc_errors;
@@ -0,0 +1,162 @@
+//! Logic related to the locator filter, a probabilistic index over the locators of the stored appointments.
+//!
+//! Most transactions in a block do not trigger any appointment, so checking a cheap in-memory filter before hitting
+//! the database saves most of the lookups on every block. The filter may return false positives but never false
+//! negatives, so anything matching it still needs to be checked against the database.
+
+use teos_common::appointment::Locator;
+
+/// The false positive rate the filter is sized for.
+const FP_RATE: f64 = 0.01;
+/// The minimum number of items the filter is sized for.
+const MIN_CAPACITY: usize = 1024;
+
+/// Bloom filter over appointment [Locator]s.
+///
+/// Bloom filters do not support deletion, so removed locators keep matching (as false positives) until the filter is
+/// rebuilt. The filter keeps track of how many of its items are stale so the owner knows when a rebuild is worth it.
+#[derive(Debug)]
+pub(crate) struct LocatorFilter {
+    /// The filter bitmap.
+    bits: Vec<u64>,
+    /// The number of bits set per item.
+    num_hashes: u64,
+    /// The number of items the filter was sized for.
+    capacity: usize,
+    /// The number of items added to the filter.
+    items: usize,
+    /// The number of items removed from the backing store since the filter was built.
+    stale: usize,
+}
+
+impl LocatorFilter {
+    /// Creates an empty [LocatorFilter] sized to hold `capacity` items at [FP_RATE].
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(MIN_CAPACITY);
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-(capacity as f64) * FP_RATE.ln() / (ln2 * ln2)).ceil() as usize;
+        let num_hashes = ((num_bits as f64 / capacity as f64) * ln2).round().max(1.0) as u64;
+
+        LocatorFilter {
+            bits: vec![0; num_bits.div_ceil(64)],
+            num_hashes,
+            capacity,
+            items: 0,
+            stale: 0,
+        }
+    }
+
+    /// Builds a [LocatorFilter] containing the given locators.
+    ///
+    /// The filter is sized with enough room for the current set to double before needing to be rebuilt.
+    pub(crate) fn from_locators(locators: Vec<Locator>) -> Self {
+        let mut filter = LocatorFilter::new(locators.len() * 2);
+        for locator in locators.iter() {
+            filter.insert(locator);
+        }
+        filter
+    }
+
+    /// Computes the bit positions for a given locator.
+    ///
+    /// Locators are derived from transaction ids, so their bytes are already uniformly distributed and can be used
+    /// straightaway for double hashing.
+    fn positions(&self, locator: &Locator) -> impl Iterator<Item = usize> {
+        let bytes = locator.as_ref();
+        let h1 = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) | 1;
+        let num_bits = (self.bits.len() * 64) as u64;
+
+        (0..self.num_hashes).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+
+    /// Adds a locator to the filter.
+    pub(crate) fn insert(&mut self, locator: &Locator) {
+        for pos in self.positions(locator).collect::<Vec<_>>() {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+        self.items += 1;
+    }
+
+    /// Checks whether a locator may be in the filter.
+    pub(crate) fn contains(&self, locator: &Locator) -> bool {
+        self.positions(locator)
+            .all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+
+    /// Records that `count` items have been removed from the backing store.
+    pub(crate) fn mark_removed(&mut self, count: usize) {
+        self.stale = (self.stale + count).min(self.items);
+    }
+
+    /// Whether the filter should be rebuilt, either because it is over capacity (so the false positive rate is above
+    /// target) or because most of its items have already been removed.
+    pub(crate) fn needs_rebuild(&self) -> bool {
+        self.items > self.capacity || self.stale * 2 > self.items
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use teos_common::test_utils::get_random_locator;
+
+    #[test]
+    fn test_insert_contains() {
+        let mut filter = LocatorFilter::new(0);
+        let locators: Vec<Locator> = (0..100).map(|_| get_random_locator()).collect();
+
+        for locator in locators.iter() {
+            assert!(!filter.contains(locator));
+            filter.insert(locator);
+            assert!(filter.contains(locator));
+        }
+
+        // No false negatives
+        assert!(locators.iter().all(|l| filter.contains(l)));
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let capacity = 10_000;
+        let mut filter =
+            LocatorFilter::from_locators((0..capacity / 2).map(|_| get_random_locator()).collect());
+
+        // Once at capacity, the false positive rate should be around FP_RATE. Leave some margin to prevent flakiness.
+        for _ in 0..capacity / 2 {
+            filter.insert(&get_random_locator());
+        }
+        let false_positives = (0..capacity)
+            .filter(|_| filter.contains(&get_random_locator()))
+            .count();
+        assert!((false_positives as f64 / capacity as f64) < FP_RATE * 3.0);
+    }
+
+    #[test]
+    fn test_needs_rebuild() {
+        let mut filter = LocatorFilter::from_locators(
+            (0..MIN_CAPACITY / 2)
+                .map(|_| get_random_locator())
+                .collect(),
+        );
+        assert!(!filter.needs_rebuild());
+
+        // Filling the filter over its capacity requires a rebuild
+        for _ in 0..=MIN_CAPACITY / 2 {
+            filter.insert(&get_random_locator());
+        }
+        assert!(filter.needs_rebuild());
+
+        // So does having most of the items removed
+        let mut filter = LocatorFilter::from_locators(
+            (0..MIN_CAPACITY / 2)
+                .map(|_| get_random_locator())
+                .collect(),
+        );
+        filter.mark_removed(MIN_CAPACITY / 4);
+        assert!(!filter.needs_rebuild());
+        filter.mark_removed(1);
+        assert!(filter.needs_rebuild());
+    }
+}
@@ -2,7 +2,7 @@ use log::LevelFilter;
 use simple_logger::SimpleLogger;
 use std::fs;
 use std::io::ErrorKind;
-use std::ops::{Deref, DerefMut};
+use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::{Arc, Condvar, Mutex};
 use structopt::StructOpt;
@@ -11,25 +11,27 @@ use tonic::transport::{Certificate, Server, ServerTlsConfig};
 
 use bitcoin::network::constants::Network;
 use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
-use bitcoincore_rpc::{Auth, Client, RpcApi};
+use bitcoincore_rpc::{Auth, RpcApi};
 use lightning_block_sync::init::validate_best_block_header;
-use lightning_block_sync::poll::{
-    ChainPoller, Poll, Validate, ValidatedBlock, ValidatedBlockHeader,
-};
-use lightning_block_sync::{BlockSource, BlockSourceError, SpvClient, UnboundedCache};
+use lightning_block_sync::poll::{ChainPoller, Validate};
+use lightning_block_sync::{BlockSource, SpvClient, UnboundedCache};
 
+use teos::anchors::AnchorMaterial;
 use teos::api::internal::InternalAPI;
 use teos::api::{http, tor::TorAPI};
 use teos::bitcoin_cli::BitcoindClient;
-use teos::carrier::Carrier;
-use teos::chain_monitor::ChainMonitor;
+use teos::carrier::{self, Carrier};
+use teos::chain_monitor::{get_last_n_blocks, ChainMonitor};
 use teos::config::{self, AuthMethod, Config, Opt};
+use teos::confirmations::{BitcoindConfirmer, CorroborationPolicy, Corroborator};
 use teos::dbm::DBM;
-use teos::gatekeeper::Gatekeeper;
+use teos::esplora::EsploraClient;
+use teos::gatekeeper::{Gatekeeper, UserAccessLists};
+use teos::payments::ClnPaymentVerifier;
 use teos::protos as msgs;
 use teos::protos::private_tower_services_server::PrivateTowerServicesServer;
 use teos::protos::public_tower_services_server::PublicTowerServicesServer;
-use teos::responder::Responder;
+use teos::responder::{LapsedSubscriptionPolicy, Responder, UnconstructablePenaltyPolicy};
 use teos::tls::tls_init;
 use teos::watcher::Watcher;
 
@@ -37,26 +39,6 @@ use teos_common::constants::IRREVOCABLY_RESOLVED;
 use teos_common::cryptography::get_random_keypair;
 use teos_common::TowerId;
 
-async fn get_last_n_blocks<B, T>(
-    poller: &mut ChainPoller<B, T>,
-    mut last_known_block: ValidatedBlockHeader,
-    n: usize,
-) -> Result<Vec<ValidatedBlock>, BlockSourceError>
-where
-    B: DerefMut<Target = T> + Sized + Send + Sync,
-    T: BlockSource,
-{
-    let mut last_n_blocks = Vec::with_capacity(n);
-    for _ in 0..n {
-        log::debug!("Fetching block #{}", last_known_block.height);
-        let block = poller.fetch_block(&last_known_block).await?;
-        last_known_block = poller.look_up_previous_header(&last_known_block).await?;
-        last_n_blocks.push(block);
-    }
-
-    Ok(last_n_blocks)
-}
-
 fn create_new_tower_keypair(db: &DBM) -> (SecretKey, PublicKey) {
     let (sk, pk) = get_random_keypair();
     db.store_tower_key(&sk).unwrap();
@@ -82,6 +64,7 @@ async fn main() {
         eprintln!("{e}");
         std::process::exit(1);
     });
+    let reward_script = conf.reward_script();
 
     // Set log level
     SimpleLogger::new()
@@ -122,9 +105,11 @@ async fn main() {
         conf.log_non_default_options();
     }
 
-    let dbm = Arc::new(Mutex::new(
-        DBM::new(path_network.join("teos_db.sql3")).unwrap(),
-    ));
+    let dbm = DBM::with_shards(path_network.join("teos_db.sql3"), conf.db_shards as usize).unwrap();
+    if conf.locator_filter {
+        dbm.enable_locator_filter();
+    }
+    let dbm = Arc::new(Mutex::new(dbm));
 
     // Load tower secret key or create a fresh one if none is found. If overwrite key is set, create a new
     // key straightaway
@@ -142,6 +127,28 @@ async fn main() {
     };
     log::info!("tower_id: {tower_pk}");
 
+    // Anchors are spent with a key of their own, so on-chain outputs cannot be linked to the tower identity
+    let anchor_material = conf.anchor_cpfp.then(|| {
+        let locked_db = dbm.lock().unwrap();
+        let anchor_sk = locked_db.load_anchor_key().unwrap_or_else(|| {
+            log::info!("Anchor key not found. Creating a fresh one");
+            let (sk, _) = get_random_keypair();
+            locked_db.store_anchor_key(&sk).unwrap();
+            sk
+        });
+        log::info!(
+            "anchor_key: {}",
+            PublicKey::from_secret_key(&Secp256k1::new(), &anchor_sk)
+        );
+        AnchorMaterial::new(anchor_sk)
+    });
+
+    // Penalties can only be manually triggered on mainnet if explicitly allowed
+    let penalty_triggers = conf.penalty_triggers_allowed();
+    if penalty_triggers && conf.btc_network == "main" {
+        log::warn!("Manual penalty triggers are enabled on mainnet");
+    }
+
     let btc_rpc_auth = match conf.get_auth_method() {
         AuthMethod::CookieFile => {
             Auth::CookieFile(config::data_dir_absolute_path(conf.btc_rpc_cookie))
@@ -152,6 +159,7 @@ async fn main() {
     };
 
     // Initialize our bitcoind client
+    let btc_rpc_timeout = std::time::Duration::from_secs(conf.btc_rpc_timeout as u64);
     let (bitcoin_cli, bitcoind_reachable) = match BitcoindClient::new(
         &conf.btc_rpc_connect,
         conf.btc_rpc_port,
@@ -161,7 +169,7 @@ async fn main() {
     .await
     {
         Ok(client) => (
-            Arc::new(client),
+            Arc::new(client.with_rpc_timeout(btc_rpc_timeout, conf.btc_rpc_retries)),
             Arc::new((Mutex::new(true), Condvar::new())),
         ),
         Err(e) => {
@@ -182,12 +190,36 @@ async fn main() {
         ""
     };
     let rpc = Arc::new(
-        Client::new(
+        carrier::new_rpc_client(
             &format!("{schema}{}:{}", conf.btc_rpc_connect, conf.btc_rpc_port),
             btc_rpc_auth,
+            btc_rpc_timeout,
         )
         .unwrap(),
     );
+    // The chain reported by bitcoind is not the actual tip while on its initial block download, so wait it out.
+    let polling_delta = std::time::Duration::from_secs(conf.polling_delta as u64);
+    let bitcoind_syncing = Arc::new(Mutex::new(false));
+    let mut ibd_logged = false;
+    loop {
+        match bitcoin_cli.is_initial_block_download().await {
+            Ok(false) => break,
+            Ok(true) => {
+                if !ibd_logged {
+                    log::info!(
+                        "bitcoind is syncing (initial block download). Waiting for it to finish"
+                    );
+                    ibd_logged = true;
+                }
+                tokio::time::sleep(polling_delta).await;
+            }
+            Err(e) => {
+                log::error!("Failed to check whether bitcoind is syncing. Error: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+
     let mut derefed = bitcoin_cli.deref();
     // Load last known block from DB if found. Poll it from Bitcoind otherwise.
     let last_known_block = dbm.lock().unwrap().load_last_known_block();
@@ -267,44 +299,133 @@ async fn main() {
     };
 
     // Build components
-    let gatekeeper = Arc::new(Gatekeeper::new(
+    let mut gatekeeper = Gatekeeper::new(
         tip.height,
         conf.subscription_slots,
         conf.subscription_duration,
         conf.expiry_delta,
+        conf.renewal_window,
+        conf.max_expiry_horizon,
         dbm.clone(),
-    ));
+    );
+    if !conf.cln_rpc_path.is_empty() {
+        log::info!(
+            "Registrations require a payment of {} msat",
+            conf.subscription_price_msat
+        );
+        gatekeeper = gatekeeper.with_payment_verifier(Arc::new(ClnPaymentVerifier::new(
+            config::data_dir_absolute_path(conf.cln_rpc_path.clone()),
+            conf.subscription_price_msat,
+        )));
+    }
+    if !conf.user_allowlist.is_empty() || !conf.user_blocklist.is_empty() {
+        let path = |p: &String| (!p.is_empty()).then(|| config::data_dir_absolute_path(p.clone()));
+        let access_lists =
+            UserAccessLists::new(path(&conf.user_allowlist), path(&conf.user_blocklist))
+                .unwrap_or_else(|e| {
+                    log::error!("Cannot load the user lists. {e}");
+                    std::process::exit(1);
+                });
+        let (allowed_users, blocked_users) = access_lists.sizes();
+        match allowed_users {
+            Some(n) => {
+                log::info!("Registrations restricted to {n} users ({blocked_users} blocked)")
+            }
+            None => log::info!("{blocked_users} users blocked from registering"),
+        }
+        gatekeeper = gatekeeper.with_access_lists(access_lists);
+    }
+    let gatekeeper = Arc::new(gatekeeper);
 
     let mut poller = ChainPoller::new(&mut derefed, Network::from_str(btc_network).unwrap());
     let (responder, watcher) = {
         let last_n_blocks = get_last_n_blocks(&mut poller, tip, IRREVOCABLY_RESOLVED as usize)
             .await.unwrap_or_else(|e| {
-                // I'm pretty sure this can only happen if we are pulling blocks from the target to the prune height, and by the time we get to
-                // the end at least one has been pruned.
                 log::error!("Couldn't load the latest {IRREVOCABLY_RESOLVED} blocks. Please try again (Error: {})", e.into_inner());
                 std::process::exit(1);
             }
         );
 
-        let responder = Arc::new(Responder::new(
+        let new_esplora_client = |url: &str| {
+            EsploraClient::new(url).unwrap_or_else(|e| {
+                log::error!("Couldn't create a client for {url}. Error: {e}");
+                std::process::exit(1);
+            })
+        };
+        let broadcast_fallback = (!conf.btc_broadcast_fallback.is_empty())
+            .then(|| new_esplora_client(&conf.btc_broadcast_fallback));
+        let confirmation_source =
+            (!conf.btc_confirmation_source.is_empty()).then(|| match &broadcast_fallback {
+                // A single HTTP client is shared by both
+                Some(esplora) => esplora.with_url(&conf.btc_confirmation_source),
+                None => new_esplora_client(&conf.btc_confirmation_source),
+            });
+
+        let mut responder = Responder::new(
             &last_n_blocks,
             tip.height,
-            Carrier::new(rpc, bitcoind_reachable.clone(), tip.height),
+            Carrier::new(rpc.clone(), bitcoind_reachable.clone(), tip.height)
+                .with_rpc_retries(conf.btc_rpc_retries)
+                .with_broadcast_fallback(broadcast_fallback),
             gatekeeper.clone(),
             dbm.clone(),
-        ));
-        let watcher = Arc::new(Watcher::new(
-            gatekeeper.clone(),
-            responder.clone(),
-            &last_n_blocks[0..6],
-            tip.height,
-            tower_sk,
-            TowerId(tower_pk),
-            dbm.clone(),
-        ));
+            anchor_material,
+            conf.min_penalty_value,
+        )
+        .with_max_broadcast_delay(conf.max_penalty_broadcast_delay)
+        .with_lapsed_policy(if conf.decline_lapsed_penalties {
+            LapsedSubscriptionPolicy::Decline
+        } else {
+            LapsedSubscriptionPolicy::Respond
+        })
+        .with_unconstructable_policy(if conf.recover_unconstructable_penalties {
+            UnconstructablePenaltyPolicy::Recover
+        } else {
+            UnconstructablePenaltyPolicy::Alert
+        });
+        if let Some(reward_script) = reward_script {
+            responder = responder.with_reward_script(reward_script);
+        }
+        if let Some(confirmation_source) = confirmation_source {
+            let corroborator = Arc::new(Corroborator::new(
+                Arc::new(BitcoindConfirmer::new(rpc)),
+                Arc::new(confirmation_source),
+                if conf.lenient_confirmation_source {
+                    CorroborationPolicy::Warn
+                } else {
+                    CorroborationPolicy::Require
+                },
+            ));
+            responder = responder.with_corroborator(corroborator.clone());
+            task::spawn(async move { corroborator.run().await });
+        }
+        // Fee estimates are refreshed on every block, so get them ready for the requests received before the next one
+        responder.update_fee_estimates();
+        let responder = Arc::new(responder);
+        let watcher = Arc::new(
+            Watcher::new(
+                gatekeeper.clone(),
+                responder.clone(),
+                &last_n_blocks[0..last_n_blocks.len().min(6)],
+                tip.height,
+                tower_sk,
+                TowerId(tower_pk),
+                dbm.clone(),
+            )
+            .with_max_db_size(conf.max_db_size.saturating_mul(1024 * 1024))
+            .with_deletion_refunds(conf.refund_deleted_appointments),
+        );
         (responder, watcher)
     };
 
+    // The data is only checked here. Discrepancies can be repaired using the check_integrity RPC.
+    let integrity_issues = watcher.report_integrity_issues();
+    if integrity_issues > 0 {
+        log::warn!(
+            "Found {integrity_issues} integrity issue(s) in the database. Run `teos-cli checkintegrity --repair` to fix them"
+        );
+    }
+
     if watcher.is_fresh() & responder.is_fresh() & gatekeeper.is_fresh() {
         log::info!("Fresh bootstrap");
     } else {
@@ -326,11 +447,15 @@ async fn main() {
         spv_client,
         tip,
         dbm,
-        conf.polling_delta,
+        polling_delta,
         shutdown_signal_cm,
         bitcoind_reachable.clone(),
     )
-    .await;
+    .await
+    .with_sync_source(bitcoin_cli.deref(), bitcoind_syncing.clone());
+    if !conf.btc_zmq_block.is_empty() {
+        chain_monitor = chain_monitor.with_zmq(conf.btc_zmq_block.clone());
+    }
 
     // Get all the components up to date if there's a backlog of blocks
     chain_monitor.poll_best_tip().await;
@@ -364,12 +489,16 @@ async fn main() {
         None
     };
 
-    let internal_api = Arc::new(InternalAPI::new(
-        watcher,
-        addresses,
-        bitcoind_reachable.clone(),
-        shutdown_trigger,
-    ));
+    let internal_api = Arc::new(
+        InternalAPI::new(
+            watcher,
+            addresses,
+            bitcoind_reachable.clone(),
+            shutdown_trigger,
+        )
+        .with_penalty_triggers(penalty_triggers)
+        .with_bitcoind_syncing(bitcoind_syncing),
+    );
     let internal_api_cloned = internal_api.clone();
 
     let rpc_api_addr = format!("{}:{}", conf.rpc_bind, conf.rpc_port)
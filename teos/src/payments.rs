@@ -0,0 +1,408 @@
+//! Logic related to gating registrations behind Lightning payments.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+use bitcoin::hashes::sha256;
+
+use teos_common::UserId;
+
+/// Time to wait for the Lightning node to answer before giving up on it.
+const RPC_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Error raised if the Lightning node payments are verified against cannot be queried.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PaymentError(pub String);
+
+impl fmt::Display for PaymentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Payment verification error: {}", self.0)
+    }
+}
+
+impl std::error::Error for PaymentError {}
+
+/// Issues the invoices users need to pay to get a subscription, and checks whether they have been paid.
+///
+/// Verification is done through the tower's own Lightning node, so users only need to prove they know the preimage
+/// of a paid invoice.
+pub trait PaymentVerifier: Send + Sync + fmt::Debug {
+    /// Creates a BOLT11 invoice for a subscription of the given user. An invoice already issued to the user may be
+    /// handed again instead, as long as it is still waiting to be paid.
+    fn create_invoice(&self, user_id: UserId) -> Result<String, PaymentError>;
+    /// Whether the invoice with the given payment hash has been issued by the tower to the given user, and paid in full.
+    fn is_paid(&self, user_id: UserId, payment_hash: &sha256::Hash) -> Result<bool, PaymentError>;
+}
+
+/// An invoice issued to a user that may still be pending payment.
+#[derive(Debug, Clone)]
+struct PendingInvoice {
+    /// The label the invoice was created with.
+    label: String,
+    /// The BOLT11 encoded invoice.
+    bolt11: String,
+    /// When the invoice expires, as a UNIX timestamp.
+    expires_at: u64,
+}
+
+/// [PaymentVerifier] backed by a CoreLN node, reached through its RPC socket.
+#[derive(Debug)]
+pub struct ClnPaymentVerifier {
+    /// Path to the `lightning-rpc` socket of the node.
+    rpc_path: PathBuf,
+    /// Price of a subscription, in millisatoshis.
+    amount_msat: u64,
+    /// The last invoice issued to each user, so it can be handed again instead of creating a new one every time the
+    /// user asks to register before paying. Entries are dropped once their invoice expires.
+    ///
+    /// This is not persisted. If the tower is restarted, users that ask again are issued a new invoice.
+    pending_invoices: Mutex<HashMap<UserId, PendingInvoice>>,
+}
+
+impl ClnPaymentVerifier {
+    /// Creates a new [ClnPaymentVerifier] instance.
+    pub fn new(rpc_path: PathBuf, amount_msat: u64) -> Self {
+        Self {
+            rpc_path,
+            amount_msat,
+            pending_invoices: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Gets the invoice last issued to the given user, as long as it is still waiting to be paid.
+    fn get_pending_invoice(&self, user_id: UserId) -> Result<Option<String>, PaymentError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let pending = {
+            let mut pending_invoices = self.pending_invoices.lock().unwrap();
+            pending_invoices.retain(|_, invoice| invoice.expires_at > now);
+            match pending_invoices.get(&user_id) {
+                Some(invoice) => invoice.clone(),
+                None => return Ok(None),
+            }
+        };
+
+        // The invoice may have been paid (or deleted) in the meantime
+        let result = self.call("listinvoices", json!({ "label": pending.label }))?;
+        let is_unpaid = result["invoices"].as_array().is_some_and(|invoices| {
+            invoices
+                .iter()
+                .any(|i| i["status"] == "unpaid" && i["amount_msat"] == self.amount_msat)
+        });
+        if is_unpaid {
+            Ok(Some(pending.bolt11))
+        } else {
+            self.pending_invoices.lock().unwrap().remove(&user_id);
+            Ok(None)
+        }
+    }
+
+    /// Sends a JSON-RPC request to the node and returns the result.
+    fn call(&self, method: &str, params: Value) -> Result<Value, PaymentError> {
+        let stream = UnixStream::connect(&self.rpc_path).map_err(|e| {
+            PaymentError(format!(
+                "Cannot connect to {}. Error: {e}",
+                self.rpc_path.display()
+            ))
+        })?;
+        stream
+            .set_read_timeout(Some(RPC_TIMEOUT))
+            .map_err(|e| PaymentError(e.to_string()))?;
+
+        serde_json::to_writer(
+            &stream,
+            &json!({"jsonrpc": "2.0", "id": 0, "method": method, "params": params}),
+        )
+        .map_err(|e| PaymentError(format!("Cannot send {method} request. Error: {e}")))?;
+
+        // The node does not close the connection after replying, so only the first value is read
+        let mut response = serde_json::Deserializer::from_reader(&stream)
+            .into_iter::<Value>()
+            .next()
+            .ok_or_else(|| PaymentError(format!("No response received for {method}")))?
+            .map_err(|e| PaymentError(format!("Cannot decode {method} response. Error: {e}")))?;
+
+        if let Some(error) = response.get("error") {
+            return Err(PaymentError(format!("{method} failed. Error: {error}")));
+        }
+        response
+            .get_mut("result")
+            .map(Value::take)
+            .ok_or_else(|| PaymentError(format!("Unexpected {method} response: {response}")))
+    }
+}
+
+/// Prefix of the labels of the invoices issued to a given user.
+fn label_prefix(user_id: UserId) -> String {
+    format!("teos-{user_id}-")
+}
+
+impl PaymentVerifier for ClnPaymentVerifier {
+    fn create_invoice(&self, user_id: UserId) -> Result<String, PaymentError> {
+        if let Some(bolt11) = self.get_pending_invoice(user_id)? {
+            return Ok(bolt11);
+        }
+
+        // Labels must be unique, and a user may request more than one invoice (e.g. to renew their subscription)
+        let nonce = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let label = format!("{}{nonce}", label_prefix(user_id));
+        let result = self.call(
+            "invoice",
+            json!({
+                "amount_msat": self.amount_msat,
+                "label": label,
+                "description": format!("Watchtower subscription for {user_id}"),
+            }),
+        )?;
+
+        let bolt11 = result["bolt11"]
+            .as_str()
+            .map(|invoice| invoice.to_owned())
+            .ok_or_else(|| PaymentError(format!("Unexpected invoice response: {result}")))?;
+        if let Some(expires_at) = result["expires_at"].as_u64() {
+            self.pending_invoices.lock().unwrap().insert(
+                user_id,
+                PendingInvoice {
+                    label,
+                    bolt11: bolt11.clone(),
+                    expires_at,
+                },
+            );
+        }
+
+        Ok(bolt11)
+    }
+
+    fn is_paid(&self, user_id: UserId, payment_hash: &sha256::Hash) -> Result<bool, PaymentError> {
+        let result = self.call(
+            "listinvoices",
+            json!({ "payment_hash": payment_hash.to_string() }),
+        )?;
+
+        // The node may have issued invoices for other purposes, or for a different price, so only the ones issued
+        // by the tower to this very user, and for at least the current price, are accepted
+        let label_prefix = label_prefix(user_id);
+        Ok(result["invoices"].as_array().is_some_and(|invoices| {
+            invoices.iter().any(|i| {
+                i["status"] == "paid"
+                    && i["label"]
+                        .as_str()
+                        .is_some_and(|label| label.starts_with(&label_prefix))
+                    && i["amount_msat"]
+                        .as_u64()
+                        .is_some_and(|amount| amount >= self.amount_msat)
+            })
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    use bitcoin::hashes::Hash;
+    use tempdir::TempDir;
+
+    use teos_common::test_utils::get_random_user_id;
+
+    /// Serves a single connection with the given response, returning the request that was received.
+    fn serve_once(listener: UnixListener, response: Value) -> thread::JoinHandle<Value> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let request = serde_json::Deserializer::from_reader(&stream)
+                .into_iter::<Value>()
+                .next()
+                .unwrap()
+                .unwrap();
+            stream.write_all(response.to_string().as_bytes()).unwrap();
+            request
+        })
+    }
+
+    #[test]
+    fn test_create_invoice() {
+        let tmp_dir = TempDir::new("teos_payments").unwrap();
+        let rpc_path = tmp_dir.path().join("lightning-rpc");
+        let verifier = ClnPaymentVerifier::new(rpc_path.clone(), 42000);
+
+        let server = serve_once(
+            UnixListener::bind(&rpc_path).unwrap(),
+            json!({"jsonrpc": "2.0", "id": 0, "result": {"bolt11": "lnbcrt420n1invoice"}}),
+        );
+        let user_id = get_random_user_id();
+        assert_eq!(
+            verifier.create_invoice(user_id),
+            Ok("lnbcrt420n1invoice".to_owned())
+        );
+
+        let request = server.join().unwrap();
+        assert_eq!(request["method"], "invoice");
+        assert_eq!(request["params"]["amount_msat"], 42000);
+        assert!(request["params"]["label"]
+            .as_str()
+            .unwrap()
+            .contains(&user_id.to_string()));
+    }
+
+    /// Serves a connection per given response, in order, returning the requests that were received.
+    fn serve(listener: UnixListener, responses: Vec<Value>) -> thread::JoinHandle<Vec<Value>> {
+        thread::spawn(move || {
+            responses
+                .into_iter()
+                .map(|response| {
+                    let (mut stream, _) = listener.accept().unwrap();
+                    let request = serde_json::Deserializer::from_reader(&stream)
+                        .into_iter::<Value>()
+                        .next()
+                        .unwrap()
+                        .unwrap();
+                    stream.write_all(response.to_string().as_bytes()).unwrap();
+                    request
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_create_invoice_pending() {
+        let tmp_dir = TempDir::new("teos_payments").unwrap();
+        let rpc_path = tmp_dir.path().join("lightning-rpc");
+        let verifier = ClnPaymentVerifier::new(rpc_path.clone(), 42000);
+        let user_id = get_random_user_id();
+
+        let expires_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 3600;
+        let invoice_response = |bolt11: &str| json!({"jsonrpc": "2.0", "id": 0, "result": {"bolt11": bolt11, "expires_at": expires_at}});
+        let listinvoices_response = |status: &str| json!({"jsonrpc": "2.0", "id": 0, "result": {"invoices": [{"status": status, "amount_msat": 42000}]}});
+
+        // The invoice issued to a user is handed again while unpaid, and a new one is created once paid
+        let server = serve(
+            UnixListener::bind(&rpc_path).unwrap(),
+            vec![
+                invoice_response("lnbcrt420n1first"),
+                listinvoices_response("unpaid"),
+                listinvoices_response("paid"),
+                invoice_response("lnbcrt420n1second"),
+            ],
+        );
+        assert_eq!(
+            verifier.create_invoice(user_id),
+            Ok("lnbcrt420n1first".to_owned())
+        );
+        assert_eq!(
+            verifier.create_invoice(user_id),
+            Ok("lnbcrt420n1first".to_owned())
+        );
+        assert_eq!(
+            verifier.create_invoice(user_id),
+            Ok("lnbcrt420n1second".to_owned())
+        );
+        let requests = server.join().unwrap();
+        assert_eq!(
+            requests.iter().map(|r| &r["method"]).collect::<Vec<_>>(),
+            ["invoice", "listinvoices", "listinvoices", "invoice"]
+        );
+        assert_eq!(
+            requests[1]["params"]["label"],
+            requests[0]["params"]["label"]
+        );
+        std::fs::remove_file(&rpc_path).unwrap();
+
+        // Expired invoices are forgotten without even asking the node about them
+        verifier
+            .pending_invoices
+            .lock()
+            .unwrap()
+            .get_mut(&user_id)
+            .unwrap()
+            .expires_at = 0;
+        let server = serve(
+            UnixListener::bind(&rpc_path).unwrap(),
+            vec![invoice_response("lnbcrt420n1third")],
+        );
+        assert_eq!(
+            verifier.create_invoice(user_id),
+            Ok("lnbcrt420n1third".to_owned())
+        );
+        assert_eq!(server.join().unwrap()[0]["method"], "invoice");
+    }
+
+    #[test]
+    fn test_is_paid() {
+        let tmp_dir = TempDir::new("teos_payments").unwrap();
+        let rpc_path = tmp_dir.path().join("lightning-rpc");
+        let verifier = ClnPaymentVerifier::new(rpc_path.clone(), 42000);
+        let payment_hash = sha256::Hash::hash(&[0; 32]);
+        let user_id = get_random_user_id();
+        let label = format!("teos-{user_id}-0");
+
+        for (status, paid) in [("paid", true), ("unpaid", false), ("expired", false)] {
+            let server = serve_once(
+                UnixListener::bind(&rpc_path).unwrap(),
+                json!({"jsonrpc": "2.0", "id": 0, "result": {"invoices": [
+                    {"status": status, "label": label, "amount_msat": 42000}
+                ]}}),
+            );
+            assert_eq!(verifier.is_paid(user_id, &payment_hash), Ok(paid));
+            let request = server.join().unwrap();
+            assert_eq!(request["method"], "listinvoices");
+            assert_eq!(request["params"]["payment_hash"], payment_hash.to_string());
+            std::fs::remove_file(&rpc_path).unwrap();
+        }
+
+        // Paid invoices are not accepted if they were issued to someone else, for something else, or for less than
+        // the subscription price
+        for invoice in [
+            json!({"status": "paid", "label": format!("teos-{}-0", get_random_user_id()), "amount_msat": 42000}),
+            json!({"status": "paid", "label": "coffee", "amount_msat": 42000}),
+            json!({"status": "paid", "amount_msat": 42000}),
+            json!({"status": "paid", "label": label, "amount_msat": 41999}),
+            json!({"status": "paid", "label": label}),
+        ] {
+            let server = serve_once(
+                UnixListener::bind(&rpc_path).unwrap(),
+                json!({"jsonrpc": "2.0", "id": 0, "result": {"invoices": [invoice]}}),
+            );
+            assert_eq!(verifier.is_paid(user_id, &payment_hash), Ok(false));
+            server.join().unwrap();
+            std::fs::remove_file(&rpc_path).unwrap();
+        }
+
+        // Unknown invoices are not paid
+        let server = serve_once(
+            UnixListener::bind(&rpc_path).unwrap(),
+            json!({"jsonrpc": "2.0", "id": 0, "result": {"invoices": []}}),
+        );
+        assert_eq!(verifier.is_paid(user_id, &payment_hash), Ok(false));
+        server.join().unwrap();
+        std::fs::remove_file(&rpc_path).unwrap();
+
+        // Errors from the node are reported, and so is not being able to reach it
+        let server = serve_once(
+            UnixListener::bind(&rpc_path).unwrap(),
+            json!({"jsonrpc": "2.0", "id": 0, "error": {"code": -32602, "message": "Invalid hash"}}),
+        );
+        assert!(verifier.is_paid(user_id, &payment_hash).is_err());
+        server.join().unwrap();
+        std::fs::remove_file(&rpc_path).unwrap();
+        assert!(verifier.is_paid(user_id, &payment_hash).is_err());
+    }
+}
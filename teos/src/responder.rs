@@ -1,26 +1,45 @@
 //! Logic related to the Responder, the components in charge of making sure breaches get properly punished.
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use bitcoin::blockdata::script::Instruction;
+use bitcoin::hashes::Hash;
 use bitcoin::{consensus, BlockHash};
-use bitcoin::{BlockHeader, Transaction, Txid};
+use bitcoin::{BlockHeader, OutPoint, Script, Transaction, TxOut, Txid};
+use bitcoin::{EcdsaSig, EcdsaSighashType};
 use lightning::chain;
 use lightning_block_sync::poll::ValidatedBlock;
+use rand::Rng;
+use tokio::sync::broadcast;
 
+use teos_common::appointment::Locator;
 use teos_common::constants;
+use teos_common::cryptography::{self, DecryptingError};
 use teos_common::protos as common_msgs;
 use teos_common::UserId;
 
+use crate::anchors::{self, AnchorMaterial};
 use crate::carrier::Carrier;
+use crate::confirmations::Corroborator;
 use crate::dbm::DBM;
 use crate::extended_appointment::UUID;
 use crate::gatekeeper::Gatekeeper;
+use crate::protos as msgs;
 use crate::tx_index::TxIndex;
 use crate::watcher::Breach;
+use crate::{errors, rpc_errors};
 
 /// Number of missed confirmations to wait before rebroadcasting a transaction.
 const CONFIRMATIONS_BEFORE_RETRY: u8 = 6;
+/// Confirmation target used to estimate the feerate of fee-bumped (CPFP) penalties.
+const CPFP_CONFIRMATION_TARGET: u16 = 2;
+/// Number of [ResponderEvent]s kept for subscribers that are lagging behind. Older ones are dropped.
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+/// Confirmation targets the feerate estimates served to users are refreshed for on every block.
+const FEE_ESTIMATE_TARGETS: [u16; 10] = [1, 2, 3, 6, 12, 24, 48, 144, 504, 1008];
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// The confirmation status of a given penalty transaction.
@@ -116,6 +135,268 @@ impl PenaltySummary {
     }
 }
 
+/// How exposed the penalties tracked by the [Responder] are to reorgs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct ReorgExposure {
+    /// Number of penalties that are confirmed but not irrevocably resolved yet.
+    pub unresolved_penalties: usize,
+    /// Confirmation count of the shallowest confirmed penalty, if any. A penalty in the chain tip has one confirmation.
+    pub min_confirmations: Option<u32>,
+}
+
+/// The status of a penalty in the penalty ledger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PenaltyStatus {
+    /// The penalty was accepted by the network but has not confirmed yet.
+    Broadcast,
+    /// The penalty has been confirmed, but not irrevocably resolved yet.
+    Confirmed,
+    /// The penalty has been irrevocably resolved.
+    Resolved,
+    /// The penalty was rejected by the network after being accepted (e.g. due to a reorg) and was given up on.
+    Rejected,
+    /// The penalty was not broadcast given it was not worth the fees needed to get it confirmed.
+    Skipped,
+    /// The dispute output was spent by a transaction other than the penalty (e.g. a newer justice transaction).
+    Superseded,
+    /// The penalty was found to be invalid by the node (e.g. it had a bad signature or its inputs were already spent)
+    /// and was given up on.
+    Invalid,
+    /// The penalty was not broadcast given the subscription of the user had expired (see [LapsedSubscriptionPolicy]).
+    Declined,
+    /// The penalty could not be built out of the appointment (e.g. its blob could not be decrypted), so there was
+    /// nothing to broadcast (see [UnconstructablePenaltyPolicy]).
+    Unconstructable,
+}
+
+impl PenaltyStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PenaltyStatus::Broadcast => "broadcast",
+            PenaltyStatus::Confirmed => "confirmed",
+            PenaltyStatus::Resolved => "resolved",
+            PenaltyStatus::Rejected => "rejected",
+            PenaltyStatus::Skipped => "skipped",
+            PenaltyStatus::Superseded => "superseded",
+            PenaltyStatus::Invalid => "invalid",
+            PenaltyStatus::Declined => "declined",
+            PenaltyStatus::Unconstructable => "unconstructable",
+        }
+    }
+}
+
+impl FromStr for PenaltyStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "broadcast" => Ok(PenaltyStatus::Broadcast),
+            "confirmed" => Ok(PenaltyStatus::Confirmed),
+            "resolved" => Ok(PenaltyStatus::Resolved),
+            "rejected" => Ok(PenaltyStatus::Rejected),
+            "skipped" => Ok(PenaltyStatus::Skipped),
+            "superseded" => Ok(PenaltyStatus::Superseded),
+            "invalid" => Ok(PenaltyStatus::Invalid),
+            "declined" => Ok(PenaltyStatus::Declined),
+            "unconstructable" => Ok(PenaltyStatus::Unconstructable),
+            _ => Err(format!("Unknown penalty status: {s}")),
+        }
+    }
+}
+
+/// A fee-bump of a stuck penalty, that is, a child transaction spending its anchor output (CPFP).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FeeBump {
+    /// The id of the child transaction.
+    pub txid: Txid,
+    /// The package feerate (in sat/vB) targeted by the bump.
+    pub feerate: u64,
+    /// The fee (in sats) paid by the child transaction.
+    pub fee: u64,
+    /// When the bump was broadcast (Unix time, in seconds).
+    pub timestamp: u64,
+}
+
+impl From<FeeBump> for msgs::FeeBump {
+    fn from(b: FeeBump) -> Self {
+        msgs::FeeBump {
+            txid: b.txid.to_vec(),
+            feerate: b.feerate,
+            fee: b.fee,
+            timestamp: b.timestamp,
+        }
+    }
+}
+
+/// A replacement of a tracked penalty by a superseding one (see [Responder::replace_penalty]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PenaltyReplacement {
+    pub old_penalty_txid: Txid,
+    pub new_penalty_txid: Txid,
+    /// When the penalty was replaced (Unix time, in seconds).
+    pub timestamp: u64,
+}
+
+impl From<PenaltyReplacement> for msgs::PenaltyReplacement {
+    fn from(r: PenaltyReplacement) -> Self {
+        msgs::PenaltyReplacement {
+            old_penalty_txid: r.old_penalty_txid.to_vec(),
+            new_penalty_txid: r.new_penalty_txid.to_vec(),
+            timestamp: r.timestamp,
+        }
+    }
+}
+
+/// Reasons why the penalty of a tracker cannot be replaced (see [Responder::replace_penalty]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ReplacePenaltyFailure {
+    /// There is no tracker for the given appointment.
+    UnknownTracker,
+    /// The penalty is already confirmed, so there is nothing to replace.
+    AlreadyConfirmed,
+    /// The new penalty is meant for a different dispute.
+    DisputeMismatch,
+    /// The new penalty does not pay a higher fee (and feerate) than the tracked one.
+    NotSuperseding,
+    /// The new penalty is deemed invalid by the node.
+    Invalid(String),
+    /// The new penalty was rejected by the network.
+    Rejected(i32),
+}
+
+/// An entry of the penalty ledger, the persistent record of every breach the [Responder] has responded to.
+///
+/// Unlike [TransactionTracker]s, records are kept once the penalty is resolved (or given up on).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PenaltyRecord {
+    /// The identifier of the appointment that triggered the penalty.
+    pub uuid: UUID,
+    /// The user the appointment belongs to.
+    pub user_id: UserId,
+    pub dispute_txid: Txid,
+    pub penalty_txid: Txid,
+    /// Value (in sats) of the penalty transaction outputs.
+    pub value: u64,
+    /// When the breach was responded to (Unix time, in seconds).
+    pub timestamp: u64,
+    pub status: PenaltyStatus,
+    /// The fee-bumps of the penalty, oldest first.
+    pub fee_bumps: Vec<FeeBump>,
+    /// The replacements of the penalty, oldest first.
+    pub replacements: Vec<PenaltyReplacement>,
+}
+
+impl PenaltyRecord {
+    /// Creates a new [PenaltyRecord] for a freshly added [TransactionTracker].
+    pub fn new(uuid: UUID, tracker: &TransactionTracker, timestamp: u64) -> Self {
+        PenaltyRecord {
+            uuid,
+            user_id: tracker.user_id,
+            dispute_txid: tracker.dispute_tx.txid(),
+            penalty_txid: tracker.penalty_tx.txid(),
+            value: tracker.penalty_tx.output.iter().map(|o| o.value).sum(),
+            timestamp,
+            status: if let ConfirmationStatus::ConfirmedIn(_) = tracker.status {
+                PenaltyStatus::Confirmed
+            } else {
+                PenaltyStatus::Broadcast
+            },
+            fee_bumps: Vec::new(),
+            replacements: Vec::new(),
+        }
+    }
+
+    /// The fees (in sats) spent fee-bumping the penalty.
+    ///
+    /// All bumps spend the same anchor output, so each of them replaces the previous one and only the fee of the latest
+    /// is actually paid.
+    pub fn bump_fees(&self) -> u64 {
+        self.fee_bumps.last().map_or(0, |bump| bump.fee)
+    }
+}
+
+impl From<PenaltyRecord> for msgs::PenaltyRecord {
+    fn from(r: PenaltyRecord) -> Self {
+        let bump_fees = r.bump_fees();
+        msgs::PenaltyRecord {
+            uuid: r.uuid.to_vec(),
+            user_id: r.user_id.to_vec(),
+            dispute_txid: r.dispute_txid.to_vec(),
+            penalty_txid: r.penalty_txid.to_vec(),
+            value: r.value,
+            timestamp: r.timestamp,
+            status: r.status.as_str().to_owned(),
+            fee_bumps: r.fee_bumps.into_iter().map(|bump| bump.into()).collect(),
+            bump_fees,
+            replacements: r.replacements.into_iter().map(|r| r.into()).collect(),
+        }
+    }
+}
+
+/// What the [Responder] does with the breaches of users whose subscription has expired.
+///
+/// The appointments of lapsed users are kept for [expiry_delta](Gatekeeper) blocks so they have a chance to renew, so
+/// their breaches can still be seen within that window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LapsedSubscriptionPolicy {
+    /// Breaches are responded to as long as the appointment is stored, regardless of the subscription status.
+    #[default]
+    Respond,
+    /// Breaches are not responded to, and are recorded as [PenaltyStatus::Declined] in the penalty ledger.
+    Decline,
+}
+
+/// What the [Responder] does with the breaches whose penalty cannot be built out of the appointment (e.g. the blob
+/// cannot be decrypted using the dispute txid, or it does not decrypt to a transaction).
+///
+/// Either way, breaches that end up with no penalty are recorded as [PenaltyStatus::Unconstructable] in the penalty
+/// ledger.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnconstructablePenaltyPolicy {
+    /// The breach is only logged and recorded.
+    #[default]
+    Alert,
+    /// Decrypting the blob is also attempted with the keys clients are known to mix up the dispute txid with (its
+    /// byte-reversed form and the dispute wtxid).
+    Recover,
+}
+
+/// Checks whether an output can be appended to a transaction without invalidating the signatures of its inputs.
+///
+/// That is only the case if every input is signed using `SIGHASH_SINGLE|ANYONECANPAY` and the output each signature
+/// commits to (the one at the same index as its input) already exists, so the appended one is not covered by any of
+/// them. Inputs with no recognizable signatures do not allow it either.
+fn allows_extra_outputs(tx: &Transaction) -> bool {
+    tx.input.iter().enumerate().all(|(i, txin)| {
+        let script_sig_pushes = txin.script_sig.instructions().filter_map(|ins| match ins {
+            Ok(Instruction::PushBytes(data)) => Some(data),
+            _ => None,
+        });
+        let mut signatures = txin
+            .witness
+            .iter()
+            .chain(script_sig_pushes)
+            .filter_map(|data| EcdsaSig::from_slice(data).ok())
+            .peekable();
+
+        i < tx.output.len()
+            && signatures.peek().is_some()
+            && signatures.all(|sig| sig.hash_ty == EcdsaSighashType::SinglePlusAnyoneCanPay)
+    })
+}
+
+/// Events the [Responder] notifies its subscribers about (see [Responder::subscribe]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponderEvent {
+    /// A penalty was not broadcast given it does not recover [min_penalty_value](Responder::min_penalty_value) once
+    /// paid the fees to get it confirmed.
+    PenaltySkipped {
+        locator: Locator,
+        user_id: UserId,
+        penalty_txid: Txid,
+    },
+}
+
 /// Component in charge of keeping track of triggered appointments.
 ///
 /// The [Responder] receives data from the [Watcher](crate::watcher::Watcher) in form of a [Breach].
@@ -133,6 +414,38 @@ pub struct Responder {
     dbm: Arc<Mutex<DBM>>,
     /// A list of all the reorged trackers that might need to be republished after reorg resolution.
     reorged_trackers: Mutex<HashSet<UUID>>,
+    /// The key material used to fee-bump penalties via their anchors, if any. CPFP is disabled otherwise.
+    anchor_material: Option<AnchorMaterial>,
+    /// The minimum value (in sats) a penalty must recover, once paid the fees to get it confirmed, to be broadcast.
+    min_penalty_value: u64,
+    /// What to do with the breaches of users whose subscription has expired.
+    lapsed_policy: LapsedSubscriptionPolicy,
+    /// What to do with the breaches whose penalty cannot be built.
+    unconstructable_policy: UnconstructablePenaltyPolicy,
+    /// The script the operator reward outputs pay to, if the tower takes rewards at all.
+    reward_script: Option<Script>,
+    /// The maximum number of blocks the broadcast of a penalty can be delayed by (see [Responder::broadcast_delay]).
+    max_broadcast_delay: u32,
+    /// Penalties whose broadcast has been delayed, alongside the height they are due to be broadcast at.
+    ///
+    /// This is not persisted. Delayed penalties are tracked as in mempool, so if the tower is restarted before they are
+    /// broadcast, they are picked up as evicted from the mempool (see [Responder::rebroadcast_evicted_txs]) instead.
+    scheduled_broadcasts: Mutex<HashMap<UUID, u32>>,
+    /// Penalties whose inputs have been spent by a competing transaction, alongside its txid and the height it was
+    /// confirmed at (see [Responder::check_superseded]).
+    ///
+    /// This is not persisted. If the tower is restarted before the competing transaction is irrevocably resolved, the
+    /// penalties are picked up as invalid once due to be rebroadcast (see [Responder::check_stale_penalties]) instead.
+    superseding_txs: Mutex<HashMap<UUID, (Txid, u32)>>,
+    /// Corroborates penalties are irrevocably resolved before finalizing their trackers, if set.
+    corroborator: Option<Arc<Corroborator>>,
+    /// Sender end of the channel [ResponderEvent]s are notified through.
+    events: broadcast::Sender<ResponderEvent>,
+    /// The feerate estimates (in sat/vB) for each of the [FEE_ESTIMATE_TARGETS], so users can be served without
+    /// querying `bitcoind` (see [Responder::estimate_feerate]).
+    ///
+    /// This is not persisted. It is refreshed on every block (see [Responder::update_fee_estimates]).
+    fee_estimates: Mutex<BTreeMap<u16, f64>>,
 }
 
 impl Responder {
@@ -143,6 +456,8 @@ impl Responder {
         carrier: Carrier,
         gatekeeper: Arc<Gatekeeper>,
         dbm: Arc<Mutex<DBM>>,
+        anchor_material: Option<AnchorMaterial>,
+        min_penalty_value: u64,
     ) -> Self {
         Responder {
             carrier: Mutex::new(carrier),
@@ -150,9 +465,63 @@ impl Responder {
             dbm,
             gatekeeper,
             reorged_trackers: Mutex::new(HashSet::new()),
+            anchor_material,
+            min_penalty_value,
+            lapsed_policy: LapsedSubscriptionPolicy::default(),
+            unconstructable_policy: UnconstructablePenaltyPolicy::default(),
+            reward_script: None,
+            max_broadcast_delay: 0,
+            scheduled_broadcasts: Mutex::new(HashMap::new()),
+            superseding_txs: Mutex::new(HashMap::new()),
+            corroborator: None,
+            events: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            fee_estimates: Mutex::new(BTreeMap::new()),
         }
     }
 
+    /// Subscribes to the [ResponderEvent]s notified from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<ResponderEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sets what to do with the breaches of users whose subscription has expired. They are responded to by default.
+    pub fn with_lapsed_policy(mut self, lapsed_policy: LapsedSubscriptionPolicy) -> Self {
+        self.lapsed_policy = lapsed_policy;
+        self
+    }
+
+    /// Sets what to do with the breaches whose penalty cannot be built. They are only logged and recorded by default.
+    pub fn with_unconstructable_policy(
+        mut self,
+        unconstructable_policy: UnconstructablePenaltyPolicy,
+    ) -> Self {
+        self.unconstructable_policy = unconstructable_policy;
+        self
+    }
+
+    /// Sets the script the operator reward outputs pay to. No rewards are claimed otherwise, so penalties are always
+    /// broadcast as handed by the users.
+    pub fn with_reward_script(mut self, reward_script: Script) -> Self {
+        self.reward_script = Some(reward_script);
+        self
+    }
+
+    /// Sets the maximum number of blocks the broadcast of a penalty can be delayed by, so the tower watching a channel
+    /// is harder to tell apart. Penalties are broadcast right away by default.
+    pub fn with_max_broadcast_delay(mut self, max_broadcast_delay: u32) -> Self {
+        self.max_broadcast_delay = max_broadcast_delay;
+        self
+    }
+
+    /// Sets a [Corroborator] penalties are checked against before their trackers are finalized. Trackers are finalized
+    /// based on the blocks `bitcoind` feeds the tower alone otherwise.
+    ///
+    /// The corroborator needs to be run on a task of its own (see [Corroborator::run]).
+    pub fn with_corroborator(mut self, corroborator: Arc<Corroborator>) -> Self {
+        self.corroborator = Some(corroborator);
+        self
+    }
+
     /// Returns whether the [Responder] has been created from scratch (fresh) or from backed-up data.
     pub fn is_fresh(&self) -> bool {
         self.get_trackers_count() == 0
@@ -171,24 +540,76 @@ impl Responder {
     /// Data entry point for the [Responder]. Handles a [Breach] provided by the [Watcher](crate::watcher::Watcher).
     ///
     /// Breaches can either be added to the [Responder] in the form of a [TransactionTracker] if the [penalty transaction](Breach::penalty_tx)
-    /// is accepted by the `bitcoind` or rejected otherwise.
+    /// is accepted by the `bitcoind` or rejected otherwise. Penalties that are not worth broadcasting (see [Responder::is_economical])
+    /// are rejected without reaching the network, and recorded as skipped in the penalty ledger. So are penalties that
+    /// `bitcoind` deems invalid (see [Carrier::test_accept]), which are recorded as invalid, and the ones of lapsed users
+    /// if the [LapsedSubscriptionPolicy] says so, which are recorded as declined.
+    ///
+    /// Penalties of appointments that carry reward terms may be extended with an operator reward output before being
+    /// handled (see [Responder::claim_reward]).
+    ///
+    /// If the [Responder] is set to delay broadcasts, valid penalties may be tracked right away but only broadcast a
+    /// few blocks later (see [Responder::broadcast_delay]).
     pub(crate) fn handle_breach(
         &self,
         uuid: UUID,
         breach: Breach,
         user_id: UserId,
     ) -> ConfirmationStatus {
+        if let Ok((true, expiry)) = self.gatekeeper.has_subscription_expired(user_id) {
+            match self.lapsed_policy {
+                LapsedSubscriptionPolicy::Respond => log::info!(
+                    "Responding to breach of a lapsed subscription (uuid={uuid}, expired at height {expiry})"
+                ),
+                LapsedSubscriptionPolicy::Decline => {
+                    self.decline_penalty(uuid, breach, user_id, expiry);
+                    return ConfirmationStatus::Rejected(errors::RPC_SUBSCRIPTION_LAPSED);
+                }
+            }
+        }
+
         let mut carrier = self.carrier.lock().unwrap();
         let tx_index = self.tx_index.lock().unwrap();
+        // bitcoind cannot be queried while it is unreachable, so penalties are pushed through the broadcast fallback as
+        // handed by the user, with no further checks.
+        let use_fallback = carrier.uses_broadcast_fallback();
+        let breach = if use_fallback {
+            breach
+        } else {
+            self.claim_reward(&carrier, &tx_index, uuid, breach)
+        };
 
         // Check whether the transaction is in mempool or part of our internal txindex. Send it to our node otherwise.
         let status = if let Some(block_hash) = tx_index.get(&breach.penalty_tx.txid()) {
             ConfirmationStatus::ConfirmedIn(tx_index.get_height(block_hash).unwrap() as u32)
+        } else if use_fallback {
+            carrier.send_transaction(&breach.penalty_tx)
         } else if carrier.in_mempool(&breach.penalty_tx.txid()) {
             // If it's in mempool we assume it was just included
             ConfirmationStatus::InMempoolSince(carrier.block_height())
+        } else if !self.is_economical(&carrier, &breach) {
+            self.skip_penalty(uuid, breach, user_id);
+            return ConfirmationStatus::Rejected(errors::RPC_PENALTY_UNECONOMICAL);
+        } else if let Err(reason) = carrier.test_accept(&breach.penalty_tx) {
+            self.reject_invalid_penalty(uuid, breach, user_id, &reason);
+            return ConfirmationStatus::Rejected(rpc_errors::RPC_VERIFY_REJECTED);
         } else {
-            carrier.send_transaction(&breach.penalty_tx)
+            match self.broadcast_delay(uuid) {
+                0 => carrier.send_transaction(&breach.penalty_tx),
+                delay => {
+                    let height = carrier.block_height();
+                    log::info!(
+                        "Delaying the broadcast of penalty transaction {} by {delay} blocks (uuid={uuid})",
+                        breach.penalty_tx.txid()
+                    );
+                    self.scheduled_broadcasts
+                        .lock()
+                        .unwrap()
+                        .insert(uuid, height + delay);
+                    // The penalty is valid (see above), so it is tracked as if it had been accepted already.
+                    ConfirmationStatus::InMempoolSince(height)
+                }
+            }
         };
 
         if status.accepted() {
@@ -198,6 +619,262 @@ impl Responder {
         status
     }
 
+    /// Picks how many blocks to delay the broadcast of a given penalty by, at random up to the configured maximum.
+    ///
+    /// The delay is capped to half the `to_self_delay` of the breached channel, so the penalty is given at least half
+    /// the dispute window to confirm. Penalties of appointments that cannot be found are not delayed.
+    fn broadcast_delay(&self, uuid: UUID) -> u32 {
+        if self.max_broadcast_delay == 0 {
+            return 0;
+        }
+        let to_self_delay = match self.dbm.lock().unwrap().load_appointment(uuid) {
+            Some(appointment) => appointment.to_self_delay(),
+            None => return 0,
+        };
+
+        rand::thread_rng().gen_range(0..=self.max_broadcast_delay.min(to_self_delay / 2))
+    }
+
+    /// Whether the broadcast of a given penalty is still pending (see [Responder::broadcast_delay]).
+    fn is_scheduled(&self, uuid: UUID) -> bool {
+        self.scheduled_broadcasts
+            .lock()
+            .unwrap()
+            .contains_key(&uuid)
+    }
+
+    /// Whether a given penalty has had its inputs spent by a competing transaction (see [Responder::check_superseded]).
+    fn is_superseded(&self, uuid: UUID) -> bool {
+        self.superseding_txs.lock().unwrap().contains_key(&uuid)
+    }
+
+    /// Appends an output paying the operator reward to the penalty of an appointment that carries reward terms.
+    ///
+    /// The penalty is left untouched if the tower takes no rewards, the appointment carries no reward terms or the
+    /// penalty already pays the reward. So it is if, once the reward is paid, the value the penalty leaves unallocated
+    /// (out of the dispute outputs it spends) does not cover the fees needed to get it confirmed at the current feerate,
+    /// or there is no feerate estimate to tell.
+    ///
+    /// Given the penalty is signed by the user, the reward can only be claimed if its signatures do not cover the
+    /// appended output (see [allows_extra_outputs]). Even so, the rewarded version is only used if `bitcoind` deems it
+    /// valid, falling back to the one handed by the user otherwise.
+    fn claim_reward(
+        &self,
+        carrier: &Carrier,
+        tx_index: &TxIndex<Txid, BlockHash>,
+        uuid: UUID,
+        breach: Breach,
+    ) -> Breach {
+        let reward = self.dbm.lock().unwrap().load_appointment_reward(uuid);
+        let (reward_script, reward) = match (&self.reward_script, reward) {
+            (Some(reward_script), Some(reward)) => (reward_script, reward),
+            _ => return breach,
+        };
+        if breach
+            .penalty_tx
+            .output
+            .iter()
+            .any(|o| o.script_pubkey == *reward_script && o.value >= reward)
+        {
+            return breach;
+        }
+        if !allows_extra_outputs(&breach.penalty_tx) {
+            log::warn!("Penalty is not signed using SIGHASH_SINGLE|ANYONECANPAY, so the reward cannot be claimed (uuid={uuid}). Broadcasting it as is");
+            return breach;
+        }
+
+        let unallocated = breach.unallocated_value();
+
+        let mut rewarded = breach.clone();
+        rewarded.penalty_tx.output.push(TxOut {
+            value: reward,
+            script_pubkey: reward_script.clone(),
+        });
+        let fee = match carrier.estimate_feerate(CPFP_CONFIRMATION_TARGET) {
+            Some(feerate) => feerate * anchors::vsize(&rewarded.penalty_tx),
+            None => {
+                log::warn!("Cannot claim the reward without a feerate estimate (uuid={uuid}). Broadcasting the penalty as is");
+                return breach;
+            }
+        };
+        if unallocated < reward.saturating_add(fee) {
+            log::warn!(
+                "Penalty does not leave enough value to claim the reward (uuid={uuid}, reward={reward}, fee={fee}, unallocated={unallocated}). Broadcasting it as is"
+            );
+            return breach;
+        }
+        let penalty_txid = rewarded.penalty_tx.txid();
+        if tx_index.get(&penalty_txid).is_some() || carrier.in_mempool(&penalty_txid) {
+            return rewarded;
+        }
+
+        match carrier.test_accept(&rewarded.penalty_tx) {
+            Ok(()) => {
+                log::info!(
+                    "Claiming a reward of {reward} sats (uuid={uuid}, penalty_txid={penalty_txid})"
+                );
+                rewarded
+            }
+            Err(reason) => {
+                log::warn!("Cannot claim the reward (uuid={uuid}). Broadcasting the penalty as is. Reason: {reason}");
+                breach
+            }
+        }
+    }
+
+    /// Checks whether a penalty recovers at least [min_penalty_value](Self::min_penalty_value) once the fees needed to get
+    /// it confirmed at the current feerate are discounted.
+    ///
+    /// The fee the penalty already pays is not part of its outputs, so only what is missing to reach the current feerate
+    /// (which would need to be added via CPFP) is discounted. The value of the penalty is taken as is if no feerate
+    /// estimate is available.
+    fn is_economical(&self, carrier: &Carrier, breach: &Breach) -> bool {
+        if self.min_penalty_value == 0 {
+            return true;
+        }
+
+        let value: u64 = breach.penalty_tx.output.iter().map(|o| o.value).sum();
+        let missing_fee = carrier
+            .estimate_feerate(CPFP_CONFIRMATION_TARGET)
+            .map_or(0, |feerate| {
+                (feerate * anchors::vsize(&breach.penalty_tx))
+                    .saturating_sub(breach.unallocated_value())
+            });
+
+        value.saturating_sub(missing_fee) >= self.min_penalty_value
+    }
+
+    /// Records a penalty that is not worth broadcasting in the penalty ledger, flagged as [PenaltyStatus::Skipped].
+    fn skip_penalty(&self, uuid: UUID, breach: Breach, user_id: UserId) {
+        let tracker = TransactionTracker::new(
+            breach,
+            user_id,
+            ConfirmationStatus::Rejected(errors::RPC_PENALTY_UNECONOMICAL),
+        );
+        log::warn!(
+            "Uneconomical penalty skipped (uuid={uuid}, penalty_txid={}). It recovers less than {} sats once paid the fees",
+            tracker.penalty_tx.txid(),
+            self.min_penalty_value
+        );
+        self.record_unbroadcast_penalty(uuid, &tracker, PenaltyStatus::Skipped);
+        // Sending only fails if there are no subscribers, in which case there is no one to notify
+        let _ = self.events.send(ResponderEvent::PenaltySkipped {
+            locator: Locator::new(tracker.dispute_tx.txid()),
+            user_id,
+            penalty_txid: tracker.penalty_tx.txid(),
+        });
+    }
+
+    /// Records a penalty that `bitcoind` deems invalid in the penalty ledger, flagged as [PenaltyStatus::Invalid].
+    fn reject_invalid_penalty(&self, uuid: UUID, breach: Breach, user_id: UserId, reason: &str) {
+        let tracker = TransactionTracker::new(
+            breach,
+            user_id,
+            ConfirmationStatus::Rejected(rpc_errors::RPC_VERIFY_REJECTED),
+        );
+        log::warn!(
+            "Invalid penalty not broadcast (uuid={uuid}, penalty_txid={}). Reason: {reason}",
+            tracker.penalty_tx.txid()
+        );
+        self.record_unbroadcast_penalty(uuid, &tracker, PenaltyStatus::Invalid);
+    }
+
+    /// Records the penalty of a lapsed user in the penalty ledger, flagged as [PenaltyStatus::Declined].
+    fn decline_penalty(&self, uuid: UUID, breach: Breach, user_id: UserId, expiry: u32) {
+        let tracker = TransactionTracker::new(
+            breach,
+            user_id,
+            ConfirmationStatus::Rejected(errors::RPC_SUBSCRIPTION_LAPSED),
+        );
+        log::warn!(
+            "Penalty of a lapsed subscription not broadcast (uuid={uuid}, penalty_txid={}, expired at height {expiry})",
+            tracker.penalty_tx.txid()
+        );
+        self.record_unbroadcast_penalty(uuid, &tracker, PenaltyStatus::Declined);
+    }
+
+    /// Handles a breach whose penalty could not be built out of its appointment blob (see [UnconstructablePenaltyPolicy]).
+    ///
+    /// Returns the recovered penalty, if any. Otherwise, the breach is recorded in the penalty ledger flagged as
+    /// [PenaltyStatus::Unconstructable]. Given there is no penalty, the record has a zeroed penalty txid and no value.
+    pub(crate) fn recover_penalty(
+        &self,
+        uuid: UUID,
+        dispute_tx: &Transaction,
+        encrypted_blob: &[u8],
+        user_id: UserId,
+        error: DecryptingError,
+    ) -> Option<Transaction> {
+        if self.unconstructable_policy == UnconstructablePenaltyPolicy::Recover {
+            let mut reversed_txid = dispute_tx.txid().into_inner();
+            reversed_txid.reverse();
+            let keys = [
+                Txid::from_inner(reversed_txid),
+                Txid::from_inner(dispute_tx.wtxid().into_inner()),
+            ];
+            if let Some(penalty_tx) = keys
+                .iter()
+                .find_map(|key| cryptography::decrypt(encrypted_blob, key).ok())
+            {
+                log::warn!("Penalty recovered using an alternative key (uuid={uuid})");
+                return Some(penalty_tx);
+            }
+        }
+
+        let reason = match error {
+            DecryptingError::AED(_) => "the blob cannot be decrypted using the dispute txid",
+            DecryptingError::Encode(_) => "the decrypted blob is not a transaction",
+        };
+        log::error!(
+            "Cannot build the penalty for a breach (uuid={uuid}, dispute_txid={}). Reason: {reason}",
+            dispute_tx.txid()
+        );
+        let record = PenaltyRecord {
+            uuid,
+            user_id,
+            dispute_txid: dispute_tx.txid(),
+            penalty_txid: Txid::from_inner([0; 32]),
+            value: 0,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            status: PenaltyStatus::Unconstructable,
+            fee_bumps: Vec::new(),
+            replacements: Vec::new(),
+        };
+        self.dbm
+            .lock()
+            .unwrap()
+            .store_penalty_record(&record)
+            .unwrap_or_else(|e| {
+                log::error!("Failed to add penalty to the ledger (uuid={uuid}). Error: {e:?}")
+            });
+        None
+    }
+
+    /// Records a penalty that never reached the network in the penalty ledger, flagged with the given status.
+    fn record_unbroadcast_penalty(
+        &self,
+        uuid: UUID,
+        tracker: &TransactionTracker,
+        status: PenaltyStatus,
+    ) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let mut record = PenaltyRecord::new(uuid, tracker, timestamp);
+        record.status = status;
+        self.dbm
+            .lock()
+            .unwrap()
+            .store_penalty_record(&record)
+            .unwrap_or_else(|e| {
+                log::error!("Failed to add penalty to the ledger (uuid={uuid}). Error: {e:?}")
+            });
+    }
+
     /// Adds a [TransactionTracker] to the [Responder] from a given [Breach].
     ///
     /// From this point on, transactions are accepted as valid. They may not end up being confirmed, but they
@@ -213,14 +890,18 @@ impl Responder {
         user_id: UserId,
         status: ConfirmationStatus,
     ) {
-        if self
-            .dbm
-            .lock()
-            .unwrap()
-            .store_tracker(uuid, &TransactionTracker::new(breach, user_id, status))
-            .is_ok()
-        {
+        let tracker = TransactionTracker::new(breach, user_id, status);
+        let dbm = self.dbm.lock().unwrap();
+        if dbm.store_tracker(uuid, &tracker).is_ok() {
             log::info!("New tracker added (uuid={uuid})");
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            dbm.store_penalty_record(&PenaltyRecord::new(uuid, &tracker, timestamp))
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to add penalty to the ledger (uuid={uuid}). Error: {e:?}")
+                });
         } else {
             log::error!(
                 "Failed to store tracker in database (uuid={uuid}). It might be already stored."
@@ -228,15 +909,163 @@ impl Responder {
         }
     }
 
+    /// Replaces the penalty of an in-flight tracker with a superseding one for the same dispute.
+    ///
+    /// The new penalty needs to pay both a higher fee and a higher feerate than the tracked one (so it can replace it in
+    /// the mempool) and be deemed valid by the node, so a penalty is never downgraded. Penalties that have already
+    /// confirmed are not replaced. The new penalty is broadcast right away, unless the broadcast of the tracked one is
+    /// still being delayed (see [Responder::broadcast_delay]), in which case it is broadcast in its place when due.
+    ///
+    /// The replacement is recorded in the penalty ledger, which moves on to track the new penalty.
+    pub(crate) fn replace_penalty(
+        &self,
+        uuid: UUID,
+        breach: Breach,
+    ) -> Result<ConfirmationStatus, ReplacePenaltyFailure> {
+        let dbm = self.dbm.lock().unwrap();
+        let tracker = dbm
+            .load_tracker(uuid)
+            .ok_or(ReplacePenaltyFailure::UnknownTracker)?;
+        if breach.dispute_tx.txid() != tracker.dispute_tx.txid() {
+            return Err(ReplacePenaltyFailure::DisputeMismatch);
+        }
+        if let ConfirmationStatus::ConfirmedIn(_) = tracker.status {
+            return Err(ReplacePenaltyFailure::AlreadyConfirmed);
+        }
+
+        let old_fee = anchors::penalty_fee(&tracker.dispute_tx, &tracker.penalty_tx);
+        let new_fee = anchors::penalty_fee(&breach.dispute_tx, &breach.penalty_tx);
+        // new_fee / new_vsize > old_fee / old_vsize, without dealing with floats
+        let higher_feerate = new_fee as u128 * anchors::vsize(&tracker.penalty_tx) as u128
+            > old_fee as u128 * anchors::vsize(&breach.penalty_tx) as u128;
+        if new_fee <= old_fee || !higher_feerate {
+            log::info!(
+                "Not replacing penalty {} with {}. It does not pay more fees (uuid={uuid})",
+                tracker.penalty_tx.txid(),
+                breach.penalty_tx.txid()
+            );
+            return Err(ReplacePenaltyFailure::NotSuperseding);
+        }
+
+        let mut carrier = self.carrier.lock().unwrap();
+        carrier
+            .test_accept(&breach.penalty_tx)
+            .map_err(ReplacePenaltyFailure::Invalid)?;
+        let status = if self.is_scheduled(uuid) {
+            tracker.status
+        } else {
+            match carrier.send_transaction(&breach.penalty_tx) {
+                ConfirmationStatus::Rejected(reason) => {
+                    return Err(ReplacePenaltyFailure::Rejected(reason))
+                }
+                status => status,
+            }
+        };
+
+        let replacement = PenaltyReplacement {
+            old_penalty_txid: tracker.penalty_tx.txid(),
+            new_penalty_txid: breach.penalty_tx.txid(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        };
+        log::info!(
+            "Replacing penalty {} with {} (uuid={uuid})",
+            replacement.old_penalty_txid,
+            replacement.new_penalty_txid
+        );
+        dbm.update_tracker_penalty(uuid, &breach.penalty_tx, &status)
+            .unwrap();
+        let value = breach.penalty_tx.output.iter().map(|o| o.value).sum();
+        dbm.store_penalty_replacement(uuid, value, &replacement)
+            .unwrap_or_else(|e| {
+                log::error!(
+                    "Failed to add penalty replacement to the ledger (uuid={uuid}). Error: {e:?}"
+                )
+            });
+
+        Ok(status)
+    }
+
+    /// Gets the penalty ledger records within a given time range (Unix time, in seconds, both ends included), oldest first.
+    pub(crate) fn get_penalties(&self, start: Option<u64>, end: Option<u64>) -> Vec<PenaltyRecord> {
+        self.dbm.lock().unwrap().load_penalty_records(start, end)
+    }
+
+    /// Updates the status of some penalties in the ledger.
+    fn update_penalties_status(&self, uuids: &[UUID], status: PenaltyStatus) {
+        let dbm = self.dbm.lock().unwrap();
+        for uuid in uuids {
+            dbm.update_penalty_status(*uuid, status).ok();
+        }
+    }
+
     /// Checks whether a given tracker can be found in the [Responder].
     pub(crate) fn has_tracker(&self, uuid: UUID) -> bool {
         self.dbm.lock().unwrap().tracker_exists(uuid)
     }
 
+    /// Refreshes the feerate estimates served to users for each of the [FEE_ESTIMATE_TARGETS]. Targets `bitcoind` has
+    /// no estimate for are dropped.
+    pub fn update_fee_estimates(&self) {
+        let fee_estimates = {
+            let carrier = self.carrier.lock().unwrap();
+            FEE_ESTIMATE_TARGETS
+                .iter()
+                .filter_map(|&target| {
+                    carrier
+                        .estimate_exact_feerate(target)
+                        .map(|feerate| (target, feerate))
+                })
+                .collect()
+        };
+        *self.fee_estimates.lock().unwrap() = fee_estimates;
+    }
+
+    /// Gets the feerate (in sat/vB) needed for a transaction to confirm within `conf_target` blocks, as estimated on the
+    /// last block (see [Responder::update_fee_estimates]). Targets in between the [FEE_ESTIMATE_TARGETS] get the estimate
+    /// of the closest lower one, which is never below their own. Returns [None] if no estimate is available.
+    pub(crate) fn estimate_feerate(&self, conf_target: u16) -> Option<f64> {
+        self.fee_estimates
+            .lock()
+            .unwrap()
+            .range(..=conf_target)
+            .next_back()
+            .map(|(_, feerate)| *feerate)
+    }
+
+    /// Gets how exposed the confirmed penalties are to reorgs, given the last known block height.
+    ///
+    /// Penalties are exposed until they are [irrevocably resolved](constants::IRREVOCABLY_RESOLVED), at which point they
+    /// stop being tracked. Reorged trackers are not accounted for, given their stored status is outdated.
+    pub(crate) fn get_reorg_exposure(&self) -> ReorgExposure {
+        let height = self.carrier.lock().unwrap().block_height();
+        let reorged_trackers = self.reorged_trackers.lock().unwrap();
+        let confirmations: Vec<u32> = self
+            .dbm
+            .lock()
+            .unwrap()
+            .load_penalties_summaries()
+            .into_iter()
+            .filter(|(uuid, _)| !reorged_trackers.contains(uuid))
+            .filter_map(|(_, summary)| match summary.status {
+                ConfirmationStatus::ConfirmedIn(h) => Some(height.saturating_sub(h) + 1),
+                _ => None,
+            })
+            .collect();
+
+        ReorgExposure {
+            unresolved_penalties: confirmations.len(),
+            min_confirmations: confirmations.into_iter().min(),
+        }
+    }
+
     /// Checks the confirmation count for the [TransactionTracker]s.
     ///
     /// For unconfirmed transactions, it checks whether they have been confirmed or keep missing confirmations.
     /// For confirmed transactions, nothing is done until they are completed (confirmation count reaches [IRREVOCABLY_RESOLVED](constants::IRREVOCABLY_RESOLVED))
+    /// If a corroborator is set, completed trackers may be held back until it agrees (see [Corroborator::is_corroborated]).
     /// Returns the set of completed trackers or [None] if none were completed.
     fn check_confirmations(&self, txids: HashSet<Txid>, current_height: u32) -> Option<Vec<UUID>> {
         let mut completed_trackers = Vec::new();
@@ -248,6 +1077,8 @@ impl Responder {
                 // First confirmation was received
                 dbm.update_tracker_status(uuid, &ConfirmationStatus::ConfirmedIn(current_height))
                     .unwrap();
+                dbm.update_penalty_status(uuid, PenaltyStatus::Confirmed)
+                    .ok();
                 // Remove that uuid from reorged trackers if it was confirmed.
                 reorged_trackers.remove(&uuid);
             // TODO: We won't need this check when we persist the correct tracker status
@@ -257,9 +1088,21 @@ impl Responder {
                 continue;
             } else if let ConfirmationStatus::ConfirmedIn(h) = penalty_summary.status {
                 let confirmations = current_height - h;
-                if confirmations == constants::IRREVOCABLY_RESOLVED {
+                // Trackers held back by the corroborator go past the mark, so they are checked again
+                if confirmations == constants::IRREVOCABLY_RESOLVED
+                    || (self.corroborator.is_some()
+                        && confirmations > constants::IRREVOCABLY_RESOLVED)
+                {
                     // Tracker is deep enough in the chain, it can be deleted
-                    completed_trackers.push(uuid);
+                    let corroborated = match &self.corroborator {
+                        Some(corroborator) => {
+                            corroborator.is_corroborated(&penalty_summary.penalty_txid, h)
+                        }
+                        None => true,
+                    };
+                    if corroborated {
+                        completed_trackers.push(uuid);
+                    }
                 } else {
                     log::info!("{uuid} received a confirmation (count={confirmations})");
                 }
@@ -276,20 +1119,93 @@ impl Responder {
         (!completed_trackers.is_empty()).then_some(completed_trackers)
     }
 
-    /// Handles the reorged out trackers when we start connecting to the stronger chain.
+    /// Checks whether any of the unconfirmed [TransactionTracker]s has been superseded by a transaction in the given block.
     ///
-    /// This is called in the first block connection after a bunch of block disconnections.
-    /// It tries to publish the dispute and penalty transactions of reorged trackers to the blockchain.
+    /// A tracker is superseded if any of the outputs spent by its penalty is spent by a different transaction (e.g. a
+    /// newer justice transaction built by the user or by another tower). Such a penalty cannot confirm, so it is not
+    /// rebroadcast anymore. However, the competing transaction may still be reorged out, so the tracker is only given up
+    /// on once the competing transaction is [irrevocably resolved](constants::IRREVOCABLY_RESOLVED).
     ///
-    /// Returns a vector of rejected trackers during rebroadcast if any were rejected, [None] otherwise.
-    fn handle_reorged_txs(&self, height: u32) -> Option<Vec<UUID>> {
-        // NOTE: We are draining the reorged trackers set, meaning that we won't try sending these disputes again.
-        let reorged_trackers: Vec<UUID> = self.reorged_trackers.lock().unwrap().drain().collect();
-        let mut carrier = self.carrier.lock().unwrap();
+    /// Returns the set of trackers superseded by an irrevocably resolved transaction or [None] if there are none.
+    fn check_superseded(
+        &self,
+        txdata: &chain::transaction::TransactionData,
+        current_height: u32,
+    ) -> Option<Vec<UUID>> {
+        let spent_outpoints: HashMap<OutPoint, Txid> = txdata
+            .iter()
+            .flat_map(|(_, tx)| {
+                let txid = tx.txid();
+                tx.input
+                    .iter()
+                    .map(move |txin| (txin.previous_output, txid))
+            })
+            .collect();
+
+        let reorged_trackers = self.reorged_trackers.lock().unwrap();
         let dbm = self.dbm.lock().unwrap();
+        let mut superseding_txs = self.superseding_txs.lock().unwrap();
 
-        let mut rejected = Vec::new();
-        // Republish all the dispute transactions of the reorged trackers.
+        if !spent_outpoints.is_empty() {
+            for uuid in dbm
+                .load_trackers_with_confirmation_status(ConfirmationStatus::InMempoolSince(
+                    current_height,
+                ))
+                .unwrap()
+            {
+                // Reorged trackers are dealt with in `handle_reorged_txs`, and superseded ones are already being waited on.
+                if reorged_trackers.contains(&uuid) || superseding_txs.contains_key(&uuid) {
+                    continue;
+                }
+
+                let tracker = dbm.load_tracker(uuid).unwrap();
+                let penalty_txid = tracker.penalty_tx.txid();
+                if let Some(spending_txid) = tracker.penalty_tx.input.iter().find_map(|txin| {
+                    spent_outpoints
+                        .get(&txin.previous_output)
+                        .filter(|txid| **txid != penalty_txid)
+                }) {
+                    log::warn!(
+                        "Penalty transaction {penalty_txid} superseded by {spending_txid} (dispute_txid={}). Waiting for it to be irrevocably resolved",
+                        tracker.dispute_tx.txid()
+                    );
+                    superseding_txs.insert(uuid, (*spending_txid, current_height));
+                }
+            }
+        }
+
+        let superseded_trackers: Vec<UUID> = superseding_txs
+            .iter()
+            .filter_map(|(uuid, (_, height))| {
+                (current_height - height >= constants::IRREVOCABLY_RESOLVED).then_some(*uuid)
+            })
+            .collect();
+        for uuid in superseded_trackers.iter() {
+            superseding_txs.remove(uuid);
+        }
+        // Trackers may have been deleted for some other reason meanwhile
+        let superseded_trackers: Vec<UUID> = superseded_trackers
+            .into_iter()
+            .filter(|uuid| dbm.load_tracker(*uuid).is_some())
+            .collect();
+
+        (!superseded_trackers.is_empty()).then_some(superseded_trackers)
+    }
+
+    /// Handles the reorged out trackers when we start connecting to the stronger chain.
+    ///
+    /// This is called in the first block connection after a bunch of block disconnections.
+    /// It tries to publish the dispute and penalty transactions of reorged trackers to the blockchain.
+    ///
+    /// Returns a vector of rejected trackers during rebroadcast if any were rejected, [None] otherwise.
+    fn handle_reorged_txs(&self, height: u32) -> Option<Vec<UUID>> {
+        // NOTE: We are draining the reorged trackers set, meaning that we won't try sending these disputes again.
+        let reorged_trackers: Vec<UUID> = self.reorged_trackers.lock().unwrap().drain().collect();
+        let mut carrier = self.carrier.lock().unwrap();
+        let dbm = self.dbm.lock().unwrap();
+
+        let mut rejected = Vec::new();
+        // Republish all the dispute transactions of the reorged trackers.
         for uuid in reorged_trackers {
             let tracker = dbm.load_tracker(uuid).unwrap();
             let dispute_txid = tracker.dispute_tx.txid();
@@ -334,7 +1250,9 @@ impl Responder {
                     // is fully synced with the stronger chain already, but we won't know which block was it confirmed in.
                     // We should see the tracker appear in the blockchain in the next couple of connected blocks.
                     dbm.update_tracker_status(uuid, &ConfirmationStatus::InMempoolSince(height))
-                        .unwrap()
+                        .unwrap();
+                    dbm.update_penalty_status(uuid, PenaltyStatus::Broadcast)
+                        .ok();
                 }
             } else {
                 rejected.push(uuid)
@@ -344,10 +1262,131 @@ impl Responder {
         (!rejected.is_empty()).then_some(rejected)
     }
 
+    /// Checks whether the penalties due to be rebroadcast (see [Responder::rebroadcast_stale_txs]) would still be
+    /// accepted by `bitcoind`.
+    ///
+    /// Definitely invalid penalties (e.g. whose inputs have already been spent by someone else) will never make it to
+    /// the chain, so they are given up on instead of being rebroadcast block after block.
+    ///
+    /// Returns a vector of invalid trackers if any were found, [None] otherwise.
+    fn check_stale_penalties(&self, height: u32) -> Option<Vec<UUID>> {
+        let dbm = self.dbm.lock().unwrap();
+        let carrier = self.carrier.lock().unwrap();
+
+        let stale_confirmation_status =
+            ConfirmationStatus::InMempoolSince(height - CONFIRMATIONS_BEFORE_RETRY as u32);
+        let invalid: Vec<UUID> = dbm
+            .load_trackers_with_confirmation_status(stale_confirmation_status)
+            .unwrap()
+            .into_iter()
+            .filter(|uuid| {
+                // Superseded penalties are known to be invalid for the time being, but may not be for good
+                if self.is_superseded(*uuid) {
+                    return false;
+                }
+                let tracker = dbm.load_tracker(*uuid).unwrap();
+                carrier.test_accept(&tracker.penalty_tx).is_err()
+            })
+            .collect();
+
+        (!invalid.is_empty()).then_some(invalid)
+    }
+
+    /// Broadcasts the penalties whose delayed broadcast is due at the given height (see [Responder::broadcast_delay]).
+    ///
+    /// The status of the broadcast ones is updated to the current height, so they are given the usual number of blocks
+    /// to confirm before being rebroadcast. Penalties whose tracker is gone (e.g. due to a reorg) are dropped.
+    ///
+    /// Returns a vector of rejected trackers during broadcast if any were rejected, [None] otherwise.
+    fn broadcast_scheduled_txs(&self, height: u32) -> Option<Vec<UUID>> {
+        let due: Vec<UUID> = {
+            let mut scheduled_broadcasts = self.scheduled_broadcasts.lock().unwrap();
+            let due = scheduled_broadcasts
+                .iter()
+                .filter_map(|(uuid, h)| (*h <= height).then_some(*uuid))
+                .collect::<Vec<_>>();
+            for uuid in due.iter() {
+                scheduled_broadcasts.remove(uuid);
+            }
+            due
+        };
+
+        let dbm = self.dbm.lock().unwrap();
+        let mut carrier = self.carrier.lock().unwrap();
+        let mut rejected = Vec::new();
+
+        for uuid in due {
+            let tracker = match dbm.load_tracker(uuid) {
+                Some(tracker) if !self.is_superseded(uuid) => tracker,
+                _ => continue,
+            };
+            log::info!(
+                "Broadcasting delayed penalty transaction: {}",
+                tracker.penalty_tx.txid()
+            );
+            match carrier.send_transaction(&tracker.penalty_tx) {
+                ConfirmationStatus::Rejected(_) => rejected.push(uuid),
+                status => dbm.update_tracker_status(uuid, &status).unwrap(),
+            }
+        }
+
+        (!rejected.is_empty()).then_some(rejected)
+    }
+
+    /// Rebroadcasts the penalties that have been evicted from the mempool before getting confirmed.
+    ///
+    /// Penalties can be dropped from the mempool between being broadcast and being confirmed (e.g. due to a low fee when
+    /// the mempool is full). Those found neither in the mempool nor in the chain are rebroadcast straightaway, instead
+    /// of waiting for them to miss [CONFIRMATIONS_BEFORE_RETRY] confirmations, and fee-bumped if possible (see
+    /// [Responder::bump_fee]). Penalties due to be rebroadcast anyway (see [Responder::rebroadcast_stale_txs]) are left
+    /// alone, and so is the status of the rebroadcast ones, so the periodic rebroadcasting keeps working as usual.
+    ///
+    /// Returns a vector of rejected trackers during rebroadcast if any were rejected, [None] otherwise.
+    fn rebroadcast_evicted_txs(&self, height: u32) -> Option<Vec<UUID>> {
+        let dbm = self.dbm.lock().unwrap();
+        let mut carrier = self.carrier.lock().unwrap();
+        let tx_index = self.tx_index.lock().unwrap();
+        let mut rejected = Vec::new();
+
+        for uuid in dbm
+            .load_trackers_with_confirmation_status(ConfirmationStatus::InMempoolSince(height))
+            .unwrap()
+        {
+            let tracker = dbm.load_tracker(uuid).unwrap();
+            let penalty_txid = tracker.penalty_tx.txid();
+            let stale = matches!(tracker.status, ConfirmationStatus::InMempoolSince(h)
+                if height.saturating_sub(h) >= CONFIRMATIONS_BEFORE_RETRY as u32);
+            if stale
+                || self.is_scheduled(uuid)
+                || self.is_superseded(uuid)
+                || tx_index.get(&penalty_txid).is_some()
+                || carrier.in_mempool(&penalty_txid)
+            {
+                continue;
+            }
+
+            log::warn!("Penalty transaction was evicted from the mempool: {penalty_txid}");
+            if carrier.resend_transaction(&tracker.penalty_tx).accepted() {
+                if let Some(bump) = self.bump_fee(&mut carrier, &tracker) {
+                    dbm.store_fee_bump(uuid, &bump).unwrap_or_else(|e| {
+                        log::error!(
+                            "Failed to add fee-bump to the ledger (uuid={uuid}). Error: {e:?}"
+                        )
+                    });
+                }
+            } else {
+                rejected.push(uuid);
+            }
+        }
+
+        (!rejected.is_empty()).then_some(rejected)
+    }
+
     /// Rebroadcasts a list of penalty transactions that have missed too many confirmations.
     ///
     /// This covers the case where a transaction is not getting confirmations (most likely due to low
-    /// fess and needs to be bumped, but there is not much we can do until anchors).
+    /// fess and needs to be bumped). If the [Responder] holds [AnchorMaterial] and the penalty has an anchor
+    /// output, the penalty is also fee-bumped via CPFP (see [Responder::bump_fee]).
     ///
     /// Returns a vector of rejected trackers during rebroadcast if any were rejected, [None] otherwise.
     fn rebroadcast_stale_txs(&self, height: u32) -> Option<Vec<UUID>> {
@@ -364,6 +1403,8 @@ impl Responder {
         for uuid in dbm
             .load_trackers_with_confirmation_status(stale_confirmation_status)
             .unwrap()
+            .into_iter()
+            .filter(|uuid| !self.is_scheduled(*uuid) && !self.is_superseded(*uuid))
         {
             let tracker = dbm.load_tracker(uuid).unwrap();
             log::warn!(
@@ -380,11 +1421,99 @@ impl Responder {
                 // We might want to replace `ConfirmationStatus::IrrevocablyResolved` variant with
                 // `ConfirmationStatus::ConfirmedIn(height - IRREVOCABLY_RESOLVED)
                 dbm.update_tracker_status(uuid, &status).unwrap();
+                if let Some(bump) = self.bump_fee(&mut carrier, &tracker) {
+                    dbm.store_fee_bump(uuid, &bump).unwrap_or_else(|e| {
+                        log::error!(
+                            "Failed to add fee-bump to the ledger (uuid={uuid}). Error: {e:?}"
+                        )
+                    });
+                }
             }
         }
 
         (!rejected.is_empty()).then_some(rejected)
     }
+
+    /// Rebroadcasts, right away, the penalties of all the trackers that have not been confirmed yet.
+    ///
+    /// Meant for when penalties are known to have been wiped from the mempool, instead of waiting for them to miss
+    /// [CONFIRMATIONS_BEFORE_RETRY] confirmations. Confirmed penalties are left untouched, and so is the status of the
+    /// rebroadcast ones, so the periodic rebroadcasting (and fee-bumping) keeps working as usual. Penalties whose
+    /// broadcast is still scheduled, or whose inputs have been spent by a competing transaction, are skipped as well.
+    ///
+    /// Returns the trackers whose penalty was rebroadcast and the ones whose penalty was rejected.
+    pub(crate) fn rebroadcast_all(&self) -> (Vec<UUID>, Vec<UUID>) {
+        let dbm = self.dbm.lock().unwrap();
+        let mut carrier = self.carrier.lock().unwrap();
+        let mut rebroadcast = Vec::new();
+        let mut rejected = Vec::new();
+
+        for uuid in dbm
+            .load_trackers_with_confirmation_status(ConfirmationStatus::InMempoolSince(
+                carrier.block_height(),
+            ))
+            .unwrap()
+            .into_iter()
+            .filter(|uuid| !self.is_scheduled(*uuid) && !self.is_superseded(*uuid))
+        {
+            let tracker = dbm.load_tracker(uuid).unwrap();
+            log::info!(
+                "Manually rebroadcasting penalty transaction: {}",
+                tracker.penalty_tx.txid()
+            );
+            if carrier.resend_transaction(&tracker.penalty_tx).accepted() {
+                rebroadcast.push(uuid);
+            } else {
+                rejected.push(uuid);
+            }
+        }
+
+        (rebroadcast, rejected)
+    }
+
+    /// Fee-bumps a stuck penalty by broadcasting a child transaction that spends its anchor output (CPFP).
+    ///
+    /// This is a no-op if the [Responder] holds no [AnchorMaterial], the penalty has no anchor, or no feerate
+    /// estimate is available. Returns the details of the bump if the child transaction was accepted by the node.
+    fn bump_fee(&self, carrier: &mut Carrier, tracker: &TransactionTracker) -> Option<FeeBump> {
+        let anchor_material = self.anchor_material.as_ref()?;
+        let (_, anchor_value) = anchor_material.find_anchor(&tracker.penalty_tx)?;
+
+        let target_feerate = carrier.estimate_feerate(CPFP_CONFIRMATION_TARGET)?;
+        let parent_fee = anchors::penalty_fee(&tracker.dispute_tx, &tracker.penalty_tx);
+        let child =
+            match anchor_material.build_cpfp(&tracker.penalty_tx, parent_fee, target_feerate) {
+                Some(child) => child,
+                None => {
+                    log::info!(
+                        "Penalty transaction cannot be fee-bumped further: {}",
+                        tracker.penalty_tx.txid()
+                    );
+                    return None;
+                }
+            };
+
+        let child_fee = anchor_value - child.output[0].value;
+        log::info!(
+            "Fee-bumping penalty transaction {} via CPFP (child={}, package feerate={:.2} sat/vB)",
+            tracker.penalty_tx.txid(),
+            child.txid(),
+            anchors::package_feerate(parent_fee + child_fee, &[&tracker.penalty_tx, &child])
+        );
+
+        carrier
+            .send_transaction(&child)
+            .accepted()
+            .then(|| FeeBump {
+                txid: child.txid(),
+                feerate: target_feerate,
+                fee: child_fee,
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            })
+    }
 }
 
 /// Listen implementation by the [Responder]. Handles monitoring and reorgs.
@@ -398,7 +1527,7 @@ impl chain::Listen for Responder {
     ///
     /// Every time a block is received the tracking conditions are checked against the monitored [TransactionTracker]s and
     /// data deletion is performed accordingly. Moreover, lack of confirmations is check for the tracked transactions and
-    /// rebroadcasting is performed for those that have missed too many.
+    /// rebroadcasting is performed for those that have missed too many, or that have been evicted from the mempool.
     fn filtered_block_connected(
         &self,
         header: &BlockHeader,
@@ -406,7 +1535,13 @@ impl chain::Listen for Responder {
         height: u32,
     ) {
         log::info!("New block received: {}", header.block_hash());
-        self.carrier.lock().unwrap().update_height(height);
+        {
+            let mut carrier = self.carrier.lock().unwrap();
+            carrier.update_height(height);
+            // bitcoind is reachable again if blocks are coming through, so it can take what was broadcast without it.
+            carrier.reconcile_fallback_broadcasts();
+        }
+        self.update_fee_estimates();
 
         let txs = txdata
             .iter()
@@ -416,6 +1551,13 @@ impl chain::Listen for Responder {
 
         // Delete trackers completed at this height
         if let Some(trackers) = self.check_confirmations(txs.keys().cloned().collect(), height) {
+            self.update_penalties_status(&trackers, PenaltyStatus::Resolved);
+            self.gatekeeper.delete_appointments(trackers, true);
+        }
+
+        // Stop tracking penalties whose inputs have been spent by someone else for good
+        if let Some(trackers) = self.check_superseded(txdata, height) {
+            self.update_penalties_status(&trackers, PenaltyStatus::Superseded);
             self.gatekeeper.delete_appointments(trackers, true);
         }
 
@@ -429,12 +1571,27 @@ impl chain::Listen for Responder {
             }
         }
 
-        // Rebroadcast those transactions that need to
+        // Give up on the penalties that can never make it to the chain instead of rebroadcasting them
+        if let Some(trackers) = self.check_stale_penalties(height) {
+            self.update_penalties_status(&trackers, PenaltyStatus::Invalid);
+            self.gatekeeper.delete_appointments(trackers, false);
+        }
+
+        // Broadcast the delayed penalties that are due
+        if let Some(trackers) = self.broadcast_scheduled_txs(height) {
+            trackers_to_delete.extend(trackers);
+        }
+
+        // Rebroadcast the penalties that have been dropped from the mempool, and those that have missed too many confirmations
+        if let Some(trackers) = self.rebroadcast_evicted_txs(height) {
+            trackers_to_delete.extend(trackers);
+        }
         if let Some(trackers) = self.rebroadcast_stale_txs(height) {
             trackers_to_delete.extend(trackers);
         }
 
         if !trackers_to_delete.is_empty() {
+            self.update_penalties_status(&trackers_to_delete, PenaltyStatus::Rejected);
             self.gatekeeper
                 .delete_appointments(trackers_to_delete, false);
         }
@@ -463,6 +1620,11 @@ impl chain::Listen for Responder {
                 .load_trackers_with_confirmation_status(ConfirmationStatus::ConfirmedIn(height))
                 .unwrap(),
         );
+        // Penalties superseded by a transaction in the disconnected block are valid again.
+        self.superseding_txs
+            .lock()
+            .unwrap()
+            .retain(|_, (_, h)| *h < height);
     }
 }
 
@@ -470,24 +1632,33 @@ impl chain::Listen for Responder {
 mod tests {
     use super::*;
     use lightning::chain::Listen;
-    use teos_common::appointment::Locator;
 
     use std::collections::HashMap;
     use std::iter::FromIterator;
     use std::sync::{Arc, Mutex};
 
+    use crate::confirmations::{Confirmer, CorroborationPolicy};
     use crate::dbm::DBM;
+    use crate::esplora::EsploraClient;
+    use crate::gatekeeper::UserInfo;
     use crate::rpc_errors;
     use crate::test_utils::{
         create_carrier, generate_dummy_appointment, generate_dummy_appointment_with_user,
-        generate_uuid, get_last_n_blocks, get_random_breach, get_random_tracker, get_random_tx,
-        store_appointment_and_its_user, BitcoindStopper, Blockchain, MockedServerQuery, DURATION,
-        EXPIRY_DELTA, SLOTS, START_HEIGHT,
+        generate_uuid, get_anchor_penalty, get_last_n_blocks, get_random_breach,
+        get_random_tracker, get_random_tx, store_appointment_and_its_user, BitcoindStopper,
+        Blockchain, MockedServerQuery, DURATION, EXPIRY_DELTA, MAX_EXPIRY_HORIZON, MOCKED_FEERATE,
+        RENEWAL_WINDOW, SLOTS, START_HEIGHT,
     };
 
     use teos_common::constants::IRREVOCABLY_RESOLVED;
+    use teos_common::cryptography::get_random_keypair;
     use teos_common::test_utils::get_random_user_id;
 
+    use bitcoin::consensus::encode::serialize_hex;
+    use bitcoin::secp256k1::{Message, Secp256k1};
+    use bitcoin::util::sighash::SighashCache;
+    use bitcoin::Witness;
+
     impl TransactionTracker {
         pub fn locator(&self) -> Locator {
             Locator::new(self.dispute_tx.txid())
@@ -548,142 +1719,841 @@ mod tests {
         }
     }
 
-    async fn create_responder(
-        chain: &mut Blockchain,
-        gatekeeper: Arc<Gatekeeper>,
-        dbm: Arc<Mutex<DBM>>,
-        query: MockedServerQuery,
-    ) -> (Responder, BitcoindStopper) {
-        let height = if chain.tip().height < IRREVOCABLY_RESOLVED {
-            chain.tip().height
-        } else {
-            IRREVOCABLY_RESOLVED
+    async fn create_responder(
+        chain: &mut Blockchain,
+        gatekeeper: Arc<Gatekeeper>,
+        dbm: Arc<Mutex<DBM>>,
+        query: MockedServerQuery,
+    ) -> (Responder, BitcoindStopper) {
+        let height = if chain.tip().height < IRREVOCABLY_RESOLVED {
+            chain.tip().height
+        } else {
+            IRREVOCABLY_RESOLVED
+        };
+
+        let last_n_blocks = get_last_n_blocks(chain, height as usize).await;
+
+        let (carrier, bitcoind_stopper) = create_carrier(query, chain.tip().height);
+        (
+            Responder::new(
+                &last_n_blocks,
+                chain.tip().height,
+                carrier,
+                gatekeeper,
+                dbm,
+                None,
+                0,
+            ),
+            bitcoind_stopper,
+        )
+    }
+
+    async fn init_responder_with_chain_and_dbm(
+        mocked_query: MockedServerQuery,
+        chain: &mut Blockchain,
+        dbm: Arc<Mutex<DBM>>,
+    ) -> (Responder, BitcoindStopper) {
+        let gk = Gatekeeper::new(
+            chain.get_block_count(),
+            SLOTS,
+            DURATION,
+            EXPIRY_DELTA,
+            RENEWAL_WINDOW,
+            MAX_EXPIRY_HORIZON,
+            dbm.clone(),
+        );
+        create_responder(chain, Arc::new(gk), dbm, mocked_query).await
+    }
+
+    async fn init_responder(mocked_query: MockedServerQuery) -> (Responder, BitcoindStopper) {
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
+        init_responder_with_chain_and_dbm(mocked_query, &mut chain, dbm).await
+    }
+
+    #[test]
+    fn test_confirmation_status_from_db_data() {
+        // These are pretty simple tests. The db can only store trackers with a confirmation status
+        // that's either ConfirmedIn or InMempoolSince (Rejected and IrrevocablyResolved are never passed to store).
+        let h = 21;
+
+        assert_eq!(
+            ConfirmationStatus::from_db_data(h, true),
+            ConfirmationStatus::ConfirmedIn(h)
+        );
+        assert_eq!(
+            ConfirmationStatus::from_db_data(h, false),
+            ConfirmationStatus::InMempoolSince(h)
+        );
+    }
+
+    #[test]
+    fn test_confirmation_status_to_db_data() {
+        // Analogous to the previous test, this will only construct ConfirmedIn and InMempolSince statuses.
+        // The None case has to be threaten though.
+        let h = 21;
+
+        assert_eq!(
+            ConfirmationStatus::ConfirmedIn(h).to_db_data(),
+            Some((h, true))
+        );
+        assert_eq!(
+            ConfirmationStatus::InMempoolSince(h).to_db_data(),
+            Some((h, false))
+        );
+        assert_eq!(ConfirmationStatus::Rejected(0).to_db_data(), None);
+        assert_eq!(ConfirmationStatus::IrrevocablyResolved.to_db_data(), None);
+    }
+
+    #[tokio::test]
+    async fn test_responder_new() {
+        // A fresh responder has no associated data
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let (responder, _s) =
+            init_responder_with_chain_and_dbm(MockedServerQuery::Regular, &mut chain, dbm.clone())
+                .await;
+        assert!(responder.is_fresh());
+
+        // If we add some trackers to the system and create a new Responder reusing the same db
+        // (as if simulating a bootstrap from existing data), the data should be properly loaded.
+        for i in 0..10 {
+            let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+            let breach = get_random_breach();
+            let s = if i % 2 == 0 {
+                ConfirmationStatus::InMempoolSince(i)
+            } else {
+                ConfirmationStatus::ConfirmedIn(i)
+            };
+            responder.add_tracker(uuid, breach.clone(), user_id, s);
+        }
+
+        // Create a new Responder reusing the same DB and check that the data is loaded
+        let (another_r, _) =
+            init_responder_with_chain_and_dbm(MockedServerQuery::Regular, &mut chain, dbm).await;
+        assert!(!responder.is_fresh());
+        assert_eq!(responder, another_r);
+    }
+
+    #[tokio::test]
+    async fn test_handle_breach_accepted() {
+        let start_height = START_HEIGHT as u32;
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_random_breach();
+
+        assert_eq!(
+            responder.handle_breach(uuid, breach, user_id),
+            ConfirmationStatus::InMempoolSince(start_height)
+        );
+        let tracker = responder.dbm.lock().unwrap().load_tracker(uuid).unwrap();
+        assert_eq!(
+            tracker.status,
+            ConfirmationStatus::InMempoolSince(start_height)
+        );
+
+        // Breaches won't be overwritten once passed to the Responder. If the same UUID is
+        // passed twice, the receipt corresponding to the first breach will be handed back.
+        let another_breach = get_random_breach();
+        assert_eq!(
+            responder.handle_breach(uuid, another_breach, user_id),
+            ConfirmationStatus::InMempoolSince(start_height)
+        );
+        // Getting the tracker should return the old one.
+        assert_eq!(
+            tracker,
+            responder.dbm.lock().unwrap().load_tracker(uuid).unwrap()
+        );
+    }
+
+    // Multi-threaded, given the fallback broadcast blocks the thread it is run from until the mocked API answers.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_handle_breach_broadcast_fallback() {
+        let start_height = START_HEIGHT as u32;
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_random_breach();
+
+        let mut esplora = mockito::Server::new_async().await;
+        let esplora_mock = esplora
+            .mock("POST", "/tx")
+            .match_body(serialize_hex(&breach.penalty_tx).as_str())
+            .with_status(200)
+            .expect(1)
+            .create_async()
+            .await;
+
+        // bitcoind is unreachable, so the penalty is pushed through the fallback without being checked against it
+        let (carrier, _fallback_stopper) = create_carrier(MockedServerQuery::Regular, start_height);
+        let carrier =
+            carrier.with_broadcast_fallback(Some(EsploraClient::new(&esplora.url()).unwrap()));
+        carrier.set_bitcoind_unreachable();
+        *responder.carrier.lock().unwrap() = carrier;
+
+        assert_eq!(
+            responder.handle_breach(uuid, breach, user_id),
+            ConfirmationStatus::InMempoolSince(start_height)
+        );
+        esplora_mock.assert_async().await;
+        assert_eq!(
+            responder
+                .dbm
+                .lock()
+                .unwrap()
+                .load_tracker(uuid)
+                .unwrap()
+                .status,
+            ConfirmationStatus::InMempoolSince(start_height)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_breach_delayed_broadcast() {
+        let start_height = START_HEIGHT as u32;
+        let max_delay = 10;
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let responder = responder.with_max_broadcast_delay(max_delay);
+
+        // Delays are random up to the configured maximum, but never past half the to_self_delay of the channel
+        let mut scheduled = HashMap::new();
+        for (to_self_delay, bound) in [(8, 4), (1000, max_delay)] {
+            for _ in 0..20 {
+                let mut appointment = generate_dummy_appointment(None);
+                appointment.inner.to_self_delay = to_self_delay;
+                store_appointment_and_its_user(&responder.dbm.lock().unwrap(), &appointment);
+                let (uuid, user_id) = (appointment.uuid(), appointment.user_id);
+                let breach = get_random_breach();
+                let penalty_txid = breach.penalty_tx.txid();
+
+                // Delayed or not, penalties are tracked straightaway
+                assert_eq!(
+                    responder.handle_breach(uuid, breach, user_id),
+                    ConfirmationStatus::InMempoolSince(start_height)
+                );
+                assert!(responder.has_tracker(uuid));
+
+                let broadcast = responder
+                    .get_carrier()
+                    .lock()
+                    .unwrap()
+                    .get_issued_receipts()
+                    .contains_key(&penalty_txid);
+                match responder.scheduled_broadcasts.lock().unwrap().get(&uuid) {
+                    Some(due) => {
+                        assert!(!broadcast);
+                        assert!(*due > start_height && *due <= start_height + bound);
+                        scheduled.insert(uuid, (*due, penalty_txid));
+                    }
+                    None => assert!(broadcast),
+                }
+            }
+        }
+        assert!(!scheduled.is_empty());
+
+        // Delayed penalties are neither rebroadcast as evicted nor as stale while they wait
+        responder.get_carrier().lock().unwrap().clear_receipts();
+        assert!(responder
+            .rebroadcast_evicted_txs(start_height + 1)
+            .is_none());
+        assert!(responder
+            .rebroadcast_stale_txs(start_height + CONFIRMATIONS_BEFORE_RETRY as u32)
+            .is_none());
+        for (_, penalty_txid) in scheduled.values() {
+            assert!(!responder
+                .get_carrier()
+                .lock()
+                .unwrap()
+                .get_issued_receipts()
+                .contains_key(penalty_txid));
+        }
+
+        // Each delayed penalty is broadcast once its height is reached, and it is given the usual blocks to confirm
+        for height in start_height + 1..=start_height + max_delay {
+            responder
+                .get_carrier()
+                .lock()
+                .unwrap()
+                .update_height(height);
+            assert!(responder.broadcast_scheduled_txs(height).is_none());
+            let receipts = responder
+                .get_carrier()
+                .lock()
+                .unwrap()
+                .get_issued_receipts()
+                .clone();
+            for (uuid, (due, penalty_txid)) in scheduled.iter() {
+                assert_eq!(receipts.contains_key(penalty_txid), *due <= height);
+                if *due <= height {
+                    assert_eq!(
+                        responder
+                            .dbm
+                            .lock()
+                            .unwrap()
+                            .load_tracker(*uuid)
+                            .unwrap()
+                            .status,
+                        ConfirmationStatus::InMempoolSince(*due)
+                    );
+                }
+            }
+        }
+        assert!(responder.scheduled_broadcasts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handle_breach_penalty_ledger() {
+        let start_height = START_HEIGHT as u32;
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_random_breach();
+        let dispute_txid = breach.dispute_tx.txid();
+        let penalty_txid = breach.penalty_tx.txid();
+        let value: u64 = breach.penalty_tx.output.iter().map(|o| o.value).sum();
+
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        responder.handle_breach(uuid, breach, user_id);
+
+        // Responding to the breach creates a ledger entry
+        let penalties = responder.get_penalties(None, None);
+        assert_eq!(penalties.len(), 1);
+        let record = penalties[0].clone();
+        assert_eq!(record.uuid, uuid);
+        assert_eq!(record.user_id, user_id);
+        assert_eq!(record.dispute_txid, dispute_txid);
+        assert_eq!(record.penalty_txid, penalty_txid);
+        assert_eq!(record.value, value);
+        assert!(record.timestamp >= before);
+        assert_eq!(record.status, PenaltyStatus::Broadcast);
+
+        // The time range is honored
+        assert_eq!(
+            responder.get_penalties(Some(record.timestamp), Some(record.timestamp)),
+            vec![record.clone()]
+        );
+        assert!(responder
+            .get_penalties(Some(record.timestamp + 1), None)
+            .is_empty());
+        assert!(responder
+            .get_penalties(None, Some(record.timestamp - 1))
+            .is_empty());
+
+        // The status is updated once the penalty confirms
+        responder.check_confirmations(HashSet::from_iter([penalty_txid]), start_height + 1);
+        assert_eq!(
+            responder.get_penalties(None, None)[0].status,
+            PenaltyStatus::Confirmed
+        );
+
+        // And the entry is kept once the tracker is gone
+        responder.gatekeeper.delete_appointments(vec![uuid], false);
+        assert!(responder.dbm.lock().unwrap().load_tracker(uuid).is_none());
+        assert_eq!(responder.get_penalties(None, None).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_recover_penalty() {
+        let (mut responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_random_breach();
+
+        // A corrupted blob cannot be turned into a penalty, so the breach is recorded as unconstructable
+        let mut corrupted_blob =
+            cryptography::encrypt(&breach.penalty_tx, &breach.dispute_tx.txid()).unwrap();
+        corrupted_blob.reverse();
+        let error = cryptography::decrypt(&corrupted_blob, &breach.dispute_tx.txid()).unwrap_err();
+        assert_eq!(
+            responder.recover_penalty(uuid, &breach.dispute_tx, &corrupted_blob, user_id, error),
+            None
+        );
+        let penalties = responder.get_penalties(None, None);
+        assert_eq!(penalties.len(), 1);
+        let record = penalties[0].clone();
+        assert_eq!(record.uuid, uuid);
+        assert_eq!(record.dispute_txid, breach.dispute_tx.txid());
+        assert_eq!(record.penalty_txid, Txid::from_inner([0; 32]));
+        assert_eq!(record.value, 0);
+        assert_eq!(record.status, PenaltyStatus::Unconstructable);
+
+        // A blob encrypted with the byte-reversed dispute txid is only recovered if the policy says so
+        let mut reversed_txid = breach.dispute_tx.txid().into_inner();
+        reversed_txid.reverse();
+        let blob =
+            cryptography::encrypt(&breach.penalty_tx, &Txid::from_inner(reversed_txid)).unwrap();
+        let decrypt_error = || cryptography::decrypt(&blob, &breach.dispute_tx.txid()).unwrap_err();
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        assert_eq!(
+            responder.recover_penalty(uuid, &breach.dispute_tx, &blob, user_id, decrypt_error()),
+            None
+        );
+
+        responder.unconstructable_policy = UnconstructablePenaltyPolicy::Recover;
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        assert_eq!(
+            responder.recover_penalty(uuid, &breach.dispute_tx, &blob, user_id, decrypt_error()),
+            Some(breach.penalty_tx.clone())
+        );
+        assert!(!responder
+            .get_penalties(None, None)
+            .iter()
+            .any(|record| record.uuid == uuid));
+
+        // Blobs that cannot be recovered are still recorded
+        let error = cryptography::decrypt(&corrupted_blob, &breach.dispute_tx.txid()).unwrap_err();
+        assert_eq!(
+            responder.recover_penalty(uuid, &breach.dispute_tx, &corrupted_blob, user_id, error),
+            None
+        );
+        assert_eq!(
+            responder
+                .get_penalties(None, None)
+                .iter()
+                .filter(|record| record.status == PenaltyStatus::Unconstructable)
+                .count(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_breach_uneconomical() {
+        let start_height = START_HEIGHT as u32;
+        let (mut responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        responder.min_penalty_value = 1_000;
+
+        // The fees needed to confirm the penalty at the current feerate are discounted from its value. A penalty worth more
+        // than the threshold may therefore still be skipped.
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let mut breach = get_random_breach();
+        let fee = MOCKED_FEERATE * anchors::vsize(&breach.penalty_tx);
+        breach.penalty_tx.output[0].value = responder.min_penalty_value + fee - 1;
+        let locator = Locator::new(breach.dispute_tx.txid());
+        let penalty_txid = breach.penalty_tx.txid();
+        let mut events = responder.subscribe();
+
+        assert_eq!(
+            responder.handle_breach(uuid, breach, user_id),
+            ConfirmationStatus::Rejected(errors::RPC_PENALTY_UNECONOMICAL)
+        );
+        assert_eq!(
+            events.try_recv().unwrap(),
+            ResponderEvent::PenaltySkipped {
+                locator,
+                user_id,
+                penalty_txid
+            }
+        );
+        assert!(responder.dbm.lock().unwrap().load_tracker(uuid).is_none());
+        assert!(!responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .contains_key(&penalty_txid));
+        let penalties = responder.get_penalties(None, None);
+        assert_eq!(penalties.len(), 1);
+        assert_eq!(penalties[0].uuid, uuid);
+        assert_eq!(penalties[0].penalty_txid, penalty_txid);
+        assert_eq!(penalties[0].status, PenaltyStatus::Skipped);
+
+        // Penalties recovering enough are broadcast as usual
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let mut breach = get_random_breach();
+        let fee = MOCKED_FEERATE * anchors::vsize(&breach.penalty_tx);
+        breach.penalty_tx.output[0].value = responder.min_penalty_value + fee;
+
+        assert_eq!(
+            responder.handle_breach(uuid, breach, user_id),
+            ConfirmationStatus::InMempoolSince(start_height)
+        );
+        assert!(responder.dbm.lock().unwrap().load_tracker(uuid).is_some());
+        let record = responder
+            .get_penalties(None, None)
+            .into_iter()
+            .find(|r| r.uuid == uuid)
+            .unwrap();
+        assert_eq!(record.status, PenaltyStatus::Broadcast);
+
+        // The fee the penalty already pays is not discounted twice
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let mut breach = get_random_breach();
+        let fee = MOCKED_FEERATE * anchors::vsize(&breach.penalty_tx);
+        breach.dispute_tx.output[0].value = responder.min_penalty_value + fee;
+        breach.penalty_tx.input[0].previous_output = OutPoint::new(breach.dispute_tx.txid(), 0);
+        breach.penalty_tx.output[0].value = responder.min_penalty_value;
+        assert_eq!(breach.unallocated_value(), fee);
+
+        assert_eq!(
+            responder.handle_breach(uuid, breach, user_id),
+            ConfirmationStatus::InMempoolSince(start_height)
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handle_breach_reward() {
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let reward_script = AnchorMaterial::new(get_random_keypair().0)
+            .script_pubkey()
+            .clone();
+        let responder = responder.with_reward_script(reward_script.clone());
+        let reward = 1_000;
+        let reward_output = TxOut {
+            value: reward,
+            script_pubkey: reward_script.clone(),
+        };
+
+        // The penalties spend a P2WPKH dispute output so their signatures can be checked against the final transaction
+        let secp = Secp256k1::new();
+        let (sk, pk) = get_random_keypair();
+        let pk = bitcoin::PublicKey::new(pk);
+        let script_code = Script::new_p2pkh(&pk.pubkey_hash());
+        let dispute_value = 100_000;
+
+        // Builds a breach whose penalty, signed using `sighash_type`, leaves `unallocated` sats of the dispute output
+        // it spends unassigned.
+        let get_breach = |unallocated: u64, sighash_type: EcdsaSighashType| {
+            let mut breach = get_random_breach();
+            breach.dispute_tx.output[0] = TxOut {
+                value: dispute_value,
+                script_pubkey: Script::new_v0_p2wpkh(&pk.wpubkey_hash().unwrap()),
+            };
+            breach.penalty_tx.input[0].previous_output = OutPoint::new(breach.dispute_tx.txid(), 0);
+            breach.penalty_tx.output[0].value = dispute_value - unallocated;
+            let sighash = SighashCache::new(&breach.penalty_tx)
+                .segwit_signature_hash(0, &script_code, dispute_value, sighash_type)
+                .unwrap();
+            let sig = EcdsaSig {
+                sig: secp.sign_ecdsa(&Message::from_slice(&sighash).unwrap(), &sk),
+                hash_ty: sighash_type,
+            };
+            breach.penalty_tx.input[0].witness =
+                Witness::from_vec(vec![sig.to_vec(), pk.to_bytes()]);
+            breach
+        };
+        // Checks whether the signature of the penalty input is valid for the given transaction
+        let verify = |tx: &Transaction| {
+            let sig = EcdsaSig::from_slice(&tx.input[0].witness.to_vec()[0]).unwrap();
+            let sighash = SighashCache::new(tx)
+                .segwit_signature_hash(0, &script_code, dispute_value, sig.hash_ty)
+                .unwrap();
+            secp.verify_ecdsa(&Message::from_slice(&sighash).unwrap(), &sig.sig, &pk.inner)
+                .is_ok()
         };
 
-        let last_n_blocks = get_last_n_blocks(chain, height as usize).await;
+        // The fee the penalty needs to pay once the reward output is added (its size does not depend on the values).
+        // The largest possible signature is used, so the actual penalties may be at most a vbyte smaller.
+        let mut rewarded_tx = get_breach(0, EcdsaSighashType::SinglePlusAnyoneCanPay).penalty_tx;
+        rewarded_tx.input[0].witness = Witness::from_vec(vec![vec![0; 73], pk.to_bytes()]);
+        rewarded_tx.output.push(reward_output.clone());
+        let fee = MOCKED_FEERATE * anchors::vsize(&rewarded_tx);
 
-        let (carrier, bitcoind_stopper) = create_carrier(query, chain.tip().height);
-        (
-            Responder::new(&last_n_blocks, chain.tip().height, carrier, gatekeeper, dbm),
-            bitcoind_stopper,
-        )
-    }
+        // Penalties of appointments without reward terms are broadcast as handed by the user
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_breach(reward + fee, EcdsaSighashType::SinglePlusAnyoneCanPay);
+        let penalty_tx = breach.penalty_tx.clone();
+        assert!(responder.handle_breach(uuid, breach, user_id).accepted());
+        let tracker = responder.dbm.lock().unwrap().load_tracker(uuid).unwrap();
+        assert_eq!(tracker.penalty_tx, penalty_tx);
 
-    async fn init_responder_with_chain_and_dbm(
-        mocked_query: MockedServerQuery,
-        chain: &mut Blockchain,
-        dbm: Arc<Mutex<DBM>>,
-    ) -> (Responder, BitcoindStopper) {
-        let gk = Gatekeeper::new(
-            chain.get_block_count(),
-            SLOTS,
-            DURATION,
-            EXPIRY_DELTA,
-            dbm.clone(),
+        // Penalties of appointments with reward terms get an output paying the reward, which does not invalidate
+        // their signatures
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        responder
+            .dbm
+            .lock()
+            .unwrap()
+            .store_appointment_reward(uuid, Some(reward))
+            .unwrap();
+        let breach = get_breach(reward + fee, EcdsaSighashType::SinglePlusAnyoneCanPay);
+        let penalty_tx = breach.penalty_tx.clone();
+        assert!(responder.handle_breach(uuid, breach, user_id).accepted());
+        let tracker = responder.dbm.lock().unwrap().load_tracker(uuid).unwrap();
+        assert_eq!(tracker.penalty_tx.input, penalty_tx.input);
+        assert_eq!(tracker.penalty_tx.output[0], penalty_tx.output[0]);
+        assert_eq!(tracker.penalty_tx.output[1], reward_output);
+        assert!(verify(&tracker.penalty_tx));
+        assert_eq!(
+            responder
+                .get_penalties(None, None)
+                .into_iter()
+                .find(|r| r.uuid == uuid)
+                .unwrap()
+                .penalty_txid,
+            tracker.penalty_tx.txid()
         );
-        create_responder(chain, Arc::new(gk), dbm, mocked_query).await
-    }
 
-    async fn init_responder(mocked_query: MockedServerQuery) -> (Responder, BitcoindStopper) {
-        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
-        let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
-        init_responder_with_chain_and_dbm(mocked_query, &mut chain, dbm).await
+        // Unless the penalty is signed using a sighash that commits to all the outputs, given adding one would
+        // invalidate the signature
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        responder
+            .dbm
+            .lock()
+            .unwrap()
+            .store_appointment_reward(uuid, Some(reward))
+            .unwrap();
+        let breach = get_breach(reward + fee, EcdsaSighashType::All);
+        let penalty_tx = breach.penalty_tx.clone();
+        let mut modified_tx = penalty_tx.clone();
+        modified_tx.output.push(reward_output.clone());
+        assert!(verify(&penalty_tx));
+        assert!(!verify(&modified_tx));
+        assert!(responder.handle_breach(uuid, breach, user_id).accepted());
+        let tracker = responder.dbm.lock().unwrap().load_tracker(uuid).unwrap();
+        assert_eq!(tracker.penalty_tx, penalty_tx);
+
+        // Or it does not leave enough value to cover the reward and the fees at the current feerate
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        responder
+            .dbm
+            .lock()
+            .unwrap()
+            .store_appointment_reward(uuid, Some(reward))
+            .unwrap();
+        let breach = get_breach(
+            reward + fee - MOCKED_FEERATE - 1,
+            EcdsaSighashType::SinglePlusAnyoneCanPay,
+        );
+        let penalty_tx = breach.penalty_tx.clone();
+        assert!(responder.handle_breach(uuid, breach, user_id).accepted());
+        let tracker = responder.dbm.lock().unwrap().load_tracker(uuid).unwrap();
+        assert_eq!(tracker.penalty_tx, penalty_tx);
     }
 
-    #[test]
-    fn test_confirmation_status_from_db_data() {
-        // These are pretty simple tests. The db can only store trackers with a confirmation status
-        // that's either ConfirmedIn or InMempoolSince (Rejected and IrrevocablyResolved are never passed to store).
-        let h = 21;
+    #[tokio::test]
+    async fn test_handle_breach_lapsed_subscription() {
+        let start_height = START_HEIGHT as u32;
+        let (mut responder, _s) = init_responder(MockedServerQuery::Regular).await;
 
+        // Breaches seen after the subscription expired (but before the appointments are deleted) are responded to by
+        // default
+        let set_expiry = |responder: &Responder, user_id, expiry| {
+            responder
+                .gatekeeper
+                .get_registered_users()
+                .lock()
+                .unwrap()
+                .insert(user_id, UserInfo::new(SLOTS, 0, expiry));
+        };
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        set_expiry(&responder, user_id, start_height);
         assert_eq!(
-            ConfirmationStatus::from_db_data(h, true),
-            ConfirmationStatus::ConfirmedIn(h)
+            responder.handle_breach(uuid, get_random_breach(), user_id),
+            ConfirmationStatus::InMempoolSince(start_height)
         );
+        assert!(responder.has_tracker(uuid));
         assert_eq!(
-            ConfirmationStatus::from_db_data(h, false),
-            ConfirmationStatus::InMempoolSince(h)
+            responder.get_penalties(None, None)[0].status,
+            PenaltyStatus::Broadcast
         );
-    }
-
-    #[test]
-    fn test_confirmation_status_to_db_data() {
-        // Analogous to the previous test, this will only construct ConfirmedIn and InMempolSince statuses.
-        // The None case has to be threaten though.
-        let h = 21;
 
+        // Unless told to decline them
+        responder.lapsed_policy = LapsedSubscriptionPolicy::Decline;
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        set_expiry(&responder, user_id, start_height);
+        let breach = get_random_breach();
+        let penalty_txid = breach.penalty_tx.txid();
         assert_eq!(
-            ConfirmationStatus::ConfirmedIn(h).to_db_data(),
-            Some((h, true))
+            responder.handle_breach(uuid, breach, user_id),
+            ConfirmationStatus::Rejected(errors::RPC_SUBSCRIPTION_LAPSED)
         );
+        assert!(!responder.has_tracker(uuid));
+        assert!(!responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .contains_key(&penalty_txid));
+        let record = responder
+            .get_penalties(None, None)
+            .into_iter()
+            .find(|r| r.uuid == uuid)
+            .unwrap();
+        assert_eq!(record.penalty_txid, penalty_txid);
+        assert_eq!(record.status, PenaltyStatus::Declined);
+
+        // Users with an active subscription are responded to regardless of the policy
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        set_expiry(&responder, user_id, start_height + 1);
         assert_eq!(
-            ConfirmationStatus::InMempoolSince(h).to_db_data(),
-            Some((h, false))
+            responder.handle_breach(uuid, get_random_breach(), user_id),
+            ConfirmationStatus::InMempoolSince(start_height)
         );
-        assert_eq!(ConfirmationStatus::Rejected(0).to_db_data(), None);
-        assert_eq!(ConfirmationStatus::IrrevocablyResolved.to_db_data(), None);
+        assert!(responder.has_tracker(uuid));
     }
 
     #[tokio::test]
-    async fn test_responder_new() {
-        // A fresh responder has no associated data
-        let mut chain = Blockchain::default().with_height(START_HEIGHT);
-        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+    async fn test_handle_breach_invalid() {
         let (responder, _s) =
-            init_responder_with_chain_and_dbm(MockedServerQuery::Regular, &mut chain, dbm.clone())
-                .await;
-        assert!(responder.is_fresh());
+            init_responder(MockedServerQuery::MempoolRejection("missing-inputs")).await;
 
-        // If we add some trackers to the system and create a new Responder reusing the same db
-        // (as if simulating a bootstrap from existing data), the data should be properly loaded.
-        for i in 0..10 {
-            let (user_id, uuid) = responder.store_dummy_appointment_to_db();
-            let breach = get_random_breach();
-            let s = if i % 2 == 0 {
-                ConfirmationStatus::InMempoolSince(i)
-            } else {
-                ConfirmationStatus::ConfirmedIn(i)
-            };
-            responder.add_tracker(uuid, breach.clone(), user_id, s);
-        }
+        // Penalties deemed invalid by the node are not broadcast, but recorded in the ledger
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_random_breach();
+        let penalty_txid = breach.penalty_tx.txid();
 
-        // Create a new Responder reusing the same DB and check that the data is loaded
-        let (another_r, _) =
-            init_responder_with_chain_and_dbm(MockedServerQuery::Regular, &mut chain, dbm).await;
-        assert!(!responder.is_fresh());
-        assert_eq!(responder, another_r);
+        assert_eq!(
+            responder.handle_breach(uuid, breach, user_id),
+            ConfirmationStatus::Rejected(rpc_errors::RPC_VERIFY_REJECTED)
+        );
+        assert!(!responder.has_tracker(uuid));
+        assert!(!responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .contains_key(&penalty_txid));
+        let penalties = responder.get_penalties(None, None);
+        assert_eq!(penalties.len(), 1);
+        assert_eq!(penalties[0].uuid, uuid);
+        assert_eq!(penalties[0].status, PenaltyStatus::Invalid);
     }
 
     #[tokio::test]
-    async fn test_handle_breach_accepted() {
+    async fn test_replace_penalty() {
         let start_height = START_HEIGHT as u32;
         let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
 
+        let mut dispute_tx = get_random_tx();
+        dispute_tx.output[0].value = 100_000;
+        let penalty_paying = |fee: u64| {
+            let mut penalty_tx = get_random_tx();
+            penalty_tx.input[0].previous_output = OutPoint::new(dispute_tx.txid(), 0);
+            penalty_tx.output[0].value = 100_000 - fee;
+            penalty_tx
+        };
+
         let (user_id, uuid) = responder.store_dummy_appointment_to_db();
-        let breach = get_random_breach();
+        let penalty_tx = penalty_paying(1000);
+        responder.handle_breach(
+            uuid,
+            Breach::new(dispute_tx.clone(), penalty_tx.clone()),
+            user_id,
+        );
 
+        // Penalties are never downgraded, nor replaced by ones for other disputes
+        for penalty in [penalty_tx.clone(), penalty_paying(500)] {
+            assert_eq!(
+                responder.replace_penalty(uuid, Breach::new(dispute_tx.clone(), penalty)),
+                Err(ReplacePenaltyFailure::NotSuperseding)
+            );
+        }
         assert_eq!(
-            responder.handle_breach(uuid, breach, user_id),
-            ConfirmationStatus::InMempoolSince(start_height)
+            responder.replace_penalty(uuid, get_random_breach()),
+            Err(ReplacePenaltyFailure::DisputeMismatch)
         );
-        let tracker = responder.dbm.lock().unwrap().load_tracker(uuid).unwrap();
         assert_eq!(
-            tracker.status,
-            ConfirmationStatus::InMempoolSince(start_height)
+            responder.replace_penalty(
+                generate_uuid(),
+                Breach::new(dispute_tx.clone(), penalty_paying(2000))
+            ),
+            Err(ReplacePenaltyFailure::UnknownTracker)
+        );
+        assert_eq!(
+            responder
+                .dbm
+                .lock()
+                .unwrap()
+                .load_tracker(uuid)
+                .unwrap()
+                .penalty_tx,
+            penalty_tx
         );
 
-        // Breaches won't be overwritten once passed to the Responder. If the same UUID is
-        // passed twice, the receipt corresponding to the first breach will be handed back.
-        let another_breach = get_random_breach();
+        // A superseding penalty replaces the in-flight one and is broadcast straightaway
+        let superseding_tx = penalty_paying(2000);
         assert_eq!(
-            responder.handle_breach(uuid, another_breach, user_id),
-            ConfirmationStatus::InMempoolSince(start_height)
+            responder.replace_penalty(
+                uuid,
+                Breach::new(dispute_tx.clone(), superseding_tx.clone())
+            ),
+            Ok(ConfirmationStatus::InMempoolSince(start_height))
         );
-        // Getting the tracker should return the old one.
         assert_eq!(
-            tracker,
-            responder.dbm.lock().unwrap().load_tracker(uuid).unwrap()
+            responder
+                .dbm
+                .lock()
+                .unwrap()
+                .load_tracker(uuid)
+                .unwrap()
+                .penalty_tx,
+            superseding_tx
+        );
+        assert!(responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .contains_key(&superseding_tx.txid()));
+
+        // The transition is recorded in the ledger
+        let record = responder.get_penalties(None, None)[0].clone();
+        assert_eq!(record.penalty_txid, superseding_tx.txid());
+        assert_eq!(record.value, 98_000);
+        assert_eq!(record.status, PenaltyStatus::Broadcast);
+        assert_eq!(record.replacements.len(), 1);
+        assert_eq!(record.replacements[0].old_penalty_txid, penalty_tx.txid());
+        assert_eq!(
+            record.replacements[0].new_penalty_txid,
+            superseding_tx.txid()
+        );
+
+        // Confirmed penalties are not replaced anymore
+        responder.check_confirmations(
+            HashSet::from_iter([superseding_tx.txid()]),
+            start_height + 1,
         );
+        assert_eq!(
+            responder.replace_penalty(uuid, Breach::new(dispute_tx.clone(), penalty_paying(3000))),
+            Err(ReplacePenaltyFailure::AlreadyConfirmed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replace_penalty_invalid() {
+        let (responder, _s) = init_responder(MockedServerQuery::MempoolRejection(
+            "bad-txns-inputs-missingorspent",
+        ))
+        .await;
+
+        // The tracker is added straight away, given the node would reject its penalty
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let mut dispute_tx = get_random_tx();
+        dispute_tx.output[0].value = 100_000;
+        let mut penalty_tx = get_random_tx();
+        penalty_tx.input[0].previous_output = OutPoint::new(dispute_tx.txid(), 0);
+        penalty_tx.output[0].value = 99_000;
+        responder.add_tracker(
+            uuid,
+            Breach::new(dispute_tx.clone(), penalty_tx.clone()),
+            user_id,
+            ConfirmationStatus::InMempoolSince(START_HEIGHT as u32),
+        );
+
+        // Penalties deemed invalid by the node are not taken, no matter the fee they pay
+        let mut superseding_tx = penalty_tx.clone();
+        superseding_tx.output[0].value = 90_000;
+        assert!(matches!(
+            responder.replace_penalty(uuid, Breach::new(dispute_tx, superseding_tx)),
+            Err(ReplacePenaltyFailure::Invalid(_))
+        ));
+        assert_eq!(
+            responder
+                .dbm
+                .lock()
+                .unwrap()
+                .load_tracker(uuid)
+                .unwrap()
+                .penalty_tx,
+            penalty_tx
+        );
+        assert!(responder.get_penalties(None, None)[0]
+            .replacements
+            .is_empty());
     }
 
     #[tokio::test]
@@ -972,19 +2842,122 @@ mod tests {
             );
         }
 
-        // The ones that were already confirmed but have not reached the end should remain the same
-        for uuid in confirmed {
-            assert_eq!(
-                responder
-                    .dbm
-                    .lock()
-                    .unwrap()
-                    .load_tracker(uuid)
-                    .unwrap()
-                    .status,
-                ConfirmationStatus::ConfirmedIn(42)
-            );
-        }
+        // The ones that were already confirmed but have not reached the end should remain the same
+        for uuid in confirmed {
+            assert_eq!(
+                responder
+                    .dbm
+                    .lock()
+                    .unwrap()
+                    .load_tracker(uuid)
+                    .unwrap()
+                    .status,
+                ConfirmationStatus::ConfirmedIn(42)
+            );
+        }
+    }
+
+    /// Confirmer reporting a fixed confirmation count for every transaction.
+    #[derive(Debug)]
+    struct MockConfirmer(Mutex<Option<u32>>);
+
+    impl Confirmer for MockConfirmer {
+        fn get_confirmations(
+            &self,
+            _: &Txid,
+            _: u32,
+        ) -> Result<Option<u32>, crate::confirmations::ConfirmationError> {
+            Ok(*self.0.lock().unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_confirmations_corroborated() {
+        let primary = Arc::new(MockConfirmer(Mutex::new(Some(
+            constants::IRREVOCABLY_RESOLVED,
+        ))));
+        let secondary = Arc::new(MockConfirmer(Mutex::new(Some(42))));
+        let corroborator = Arc::new(Corroborator::new(
+            primary,
+            secondary.clone(),
+            CorroborationPolicy::Require,
+        ));
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let responder = responder.with_corroborator(corroborator.clone());
+        let confirmed_in = 42;
+        let uuid = responder
+            .add_random_tracker(ConfirmationStatus::ConfirmedIn(confirmed_in))
+            .uuid();
+
+        // Checking the sources does not hold the block processing back, so the tracker is kept until they answer
+        let height = confirmed_in + constants::IRREVOCABLY_RESOLVED;
+        assert!(responder
+            .check_confirmations(HashSet::new(), height)
+            .is_none());
+
+        // The secondary confirmer disagrees, so the tracker is kept, even past the mark
+        corroborator.corroborate_pending().await;
+        assert!(responder
+            .check_confirmations(HashSet::new(), height + 1)
+            .is_none());
+        assert!(responder.has_tracker(uuid));
+
+        // And so it is if it knows nothing about the penalty
+        *secondary.0.lock().unwrap() = None;
+        corroborator.corroborate_pending().await;
+        assert!(responder
+            .check_confirmations(HashSet::new(), height + 2)
+            .is_none());
+
+        // Once both sources agree, the tracker is completed
+        *secondary.0.lock().unwrap() = Some(constants::IRREVOCABLY_RESOLVED);
+        corroborator.corroborate_pending().await;
+        assert_eq!(
+            responder.check_confirmations(HashSet::new(), height + 3),
+            Some(vec![uuid])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_confirmations_corroboration_warn() {
+        // Disagreements of the secondary confirmer are only logged if corroboration is not required
+        let primary = Arc::new(MockConfirmer(Mutex::new(Some(
+            constants::IRREVOCABLY_RESOLVED,
+        ))));
+        let corroborator = Arc::new(Corroborator::new(
+            primary.clone(),
+            Arc::new(MockConfirmer(Mutex::new(None))),
+            CorroborationPolicy::Warn,
+        ));
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let responder = responder.with_corroborator(corroborator.clone());
+        let uuid = responder
+            .add_random_tracker(ConfirmationStatus::ConfirmedIn(42))
+            .uuid();
+
+        let height = 42 + constants::IRREVOCABLY_RESOLVED;
+        assert!(responder
+            .check_confirmations(HashSet::new(), height)
+            .is_none());
+        corroborator.corroborate_pending().await;
+        assert_eq!(
+            responder.check_confirmations(HashSet::new(), height + 1),
+            Some(vec![uuid])
+        );
+
+        // Whereas those of the primary one keep the tracker regardless
+        *primary.0.lock().unwrap() = None;
+        let uuid = responder
+            .add_random_tracker(ConfirmationStatus::ConfirmedIn(43))
+            .uuid();
+        assert!(responder
+            .check_confirmations(HashSet::new(), height + 1)
+            .is_none());
+        corroborator.corroborate_pending().await;
+        assert!(responder
+            .check_confirmations(HashSet::new(), height + 2)
+            .is_none());
+        assert!(responder.has_tracker(uuid));
     }
 
     #[tokio::test]
@@ -1102,6 +3075,189 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_estimate_feerate() {
+        let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let (responder, _s) =
+            init_responder_with_chain_and_dbm(MockedServerQuery::Regular, &mut chain, dbm).await;
+
+        // No estimates are available until they are fetched
+        assert_eq!(responder.estimate_feerate(6), None);
+        responder.update_fee_estimates();
+        assert_eq!(responder.estimate_feerate(6), Some(MOCKED_FEERATE as f64));
+        // Targets in between the cached ones get the estimate of the closest lower one
+        assert_eq!(responder.estimate_feerate(7), Some(MOCKED_FEERATE as f64));
+
+        // Estimates are served from the cache, so changes in bitcoind are not picked up until the next block
+        let (carrier, _s2) =
+            create_carrier(MockedServerQuery::Feerate(12.5), chain.get_block_count());
+        *responder.carrier.lock().unwrap() = carrier;
+        assert_eq!(responder.estimate_feerate(6), Some(MOCKED_FEERATE as f64));
+
+        // Fractional feerates are kept as is
+        responder.block_connected(&chain.generate(None), chain.get_block_count());
+        assert_eq!(responder.estimate_feerate(6), Some(12.5));
+    }
+
+    #[tokio::test]
+    async fn test_get_reorg_exposure() {
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let height = responder.carrier.lock().unwrap().block_height();
+
+        // Nothing is exposed if there are no trackers
+        assert_eq!(responder.get_reorg_exposure(), ReorgExposure::default());
+
+        // Unconfirmed trackers are not accounted for
+        responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height - 2));
+        assert_eq!(responder.get_reorg_exposure(), ReorgExposure::default());
+
+        // Confirmed ones are, and the shallowest one is reported
+        for depth in [10, 3, 50] {
+            responder.add_random_tracker(ConfirmationStatus::ConfirmedIn(height - depth));
+        }
+        assert_eq!(
+            responder.get_reorg_exposure(),
+            ReorgExposure {
+                unresolved_penalties: 3,
+                min_confirmations: Some(4),
+            }
+        );
+
+        // A penalty confirmed in the tip has a single confirmation
+        let tracker = responder.add_random_tracker(ConfirmationStatus::ConfirmedIn(height));
+        assert_eq!(
+            responder.get_reorg_exposure(),
+            ReorgExposure {
+                unresolved_penalties: 4,
+                min_confirmations: Some(1),
+            }
+        );
+
+        // Reorged trackers are left out until they are confirmed again
+        responder
+            .reorged_trackers
+            .lock()
+            .unwrap()
+            .insert(tracker.uuid());
+        assert_eq!(
+            responder.get_reorg_exposure(),
+            ReorgExposure {
+                unresolved_penalties: 3,
+                min_confirmations: Some(4),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_all() {
+        let (responder, _s) = init_responder(MockedServerQuery::InMempoool).await;
+        let height = responder.carrier.lock().unwrap().block_height();
+        let mut statues = HashMap::new();
+        let mut unconfirmed = HashSet::new();
+        let mut penalty_txids = Vec::new();
+
+        for i in 0..10 {
+            let status = if i % 2 == 0 {
+                ConfirmationStatus::ConfirmedIn(height - i)
+            } else {
+                ConfirmationStatus::InMempoolSince(height - i)
+            };
+
+            let tracker = responder.add_random_tracker(status);
+            if !matches!(status, ConfirmationStatus::ConfirmedIn(_)) {
+                unconfirmed.insert(tracker.uuid());
+                penalty_txids.push(tracker.penalty_tx.txid());
+                // Penalties already sent within this block are rebroadcast nonetheless
+                responder
+                    .carrier
+                    .lock()
+                    .unwrap()
+                    .get_issued_receipts()
+                    .insert(tracker.penalty_tx.txid(), status);
+            }
+            statues.insert(tracker.uuid(), status);
+        }
+
+        // All the unconfirmed penalties are rebroadcast, and only them
+        let (rebroadcast, rejected) = responder.rebroadcast_all();
+        assert_eq!(HashSet::from_iter(rebroadcast), unconfirmed);
+        assert!(rejected.is_empty());
+        let mut carrier = responder.carrier.lock().unwrap();
+        for txid in penalty_txids {
+            assert_eq!(
+                carrier.get_issued_receipts()[&txid],
+                ConfirmationStatus::InMempoolSince(height)
+            );
+        }
+        drop(carrier);
+
+        // The status of the trackers is left untouched
+        for (uuid, status) in statues {
+            assert_eq!(
+                responder
+                    .dbm
+                    .lock()
+                    .unwrap()
+                    .load_tracker(uuid)
+                    .unwrap()
+                    .status,
+                status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_all_scheduled_and_superseded() {
+        let (responder, _s) = init_responder(MockedServerQuery::InMempoool).await;
+        let height = responder.carrier.lock().unwrap().block_height();
+
+        let unconfirmed = responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height));
+        let scheduled = responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height));
+        let superseded = responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height));
+        responder
+            .scheduled_broadcasts
+            .lock()
+            .unwrap()
+            .insert(scheduled.uuid(), height + 1);
+        responder
+            .superseding_txs
+            .lock()
+            .unwrap()
+            .insert(superseded.uuid(), (get_random_tx().txid(), height));
+
+        // Penalties that are not due yet, or that cannot make it to the chain anymore, are not rebroadcast
+        let (rebroadcast, rejected) = responder.rebroadcast_all();
+        assert_eq!(rebroadcast, vec![unconfirmed.uuid()]);
+        assert!(rejected.is_empty());
+        let mut carrier = responder.carrier.lock().unwrap();
+        assert!(!carrier
+            .get_issued_receipts()
+            .contains_key(&scheduled.penalty_tx.txid()));
+        assert!(!carrier
+            .get_issued_receipts()
+            .contains_key(&superseded.penalty_tx.txid()));
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_all_rejected() {
+        let (responder, _s) = init_responder(MockedServerQuery::Error(
+            rpc_errors::RPC_VERIFY_ERROR as i64,
+        ))
+        .await;
+        let height = responder.carrier.lock().unwrap().block_height();
+
+        let confirmed = responder.add_random_tracker(ConfirmationStatus::ConfirmedIn(height));
+        let unconfirmed = responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height));
+
+        // Rejected penalties are reported, but their trackers are kept
+        let (rebroadcast, rejected) = responder.rebroadcast_all();
+        assert!(rebroadcast.is_empty());
+        assert_eq!(rejected, vec![unconfirmed.uuid()]);
+        assert!(responder.has_tracker(unconfirmed.uuid()));
+        assert!(responder.has_tracker(confirmed.uuid()));
+    }
+
     #[tokio::test]
     async fn test_rebroadcast_stale_txs_rejected() {
         let (responder, _s) = init_responder(MockedServerQuery::Error(
@@ -1150,6 +3306,183 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_rebroadcast_evicted_txs() {
+        // The mocked backend cannot find any transaction, as if all of them had been evicted from the mempool
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let height = 100;
+
+        let evicted = responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height - 1));
+        let stale = responder.add_random_tracker(ConfirmationStatus::InMempoolSince(
+            height - CONFIRMATIONS_BEFORE_RETRY as u32,
+        ));
+        let confirmed = responder.add_random_tracker(ConfirmationStatus::ConfirmedIn(height - 1));
+        responder.get_carrier().lock().unwrap().clear_receipts();
+
+        // Only the evicted penalty is rebroadcast. Stale ones are left to `rebroadcast_stale_txs`
+        assert!(responder.rebroadcast_evicted_txs(height).is_none());
+        let receipts = responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .clone();
+        assert_eq!(receipts.len(), 1);
+        assert!(receipts.contains_key(&evicted.penalty_tx.txid()));
+
+        // The status of the trackers is left untouched
+        for tracker in [evicted, stale, confirmed] {
+            assert_eq!(
+                responder
+                    .dbm
+                    .lock()
+                    .unwrap()
+                    .load_tracker(tracker.uuid())
+                    .unwrap()
+                    .status,
+                tracker.status
+            );
+        }
+
+        // Penalties that are still in the mempool are not rebroadcast
+        let (responder, _s) = init_responder(MockedServerQuery::InMempoool).await;
+        responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height - 1));
+        responder.get_carrier().lock().unwrap().clear_receipts();
+        assert!(responder.rebroadcast_evicted_txs(height).is_none());
+        assert!(responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .is_empty());
+
+        // Evicted penalties that cannot be rebroadcast are reported
+        let (responder, _s) = init_responder(MockedServerQuery::Error(
+            rpc_errors::RPC_VERIFY_REJECTED as i64,
+        ))
+        .await;
+        let rejected = responder.add_random_tracker(ConfirmationStatus::InMempoolSince(height - 1));
+        assert_eq!(
+            responder.rebroadcast_evicted_txs(height),
+            Some(vec![rejected.uuid()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_stale_txs_cpfp() {
+        let (mut responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let anchor_material = AnchorMaterial::new(get_random_keypair().0);
+        let height = 100;
+        let parent_fee = 100;
+
+        // Add a penalty with an anchor that has been stuck in mempool for too long.
+        let (dispute_tx, penalty_tx) = get_anchor_penalty(&anchor_material, parent_fee, 10_000);
+        let tracker = TransactionTracker::new(
+            Breach::new(dispute_tx, penalty_tx.clone()),
+            get_random_user_id(),
+            ConfirmationStatus::InMempoolSince(height - CONFIRMATIONS_BEFORE_RETRY as u32),
+        );
+        responder.add_dummy_tracker(&tracker);
+
+        // Without anchor material there is nothing to bump.
+        assert!(responder.rebroadcast_stale_txs(height).is_none());
+        assert_eq!(
+            responder
+                .get_carrier()
+                .lock()
+                .unwrap()
+                .get_issued_receipts()
+                .len(),
+            1
+        );
+
+        // With it, a child spending the anchor is broadcast alongside the penalty.
+        responder.anchor_material = Some(anchor_material.clone());
+        responder.get_carrier().lock().unwrap().clear_receipts();
+        responder
+            .dbm
+            .lock()
+            .unwrap()
+            .update_tracker_status(tracker.uuid(), &tracker.status)
+            .unwrap();
+        assert!(responder.rebroadcast_stale_txs(height).is_none());
+
+        // Signatures are deterministic, so the expected child can be rebuilt.
+        let child = anchor_material
+            .build_cpfp(&penalty_tx, parent_fee, MOCKED_FEERATE)
+            .unwrap();
+        assert!(responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .contains_key(&child.txid()));
+
+        // The child raises the package feerate up to the estimate.
+        let child_fee = 10_000 - child.output[0].value;
+        let parent_feerate = anchors::package_feerate(parent_fee, &[&penalty_tx]);
+        let package_feerate =
+            anchors::package_feerate(parent_fee + child_fee, &[&penalty_tx, &child]);
+        assert!(package_feerate > parent_feerate);
+        assert!(package_feerate >= MOCKED_FEERATE as f64);
+    }
+
+    #[tokio::test]
+    async fn test_rebroadcast_stale_txs_fee_bump_history() {
+        let (mut responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let anchor_material = AnchorMaterial::new(get_random_keypair().0);
+        responder.anchor_material = Some(anchor_material.clone());
+        let height = 100;
+        let parent_fee = 100;
+
+        let (dispute_tx, penalty_tx) = get_anchor_penalty(&anchor_material, parent_fee, 10_000);
+        let tracker = TransactionTracker::new(
+            Breach::new(dispute_tx, penalty_tx.clone()),
+            get_random_user_id(),
+            ConfirmationStatus::InMempoolSince(height - CONFIRMATIONS_BEFORE_RETRY as u32),
+        );
+        responder.add_dummy_tracker(&tracker);
+        responder
+            .dbm
+            .lock()
+            .unwrap()
+            .store_penalty_record(&PenaltyRecord::new(tracker.uuid(), &tracker, 1000))
+            .unwrap();
+
+        // Bump the penalty twice, the second time at a higher feerate.
+        let mut expected_bumps = Vec::new();
+        let mut stoppers = Vec::new();
+        for feerate in [MOCKED_FEERATE, MOCKED_FEERATE + 5] {
+            let (carrier, s) = create_carrier(MockedServerQuery::Feerate(feerate as f64), height);
+            *responder.get_carrier().lock().unwrap() = carrier;
+            stoppers.push(s);
+            responder
+                .dbm
+                .lock()
+                .unwrap()
+                .update_tracker_status(tracker.uuid(), &tracker.status)
+                .unwrap();
+            assert!(responder.rebroadcast_stale_txs(height).is_none());
+
+            let child = anchor_material
+                .build_cpfp(&penalty_tx, parent_fee, feerate)
+                .unwrap();
+            expected_bumps.push((child.txid(), feerate, 10_000 - child.output[0].value));
+        }
+
+        // Both bumps are recorded in the ledger, but only the fee of the latest one counts as spent.
+        let record = responder.get_penalties(None, None).pop().unwrap();
+        assert_eq!(
+            record
+                .fee_bumps
+                .iter()
+                .map(|bump| (bump.txid, bump.feerate, bump.fee))
+                .collect::<Vec<_>>(),
+            expected_bumps
+        );
+        assert_eq!(record.bump_fees(), expected_bumps[1].2);
+    }
+
     #[tokio::test]
     async fn test_filtered_block_connected() {
         let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
@@ -1415,6 +3748,145 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_filtered_block_connected_superseded() {
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (responder, _s) =
+            init_responder_with_chain_and_dbm(MockedServerQuery::Regular, &mut chain, dbm).await;
+
+        // Add a couple of trackers, one of them will have its dispute output spent by a competing transaction
+        let user_id = get_random_user_id();
+        responder.gatekeeper.add_update_user(user_id).unwrap();
+        let mut uuids = Vec::new();
+        for _ in 0..2 {
+            let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+            responder
+                .gatekeeper
+                .add_update_appointment(user_id, uuid, &appointment)
+                .unwrap();
+            responder
+                .dbm
+                .lock()
+                .unwrap()
+                .store_appointment(uuid, &appointment)
+                .unwrap();
+            uuids.push(uuid);
+        }
+        let (superseded_uuid, uuid) = (uuids[0], uuids[1]);
+
+        let breach = get_random_breach();
+        let penalty_tx = breach.penalty_tx.clone();
+        responder.handle_breach(superseded_uuid, breach, user_id);
+        responder.handle_breach(uuid, get_random_breach(), user_id);
+
+        // The competing transaction spends the same output as the penalty, but pays out differently
+        let mut competing_tx = penalty_tx.clone();
+        competing_tx.output[0].value += 1;
+        assert_ne!(competing_tx.txid(), penalty_tx.txid());
+
+        let block = chain.generate(Some(vec![competing_tx]));
+        responder.block_connected(&block, chain.get_block_count());
+
+        // The competing transaction may still be reorged out, so the tracker is kept until it is irrevocably resolved
+        assert!(responder.has_tracker(superseded_uuid));
+        for _ in 0..constants::IRREVOCABLY_RESOLVED - 1 {
+            responder.block_connected(&chain.generate(None), chain.get_block_count());
+        }
+        assert!(responder.has_tracker(superseded_uuid));
+        responder.block_connected(&chain.generate(None), chain.get_block_count());
+
+        // The superseded tracker is gone, but its ledger entry is kept (and flagged)
+        assert!(!responder.has_tracker(superseded_uuid));
+        let penalties = responder.get_penalties(None, None);
+        let record = penalties
+            .iter()
+            .find(|r| r.uuid == superseded_uuid)
+            .unwrap();
+        assert_eq!(record.status, PenaltyStatus::Superseded);
+
+        // The other tracker is left untouched
+        assert!(responder.has_tracker(uuid));
+        let record = penalties.iter().find(|r| r.uuid == uuid).unwrap();
+        assert_eq!(record.status, PenaltyStatus::Broadcast);
+    }
+
+    #[tokio::test]
+    async fn test_block_disconnected_superseded() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (responder, _s) = init_responder(MockedServerQuery::Regular).await;
+        let height = chain.get_block_count() + 1;
+
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_random_breach();
+        let mut competing_tx = breach.penalty_tx.clone();
+        competing_tx.output[0].value += 1;
+        responder.add_tracker(
+            uuid,
+            breach,
+            user_id,
+            ConfirmationStatus::InMempoolSince(height - 1),
+        );
+
+        let block = chain.generate(Some(vec![competing_tx]));
+        let txdata: Vec<(usize, &Transaction)> = block.txdata.iter().enumerate().collect();
+        assert!(responder.check_superseded(&txdata, height).is_none());
+        assert!(responder.is_superseded(uuid));
+
+        // If the competing transaction is reorged out, the penalty is valid again
+        responder.block_disconnected(&block.header, height);
+        assert!(!responder.is_superseded(uuid));
+        assert!(responder
+            .check_superseded(&[], height + constants::IRREVOCABLY_RESOLVED)
+            .is_none());
+        assert!(responder.has_tracker(uuid));
+    }
+
+    #[tokio::test]
+    async fn test_filtered_block_connected_invalid_stale_penalty() {
+        let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (responder, _s) = init_responder_with_chain_and_dbm(
+            MockedServerQuery::MempoolRejection("missing-inputs"),
+            &mut chain,
+            dbm,
+        )
+        .await;
+
+        // Add a tracker that is due to be rebroadcast, but whose penalty is no longer valid
+        let height = chain.get_block_count() + 1;
+        let (user_id, uuid) = responder.store_dummy_appointment_to_db();
+        let breach = get_random_breach();
+        let penalty_txid = breach.penalty_tx.txid();
+        responder.add_tracker(
+            uuid,
+            breach,
+            user_id,
+            ConfirmationStatus::InMempoolSince(height - CONFIRMATIONS_BEFORE_RETRY as u32),
+        );
+
+        // The tracker is given up on instead of rebroadcast
+        responder.block_connected(&chain.generate(None), height);
+        assert!(!responder.has_tracker(uuid));
+        assert!(!responder
+            .get_carrier()
+            .lock()
+            .unwrap()
+            .get_issued_receipts()
+            .contains_key(&penalty_txid));
+        let penalties = responder.get_penalties(None, None);
+        assert_eq!(penalties.len(), 1);
+        assert_eq!(penalties[0].status, PenaltyStatus::Invalid);
+
+        // So it does not come back in later blocks
+        responder.block_connected(&chain.generate(None), height + 1);
+        assert!(!responder.has_tracker(uuid));
+        assert_eq!(
+            responder.get_penalties(None, None)[0].status,
+            PenaltyStatus::Invalid
+        );
+    }
+
     #[tokio::test]
     async fn test_block_disconnected() {
         let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
@@ -8,8 +8,11 @@
 */
 
 use rand::Rng;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use jsonrpc_http_server::jsonrpc_core::error::ErrorCode as JsonRpcErrorCode;
 use jsonrpc_http_server::jsonrpc_core::{Error as JsonRpcError, IoHandler, Params, Value};
@@ -23,7 +26,7 @@ use bitcoin::blockdata::script::{Builder, Script};
 use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
 use bitcoin::hash_types::BlockHash;
 use bitcoin::hash_types::Txid;
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::network::constants::Network;
 use bitcoin::util::hash::bitcoin_merkle_root;
 use bitcoin::util::uint::Uint256;
@@ -40,11 +43,14 @@ use teos_common::cryptography::{get_random_bytes, get_random_keypair};
 use teos_common::test_utils::{generate_random_appointment, get_random_user_id, TXID_HEX, TX_HEX};
 use teos_common::UserId;
 
+use crate::anchors::AnchorMaterial;
 use crate::api::internal::InternalAPI;
 use crate::carrier::Carrier;
+use crate::chain_monitor::PRUNED_BLOCK_ERROR;
 use crate::dbm::DBM;
 use crate::extended_appointment::{ExtendedAppointment, UUID};
-use crate::gatekeeper::{Gatekeeper, UserInfo};
+use crate::gatekeeper::{Gatekeeper, UserAccessLists, UserInfo};
+use crate::payments::{PaymentError, PaymentVerifier};
 use crate::protos as msgs;
 use crate::responder::{ConfirmationStatus, Responder, TransactionTracker};
 use crate::rpc_errors;
@@ -53,7 +59,11 @@ use crate::watcher::{Breach, Watcher};
 pub(crate) const SLOTS: u32 = 21;
 pub(crate) const DURATION: u32 = 500;
 pub(crate) const EXPIRY_DELTA: u32 = 42;
+pub(crate) const RENEWAL_WINDOW: u32 = DURATION;
+pub(crate) const MAX_EXPIRY_HORIZON: u32 = 2 * DURATION;
 pub(crate) const START_HEIGHT: usize = 100;
+/// Feerate (sat/vB) returned by the mocked `estimatesmartfee`.
+pub(crate) const MOCKED_FEERATE: u64 = 20;
 
 pub(crate) const AVAILABLE_SLOTS: u32 = 21;
 pub(crate) const SUBSCRIPTION_START: u32 = START_HEIGHT as u32;
@@ -63,9 +73,12 @@ pub(crate) const SUBSCRIPTION_EXPIRY: u32 = SUBSCRIPTION_START + 42;
 pub(crate) struct Blockchain {
     pub blocks: Vec<Block>,
     without_blocks: Option<std::ops::RangeFrom<usize>>,
+    pruned_below: Option<usize>,
     without_headers: bool,
     malformed_headers: bool,
     pub unreachable: Arc<Mutex<bool>>,
+    /// When the best block was requested, to check how often the chain is polled.
+    pub polls: Arc<Mutex<Vec<Instant>>>,
 }
 
 #[allow(dead_code)]
@@ -106,6 +119,14 @@ impl Blockchain {
         }
     }
 
+    /// Mocks a pruned node, answering block requests below the given height the way `bitcoind` does.
+    pub fn pruned_below(self, height: usize) -> Self {
+        Self {
+            pruned_below: Some(height),
+            ..self
+        }
+    }
+
     pub fn without_headers(self) -> Self {
         Self {
             without_headers: true,
@@ -140,6 +161,7 @@ impl Blockchain {
             blocks,
             without_blocks: None,
             unreachable: self.unreachable.clone(),
+            polls: self.polls.clone(),
             ..*self
         }
     }
@@ -254,6 +276,11 @@ impl BlockSource for Blockchain {
                             return Err(BlockSourceError::persistent("block not found"));
                         }
                     }
+                    if matches!(self.pruned_below, Some(pruned_below) if height < pruned_below) {
+                        return Err(BlockSourceError::transient(std::io::Error::other(
+                            PRUNED_BLOCK_ERROR,
+                        )));
+                    }
 
                     return Ok(block.clone());
                 }
@@ -264,6 +291,7 @@ impl BlockSource for Blockchain {
 
     fn get_best_block(&self) -> AsyncBlockSourceResult<(BlockHash, Option<u32>)> {
         Box::pin(async move {
+            self.polls.lock().unwrap().push(Instant::now());
             if *self.unreachable.lock().unwrap() {
                 return Err(BlockSourceError::transient("Connection refused"));
             }
@@ -307,6 +335,26 @@ pub(crate) fn get_random_tx() -> Transaction {
     }
 }
 
+/// Builds a (dispute, penalty) pair where the penalty pays `fee` and includes an anchor of `anchor_value`.
+pub(crate) fn get_anchor_penalty(
+    anchor: &AnchorMaterial,
+    fee: u64,
+    anchor_value: u64,
+) -> (Transaction, Transaction) {
+    let mut dispute_tx = get_random_tx();
+    dispute_tx.output[0].value = 100_000;
+
+    let mut penalty_tx = get_random_tx();
+    penalty_tx.input[0].previous_output = OutPoint::new(dispute_tx.txid(), 0);
+    penalty_tx.output[0].value = 100_000 - fee - anchor_value;
+    penalty_tx.output.push(TxOut {
+        value: anchor_value,
+        script_pubkey: anchor.script_pubkey().clone(),
+    });
+
+    (dispute_tx, penalty_tx)
+}
+
 pub(crate) fn generate_dummy_appointment(dispute_txid: Option<&Txid>) -> ExtendedAppointment {
     let appointment = generate_random_appointment(dispute_txid);
     let user_id = get_random_user_id();
@@ -371,6 +419,8 @@ pub(crate) enum MockedServerQuery {
     Regular,
     InMempoool,
     Error(i64),
+    MempoolRejection(&'static str),
+    Feerate(f64),
 }
 
 pub(crate) fn create_carrier(query: MockedServerQuery, height: u32) -> (Carrier, BitcoindStopper) {
@@ -378,6 +428,12 @@ pub(crate) fn create_carrier(query: MockedServerQuery, height: u32) -> (Carrier,
         MockedServerQuery::Regular => BitcoindMock::new(MockOptions::default()),
         MockedServerQuery::InMempoool => BitcoindMock::new(MockOptions::in_mempool()),
         MockedServerQuery::Error(x) => BitcoindMock::new(MockOptions::with_error(x)),
+        MockedServerQuery::MempoolRejection(reason) => {
+            BitcoindMock::new(MockOptions::with_mempool_rejection(reason))
+        }
+        MockedServerQuery::Feerate(feerate) => {
+            BitcoindMock::new(MockOptions::with_feerate(feerate))
+        }
     };
     let bitcoin_cli = Arc::new(BitcoindClient::new(bitcoind_mock.url(), Auth::None).unwrap());
     let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
@@ -405,7 +461,7 @@ pub(crate) async fn create_responder(
     let bitcoind_reachable = Arc::new((Mutex::new(true), Condvar::new()));
     let carrier = Carrier::new(bitcoin_cli, bitcoind_reachable, height);
 
-    Responder::new(&last_n_blocks, height, carrier, gatekeeper, dbm)
+    Responder::new(&last_n_blocks, height, carrier, gatekeeper, dbm, None, 0)
 }
 
 pub(crate) async fn create_watcher(
@@ -433,11 +489,56 @@ pub(crate) async fn create_watcher(
         bitcoind_mock.stopper,
     )
 }
+/// [PaymentVerifier] that deems paid the invoices whose preimage has been passed to [MockedPaymentVerifier::pay],
+/// as long as they are redeemed by the user they were paid for.
+#[derive(Debug, Default)]
+pub(crate) struct MockedPaymentVerifier {
+    paid: Mutex<HashMap<sha256::Hash, UserId>>,
+    unreachable: AtomicBool,
+}
+
+impl MockedPaymentVerifier {
+    pub fn pay(&self, user_id: UserId, payment_preimage: &[u8]) {
+        self.paid
+            .lock()
+            .unwrap()
+            .insert(sha256::Hash::hash(payment_preimage), user_id);
+    }
+
+    pub fn set_unreachable(&self, unreachable: bool) {
+        self.unreachable.store(unreachable, Ordering::Relaxed);
+    }
+
+    fn check_reachable(&self) -> Result<(), PaymentError> {
+        if self.unreachable.load(Ordering::Relaxed) {
+            Err(PaymentError("Node unreachable".to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl PaymentVerifier for MockedPaymentVerifier {
+    fn create_invoice(&self, user_id: UserId) -> Result<String, PaymentError> {
+        self.check_reachable()?;
+        Ok(format!("lnbcrt1invoice{user_id}"))
+    }
+
+    fn is_paid(&self, user_id: UserId, payment_hash: &sha256::Hash) -> Result<bool, PaymentError> {
+        self.check_reachable()?;
+        Ok(self.paid.lock().unwrap().get(payment_hash) == Some(&user_id))
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct ApiConfig {
     slots: u32,
     duration: u32,
     bitcoind_reachable: bool,
+    bitcoind_syncing: bool,
+    penalty_triggers: bool,
+    payment_verifier: Option<Arc<dyn PaymentVerifier>>,
+    access_lists: Option<UserAccessLists>,
 }
 
 impl ApiConfig {
@@ -446,13 +547,37 @@ impl ApiConfig {
             slots,
             duration,
             bitcoind_reachable: true,
+            bitcoind_syncing: false,
+            penalty_triggers: false,
+            payment_verifier: None,
+            access_lists: None,
         }
     }
 
+    pub fn with_payment_verifier(&mut self, payment_verifier: Arc<dyn PaymentVerifier>) -> Self {
+        self.payment_verifier = Some(payment_verifier);
+        self.clone()
+    }
+
+    pub fn with_access_lists(&mut self, access_lists: UserAccessLists) -> Self {
+        self.access_lists = Some(access_lists);
+        self.clone()
+    }
+
     pub fn bitcoind_unreachable(&mut self) -> Self {
         self.bitcoind_reachable = false;
         self.clone()
     }
+
+    pub fn bitcoind_syncing(&mut self) -> Self {
+        self.bitcoind_syncing = true;
+        self.clone()
+    }
+
+    pub fn penalty_triggers(&mut self) -> Self {
+        self.penalty_triggers = true;
+        self.clone()
+    }
 }
 
 impl Default for ApiConfig {
@@ -461,6 +586,10 @@ impl Default for ApiConfig {
             slots: SLOTS,
             duration: DURATION,
             bitcoind_reachable: true,
+            bitcoind_syncing: false,
+            penalty_triggers: false,
+            payment_verifier: None,
+            access_lists: None,
         }
     }
 }
@@ -472,33 +601,47 @@ pub(crate) async fn create_api_with_config(
     let mut chain = Blockchain::default().with_height(START_HEIGHT);
 
     let dbm = Arc::new(Mutex::new(DBM::in_memory().unwrap()));
-    let gk = Arc::new(Gatekeeper::new(
+    let mut gk = Gatekeeper::new(
         chain.get_block_count(),
         api_config.slots,
         api_config.duration,
         EXPIRY_DELTA,
+        RENEWAL_WINDOW,
+        MAX_EXPIRY_HORIZON,
         dbm.clone(),
-    ));
+    );
+    if let Some(payment_verifier) = api_config.payment_verifier {
+        gk = gk.with_payment_verifier(payment_verifier);
+    }
+    if let Some(access_lists) = api_config.access_lists {
+        gk = gk.with_access_lists(access_lists);
+    }
+    let gk = Arc::new(gk);
     let responder =
-        create_responder(&mut chain, gk.clone(), dbm.clone(), bitcoind_mock.url()).await;
+        Arc::new(create_responder(&mut chain, gk.clone(), dbm.clone(), bitcoind_mock.url()).await);
     let (watcher, stopper) = create_watcher(
         &mut chain,
-        Arc::new(responder),
+        responder.clone(),
         gk.clone(),
         bitcoind_mock,
         dbm.clone(),
     )
     .await;
+    responder.update_fee_estimates();
 
     let bitcoind_reachable = Arc::new((Mutex::new(api_config.bitcoind_reachable), Condvar::new()));
     let (shutdown_trigger, _) = triggered::trigger();
     (
-        Arc::new(InternalAPI::new(
-            Arc::new(watcher),
-            vec![msgs::NetworkAddress::from_ipv4("address".to_string(), 21)],
-            bitcoind_reachable,
-            shutdown_trigger,
-        )),
+        Arc::new(
+            InternalAPI::new(
+                Arc::new(watcher),
+                vec![msgs::NetworkAddress::from_ipv4("address".to_string(), 21)],
+                bitcoind_reachable,
+                shutdown_trigger,
+            )
+            .with_penalty_triggers(api_config.penalty_triggers)
+            .with_bitcoind_syncing(Arc::new(Mutex::new(api_config.bitcoind_syncing))),
+        ),
         stopper,
     )
 }
@@ -538,20 +681,64 @@ pub(crate) struct BitcoindMock {
 pub(crate) struct MockOptions {
     error_code: Option<i64>,
     in_mempool: bool,
+    mempool_rejection: Option<&'static str>,
+    slow_calls: Option<(Duration, usize)>,
+    broadcasts: Option<Arc<Mutex<Vec<String>>>>,
+    feerate: Option<f64>,
+    confirmations: Option<u32>,
 }
 
 impl MockOptions {
     pub fn with_error(error_code: i64) -> Self {
         Self {
             error_code: Some(error_code),
-            in_mempool: false,
+            ..Default::default()
         }
     }
 
     pub fn in_mempool() -> Self {
         Self {
-            error_code: None,
             in_mempool: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_mempool_rejection(reason: &'static str) -> Self {
+        Self {
+            mempool_rejection: Some(reason),
+            ..Default::default()
+        }
+    }
+
+    /// The first `calls` calls to `estimatesmartfee` take `delay` to be answered.
+    pub fn slow(delay: Duration, calls: usize) -> Self {
+        Self {
+            slow_calls: Some((delay, calls)),
+            ..Default::default()
+        }
+    }
+
+    /// `estimatesmartfee` returns `feerate` (sat/vB) instead of [MOCKED_FEERATE].
+    pub fn with_feerate(feerate: f64) -> Self {
+        Self {
+            feerate: Some(feerate),
+            ..Default::default()
+        }
+    }
+
+    /// `getrawtransaction` finds every transaction, reporting it has `confirmations` confirmations.
+    pub fn with_confirmations(confirmations: u32) -> Self {
+        Self {
+            confirmations: Some(confirmations),
+            ..Default::default()
+        }
+    }
+
+    /// The raw transactions sent via `sendrawtransaction` are pushed to `broadcasts`, in the order they are received.
+    pub fn recording_broadcasts(broadcasts: Arc<Mutex<Vec<String>>>) -> Self {
+        Self {
+            broadcasts: Some(broadcasts),
+            ..Default::default()
         }
     }
 }
@@ -566,9 +753,18 @@ impl BitcoindMock {
             });
             io.add_alias("sendrawtransaction", "error");
             io.add_alias("getrawtransaction", "error");
+            io.add_alias("estimatesmartfee", "error");
+            io.add_alias("testmempoolaccept", "error");
         } else {
-            BitcoindMock::add_sendrawtransaction(&mut io);
-            BitcoindMock::add_getrawtransaction(&mut io, options.in_mempool);
+            BitcoindMock::add_sendrawtransaction(&mut io, options.broadcasts);
+            BitcoindMock::add_getrawtransaction(&mut io, options.in_mempool, options.confirmations);
+            BitcoindMock::add_getblockhash(&mut io);
+            BitcoindMock::add_estimatesmartfee(
+                &mut io,
+                options.slow_calls,
+                options.feerate.unwrap_or(MOCKED_FEERATE as f64),
+            );
+            BitcoindMock::add_testmempoolaccept(&mut io, options.mempool_rejection);
         }
 
         let server = ServerBuilder::new(io)
@@ -583,15 +779,56 @@ impl BitcoindMock {
         }
     }
 
-    fn add_sendrawtransaction(io: &mut IoHandler) {
-        io.add_method("sendrawtransaction", |_params: Params| async {
+    fn add_sendrawtransaction(io: &mut IoHandler, broadcasts: Option<Arc<Mutex<Vec<String>>>>) {
+        io.add_sync_method("sendrawtransaction", move |params: Params| {
+            if let (Some(broadcasts), Params::Array(params)) = (&broadcasts, params) {
+                if let Some(Value::String(raw_tx)) = params.first() {
+                    broadcasts.lock().unwrap().push(raw_tx.clone());
+                }
+            }
             Ok(Value::String(TXID_HEX.to_owned()))
         });
     }
 
-    fn add_getrawtransaction(io: &mut IoHandler, in_mempool: bool) {
+    fn add_estimatesmartfee(
+        io: &mut IoHandler,
+        slow_calls: Option<(Duration, usize)>,
+        feerate: f64,
+    ) {
+        let calls = AtomicUsize::new(0);
+        io.add_sync_method("estimatesmartfee", move |_params: Params| {
+            if let Some((delay, slow_calls)) = slow_calls {
+                if calls.fetch_add(1, Ordering::Relaxed) < slow_calls {
+                    thread::sleep(delay);
+                }
+            }
+            Ok(serde_json::json!({ "feerate": feerate / 100_000.0, "blocks": 2 }))
+        });
+    }
+
+    fn add_testmempoolaccept(io: &mut IoHandler, rejection: Option<&'static str>) {
+        io.add_method("testmempoolaccept", move |_params: Params| async move {
+            Ok(match rejection {
+                Some(reason) => {
+                    serde_json::json!([{ "txid": TXID_HEX, "allowed": false, "reject-reason": reason }])
+                }
+                None => serde_json::json!([{ "txid": TXID_HEX, "allowed": true }]),
+            })
+        });
+    }
+
+    fn add_getblockhash(io: &mut IoHandler) {
+        io.add_sync_method("getblockhash", |_params: Params| {
+            Ok(Value::String(BlockHash::from_inner([0; 32]).to_string()))
+        });
+    }
+
+    fn add_getrawtransaction(io: &mut IoHandler, in_mempool: bool, confirmations: Option<u32>) {
         io.add_sync_method("getrawtransaction", move |_params: Params|  {
-            if !in_mempool {
+            if let Some(confirmations) = confirmations {
+                Ok(serde_json::json!({"hex": TX_HEX, "txid": TXID_HEX, "hash": TXID_HEX, "size": 0,
+                "vsize": 0, "version": 1, "locktime": 0, "vin": [], "vout": [], "confirmations": confirmations }))
+            } else if !in_mempool {
                 Err(JsonRpcError::new(JsonRpcErrorCode::ServerError(rpc_errors::RPC_INVALID_ADDRESS_OR_KEY as i64)))
             } else {
                 match _params {
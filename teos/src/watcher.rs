@@ -1,25 +1,34 @@
 //! Logic related to the Watcher, the components in charge of watching for breaches on chain.
 
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::secp256k1::SecretKey;
 use bitcoin::{BlockHeader, Transaction};
 use lightning::chain;
 use lightning_block_sync::poll::ValidatedBlock;
 
-use teos_common::appointment::{Appointment, Locator};
+use teos_common::appointment::{compute_appointment_slots, Appointment, Locator, SignatureVersion};
+use teos_common::constants::ENCRYPTED_BLOB_MAX_SIZE;
 use teos_common::cryptography;
-use teos_common::receipts::{AppointmentReceipt, RegistrationReceipt};
-use teos_common::{TowerId, UserId};
+use teos_common::receipts::{AppointmentReceipt, DeletionReceipt, RegistrationReceipt};
+use teos_common::{delete_appointment_message, transfer_subscription_message, TowerId, UserId};
 
 use crate::dbm::DBM;
 use crate::extended_appointment::{ExtendedAppointment, UUID};
-use crate::gatekeeper::{Gatekeeper, MaxSlotsReached, UserInfo};
-use crate::responder::{ConfirmationStatus, Responder, TransactionTracker};
+use crate::gatekeeper::{Gatekeeper, RenewalFailure, TransferFailure, UserInfo};
+use crate::responder::{
+    ConfirmationStatus, PenaltyRecord, ReorgExposure, Responder, TransactionTracker,
+};
 use crate::tx_index::TxIndex;
 
+/// Number of appointments evicted at once when the database is full (see [Watcher::make_room]).
+const EVICTION_BATCH_SIZE: usize = 100;
+
 /// Structure holding data regarding a breach.
 ///
 /// Breaches are computed after spotting a [Locator] on chain and
@@ -41,6 +50,41 @@ impl Breach {
             penalty_tx,
         }
     }
+
+    /// The value of the dispute outputs spent by the penalty that is not assigned to any of its outputs, that is, the
+    /// fee the penalty pays. Zero if the penalty spends something other than the dispute outputs (so it cannot be told).
+    pub fn unallocated_value(&self) -> u64 {
+        let dispute_txid = self.dispute_tx.txid();
+        let input_value: Option<u64> = self
+            .penalty_tx
+            .input
+            .iter()
+            .map(|txin| {
+                (txin.previous_output.txid == dispute_txid)
+                    .then(|| {
+                        self.dispute_tx
+                            .output
+                            .get(txin.previous_output.vout as usize)
+                    })
+                    .flatten()
+                    .map(|o| o.value)
+            })
+            .sum();
+        let output_value: u64 = self.penalty_tx.output.iter().map(|o| o.value).sum();
+
+        input_value.map_or(0, |v| v.saturating_sub(output_value))
+    }
+}
+
+/// Optional terms users can attach to an appointment when adding it (see [Watcher::add_appointment_with_terms]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct AppointmentTerms {
+    /// Number of blocks (counting from its `start_block`) the appointment needs to be watched for.
+    pub ttl: Option<u32>,
+    /// Priority of the appointment penalty over the ones of appointments triggered in the same block.
+    pub priority: Option<u32>,
+    /// Reward (in sats) the tower is entitled to if the appointment is triggered.
+    pub reward: Option<u64>,
 }
 
 /// Packs the reasons why trying to add an appointment may fail.
@@ -51,6 +95,7 @@ pub(crate) enum AddAppointmentFailure {
     NotEnoughSlots,
     SubscriptionExpired(u32),
     AlreadyTriggered,
+    StorageFull,
 }
 
 /// Packs the reasons why trying to query an appointment may fail.
@@ -61,6 +106,15 @@ pub(crate) enum GetAppointmentFailure {
     NotFound,
 }
 
+/// Packs the reasons why trying to delete an appointment may fail.
+#[derive(Debug)]
+pub(crate) enum DeleteAppointmentFailure {
+    AuthenticationFailure,
+    SubscriptionExpired(u32),
+    NotFound,
+    AlreadyTriggered,
+}
+
 /// Packs the reasons why trying to query a subscription info may fail.
 #[derive(Debug)]
 pub(crate) enum GetSubscriptionInfoFailure {
@@ -68,6 +122,65 @@ pub(crate) enum GetSubscriptionInfoFailure {
     SubscriptionExpired(u32),
 }
 
+/// Packs the reasons why trying to transfer a subscription may fail.
+#[derive(Debug)]
+pub(crate) enum TransferSubscriptionFailure {
+    AuthenticationFailure,
+    SubscriptionExpired(u32),
+    AlreadyRegistered,
+    Replayed,
+    NotAllowed,
+}
+
+/// Packs the reasons why manually triggering a penalty may fail.
+#[derive(Debug)]
+pub(crate) enum TriggerPenaltyFailure {
+    NotFound,
+    AlreadyTriggered,
+    DecryptionFailed,
+    Rejected(i32),
+}
+
+/// A discrepancy between the different pieces of data held by the tower (see [Watcher::check_integrity]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum IntegrityIssue {
+    /// A row of the given table references data that cannot be found: an appointment whose user is gone, or some
+    /// appointment data (e.g. a tracker) whose appointment is gone.
+    DanglingRow { table: &'static str, uuid: UUID },
+    /// The slots available to a user according to the [Gatekeeper] do not match the stored ones (if stored at all).
+    SlotMismatch {
+        user_id: UserId,
+        available_slots: u32,
+        stored_slots: Option<u32>,
+    },
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntegrityIssue::DanglingRow { table, uuid } => {
+                write!(f, "Dangling row in {table}: {uuid}")
+            }
+            IntegrityIssue::SlotMismatch {
+                user_id,
+                available_slots,
+                stored_slots: Some(stored_slots),
+            } => write!(
+                f,
+                "Slot mismatch for user {user_id}: {available_slots} available, {stored_slots} stored"
+            ),
+            IntegrityIssue::SlotMismatch {
+                user_id,
+                available_slots,
+                stored_slots: None,
+            } => write!(
+                f,
+                "User {user_id} not stored ({available_slots} slots available)"
+            ),
+        }
+    }
+}
+
 /// Wraps the returning information regarding a queried appointment.
 ///
 /// Either an [Appointment] or a [TransactionTracker] can be
@@ -110,6 +223,10 @@ pub struct Watcher {
     pub tower_id: TowerId,
     /// A [DBM] (database manager) instance. Used to persist appointment data into disk.
     dbm: Arc<Mutex<DBM>>,
+    /// The maximum size (in bytes) the database can grow to. Zero means unlimited.
+    max_db_size: u64,
+    /// Whether the slots of the appointments deleted at the request of their owners are given back to them.
+    refund_deletions: bool,
 }
 
 impl Watcher {
@@ -131,9 +248,26 @@ impl Watcher {
             signing_key,
             tower_id,
             dbm,
+            max_db_size: 0,
+            refund_deletions: false,
         }
     }
 
+    /// Sets the maximum size (in bytes) the database can grow to. Zero (the default) means unlimited.
+    ///
+    /// Once reached, the oldest appointments of expired subscriptions are evicted to make room for new ones. If there is
+    /// nothing left to evict, new appointments are rejected.
+    pub fn with_max_db_size(mut self, max_db_size: u64) -> Self {
+        self.max_db_size = max_db_size;
+        self
+    }
+
+    /// Sets whether the slots of the appointments deleted at the request of their owners are given back to them.
+    pub fn with_deletion_refunds(mut self, refund_deletions: bool) -> Self {
+        self.refund_deletions = refund_deletions;
+        self
+    }
+
     /// Returns whether the [Watcher] has been created from scratch (fresh) or from backed-up data.
     pub fn is_fresh(&self) -> bool {
         self.get_appointments_count() == 0
@@ -141,14 +275,48 @@ impl Watcher {
 
     /// Registers a new user within the [Watcher]. This request is passed to the [Gatekeeper], who is in
     /// charge of managing users.
-    pub(crate) fn register(&self, user_id: UserId) -> Result<RegistrationReceipt, MaxSlotsReached> {
+    pub(crate) fn register(&self, user_id: UserId) -> Result<RegistrationReceipt, RenewalFailure> {
         let mut receipt = self.gatekeeper.add_update_user(user_id)?;
         receipt.sign(&self.signing_key);
 
         Ok(receipt)
     }
 
-    /// Adds a new [Appointment] to the tower.
+    /// Sets the [SignatureVersion] agreed with a given user. Internally calls [Gatekeeper::set_signature_version].
+    pub(crate) fn set_signature_version(&self, user_id: UserId, version: SignatureVersion) {
+        self.gatekeeper.set_signature_version(user_id, version)
+    }
+
+    /// Gets the [SignatureVersion] agreed with a given user. Internally calls [Gatekeeper::get_signature_version].
+    pub(crate) fn get_signature_version(&self, user_id: UserId) -> Option<SignatureVersion> {
+        self.gatekeeper.get_signature_version(user_id)
+    }
+
+    /// Registers a new user within the [Watcher] given the preimage of a paid invoice (see [Gatekeeper::add_update_paid_user]).
+    pub(crate) fn register_with_payment(
+        &self,
+        user_id: UserId,
+        payment_preimage: &[u8],
+    ) -> Result<RegistrationReceipt, RenewalFailure> {
+        let mut receipt = self
+            .gatekeeper
+            .add_update_paid_user(user_id, payment_preimage)?;
+        receipt.sign(&self.signing_key);
+
+        Ok(receipt)
+    }
+
+    /// Adds a new [Appointment] to the tower with no [AppointmentTerms] (see [add_appointment_with_terms](Self::add_appointment_with_terms)).
+    #[cfg(test)]
+    pub(crate) fn add_appointment(
+        &self,
+        appointment: Appointment,
+        user_signature: String,
+    ) -> Result<(AppointmentReceipt, u32, u32), AddAppointmentFailure> {
+        self.add_appointment_with_terms(appointment, user_signature, AppointmentTerms::default())
+    }
+
+    /// Adds a new [Appointment] to the tower alongside the [AppointmentTerms] set by the user.
     ///
     /// Appointments are only added provided:
     /// - The user is registered into the system
@@ -159,14 +327,33 @@ impl Watcher {
     /// If an appointment is accepted, an [ExtendedAppointment] (constructed from the [Appointment]) will be persisted on disk.
     /// In case the locator for the given appointment can be found in the cache (meaning the appointment has been
     /// triggered recently) the data will be passed to the [Responder] straightaway (modulo it being valid).
-    pub(crate) fn add_appointment(
+    ///
+    /// Re-sending an appointment that is already stored (same data and signature) is idempotent: no slots are charged
+    /// and the receipt handed the first time is returned again.
+    ///
+    /// Users can hint for how long an appointment needs to be watched (`ttl`). If so, the appointment is pruned (and its
+    /// slots given back to the user) once `ttl` blocks have been mined on top of its `start_block`.
+    ///
+    /// Users can also set a `priority` for the appointment. If several appointments are triggered in the same block,
+    /// their penalties are handed to the [Responder] from the highest to the lowest priority. Appointments with no
+    /// priority have the lowest one.
+    ///
+    /// Finally, appointments can carry the `reward` (in sats) the tower is entitled to if they are triggered, which the
+    /// [Responder] may claim when broadcasting their penalty (see [Responder::with_reward_script]). Given the penalty
+    /// is signed by the user, the reward needs to be covered by the user signature too (see [SignatureVersion::V3]),
+    /// and the penalty signed using `SIGHASH_SINGLE|ANYONECANPAY` so the reward output can be appended to it.
+    ///
+    /// Updating an appointment replaces all its terms, so an update without a TTL makes the appointment be kept for as
+    /// long as the user subscription is.
+    pub(crate) fn add_appointment_with_terms(
         &self,
         appointment: Appointment,
         user_signature: String,
+        terms: AppointmentTerms,
     ) -> Result<(AppointmentReceipt, u32, u32), AddAppointmentFailure> {
         let user_id = self
             .gatekeeper
-            .authenticate_user(&appointment.to_vec(), &user_signature)
+            .authenticate_appointment(&appointment, terms.reward, &user_signature)
             .map_err(|_| AddAppointmentFailure::AuthenticationFailure)?;
 
         let (has_subscription_expired, expiry) =
@@ -186,10 +373,53 @@ impl Watcher {
         let uuid = extended_appointment.uuid();
 
         if self.responder.has_tracker(uuid) {
+            // Updates of triggered appointments are only taken if their penalty supersedes the one being broadcast
+            if let Some(available_slots) =
+                self.update_triggered_appointment(uuid, &extended_appointment)
+            {
+                let mut receipt = AppointmentReceipt::new(
+                    extended_appointment.user_signature,
+                    extended_appointment.start_block,
+                );
+                receipt.sign(&self.signing_key);
+                return Ok((receipt, available_slots, expiry));
+            }
             log::info!("Tracker for {uuid} already found in Responder");
             return Err(AddAppointmentFailure::AlreadyTriggered);
         }
 
+        // The user may be re-sending the appointment because it did not get the receipt the first time around (e.g. it
+        // crashed before persisting it), so the stored one is kept as is.
+        let stored_appointment = self.dbm.lock().unwrap().load_appointment(uuid);
+        if let Some(stored) = stored_appointment.filter(|stored| {
+            stored.inner == extended_appointment.inner
+                && stored.user_signature == extended_appointment.user_signature
+        }) {
+            log::info!("Appointment {uuid} is already stored. Handing the existing receipt");
+            // The difference in size is zero, so no slots are charged
+            let available_slots = self
+                .gatekeeper
+                .add_update_appointment(user_id, uuid, &stored)
+                .map_err(|_| AddAppointmentFailure::NotEnoughSlots)?;
+            let dbm = self.dbm.lock().unwrap();
+            dbm.store_appointment_expiry(
+                uuid,
+                terms.ttl.map(|ttl| stored.start_block.saturating_add(ttl)),
+            )
+            .unwrap();
+            dbm.store_appointment_priority(uuid, terms.priority)
+                .unwrap();
+            dbm.store_appointment_reward(uuid, terms.reward).unwrap();
+
+            let mut receipt = AppointmentReceipt::new(stored.user_signature, stored.start_block);
+            receipt.sign(&self.signing_key);
+            return Ok((receipt, available_slots, expiry));
+        }
+
+        if !self.make_room() {
+            return Err(AddAppointmentFailure::StorageFull);
+        }
+
         // TODO: This is not atomic, we update the users slots and THEN add their appointment
         // this means it can happen that we update the slots but some failure happens before we insert their appointment.
         let available_slots = self
@@ -209,11 +439,28 @@ impl Watcher {
         {
             // Appointments that were triggered in blocks held in the cache
             Some(dispute_tx) => {
-                self.store_triggered_appointment(uuid, &extended_appointment, user_id, dispute_tx);
+                self.store_triggered_appointment(
+                    uuid,
+                    &extended_appointment,
+                    user_id,
+                    dispute_tx,
+                    terms.reward,
+                );
             }
             // Regular appointments that have not been triggered (or, at least, not recently)
             None => {
                 self.store_appointment(uuid, &extended_appointment);
+                let dbm = self.dbm.lock().unwrap();
+                dbm.store_appointment_expiry(
+                    uuid,
+                    terms
+                        .ttl
+                        .map(|ttl| extended_appointment.start_block.saturating_add(ttl)),
+                )
+                .unwrap();
+                dbm.store_appointment_priority(uuid, terms.priority)
+                    .unwrap();
+                dbm.store_appointment_reward(uuid, terms.reward).unwrap();
             }
         };
 
@@ -226,6 +473,51 @@ impl Watcher {
         Ok((receipt, available_slots, expiry))
     }
 
+    /// Makes sure the database is below its [maximum size](Self::max_db_size), evicting the oldest appointments of
+    /// expired subscriptions if needed. Appointments that have already been triggered are never evicted.
+    ///
+    /// Returns whether there is room for new data.
+    fn make_room(&self) -> bool {
+        if self.max_db_size == 0 {
+            return true;
+        }
+
+        let height = self.last_known_block_height.load(Ordering::Acquire);
+        loop {
+            // WARNING(deadlock): Don't lock `self.dbm` over the loop since `Gatekeeper::delete_appointments` uses it as well.
+            let (size, evictable) = {
+                let dbm = self.dbm.lock().unwrap();
+                let size = dbm.get_used_size();
+                if size < self.max_db_size {
+                    return true;
+                }
+                (
+                    size,
+                    dbm.load_evictable_appointments(height, EVICTION_BATCH_SIZE),
+                )
+            };
+
+            if evictable.is_empty() {
+                log::warn!(
+                    "Database is full ({size}/{} bytes) and there is nothing left to evict. Rejecting new appointments",
+                    self.max_db_size
+                );
+                return false;
+            }
+            log::info!(
+                "Database is full ({size}/{} bytes). Evicting {} appointments of expired subscriptions",
+                self.max_db_size,
+                evictable.len()
+            );
+            self.gatekeeper.delete_appointments(evictable, false);
+        }
+    }
+
+    /// Gets the size (in bytes) of the data held by the database, alongside its maximum size (zero meaning unlimited).
+    pub(crate) fn get_db_size(&self) -> (u64, u64) {
+        (self.dbm.lock().unwrap().get_used_size(), self.max_db_size)
+    }
+
     /// Stores an appointment in the database (or updates it if it already exists).
     fn store_appointment(
         &self,
@@ -246,6 +538,56 @@ impl Watcher {
         }
     }
 
+    /// Updates an appointment that has already been triggered, as long as its penalty supersedes the one the [Responder]
+    /// is broadcasting (see [Responder::replace_penalty]).
+    ///
+    /// Updates cannot take more slots than the appointment they replace. Returns the slots left to the user if the
+    /// appointment was updated, [None] otherwise.
+    fn update_triggered_appointment(
+        &self,
+        uuid: UUID,
+        appointment: &ExtendedAppointment,
+    ) -> Option<u32> {
+        let (dispute_tx, stored_size) = {
+            let dbm = self.dbm.lock().unwrap();
+            (
+                dbm.load_tracker(uuid)?.dispute_tx,
+                dbm.get_appointment_length(uuid)?,
+            )
+        };
+        if compute_appointment_slots(appointment.encrypted_blob().len(), ENCRYPTED_BLOB_MAX_SIZE)
+            > compute_appointment_slots(stored_size, ENCRYPTED_BLOB_MAX_SIZE)
+        {
+            log::info!("Update of triggered appointment {uuid} needs more slots. Ignoring it");
+            return None;
+        }
+        let penalty_tx =
+            cryptography::decrypt(appointment.encrypted_blob(), &dispute_tx.txid()).ok()?;
+
+        match self
+            .responder
+            .replace_penalty(uuid, Breach::new(dispute_tx, penalty_tx))
+        {
+            Ok(_) => {
+                // The update takes no more slots than the stored appointment, so there is always room for it
+                let available_slots = self
+                    .gatekeeper
+                    .add_update_appointment(appointment.user_id, uuid, appointment)
+                    .unwrap();
+                self.dbm
+                    .lock()
+                    .unwrap()
+                    .update_appointment(uuid, appointment)
+                    .unwrap();
+                Some(available_slots)
+            }
+            Err(e) => {
+                log::info!("Not updating triggered appointment {uuid}. Its penalty cannot be replaced: {e:?}");
+                None
+            }
+        }
+    }
+
     /// Stores and already triggered appointment in the database and hands it to the [Responder].
     ///
     /// If the appointment is rejected by the [Responder] (i.e. for being invalid), the data is wiped
@@ -256,23 +598,26 @@ impl Watcher {
         appointment: &ExtendedAppointment,
         user_id: UserId,
         dispute_tx: &Transaction,
+        reward: Option<u64>,
     ) -> TriggeredAppointment {
         log::info!(
             "Trigger for locator {} found in cache",
             appointment.locator()
         );
-        match cryptography::decrypt(appointment.encrypted_blob(), &dispute_tx.txid()) {
-            Ok(penalty_tx) => {
+        match self.build_penalty(uuid, appointment, user_id, dispute_tx) {
+            Some(penalty_tx) => {
                 // Data needs to be added the database straightaway since appointments are
                 // FKs to trackers. If handle breach fails, data will be deleted later.
-                self.dbm
-                    .lock()
-                    .unwrap()
-                    .store_appointment(uuid, appointment)
-                    // TODO: Don't unwrap, or better, make this insertion atomic with the
-                    // `responder.has_tracker` that might cause the unwrap in the first place.
-                    // ref: https://github.com/talaia-labs/rust-teos/pull/190#discussion_r1218235632
-                    .unwrap();
+                {
+                    let dbm = self.dbm.lock().unwrap();
+                    dbm.store_appointment(uuid, appointment)
+                        // TODO: Don't unwrap, or better, make this insertion atomic with the
+                        // `responder.has_tracker` that might cause the unwrap in the first place.
+                        // ref: https://github.com/talaia-labs/rust-teos/pull/190#discussion_r1218235632
+                        .unwrap();
+                    // The reward terms are needed by the Responder to build the penalty.
+                    dbm.store_appointment_reward(uuid, reward).unwrap();
+                }
 
                 if let ConfirmationStatus::Rejected(reason) = self.responder.handle_breach(
                     uuid,
@@ -292,7 +637,7 @@ impl Watcher {
             // If data inside the encrypted blob is invalid, the appointment is accepted but the data is dropped.
             // (same as with data that bounces in the Responder). This reduces the appointment slot count so it
             // could be used to discourage user misbehavior.
-            Err(_) => {
+            None => {
                 log::info!(
                     "The appointment contained invalid data {}",
                     appointment.locator()
@@ -302,6 +647,21 @@ impl Watcher {
         }
     }
 
+    /// Prunes the appointments whose TTL has elapsed by `height`, giving their slots back to their owners.
+    ///
+    /// Appointments that have already been triggered are left alone, since they are now handled by the [Responder].
+    fn prune_expired_appointments(&self, height: u32) {
+        let expired_appointments = self.dbm.lock().unwrap().load_expired_appointments(height);
+        if !expired_appointments.is_empty() {
+            log::info!(
+                "Pruning {} appointments whose TTL has elapsed",
+                expired_appointments.len()
+            );
+            self.gatekeeper
+                .delete_appointments(expired_appointments, true);
+        }
+    }
+
     /// Retrieves an [Appointment] from the tower.
     ///
     /// Appointments can only be retrieved provided:
@@ -342,6 +702,69 @@ impl Watcher {
             })
     }
 
+    /// Deletes an [Appointment] at the request of its owner (e.g. because the channel has been cooperatively closed).
+    ///
+    /// Appointments can only be deleted provided:
+    /// - The user is registered into the system
+    /// - The user subscription has not expired
+    /// - The appointment belongs to the user
+    /// - The appointment can be found in the [Watcher], and was accepted at `start_block`. Triggered appointments are handled by
+    ///   the [Responder] and cannot be deleted
+    ///
+    /// The user signs the locator and the `start_block` alongside the tower id (see [delete_appointment_message]).
+    ///
+    /// The appointment slots are only given back to the user if [refund_deletions](Self::refund_deletions) is set.
+    /// Returns a [DeletionReceipt] signed by the tower alongside the slots available to the user after the deletion.
+    pub(crate) fn delete_appointment(
+        &self,
+        locator: Locator,
+        start_block: u32,
+        user_signature: &str,
+    ) -> Result<(DeletionReceipt, u32), DeleteAppointmentFailure> {
+        let user_id = self
+            .gatekeeper
+            .authenticate_user(
+                &delete_appointment_message(self.tower_id, locator, start_block),
+                user_signature,
+            )
+            .map_err(|_| DeleteAppointmentFailure::AuthenticationFailure)?;
+
+        let (has_subscription_expired, expiry) =
+            self.gatekeeper.has_subscription_expired(user_id).unwrap();
+
+        if has_subscription_expired {
+            return Err(DeleteAppointmentFailure::SubscriptionExpired(expiry));
+        }
+
+        let uuid = UUID::new(locator, user_id);
+        if self.responder.has_tracker(uuid) {
+            log::info!("Tracker for {uuid} already found in Responder");
+            return Err(DeleteAppointmentFailure::AlreadyTriggered);
+        }
+        // The signed start block must match the one of the stored appointment, so requests for an appointment that has
+        // since been replaced under the same locator do not delete the new one
+        if !matches!(
+            self.dbm.lock().unwrap().load_appointment(uuid),
+            Some(appointment) if appointment.start_block == start_block
+        ) {
+            log::info!("Cannot find {locator} (start_block={start_block})");
+            return Err(DeleteAppointmentFailure::NotFound);
+        }
+
+        self.gatekeeper
+            .delete_appointments(vec![uuid], self.refund_deletions);
+        log::info!("Appointment {uuid} deleted at the request of its owner");
+
+        let (user_info, _) = self.gatekeeper.get_user_info(user_id).unwrap();
+        let mut receipt = DeletionReceipt::new(
+            user_signature.to_owned(),
+            self.last_known_block_height.load(Ordering::Acquire),
+        );
+        receipt.sign(&self.signing_key);
+
+        Ok((receipt, user_info.available_slots))
+    }
+
     /// Gets a map of breaches provided a map between locators and transactions.
     ///
     /// The provided map if intersected with the map of all locators monitored by [Watcher] and the result
@@ -371,43 +794,116 @@ impl Watcher {
 
     /// Responds to breaches.
     ///
-    /// Decrypts triggered appointments using the dispute transaction ID and publishes them.
+    /// Decrypts triggered appointments using the dispute transaction ID and publishes them, from the highest to the
+    /// lowest priority appointment.
     /// If the decryption fails for some appointments or if it succeeds but they get rejected when sent to the network,
     /// they are marked as an invalid breaches and returned.
     /// [None] is returned if none of these breaches are invalid.
     fn handle_breaches(&self, breaches: HashMap<Locator, Transaction>) -> Option<Vec<UUID>> {
         let mut invalid_breaches = Vec::new();
 
+        let mut triggered = Vec::new();
         for (locator, dispute_tx) in breaches.into_iter() {
+            let dbm = self.dbm.lock().unwrap();
+            for uuid in dbm.load_uuids(locator) {
+                triggered.push((
+                    dbm.load_appointment_priority(uuid),
+                    uuid,
+                    dispute_tx.clone(),
+                ));
+            }
+        }
+        // The sort is stable, so appointments with the same priority are not reordered.
+        triggered.sort_by_key(|(priority, ..)| Reverse(*priority));
+
+        for (_, uuid, dispute_tx) in triggered {
             // WARNING(deadlock): Don't lock `self.dbm` over the loop since `Responder::handle_breach` uses it as well.
-            let uuids = self.dbm.lock().unwrap().load_uuids(locator);
-            for uuid in uuids {
-                let appointment = self.dbm.lock().unwrap().load_appointment(uuid).unwrap();
-                match cryptography::decrypt(appointment.encrypted_blob(), &dispute_tx.txid()) {
-                    Ok(penalty_tx) => {
-                        if let ConfirmationStatus::Rejected(_) = self.responder.handle_breach(
-                            uuid,
-                            Breach::new(dispute_tx.clone(), penalty_tx),
-                            appointment.user_id,
-                        ) {
-                            invalid_breaches.push(uuid);
-                        }
-                    }
-                    Err(_) => {
+            let appointment = self.dbm.lock().unwrap().load_appointment(uuid).unwrap();
+            match self.build_penalty(uuid, &appointment, appointment.user_id, &dispute_tx) {
+                Some(penalty_tx) => {
+                    if let ConfirmationStatus::Rejected(_) = self.responder.handle_breach(
+                        uuid,
+                        Breach::new(dispute_tx, penalty_tx),
+                        appointment.user_id,
+                    ) {
                         invalid_breaches.push(uuid);
                     }
                 }
+                None => {
+                    invalid_breaches.push(uuid);
+                }
             }
         }
 
         (!invalid_breaches.is_empty()).then_some(invalid_breaches)
     }
 
+    /// Builds the penalty of a triggered appointment by decrypting its blob using the dispute transaction id.
+    ///
+    /// If that is not possible, the [Responder] handles the breach as unconstructable, and may still recover the penalty.
+    fn build_penalty(
+        &self,
+        uuid: UUID,
+        appointment: &ExtendedAppointment,
+        user_id: UserId,
+        dispute_tx: &Transaction,
+    ) -> Option<Transaction> {
+        cryptography::decrypt(appointment.encrypted_blob(), &dispute_tx.txid()).map_or_else(
+            |e| {
+                self.responder.recover_penalty(
+                    uuid,
+                    dispute_tx,
+                    appointment.encrypted_blob(),
+                    user_id,
+                    e,
+                )
+            },
+            Some,
+        )
+    }
+
+    /// Triggers the penalty of a given appointment as if `dispute_tx` had been seen on chain.
+    ///
+    /// The penalty is decrypted using the dispute transaction id and handed to the [Responder], so it is broadcast and
+    /// tracked as any other. This is meant to test the response path end to end, so the appointment is kept even if
+    /// the penalty cannot be decrypted or gets rejected.
+    pub(crate) fn trigger_penalty(
+        &self,
+        locator: Locator,
+        user_id: UserId,
+        dispute_tx: Transaction,
+    ) -> Result<TransactionTracker, TriggerPenaltyFailure> {
+        let uuid = UUID::new(locator, user_id);
+        if self.responder.has_tracker(uuid) {
+            return Err(TriggerPenaltyFailure::AlreadyTriggered);
+        }
+        let appointment = self
+            .dbm
+            .lock()
+            .unwrap()
+            .load_appointment(uuid)
+            .ok_or(TriggerPenaltyFailure::NotFound)?;
+
+        log::warn!("Manually triggering the penalty for {locator} (user_id={user_id})");
+        let penalty_tx = cryptography::decrypt(appointment.encrypted_blob(), &dispute_tx.txid())
+            .map_err(|_| TriggerPenaltyFailure::DecryptionFailed)?;
+        let breach = Breach::new(dispute_tx, penalty_tx);
+        match self.responder.handle_breach(uuid, breach.clone(), user_id) {
+            ConfirmationStatus::Rejected(reason) => Err(TriggerPenaltyFailure::Rejected(reason)),
+            status => Ok(TransactionTracker::new(breach, user_id, status)),
+        }
+    }
+
     /// Ges the number of users currently registered with the tower.
     pub(crate) fn get_registered_users_count(&self) -> usize {
         self.gatekeeper.get_registered_users_count()
     }
 
+    /// Gets the number of users whose subscription has expired but are not outdated yet.
+    pub(crate) fn get_expired_users_count(&self) -> usize {
+        self.gatekeeper.get_expired_users_count()
+    }
+
     /// Gets the total number of appointments excluding trackers.
     pub(crate) fn get_appointments_count(&self) -> usize {
         self.dbm.lock().unwrap().get_appointments_count()
@@ -418,6 +914,17 @@ impl Watcher {
         self.responder.get_trackers_count()
     }
 
+    /// Estimates the feerate (in sat/vB) needed for a transaction to confirm within `conf_target` blocks, so users can
+    /// pre-sign their penalties accordingly. Returns [None] if no estimate is available.
+    pub(crate) fn estimate_feerate(&self, conf_target: u16) -> Option<f64> {
+        self.responder.estimate_feerate(conf_target)
+    }
+
+    /// Gets how exposed the penalties tracked by the [Responder] are to reorgs.
+    pub(crate) fn get_reorg_exposure(&self) -> ReorgExposure {
+        self.responder.get_reorg_exposure()
+    }
+
     /// Gets all the appointments stored in the [Watcher] (from the database).
     pub(crate) fn get_all_watcher_appointments(&self) -> HashMap<UUID, ExtendedAppointment> {
         self.dbm.lock().unwrap().load_appointments(None)
@@ -454,6 +961,78 @@ impl Watcher {
         self.gatekeeper.get_user_info(user_id)
     }
 
+    /// Gets the penalty ledger records within a given time range.
+    /// Internally calls [Responder::get_penalties].
+    pub(crate) fn get_penalties(&self, start: Option<u64>, end: Option<u64>) -> Vec<PenaltyRecord> {
+        self.responder.get_penalties(start, end)
+    }
+
+    /// Reloads the lists of users allowed to, or blocked from, registering with the tower.
+    /// Internally calls [Gatekeeper::reload_access_lists].
+    pub(crate) fn reload_access_lists(&self) -> Result<(Option<usize>, usize), String> {
+        self.gatekeeper.reload_access_lists()
+    }
+
+    /// Rebroadcasts the penalties that have not been confirmed yet. Internally calls [Responder::rebroadcast_all].
+    pub(crate) fn rebroadcast_penalties(&self) -> (Vec<UUID>, Vec<UUID>) {
+        self.responder.rebroadcast_all()
+    }
+
+    /// Checks the data held by the tower is consistent: every tracker (and any other appointment data) references a stored
+    /// appointment, every appointment references a stored user, and the slots available to every user according to the
+    /// [Gatekeeper] match the stored ones. Returns the discrepancies found.
+    ///
+    /// Nothing is modified unless `repair` is set, in which case dangling rows are removed and the stored users are
+    /// overwritten with the data held by the [Gatekeeper].
+    pub(crate) fn check_integrity(&self, repair: bool) -> Vec<IntegrityIssue> {
+        let mut issues = self
+            .dbm
+            .lock()
+            .unwrap()
+            .load_dangling_rows()
+            .into_iter()
+            .map(|(table, uuid)| IntegrityIssue::DanglingRow { table, uuid })
+            .collect::<Vec<_>>();
+        let slot_mismatches = self.gatekeeper.get_slot_mismatches();
+        issues.extend(
+            slot_mismatches
+                .iter()
+                .map(
+                    |(user_id, available_slots, stored_slots)| IntegrityIssue::SlotMismatch {
+                        user_id: *user_id,
+                        available_slots: *available_slots,
+                        stored_slots: *stored_slots,
+                    },
+                ),
+        );
+
+        if repair && !issues.is_empty() {
+            // Users go first, so the appointments of the users that were missing are not taken as dangling.
+            self.gatekeeper.persist_users(
+                &slot_mismatches
+                    .iter()
+                    .map(|(user_id, ..)| *user_id)
+                    .collect::<Vec<_>>(),
+            );
+            match self.dbm.lock().unwrap().remove_dangling_rows() {
+                Ok(removed) => log::info!("Integrity repair removed {removed} dangling row(s)"),
+                Err(e) => log::error!("Couldn't remove dangling rows. Error: {e:?}"),
+            }
+        }
+
+        issues
+    }
+
+    /// Checks the data held by the tower (see [Self::check_integrity]) without modifying it, logging the discrepancies
+    /// found. Returns how many were found. Meant to be run on startup.
+    pub fn report_integrity_issues(&self) -> usize {
+        let issues = self.check_integrity(false);
+        for issue in issues.iter() {
+            log::warn!("{issue}");
+        }
+        issues.len()
+    }
+
     /// Gets information about a user's subscription.
     pub(crate) fn get_subscription_info(
         &self,
@@ -476,6 +1055,55 @@ impl Watcher {
         let (subscription_info, locators) = self.gatekeeper.get_user_info(user_id).unwrap();
         Ok((subscription_info, locators))
     }
+
+    /// Transfers a user subscription (slots, expiry and appointments) from `old_user_id` to `new_user_id`.
+    ///
+    /// The transfer message (see [transfer_subscription_message]) must be signed by both the current user key, which
+    /// needs an ongoing subscription, and the new one. The resulting [RegistrationReceipt] is issued for the new user.
+    pub(crate) fn transfer_subscription(
+        &self,
+        old_user_id: UserId,
+        new_user_id: UserId,
+        nonce: u64,
+        old_user_signature: &str,
+        new_user_signature: &str,
+    ) -> Result<RegistrationReceipt, TransferSubscriptionFailure> {
+        let message = transfer_subscription_message(self.tower_id, old_user_id, new_user_id, nonce);
+
+        let user_id = self
+            .gatekeeper
+            .authenticate_user(&message, old_user_signature)
+            .map_err(|_| TransferSubscriptionFailure::AuthenticationFailure)?;
+        if user_id != old_user_id
+            || !cryptography::verify(&message, new_user_signature, &new_user_id.0)
+        {
+            return Err(TransferSubscriptionFailure::AuthenticationFailure);
+        }
+
+        let (has_subscription_expired, expiry) =
+            self.gatekeeper.has_subscription_expired(user_id).unwrap();
+
+        if has_subscription_expired {
+            return Err(TransferSubscriptionFailure::SubscriptionExpired(expiry));
+        }
+
+        // The message commits to both users, the tower and the nonce, so it identifies the transfer.
+        let transfer_id = sha256::Hash::hash(&message);
+        let mut receipt = self
+            .gatekeeper
+            .transfer_subscription(user_id, new_user_id, &transfer_id)
+            .map_err(|e| match e {
+                TransferFailure::UserNotFound => TransferSubscriptionFailure::AuthenticationFailure,
+                TransferFailure::AlreadyRegistered => {
+                    TransferSubscriptionFailure::AlreadyRegistered
+                }
+                TransferFailure::Replayed => TransferSubscriptionFailure::Replayed,
+                TransferFailure::NotAllowed => TransferSubscriptionFailure::NotAllowed,
+            })?;
+        receipt.sign(&self.signing_key);
+
+        Ok(receipt)
+    }
 }
 
 /// Listen implementation by the [Watcher]. Handles monitoring and reorgs.
@@ -489,8 +1117,8 @@ impl chain::Listen for Watcher {
     /// Then, the potential locators are checked against the data being monitored by the [Watcher] and passed to the
     /// [Responder] if valid. Otherwise data is removed from the tower.
     ///
-    /// This also takes care of updating the [LocatorCache] and removing outdated data from the [Watcher] when
-    /// told by the [Gatekeeper].
+    /// This also takes care of updating the [LocatorCache], pruning appointments whose TTL has elapsed, and removing
+    /// outdated data from the [Watcher] when told by the [Gatekeeper].
     fn filtered_block_connected(
         &self,
         header: &BlockHeader,
@@ -514,6 +1142,10 @@ impl chain::Listen for Watcher {
             self.gatekeeper.delete_appointments(invalid_breaches, false);
         }
 
+        // Prune the appointments that didn't need to be watched past this block. This is done after handling the
+        // breaches so appointments are still watched in the last block of their TTL.
+        self.prune_expired_appointments(height);
+
         // Update last known block
         self.last_known_block_height
             .store(height, Ordering::Release);
@@ -542,15 +1174,18 @@ mod tests {
     use std::sync::{Arc, Mutex};
 
     use crate::dbm::DBM;
-    use crate::responder::ConfirmationStatus;
+    use crate::responder::{ConfirmationStatus, PenaltyStatus};
     use crate::rpc_errors;
     use crate::test_utils::{
         create_carrier, create_responder, create_watcher, generate_dummy_appointment,
-        generate_dummy_appointment_with_user, get_random_tx, BitcoindMock, BitcoindStopper,
-        Blockchain, MockOptions, MockedServerQuery, DURATION, EXPIRY_DELTA, SLOTS, START_HEIGHT,
+        generate_dummy_appointment_with_user, get_random_tracker, get_random_tx, BitcoindMock,
+        BitcoindStopper, Blockchain, MockOptions, MockedServerQuery, DURATION, EXPIRY_DELTA,
+        MAX_EXPIRY_HORIZON, RENEWAL_WINDOW, SLOTS, START_HEIGHT,
     };
     use teos_common::cryptography::get_random_keypair;
+    use teos_common::dbm::DatabaseConnection;
 
+    use bitcoin::consensus;
     use bitcoin::secp256k1::{PublicKey, Secp256k1};
 
     use lightning::chain::Listen;
@@ -587,13 +1222,23 @@ mod tests {
         chain: &mut Blockchain,
         dbm: Arc<Mutex<DBM>>,
     ) -> (Watcher, BitcoindStopper) {
-        let bitcoind_mock = BitcoindMock::new(MockOptions::default());
+        init_watcher_with_options(chain, dbm, MockOptions::default()).await
+    }
+
+    async fn init_watcher_with_options(
+        chain: &mut Blockchain,
+        dbm: Arc<Mutex<DBM>>,
+        options: MockOptions,
+    ) -> (Watcher, BitcoindStopper) {
+        let bitcoind_mock = BitcoindMock::new(options);
 
         let gk = Arc::new(Gatekeeper::new(
             chain.get_block_count(),
             SLOTS,
             DURATION,
             EXPIRY_DELTA,
+            RENEWAL_WINDOW,
+            MAX_EXPIRY_HORIZON,
             dbm.clone(),
         ));
         let responder = create_responder(chain, gk.clone(), dbm.clone(), bitcoind_mock.url()).await;
@@ -867,20 +1512,218 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_store_appointment() {
+    async fn test_add_appointment_idempotent() {
         let mut chain = Blockchain::default().with_height(START_HEIGHT);
         let (watcher, _s) = init_watcher(&mut chain).await;
 
-        // Register the user
-        let (_, user_pk) = get_random_keypair();
+        let (user_sk, user_pk) = get_random_keypair();
         let user_id = UserId(user_pk);
         watcher.register(user_id).unwrap();
-        let dispute_txid = get_random_tx().txid();
+        let appointment = generate_dummy_appointment(None).inner;
+        let user_sig = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
 
-        let (uuid, appointment) =
-            generate_dummy_appointment_with_user(user_id, Some(&dispute_txid));
+        let (receipt, slots, _) = watcher
+            .add_appointment(appointment.clone(), user_sig.clone())
+            .unwrap();
+        assert_eq!(slots, SLOTS - 1);
 
-        // Storing a new appointment should return New
+        // Sending the very same appointment again, even blocks later, does not consume another slot and gets the same
+        // receipt back
+        watcher
+            .last_known_block_height
+            .store(START_HEIGHT as u32 + 1, Ordering::Release);
+        let (same_receipt, slots, _) = watcher
+            .add_appointment(appointment.clone(), user_sig.clone())
+            .unwrap();
+        assert_eq!(same_receipt, receipt);
+        assert_eq!(slots, SLOTS - 1);
+        let uuid = UUID::new(appointment.locator, user_id);
+        assert_eq!(
+            watcher
+                .dbm
+                .lock()
+                .unwrap()
+                .load_appointment(uuid)
+                .unwrap()
+                .start_block,
+            START_HEIGHT as u32
+        );
+
+        // Actual updates are still handled as such
+        let mut update = generate_dummy_appointment(None).inner;
+        update.locator = appointment.locator;
+        let update_sig = cryptography::sign(&update.to_vec(), &user_sk).unwrap();
+        let (update_receipt, slots, _) =
+            watcher.add_appointment(update, update_sig.clone()).unwrap();
+        assert_eq!(update_receipt.start_block(), START_HEIGHT as u32 + 1);
+        assert_eq!(update_receipt.user_signature(), update_sig);
+        assert_eq!(slots, SLOTS - 1);
+    }
+
+    #[tokio::test]
+    async fn test_add_appointment_reward() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+        let terms = AppointmentTerms {
+            reward: Some(1000),
+            ..Default::default()
+        };
+
+        // Users that agreed on a signature version that does not commit to the reward cannot set one
+        let (user_sk, user_pk) = get_random_keypair();
+        watcher.register(UserId(user_pk)).unwrap();
+        let appointment = generate_dummy_appointment(None).inner;
+        let user_sig = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        assert!(matches!(
+            watcher.add_appointment_with_terms(appointment, user_sig, terms),
+            Err(AddAppointmentFailure::AuthenticationFailure)
+        ));
+
+        // Whereas those who did get it stored along the appointment
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+        watcher.set_signature_version(user_id, SignatureVersion::V3);
+        let appointment = generate_dummy_appointment(None).inner;
+        let user_sig = cryptography::sign(
+            &appointment.to_signable_vec_with_reward(SignatureVersion::V3, terms.reward),
+            &user_sk,
+        )
+        .unwrap();
+        watcher
+            .add_appointment_with_terms(appointment.clone(), user_sig, terms)
+            .unwrap();
+        assert_eq!(
+            watcher
+                .dbm
+                .lock()
+                .unwrap()
+                .load_appointment_reward(UUID::new(appointment.locator, user_id)),
+            terms.reward
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_appointment_swept_expired_subscription() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+        let expiry = START_HEIGHT as u32 + DURATION;
+
+        // Connecting blocks up to the expiry height makes the gatekeeper sweep flag the subscription
+        for height in START_HEIGHT as u32 + 1..=expiry {
+            watcher
+                .gatekeeper
+                .block_connected(&chain.generate(None), height);
+        }
+        assert_eq!(watcher.get_expired_users_count(), 1);
+
+        // The user's subsequent appointments are rejected straightaway
+        let appointment = generate_dummy_appointment(None).inner;
+        let user_sig = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        assert!(matches!(
+            watcher.add_appointment(appointment, user_sig),
+            Err(AddAppointmentFailure::SubscriptionExpired(e)) if e == expiry
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_add_appointment_db_full() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (mut watcher, _s) = init_watcher(&mut chain).await;
+
+        // Fill the database with the appointments of a user whose subscription has expired. One of them has already
+        // been triggered.
+        let expired_user_id = UserId(get_random_keypair().1);
+        watcher.register(expired_user_id).unwrap();
+        let mut expired_uuids = Vec::new();
+        for _ in 0..200 {
+            let (uuid, appointment) = generate_dummy_appointment_with_user(expired_user_id, None);
+            watcher.store_appointment(uuid, &appointment);
+            expired_uuids.push(uuid);
+        }
+        let tracker = get_random_tracker(expired_user_id, ConfirmationStatus::ConfirmedIn(42));
+        watcher
+            .dbm
+            .lock()
+            .unwrap()
+            .store_tracker(expired_uuids[0], &tracker)
+            .unwrap();
+        {
+            let mut registered_users = watcher.gatekeeper.get_registered_users().lock().unwrap();
+            let user = registered_users.get_mut(&expired_user_id).unwrap();
+            user.subscription_expiry = START_HEIGHT as u32;
+            watcher
+                .dbm
+                .lock()
+                .unwrap()
+                .update_user(expired_user_id, user);
+        }
+
+        // Appointments are accepted as long as there is room for them
+        let (user_sk, user_pk) = get_random_keypair();
+        watcher.register(UserId(user_pk)).unwrap();
+        let add_appointment = |watcher: &Watcher| {
+            let appointment = generate_dummy_appointment(None).inner;
+            let user_sig = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+            watcher
+                .add_appointment(appointment.clone(), user_sig)
+                .map(|_| UUID::new(appointment.locator, UserId(user_pk)))
+        };
+        let uuid = add_appointment(&watcher).unwrap();
+
+        // Once the database is full, the oldest appointments of expired subscriptions are evicted to make room
+        let (full_size, _) = watcher.get_db_size();
+        watcher.max_db_size = full_size;
+        let new_uuid = add_appointment(&watcher).unwrap();
+        let (size, max_size) = watcher.get_db_size();
+        assert_eq!(max_size, full_size);
+        assert!(size < full_size);
+        {
+            let dbm = watcher.dbm.lock().unwrap();
+            let evicted = expired_uuids
+                .iter()
+                .filter(|uuid| !dbm.appointment_exists(**uuid))
+                .count();
+            assert!(evicted > 0);
+            assert!(dbm.appointment_exists(uuid));
+            assert!(dbm.appointment_exists(new_uuid));
+        }
+
+        // If there is nothing left to evict, appointments are rejected. Triggered appointments, and the ones of active
+        // subscriptions, are never evicted
+        watcher.max_db_size = 1;
+        assert!(matches!(
+            add_appointment(&watcher),
+            Err(AddAppointmentFailure::StorageFull)
+        ));
+        let dbm = watcher.dbm.lock().unwrap();
+        assert!(dbm.appointment_exists(expired_uuids[0]));
+        assert!(expired_uuids[1..]
+            .iter()
+            .all(|uuid| !dbm.appointment_exists(*uuid)));
+        assert!(dbm.appointment_exists(uuid));
+        assert!(dbm.appointment_exists(new_uuid));
+    }
+
+    #[tokio::test]
+    async fn test_store_appointment() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+
+        // Register the user
+        let (_, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+        let dispute_txid = get_random_tx().txid();
+
+        let (uuid, appointment) =
+            generate_dummy_appointment_with_user(user_id, Some(&dispute_txid));
+
+        // Storing a new appointment should return New
         assert_eq!(
             watcher.store_appointment(uuid, &appointment),
             StoredAppointment::New,
@@ -921,7 +1764,7 @@ mod tests {
 
         // Valid triggered appointments should be accepted by the Responder
         assert_eq!(
-            watcher.store_triggered_appointment(uuid, &appointment, user_id, &dispute_tx),
+            watcher.store_triggered_appointment(uuid, &appointment, user_id, &dispute_tx, None),
             TriggeredAppointment::Accepted,
         );
         // In this case the appointment is kept in the Responder and, therefore, in the database
@@ -939,7 +1782,7 @@ mod tests {
         let (uuid, appointment) =
             generate_dummy_appointment_with_user(user_id, Some(&dispute_tx.txid()));
         assert_eq!(
-            watcher.store_triggered_appointment(uuid, &appointment, user_id, &dispute_tx),
+            watcher.store_triggered_appointment(uuid, &appointment, user_id, &dispute_tx, None),
             TriggeredAppointment::Rejected,
         );
         // In this case the appointment is not kept in the Responder nor in the database
@@ -951,7 +1794,7 @@ mod tests {
         // (the same applies to invalid formatted transactions)
         let (uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
         assert_eq!(
-            watcher.store_triggered_appointment(uuid, &appointment, user_id, &dispute_tx),
+            watcher.store_triggered_appointment(uuid, &appointment, user_id, &dispute_tx, None),
             TriggeredAppointment::Invalid,
         );
         // The appointment is not kept anywhere
@@ -1047,6 +1890,359 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn test_delete_appointment() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (mut watcher, _s) = init_watcher(&mut chain).await;
+        let tower_id = watcher.tower_id;
+
+        // If the user cannot be properly identified, the request will fail
+        let appointment = generate_dummy_appointment(None).inner;
+        let wrong_sig = String::from_utf8((0..65).collect()).unwrap();
+        assert!(matches!(
+            watcher.delete_appointment(appointment.locator, START_HEIGHT as u32, &wrong_sig),
+            Err(DeleteAppointmentFailure::AuthenticationFailure)
+        ));
+
+        // If the appointment cannot be found, NotFound is returned
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+        let sign_deletion = |locator, start_block| {
+            cryptography::sign(
+                &delete_appointment_message(tower_id, locator, start_block),
+                &user_sk,
+            )
+            .unwrap()
+        };
+        let start_block = watcher.last_known_block_height.load(Ordering::Relaxed);
+        assert!(matches!(
+            watcher.delete_appointment(
+                appointment.locator,
+                start_block,
+                &sign_deletion(appointment.locator, start_block)
+            ),
+            Err(DeleteAppointmentFailure::NotFound)
+        ));
+
+        // Otherwise, the appointment is deleted and a deletion receipt signed by the tower is returned
+        let appointments: Vec<Appointment> = (0..3)
+            .map(|_| {
+                let appointment = generate_dummy_appointment(None).inner;
+                let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+                watcher
+                    .add_appointment(appointment.clone(), signature)
+                    .unwrap();
+                appointment
+            })
+            .collect();
+
+        // Requests signed for a different tower, or for a different start block, are not valid for this appointment
+        let other_tower_signature = cryptography::sign(
+            &delete_appointment_message(
+                TowerId(get_random_keypair().1),
+                appointments[0].locator,
+                start_block,
+            ),
+            &user_sk,
+        )
+        .unwrap();
+        assert!(matches!(
+            watcher.delete_appointment(
+                appointments[0].locator,
+                start_block,
+                &other_tower_signature
+            ),
+            Err(DeleteAppointmentFailure::AuthenticationFailure)
+        ));
+        assert!(matches!(
+            watcher.delete_appointment(
+                appointments[0].locator,
+                start_block - 1,
+                &sign_deletion(appointments[0].locator, start_block - 1)
+            ),
+            Err(DeleteAppointmentFailure::NotFound)
+        ));
+
+        let signature = sign_deletion(appointments[0].locator, start_block);
+        let (receipt, slots) = watcher
+            .delete_appointment(appointments[0].locator, start_block, &signature)
+            .unwrap();
+        assert!(receipt.verify(&tower_id));
+        assert_eq!(receipt.user_signature(), signature);
+        assert_eq!(
+            receipt.deletion_block(),
+            watcher.last_known_block_height.load(Ordering::Relaxed)
+        );
+        assert!(!watcher
+            .dbm
+            .lock()
+            .unwrap()
+            .appointment_exists(UUID::new(appointments[0].locator, user_id)));
+        let get_signature = cryptography::sign(
+            format!("get appointment {}", appointments[0].locator).as_bytes(),
+            &user_sk,
+        )
+        .unwrap();
+        assert!(matches!(
+            watcher.get_appointment(appointments[0].locator, &get_signature),
+            Err(GetAppointmentFailure::NotFound)
+        ));
+
+        // The slots are not given back unless the tower is set to do so
+        assert_eq!(slots, SLOTS - 3);
+        watcher.refund_deletions = true;
+        let (_, slots) = watcher
+            .delete_appointment(
+                appointments[1].locator,
+                start_block,
+                &sign_deletion(appointments[1].locator, start_block),
+            )
+            .unwrap();
+        assert_eq!(slots, SLOTS - 2);
+
+        // Deleting the same appointment twice fails
+        assert!(matches!(
+            watcher.delete_appointment(
+                appointments[1].locator,
+                start_block,
+                &sign_deletion(appointments[1].locator, start_block)
+            ),
+            Err(DeleteAppointmentFailure::NotFound)
+        ));
+
+        // Appointments that have already been triggered cannot be deleted
+        let uuid = UUID::new(appointments[2].locator, user_id);
+        let breach = Breach::new(get_random_tx(), get_random_tx());
+        let status = ConfirmationStatus::InMempoolSince(chain.get_block_count());
+        watcher.responder.add_tracker(uuid, breach, user_id, status);
+        assert!(matches!(
+            watcher.delete_appointment(
+                appointments[2].locator,
+                start_block,
+                &sign_deletion(appointments[2].locator, start_block)
+            ),
+            Err(DeleteAppointmentFailure::AlreadyTriggered)
+        ));
+
+        // If the user subscription has expired, the request will fail
+        watcher
+            .gatekeeper
+            .add_outdated_user(user_id, START_HEIGHT as u32);
+        assert!(matches!(
+            watcher.delete_appointment(
+                appointments[2].locator,
+                start_block,
+                &sign_deletion(appointments[2].locator, start_block)
+            ),
+            Err(DeleteAppointmentFailure::SubscriptionExpired { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_subscription() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+        let tower_pk = watcher.tower_id.0;
+
+        let (old_sk, old_pk) = get_random_keypair();
+        let old_user_id = UserId(old_pk);
+        let (new_sk, new_pk) = get_random_keypair();
+        let new_user_id = UserId(new_pk);
+        watcher.register(old_user_id).unwrap();
+
+        // Add an appointment and a tracker to the subscription
+        let appointment = generate_dummy_appointment(None).inner;
+        watcher
+            .add_appointment(
+                appointment.clone(),
+                cryptography::sign(&appointment.to_vec(), &old_sk).unwrap(),
+            )
+            .unwrap();
+        let dispute_tx = get_random_tx();
+        let triggered = generate_dummy_appointment(Some(&dispute_tx.txid())).inner;
+        watcher
+            .add_appointment(
+                triggered.clone(),
+                cryptography::sign(&triggered.to_vec(), &old_sk).unwrap(),
+            )
+            .unwrap();
+        let breach = Breach::new(dispute_tx, get_random_tx());
+        let status = ConfirmationStatus::InMempoolSince(chain.get_block_count());
+        watcher.responder.add_tracker(
+            UUID::new(triggered.locator, old_user_id),
+            breach.clone(),
+            old_user_id,
+            status,
+        );
+        let (old_info, _) = watcher.get_user_info(old_user_id).unwrap();
+
+        // Both signatures are required
+        let nonce = 42;
+        let message =
+            transfer_subscription_message(watcher.tower_id, old_user_id, new_user_id, nonce);
+        let old_sig = cryptography::sign(&message, &old_sk).unwrap();
+        let new_sig = cryptography::sign(&message, &new_sk).unwrap();
+        let wrong_sig = cryptography::sign(&message, &get_random_keypair().0).unwrap();
+        assert!(matches!(
+            watcher.transfer_subscription(old_user_id, new_user_id, nonce, &old_sig, &wrong_sig),
+            Err(TransferSubscriptionFailure::AuthenticationFailure)
+        ));
+        assert!(matches!(
+            watcher.transfer_subscription(old_user_id, new_user_id, nonce, &wrong_sig, &new_sig),
+            Err(TransferSubscriptionFailure::AuthenticationFailure)
+        ));
+        // The signatures must cover the given nonce
+        assert!(matches!(
+            watcher.transfer_subscription(old_user_id, new_user_id, nonce + 1, &old_sig, &new_sig),
+            Err(TransferSubscriptionFailure::AuthenticationFailure)
+        ));
+
+        let receipt = watcher
+            .transfer_subscription(old_user_id, new_user_id, nonce, &old_sig, &new_sig)
+            .unwrap();
+        assert_eq!(receipt.user_id(), new_user_id);
+        assert_eq!(receipt.available_slots(), old_info.available_slots);
+        assert_eq!(receipt.subscription_start(), old_info.subscription_start);
+        assert_eq!(receipt.subscription_expiry(), old_info.subscription_expiry);
+        assert!(cryptography::verify(
+            &receipt.to_vec(),
+            &receipt.signature().unwrap(),
+            &tower_pk
+        ));
+
+        // The old user is gone, and so is the data it could access
+        assert!(watcher.get_user_info(old_user_id).is_none());
+        let get_message = format!("get appointment {}", appointment.locator);
+        assert!(matches!(
+            watcher.get_appointment(
+                appointment.locator,
+                &cryptography::sign(get_message.as_bytes(), &old_sk).unwrap()
+            ),
+            Err(GetAppointmentFailure::AuthenticationFailure)
+        ));
+
+        // The appointments (and trackers) have moved to the new user
+        let (new_info, locators) = watcher.get_user_info(new_user_id).unwrap();
+        assert_eq!(new_info, old_info);
+        assert_eq!(
+            HashSet::<Locator>::from_iter(locators),
+            HashSet::from_iter([appointment.locator, triggered.locator])
+        );
+        match watcher
+            .get_appointment(
+                appointment.locator,
+                &cryptography::sign(get_message.as_bytes(), &new_sk).unwrap(),
+            )
+            .unwrap()
+        {
+            AppointmentInfo::Appointment(a) => assert_eq!(a, appointment),
+            AppointmentInfo::Tracker { .. } => {
+                panic!("Should have received an appointment, not a tracker")
+            }
+        }
+        let tracker_message = format!("get appointment {}", triggered.locator);
+        match watcher
+            .get_appointment(
+                triggered.locator,
+                &cryptography::sign(tracker_message.as_bytes(), &new_sk).unwrap(),
+            )
+            .unwrap()
+        {
+            AppointmentInfo::Appointment { .. } => {
+                panic!("Should have received a tracker, not an appointment")
+            }
+            AppointmentInfo::Tracker(t) => {
+                assert_eq!(t, TransactionTracker::new(breach, new_user_id, status))
+            }
+        }
+
+        // The transfer cannot be repeated
+        assert!(matches!(
+            watcher.transfer_subscription(old_user_id, new_user_id, nonce, &old_sig, &new_sig),
+            Err(TransferSubscriptionFailure::AuthenticationFailure)
+        ));
+
+        // Subscriptions cannot be transferred to registered users
+        let (another_sk, another_pk) = get_random_keypair();
+        watcher.register(UserId(another_pk)).unwrap();
+        let message =
+            transfer_subscription_message(watcher.tower_id, UserId(another_pk), new_user_id, nonce);
+        let new_sig = cryptography::sign(&message, &new_sk).unwrap();
+        assert!(matches!(
+            watcher.transfer_subscription(
+                UserId(another_pk),
+                new_user_id,
+                nonce,
+                &cryptography::sign(&message, &another_sk).unwrap(),
+                &new_sig
+            ),
+            Err(TransferSubscriptionFailure::AlreadyRegistered)
+        ));
+
+        // Nor can expired subscriptions
+        watcher
+            .gatekeeper
+            .add_outdated_user(UserId(another_pk), START_HEIGHT as u32);
+        let (yet_another_sk, yet_another_pk) = get_random_keypair();
+        let message = transfer_subscription_message(
+            watcher.tower_id,
+            UserId(another_pk),
+            UserId(yet_another_pk),
+            nonce,
+        );
+        assert!(matches!(
+            watcher.transfer_subscription(
+                UserId(another_pk),
+                UserId(yet_another_pk),
+                nonce,
+                &cryptography::sign(&message, &another_sk).unwrap(),
+                &cryptography::sign(&message, &yet_another_sk).unwrap(),
+            ),
+            Err(TransferSubscriptionFailure::SubscriptionExpired { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_transfer_subscription_other_tower() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+        let (other_watcher, _s2) = init_watcher(&mut chain).await;
+        assert_ne!(watcher.tower_id, other_watcher.tower_id);
+
+        // The user is registered with both towers
+        let (old_sk, old_pk) = get_random_keypair();
+        let old_user_id = UserId(old_pk);
+        let (new_sk, new_pk) = get_random_keypair();
+        let new_user_id = UserId(new_pk);
+        watcher.register(old_user_id).unwrap();
+        other_watcher.register(old_user_id).unwrap();
+
+        // A transfer signed for one of the towers is accepted by it
+        let nonce = 42;
+        let message =
+            transfer_subscription_message(watcher.tower_id, old_user_id, new_user_id, nonce);
+        let old_sig = cryptography::sign(&message, &old_sk).unwrap();
+        let new_sig = cryptography::sign(&message, &new_sk).unwrap();
+        watcher
+            .transfer_subscription(old_user_id, new_user_id, nonce, &old_sig, &new_sig)
+            .unwrap();
+
+        // But it cannot be replayed against the other one
+        assert!(matches!(
+            other_watcher.transfer_subscription(
+                old_user_id,
+                new_user_id,
+                nonce,
+                &old_sig,
+                &new_sig
+            ),
+            Err(TransferSubscriptionFailure::AuthenticationFailure)
+        ));
+        assert!(other_watcher.get_user_info(old_user_id).is_some());
+        assert!(other_watcher.get_user_info(new_user_id).is_none());
+    }
+
     #[tokio::test]
     async fn test_get_breaches() {
         let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
@@ -1103,6 +2299,49 @@ mod tests {
         assert!(watcher.handle_breaches(breaches).is_none())
     }
 
+    #[tokio::test]
+    async fn test_handle_breaches_priority() {
+        let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
+        let broadcasts = Arc::new(Mutex::new(Vec::new()));
+        let (watcher, _s) = init_watcher_with_options(
+            &mut chain,
+            Arc::new(Mutex::new(DBM::in_memory().unwrap())),
+            MockOptions::recording_broadcasts(broadcasts.clone()),
+        )
+        .await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+
+        // Two appointments triggered in the same block, the second one with a higher priority.
+        let mut breaches = HashMap::new();
+        let mut penalties = Vec::new();
+        for priority in [Some(1), Some(5)] {
+            let dispute_tx = get_random_tx();
+            let appointment = generate_dummy_appointment(Some(&dispute_tx.txid())).inner;
+            let penalty_tx =
+                cryptography::decrypt(&appointment.encrypted_blob, &dispute_tx.txid()).unwrap();
+            let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+            watcher
+                .add_appointment_with_terms(
+                    appointment,
+                    signature,
+                    AppointmentTerms {
+                        priority,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            breaches.insert(Locator::new(dispute_tx.txid()), dispute_tx);
+            penalties.push(consensus::encode::serialize_hex(&penalty_tx));
+        }
+
+        assert!(watcher.handle_breaches(breaches).is_none());
+        penalties.reverse();
+        assert_eq!(*broadcasts.lock().unwrap(), penalties);
+    }
+
     #[tokio::test]
     async fn test_handle_breaches_rejected_decryption() {
         let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
@@ -1137,6 +2376,16 @@ mod tests {
             rejected,
             HashSet::from_iter(watcher.handle_breaches(breaches).unwrap())
         );
+
+        // The malformed ones are recorded as unconstructable instead of bringing the tower down
+        let unconstructable: HashSet<_> = watcher
+            .responder
+            .get_penalties(None, None)
+            .into_iter()
+            .filter(|record| record.status == PenaltyStatus::Unconstructable)
+            .map(|record| record.uuid)
+            .collect();
+        assert_eq!(unconstructable, rejected);
     }
 
     #[tokio::test]
@@ -1214,6 +2463,166 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_trigger_penalty() {
+        let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+
+        // Appointments that cannot be found cannot be triggered
+        let dispute_tx = get_random_tx();
+        let locator = Locator::new(dispute_tx.txid());
+        assert!(matches!(
+            watcher.trigger_penalty(locator, user_id, dispute_tx.clone()),
+            Err(TriggerPenaltyFailure::NotFound)
+        ));
+
+        // Once added, the penalty is decrypted, broadcast and tracked by the Responder
+        let (uuid, appointment) =
+            generate_dummy_appointment_with_user(user_id, Some(&dispute_tx.txid()));
+        let appointment = appointment.inner;
+        let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        watcher.add_appointment(appointment, signature).unwrap();
+
+        let tracker = watcher
+            .trigger_penalty(locator, user_id, dispute_tx.clone())
+            .unwrap();
+        assert_eq!(tracker.dispute_tx, dispute_tx);
+        assert!(tracker.status.accepted());
+        assert!(watcher.responder.has_tracker(uuid));
+
+        // Triggering it again is not possible
+        assert!(matches!(
+            watcher.trigger_penalty(locator, user_id, dispute_tx),
+            Err(TriggerPenaltyFailure::AlreadyTriggered)
+        ));
+
+        // The penalty cannot be decrypted if the dispute transaction does not match the appointment
+        let dispute_tx = get_random_tx();
+        let locator = Locator::new(dispute_tx.txid());
+        let mut appointment = generate_dummy_appointment(Some(&dispute_tx.txid())).inner;
+        appointment.encrypted_blob.reverse();
+        let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        watcher.add_appointment(appointment, signature).unwrap();
+        assert!(matches!(
+            watcher.trigger_penalty(locator, user_id, dispute_tx),
+            Err(TriggerPenaltyFailure::DecryptionFailed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_trigger_penalty_rejected() {
+        let mut chain = Blockchain::default().with_height_and_txs(START_HEIGHT, 10);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+
+        // Replace the carrier with an erroneous one
+        let (carrier, _s) = create_carrier(
+            MockedServerQuery::Error(rpc_errors::RPC_VERIFY_ERROR as i64),
+            chain.tip().deref().height,
+        );
+        *watcher.responder.get_carrier().lock().unwrap() = carrier;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+
+        let dispute_tx = get_random_tx();
+        let (uuid, appointment) =
+            generate_dummy_appointment_with_user(user_id, Some(&dispute_tx.txid()));
+        let appointment = appointment.inner;
+        let signature = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        watcher.add_appointment(appointment, signature).unwrap();
+
+        // The rejection is reported, but the appointment is kept
+        assert!(matches!(
+            watcher.trigger_penalty(Locator::new(dispute_tx.txid()), user_id, dispute_tx),
+            Err(TriggerPenaltyFailure::Rejected(
+                rpc_errors::RPC_VERIFY_ERROR
+            ))
+        ));
+        assert!(!watcher.responder.has_tracker(uuid));
+        assert!(watcher.dbm.lock().unwrap().appointment_exists(uuid));
+    }
+
+    #[tokio::test]
+    async fn test_check_integrity() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+        let appointment = generate_dummy_appointment(None).inner;
+        let user_sig = cryptography::sign(&appointment.to_vec(), &user_sk).unwrap();
+        watcher.add_appointment(appointment, user_sig).unwrap();
+        watcher.add_random_tracker_to_responder();
+        assert!(watcher.check_integrity(false).is_empty());
+
+        // Make the tracker dangle by removing its appointment behind the tower's back
+        let uuid = *watcher.get_all_responder_trackers().keys().next().unwrap();
+        {
+            let dbm = watcher.dbm.lock().unwrap();
+            let connection = dbm.get_connection();
+            connection.execute("PRAGMA foreign_keys=0", []).unwrap();
+            connection
+                .execute("DELETE FROM appointments WHERE UUID=(?)", [uuid.to_vec()])
+                .unwrap();
+            connection.execute("PRAGMA foreign_keys=1", []).unwrap();
+        }
+        // And make the slots held in memory drift from the stored ones
+        let stored_slots = watcher
+            .dbm
+            .lock()
+            .unwrap()
+            .load_user(user_id)
+            .unwrap()
+            .available_slots;
+        watcher
+            .gatekeeper
+            .get_registered_users()
+            .lock()
+            .unwrap()
+            .get_mut(&user_id)
+            .unwrap()
+            .available_slots += 1;
+
+        let expected_issues = vec![
+            IntegrityIssue::DanglingRow {
+                table: "trackers",
+                uuid,
+            },
+            IntegrityIssue::SlotMismatch {
+                user_id,
+                available_slots: stored_slots + 1,
+                stored_slots: Some(stored_slots),
+            },
+        ];
+
+        // Checking does not modify anything
+        assert_eq!(watcher.check_integrity(false), expected_issues);
+        assert_eq!(watcher.check_integrity(false), expected_issues);
+        assert_eq!(watcher.dbm.lock().unwrap().get_trackers_count(), 1);
+
+        // Unless a repair is requested
+        assert_eq!(watcher.check_integrity(true), expected_issues);
+        assert!(watcher.check_integrity(false).is_empty());
+        assert_eq!(watcher.dbm.lock().unwrap().get_trackers_count(), 0);
+        assert_eq!(
+            watcher
+                .dbm
+                .lock()
+                .unwrap()
+                .load_user(user_id)
+                .unwrap()
+                .available_slots,
+            stored_slots + 1
+        );
+        assert_eq!(watcher.get_appointments_count(), 1);
+    }
+
     #[tokio::test]
     async fn test_filtered_block_connected() {
         let mut chain = Blockchain::default().with_height(START_HEIGHT);
@@ -1358,6 +2767,78 @@ mod tests {
         assert!(!watcher.dbm.lock().unwrap().appointment_exists(uuid));
     }
 
+    #[tokio::test]
+    async fn test_filtered_block_connected_prune_expired_appointments() {
+        let mut chain = Blockchain::default().with_height(START_HEIGHT);
+        let (watcher, _s) = init_watcher(&mut chain).await;
+
+        let (user_sk, user_pk) = get_random_keypair();
+        let user_id = UserId(user_pk);
+        watcher.register(user_id).unwrap();
+
+        // Add an appointment with a TTL, one without, and one with a TTL that is triggered right before elapsing.
+        let (ttl_uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+        let sig = cryptography::sign(&appointment.inner.to_vec(), &user_sk).unwrap();
+        watcher
+            .add_appointment_with_terms(
+                appointment.inner,
+                sig,
+                AppointmentTerms {
+                    ttl: Some(2),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let (no_ttl_uuid, appointment) = generate_dummy_appointment_with_user(user_id, None);
+        let sig = cryptography::sign(&appointment.inner.to_vec(), &user_sk).unwrap();
+        watcher.add_appointment(appointment.inner, sig).unwrap();
+
+        let dispute_tx = get_random_tx();
+        let (triggered_uuid, appointment) =
+            generate_dummy_appointment_with_user(user_id, Some(&dispute_tx.txid()));
+        let sig = cryptography::sign(&appointment.inner.to_vec(), &user_sk).unwrap();
+        let (_, available_slots, _) = watcher
+            .add_appointment_with_terms(
+                appointment.inner,
+                sig,
+                AppointmentTerms {
+                    ttl: Some(1),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(available_slots, SLOTS - 3);
+
+        // The triggered appointment is handed to the Responder before its TTL is checked, so it is not pruned.
+        watcher.block_connected(
+            &chain.generate(Some(vec![dispute_tx])),
+            chain.get_block_count(),
+        );
+        assert!(watcher.responder.has_tracker(triggered_uuid));
+        assert!(watcher.dbm.lock().unwrap().appointment_exists(ttl_uuid));
+
+        // Once the TTL elapses the appointment is pruned, and its slot is given back to the user.
+        watcher.block_connected(&chain.generate(None), chain.get_block_count());
+        assert!(!watcher.dbm.lock().unwrap().appointment_exists(ttl_uuid));
+        assert!(watcher.dbm.lock().unwrap().appointment_exists(no_ttl_uuid));
+        assert!(watcher.responder.has_tracker(triggered_uuid));
+        assert_eq!(
+            watcher.gatekeeper.get_registered_users().lock().unwrap()[&user_id].available_slots,
+            SLOTS - 2
+        );
+        assert_eq!(
+            watcher
+                .dbm
+                .lock()
+                .unwrap()
+                .load_user(user_id)
+                .unwrap()
+                .available_slots,
+            SLOTS - 2
+        );
+    }
+
     #[tokio::test]
     async fn test_block_disconnected() {
         let mut chain = Blockchain::default().with_height(START_HEIGHT);
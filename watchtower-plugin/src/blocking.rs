@@ -0,0 +1,401 @@
+//! A synchronous facade over the watchtower client, for callers that do not run an async runtime (e.g. CLI tools or
+//! scripts).
+//!
+//! [BlockingClient] owns a small internal runtime that drives both the requests to the towers and a [RetryManager] in
+//! the background, so unreachable towers are retried just like within the plugin.
+//!
+//! Only available with the `blocking` feature. Notice [BlockingClient] cannot be used from within an async runtime.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use tokio::runtime::{Builder, Runtime};
+use tokio::sync::mpsc::unbounded_channel;
+
+use teos_common::appointment::Appointment;
+use teos_common::cryptography;
+use teos_common::errors;
+use teos_common::net::NetAddr;
+use teos_common::receipts::{AppointmentReceipt, RegistrationReceipt};
+use teos_common::{TowerId, UserId};
+
+use crate::constants;
+use crate::net::http::{self, AddAppointmentError, RequestError};
+use crate::retrier::RetryManager;
+use crate::wt_client::{TowerError, WTClient};
+use crate::{TowerInfo, TowerStatus, TowerSummary};
+
+/// Errors returned by the [BlockingClient].
+#[derive(Debug)]
+pub enum Error {
+    /// The internal runtime could not be created.
+    Runtime(std::io::Error),
+    /// The request to the tower failed.
+    Request(RequestError),
+    /// The appointment could not be delivered to the tower.
+    AddAppointment(Box<AddAppointmentError>),
+    /// The registration receipt returned by the tower is not valid.
+    InvalidReceipt(String),
+    /// The tower is not known by the client.
+    UnknownTower(TowerId),
+    /// The tower cannot be sent appointments right now. The appointment is kept as pending, if possible.
+    NotReachable(TowerId, TowerStatus),
+    /// The tower cannot be retried.
+    Retry(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Runtime(e) => write!(f, "Cannot create runtime: {e}"),
+            Error::Request(e) => write!(f, "{e}"),
+            Error::AddAppointment(e) => write!(f, "{e}"),
+            Error::InvalidReceipt(x) => write!(f, "{x}"),
+            Error::UnknownTower(tower_id) => write!(f, "Unknown tower {tower_id}"),
+            Error::NotReachable(tower_id, status) => write!(f, "{tower_id} is {status}"),
+            Error::Retry(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Synchronous counterpart of the [WTClient].
+pub struct BlockingClient {
+    runtime: Runtime,
+    wt_client: Arc<Mutex<WTClient>>,
+}
+
+impl BlockingClient {
+    /// Creates a new [BlockingClient] using `data_dir` as data folder.
+    ///
+    /// Unreachable towers are retried using the default retry configuration of the plugin.
+    pub fn new(data_dir: PathBuf) -> Result<Self, Error> {
+        let runtime = Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()
+            .map_err(Error::Runtime)?;
+
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(runtime.block_on(WTClient::new(data_dir, tx))));
+
+        let mut retry_manager = RetryManager::new(
+            wt_client.clone(),
+            rx,
+            constants::DEFAULT_WT_MAX_RETRY_TIME as u16,
+            constants::DEFAULT_WT_AUTO_RETRY_DELAY as u32,
+            constants::DEFAULT_DEV_WT_MAX_RETRY_INTERVAL as u16,
+            constants::DEFAULT_WT_RETRY_POLLING_INTERVAL as u64,
+        );
+        runtime.spawn(async move { retry_manager.manage_retry().await });
+
+        Ok(Self { runtime, wt_client })
+    }
+
+    /// Gets the underlying [WTClient], for operations not covered by the facade.
+    pub fn wt_client(&self) -> Arc<Mutex<WTClient>> {
+        self.wt_client.clone()
+    }
+
+    /// Gets the user identifier.
+    pub fn user_id(&self) -> UserId {
+        self.wt_client.lock().unwrap().user_id
+    }
+
+    /// Registers the client with the tower identified by `tower_id`, reachable at `net_addr` (e.g. `http://host:port`).
+    pub fn register(
+        &self,
+        tower_id: TowerId,
+        net_addr: &str,
+    ) -> Result<RegistrationReceipt, Error> {
+        let tower_net_addr = NetAddr::new(net_addr.to_owned());
+        let (user_id, options) = {
+            let state = self.wt_client.lock().unwrap();
+            let use_proxy = tower_net_addr.is_onion()
+                || state.towers.get(&tower_id).is_some_and(|t| t.use_proxy);
+            (
+                state.user_id,
+                state.resolve_request_options(tower_id, state.resolve_proxy(use_proxy).is_some()),
+            )
+        };
+
+        let (receipt, signature_version) = self
+            .runtime
+            .block_on(http::register(
+                tower_id,
+                user_id,
+                &tower_net_addr,
+                None,
+                &options,
+            ))
+            .map_err(Error::Request)?;
+
+        if !receipt.verify(&tower_id) {
+            return Err(Error::InvalidReceipt(
+                "Registration receipt contains bad signature".to_owned(),
+            ));
+        }
+
+        let mut state = self.wt_client.lock().unwrap();
+        state
+            .add_update_tower(tower_id, tower_net_addr.net_addr(), &receipt)
+            .map_err(|_| Error::InvalidReceipt("Registration receipt is not valid".to_owned()))?;
+        state.set_tower_signature_version(tower_id, signature_version);
+        drop(state);
+
+        Ok(receipt)
+    }
+
+    /// Sends an appointment to a given tower.
+    ///
+    /// Failures are handled like in the plugin: appointments that cannot be delivered due to the tower being
+    /// unreachable (or a subscription issue) are kept as pending and retried, whereas rejected ones are flagged as invalid.
+    pub fn add_appointment(
+        &self,
+        tower_id: TowerId,
+        appointment: &Appointment,
+    ) -> Result<AppointmentReceipt, Error> {
+        let (net_addr, status, options, signature) = {
+            let state = self.wt_client.lock().unwrap();
+            let tower = state
+                .towers
+                .get(&tower_id)
+                .ok_or(Error::UnknownTower(tower_id))?;
+            (
+                tower.net_addr.clone(),
+                tower.status,
+                state.get_request_options(tower_id),
+                cryptography::sign(
+                    &appointment.to_signable_vec(tower.signature_version),
+                    &state.user_sk,
+                )
+                .unwrap(),
+            )
+        };
+
+        if !status.is_reachable() {
+            if !status.is_misbehaving() {
+                let mut state = self.wt_client.lock().unwrap();
+                state.add_pending_appointment(tower_id, appointment);
+                if !status.is_unreachable() {
+                    state.send_to_retrier(tower_id, appointment.locator);
+                }
+            }
+            return Err(Error::NotReachable(tower_id, status));
+        }
+
+        match self.runtime.block_on(http::add_appointment(
+            tower_id,
+            &net_addr,
+            &options,
+            appointment,
+            &signature,
+        )) {
+            Ok((slots, receipt)) => {
+                self.wt_client.lock().unwrap().add_appointment_receipt(
+                    tower_id,
+                    appointment.locator,
+                    slots,
+                    &receipt,
+                );
+                Ok(receipt)
+            }
+            Err(e) => {
+                let mut state = self.wt_client.lock().unwrap();
+                state.record_error(tower_id, TowerError::from(&e));
+                match &e {
+                    AddAppointmentError::RequestError(e) => {
+                        if e.is_connection() {
+                            state.set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
+                            state.add_pending_appointment(tower_id, appointment);
+                            state.send_to_retrier(tower_id, appointment.locator);
+                        } else if matches!(e, RequestError::PinMismatch(_)) {
+                            // Not retried automatically, the tower may be being impersonated
+                            state.set_tower_status(tower_id, TowerStatus::Unreachable);
+                            state.add_pending_appointment(tower_id, appointment);
+                        }
+                    }
+                    AddAppointmentError::ApiError(e) => {
+                        if e.error_code == errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR {
+                            state.set_tower_status(tower_id, TowerStatus::SubscriptionError);
+                            state.add_pending_appointment(tower_id, appointment);
+                            state.send_to_retrier(tower_id, appointment.locator);
+                        } else {
+                            state.add_rejected_appointment(tower_id, appointment, e);
+                        }
+                    }
+                    AddAppointmentError::SignatureError(proof) => {
+                        state.flag_misbehaving_tower(tower_id, proof.clone())
+                    }
+                }
+                Err(Error::AddAppointment(Box::new(e)))
+            }
+        }
+    }
+
+    /// Flags a tower for retry. See [WTClient::retry_tower].
+    pub fn retry_tower(&self, tower_id: TowerId) -> Result<(), Error> {
+        self.wt_client
+            .lock()
+            .unwrap()
+            .retry_tower(tower_id)
+            .map_err(Error::Retry)
+    }
+
+    /// Gets the status of a given tower, if known.
+    pub fn get_tower_status(&self, tower_id: TowerId) -> Option<TowerStatus> {
+        self.wt_client.lock().unwrap().get_tower_status(&tower_id)
+    }
+
+    /// Gets the last error faced when sending data to a given tower, if any.
+    pub fn get_last_error(&self, tower_id: TowerId) -> Option<TowerError> {
+        self.wt_client
+            .lock()
+            .unwrap()
+            .last_errors
+            .get(&tower_id)
+            .cloned()
+    }
+
+    /// Gets all the information about a given tower, if known.
+    pub fn get_tower_info(&self, tower_id: TowerId) -> Option<TowerInfo> {
+        let state = self.wt_client.lock().unwrap();
+        state.load_tower_info(tower_id).map(|info| {
+            info.with_retrier(state.get_retrier_status(&tower_id).map(Into::into))
+                .with_last_error(state.last_errors.get(&tower_id).cloned())
+        })
+    }
+
+    /// Gets a summary of all the towers known by the client.
+    pub fn list_towers(&self) -> HashMap<TowerId, TowerSummary> {
+        self.wt_client.lock().unwrap().towers.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    use teos_common::appointment::SignatureVersion;
+    use teos_common::net::http::Endpoint;
+    use teos_common::protos as common_msgs;
+    use teos_common::test_utils::{generate_random_appointment, get_random_user_id};
+
+    use crate::net::http::ErrorKind;
+    use crate::test_utils::get_dummy_add_appointment_response;
+
+    #[test]
+    fn test_blocking_client() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let client = BlockingClient::new(tmp_path.path().to_path_buf()).unwrap();
+        let mut server = mockito::Server::new();
+
+        // Register with a tower
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let mut registration_receipt = RegistrationReceipt::new(client.user_id(), 21, 42, 420);
+        registration_receipt.sign(&tower_sk);
+        let register_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(common_msgs::RegisterResponse {
+                    user_id: client.user_id().to_vec(),
+                    available_slots: registration_receipt.available_slots(),
+                    subscription_start: registration_receipt.subscription_start(),
+                    subscription_expiry: registration_receipt.subscription_expiry(),
+                    subscription_signature: registration_receipt.signature().unwrap(),
+                    signature_version: SignatureVersion::V2.into(),
+                })
+                .to_string(),
+            )
+            .create();
+        assert_eq!(
+            client.register(tower_id, &server.url()).unwrap(),
+            registration_receipt
+        );
+        register_mock.assert();
+        assert_eq!(
+            client.get_tower_status(tower_id),
+            Some(TowerStatus::Reachable)
+        );
+
+        // Send an appointment to it (signed following the version agreed on registration)
+        let appointment = generate_random_appointment(None);
+        let mut appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(
+                &appointment.to_signable_vec(SignatureVersion::V2),
+                &client.wt_client().lock().unwrap().user_sk,
+            )
+            .unwrap(),
+            42,
+        );
+        appointment_receipt.sign(&tower_sk);
+        let appointment_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(get_dummy_add_appointment_response(
+                    appointment.locator,
+                    &appointment_receipt
+                ))
+                .to_string(),
+            )
+            .create();
+        assert_eq!(
+            client.add_appointment(tower_id, &appointment).unwrap(),
+            appointment_receipt
+        );
+        appointment_mock.assert();
+
+        let info = client.get_tower_info(tower_id).unwrap();
+        assert!(info.appointments.contains_key(&appointment.locator));
+        assert!(info.retrier.is_none());
+        assert!(info.last_error.is_none());
+        assert!(client.list_towers().contains_key(&tower_id));
+
+        // Reachable towers cannot be retried
+        assert!(matches!(
+            client.retry_tower(tower_id),
+            Err(Error::Retry(..))
+        ));
+
+        // If the tower cannot be reached, the appointment is kept as pending and the tower is retried in the background
+        let unreachable_tower_id = get_random_user_id();
+        let mut receipt = RegistrationReceipt::new(client.user_id(), 21, 42, 420);
+        receipt.sign(&cryptography::get_random_keypair().0);
+        client
+            .wt_client()
+            .lock()
+            .unwrap()
+            .add_update_tower(unreachable_tower_id, "http://unreachable.tower", &receipt)
+            .unwrap();
+        let appointment = generate_random_appointment(None);
+        assert!(matches!(
+            client.add_appointment(unreachable_tower_id, &appointment),
+            Err(Error::AddAppointment(e)) if matches!(*e, AddAppointmentError::RequestError(..))
+        ));
+        let info = client.get_tower_info(unreachable_tower_id).unwrap();
+        assert!(info.pending_appointments.contains(&appointment));
+        // The error is kept so it can be queried later on
+        let last_error = client.get_last_error(unreachable_tower_id).unwrap();
+        assert_eq!(last_error.kind, ErrorKind::Connection);
+        assert_eq!(info.last_error, Some(last_error));
+
+        // Unknown towers are rejected
+        assert!(matches!(
+            client.add_appointment(get_random_user_id(), &appointment),
+            Err(Error::UnknownTower(..))
+        ));
+        assert!(matches!(
+            client.retry_tower(get_random_user_id()),
+            Err(Error::Retry(..))
+        ));
+    }
+}
@@ -10,9 +10,75 @@ pub const WT_PORT_DESC: &str = "tower API port";
 pub const WT_MAX_RETRY_TIME: &str = "watchtower-max-retry-time";
 pub const DEFAULT_WT_MAX_RETRY_TIME: i64 = 3600;
 pub const WT_MAX_RETRY_TIME_DESC: &str = "for how long (in seconds) a retry strategy will try to reach a temporary unreachable tower before giving up. Defaults to 1 hour";
+pub const WT_MAX_RETRIES: &str = "watchtower-max-retries";
+pub const DEFAULT_WT_MAX_RETRIES: i64 = 0;
+pub const WT_MAX_RETRIES_DESC: &str = "how many attempts a retry strategy will make to reach a temporary unreachable tower before giving up, regardless of watchtower-max-retry-time. Defaults to 0 (no limit)";
+pub const WT_AGGRESSIVE_MAX_RETRY_TIME: &str = "watchtower-aggressive-max-retry-time";
+pub const DEFAULT_WT_AGGRESSIVE_MAX_RETRY_TIME: i64 = 300;
+pub const WT_AGGRESSIVE_MAX_RETRY_TIME_DESC: &str = "for how long (in seconds) an aggressive manual retry (retrytower with aggressive set) will try to reach a tower before giving up. Defaults to 5 min";
+pub const WT_AGGRESSIVE_MAX_RETRY_INTERVAL: &str = "watchtower-aggressive-max-retry-interval";
+pub const DEFAULT_WT_AGGRESSIVE_MAX_RETRY_INTERVAL: i64 = 5;
+pub const WT_AGGRESSIVE_MAX_RETRY_INTERVAL_DESC: &str = "maximum length (in seconds) for a retry interval of an aggressive manual retry. Defaults to 5 seconds";
 pub const WT_AUTO_RETRY_DELAY: &str = "watchtower-auto-retry-delay";
 pub const DEFAULT_WT_AUTO_RETRY_DELAY: i64 = 28800;
 pub const WT_AUTO_RETRY_DELAY_DESC: &str = "how long (in seconds) a retrier will wait before auto-retrying a failed tower. Defaults to once every 8 hours";
+pub const WT_RETRY_POLLING_INTERVAL: &str = "watchtower-retry-polling-interval";
+pub const DEFAULT_WT_RETRY_POLLING_INTERVAL: i64 = 1000;
+pub const WT_RETRY_POLLING_INTERVAL_DESC: &str = "how often (in milliseconds) the retry manager checks for new data to retry. Cannot be lower than 100. Defaults to 1 second";
+pub const WT_USER_AGENT: &str = "watchtower-user-agent";
+pub const DEFAULT_WT_USER_AGENT: &str = concat!("rusty-teos-plugin/", env!("CARGO_PKG_VERSION"));
+pub const WT_USER_AGENT_DESC: &str = "the User-Agent sent along with the requests to the towers. Defaults to rusty-teos-plugin/<version>";
+pub const WT_EXTRA_HEADERS: &str = "watchtower-extra-headers";
+pub const DEFAULT_WT_EXTRA_HEADERS: &str = "";
+pub const WT_EXTRA_HEADERS_DESC: &str = "additional headers to send to specific towers, as a JSON object: {\"<tower_id>\": {\"<name>\": \"<value>\"}}. Headers such as Host, Content-Type or Authorization cannot be set";
+pub const WT_LIST_MAINTAINER: &str = "watchtower-list-maintainer";
+pub const DEFAULT_WT_LIST_MAINTAINER: &str = "";
+pub const WT_LIST_MAINTAINER_DESC: &str = "public key of the maintainer of the tower lists accepted by importlist. Importing lists is disabled if not set";
+pub const WT_DB_BUSY_TIMEOUT: &str = "watchtower-db-busy-timeout";
+pub const DEFAULT_WT_DB_BUSY_TIMEOUT: i64 = 5000;
+pub const WT_DB_BUSY_TIMEOUT_DESC: &str = "for how long (in milliseconds) database queries wait for the database to be unlocked before failing as busy. Defaults to 5 seconds";
+pub const WT_PRUNE_AGE: &str = "watchtower-prune-age";
+pub const DEFAULT_WT_PRUNE_AGE: i64 = 604800;
+pub const WT_PRUNE_AGE_DESC: &str = "for how long (in seconds) a tower needs to have been unreachable to be removed by prunefailed. Defaults to 1 week";
+pub const WT_STALE_FEED: &str = "watchtower-stale-feed";
+pub const DEFAULT_WT_STALE_FEED: &str = "auto";
+pub const WT_STALE_FEED_DESC: &str = "how the appointments left pending from previous runs are fed to the retriers on startup: eager (all at once), lazy (in chunks, loaded from the database) or auto (lazy only for big backlogs). Defaults to auto";
+pub const WT_INVALID_RETRY_DELAY: &str = "watchtower-invalid-retry-delay";
+pub const DEFAULT_WT_INVALID_RETRY_DELAY: i64 = 0;
+pub const WT_INVALID_RETRY_DELAY_DESC: &str = "for how long (in seconds) an appointment rejected by a tower is kept as invalid before being retried. Defaults to 0 (rejected appointments are not retried)";
+pub const WT_INVALID_MAX_RETRIES: &str = "watchtower-invalid-max-retries";
+pub const DEFAULT_WT_INVALID_MAX_RETRIES: i64 = 3;
+pub const WT_INVALID_MAX_RETRIES_DESC: &str = "how many times an appointment rejected by a tower is retried before being considered permanently invalid. Only used if watchtower-invalid-retry-delay is set. Defaults to 3";
+pub const WT_MAX_APPOINTMENT_AGE: &str = "watchtower-max-appointment-age";
+pub const DEFAULT_WT_MAX_APPOINTMENT_AGE: i64 = 0;
+pub const WT_MAX_APPOINTMENT_AGE_DESC: &str = "for how long (in seconds) an appointment is kept as pending. Older ones are flagged as expired instead of being reloaded when an idle tower is retried. Defaults to 0 (no limit)";
+pub const WT_AUTO_RENEW_BLOCKS: &str = "watchtower-auto-renew-blocks";
+pub const DEFAULT_WT_AUTO_RENEW_BLOCKS: i64 = 144;
+pub const WT_AUTO_RENEW_BLOCKS_DESC: &str = "how many blocks ahead of their expiry the subscriptions flagged with setautorenew are renewed. Defaults to 144 (roughly a day). Zero disables auto-renewals";
+pub const WT_SUBMISSION_POLICY: &str = "watchtower-submission-policy";
+pub const DEFAULT_WT_SUBMISSION_POLICY: &str = "async";
+pub const WT_SUBMISSION_POLICY_DESC: &str = "how appointments are submitted to the towers on every commitment revocation: async (in the background) or sync-at-least-one (the revocation is held until a tower confirms the appointment, for up to watchtower-submission-timeout). Defaults to async";
+pub const WT_SUBMISSION_TIMEOUT: &str = "watchtower-submission-timeout";
+pub const DEFAULT_WT_SUBMISSION_TIMEOUT: i64 = 10;
+pub const WT_SUBMISSION_TIMEOUT_DESC: &str = "for how long (in seconds) a revocation is held waiting for a tower to confirm the appointment when using the sync-at-least-one submission policy. Appointments are carried on in the background afterwards. Defaults to 10";
+pub const WT_MAX_FANOUT: &str = "watchtower-max-fanout";
+pub const DEFAULT_WT_MAX_FANOUT: i64 = 8;
+pub const WT_MAX_FANOUT_DESC: &str = "maximum number of towers a fresh appointment is sent to concurrently. Further deliveries wait for an ongoing one to be over. Defaults to 8";
+pub const WT_DEAD_LETTER_LIMIT: &str = "watchtower-dead-letter-limit";
+pub const DEFAULT_WT_DEAD_LETTER_LIMIT: i64 = 1000;
+pub const WT_DEAD_LETTER_LIMIT_DESC: &str = "how many appointments permanently rejected by the towers are kept, alongside the rejection, in the dead-letter queue (see deadletters). The oldest are dropped once over the limit. Defaults to 1000. Zero disables it";
+pub const WT_STATUS_EVENTS: &str = "watchtower-status-events";
+pub const DEFAULT_WT_STATUS_EVENTS: &str = "all";
+pub const WT_STATUS_EVENTS_DESC: &str = "which tower status changes emit a tower_status_changed notification, given the status the tower changes to: all, or a comma separated list of statuses (e.g. unreachable,misbehaving,subscription_error). Defaults to all";
+pub const WT_WEBHOOK_URL: &str = "watchtower-webhook-url";
+pub const DEFAULT_WT_WEBHOOK_URL: &str = "";
+pub const WT_WEBHOOK_URL_DESC: &str = "URL appointment deliveries, expiries and tower status changes are POSTed to, as JSON. Disabled if not set";
+pub const WT_WEBHOOK_SECRET: &str = "watchtower-webhook-secret";
+pub const DEFAULT_WT_WEBHOOK_SECRET: &str = "";
+pub const WT_WEBHOOK_SECRET_DESC: &str = "secret the webhook requests are signed with (HMAC-SHA256 of the body, sent in the X-Watchtower-Signature header). Requests are not signed if not set";
+pub const WT_COMPACT_APPOINTMENTS: &str = "watchtower-compact-appointments";
+pub const DEFAULT_WT_COMPACT_APPOINTMENTS: bool = false;
+pub const WT_COMPACT_APPOINTMENTS_DESC: &str = "whether appointments are sent to the towers using a compact binary (protobuf) encoding instead of JSON, saving bandwidth. Only for towers that support it. Defaults to false";
 pub const DEV_WT_MAX_RETRY_INTERVAL: &str = "dev-watchtower-max-retry-interval";
 pub const DEFAULT_DEV_WT_MAX_RETRY_INTERVAL: i64 = 900;
 pub const DEV_WT_MAX_RETRY_INTERVAL_DESC: &str =
@@ -32,21 +98,108 @@ pub const RPC_GET_APPOINTMENT_DESC: &str =
 pub const RPC_GET_APPOINTMENT_RECEIPT: &str = "getappointmentreceipt";
 pub const RPC_GET_APPOINTMENT_RECEIPT_DESC: &str =
     "Gets a (local) appointment receipt given a tower id and a locator";
+pub const RPC_DELETE_APPOINTMENT: &str = "deleteappointment";
+pub const RPC_DELETE_APPOINTMENT_DESC: &str =
+    "Asks a tower to delete an appointment given a tower id and a locator, and stores the deletion receipt";
 pub const RPC_GET_SUBSCRIPTION_INFO: &str = "getsubscriptioninfo";
 pub const RPC_GET_SUBSCRIPTION_INFO_DESC: &str =
     "Gets the subscription information directly from the tower";
 pub const RPC_LIST_TOWERS: &str = "listtowers";
-pub const RPC_LIST_TOWERS_DESC: &str = "Lists all registered towers";
+pub const RPC_LIST_TOWERS_DESC: &str =
+    "Lists all registered towers, or only the ones tagged with a given label. With --verbose, reports the status of every tower alongside the one of its retrier and its number of pending appointments";
 pub const RPC_GET_TOWER_INFO: &str = "gettowerinfo";
 pub const RPC_GET_TOWER_INFO_DESC: &str = "Shows the info about a tower given a tower id";
+pub const RPC_DIAGNOSE: &str = "diagnose";
+pub const RPC_DIAGNOSE_DESC: &str = "Explains why a tower is at its current status";
 pub const RPC_RETRY_TOWER: &str = "retrytower";
 pub const RPC_RETRY_TOWER_DESC: &str =
     "Retries to send pending appointment to an unreachable tower";
+pub const RPC_RETRY_ALL: &str = "retryall";
+pub const RPC_RETRY_ALL_DESC: &str =
+    "Retries to send pending appointments to all unreachable towers, or only the ones tagged with a given label";
+pub const RPC_RESYNC_TOWER: &str = "resynctower";
+pub const RPC_RESYNC_TOWER_DESC: &str =
+    "Syncs the local data of a tower with the data the tower holds, re-sending what the tower is missing";
+pub const RPC_BACKFILL_TOWER: &str = "backfill";
+pub const RPC_BACKFILL_TOWER_DESC: &str =
+    "Sends the appointments still being delivered to the rest of towers to a given tower, so it covers the existing channels";
 pub const RPC_ABANDON_TOWER: &str = "abandontower";
 pub const RPC_ABANDON_TOWER_DESC: &str = "Forgets about a tower and wipes all local data";
+pub const RPC_PRUNE_FAILED: &str = "prunefailed";
+pub const RPC_PRUNE_FAILED_DESC: &str =
+    "Abandons all towers that failed to be retried or have been unreachable for longer than watchtower-prune-age";
+pub const RPC_SET_CHANNEL_TOWERS: &str = "setchanneltowers";
+pub const RPC_SET_CHANNEL_TOWERS_DESC: &str =
+    "Restricts the towers the appointments of a given channel are sent to. Lifts the restriction if no tower is given";
+pub const RPC_CHANNEL_COVERAGE: &str = "channelcoverage";
+pub const RPC_CHANNEL_COVERAGE_DESC: &str =
+    "Shows the appointments of the channel funded by a given outpoint, alongside their status for every tower";
+pub const RPC_SET_TOWER_LABELS: &str = "settowerlabels";
+pub const RPC_SET_TOWER_LABELS_DESC: &str =
+    "Sets the labels of a given tower, replacing any previous ones. Removes all labels if none is given";
+pub const RPC_SET_TOWER_PIN: &str = "settowerpin";
+pub const RPC_SET_TOWER_PIN_DESC: &str =
+    "Pins the TLS certificate of a given tower (by its SHA-256 fingerprint). Removes the pin if none is given";
+pub const RPC_SET_TOWER_PROXY: &str = "settowerproxy";
+pub const RPC_SET_TOWER_PROXY_DESC: &str =
+    "Sets whether a given tower is always reached through the proxy, regardless of always-use-proxy";
+pub const RPC_SET_AUTO_RENEW: &str = "setautorenew";
+pub const RPC_SET_AUTO_RENEW_DESC: &str =
+    "Sets whether the subscription with a given tower is automatically renewed when it is about to expire";
+pub const RPC_SET_MIRROR: &str = "setmirror";
+pub const RPC_SET_MIRROR_DESC: &str =
+    "Sets a tower every pending appointment is also sent to, so it acts as a backup. Removes it if none is given";
+pub const RPC_SET_TOWER_ORDER: &str = "settowerorder";
+pub const RPC_SET_TOWER_ORDER_DESC: &str =
+    "Sets the towers appointments are delivered to one at a time, falling back to the next only once the previous one fails for good. Removes the order if none is given";
+pub const RPC_GET_METRICS: &str = "getmetrics";
+pub const RPC_GET_METRICS_DESC: &str =
+    "Shows how many appointments have been delivered to the towers since the plugin was started";
+pub const RPC_GET_HEALTH: &str = "gethealth";
+pub const RPC_GET_HEALTH_DESC: &str =
+    "Shows when the retry manager last ran, so external monitoring can check whether it has stalled, and the towers that need attention. Subscriptions about to expire are checked against the given block height, or the last one seen if none is given";
+pub const RPC_VERIFY_RECEIPTS: &str = "verifyreceipts";
+pub const RPC_VERIFY_RECEIPTS_DESC: &str =
+    "Checks that all the stored receipts are signed by the towers they belong to, and reports the ones that are not";
+pub const RPC_IMPORT_LIST: &str = "importlist";
+pub const RPC_IMPORT_LIST_DESC: &str =
+    "Registers with all the towers in a tower list file, given it is signed by the configured maintainer";
+pub const RPC_EXPORT_STATE: &str = "exportstate";
+pub const RPC_EXPORT_STATE_DESC: &str =
+    "Exports the towers known by the client alongside their pending and invalid appointments, so the state can be compared against the one of a different plugin instance";
+pub const RPC_DIFF_STATE: &str = "diffstate";
+pub const RPC_DIFF_STATE_DESC: &str =
+    "Compares the client state against the one exported (via exportstate) by a different plugin instance, reporting the towers and appointments they disagree on";
+pub const RPC_EXPORT_CSV: &str = "exportcsv";
+pub const RPC_EXPORT_CSV_DESC: &str =
+    "Exports every appointment delivered to the towers, alongside the slots left after the delivery, the delivery time and the tower signature, to a CSV file";
+pub const RPC_CAPABILITIES: &str = "capabilities";
+pub const RPC_CAPABILITIES_DESC: &str =
+    "Reports the version of the plugin and the features it supports, so front-ends can adapt to it";
+pub const RPC_DEAD_LETTERS: &str = "deadletters";
+pub const RPC_DEAD_LETTERS_DESC: &str =
+    "Lists the appointments permanently rejected by a given tower, alongside the error returned by the tower and when it happened";
+pub const RPC_SELF_TEST: &str = "selftest";
+pub const RPC_SELF_TEST_DESC: &str =
+    "Registers with a test tower, sends it a synthetic appointment and checks it is being watched, reporting pass/fail for each step. Refuses to run on mainnet";
 pub const RPC_PING: &str = "pingtower";
 pub const RPC_PING_DESC: &str = "Polls the tower to check if it is online";
+pub const RPC_TOWER_TERMS: &str = "towerterms";
+pub const RPC_TOWER_TERMS_DESC: &str =
+    "Shows the terms (price per slot, payment methods and subscription duration) advertised by a tower at a given address";
+pub const RPC_COVERAGE_ESTIMATE: &str = "coverageestimate";
+pub const RPC_COVERAGE_ESTIMATE_DESC: &str =
+    "Estimates the slots and subscription renewals covering a new channel with the current towers would require, and what they would cost, given the number of commitment updates and (optionally) the lifetime in blocks of the channel";
 
 /// Collections of hook names
 
 pub const HOOK_COMMITMENT_REVOCATION: &str = "commitment_revocation";
+
+// Collections of notification topics
+
+pub const NOTIFICATION_TOWER_STATUS: &str = "tower_status_changed";
+
+// Collections of subscription topics
+
+pub const SUBSCRIPTION_SHUTDOWN: &str = "shutdown";
+pub const SUBSCRIPTION_BLOCK_ADDED: &str = "block_added";
@@ -1,14 +1,100 @@
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
-use std::{convert::TryFrom, str::FromStr};
+use std::str::FromStr;
 
 use hex::FromHex;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use bitcoin::{Transaction, Txid};
+use bitcoin::{OutPoint, Transaction, Txid};
 
 use teos_common::appointment::Locator;
-use teos_common::TowerId;
+use teos_common::{TowerId, USER_ID_LEN};
+
+use crate::net::TlsPin;
+
+/// Parses a tower id provided by the user, which must be a hex encoded compressed public key.
+pub fn parse_tower_id(tower_id: &str) -> Result<TowerId, String> {
+    Vec::from_hex(tower_id)
+        .ok()
+        .filter(|bytes| bytes.len() == USER_ID_LEN)
+        .and_then(|bytes| TowerId::from_slice(&bytes).ok())
+        .ok_or_else(|| {
+            format!(
+                "Invalid tower id: {tower_id}. Expected a 33-byte hex encoded compressed public key"
+            )
+        })
+}
+
+/// Parses the params of the commands that only take a tower id.
+///
+/// Malformed ids are reported as such (see [parse_tower_id]), any other issue with the params is reported by
+/// [TowerId::try_from].
+pub fn tower_id_from_params(value: serde_json::Value) -> Result<TowerId, String> {
+    let tower_id = match &value {
+        serde_json::Value::String(s) => Some(s.as_str()),
+        serde_json::Value::Array(a) if a.len() == 1 => a[0].as_str(),
+        serde_json::Value::Object(m) if m.len() == 1 => m.get("tower_id").and_then(|v| v.as_str()),
+        _ => None,
+    };
+
+    match tower_id {
+        Some(tower_id) => parse_tower_id(tower_id),
+        None => TowerId::try_from(value),
+    }
+}
+
+/// Parses the params of the commands that only take the address of a tower (`host[:port]`).
+///
+/// Returns the host (including the scheme, if given) and the port, if given.
+pub fn net_addr_from_params(value: serde_json::Value) -> Result<(String, Option<u16>), String> {
+    let addr = match &value {
+        serde_json::Value::String(s) => Some(s.as_str()),
+        serde_json::Value::Array(a) if a.len() == 1 => a[0].as_str(),
+        serde_json::Value::Object(m) if m.len() == 1 => m.get("addr").and_then(|v| v.as_str()),
+        _ => None,
+    }
+    .map(str::trim)
+    .filter(|addr| !addr.is_empty() && !addr.contains(' '))
+    .ok_or_else(|| {
+        format!("Unexpected request format. Expected: 'host[:port]'. Received: '{value}'")
+    })?;
+
+    // The scheme separator is not a port separator
+    match addr.rsplit_once(':') {
+        Some((host, port)) if !port.starts_with("//") => port
+            .parse()
+            .map(|port| (host.to_owned(), Some(port)))
+            .map_err(|_| format!("Invalid port: {port}")),
+        _ => Ok((addr.to_owned(), None)),
+    }
+}
+
+/// Parses the params of the `gethealth` command, which optionally takes the current block height.
+pub fn block_height_from_params(value: serde_json::Value) -> Result<Option<u32>, String> {
+    let block_height = match &value {
+        serde_json::Value::Null => return Ok(None),
+        serde_json::Value::Array(a) if a.len() <= 1 => a.first(),
+        serde_json::Value::Object(m) if m.keys().all(|k| k == "block_height") => {
+            m.get("block_height")
+        }
+        _ => {
+            return Err(format!(
+                "Unexpected request format. Expected: [block_height]. Received: '{value}'"
+            ))
+        }
+    };
+
+    block_height
+        .filter(|h| !h.is_null())
+        .map(|h| {
+            h.as_u64()
+                .and_then(|h| u32::try_from(h).ok())
+                .ok_or_else(|| format!("Invalid block height: {h}"))
+        })
+        .transpose()
+}
 
 /// Errors related to the `registertower` command.
 #[derive(Debug)]
@@ -36,6 +122,9 @@ pub struct RegisterParams {
     pub tower_id: TowerId,
     pub host: Option<String>,
     pub port: Option<u16>,
+    /// Preimage of a paid subscription invoice, for towers that require payment to register.
+    #[serde(skip)]
+    pub payment_preimage: Option<Vec<u8>>,
 }
 
 impl RegisterParams {
@@ -55,10 +144,10 @@ impl RegisterParams {
 
     fn from_id(tower_id: &str) -> Result<Self, RegisterError> {
         Ok(Self {
-            tower_id: TowerId::from_str(tower_id)
-                .map_err(|_| RegisterError::InvalidId("Invalid tower id".to_owned()))?,
+            tower_id: parse_tower_id(tower_id).map_err(RegisterError::InvalidId)?,
             host: None,
             port: None,
+            payment_preimage: None,
         })
     }
 
@@ -140,6 +229,16 @@ impl TryFrom<serde_json::Value> for RegisterParams {
                 }
             },
             serde_json::Value::Object(mut m) => {
+                // The payment preimage can only be passed as a named parameter
+                let payment_preimage = match m.remove("payment_preimage") {
+                    Some(p) => Some(
+                        p.as_str()
+                            .and_then(|p| hex::decode(p).ok())
+                            .filter(|p| p.len() == 32)
+                            .ok_or_else(|| RegisterError::InvalidFormat("payment_preimage must be a 32-byte hex encoded string".to_owned()))?,
+                    ),
+                    None => None,
+                };
                 let allowed_keys = ["tower_id", "host", "port"];
                 let param_count = m.len();
 
@@ -157,7 +256,10 @@ impl TryFrom<serde_json::Value> for RegisterParams {
                         }
                     }
 
-                    RegisterParams::try_from(json!(params))
+                    RegisterParams::try_from(json!(params)).map(|params| RegisterParams {
+                        payment_preimage,
+                        ..params
+                    })
                 }
             },
             _ => Err(RegisterError::InvalidFormat(
@@ -207,9 +309,7 @@ impl TryFrom<serde_json::Value> for GetAppointmentParams {
                     )))
                 } else {
                     let tower_id = if let Some(s) = a.get(0).unwrap().as_str() {
-                        TowerId::from_str(s).map_err(|_| {
-                            GetAppointmentError::InvalidId("Invalid tower id".to_owned())
-                        })
+                        parse_tower_id(s).map_err(GetAppointmentError::InvalidId)
                     } else {
                         Err(GetAppointmentError::InvalidId(
                             "tower_id must be a hex encoded string".to_owned(),
@@ -262,176 +362,1094 @@ impl TryFrom<serde_json::Value> for GetAppointmentParams {
     }
 }
 
-/// Data associated with a commitment revocation. Represents the data sent by CoreLN through the `commitment_revocation` hook.
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CommitmentRevocation {
+/// Errors related to the `setchanneltowers` command.
+#[derive(Debug)]
+pub enum ChannelTowersError {
+    InvalidChannelId(String),
+    InvalidId(String),
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for ChannelTowersError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelTowersError::InvalidChannelId(x) => write!(f, "{x}"),
+            ChannelTowersError::InvalidId(x) => write!(f, "{x}"),
+            ChannelTowersError::InvalidFormat(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Parameters related to the `setchanneltowers` command.
+#[derive(Debug)]
+pub struct ChannelTowersParams {
     pub channel_id: String,
-    #[serde(rename(deserialize = "commitnum"))]
-    pub commit_num: u32,
-    pub commitment_txid: Txid,
-    #[serde(deserialize_with = "crate::ser::deserialize_tx")]
-    pub penalty_tx: Transaction,
+    /// The towers the appointments of the channel are sent to. [None] means all towers.
+    pub tower_ids: Option<HashSet<TowerId>>,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
-    use std::collections::HashMap;
+impl ChannelTowersParams {
+    fn parse_tower_id(value: &serde_json::Value) -> Result<TowerId, ChannelTowersError> {
+        value
+            .as_str()
+            .ok_or_else(|| {
+                ChannelTowersError::InvalidId("tower_id must be a hex encoded string".to_owned())
+            })
+            .and_then(|s| parse_tower_id(s).map_err(ChannelTowersError::InvalidId))
+    }
+}
 
-    const VALID_ID: &str = "020dea894c967319407265764aba31bdef75d463f96800f34dd6df61380d82dfc0";
+impl TryFrom<serde_json::Value> for ChannelTowersParams {
+    type Error = ChannelTowersError;
 
-    mod register_command {
-        use super::*;
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Array(a) => {
+                let param_count = a.len();
+                if !(1..=2).contains(&param_count) {
+                    return Err(ChannelTowersError::InvalidFormat(format!(
+                        "Unexpected request format. The request needs 1-2 parameters. Received: {param_count}"
+                    )));
+                }
 
-        #[test]
-        fn test_from_id() {
-            // The tower id should be a valid id, otherwise the params construction will fail
-            let params = RegisterParams::from_id(VALID_ID).unwrap();
-            assert!(params.host.is_none());
-            assert!(params.port.is_none());
+                let channel_id = match a[0].as_str() {
+                    Some(s) if s.len() == 64 && hex::decode(s).is_ok() => Ok(s.to_owned()),
+                    _ => Err(ChannelTowersError::InvalidChannelId(
+                        "channel_id must be a 32-byte hex encoded string".to_owned(),
+                    )),
+                }?;
+
+                let tower_ids = match a.get(1) {
+                    None | Some(serde_json::Value::Null) => None,
+                    Some(serde_json::Value::Array(ids)) if ids.is_empty() => None,
+                    Some(serde_json::Value::Array(ids)) => Some(
+                        ids.iter()
+                            .map(ChannelTowersParams::parse_tower_id)
+                            .collect::<Result<HashSet<_>, _>>()?,
+                    ),
+                    Some(id) => Some(HashSet::from([ChannelTowersParams::parse_tower_id(id)?])),
+                };
+
+                Ok(Self {
+                    channel_id,
+                    tower_ids,
+                })
+            }
+            serde_json::Value::Object(mut m) => {
+                let allowed_keys = ["channel_id", "tower_ids"];
 
-            // Any incorrectly formatted id will make it fail
-            assert!(matches!(
-                RegisterParams::from_id(""),
-                Err(RegisterError::InvalidId(..))
-            ));
+                if m.keys().any(|k| !allowed_keys.contains(&k.as_str())) {
+                    return Err(ChannelTowersError::InvalidFormat(
+                        "Invalid named argument found in request".to_owned(),
+                    ));
+                }
+
+                let channel_id = m.remove("channel_id").ok_or_else(|| {
+                    ChannelTowersError::InvalidFormat("channel_id is mandatory".to_owned())
+                })?;
+                let mut params = vec![channel_id];
+                if let Some(tower_ids) = m.remove("tower_ids") {
+                    params.push(tower_ids);
+                }
+                ChannelTowersParams::try_from(json!(params))
+            }
+            _ => Err(ChannelTowersError::InvalidFormat(format!(
+                "Unexpected request format. Expected: channel_id [tower_ids]. Received: '{value}'"
+            ))),
         }
+    }
+}
 
-        #[test]
-        fn test_with_host() {
-            // Any properly formatted host should work
-            let params = RegisterParams::from_id(VALID_ID).unwrap();
-            let host = "myhost";
-            assert_eq!(params.with_host(host).unwrap().host, Some(host.to_owned()));
+/// Errors related to the `settowerorder` command.
+#[derive(Debug)]
+pub enum TowerOrderError {
+    InvalidId(String),
+    InvalidFormat(String),
+}
 
-            // Host must not be empty not have spaces
-            assert!(matches!(
-                RegisterParams::from_id(VALID_ID).unwrap().with_host(""),
-                Err(RegisterError::InvalidHost(..))
-            ));
-            assert!(matches!(
-                RegisterParams::from_id(VALID_ID)
-                    .unwrap()
-                    .with_host("myhost "),
-                Err(RegisterError::InvalidHost(..))
-            ));
+impl std::fmt::Display for TowerOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TowerOrderError::InvalidId(x) => write!(f, "{x}"),
+            TowerOrderError::InvalidFormat(x) => write!(f, "{x}"),
         }
+    }
+}
 
-        #[test]
-        fn test_with_port() {
-            let mut params = RegisterParams::from_id(VALID_ID).unwrap();
+/// Parameters related to the `settowerorder` command.
+#[derive(Debug)]
+pub struct TowerOrderParams {
+    /// The towers appointments are delivered to, most preferred first. Empty means no order.
+    pub tower_ids: Vec<TowerId>,
+}
 
-            // Any 16-bytes value will do for the port
-            let port = 6677;
-            params = params.with_port(port).unwrap();
-            assert_eq!(params.port, Some(port as u16));
+impl TryFrom<serde_json::Value> for TowerOrderParams {
+    type Error = TowerOrderError;
 
-            // Going over u16::MAX will make this fail
-            let port = u16::MAX as u64 + 1;
-            assert!(matches!(
-                params.with_port(port),
-                Err(RegisterError::InvalidPort(..))
-            ));
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        let ids = match value {
+            serde_json::Value::Null => Vec::new(),
+            // Towers can be passed either as separate params or as a single list
+            serde_json::Value::Array(mut a) if a.len() == 1 && a[0].is_array() => {
+                a.remove(0).as_array().unwrap().clone()
+            }
+            serde_json::Value::Array(a) => a,
+            serde_json::Value::Object(m) if m.is_empty() => Vec::new(),
+            serde_json::Value::Object(mut m) if m.len() == 1 && m.contains_key("tower_ids") => {
+                return TowerOrderParams::try_from(json!([m.remove("tower_ids").unwrap()]));
+            }
+            _ => {
+                return Err(TowerOrderError::InvalidFormat(format!(
+                    "Unexpected request format. Expected: [tower_ids]. Received: '{value}'"
+                )))
+            }
+        };
+
+        let mut tower_ids = Vec::new();
+        for id in ids.iter() {
+            let tower_id = id
+                .as_str()
+                .ok_or_else(|| {
+                    TowerOrderError::InvalidId("tower_id must be a hex encoded string".to_owned())
+                })
+                .and_then(|s| parse_tower_id(s).map_err(TowerOrderError::InvalidId))?;
+            if tower_ids.contains(&tower_id) {
+                return Err(TowerOrderError::InvalidFormat(format!(
+                    "{tower_id} can only be ordered once"
+                )));
+            }
+            tower_ids.push(tower_id);
         }
 
-        #[test]
-        fn test_try_from_json_string() {
-            let ok = [
-                format!("{VALID_ID}@host:80"),
-                format!("{VALID_ID}@host"),
-                VALID_ID.to_string(),
-            ];
-            let wrong_id = ["", "id@host:80", "@host:80", "@:80"];
-            let wrong_host = [
-                format!("{VALID_ID}@"),
-                format!("{VALID_ID}@ "),
-                format!("{VALID_ID}@ host"),
-                format!("{VALID_ID}@:80"),
-            ];
-            let wrong_port = [format!("{VALID_ID}@host:"), format!("{VALID_ID}@host:port")];
+        Ok(Self { tower_ids })
+    }
+}
 
-            for s in ok {
-                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
-                let p = RegisterParams::try_from(v);
-                assert!(matches!(p, Ok(..)));
-            }
+/// Errors related to the `channelcoverage` command.
+#[derive(Debug)]
+pub enum ChannelCoverageError {
+    InvalidOutpoint(String),
+    InvalidFormat(String),
+}
 
-            for s in wrong_id {
-                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
-                let p = RegisterParams::try_from(v);
-                assert!(matches!(p, Err(RegisterError::InvalidId(..))));
-            }
+impl std::fmt::Display for ChannelCoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelCoverageError::InvalidOutpoint(x) => write!(f, "{x}"),
+            ChannelCoverageError::InvalidFormat(x) => write!(f, "{x}"),
+        }
+    }
+}
 
-            for s in wrong_host {
-                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
-                let p = RegisterParams::try_from(v);
-                assert!(matches!(p, Err(RegisterError::InvalidHost(..))));
-            }
+/// Parameters related to the `channelcoverage` command.
+#[derive(Debug)]
+pub struct ChannelCoverageParams {
+    /// The funding outpoint of the channel.
+    pub outpoint: OutPoint,
+}
 
-            for s in wrong_port {
-                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
-                let p = RegisterParams::try_from(v);
-                assert!(matches!(p, Err(RegisterError::InvalidPort(..))));
+impl TryFrom<serde_json::Value> for ChannelCoverageParams {
+    type Error = ChannelCoverageError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        let outpoint = match value {
+            serde_json::Value::Array(mut a) if a.len() == 1 => a.remove(0),
+            serde_json::Value::Object(mut m) if m.len() == 1 && m.contains_key("outpoint") => {
+                m.remove("outpoint").unwrap()
+            }
+            _ => {
+                return Err(ChannelCoverageError::InvalidFormat(format!(
+                    "Unexpected request format. Expected: outpoint. Received: '{value}'"
+                )))
             }
+        };
+
+        match outpoint.as_str().map(OutPoint::from_str) {
+            Some(Ok(outpoint)) => Ok(Self { outpoint }),
+            _ => Err(ChannelCoverageError::InvalidOutpoint(format!(
+                "outpoint must be formatted as txid:vout. Received: {outpoint}"
+            ))),
         }
+    }
+}
 
-        #[test]
-        fn test_try_from_json_array() {
-            let id = json!(VALID_ID);
-            let number_id = json!(0);
+/// Errors related to the `coverageestimate` command.
+#[derive(Debug)]
+pub enum CoverageEstimateError {
+    InvalidValue(String),
+    InvalidFormat(String),
+}
 
-            let host = json!("host");
-            let number_host = json!(1);
+impl std::fmt::Display for CoverageEstimateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoverageEstimateError::InvalidValue(x) => write!(f, "{x}"),
+            CoverageEstimateError::InvalidFormat(x) => write!(f, "{x}"),
+        }
+    }
+}
 
-            let port = json!(80);
-            let string_port = json!("80");
+/// Parameters related to the `coverageestimate` command.
+#[derive(Debug)]
+pub struct CoverageEstimateParams {
+    /// The number of commitment updates the channel is expected to go through.
+    pub updates: u32,
+    /// For how long (in blocks) the channel is expected to be open, if known.
+    pub lifetime: Option<u32>,
+}
 
-            for v in [vec![&id, &host, &port], vec![&id, &host], vec![&id]] {
-                let p = RegisterParams::try_from(json!(v));
-                assert!(matches!(p, Ok(..)));
+impl TryFrom<serde_json::Value> for CoverageEstimateParams {
+    type Error = CoverageEstimateError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        let parse = |name: &str, value: &serde_json::Value| {
+            value
+                .as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| {
+                    CoverageEstimateError::InvalidValue(format!(
+                        "{name} must be a positive integer. Received: {value}"
+                    ))
+                })
+        };
+
+        match value {
+            serde_json::Value::Array(a) => {
+                let param_count = a.len();
+                if !(1..=2).contains(&param_count) {
+                    return Err(CoverageEstimateError::InvalidFormat(format!(
+                        "Unexpected request format. The request needs 1-2 parameters. Received: {param_count}"
+                    )));
+                }
+
+                Ok(Self {
+                    updates: parse("updates", &a[0])?,
+                    lifetime: match a.get(1) {
+                        None | Some(serde_json::Value::Null) => None,
+                        Some(lifetime) => Some(parse("lifetime", lifetime)?),
+                    },
+                })
             }
+            serde_json::Value::Object(mut m) => {
+                let allowed_keys = ["updates", "lifetime"];
 
-            // Wrong id
-            let p = RegisterParams::try_from(json!(vec![&number_id, &host, &port]));
-            assert!(matches!(p, Err(RegisterError::InvalidId(..))));
+                if m.keys().any(|k| !allowed_keys.contains(&k.as_str())) {
+                    return Err(CoverageEstimateError::InvalidFormat(
+                        "Invalid named argument found in request".to_owned(),
+                    ));
+                }
 
-            // Wrong host
-            let p = RegisterParams::try_from(json!(vec![&id, &number_host, &port]));
-            assert!(matches!(p, Err(RegisterError::InvalidHost(..))));
+                let updates = m.remove("updates").ok_or_else(|| {
+                    CoverageEstimateError::InvalidFormat("updates is mandatory".to_owned())
+                })?;
+                let mut params = vec![updates];
+                if let Some(lifetime) = m.remove("lifetime") {
+                    params.push(lifetime);
+                }
+                CoverageEstimateParams::try_from(json!(params))
+            }
+            _ => Err(CoverageEstimateError::InvalidFormat(format!(
+                "Unexpected request format. Expected: updates [lifetime]. Received: '{value}'"
+            ))),
+        }
+    }
+}
 
-            // Wrong port
-            let p = RegisterParams::try_from(json!(vec![&id, &host, &string_port]));
-            assert!(matches!(p, Err(RegisterError::InvalidPort(..))));
+/// Errors related to the `settowerlabels` command.
+#[derive(Debug)]
+pub enum TowerLabelsError {
+    InvalidId(String),
+    InvalidLabel(String),
+    InvalidFormat(String),
+}
 
-            // Wrong param count (params should be 1-3)
-            let p = RegisterParams::try_from(json!(vec![&id, &host, &port, &id]));
-            assert!(matches!(p, Err(RegisterError::InvalidFormat(..))));
+impl std::fmt::Display for TowerLabelsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TowerLabelsError::InvalidId(x) => write!(f, "{x}"),
+            TowerLabelsError::InvalidLabel(x) => write!(f, "{x}"),
+            TowerLabelsError::InvalidFormat(x) => write!(f, "{x}"),
         }
+    }
+}
 
-        #[test]
-        fn test_try_from_json_dict() {
-            let id = json!(VALID_ID);
-            let host = json!("host");
-            let port = json!(80);
+/// Parses a tower label. Labels must be non-empty and cannot contain whitespaces.
+fn parse_label(value: &serde_json::Value) -> Result<String, TowerLabelsError> {
+    match value.as_str() {
+        Some(s) if !s.is_empty() && !s.contains(char::is_whitespace) => Ok(s.to_owned()),
+        _ => Err(TowerLabelsError::InvalidLabel(format!(
+            "Labels must be non-empty strings with no whitespaces. Received: {value}"
+        ))),
+    }
+}
 
-            for v in [
-                HashMap::from([("tower_id", &id), ("host", &host), ("port", &port)]),
-                HashMap::from([("tower_id", &id), ("host", &host)]),
-                HashMap::from([("tower_id", &id)]),
-            ] {
-                let p = RegisterParams::try_from(json!(v));
-                assert!(matches!(p, Ok(..)));
-            }
+/// Parameters related to the `settowerlabels` command.
+#[derive(Debug)]
+pub struct TowerLabelsParams {
+    pub tower_id: TowerId,
+    /// The labels of the tower. An empty set removes all labels.
+    pub labels: BTreeSet<String>,
+}
 
-            // Id key missing
-            let p =
-                RegisterParams::try_from(json!(HashMap::from([("host", &host), ("port", &port)])));
-            assert!(matches!(p, Err(RegisterError::InvalidId(..))));
+impl TryFrom<serde_json::Value> for TowerLabelsParams {
+    type Error = TowerLabelsError;
 
-            // Wrong id key
-            let p = RegisterParams::try_from(json!(HashMap::from([
-                ("wrong_tower_id", &id),
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Array(a) => {
+                let param_count = a.len();
+                if !(1..=2).contains(&param_count) {
+                    return Err(TowerLabelsError::InvalidFormat(format!(
+                        "Unexpected request format. The request needs 1-2 parameters. Received: {param_count}"
+                    )));
+                }
+
+                let tower_id = a[0]
+                    .as_str()
+                    .ok_or_else(|| {
+                        TowerLabelsError::InvalidId(format!("Invalid tower id: {}", a[0]))
+                    })
+                    .and_then(|s| parse_tower_id(s).map_err(TowerLabelsError::InvalidId))?;
+
+                let labels = match a.get(1) {
+                    None | Some(serde_json::Value::Null) => BTreeSet::new(),
+                    Some(serde_json::Value::Array(labels)) => {
+                        labels.iter().map(parse_label).collect::<Result<_, _>>()?
+                    }
+                    Some(label) => BTreeSet::from([parse_label(label)?]),
+                };
+
+                Ok(Self { tower_id, labels })
+            }
+            serde_json::Value::Object(mut m) => {
+                let allowed_keys = ["tower_id", "labels"];
+
+                if m.keys().any(|k| !allowed_keys.contains(&k.as_str())) {
+                    return Err(TowerLabelsError::InvalidFormat(
+                        "Invalid named argument found in request".to_owned(),
+                    ));
+                }
+
+                let tower_id = m.remove("tower_id").ok_or_else(|| {
+                    TowerLabelsError::InvalidFormat("tower_id is mandatory".to_owned())
+                })?;
+                let mut params = vec![tower_id];
+                if let Some(labels) = m.remove("labels") {
+                    params.push(labels);
+                }
+                TowerLabelsParams::try_from(json!(params))
+            }
+            _ => Err(TowerLabelsError::InvalidFormat(format!(
+                "Unexpected request format. Expected: tower_id [labels]. Received: '{value}'"
+            ))),
+        }
+    }
+}
+
+/// Errors related to the `settowerpin` command.
+#[derive(Debug)]
+pub enum TowerPinError {
+    InvalidId(String),
+    InvalidPin(String),
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for TowerPinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TowerPinError::InvalidId(x) => write!(f, "{x}"),
+            TowerPinError::InvalidPin(x) => write!(f, "{x}"),
+            TowerPinError::InvalidFormat(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Parameters related to the `settowerpin` command.
+#[derive(Debug)]
+pub struct TowerPinParams {
+    pub tower_id: TowerId,
+    /// The fingerprint of the certificate the tower is pinned to. [None] removes the pin.
+    pub tls_pin: Option<TlsPin>,
+}
+
+impl TryFrom<serde_json::Value> for TowerPinParams {
+    type Error = TowerPinError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Array(a) => {
+                let param_count = a.len();
+                if !(1..=2).contains(&param_count) {
+                    return Err(TowerPinError::InvalidFormat(format!(
+                        "Unexpected request format. The request needs 1-2 parameters. Received: {param_count}"
+                    )));
+                }
+
+                let tower_id = a[0]
+                    .as_str()
+                    .ok_or_else(|| TowerPinError::InvalidId(format!("Invalid tower id: {}", a[0])))
+                    .and_then(|s| parse_tower_id(s).map_err(TowerPinError::InvalidId))?;
+
+                let tls_pin = match a.get(1) {
+                    None | Some(serde_json::Value::Null) => None,
+                    Some(pin) => Some(
+                        pin.as_str()
+                            .ok_or_else(|| {
+                                TowerPinError::InvalidPin(format!("Invalid TLS pin: {pin}"))
+                            })
+                            .and_then(|s| TlsPin::from_str(s).map_err(TowerPinError::InvalidPin))?,
+                    ),
+                };
+
+                Ok(Self { tower_id, tls_pin })
+            }
+            serde_json::Value::Object(mut m) => {
+                let allowed_keys = ["tower_id", "tls_pin"];
+
+                if m.keys().any(|k| !allowed_keys.contains(&k.as_str())) {
+                    return Err(TowerPinError::InvalidFormat(
+                        "Invalid named argument found in request".to_owned(),
+                    ));
+                }
+
+                let tower_id = m.remove("tower_id").ok_or_else(|| {
+                    TowerPinError::InvalidFormat("tower_id is mandatory".to_owned())
+                })?;
+                let mut params = vec![tower_id];
+                if let Some(tls_pin) = m.remove("tls_pin") {
+                    params.push(tls_pin);
+                }
+                TowerPinParams::try_from(json!(params))
+            }
+            _ => Err(TowerPinError::InvalidFormat(format!(
+                "Unexpected request format. Expected: tower_id [tls_pin]. Received: '{value}'"
+            ))),
+        }
+    }
+}
+
+/// Errors related to the `setautorenew` command.
+#[derive(Debug)]
+pub enum AutoRenewError {
+    InvalidId(String),
+    InvalidFlag(String),
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for AutoRenewError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AutoRenewError::InvalidId(x) => write!(f, "{x}"),
+            AutoRenewError::InvalidFlag(x) => write!(f, "{x}"),
+            AutoRenewError::InvalidFormat(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Parameters related to the `setautorenew` command.
+#[derive(Debug)]
+pub struct AutoRenewParams {
+    pub tower_id: TowerId,
+    /// Whether the subscription is automatically renewed. Defaults to true.
+    pub enabled: bool,
+}
+
+impl TryFrom<serde_json::Value> for AutoRenewParams {
+    type Error = AutoRenewError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Array(a) => {
+                let param_count = a.len();
+                if !(1..=2).contains(&param_count) {
+                    return Err(AutoRenewError::InvalidFormat(format!(
+                        "Unexpected request format. The request needs 1-2 parameters. Received: {param_count}"
+                    )));
+                }
+
+                let tower_id = a[0]
+                    .as_str()
+                    .ok_or_else(|| AutoRenewError::InvalidId(format!("Invalid tower id: {}", a[0])))
+                    .and_then(|s| parse_tower_id(s).map_err(AutoRenewError::InvalidId))?;
+
+                let enabled = match a.get(1) {
+                    None => true,
+                    Some(flag) => flag.as_bool().ok_or_else(|| {
+                        AutoRenewError::InvalidFlag(format!(
+                            "enabled must be a boolean. Received: {flag}"
+                        ))
+                    })?,
+                };
+
+                Ok(Self { tower_id, enabled })
+            }
+            serde_json::Value::Object(mut m) => {
+                let allowed_keys = ["tower_id", "enabled"];
+
+                if m.keys().any(|k| !allowed_keys.contains(&k.as_str())) {
+                    return Err(AutoRenewError::InvalidFormat(
+                        "Invalid named argument found in request".to_owned(),
+                    ));
+                }
+
+                let tower_id = m.remove("tower_id").ok_or_else(|| {
+                    AutoRenewError::InvalidFormat("tower_id is mandatory".to_owned())
+                })?;
+                let mut params = vec![tower_id];
+                if let Some(enabled) = m.remove("enabled") {
+                    params.push(enabled);
+                }
+                AutoRenewParams::try_from(json!(params))
+            }
+            _ => Err(AutoRenewError::InvalidFormat(format!(
+                "Unexpected request format. Expected: tower_id [enabled]. Received: '{value}'"
+            ))),
+        }
+    }
+}
+
+/// Errors related to the `settowerproxy` command.
+#[derive(Debug)]
+pub enum TowerProxyError {
+    InvalidId(String),
+    InvalidFlag(String),
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for TowerProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TowerProxyError::InvalidId(x) => write!(f, "{x}"),
+            TowerProxyError::InvalidFlag(x) => write!(f, "{x}"),
+            TowerProxyError::InvalidFormat(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Parameters related to the `settowerproxy` command.
+#[derive(Debug)]
+pub struct TowerProxyParams {
+    pub tower_id: TowerId,
+    /// Whether the tower is always reached through the proxy. Defaults to true.
+    pub use_proxy: bool,
+}
+
+impl TryFrom<serde_json::Value> for TowerProxyParams {
+    type Error = TowerProxyError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::Array(a) => {
+                let param_count = a.len();
+                if !(1..=2).contains(&param_count) {
+                    return Err(TowerProxyError::InvalidFormat(format!(
+                        "Unexpected request format. The request needs 1-2 parameters. Received: {param_count}"
+                    )));
+                }
+
+                let tower_id = a[0]
+                    .as_str()
+                    .ok_or_else(|| {
+                        TowerProxyError::InvalidId(format!("Invalid tower id: {}", a[0]))
+                    })
+                    .and_then(|s| parse_tower_id(s).map_err(TowerProxyError::InvalidId))?;
+
+                let use_proxy = match a.get(1) {
+                    None => true,
+                    Some(flag) => flag.as_bool().ok_or_else(|| {
+                        TowerProxyError::InvalidFlag(format!(
+                            "use_proxy must be a boolean. Received: {flag}"
+                        ))
+                    })?,
+                };
+
+                Ok(Self {
+                    tower_id,
+                    use_proxy,
+                })
+            }
+            serde_json::Value::Object(mut m) => {
+                let allowed_keys = ["tower_id", "use_proxy"];
+
+                if m.keys().any(|k| !allowed_keys.contains(&k.as_str())) {
+                    return Err(TowerProxyError::InvalidFormat(
+                        "Invalid named argument found in request".to_owned(),
+                    ));
+                }
+
+                let tower_id = m.remove("tower_id").ok_or_else(|| {
+                    TowerProxyError::InvalidFormat("tower_id is mandatory".to_owned())
+                })?;
+                let mut params = vec![tower_id];
+                if let Some(use_proxy) = m.remove("use_proxy") {
+                    params.push(use_proxy);
+                }
+                TowerProxyParams::try_from(json!(params))
+            }
+            _ => Err(TowerProxyError::InvalidFormat(format!(
+                "Unexpected request format. Expected: tower_id [use_proxy]. Received: '{value}'"
+            ))),
+        }
+    }
+}
+
+/// Errors related to the `retrytower` command.
+#[derive(Debug)]
+pub enum RetryTowerError {
+    InvalidId(String),
+    InvalidFlag(String),
+    InvalidFormat(String),
+}
+
+impl std::fmt::Display for RetryTowerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RetryTowerError::InvalidId(x) => write!(f, "{x}"),
+            RetryTowerError::InvalidFlag(x) => write!(f, "{x}"),
+            RetryTowerError::InvalidFormat(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Parameters related to the `retrytower` command.
+#[derive(Debug)]
+pub struct RetryTowerParams {
+    pub tower_id: TowerId,
+    /// Whether the tower is retried using the aggressive backoff. Defaults to false.
+    pub aggressive: bool,
+}
+
+impl TryFrom<serde_json::Value> for RetryTowerParams {
+    type Error = RetryTowerError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::String(_) => Ok(Self {
+                tower_id: tower_id_from_params(value).map_err(RetryTowerError::InvalidId)?,
+                aggressive: false,
+            }),
+            serde_json::Value::Array(a) => {
+                let param_count = a.len();
+                if !(1..=2).contains(&param_count) {
+                    return Err(RetryTowerError::InvalidFormat(format!(
+                        "Unexpected request format. The request needs 1-2 parameters. Received: {param_count}"
+                    )));
+                }
+
+                let tower_id = a[0]
+                    .as_str()
+                    .ok_or_else(|| {
+                        RetryTowerError::InvalidId(format!("Invalid tower id: {}", a[0]))
+                    })
+                    .and_then(|s| parse_tower_id(s).map_err(RetryTowerError::InvalidId))?;
+
+                // The flag can also be given as `--aggressive`, so it reads like a command line switch
+                let aggressive = match a.get(1) {
+                    None | Some(serde_json::Value::Null) => false,
+                    Some(serde_json::Value::String(s)) if s == "--aggressive" => true,
+                    Some(flag) => flag.as_bool().ok_or_else(|| {
+                        RetryTowerError::InvalidFlag(format!(
+                            "aggressive must be a boolean. Received: {flag}"
+                        ))
+                    })?,
+                };
+
+                Ok(Self {
+                    tower_id,
+                    aggressive,
+                })
+            }
+            serde_json::Value::Object(mut m) => {
+                let allowed_keys = ["tower_id", "aggressive"];
+
+                if m.keys().any(|k| !allowed_keys.contains(&k.as_str())) {
+                    return Err(RetryTowerError::InvalidFormat(
+                        "Invalid named argument found in request".to_owned(),
+                    ));
+                }
+
+                let tower_id = m.remove("tower_id").ok_or_else(|| {
+                    RetryTowerError::InvalidFormat("tower_id is mandatory".to_owned())
+                })?;
+                let mut params = vec![tower_id];
+                if let Some(aggressive) = m.remove("aggressive") {
+                    params.push(aggressive);
+                }
+                RetryTowerParams::try_from(json!(params))
+            }
+            _ => Err(RetryTowerError::InvalidFormat(format!(
+                "Unexpected request format. Expected: tower_id [aggressive]. Received: '{value}'"
+            ))),
+        }
+    }
+}
+
+/// Parameters of the commands that can be filtered by tower label (e.g. `listtowers` or `retryall`).
+#[derive(Debug)]
+pub struct LabelFilterParams {
+    /// Only towers tagged with this label are considered. [None] means all towers.
+    pub label: Option<String>,
+}
+
+impl TryFrom<serde_json::Value> for LabelFilterParams {
+    type Error = TowerLabelsError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        let label = match value {
+            serde_json::Value::Null => None,
+            serde_json::Value::Array(a) if a.len() <= 1 => a.first().cloned(),
+            serde_json::Value::Object(mut m) if m.keys().all(|k| k == "label") => m.remove("label"),
+            _ => {
+                return Err(TowerLabelsError::InvalidFormat(format!(
+                    "Unexpected request format. Expected: [label]. Received: '{value}'"
+                )))
+            }
+        };
+
+        Ok(Self {
+            label: label
+                .filter(|l| !l.is_null())
+                .map(|l| parse_label(&l))
+                .transpose()?,
+        })
+    }
+}
+
+/// Parameters related to the `listtowers` command.
+#[derive(Debug)]
+pub struct ListTowersParams {
+    /// Only towers tagged with this label are listed. [None] means all towers.
+    pub label: Option<String>,
+    /// Whether the status of every tower is reported alongside the one of its retrier and its number of pending
+    /// appointments, instead of its summary. Defaults to false.
+    pub verbose: bool,
+}
+
+impl TryFrom<serde_json::Value> for ListTowersParams {
+    type Error = TowerLabelsError;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        let (verbose, filter) = match value {
+            // The flag can be given as `--verbose`, so it reads like a command line switch
+            serde_json::Value::Array(a) => {
+                let (flags, rest): (Vec<_>, Vec<_>) =
+                    a.into_iter().partition(|p| p.as_str() == Some("--verbose"));
+                (!flags.is_empty(), serde_json::Value::Array(rest))
+            }
+            serde_json::Value::Object(mut m) => {
+                let verbose = match m.remove("verbose") {
+                    None | Some(serde_json::Value::Null) => false,
+                    Some(flag) => flag.as_bool().ok_or_else(|| {
+                        TowerLabelsError::InvalidFormat(format!(
+                            "verbose must be a boolean. Received: {flag}"
+                        ))
+                    })?,
+                };
+                (verbose, serde_json::Value::Object(m))
+            }
+            _ => (false, value),
+        };
+
+        Ok(Self {
+            label: LabelFilterParams::try_from(filter)?.label,
+            verbose,
+        })
+    }
+}
+
+/// Data associated with a commitment revocation. Represents the data sent by CoreLN through the `commitment_revocation` hook.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitmentRevocation {
+    pub channel_id: String,
+    #[serde(rename(deserialize = "commitnum"))]
+    pub commit_num: u32,
+    pub commitment_txid: Txid,
+    #[serde(deserialize_with = "crate::ser::deserialize_tx")]
+    pub penalty_tx: Transaction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    const VALID_ID: &str = "020dea894c967319407265764aba31bdef75d463f96800f34dd6df61380d82dfc0";
+
+    mod tower_id {
+        use super::*;
+
+        use bitcoin::secp256k1::PublicKey;
+
+        #[test]
+        fn test_parse_tower_id() {
+            assert_eq!(
+                parse_tower_id(VALID_ID),
+                Ok(TowerId::from_str(VALID_ID).unwrap())
+            );
+
+            // Malformed ids are rejected with a friendly error, including uncompressed public keys
+            let uncompressed = hex::encode(
+                PublicKey::from_str(VALID_ID)
+                    .unwrap()
+                    .serialize_uncompressed(),
+            );
+            for tower_id in [
+                "",
+                "not_a_tower_id",
+                &VALID_ID[..64],
+                &format!("04{}", &VALID_ID[2..]),
+                &uncompressed,
+            ] {
+                assert_eq!(
+                    parse_tower_id(tower_id),
+                    Err(format!("Invalid tower id: {tower_id}. Expected a 33-byte hex encoded compressed public key"))
+                );
+            }
+        }
+
+        #[test]
+        fn test_tower_id_from_params() {
+            let tower_id = TowerId::from_str(VALID_ID).unwrap();
+            for params in [
+                json!(VALID_ID),
+                json!([VALID_ID]),
+                json!({ "tower_id": VALID_ID }),
+            ] {
+                assert_eq!(tower_id_from_params(params), Ok(tower_id));
+            }
+
+            // Malformed ids are reported as such
+            for params in [
+                json!("not_a_tower_id"),
+                json!(["not_a_tower_id"]),
+                json!({ "tower_id": "not_a_tower_id" }),
+            ] {
+                assert!(tower_id_from_params(params)
+                    .unwrap_err()
+                    .starts_with("Invalid tower id: not_a_tower_id"));
+            }
+
+            // Wrongly formatted params are reported as before
+            assert!(tower_id_from_params(json!([VALID_ID, VALID_ID]))
+                .unwrap_err()
+                .contains("Expected a single parameter"));
+        }
+    }
+
+    #[test]
+    fn test_block_height_from_params() {
+        for (params, expected) in [
+            (serde_json::Value::Null, None),
+            (json!([]), None),
+            (json!({}), None),
+            (json!([800000]), Some(800000)),
+            (json!({ "block_height": 800000 }), Some(800000)),
+        ] {
+            assert_eq!(block_height_from_params(params), Ok(expected));
+        }
+
+        for params in [json!(["800000"]), json!([-1]), json!([u64::MAX])] {
+            assert!(block_height_from_params(params)
+                .unwrap_err()
+                .starts_with("Invalid block height"));
+        }
+        for params in [json!([1, 2]), json!({ "height": 800000 }), json!(800000)] {
+            assert!(block_height_from_params(params)
+                .unwrap_err()
+                .starts_with("Unexpected request format"));
+        }
+    }
+
+    #[test]
+    fn test_net_addr_from_params() {
+        for (params, expected) in [
+            (json!("talaia.watch"), ("talaia.watch", None)),
+            (json!("talaia.watch:9814"), ("talaia.watch", Some(9814))),
+            (
+                json!(["http://talaia.watch"]),
+                ("http://talaia.watch", None),
+            ),
+            (
+                json!({ "addr": "http://talaia.watch:9814" }),
+                ("http://talaia.watch", Some(9814)),
+            ),
+        ] {
+            assert_eq!(
+                net_addr_from_params(params),
+                Ok((expected.0.to_owned(), expected.1))
+            );
+        }
+
+        assert_eq!(
+            net_addr_from_params(json!("talaia.watch:port")),
+            Err("Invalid port: port".to_owned())
+        );
+        for params in [
+            json!(""),
+            json!("talaia watch"),
+            json!(["talaia.watch", 9814]),
+            json!({ "host": "talaia.watch" }),
+            json!(9814),
+        ] {
+            assert!(net_addr_from_params(params)
+                .unwrap_err()
+                .starts_with("Unexpected request format"));
+        }
+    }
+
+    mod register_command {
+        use super::*;
+
+        #[test]
+        fn test_from_id() {
+            // The tower id should be a valid id, otherwise the params construction will fail
+            let params = RegisterParams::from_id(VALID_ID).unwrap();
+            assert!(params.host.is_none());
+            assert!(params.port.is_none());
+
+            // Any incorrectly formatted id will make it fail
+            assert!(matches!(
+                RegisterParams::from_id(""),
+                Err(RegisterError::InvalidId(..))
+            ));
+        }
+
+        #[test]
+        fn test_with_host() {
+            // Any properly formatted host should work
+            let params = RegisterParams::from_id(VALID_ID).unwrap();
+            let host = "myhost";
+            assert_eq!(params.with_host(host).unwrap().host, Some(host.to_owned()));
+
+            // Host must not be empty not have spaces
+            assert!(matches!(
+                RegisterParams::from_id(VALID_ID).unwrap().with_host(""),
+                Err(RegisterError::InvalidHost(..))
+            ));
+            assert!(matches!(
+                RegisterParams::from_id(VALID_ID)
+                    .unwrap()
+                    .with_host("myhost "),
+                Err(RegisterError::InvalidHost(..))
+            ));
+        }
+
+        #[test]
+        fn test_with_port() {
+            let mut params = RegisterParams::from_id(VALID_ID).unwrap();
+
+            // Any 16-bytes value will do for the port
+            let port = 6677;
+            params = params.with_port(port).unwrap();
+            assert_eq!(params.port, Some(port as u16));
+
+            // Going over u16::MAX will make this fail
+            let port = u16::MAX as u64 + 1;
+            assert!(matches!(
+                params.with_port(port),
+                Err(RegisterError::InvalidPort(..))
+            ));
+        }
+
+        #[test]
+        fn test_try_from_json_string() {
+            let ok = [
+                format!("{VALID_ID}@host:80"),
+                format!("{VALID_ID}@host"),
+                VALID_ID.to_string(),
+            ];
+            let wrong_id = ["", "id@host:80", "@host:80", "@:80"];
+            let wrong_host = [
+                format!("{VALID_ID}@"),
+                format!("{VALID_ID}@ "),
+                format!("{VALID_ID}@ host"),
+                format!("{VALID_ID}@:80"),
+            ];
+            let wrong_port = [format!("{VALID_ID}@host:"), format!("{VALID_ID}@host:port")];
+
+            for s in ok {
+                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
+                let p = RegisterParams::try_from(v);
+                assert!(matches!(p, Ok(..)));
+            }
+
+            for s in wrong_id {
+                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
+                let p = RegisterParams::try_from(v);
+                assert!(matches!(p, Err(RegisterError::InvalidId(..))));
+            }
+
+            for s in wrong_host {
+                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
+                let p = RegisterParams::try_from(v);
+                assert!(matches!(p, Err(RegisterError::InvalidHost(..))));
+            }
+
+            for s in wrong_port {
+                let v = serde_json::Value::Array(vec![serde_json::Value::String(s.to_string())]);
+                let p = RegisterParams::try_from(v);
+                assert!(matches!(p, Err(RegisterError::InvalidPort(..))));
+            }
+        }
+
+        #[test]
+        fn test_try_from_json_array() {
+            let id = json!(VALID_ID);
+            let number_id = json!(0);
+
+            let host = json!("host");
+            let number_host = json!(1);
+
+            let port = json!(80);
+            let string_port = json!("80");
+
+            for v in [vec![&id, &host, &port], vec![&id, &host], vec![&id]] {
+                let p = RegisterParams::try_from(json!(v));
+                assert!(matches!(p, Ok(..)));
+            }
+
+            // Wrong id
+            let p = RegisterParams::try_from(json!(vec![&number_id, &host, &port]));
+            assert!(matches!(p, Err(RegisterError::InvalidId(..))));
+
+            // Wrong host
+            let p = RegisterParams::try_from(json!(vec![&id, &number_host, &port]));
+            assert!(matches!(p, Err(RegisterError::InvalidHost(..))));
+
+            // Wrong port
+            let p = RegisterParams::try_from(json!(vec![&id, &host, &string_port]));
+            assert!(matches!(p, Err(RegisterError::InvalidPort(..))));
+
+            // Wrong param count (params should be 1-3)
+            let p = RegisterParams::try_from(json!(vec![&id, &host, &port, &id]));
+            assert!(matches!(p, Err(RegisterError::InvalidFormat(..))));
+        }
+
+        #[test]
+        fn test_try_from_json_dict() {
+            let id = json!(VALID_ID);
+            let host = json!("host");
+            let port = json!(80);
+
+            for v in [
+                HashMap::from([("tower_id", &id), ("host", &host), ("port", &port)]),
+                HashMap::from([("tower_id", &id), ("host", &host)]),
+                HashMap::from([("tower_id", &id)]),
+            ] {
+                let p = RegisterParams::try_from(json!(v));
+                assert!(matches!(p, Ok(..)));
+            }
+
+            // Id key missing
+            let p =
+                RegisterParams::try_from(json!(HashMap::from([("host", &host), ("port", &port)])));
+            assert!(matches!(p, Err(RegisterError::InvalidId(..))));
+
+            // Wrong id key
+            let p = RegisterParams::try_from(json!(HashMap::from([
+                ("wrong_tower_id", &id),
                 ("tower_id", &id),
                 ("host", &host),
                 ("port", &port)
@@ -464,6 +1482,36 @@ mod tests {
             assert!(matches!(p, Err(RegisterError::InvalidFormat(..))));
         }
 
+        #[test]
+        fn test_try_from_json_dict_payment_preimage() {
+            let id = json!(VALID_ID);
+            let host = json!("host");
+            let preimage = [7; 32];
+
+            // The preimage can be passed along any of the other named params
+            let p = RegisterParams::try_from(json!(HashMap::from([
+                ("tower_id", &id),
+                ("host", &host),
+                ("port", &json!(80)),
+                ("payment_preimage", &json!(hex::encode(preimage)))
+            ])))
+            .unwrap();
+            assert_eq!(p.host, Some("host".to_owned()));
+            assert_eq!(p.payment_preimage, Some(preimage.to_vec()));
+
+            let p = RegisterParams::try_from(json!(HashMap::from([("tower_id", &id)]))).unwrap();
+            assert!(p.payment_preimage.is_none());
+
+            // Preimages must be 32-byte hex strings
+            for preimage in [json!("preimage"), json!(hex::encode([7; 31])), json!(7)] {
+                let p = RegisterParams::try_from(json!(HashMap::from([
+                    ("tower_id", &id),
+                    ("payment_preimage", &preimage)
+                ])));
+                assert!(matches!(p, Err(RegisterError::InvalidFormat(..))));
+            }
+        }
+
         #[test]
         fn test_try_from_other_json() {
             // Unexpected json object (it must be either String or Array)
@@ -472,6 +1520,418 @@ mod tests {
         }
     }
 
+    mod channel_coverage_command {
+        use super::*;
+
+        const OUTPOINT: &str = "7f2c6a8b3e1d4f5a9b0c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f70:1";
+
+        #[test]
+        fn test_try_from() {
+            let outpoint = OutPoint::from_str(OUTPOINT).unwrap();
+            let p = ChannelCoverageParams::try_from(json!([OUTPOINT])).unwrap();
+            assert_eq!(p.outpoint, outpoint);
+            let p = ChannelCoverageParams::try_from(json!({ "outpoint": OUTPOINT })).unwrap();
+            assert_eq!(p.outpoint, outpoint);
+
+            // Wrong params
+            for outpoint in [json!(&OUTPOINT[..64]), json!("not_an_outpoint:1"), json!(1)] {
+                let p = ChannelCoverageParams::try_from(json!([outpoint]));
+                assert!(matches!(p, Err(ChannelCoverageError::InvalidOutpoint(..))));
+            }
+            for value in [
+                json!([]),
+                json!([OUTPOINT, 1]),
+                json!({"txid": OUTPOINT}),
+                json!(OUTPOINT),
+            ] {
+                let p = ChannelCoverageParams::try_from(value);
+                assert!(matches!(p, Err(ChannelCoverageError::InvalidFormat(..))));
+            }
+        }
+    }
+
+    mod coverage_estimate_command {
+        use super::*;
+
+        #[test]
+        fn test_try_from() {
+            let p = CoverageEstimateParams::try_from(json!([100])).unwrap();
+            assert_eq!((p.updates, p.lifetime), (100, None));
+            let p = CoverageEstimateParams::try_from(json!([100, 4320])).unwrap();
+            assert_eq!((p.updates, p.lifetime), (100, Some(4320)));
+            let p = CoverageEstimateParams::try_from(json!({"updates": 100})).unwrap();
+            assert_eq!((p.updates, p.lifetime), (100, None));
+            let p = CoverageEstimateParams::try_from(json!({"updates": 100, "lifetime": 4320}))
+                .unwrap();
+            assert_eq!((p.updates, p.lifetime), (100, Some(4320)));
+
+            // Wrong values
+            for value in [
+                json!([-1]),
+                json!(["100"]),
+                json!([u64::from(u32::MAX) + 1]),
+                json!([100, 1.5]),
+            ] {
+                let p = CoverageEstimateParams::try_from(value);
+                assert!(matches!(p, Err(CoverageEstimateError::InvalidValue(..))));
+            }
+            for value in [
+                json!([]),
+                json!([100, 4320, 1]),
+                json!({"lifetime": 4320}),
+                json!({"updates": 100, "blocks": 4320}),
+                json!(100),
+            ] {
+                let p = CoverageEstimateParams::try_from(value);
+                assert!(matches!(p, Err(CoverageEstimateError::InvalidFormat(..))));
+            }
+        }
+    }
+
+    mod channel_towers_command {
+        use super::*;
+
+        const CHANNEL_ID: &str = "4c9a3f5e0d4d4cbe6ff4c9bd4a6e08e2b1d0b4f1e2a7e5d3b5c6a0f8e9d7c1b2";
+
+        #[test]
+        fn test_try_from_array() {
+            // No towers means no restriction
+            let p = ChannelTowersParams::try_from(json!([CHANNEL_ID])).unwrap();
+            assert_eq!(p.channel_id, CHANNEL_ID);
+            assert!(p.tower_ids.is_none());
+            let p = ChannelTowersParams::try_from(json!([CHANNEL_ID, []])).unwrap();
+            assert!(p.tower_ids.is_none());
+
+            // Towers can be passed either as a single id or as a list
+            let p = ChannelTowersParams::try_from(json!([CHANNEL_ID, VALID_ID])).unwrap();
+            assert_eq!(p.tower_ids.unwrap().len(), 1);
+            let p = ChannelTowersParams::try_from(json!([CHANNEL_ID, [VALID_ID]])).unwrap();
+            assert_eq!(p.tower_ids.unwrap().len(), 1);
+
+            // Wrong params
+            let p = ChannelTowersParams::try_from(json!(["c69517f00d9482e6", VALID_ID]));
+            assert!(matches!(p, Err(ChannelTowersError::InvalidChannelId(..))));
+            let p = ChannelTowersParams::try_from(json!([0, VALID_ID]));
+            assert!(matches!(p, Err(ChannelTowersError::InvalidChannelId(..))));
+            let p = ChannelTowersParams::try_from(json!([CHANNEL_ID, [VALID_ID, "wrong_id"]]));
+            assert!(matches!(p, Err(ChannelTowersError::InvalidId(..))));
+            let p = ChannelTowersParams::try_from(json!([CHANNEL_ID, 1]));
+            assert!(matches!(p, Err(ChannelTowersError::InvalidId(..))));
+        }
+
+        #[test]
+        fn test_try_from_dict() {
+            let p = ChannelTowersParams::try_from(
+                json!({"channel_id": CHANNEL_ID, "tower_ids": [VALID_ID]}),
+            );
+            assert!(matches!(
+                p,
+                Ok(ChannelTowersParams {
+                    tower_ids: Some(..),
+                    ..
+                })
+            ));
+            let p = ChannelTowersParams::try_from(json!({ "channel_id": CHANNEL_ID }));
+            assert!(matches!(
+                p,
+                Ok(ChannelTowersParams {
+                    tower_ids: None,
+                    ..
+                })
+            ));
+
+            // Missing channel id
+            let p = ChannelTowersParams::try_from(json!({ "tower_ids": [VALID_ID] }));
+            assert!(matches!(p, Err(ChannelTowersError::InvalidFormat(..))));
+            // Wrong keys
+            let p = ChannelTowersParams::try_from(
+                json!({"channel_id": CHANNEL_ID, "towers": [VALID_ID]}),
+            );
+            assert!(matches!(p, Err(ChannelTowersError::InvalidFormat(..))));
+        }
+
+        #[test]
+        fn test_wrong_param_count() {
+            for params in [
+                json!([]),
+                json!([CHANNEL_ID, VALID_ID, VALID_ID]),
+                json!(true),
+            ] {
+                let p = ChannelTowersParams::try_from(params);
+                assert!(matches!(p, Err(ChannelTowersError::InvalidFormat(..))));
+            }
+        }
+    }
+
+    mod tower_order_command {
+        use super::*;
+
+        const ANOTHER_VALID_ID: &str =
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+
+        #[test]
+        fn test_try_from_array() {
+            // No towers means no order
+            for params in [json!(null), json!([]), json!([[]])] {
+                let p = TowerOrderParams::try_from(params).unwrap();
+                assert!(p.tower_ids.is_empty());
+            }
+
+            // Towers can be passed either as separate params or as a single list, and the order is kept
+            let expected = vec![
+                TowerId::from_str(VALID_ID).unwrap(),
+                TowerId::from_str(ANOTHER_VALID_ID).unwrap(),
+            ];
+            let p = TowerOrderParams::try_from(json!([VALID_ID, ANOTHER_VALID_ID])).unwrap();
+            assert_eq!(p.tower_ids, expected);
+            let p = TowerOrderParams::try_from(json!([[VALID_ID, ANOTHER_VALID_ID]])).unwrap();
+            assert_eq!(p.tower_ids, expected);
+
+            // Wrong params
+            let p = TowerOrderParams::try_from(json!([VALID_ID, "wrong_id"]));
+            assert!(matches!(p, Err(TowerOrderError::InvalidId(..))));
+            let p = TowerOrderParams::try_from(json!([VALID_ID, 1]));
+            assert!(matches!(p, Err(TowerOrderError::InvalidId(..))));
+            let p = TowerOrderParams::try_from(json!([VALID_ID, ANOTHER_VALID_ID, VALID_ID]));
+            assert!(matches!(p, Err(TowerOrderError::InvalidFormat(..))));
+        }
+
+        #[test]
+        fn test_try_from_dict() {
+            let p = TowerOrderParams::try_from(json!({ "tower_ids": [VALID_ID] })).unwrap();
+            assert_eq!(p.tower_ids, vec![TowerId::from_str(VALID_ID).unwrap()]);
+            let p = TowerOrderParams::try_from(json!({})).unwrap();
+            assert!(p.tower_ids.is_empty());
+
+            // Wrong keys
+            let p = TowerOrderParams::try_from(json!({ "towers": [VALID_ID] }));
+            assert!(matches!(p, Err(TowerOrderError::InvalidFormat(..))));
+            let p = TowerOrderParams::try_from(json!(true));
+            assert!(matches!(p, Err(TowerOrderError::InvalidFormat(..))));
+        }
+    }
+
+    mod retry_tower_command {
+        use super::*;
+
+        #[test]
+        fn test_try_from() {
+            let tower_id = TowerId::from_str(VALID_ID).unwrap();
+
+            // Not aggressive unless requested
+            for params in [json!(VALID_ID), json!([VALID_ID]), json!([VALID_ID, null])] {
+                let p = RetryTowerParams::try_from(params).unwrap();
+                assert_eq!(p.tower_id, tower_id);
+                assert!(!p.aggressive);
+            }
+            for params in [
+                json!([VALID_ID, true]),
+                json!([VALID_ID, "--aggressive"]),
+                json!({ "tower_id": VALID_ID, "aggressive": true }),
+            ] {
+                let p = RetryTowerParams::try_from(params).unwrap();
+                assert_eq!(p.tower_id, tower_id);
+                assert!(p.aggressive);
+            }
+
+            // Wrong params
+            let p = RetryTowerParams::try_from(json!(["wrong_id", true]));
+            assert!(matches!(p, Err(RetryTowerError::InvalidId(..))));
+            let p = RetryTowerParams::try_from(json!([VALID_ID, "yes"]));
+            assert!(matches!(p, Err(RetryTowerError::InvalidFlag(..))));
+            let p = RetryTowerParams::try_from(json!([VALID_ID, true, true]));
+            assert!(matches!(p, Err(RetryTowerError::InvalidFormat(..))));
+            let p = RetryTowerParams::try_from(json!({ "tower": VALID_ID }));
+            assert!(matches!(p, Err(RetryTowerError::InvalidFormat(..))));
+        }
+    }
+
+    mod tower_labels_command {
+        use super::*;
+
+        #[test]
+        fn test_try_from_array() {
+            // No labels means removing all of them
+            let p = TowerLabelsParams::try_from(json!([VALID_ID])).unwrap();
+            assert_eq!(p.tower_id, TowerId::from_str(VALID_ID).unwrap());
+            assert!(p.labels.is_empty());
+
+            // Labels can be passed either as a single label or as a list
+            let p = TowerLabelsParams::try_from(json!([VALID_ID, "backup"])).unwrap();
+            assert_eq!(p.labels, BTreeSet::from(["backup".to_owned()]));
+            let p = TowerLabelsParams::try_from(json!([VALID_ID, ["backup", "tor"]])).unwrap();
+            assert_eq!(p.labels.len(), 2);
+
+            // Wrong params
+            let p = TowerLabelsParams::try_from(json!(["wrong_id", "backup"]));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidId(..))));
+            for label in [json!(""), json!("two words"), json!(1), json!(["tor", 1])] {
+                let p = TowerLabelsParams::try_from(json!([VALID_ID, label]));
+                assert!(matches!(p, Err(TowerLabelsError::InvalidLabel(..))));
+            }
+            let p = TowerLabelsParams::try_from(json!([VALID_ID, "tor", "backup"]));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidFormat(..))));
+        }
+
+        #[test]
+        fn test_try_from_dict() {
+            let p = TowerLabelsParams::try_from(json!({"tower_id": VALID_ID, "labels": ["tor"]}))
+                .unwrap();
+            assert_eq!(p.labels, BTreeSet::from(["tor".to_owned()]));
+
+            // Missing tower id
+            let p = TowerLabelsParams::try_from(json!({ "labels": ["tor"] }));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidFormat(..))));
+            // Wrong keys
+            let p = TowerLabelsParams::try_from(json!({"tower_id": VALID_ID, "label": "tor"}));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidFormat(..))));
+        }
+
+        #[test]
+        fn test_label_filter() {
+            for params in [json!(null), json!([]), json!({}), json!([null])] {
+                assert!(LabelFilterParams::try_from(params).unwrap().label.is_none());
+            }
+            for params in [json!(["tor"]), json!({"label": "tor"})] {
+                assert_eq!(
+                    LabelFilterParams::try_from(params).unwrap().label,
+                    Some("tor".to_owned())
+                );
+            }
+
+            let p = LabelFilterParams::try_from(json!(["tor", "backup"]));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidFormat(..))));
+            let p = LabelFilterParams::try_from(json!({"tag": "tor"}));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidFormat(..))));
+            let p = LabelFilterParams::try_from(json!([""]));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidLabel(..))));
+        }
+
+        #[test]
+        fn test_list_towers() {
+            for params in [json!(null), json!([]), json!({}), json!({"verbose": false})] {
+                let p = ListTowersParams::try_from(params).unwrap();
+                assert_eq!((p.label, p.verbose), (None, false));
+            }
+            for params in [json!(["--verbose"]), json!({ "verbose": true })] {
+                let p = ListTowersParams::try_from(params).unwrap();
+                assert_eq!((p.label, p.verbose), (None, true));
+            }
+            for params in [
+                json!(["tor", "--verbose"]),
+                json!(["--verbose", "tor"]),
+                json!({"label": "tor", "verbose": true}),
+            ] {
+                let p = ListTowersParams::try_from(params).unwrap();
+                assert_eq!((p.label, p.verbose), (Some("tor".to_owned()), true));
+            }
+
+            let p = ListTowersParams::try_from(json!({"verbose": "yes"}));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidFormat(..))));
+            let p = ListTowersParams::try_from(json!(["tor", "backup", "--verbose"]));
+            assert!(matches!(p, Err(TowerLabelsError::InvalidFormat(..))));
+        }
+    }
+
+    mod tower_pin_command {
+        use super::*;
+
+        const VALID_PIN: &str =
+            "40:E6:8C:BD:92:2F:65:7D:BE:D5:5F:1E:21:2F:C1:52:5B:95:B3:C5:7A:37:AD:EF:FA:92:F1:D1:C7:96:29:5B";
+
+        #[test]
+        fn test_try_from() {
+            let tls_pin = TlsPin::from_str(VALID_PIN).unwrap();
+            for params in [
+                json!([VALID_ID, VALID_PIN]),
+                json!({"tower_id": VALID_ID, "tls_pin": VALID_PIN}),
+            ] {
+                let p = TowerPinParams::try_from(params).unwrap();
+                assert_eq!(p.tower_id, TowerId::from_str(VALID_ID).unwrap());
+                assert_eq!(p.tls_pin, Some(tls_pin));
+            }
+
+            // No pin means removing it
+            for params in [json!([VALID_ID]), json!({ "tower_id": VALID_ID })] {
+                assert!(TowerPinParams::try_from(params).unwrap().tls_pin.is_none());
+            }
+
+            // Wrong params
+            let p = TowerPinParams::try_from(json!(["wrong_id", VALID_PIN]));
+            assert!(matches!(p, Err(TowerPinError::InvalidId(..))));
+            for tls_pin in [json!("00"), json!(1)] {
+                let p = TowerPinParams::try_from(json!([VALID_ID, tls_pin]));
+                assert!(matches!(p, Err(TowerPinError::InvalidPin(..))));
+            }
+            let p = TowerPinParams::try_from(json!([VALID_ID, VALID_PIN, VALID_PIN]));
+            assert!(matches!(p, Err(TowerPinError::InvalidFormat(..))));
+            let p = TowerPinParams::try_from(json!({"tower_id": VALID_ID, "pin": VALID_PIN}));
+            assert!(matches!(p, Err(TowerPinError::InvalidFormat(..))));
+        }
+    }
+
+    mod auto_renew_command {
+        use super::*;
+
+        #[test]
+        fn test_try_from() {
+            for (params, enabled) in [
+                (json!([VALID_ID, false]), false),
+                (json!({"tower_id": VALID_ID, "enabled": false}), false),
+                // Not setting the flag means enabling it
+                (json!([VALID_ID]), true),
+                (json!({ "tower_id": VALID_ID }), true),
+            ] {
+                let p = AutoRenewParams::try_from(params).unwrap();
+                assert_eq!(p.tower_id, TowerId::from_str(VALID_ID).unwrap());
+                assert_eq!(p.enabled, enabled);
+            }
+
+            // Wrong params
+            let p = AutoRenewParams::try_from(json!(["wrong_id", true]));
+            assert!(matches!(p, Err(AutoRenewError::InvalidId(..))));
+            for flag in [json!("true"), json!(1)] {
+                let p = AutoRenewParams::try_from(json!([VALID_ID, flag]));
+                assert!(matches!(p, Err(AutoRenewError::InvalidFlag(..))));
+            }
+            let p = AutoRenewParams::try_from(json!([VALID_ID, true, true]));
+            assert!(matches!(p, Err(AutoRenewError::InvalidFormat(..))));
+            let p = AutoRenewParams::try_from(json!({"tower_id": VALID_ID, "enable": true}));
+            assert!(matches!(p, Err(AutoRenewError::InvalidFormat(..))));
+        }
+    }
+
+    mod tower_proxy_command {
+        use super::*;
+
+        #[test]
+        fn test_try_from() {
+            for (params, use_proxy) in [
+                (json!([VALID_ID, false]), false),
+                (json!({"tower_id": VALID_ID, "use_proxy": false}), false),
+                // Not setting the flag means enabling it
+                (json!([VALID_ID]), true),
+                (json!({ "tower_id": VALID_ID }), true),
+            ] {
+                let p = TowerProxyParams::try_from(params).unwrap();
+                assert_eq!(p.tower_id, TowerId::from_str(VALID_ID).unwrap());
+                assert_eq!(p.use_proxy, use_proxy);
+            }
+
+            // Wrong params
+            let p = TowerProxyParams::try_from(json!(["wrong_id", true]));
+            assert!(matches!(p, Err(TowerProxyError::InvalidId(..))));
+            for flag in [json!("true"), json!(1)] {
+                let p = TowerProxyParams::try_from(json!([VALID_ID, flag]));
+                assert!(matches!(p, Err(TowerProxyError::InvalidFlag(..))));
+            }
+            let p = TowerProxyParams::try_from(json!([VALID_ID, true, true]));
+            assert!(matches!(p, Err(TowerProxyError::InvalidFormat(..))));
+            let p = TowerProxyParams::try_from(json!({"tower_id": VALID_ID, "proxy": true}));
+            assert!(matches!(p, Err(TowerProxyError::InvalidFormat(..))));
+        }
+    }
+
     mod get_appointment_command {
         use super::*;
 
@@ -0,0 +1,289 @@
+//! Logic related to estimating what covering a new channel with the current towers would take.
+//!
+//! This is a planning tool: the estimate is pure arithmetic over the subscriptions the client holds and the terms the
+//! towers advertise, nothing is sent to the towers. Every commitment update produces a single appointment, which takes
+//! a single slot, and is sent to every tower the client is watching with.
+
+use serde::Serialize;
+
+use teos_common::TowerId;
+
+use crate::net::http::TowerTerms;
+use crate::TowerSummary;
+
+/// What is known about the terms of a tower.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KnownTerms {
+    /// The tower advertises the given terms.
+    Advertised(TowerTerms),
+    /// The tower does not advertise any terms, so it is free to use.
+    NotAdvertised,
+    /// The terms of the tower could not be fetched.
+    Unknown,
+}
+
+/// Estimate of what covering a channel would require from a given tower.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TowerCoverage {
+    pub tower_id: TowerId,
+    pub available_slots: u32,
+    /// Slots that would need to be topped up for the subscription to fit all the channel updates.
+    pub missing_slots: u32,
+    /// Cost (in sats) of the missing slots. Unset if the terms of the tower are unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_up_cost: Option<u64>,
+    /// Subscription renewals needed for the subscription to outlive the channel. Unset if either the channel lifetime
+    /// or the subscription duration of the tower are unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renewals: Option<u32>,
+}
+
+impl TowerCoverage {
+    /// Estimates what covering `updates` commitment updates over `lifetime` blocks (from `block_height`) would require
+    /// from a given tower.
+    pub fn new(
+        tower_id: TowerId,
+        tower: &TowerSummary,
+        terms: &KnownTerms,
+        updates: u32,
+        lifetime: Option<u32>,
+        block_height: Option<u32>,
+    ) -> Self {
+        let missing_slots = updates.saturating_sub(tower.available_slots);
+        let top_up_cost = match terms {
+            KnownTerms::Advertised(terms) => {
+                Some(u64::from(missing_slots).saturating_mul(terms.price_per_slot))
+            }
+            KnownTerms::NotAdvertised => Some(0),
+            KnownTerms::Unknown => None,
+        };
+        let renewals = match (terms, lifetime, block_height) {
+            (KnownTerms::Advertised(terms), Some(lifetime), Some(height))
+                if terms.subscription_duration > 0 =>
+            {
+                // Expired subscriptions need to be renewed from the current height
+                let covered_until = tower.subscription_expiry.max(height);
+                let uncovered = height
+                    .saturating_add(lifetime)
+                    .saturating_sub(covered_until);
+                Some(uncovered.div_ceil(terms.subscription_duration))
+            }
+            _ => None,
+        };
+
+        TowerCoverage {
+            tower_id,
+            available_slots: tower.available_slots,
+            missing_slots,
+            top_up_cost,
+            renewals,
+        }
+    }
+}
+
+/// Estimate of what covering a channel with all the current towers would require.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct CoverageEstimate {
+    /// Slots required from every tower (one per commitment update).
+    pub required_slots: u32,
+    pub towers: Vec<TowerCoverage>,
+    /// Cost (in sats) of topping up all the towers whose terms are known.
+    pub total_cost: u64,
+}
+
+impl CoverageEstimate {
+    /// Builds the estimate given the towers the client is watching with and what is known about their terms.
+    ///
+    /// Misbehaving towers are left out, given appointments are no longer sent to them.
+    pub fn new<'a>(
+        towers: impl IntoIterator<Item = (TowerId, &'a TowerSummary, KnownTerms)>,
+        updates: u32,
+        lifetime: Option<u32>,
+        block_height: Option<u32>,
+    ) -> Self {
+        let mut towers: Vec<TowerCoverage> = towers
+            .into_iter()
+            .filter(|(_, tower, _)| !tower.status.is_misbehaving())
+            .map(|(tower_id, tower, terms)| {
+                TowerCoverage::new(tower_id, tower, &terms, updates, lifetime, block_height)
+            })
+            .collect();
+        towers.sort_by_key(|t| t.tower_id.to_vec());
+        let total_cost = towers
+            .iter()
+            .filter_map(|t| t.top_up_cost)
+            .fold(0u64, u64::saturating_add);
+
+        CoverageEstimate {
+            required_slots: updates,
+            towers,
+            total_cost,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use teos_common::test_utils::get_random_user_id;
+
+    use crate::TowerStatus;
+
+    fn terms(price_per_slot: u64, subscription_duration: u32) -> KnownTerms {
+        KnownTerms::Advertised(TowerTerms {
+            price_per_slot,
+            payment_methods: vec!["bolt11".to_owned()],
+            subscription_duration,
+        })
+    }
+
+    #[test]
+    fn test_tower_coverage() {
+        let tower_id = get_random_user_id();
+        // 300 slots left, expiring at height 1000
+        let tower = TowerSummary::new("talaia.watch".to_owned(), 300, 0, 1000);
+
+        // 1000 updates over 5000 blocks, starting at height 500: 700 slots short, covered up to 1000 and 4500 blocks
+        // uncovered, which means 3 renewals of 2016 blocks
+        let coverage = TowerCoverage::new(
+            tower_id,
+            &tower,
+            &terms(2, 2016),
+            1000,
+            Some(5000),
+            Some(500),
+        );
+        assert_eq!(
+            coverage,
+            TowerCoverage {
+                tower_id,
+                available_slots: 300,
+                missing_slots: 700,
+                top_up_cost: Some(1400),
+                renewals: Some(3),
+            }
+        );
+
+        // Fitting updates cost nothing, and neither does outliving the channel
+        let coverage =
+            TowerCoverage::new(tower_id, &tower, &terms(2, 2016), 300, Some(400), Some(500));
+        assert_eq!(
+            (
+                coverage.missing_slots,
+                coverage.top_up_cost,
+                coverage.renewals
+            ),
+            (0, Some(0), Some(0))
+        );
+
+        // Expired subscriptions are renewed from the current height
+        let coverage = TowerCoverage::new(
+            tower_id,
+            &tower,
+            &terms(2, 2016),
+            300,
+            Some(2017),
+            Some(1500),
+        );
+        assert_eq!(coverage.renewals, Some(2));
+
+        // Free towers cost nothing, but the renewals are unknown. So are they if the lifetime is
+        let coverage = TowerCoverage::new(
+            tower_id,
+            &tower,
+            &KnownTerms::NotAdvertised,
+            1000,
+            Some(5000),
+            Some(500),
+        );
+        assert_eq!(
+            (
+                coverage.missing_slots,
+                coverage.top_up_cost,
+                coverage.renewals
+            ),
+            (700, Some(0), None)
+        );
+        let coverage = TowerCoverage::new(tower_id, &tower, &terms(2, 2016), 1000, None, Some(500));
+        assert_eq!(
+            (coverage.top_up_cost, coverage.renewals),
+            (Some(1400), None)
+        );
+
+        // Nothing but the slots can be told if the terms are unknown
+        let coverage = TowerCoverage::new(
+            tower_id,
+            &tower,
+            &KnownTerms::Unknown,
+            1000,
+            Some(5000),
+            Some(500),
+        );
+        assert_eq!(
+            (
+                coverage.missing_slots,
+                coverage.top_up_cost,
+                coverage.renewals
+            ),
+            (700, None, None)
+        );
+    }
+
+    #[test]
+    fn test_coverage_estimate() {
+        let paid_id = get_random_user_id();
+        let paid = TowerSummary::new("paid.watch".to_owned(), 100, 0, 1000);
+        let free_id = get_random_user_id();
+        let free = TowerSummary::new("free.watch".to_owned(), 10, 0, 1000);
+        let unknown_id = get_random_user_id();
+        let unknown = TowerSummary::new("unknown.watch".to_owned(), 0, 0, 1000);
+        let misbehaving = TowerSummary::new("misbehaving.watch".to_owned(), 0, 0, 1000)
+            .with_status(TowerStatus::Misbehaving);
+
+        let estimate = CoverageEstimate::new(
+            [
+                (paid_id, &paid, terms(3, 1000)),
+                (free_id, &free, KnownTerms::NotAdvertised),
+                (unknown_id, &unknown, KnownTerms::Unknown),
+                (get_random_user_id(), &misbehaving, terms(1, 1000)),
+            ],
+            250,
+            Some(2000),
+            Some(900),
+        );
+
+        let mut expected = vec![
+            TowerCoverage {
+                tower_id: paid_id,
+                available_slots: 100,
+                missing_slots: 150,
+                top_up_cost: Some(450),
+                renewals: Some(2),
+            },
+            TowerCoverage {
+                tower_id: free_id,
+                available_slots: 10,
+                missing_slots: 240,
+                top_up_cost: Some(0),
+                renewals: None,
+            },
+            TowerCoverage {
+                tower_id: unknown_id,
+                available_slots: 0,
+                missing_slots: 250,
+                top_up_cost: None,
+                renewals: None,
+            },
+        ];
+        expected.sort_by_key(|t| t.tower_id.to_vec());
+        assert_eq!(
+            estimate,
+            CoverageEstimate {
+                required_slots: 250,
+                towers: expected,
+                total_cost: 450,
+            }
+        );
+    }
+}
@@ -1,24 +1,31 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::iter::FromIterator;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::Duration;
 
-use rusqlite::{params, Connection, Error as SqliteError};
+use rusqlite::{params, Connection, Error as SqliteError, OptionalExtension};
 
 use bitcoin::secp256k1::SecretKey;
 
-use teos_common::appointment::{Appointment, Locator};
+use teos_common::appointment::{Appointment, Locator, SignatureVersion};
 use teos_common::dbm::{DatabaseConnection, DatabaseManager, Error};
-use teos_common::receipts::{AppointmentReceipt, RegistrationReceipt};
+use teos_common::net::NetAddr;
+use teos_common::receipts::{AppointmentReceipt, DeletionReceipt, RegistrationReceipt};
 use teos_common::{TowerId, UserId};
 
-use crate::{AppointmentStatus, MisbehaviorProof, TowerInfo, TowerStatus, TowerSummary};
+use crate::delivery::DeliveryRecord;
+use crate::net::TlsPin;
+use crate::{
+    AppointmentStatus, DeadLetter, MisbehaviorProof, TowerInfo, TowerStatus, TowerSummary,
+};
 
-const TABLES: [&str; 8] = [
+const TABLES: [&str; 19] = [
     "CREATE TABLE IF NOT EXISTS towers (
     tower_id INT PRIMARY KEY,
     net_addr TEXT NOT NULL,
-    available_slots INT NOT NULL
+    available_slots INT NOT NULL,
+    use_proxy INT NOT NULL DEFAULT 0
 )",
     "CREATE TABLE IF NOT EXISTS appointments (
     locator INT PRIMARY KEY,
@@ -28,6 +35,9 @@ const TABLES: [&str; 8] = [
     "CREATE TABLE IF NOT EXISTS pending_appointments (
     locator INT NOT NULL,
     tower_id INT NOT NULL,
+    deadline INT,
+    rejections INT NOT NULL DEFAULT 0,
+    created_at INT NOT NULL DEFAULT 0,
     PRIMARY KEY (locator, tower_id),
     FOREIGN KEY(locator)
         REFERENCES appointments(locator)
@@ -39,6 +49,19 @@ const TABLES: [&str; 8] = [
     "CREATE TABLE IF NOT EXISTS invalid_appointments (
     locator INT NOT NULL,
     tower_id INT NOT NULL,
+    rejections INT NOT NULL DEFAULT 1,
+    invalidated_at INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (locator, tower_id),
+    FOREIGN KEY(locator)
+        REFERENCES appointments(locator)
+        ON DELETE CASCADE
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS expired_appointments (
+    locator INT NOT NULL,
+    tower_id INT NOT NULL,
     PRIMARY KEY (locator, tower_id),
     FOREIGN KEY(locator)
         REFERENCES appointments(locator)
@@ -64,6 +87,19 @@ const TABLES: [&str; 8] = [
     start_block INT NOT NULL,
     user_signature BLOB NOT NULL,
     tower_signature BLOB NOT NULL,
+    available_slots INT,
+    delivered_at INT,
+    PRIMARY KEY (locator, tower_id),
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS deletion_receipts (
+    locator INT NOT NULL,
+    tower_id INT NOT NULL,
+    deletion_block INT NOT NULL,
+    user_signature BLOB NOT NULL,
+    tower_signature BLOB NOT NULL,
     PRIMARY KEY (locator, tower_id),
     FOREIGN KEY(tower_id)
         REFERENCES towers(tower_id)
@@ -80,6 +116,68 @@ const TABLES: [&str; 8] = [
     "CREATE TABLE IF NOT EXISTS keys (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     key INT NOT NULL
+)",
+    "CREATE TABLE IF NOT EXISTS channel_towers (
+    channel_id TEXT NOT NULL,
+    tower_id INT NOT NULL,
+    PRIMARY KEY (channel_id, tower_id)
+)",
+    "CREATE TABLE IF NOT EXISTS appointment_channels (
+    locator INT PRIMARY KEY,
+    channel_id TEXT NOT NULL,
+    commit_num INT NOT NULL
+)",
+    "CREATE TABLE IF NOT EXISTS tower_labels (
+    tower_id INT NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY (tower_id, label),
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS tower_pins (
+    tower_id INT PRIMARY KEY,
+    fingerprint BLOB NOT NULL,
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS auto_renew_towers (
+    tower_id INT PRIMARY KEY,
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS tower_signature_versions (
+    tower_id INT PRIMARY KEY,
+    version INT NOT NULL,
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS mirror_tower (
+    id INT PRIMARY KEY CHECK (id = 0),
+    tower_id INT NOT NULL,
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS tower_order (
+    position INT PRIMARY KEY,
+    tower_id INT NOT NULL UNIQUE,
+    FOREIGN KEY(tower_id)
+        REFERENCES towers(tower_id)
+        ON DELETE CASCADE
+)",
+    "CREATE TABLE IF NOT EXISTS dead_letters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    tower_id INT NOT NULL,
+    locator INT NOT NULL,
+    encrypted_blob BLOB NOT NULL,
+    to_self_delay INT NOT NULL,
+    error_code INT NOT NULL,
+    error TEXT NOT NULL,
+    rejected_at INT NOT NULL
 )",
 ];
 
@@ -109,10 +207,102 @@ impl DBM {
         connection.execute("PRAGMA foreign_keys=1;", [])?;
         let mut dbm = Self { connection };
         dbm.create_tables(Vec::from_iter(TABLES))?;
+        dbm.update_tables()?;
 
         Ok(dbm)
     }
 
+    /// Sets for how long queries wait for the database to be unlocked by other connections before failing as busy.
+    pub fn set_busy_timeout(&self, timeout: Duration) -> Result<(), SqliteError> {
+        self.connection.busy_timeout(timeout)
+    }
+
+    /// Adds the columns that were introduced after the tables were first created, so databases
+    /// created by older versions of the plugin can still be used.
+    fn update_tables(&self) -> Result<(), SqliteError> {
+        if self
+            .connection
+            .prepare("SELECT use_proxy FROM towers")
+            .is_err()
+        {
+            self.connection.execute(
+                "ALTER TABLE towers ADD COLUMN use_proxy INT NOT NULL DEFAULT 0",
+                [],
+            )?;
+            self.connection.execute(
+                "UPDATE towers SET use_proxy = 1 WHERE net_addr LIKE '%.onion%'",
+                [],
+            )?;
+        }
+        if self
+            .connection
+            .prepare("SELECT deadline FROM pending_appointments")
+            .is_err()
+        {
+            self.connection.execute(
+                "ALTER TABLE pending_appointments ADD COLUMN deadline INT",
+                [],
+            )?;
+        }
+        if self
+            .connection
+            .prepare("SELECT rejections FROM pending_appointments")
+            .is_err()
+        {
+            self.connection.execute(
+                "ALTER TABLE pending_appointments ADD COLUMN rejections INT NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        if self
+            .connection
+            .prepare("SELECT created_at FROM pending_appointments")
+            .is_err()
+        {
+            self.connection.execute(
+                "ALTER TABLE pending_appointments ADD COLUMN created_at INT NOT NULL DEFAULT 0",
+                [],
+            )?;
+            // There is no way of knowing when the existing appointments were created, so they are deemed created now
+            self.connection.execute(
+                "UPDATE pending_appointments SET created_at = CAST(strftime('%s', 'now') AS INT)",
+                [],
+            )?;
+        }
+        if self
+            .connection
+            .prepare("SELECT rejections, invalidated_at FROM invalid_appointments")
+            .is_err()
+        {
+            self.connection.execute(
+                "ALTER TABLE invalid_appointments ADD COLUMN rejections INT NOT NULL DEFAULT 1",
+                [],
+            )?;
+            self.connection.execute(
+                "ALTER TABLE invalid_appointments ADD COLUMN invalidated_at INT NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+
+        // Receipts stored before deliveries were tracked have neither the slots nor the delivery time.
+        if self
+            .connection
+            .prepare("SELECT delivered_at FROM appointment_receipts")
+            .is_err()
+        {
+            self.connection.execute(
+                "ALTER TABLE appointment_receipts ADD COLUMN available_slots INT",
+                [],
+            )?;
+            self.connection.execute(
+                "ALTER TABLE appointment_receipts ADD COLUMN delivered_at INT",
+                [],
+            )?;
+        }
+
+        Ok(())
+    }
+
     /// Stores the client secret key into the database.
     ///
     /// When a new key is generated, old keys are not overwritten but are not retrievable from the API either.
@@ -151,11 +341,19 @@ impl DBM {
         receipt: &RegistrationReceipt,
     ) -> Result<(), Error> {
         let tx = self.get_mut_connection().transaction().unwrap();
+        // Onion towers are flagged to always use the proxy. The flag is only inferred again if the address changes, so
+        // the one set by the user (see Self::store_tower_proxy) is kept otherwise.
         tx.execute(
-            "INSERT INTO towers (tower_id, net_addr, available_slots) 
-                VALUES (?1, ?2, ?3) 
-                ON CONFLICT (tower_id) DO UPDATE SET net_addr = ?2, available_slots = ?3",
-            params![tower_id.to_vec(), net_addr, receipt.available_slots()],
+            "INSERT INTO towers (tower_id, net_addr, available_slots, use_proxy) 
+                VALUES (?1, ?2, ?3, ?4) 
+                ON CONFLICT (tower_id) DO UPDATE SET net_addr = ?2, available_slots = ?3, 
+                    use_proxy = CASE WHEN net_addr = ?2 THEN use_proxy ELSE ?4 END",
+            params![
+                tower_id.to_vec(),
+                net_addr,
+                receipt.available_slots(),
+                NetAddr::new(net_addr.to_owned()).is_onion()
+            ],
         )
         .map_err(Error::Unknown)?;
         tx.execute(
@@ -174,7 +372,7 @@ impl DBM {
     pub fn load_tower_record(&self, tower_id: TowerId) -> Option<TowerInfo> {
         let mut stmt = self
         .connection
-        .prepare("SELECT t.net_addr, t.available_slots, r.subscription_start, r.subscription_expiry 
+        .prepare("SELECT t.net_addr, t.available_slots, r.subscription_start, r.subscription_expiry, t.use_proxy 
                     FROM towers as t, registration_receipts as r 
                     WHERE t.tower_id = r.tower_id AND t.tower_id = ?1 AND r.subscription_expiry = (SELECT MAX(subscription_expiry) 
                         FROM registration_receipts 
@@ -187,6 +385,7 @@ impl DBM {
                 let available_slots: u32 = row.get(1).unwrap();
                 let subscription_start: u32 = row.get(2).unwrap();
                 let subscription_expiry: u32 = row.get(3).unwrap();
+                let use_proxy: bool = row.get(4).unwrap();
                 Ok(TowerInfo::new(
                     net_addr,
                     available_slots,
@@ -195,9 +394,15 @@ impl DBM {
                     self.load_appointment_receipts(tower_id),
                     self.load_appointments(tower_id, AppointmentStatus::Pending),
                     self.load_appointments(tower_id, AppointmentStatus::Invalid),
-                ))
+                )
+                .with_proxy(use_proxy)
+                .with_labels(self.load_tower_labels(tower_id))
+                .with_tls_pin(self.load_tower_pin(tower_id))
+                .with_auto_renew(self.load_tower_auto_renew(tower_id))
+                .with_signature_version(self.load_tower_signature_version(tower_id)))
             })
             .ok()?;
+        tower.expired_appointments = self.load_appointments(tower_id, AppointmentStatus::Expired);
 
         if let Some(proof) = self.load_misbehaving_proof(tower_id) {
             tower.status = TowerStatus::Misbehaving;
@@ -250,12 +455,343 @@ impl DBM {
         self.remove_data(query, params![tower_id.to_vec()])
     }
 
+    /// Removes a batch of towers from the database in a single transaction.
+    ///
+    /// Either all of them are removed, or none is if any of them cannot be found.
+    pub fn remove_tower_records(&mut self, tower_ids: &[TowerId]) -> Result<(), Error> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        for tower_id in tower_ids {
+            match tx.execute(
+                "DELETE FROM towers WHERE tower_id=?",
+                params![tower_id.to_vec()],
+            ) {
+                Ok(0) => return Err(Error::NotFound),
+                Ok(_) => (),
+                Err(e) => return Err(Error::Unknown(e)),
+            }
+        }
+
+        tx.commit().map_err(Error::Unknown)
+    }
+
+    /// Stores the set of towers the appointments of a given channel are sent to, replacing any previous one.
+    ///
+    /// An empty set removes the restriction, so the appointments of the channel are sent to all towers again.
+    pub fn store_channel_towers(
+        &mut self,
+        channel_id: &str,
+        towers: &HashSet<TowerId>,
+    ) -> Result<(), Error> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        tx.execute(
+            "DELETE FROM channel_towers WHERE channel_id = ?",
+            params![channel_id],
+        )
+        .map_err(Error::Unknown)?;
+        for tower_id in towers {
+            tx.execute(
+                "INSERT INTO channel_towers (channel_id, tower_id) VALUES (?1, ?2)",
+                params![channel_id, tower_id.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+        }
+
+        tx.commit().map_err(Error::Unknown)
+    }
+
+    /// Loads the set of towers the appointments of a given channel are sent to.
+    ///
+    /// Returns [None] if the channel has no restriction (i.e. its appointments are sent to all towers).
+    pub fn load_channel_towers(&self, channel_id: &str) -> Option<HashSet<TowerId>> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT tower_id FROM channel_towers WHERE channel_id = ?")
+            .unwrap();
+
+        let towers: HashSet<TowerId> = stmt
+            .query_map([channel_id], |row| {
+                let raw_tower_id: Vec<u8> = row.get(0).unwrap();
+                Ok(TowerId::from_slice(&raw_tower_id).unwrap())
+            })
+            .unwrap()
+            .map(|tower_id_res| tower_id_res.unwrap())
+            .collect();
+
+        (!towers.is_empty()).then_some(towers)
+    }
+
+    /// Stores the channel (and commitment number) a given appointment was created for.
+    ///
+    /// The mapping outlives the appointment data, so it can still be looked up once the appointment has been delivered.
+    pub fn store_appointment_channel(
+        &self,
+        locator: Locator,
+        channel_id: &str,
+        commit_num: u32,
+    ) -> Result<(), Error> {
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO appointment_channels (locator, channel_id, commit_num) VALUES (?1, ?2, ?3)",
+                params![locator.to_vec(), channel_id, commit_num],
+            )
+            .map(|_| ())
+            .map_err(Error::Unknown)
+    }
+
+    /// Loads the locators of the appointments created for a given channel, alongside their commitment number.
+    ///
+    /// Appointments are sorted by commitment number.
+    pub fn load_channel_appointments(&self, channel_id: &str) -> Vec<(Locator, u32)> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT locator, commit_num FROM appointment_channels WHERE channel_id = ? ORDER BY commit_num",
+            )
+            .unwrap();
+
+        stmt.query_map([channel_id], |row| {
+            let raw_locator: Vec<u8> = row.get(0).unwrap();
+            Ok((
+                Locator::from_slice(&raw_locator).unwrap(),
+                row.get::<_, u32>(1).unwrap(),
+            ))
+        })
+        .unwrap()
+        .map(|res| res.unwrap())
+        .collect()
+    }
+
+    /// Loads the status of a given appointment for every tower it is known by.
+    pub fn load_appointment_statuses(
+        &self,
+        locator: Locator,
+    ) -> HashMap<TowerId, AppointmentStatus> {
+        let mut statuses = HashMap::new();
+        for (status, table) in [
+            (AppointmentStatus::Pending, "pending_appointments"),
+            (AppointmentStatus::Invalid, "invalid_appointments"),
+            (AppointmentStatus::Expired, "expired_appointments"),
+            (AppointmentStatus::Accepted, "appointment_receipts"),
+        ] {
+            let mut stmt = self
+                .connection
+                .prepare(&format!("SELECT tower_id FROM {table} WHERE locator = ?"))
+                .unwrap();
+            let tower_ids = stmt
+                .query_map([locator.to_vec()], |row| {
+                    let raw_tower_id: Vec<u8> = row.get(0).unwrap();
+                    Ok(TowerId::from_slice(&raw_tower_id).unwrap())
+                })
+                .unwrap();
+            for tower_id in tower_ids {
+                // An appointment accepted by a tower overrides any other status it may have been left at
+                statuses.insert(tower_id.unwrap(), status);
+            }
+        }
+
+        statuses
+    }
+
+    /// Stores the labels of a given tower, replacing any previous ones.
+    pub fn store_tower_labels(
+        &mut self,
+        tower_id: TowerId,
+        labels: &BTreeSet<String>,
+    ) -> Result<(), Error> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        tx.execute(
+            "DELETE FROM tower_labels WHERE tower_id = ?",
+            params![tower_id.to_vec()],
+        )
+        .map_err(Error::Unknown)?;
+        for label in labels {
+            tx.execute(
+                "INSERT INTO tower_labels (tower_id, label) VALUES (?1, ?2)",
+                params![tower_id.to_vec(), label],
+            )
+            .map_err(Error::Unknown)?;
+        }
+
+        tx.commit().map_err(Error::Unknown)
+    }
+
+    /// Loads the labels of a given tower.
+    pub fn load_tower_labels(&self, tower_id: TowerId) -> BTreeSet<String> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT label FROM tower_labels WHERE tower_id = ?")
+            .unwrap();
+
+        stmt.query_map([tower_id.to_vec()], |row| row.get::<_, String>(0))
+            .unwrap()
+            .map(|label_res| label_res.unwrap())
+            .collect()
+    }
+
+    /// Stores the TLS pin of a given tower, replacing any previous one. [None] removes the pin.
+    pub fn store_tower_pin(
+        &self,
+        tower_id: TowerId,
+        tls_pin: Option<&TlsPin>,
+    ) -> Result<(), Error> {
+        match tls_pin {
+            Some(tls_pin) => self.store_data(
+                "INSERT INTO tower_pins (tower_id, fingerprint) VALUES (?1, ?2)
+                    ON CONFLICT (tower_id) DO UPDATE SET fingerprint = ?2",
+                params![tower_id.to_vec(), tls_pin.to_vec()],
+            ),
+            // Removing a pin that was never set is fine, so no row being deleted is not an error.
+            None => self
+                .connection
+                .execute(
+                    "DELETE FROM tower_pins WHERE tower_id = ?",
+                    params![tower_id.to_vec()],
+                )
+                .map(|_| ())
+                .map_err(Error::Unknown),
+        }
+    }
+
+    /// Loads the TLS pin of a given tower, if any.
+    pub fn load_tower_pin(&self, tower_id: TowerId) -> Option<TlsPin> {
+        self.connection
+            .query_row(
+                "SELECT fingerprint FROM tower_pins WHERE tower_id = ?",
+                [tower_id.to_vec()],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .ok()
+            .map(|raw_pin| TlsPin::from_slice(&raw_pin).unwrap())
+    }
+
+    /// Stores whether the subscription with a given tower is automatically renewed.
+    pub fn store_tower_auto_renew(&self, tower_id: TowerId, auto_renew: bool) -> Result<(), Error> {
+        let query = if auto_renew {
+            "INSERT OR IGNORE INTO auto_renew_towers (tower_id) VALUES (?)"
+        } else {
+            "DELETE FROM auto_renew_towers WHERE tower_id = ?"
+        };
+        self.connection
+            .execute(query, params![tower_id.to_vec()])
+            .map(|_| ())
+            .map_err(Error::Unknown)
+    }
+
+    /// Stores whether a given tower is always reached through the proxy.
+    pub fn store_tower_proxy(&self, tower_id: TowerId, use_proxy: bool) -> Result<(), Error> {
+        self.connection
+            .execute(
+                "UPDATE towers SET use_proxy = ?1 WHERE tower_id = ?2",
+                params![use_proxy, tower_id.to_vec()],
+            )
+            .map(|_| ())
+            .map_err(Error::Unknown)
+    }
+
+    /// Loads whether the subscription with a given tower is automatically renewed.
+    pub fn load_tower_auto_renew(&self, tower_id: TowerId) -> bool {
+        self.connection
+            .query_row(
+                "SELECT 1 FROM auto_renew_towers WHERE tower_id = ?",
+                [tower_id.to_vec()],
+                |_| Ok(()),
+            )
+            .is_ok()
+    }
+
+    /// Stores the appointment signature version agreed with a given tower.
+    pub fn store_tower_signature_version(
+        &self,
+        tower_id: TowerId,
+        version: SignatureVersion,
+    ) -> Result<(), Error> {
+        self.store_data(
+            "INSERT INTO tower_signature_versions (tower_id, version) VALUES (?1, ?2)
+                ON CONFLICT (tower_id) DO UPDATE SET version = ?2",
+            params![tower_id.to_vec(), u32::from(version)],
+        )
+    }
+
+    /// Loads the appointment signature version agreed with a given tower. Towers registered before versions were
+    /// negotiated use [SignatureVersion::V1].
+    pub fn load_tower_signature_version(&self, tower_id: TowerId) -> SignatureVersion {
+        self.connection
+            .query_row(
+                "SELECT version FROM tower_signature_versions WHERE tower_id = ?",
+                [tower_id.to_vec()],
+                |row| row.get::<_, u32>(0),
+            )
+            .ok()
+            .and_then(|version| SignatureVersion::try_from(version).ok())
+            .unwrap_or_default()
+    }
+
+    /// Stores the tower every pending appointment is also mirrored to, replacing any previous one. [None] removes it.
+    pub fn store_mirror_tower(&self, tower_id: Option<TowerId>) -> Result<(), Error> {
+        match tower_id {
+            Some(tower_id) => self.store_data(
+                "INSERT INTO mirror_tower (id, tower_id) VALUES (0, ?)
+                    ON CONFLICT (id) DO UPDATE SET tower_id = excluded.tower_id",
+                params![tower_id.to_vec()],
+            ),
+            // Removing a mirror that was never set is fine, so no row being deleted is not an error.
+            None => self
+                .connection
+                .execute("DELETE FROM mirror_tower", [])
+                .map(|_| ())
+                .map_err(Error::Unknown),
+        }
+    }
+
+    /// Loads the tower every pending appointment is also mirrored to, if any.
+    pub fn load_mirror_tower(&self) -> Option<TowerId> {
+        self.connection
+            .query_row("SELECT tower_id FROM mirror_tower", [], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .ok()
+            .map(|raw_tower_id| TowerId::from_slice(&raw_tower_id).unwrap())
+    }
+
+    /// Stores the order in which appointments are delivered to the towers, replacing any previous one. An empty order
+    /// removes it.
+    pub fn store_tower_order(&mut self, tower_ids: &[TowerId]) -> Result<(), Error> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        tx.execute("DELETE FROM tower_order", [])
+            .map_err(Error::Unknown)?;
+        for (position, tower_id) in tower_ids.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO tower_order (position, tower_id) VALUES (?1, ?2)",
+                params![position, tower_id.to_vec()],
+            )
+            .map_err(Error::Unknown)?;
+        }
+
+        tx.commit().map_err(Error::Unknown)
+    }
+
+    /// Loads the order in which appointments are delivered to the towers. Empty if no order has been set.
+    pub fn load_tower_order(&self) -> Vec<TowerId> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT tower_id FROM tower_order ORDER BY position")
+            .unwrap();
+
+        stmt.query_map([], |row| {
+            let raw_tower_id: Vec<u8> = row.get(0).unwrap();
+            Ok(TowerId::from_slice(&raw_tower_id).unwrap())
+        })
+        .unwrap()
+        .map(|tower_id_res| tower_id_res.unwrap())
+        .collect()
+    }
+
     /// Loads all tower records from the database.
     pub fn load_towers(&self) -> HashMap<TowerId, TowerSummary> {
         let mut towers = HashMap::new();
         let mut stmt = self
             .connection
-            .prepare("SELECT tw.tower_id, tw.net_addr, tw.available_slots, rr.subscription_start, rr.subscription_expiry 
+            .prepare("SELECT tw.tower_id, tw.net_addr, tw.available_slots, rr.subscription_start, rr.subscription_expiry, tw.use_proxy 
                         FROM towers AS tw 
                         JOIN registration_receipts AS rr 
                         JOIN (SELECT tower_id, MAX(subscription_expiry) AS max_se 
@@ -273,15 +809,23 @@ impl DBM {
             let available_slots: u32 = row.get(2).unwrap();
             let start: u32 = row.get(3).unwrap();
             let expiry: u32 = row.get(4).unwrap();
+            let use_proxy: bool = row.get(5).unwrap();
 
             let mut tower = TowerSummary::with_appointments(
                 net_addr,
                 available_slots,
                 start,
                 expiry,
-                self.load_appointment_locators(tower_id, AppointmentStatus::Pending),
-                self.load_appointment_locators(tower_id, AppointmentStatus::Invalid),
-            );
+                self.load_appointment_locators(tower_id, AppointmentStatus::Pending)
+                    .unwrap(),
+                self.load_appointment_locators(tower_id, AppointmentStatus::Invalid)
+                    .unwrap(),
+            )
+            .with_proxy(use_proxy)
+            .with_labels(self.load_tower_labels(tower_id))
+            .with_tls_pin(self.load_tower_pin(tower_id))
+            .with_auto_renew(self.load_tower_auto_renew(tower_id))
+            .with_signature_version(self.load_tower_signature_version(tower_id));
 
             if self.exists_misbehaving_proof(tower_id) {
                 tower.status = TowerStatus::Misbehaving;
@@ -299,6 +843,9 @@ impl DBM {
     }
 
     /// Stores an appointments receipt into the database representing an appointment accepted by a given tower.
+    ///
+    /// Existing receipts are replaced. An appointment may be re-sent if the client stops before persisting its receipt,
+    /// in which case the tower hands the same receipt again (without charging the user another slot).
     pub fn store_appointment_receipt(
         &mut self,
         tower_id: TowerId,
@@ -308,14 +855,17 @@ impl DBM {
     ) -> Result<(), SqliteError> {
         let tx = self.get_mut_connection().transaction().unwrap();
         tx.execute(
-            "INSERT INTO appointment_receipts (locator, tower_id, start_block, user_signature, tower_signature) 
-                VALUES (?1, ?2, ?3, ?4, ?5)",
+            "INSERT INTO appointment_receipts (locator, tower_id, start_block, user_signature, tower_signature, available_slots, delivered_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, CAST(strftime('%s', 'now') AS INT))
+                ON CONFLICT (locator, tower_id) DO UPDATE SET start_block = ?3, user_signature = ?4, tower_signature = ?5,
+                    available_slots = ?6, delivered_at = CAST(strftime('%s', 'now') AS INT)",
             params![
                 locator.to_vec(),
                 tower_id.to_vec(),
                 receipt.start_block(),
                 receipt.user_signature(),
-                receipt.signature()
+                receipt.signature(),
+                available_slots
             ],
         )?;
         tx.execute(
@@ -325,6 +875,41 @@ impl DBM {
         tx.commit()
     }
 
+    /// Stores a batch of appointment receipts into the database, removing the corresponding appointments from pending.
+    ///
+    /// Everything is done within a single database transaction, so either all the deliveries are recorded or none is.
+    /// As for [Self::store_appointment_receipt], existing receipts are replaced.
+    pub fn store_appointment_receipts(
+        &mut self,
+        tower_id: TowerId,
+        available_slots: u32,
+        receipts: &[(Locator, AppointmentReceipt)],
+    ) -> Result<(), SqliteError> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        for (locator, receipt) in receipts {
+            tx.execute(
+                "INSERT INTO appointment_receipts (locator, tower_id, start_block, user_signature, tower_signature, available_slots, delivered_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, CAST(strftime('%s', 'now') AS INT))
+                    ON CONFLICT (locator, tower_id) DO UPDATE SET start_block = ?3, user_signature = ?4, tower_signature = ?5,
+                        available_slots = ?6, delivered_at = CAST(strftime('%s', 'now') AS INT)",
+                params![
+                    locator.to_vec(),
+                    tower_id.to_vec(),
+                    receipt.start_block(),
+                    receipt.user_signature(),
+                    receipt.signature(),
+                    available_slots
+                ],
+            )?;
+            delete_pending_appointment(&tx, tower_id, *locator)?;
+        }
+        tx.execute(
+            "UPDATE towers SET available_slots=?1 WHERE tower_id=?2",
+            params![available_slots, tower_id.to_vec()],
+        )?;
+        tx.commit()
+    }
+
     /// Loads a given appointment receipt of a given tower from the database.
     pub fn load_appointment_receipt(
         &self,
@@ -350,19 +935,74 @@ impl DBM {
         .ok()
     }
 
-    /// Loads the appointment receipts associated to a given tower.
+    /// Stores a deletion receipt into the database, updating the tower available slots.
     ///
-    /// TODO: Currently this is only loading a summary of the receipt, if we need to really load all the information
-    /// for any reason this method may need to be renamed.
-    pub fn load_appointment_receipts(&self, tower_id: TowerId) -> HashMap<Locator, String> {
-        let mut receipts = HashMap::new();
-        let mut stmt = self
-            .connection
-            .prepare("SELECT locator, tower_signature FROM appointment_receipts WHERE tower_id = ?")
-            .unwrap();
-        let mut rows = stmt.query([tower_id.to_vec()]).unwrap();
-
-        while let Ok(Some(row)) = rows.next() {
+    /// If a receipt for the same appointment already exists (e.g. it was re-sent and deleted again) it is replaced.
+    pub fn store_deletion_receipt(
+        &mut self,
+        tower_id: TowerId,
+        locator: Locator,
+        available_slots: u32,
+        receipt: &DeletionReceipt,
+    ) -> Result<(), SqliteError> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        tx.execute(
+            "INSERT INTO deletion_receipts (locator, tower_id, deletion_block, user_signature, tower_signature)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+                ON CONFLICT (locator, tower_id) DO UPDATE SET deletion_block = ?3, user_signature = ?4, tower_signature = ?5",
+            params![
+                locator.to_vec(),
+                tower_id.to_vec(),
+                receipt.deletion_block(),
+                receipt.user_signature(),
+                receipt.signature()
+            ],
+        )?;
+        tx.execute(
+            "UPDATE towers SET available_slots=?1 WHERE tower_id=?2",
+            params![available_slots, tower_id.to_vec()],
+        )?;
+        tx.commit()
+    }
+
+    /// Loads the deletion receipt of a given appointment from the database.
+    pub fn load_deletion_receipt(
+        &self,
+        tower_id: TowerId,
+        locator: Locator,
+    ) -> Option<DeletionReceipt> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT deletion_block, user_signature, tower_signature FROM deletion_receipts WHERE tower_id = ?1 and locator = ?2")
+            .unwrap();
+
+        stmt.query_row(params![tower_id.to_vec(), locator.to_vec()], |row| {
+            let deletion_block = row.get::<_, u32>(0).unwrap();
+            let user_sig = row.get::<_, String>(1).unwrap();
+            let tower_sig = row.get::<_, String>(2).unwrap();
+
+            Ok(DeletionReceipt::with_signature(
+                user_sig,
+                deletion_block,
+                tower_sig,
+            ))
+        })
+        .ok()
+    }
+
+    /// Loads the appointment receipts associated to a given tower.
+    ///
+    /// TODO: Currently this is only loading a summary of the receipt, if we need to really load all the information
+    /// for any reason this method may need to be renamed.
+    pub fn load_appointment_receipts(&self, tower_id: TowerId) -> HashMap<Locator, String> {
+        let mut receipts = HashMap::new();
+        let mut stmt = self
+            .connection
+            .prepare("SELECT locator, tower_signature FROM appointment_receipts WHERE tower_id = ?")
+            .unwrap();
+        let mut rows = stmt.query([tower_id.to_vec()]).unwrap();
+
+        while let Ok(Some(row)) = rows.next() {
             let locator = Locator::from_slice(&row.get::<_, Vec<u8>>(0).unwrap()).unwrap();
             let signature = row.get::<_, String>(1).unwrap();
 
@@ -372,42 +1012,227 @@ impl DBM {
         receipts
     }
 
+    /// Loads all the registration receipts of a given user, alongside the towers they were issued by.
+    pub fn load_all_registration_receipts(
+        &self,
+        user_id: UserId,
+    ) -> Vec<(TowerId, RegistrationReceipt)> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT tower_id, available_slots, subscription_start, subscription_expiry, signature
+                    FROM registration_receipts",
+            )
+            .unwrap();
+
+        stmt.query_map([], |row| {
+            let raw_tower_id: Vec<u8> = row.get(0).unwrap();
+            let slots: u32 = row.get(1).unwrap();
+            let start: u32 = row.get(2).unwrap();
+            let expiry: u32 = row.get(3).unwrap();
+            let signature: String = row.get(4).unwrap();
+
+            Ok((
+                TowerId::from_slice(&raw_tower_id).unwrap(),
+                RegistrationReceipt::with_signature(user_id, slots, start, expiry, signature),
+            ))
+        })
+        .unwrap()
+        .map(|receipt_res| receipt_res.unwrap())
+        .collect()
+    }
+
+    /// Loads all the appointment receipts, alongside the towers and locators they were issued for.
+    pub fn load_all_appointment_receipts(&self) -> Vec<(TowerId, Locator, AppointmentReceipt)> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT tower_id, locator, start_block, user_signature, tower_signature
+                    FROM appointment_receipts",
+            )
+            .unwrap();
+
+        stmt.query_map([], |row| {
+            let raw_tower_id: Vec<u8> = row.get(0).unwrap();
+            let raw_locator: Vec<u8> = row.get(1).unwrap();
+            let start_block: u32 = row.get(2).unwrap();
+            let user_sig: String = row.get(3).unwrap();
+            let tower_sig: String = row.get(4).unwrap();
+
+            Ok((
+                TowerId::from_slice(&raw_tower_id).unwrap(),
+                Locator::from_slice(&raw_locator).unwrap(),
+                AppointmentReceipt::with_signature(user_sig, start_block, tower_sig),
+            ))
+        })
+        .unwrap()
+        .map(|receipt_res| receipt_res.unwrap())
+        .collect()
+    }
+
+    /// Loads the records of all the appointments delivered to the towers, sorted by delivery time.
+    ///
+    /// Appointments delivered in the same batch share the slot count, which is the one right after the batch.
+    pub fn load_delivery_records(&self) -> Vec<DeliveryRecord> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT tower_id, locator, available_slots, delivered_at, start_block, tower_signature
+                    FROM appointment_receipts ORDER BY delivered_at, tower_id, locator",
+            )
+            .unwrap();
+
+        stmt.query_map([], |row| {
+            let raw_tower_id: Vec<u8> = row.get(0).unwrap();
+            let raw_locator: Vec<u8> = row.get(1).unwrap();
+
+            Ok(DeliveryRecord {
+                tower_id: TowerId::from_slice(&raw_tower_id).unwrap(),
+                locator: Locator::from_slice(&raw_locator).unwrap(),
+                available_slots: row.get(2).unwrap(),
+                delivered_at: row.get(3).unwrap(),
+                start_block: row.get(4).unwrap(),
+                tower_signature: row.get(5).unwrap(),
+            })
+        })
+        .unwrap()
+        .map(|record_res| record_res.unwrap())
+        .collect()
+    }
+
     /// Loads a collection of locators from the database entry associated to a given tower.
     ///
-    /// The loaded locators can be loaded either from appointment_receipts, pending_appointments or invalid_appointments
-    ///  depending on `status`.
+    /// The loaded locators can be loaded either from appointment_receipts, pending_appointments, invalid_appointments or
+    /// expired_appointments depending on `status`. Fails if the database cannot be queried (e.g. because it is busy).
     pub fn load_appointment_locators(
         &self,
         tower_id: TowerId,
         status: AppointmentStatus,
-    ) -> HashSet<Locator> {
-        let status = match status {
-            AppointmentStatus::Accepted => "appointment_receipts",
-            AppointmentStatus::Pending => "pending_appointments",
-            AppointmentStatus::Invalid => "invalid_appointments",
-        };
+    ) -> Result<HashSet<Locator>, Error> {
+        let table = appointments_table(status);
         let mut appointments = HashSet::new();
         // TODO: Can this be prepared instead of formatted (using ?1 seems to fail)?
         let mut stmt = self
             .connection
-            .prepare(&format!("SELECT locator FROM {status} WHERE tower_id = ?"))
-            .unwrap();
+            .prepare(&format!("SELECT locator FROM {table} WHERE tower_id = ?"))
+            .map_err(Error::Unknown)?;
 
-        let mut rows = stmt.query(params![tower_id.to_vec()]).unwrap();
-        while let Ok(Some(inner_row)) = rows.next() {
+        let mut rows = stmt
+            .query(params![tower_id.to_vec()])
+            .map_err(Error::Unknown)?;
+        while let Some(inner_row) = rows.next().map_err(Error::Unknown)? {
             appointments
                 .insert(Locator::from_slice(&inner_row.get::<_, Vec<u8>>(0).unwrap()).unwrap());
         }
 
-        appointments
+        Ok(appointments)
+    }
+
+    /// Counts the appointments of a given tower with a given `status`, without loading them.
+    ///
+    /// Fails if the database cannot be queried (e.g. because it is busy).
+    pub fn count_appointments(
+        &self,
+        tower_id: TowerId,
+        status: AppointmentStatus,
+    ) -> Result<usize, Error> {
+        let table = appointments_table(status);
+        self.connection
+            .query_row(
+                &format!("SELECT COUNT(*) FROM {table} WHERE tower_id = ?"),
+                params![tower_id.to_vec()],
+                |row| row.get::<_, usize>(0),
+            )
+            .map_err(Error::Unknown)
+    }
+
+    /// Counts the pending appointments of every tower, without loading them. Towers with no pending appointments are
+    /// not included.
+    ///
+    /// Fails if the database cannot be queried (e.g. because it is busy).
+    pub fn count_pending_appointments(&self) -> Result<HashMap<TowerId, usize>, Error> {
+        let mut stmt = self
+            .connection
+            .prepare("SELECT tower_id, COUNT(*) FROM pending_appointments GROUP BY tower_id")
+            .map_err(Error::Unknown)?;
+
+        let mut counts = HashMap::new();
+        let mut rows = stmt.query([]).map_err(Error::Unknown)?;
+        while let Some(row) = rows.next().map_err(Error::Unknown)? {
+            let tower_id = TowerId::from_slice(&row.get::<_, Vec<u8>>(0).unwrap()).unwrap();
+            counts.insert(tower_id, row.get::<_, usize>(1).unwrap());
+        }
+
+        Ok(counts)
+    }
+
+    /// Loads the locators of the pending appointments of a given tower that were created more than `max_age` seconds ago.
+    ///
+    /// Fails if the database cannot be queried (e.g. because it is busy).
+    pub fn load_stale_pending_locators(
+        &self,
+        tower_id: TowerId,
+        max_age: u64,
+    ) -> Result<HashSet<Locator>, Error> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT locator FROM pending_appointments 
+                    WHERE tower_id = ?1 AND created_at < CAST(strftime('%s', 'now') AS INT) - ?2",
+            )
+            .map_err(Error::Unknown)?;
+
+        let mut locators = HashSet::new();
+        let mut rows = stmt
+            .query(params![tower_id.to_vec(), max_age])
+            .map_err(Error::Unknown)?;
+        while let Some(row) = rows.next().map_err(Error::Unknown)? {
+            locators.insert(Locator::from_slice(&row.get::<_, Vec<u8>>(0).unwrap()).unwrap());
+        }
+
+        Ok(locators)
+    }
+
+    /// Loads up to `limit` pending appointment locators of a given tower, sorted, starting after `after` (if set).
+    ///
+    /// Used to page through big backlogs without having to load them all at once.
+    pub fn load_pending_locators(
+        &self,
+        tower_id: TowerId,
+        after: Option<Locator>,
+        limit: usize,
+    ) -> Result<Vec<Locator>, Error> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT locator FROM pending_appointments WHERE tower_id = ?1 AND locator > ?2
+                    ORDER BY locator LIMIT ?3",
+            )
+            .map_err(Error::Unknown)?;
+
+        let mut locators = Vec::new();
+        let mut rows = stmt
+            .query(params![
+                tower_id.to_vec(),
+                after.map_or(Vec::new(), |locator| locator.to_vec()),
+                limit as i64
+            ])
+            .map_err(Error::Unknown)?;
+        while let Some(row) = rows.next().map_err(Error::Unknown)? {
+            locators.push(Locator::from_slice(&row.get::<_, Vec<u8>>(0).unwrap()).unwrap());
+        }
+
+        Ok(locators)
     }
 
     /// Loads an appointment from the database.
-    pub fn load_appointment(&self, locator: Locator) -> Option<Appointment> {
+    ///
+    /// Returns [None] if the appointment cannot be found, and an error if the database cannot be queried (e.g. because it is busy).
+    pub fn load_appointment(&self, locator: Locator) -> Result<Option<Appointment>, Error> {
         let mut stmt = self
             .connection
             .prepare("SELECT encrypted_blob, to_self_delay FROM appointments WHERE locator = ?")
-            .unwrap();
+            .map_err(Error::Unknown)?;
 
         stmt.query_row(params![locator.to_vec()], |row| {
             let encrypted_blob = row.get::<_, Vec<u8>>(0).unwrap();
@@ -415,7 +1240,57 @@ impl DBM {
 
             Ok(Appointment::new(locator, encrypted_blob, to_self_delay))
         })
-        .ok()
+        .optional()
+        .map_err(Error::Unknown)
+    }
+
+    /// Loads the appointments pending for any tower other than `tower_id` that `tower_id` knows nothing about, alongside
+    /// their delivery deadline.
+    ///
+    /// Appointments that have been deleted from any tower, or whose channel is restricted to some other towers, are not
+    /// loaded. The deadline is the latest one among the towers the appointment is pending for ([None] if any has none).
+    pub fn load_backfill_appointments(
+        &self,
+        tower_id: TowerId,
+    ) -> Result<Vec<(Appointment, Option<u64>)>, Error> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT a.locator, a.encrypted_blob, a.to_self_delay,
+                    CASE WHEN COUNT(p.deadline) < COUNT(*) THEN NULL ELSE MAX(p.deadline) END
+                FROM appointments a JOIN pending_appointments p ON a.locator = p.locator
+                WHERE p.tower_id != ?1
+                    AND a.locator NOT IN (SELECT locator FROM pending_appointments WHERE tower_id = ?1)
+                    AND a.locator NOT IN (SELECT locator FROM invalid_appointments WHERE tower_id = ?1)
+                    AND a.locator NOT IN (SELECT locator FROM expired_appointments WHERE tower_id = ?1)
+                    AND a.locator NOT IN (SELECT locator FROM appointment_receipts WHERE tower_id = ?1)
+                    AND a.locator NOT IN (SELECT locator FROM deletion_receipts)
+                    AND a.locator NOT IN (
+                        SELECT ac.locator FROM appointment_channels ac
+                            JOIN channel_towers ct ON ac.channel_id = ct.channel_id
+                        GROUP BY ac.locator
+                        HAVING SUM(ct.tower_id = ?1) = 0
+                    )
+                GROUP BY a.locator
+                ORDER BY a.locator",
+            )
+            .map_err(Error::Unknown)?;
+
+        let mut appointments = Vec::new();
+        let mut rows = stmt
+            .query(params![tower_id.to_vec()])
+            .map_err(Error::Unknown)?;
+        while let Some(row) = rows.next().map_err(Error::Unknown)? {
+            let locator = Locator::from_slice(&row.get::<_, Vec<u8>>(0).unwrap()).unwrap();
+            let encrypted_blob = row.get::<_, Vec<u8>>(1).unwrap();
+            let to_self_delay = row.get::<_, u32>(2).unwrap();
+            appointments.push((
+                Appointment::new(locator, encrypted_blob, to_self_delay),
+                row.get::<_, Option<u64>>(3).unwrap(),
+            ));
+        }
+
+        Ok(appointments)
     }
 
     /// Stores an appointment into the database.
@@ -445,6 +1320,16 @@ impl DBM {
         &mut self,
         tower_id: TowerId,
         appointment: &Appointment,
+    ) -> Result<(), SqliteError> {
+        self.store_pending_appointment_with_deadline(tower_id, appointment, None)
+    }
+
+    /// Stores a pending appointment into the database alongside an optional delivery deadline (Unix time, in seconds).
+    pub fn store_pending_appointment_with_deadline(
+        &mut self,
+        tower_id: TowerId,
+        appointment: &Appointment,
+        deadline: Option<u64>,
     ) -> Result<(), SqliteError> {
         let tx = self.get_mut_connection().transaction().unwrap();
 
@@ -452,13 +1337,44 @@ impl DBM {
         // ignore the error.
         Self::store_appointment(&tx, appointment).ok();
         tx.execute(
-            "INSERT INTO pending_appointments (locator, tower_id) VALUES (?1, ?2)",
-            params![appointment.locator.to_vec(), tower_id.to_vec(),],
+            "INSERT INTO pending_appointments (locator, tower_id, deadline, created_at)
+                VALUES (?1, ?2, ?3, CAST(strftime('%s', 'now') AS INT))",
+            params![appointment.locator.to_vec(), tower_id.to_vec(), deadline],
         )?;
 
         tx.commit()
     }
 
+    /// Loads the delivery deadline of a given pending appointment (if any).
+    pub fn load_pending_deadline(&self, tower_id: TowerId, locator: Locator) -> Option<u64> {
+        self.connection
+            .query_row(
+                "SELECT deadline FROM pending_appointments WHERE locator = ?1 AND tower_id = ?2",
+                params![locator.to_vec(), tower_id.to_vec()],
+                |row| row.get::<_, Option<u64>>(0),
+            )
+            .ok()
+            .flatten()
+    }
+
+    /// Moves a pending appointment to expired.
+    ///
+    /// An expired appointment is an appointment that could not be delivered to the tower before its deadline.
+    pub fn expire_pending_appointment(
+        &mut self,
+        tower_id: TowerId,
+        locator: Locator,
+    ) -> Result<(), SqliteError> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        // Add it first to expired and remove it from pending later so a cascade delete is not triggered
+        tx.execute(
+            "INSERT INTO expired_appointments (locator, tower_id) VALUES (?1, ?2)",
+            params![locator.to_vec(), tower_id.to_vec()],
+        )?;
+        delete_pending_appointment(&tx, tower_id, locator)?;
+        tx.commit()
+    }
+
     /// Removes a pending appointment from the database.
     ///
     /// If the pending appointment is the only instance of the appointment, the appointment will also be deleted form the appointments table.
@@ -467,41 +1383,8 @@ impl DBM {
         tower_id: TowerId,
         locator: Locator,
     ) -> Result<(), SqliteError> {
-        // We will delete data from pending_appointments or from appointments depending on whether the later has a single reference
-        // to it or not. If that's the case, deleting the entry from appointments will trigger a cascade deletion of the entry in pending.
-        // If there are other references, this will be deleted when removing the last one.
-        let count = {
-            let mut stmt = self
-                .connection
-                .prepare("SELECT COUNT(*) FROM pending_appointments WHERE locator=?")
-                .unwrap();
-            let pending = stmt
-                .query_row(params![locator.to_vec()], |row| row.get::<_, u32>(0))
-                .unwrap();
-
-            let mut stmt = self
-                .connection
-                .prepare("SELECT COUNT(*) FROM invalid_appointments WHERE locator=?")
-                .unwrap();
-            let invalid = stmt
-                .query_row(params![locator.to_vec()], |row| row.get::<_, u32>(0))
-                .unwrap_or(0);
-
-            pending + invalid
-        };
-
         let tx = self.get_mut_connection().transaction().unwrap();
-        if count == 1 {
-            tx.execute(
-                "DELETE FROM appointments WHERE locator=?",
-                params![locator.to_vec()],
-            )?;
-        } else {
-            tx.execute(
-                "DELETE FROM pending_appointments WHERE locator=?1 AND tower_id=?2",
-                params![locator.to_vec(), tower_id.to_vec()],
-            )?;
-        };
+        delete_pending_appointment(&tx, tower_id, locator)?;
         tx.commit()
     }
 
@@ -509,28 +1392,150 @@ impl DBM {
     ///
     /// An invalid appointment is an appointment that was rejected by the tower.
     /// Storing this data may allow us to see what was the issue and send the data later on.
+    /// The rejection is timestamped and counted on top of the ones of the pending appointment (if any), so the appointment
+    /// can be retried later on (see [Self::load_recoverable_invalid_appointments]).
     /// Internally calls [Self::store_appointment].
+    ///
+    /// Returns how many times the appointment has been rejected by the tower.
     pub fn store_invalid_appointment(
         &mut self,
         tower_id: TowerId,
         appointment: &Appointment,
-    ) -> Result<(), SqliteError> {
+    ) -> Result<u32, SqliteError> {
         let tx = self.get_mut_connection().transaction().unwrap();
 
         // If the appointment already exists (because it was added by another tower as either pending or invalid) we simply
         // ignore the error.
         Self::store_appointment(&tx, appointment).ok();
         tx.execute(
-            "INSERT INTO invalid_appointments (locator, tower_id) VALUES (?1, ?2)",
+            "INSERT INTO invalid_appointments (locator, tower_id, rejections, invalidated_at) 
+                VALUES (?1, ?2, 
+                    COALESCE((SELECT rejections FROM pending_appointments WHERE locator = ?1 AND tower_id = ?2), 0) + 1, 
+                    CAST(strftime('%s', 'now') AS INT))",
             params![appointment.locator.to_vec(), tower_id.to_vec(),],
         )?;
+        let rejections = tx.query_row(
+            "SELECT rejections FROM invalid_appointments WHERE locator = ?1 AND tower_id = ?2",
+            params![appointment.locator.to_vec(), tower_id.to_vec()],
+            |row| row.get(0),
+        )?;
+
+        tx.commit()?;
+        Ok(rejections)
+    }
+
+    /// Loads the invalid appointments that are due to be retried: the ones rejected at least `cooldown` seconds ago
+    /// that have not been rejected more than `max_retries` times.
+    pub fn load_recoverable_invalid_appointments(
+        &self,
+        cooldown: u64,
+        max_retries: u32,
+    ) -> Vec<(TowerId, Locator)> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT tower_id, locator FROM invalid_appointments 
+                WHERE invalidated_at <= CAST(strftime('%s', 'now') AS INT) - ?1 AND rejections <= ?2",
+            )
+            .unwrap();
+        let mut rows = stmt.query(params![cooldown, max_retries]).unwrap();
+
+        let mut appointments = Vec::new();
+        while let Ok(Some(row)) = rows.next() {
+            let tower_id = TowerId::from_slice(&row.get::<_, Vec<u8>>(0).unwrap()).unwrap();
+            let locator = Locator::from_slice(&row.get::<_, Vec<u8>>(1).unwrap()).unwrap();
+            appointments.push((tower_id, locator));
+        }
+
+        appointments
+    }
+
+    /// Moves a collection of invalid appointments back to pending, keeping track of how many times they were rejected.
+    pub fn recover_invalid_appointments(
+        &mut self,
+        appointments: &[(TowerId, Locator)],
+    ) -> Result<(), SqliteError> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        for (tower_id, locator) in appointments {
+            // Add it first to pending and remove it from invalid later so the appointment is not left unreferenced
+            tx.execute(
+                "INSERT INTO pending_appointments (locator, tower_id, rejections, created_at) 
+                    SELECT locator, tower_id, rejections, CAST(strftime('%s', 'now') AS INT) FROM invalid_appointments 
+                    WHERE locator = ?1 AND tower_id = ?2",
+                params![locator.to_vec(), tower_id.to_vec()],
+            )?;
+            tx.execute(
+                "DELETE FROM invalid_appointments WHERE locator = ?1 AND tower_id = ?2",
+                params![locator.to_vec(), tower_id.to_vec()],
+            )?;
+        }
 
         tx.commit()
     }
 
+    /// Stores an appointment permanently rejected by a tower into the dead-letter queue.
+    ///
+    /// The full appointment is stored alongside the rejection, so it is kept even if the appointment is removed from the
+    /// rest of the tables. Only the latest `limit` dead letters are kept, the oldest are dropped.
+    pub fn store_dead_letter(
+        &mut self,
+        dead_letter: &DeadLetter,
+        limit: usize,
+    ) -> Result<(), SqliteError> {
+        let tx = self.get_mut_connection().transaction().unwrap();
+        tx.execute(
+            "INSERT INTO dead_letters (tower_id, locator, encrypted_blob, to_self_delay, error_code, error, rejected_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                dead_letter.tower_id.to_vec(),
+                dead_letter.appointment.locator.to_vec(),
+                dead_letter.appointment.encrypted_blob,
+                dead_letter.appointment.to_self_delay,
+                dead_letter.error_code,
+                dead_letter.error,
+                dead_letter.rejected_at,
+            ],
+        )?;
+        tx.execute(
+            "DELETE FROM dead_letters WHERE id NOT IN (SELECT id FROM dead_letters ORDER BY id DESC LIMIT ?)",
+            params![limit as i64],
+        )?;
+        tx.commit()
+    }
+
+    /// Loads the dead letters of a given tower, oldest first.
+    pub fn load_dead_letters(&self, tower_id: TowerId) -> Vec<DeadLetter> {
+        let mut stmt = self
+            .connection
+            .prepare(
+                "SELECT locator, encrypted_blob, to_self_delay, error_code, error, rejected_at
+                    FROM dead_letters WHERE tower_id = ? ORDER BY id",
+            )
+            .unwrap();
+
+        stmt.query_map([tower_id.to_vec()], |row| {
+            let raw_locator: Vec<u8> = row.get(0).unwrap();
+
+            Ok(DeadLetter {
+                tower_id,
+                appointment: Appointment::new(
+                    Locator::from_slice(&raw_locator).unwrap(),
+                    row.get(1).unwrap(),
+                    row.get(2).unwrap(),
+                ),
+                error_code: row.get(3).unwrap(),
+                error: row.get(4).unwrap(),
+                rejected_at: row.get(5).unwrap(),
+            })
+        })
+        .unwrap()
+        .map(|dead_letter_res| dead_letter_res.unwrap())
+        .collect()
+    }
+
     /// Loads non finalized appointments from the database for a given tower based on a status flag.
     ///
-    /// This is meant to be used only for pending and invalid appointments, if the method is called for
+    /// This is meant to be used only for pending, invalid and expired appointments, if the method is called for
     /// accepted appointment, an empty collection will be returned.
     pub fn load_appointments(
         &self,
@@ -541,6 +1546,7 @@ impl DBM {
             AppointmentStatus::Accepted => return Vec::new(),
             AppointmentStatus::Pending => "pending_appointments",
             AppointmentStatus::Invalid => "invalid_appointments",
+            AppointmentStatus::Expired => "expired_appointments",
         };
 
         let mut appointments = Vec::new();
@@ -643,15 +1649,75 @@ impl DBM {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    use teos_common::cryptography::get_random_keypair;
-    use teos_common::test_utils::{
-        generate_random_appointment, get_random_registration_receipt, get_random_user_id,
-        get_registration_receipt_from_previous,
-    };
+/// Gets the table the appointments with a given status are stored in.
+fn appointments_table(status: AppointmentStatus) -> &'static str {
+    match status {
+        AppointmentStatus::Accepted => "appointment_receipts",
+        AppointmentStatus::Pending => "pending_appointments",
+        AppointmentStatus::Invalid => "invalid_appointments",
+        AppointmentStatus::Expired => "expired_appointments",
+    }
+}
+
+/// Removes a pending appointment using the given connection (which may be an ongoing transaction).
+///
+/// If the pending appointment is the only instance of the appointment, the appointment will also be deleted form the appointments table.
+fn delete_pending_appointment(
+    conn: &Connection,
+    tower_id: TowerId,
+    locator: Locator,
+) -> Result<(), SqliteError> {
+    // We will delete data from pending_appointments or from appointments depending on whether the later has a single reference
+    // to it or not. If that's the case, deleting the entry from appointments will trigger a cascade deletion of the entry in pending.
+    // If there are other references, this will be deleted when removing the last one.
+    let pending: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM pending_appointments WHERE locator=?",
+        params![locator.to_vec()],
+        |row| row.get(0),
+    )?;
+    let invalid: u32 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM invalid_appointments WHERE locator=?",
+            params![locator.to_vec()],
+            |row| row.get(0),
+        )
+        .unwrap_or(0);
+    let expired: u32 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM expired_appointments WHERE locator=?",
+            params![locator.to_vec()],
+            |row| row.get(0),
+        )
+        .unwrap_or(0);
+
+    if pending + invalid + expired == 1 {
+        conn.execute(
+            "DELETE FROM appointments WHERE locator=?",
+            params![locator.to_vec()],
+        )?;
+    } else {
+        conn.execute(
+            "DELETE FROM pending_appointments WHERE locator=?1 AND tower_id=?2",
+            params![locator.to_vec(), tower_id.to_vec()],
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use teos_common::cryptography::get_random_keypair;
+    use teos_common::test_utils::{
+        generate_random_appointment, get_random_appointment_receipt,
+        get_random_registration_receipt, get_random_user_id,
+        get_registration_receipt_from_previous,
+    };
 
     impl DBM {
         pub(crate) fn in_memory() -> Result<Self, SqliteError> {
@@ -671,6 +1737,17 @@ mod tests {
             stmt.exists(params![locator.to_vec()]).unwrap()
         }
 
+        /// Counts the transactions committed to the database from this point on.
+        pub(crate) fn commit_counter(&self) -> Arc<AtomicUsize> {
+            let counter = Arc::new(AtomicUsize::new(0));
+            let c = counter.clone();
+            self.connection.commit_hook(Some(move || {
+                c.fetch_add(1, Ordering::Relaxed);
+                false
+            }));
+            counter
+        }
+
         pub(crate) fn appointment_receipt_exists(
             &self,
             locator: Locator,
@@ -856,6 +1933,339 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_remove_tower_records() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let receipt = get_random_registration_receipt();
+        let tower_ids = [get_random_user_id(), get_random_user_id()];
+        for tower_id in tower_ids {
+            dbm.store_tower_record(tower_id, "talaia.watch", &receipt)
+                .unwrap();
+        }
+
+        // If any of the towers is unknown nothing is removed
+        assert!(matches!(
+            dbm.remove_tower_records(&[tower_ids[0], get_random_user_id()]),
+            Err(Error::NotFound)
+        ));
+        assert!(dbm.load_tower_record(tower_ids[0]).is_some());
+
+        dbm.remove_tower_records(&tower_ids).unwrap();
+        for tower_id in tower_ids {
+            assert!(dbm.load_tower_record(tower_id).is_none());
+        }
+    }
+
+    #[test]
+    fn test_store_load_channel_towers() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let channel_id = "4c9a3f5e0d4d4cbe6ff4c9bd4a6e08e2b1d0b4f1e2a7e5d3b5c6a0f8e9d7c1b2";
+
+        // Channels with no restriction have no towers associated
+        assert_eq!(dbm.load_channel_towers(channel_id), None);
+
+        let towers = HashSet::from_iter([get_random_user_id(), get_random_user_id()]);
+        dbm.store_channel_towers(channel_id, &towers).unwrap();
+        assert_eq!(dbm.load_channel_towers(channel_id), Some(towers));
+
+        // Storing a new set replaces the old one
+        let towers = HashSet::from_iter([get_random_user_id()]);
+        dbm.store_channel_towers(channel_id, &towers).unwrap();
+        assert_eq!(dbm.load_channel_towers(channel_id), Some(towers));
+
+        // Other channels are not affected
+        assert_eq!(dbm.load_channel_towers("another_channel"), None);
+
+        // And an empty set removes the restriction
+        dbm.store_channel_towers(channel_id, &HashSet::new())
+            .unwrap();
+        assert_eq!(dbm.load_channel_towers(channel_id), None);
+    }
+
+    #[test]
+    fn test_store_load_appointment_channels() {
+        let dbm = DBM::in_memory().unwrap();
+        let channel_id = "4c9a3f5e0d4d4cbe6ff4c9bd4a6e08e2b1d0b4f1e2a7e5d3b5c6a0f8e9d7c1b2";
+        assert!(dbm.load_channel_appointments(channel_id).is_empty());
+
+        // Appointments are returned sorted by commitment number, and only for the requested channel
+        let locators: Vec<Locator> = (0..3)
+            .map(|_| generate_random_appointment(None).locator)
+            .collect();
+        for (commit_num, locator) in locators.iter().enumerate().rev() {
+            dbm.store_appointment_channel(*locator, channel_id, commit_num as u32)
+                .unwrap();
+        }
+        dbm.store_appointment_channel(
+            generate_random_appointment(None).locator,
+            "another_channel",
+            0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            dbm.load_channel_appointments(channel_id),
+            locators.into_iter().zip(0..).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_load_appointment_statuses() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let appointment = generate_random_appointment(None);
+        assert!(dbm
+            .load_appointment_statuses(appointment.locator)
+            .is_empty());
+
+        let mut towers = Vec::new();
+        for _ in 0..3 {
+            let tower_id = get_random_user_id();
+            dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+            towers.push(tower_id);
+        }
+
+        dbm.store_appointment_receipt(
+            towers[0],
+            appointment.locator,
+            21,
+            &AppointmentReceipt::with_signature(
+                "user_signature".to_owned(),
+                42,
+                "tower_signature".to_owned(),
+            ),
+        )
+        .unwrap();
+        dbm.store_pending_appointment(towers[1], &appointment)
+            .unwrap();
+        dbm.store_invalid_appointment(towers[2], &appointment)
+            .unwrap();
+
+        assert_eq!(
+            dbm.load_appointment_statuses(appointment.locator),
+            HashMap::from([
+                (towers[0], AppointmentStatus::Accepted),
+                (towers[1], AppointmentStatus::Pending),
+                (towers[2], AppointmentStatus::Invalid),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_store_load_tower_labels() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        assert!(dbm.load_tower_labels(tower_id).is_empty());
+
+        let labels = BTreeSet::from(["backup".to_owned(), "tor".to_owned()]);
+        dbm.store_tower_labels(tower_id, &labels).unwrap();
+        assert_eq!(dbm.load_tower_labels(tower_id), labels);
+        assert_eq!(dbm.load_towers()[&tower_id].labels, labels);
+        assert_eq!(dbm.load_tower_record(tower_id).unwrap().labels, labels);
+
+        // Storing a new set replaces the old one
+        let labels = BTreeSet::from(["paid".to_owned()]);
+        dbm.store_tower_labels(tower_id, &labels).unwrap();
+        assert_eq!(dbm.load_tower_labels(tower_id), labels);
+
+        // Labels are removed alongside the tower
+        dbm.remove_tower_record(tower_id).unwrap();
+        assert!(dbm.load_tower_labels(tower_id).is_empty());
+
+        // Unknown towers cannot be tagged
+        assert!(dbm
+            .store_tower_labels(get_random_user_id(), &labels)
+            .is_err());
+    }
+
+    #[test]
+    fn test_store_load_tower_pin() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        assert!(dbm.load_tower_pin(tower_id).is_none());
+
+        let tls_pin = TlsPin::from_certificate(b"certificate");
+        dbm.store_tower_pin(tower_id, Some(&tls_pin)).unwrap();
+        assert_eq!(dbm.load_tower_pin(tower_id), Some(tls_pin));
+        assert_eq!(dbm.load_towers()[&tower_id].tls_pin, Some(tls_pin));
+        assert_eq!(
+            dbm.load_tower_record(tower_id).unwrap().tls_pin,
+            Some(tls_pin)
+        );
+
+        // Storing a new pin replaces the old one
+        let tls_pin = TlsPin::from_certificate(b"another certificate");
+        dbm.store_tower_pin(tower_id, Some(&tls_pin)).unwrap();
+        assert_eq!(dbm.load_tower_pin(tower_id), Some(tls_pin));
+
+        // The pin can be removed (even if there is none)
+        dbm.store_tower_pin(tower_id, None).unwrap();
+        assert!(dbm.load_tower_pin(tower_id).is_none());
+        dbm.store_tower_pin(tower_id, None).unwrap();
+
+        // Pins are removed alongside the tower
+        dbm.store_tower_pin(tower_id, Some(&tls_pin)).unwrap();
+        dbm.remove_tower_record(tower_id).unwrap();
+        assert!(dbm.load_tower_pin(tower_id).is_none());
+
+        // Unknown towers cannot be pinned
+        assert!(dbm
+            .store_tower_pin(get_random_user_id(), Some(&tls_pin))
+            .is_err());
+    }
+
+    #[test]
+    fn test_store_load_tower_auto_renew() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        assert!(!dbm.load_tower_auto_renew(tower_id));
+
+        // Enabling it twice is fine
+        for _ in 0..2 {
+            dbm.store_tower_auto_renew(tower_id, true).unwrap();
+            assert!(dbm.load_tower_auto_renew(tower_id));
+        }
+        assert!(dbm.load_towers()[&tower_id].auto_renew);
+        assert!(dbm.load_tower_record(tower_id).unwrap().auto_renew);
+
+        dbm.store_tower_auto_renew(tower_id, false).unwrap();
+        assert!(!dbm.load_tower_auto_renew(tower_id));
+
+        // The flag is removed alongside the tower
+        dbm.store_tower_auto_renew(tower_id, true).unwrap();
+        dbm.remove_tower_record(tower_id).unwrap();
+        assert!(!dbm.load_tower_auto_renew(tower_id));
+
+        // Unknown towers cannot be flagged
+        assert!(dbm
+            .store_tower_auto_renew(get_random_user_id(), true)
+            .is_err());
+    }
+
+    #[test]
+    fn test_store_load_tower_signature_version() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        // Towers with no stored version default to the first one
+        let tower_id = get_random_user_id();
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        assert_eq!(
+            dbm.load_tower_signature_version(tower_id),
+            SignatureVersion::V1
+        );
+
+        // Storing it again overwrites it
+        for version in [
+            SignatureVersion::V2,
+            SignatureVersion::V1,
+            SignatureVersion::V2,
+        ] {
+            dbm.store_tower_signature_version(tower_id, version)
+                .unwrap();
+            assert_eq!(dbm.load_tower_signature_version(tower_id), version);
+        }
+        assert_eq!(
+            dbm.load_towers()[&tower_id].signature_version,
+            SignatureVersion::V2
+        );
+        assert_eq!(
+            dbm.load_tower_record(tower_id).unwrap().signature_version,
+            SignatureVersion::V2
+        );
+
+        // The version is removed alongside the tower
+        dbm.remove_tower_record(tower_id).unwrap();
+        assert_eq!(
+            dbm.load_tower_signature_version(tower_id),
+            SignatureVersion::V1
+        );
+
+        // Unknown towers cannot get a version
+        assert!(dbm
+            .store_tower_signature_version(get_random_user_id(), SignatureVersion::V2)
+            .is_err());
+    }
+
+    #[test]
+    fn test_store_load_mirror_tower() {
+        let mut dbm = DBM::in_memory().unwrap();
+        assert!(dbm.load_mirror_tower().is_none());
+
+        let tower_id = get_random_user_id();
+        let another_tower_id = get_random_user_id();
+        for id in [tower_id, another_tower_id] {
+            dbm.store_tower_record(id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
+
+        dbm.store_mirror_tower(Some(tower_id)).unwrap();
+        assert_eq!(dbm.load_mirror_tower(), Some(tower_id));
+
+        // Storing a new mirror replaces the old one
+        dbm.store_mirror_tower(Some(another_tower_id)).unwrap();
+        assert_eq!(dbm.load_mirror_tower(), Some(another_tower_id));
+
+        // The mirror can be removed (even if there is none)
+        dbm.store_mirror_tower(None).unwrap();
+        assert!(dbm.load_mirror_tower().is_none());
+        dbm.store_mirror_tower(None).unwrap();
+
+        // The mirror is removed alongside the tower
+        dbm.store_mirror_tower(Some(tower_id)).unwrap();
+        dbm.remove_tower_record(tower_id).unwrap();
+        assert!(dbm.load_mirror_tower().is_none());
+
+        // Unknown towers cannot be mirrors
+        assert!(dbm.store_mirror_tower(Some(tower_id)).is_err());
+    }
+
+    #[test]
+    fn test_store_load_tower_order() {
+        let mut dbm = DBM::in_memory().unwrap();
+        assert!(dbm.load_tower_order().is_empty());
+
+        let tower_ids = (0..3).map(|_| get_random_user_id()).collect::<Vec<_>>();
+        for id in tower_ids.iter() {
+            dbm.store_tower_record(*id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
+
+        // The order is kept as given
+        dbm.store_tower_order(&tower_ids).unwrap();
+        assert_eq!(dbm.load_tower_order(), tower_ids);
+
+        // Storing a new order replaces the old one
+        let reversed = tower_ids.iter().rev().cloned().collect::<Vec<_>>();
+        dbm.store_tower_order(&reversed).unwrap();
+        assert_eq!(dbm.load_tower_order(), reversed);
+
+        // Towers are removed from the order alongside their records
+        dbm.remove_tower_record(tower_ids[1]).unwrap();
+        assert_eq!(dbm.load_tower_order(), vec![tower_ids[2], tower_ids[0]]);
+
+        // The order can be removed
+        dbm.store_tower_order(&[]).unwrap();
+        assert!(dbm.load_tower_order().is_empty());
+
+        // Unknown towers cannot be ordered, nor can the same tower be ordered twice
+        assert!(dbm.store_tower_order(&[tower_ids[1]]).is_err());
+        assert!(dbm
+            .store_tower_order(&[tower_ids[0], tower_ids[0]])
+            .is_err());
+        assert!(dbm.load_tower_order().is_empty());
+    }
+
     #[test]
     fn test_store_load_appointment_receipts() {
         let mut dbm = DBM::in_memory().unwrap();
@@ -874,33 +2284,273 @@ mod tests {
         dbm.store_tower_record(tower_id, net_addr, &receipt)
             .unwrap();
 
-        // Add some appointment receipts and check they match
-        let mut receipts = HashMap::new();
-        for _ in 0..5 {
-            let appointment = generate_random_appointment(None);
-            let user_signature = "user_signature";
-            let appointment_receipt = AppointmentReceipt::with_signature(
-                user_signature.to_owned(),
-                42,
-                "tower_signature".to_owned(),
+        // Add some appointment receipts and check they match
+        let mut receipts = HashMap::new();
+        for _ in 0..5 {
+            let appointment = generate_random_appointment(None);
+            let user_signature = "user_signature";
+            let appointment_receipt = AppointmentReceipt::with_signature(
+                user_signature.to_owned(),
+                42,
+                "tower_signature".to_owned(),
+            );
+
+            tower_summary.available_slots -= 1;
+
+            dbm.store_appointment_receipt(
+                tower_id,
+                appointment.locator,
+                tower_summary.available_slots,
+                &appointment_receipt,
+            )
+            .unwrap();
+            receipts.insert(
+                appointment.locator,
+                appointment_receipt.signature().unwrap(),
+            );
+        }
+
+        assert_eq!(dbm.load_appointment_receipts(tower_id), receipts);
+    }
+
+    #[test]
+    fn test_store_appointment_receipt_twice() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+
+        // Receiving a receipt for an appointment that already has one (e.g. because it was re-sent) is fine
+        let appointment = generate_random_appointment(None);
+        dbm.store_pending_appointment(tower_id, &appointment)
+            .unwrap();
+        let receipt = AppointmentReceipt::with_signature(
+            "user_signature".to_owned(),
+            42,
+            "tower_signature".to_owned(),
+        );
+        dbm.store_appointment_receipt(tower_id, appointment.locator, 20, &receipt)
+            .unwrap();
+        dbm.store_appointment_receipts(tower_id, 20, &[(appointment.locator, receipt.clone())])
+            .unwrap();
+        assert_eq!(
+            dbm.load_appointment_receipt(tower_id, appointment.locator),
+            Some(receipt)
+        );
+        assert!(dbm
+            .load_appointment_locators(tower_id, AppointmentStatus::Pending)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_load_all_receipts() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let user_id = get_random_user_id();
+        assert!(dbm.load_all_registration_receipts(user_id).is_empty());
+        assert!(dbm.load_all_appointment_receipts().is_empty());
+
+        // Receipts from every tower are loaded, including all the registration receipts of the same tower
+        let mut registration_receipts = Vec::new();
+        let mut appointment_receipts = Vec::new();
+        for _ in 0..3 {
+            let tower_id = get_random_user_id();
+            let receipt = get_random_registration_receipt();
+            dbm.store_tower_record(tower_id, "talaia.watch", &receipt)
+                .unwrap();
+            let renewal = get_registration_receipt_from_previous(&receipt);
+            dbm.store_tower_record(tower_id, "talaia.watch", &renewal)
+                .unwrap();
+            for r in [receipt, renewal] {
+                registration_receipts.push((
+                    tower_id,
+                    RegistrationReceipt::with_signature(
+                        user_id,
+                        r.available_slots(),
+                        r.subscription_start(),
+                        r.subscription_expiry(),
+                        r.signature().unwrap(),
+                    ),
+                ));
+            }
+
+            let locator = generate_random_appointment(None).locator;
+            let receipt = AppointmentReceipt::with_signature(
+                "user_signature".to_owned(),
+                42,
+                "tower_signature".to_owned(),
+            );
+            dbm.store_appointment_receipt(tower_id, locator, 21, &receipt)
+                .unwrap();
+            appointment_receipts.push((tower_id, locator, receipt));
+        }
+
+        let mut loaded = dbm.load_all_registration_receipts(user_id);
+        loaded.sort_by_key(|(tower_id, r)| (tower_id.to_vec(), r.subscription_expiry()));
+        registration_receipts
+            .sort_by_key(|(tower_id, r)| (tower_id.to_vec(), r.subscription_expiry()));
+        assert_eq!(loaded, registration_receipts);
+
+        let mut loaded = dbm.load_all_appointment_receipts();
+        loaded.sort_by_key(|(tower_id, _, _)| tower_id.to_vec());
+        appointment_receipts.sort_by_key(|(tower_id, _, _)| tower_id.to_vec());
+        assert_eq!(loaded, appointment_receipts);
+    }
+
+    #[test]
+    fn test_load_delivery_records() {
+        let mut dbm = DBM::in_memory().unwrap();
+        assert!(dbm.load_delivery_records().is_empty());
+
+        let tower_id = get_random_user_id();
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+
+        // Both single and batched deliveries are recorded alongside the slots left after them
+        let receipt = AppointmentReceipt::with_signature(
+            "user_signature".to_owned(),
+            42,
+            "tower_signature".to_owned(),
+        );
+        let single = generate_random_appointment(None).locator;
+        dbm.store_appointment_receipt(tower_id, single, 21, &receipt)
+            .unwrap();
+        let batch = [
+            (generate_random_appointment(None).locator, receipt.clone()),
+            (generate_random_appointment(None).locator, receipt.clone()),
+        ];
+        dbm.store_appointment_receipts(tower_id, 19, &batch)
+            .unwrap();
+
+        // Receipts stored before deliveries were tracked are loaded too, with no slots nor delivery time
+        let legacy = generate_random_appointment(None).locator;
+        dbm.connection
+            .execute(
+                "INSERT INTO appointment_receipts (locator, tower_id, start_block, user_signature, tower_signature)
+                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    legacy.to_vec(),
+                    tower_id.to_vec(),
+                    42,
+                    "user_signature",
+                    "tower_signature"
+                ],
+            )
+            .unwrap();
+
+        let records = dbm.load_delivery_records();
+        assert_eq!(records.len(), 4);
+        // Records with no delivery time go first
+        assert_eq!(records[0].locator, legacy);
+        assert_eq!(records[0].available_slots, None);
+        assert_eq!(records[0].delivered_at, None);
+
+        let now = crate::retrier::now();
+        for record in records[1..].iter() {
+            assert_eq!(record.tower_id, tower_id);
+            assert_eq!(record.start_block, 42);
+            assert_eq!(record.tower_signature, "tower_signature");
+            assert!(record.delivered_at.unwrap() <= now);
+            let expected_slots = if record.locator == single { 21 } else { 19 };
+            assert_eq!(record.available_slots, Some(expected_slots));
+        }
+    }
+
+    #[test]
+    fn test_store_appointment_receipts() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let (tower_sk, tower_pk) = get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let another_tower_id = get_random_user_id();
+
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        dbm.store_tower_record(
+            another_tower_id,
+            "talaia.watch",
+            &get_random_registration_receipt(),
+        )
+        .unwrap();
+
+        // Add some pending appointments. One of them shared with another tower
+        let mut receipts = Vec::new();
+        for _ in 0..5 {
+            let appointment = generate_random_appointment(None);
+            dbm.store_pending_appointment(tower_id, &appointment)
+                .unwrap();
+            receipts.push((
+                appointment.locator,
+                get_random_appointment_receipt(tower_sk),
+            ));
+        }
+        let shared_locator = receipts[0].0;
+        dbm.store_pending_appointment(
+            another_tower_id,
+            &dbm.load_appointment(shared_locator).unwrap().unwrap(),
+        )
+        .unwrap();
+
+        // Storing the batch is done in a single transaction
+        let commits = dbm.commit_counter();
+        dbm.store_appointment_receipts(tower_id, 42, &receipts)
+            .unwrap();
+        assert_eq!(commits.load(Ordering::Relaxed), 1);
+
+        // All receipts are stored and nothing is left pending for the tower
+        for (locator, receipt) in receipts.iter() {
+            assert_eq!(
+                dbm.load_appointment_receipt(tower_id, *locator).unwrap(),
+                *receipt
             );
+        }
+        assert!(dbm
+            .load_appointment_locators(tower_id, AppointmentStatus::Pending)
+            .unwrap()
+            .is_empty());
+        assert_eq!(dbm.load_tower_record(tower_id).unwrap().available_slots, 42);
 
-            tower_summary.available_slots -= 1;
+        // The shared appointment is still pending for the other tower
+        assert!(dbm
+            .load_appointment_locators(another_tower_id, AppointmentStatus::Pending)
+            .unwrap()
+            .contains(&shared_locator));
+        assert!(dbm.appointment_exists(shared_locator));
+        assert!(!dbm.appointment_exists(receipts[1].0));
 
-            dbm.store_appointment_receipt(
-                tower_id,
-                appointment.locator,
-                tower_summary.available_slots,
-                &appointment_receipt,
+        // If anything fails, nothing is stored (make the second insert fail)
+        let appointment = generate_random_appointment(None);
+        dbm.store_pending_appointment(tower_id, &appointment)
+            .unwrap();
+        let failing_locator = generate_random_appointment(None).locator;
+        dbm.connection
+            .execute(
+                &format!(
+                    "CREATE TEMP TRIGGER fail_insert BEFORE INSERT ON appointment_receipts
+                        WHEN NEW.locator = X'{}' BEGIN SELECT RAISE(ABORT, 'failed'); END",
+                    hex::encode(failing_locator.to_vec())
+                ),
+                [],
             )
             .unwrap();
-            receipts.insert(
+        let failing = [
+            (
                 appointment.locator,
-                appointment_receipt.signature().unwrap(),
-            );
-        }
-
-        assert_eq!(dbm.load_appointment_receipts(tower_id), receipts);
+                get_random_appointment_receipt(tower_sk),
+            ),
+            (failing_locator, get_random_appointment_receipt(tower_sk)),
+        ];
+        assert!(dbm
+            .store_appointment_receipts(tower_id, 21, &failing)
+            .is_err());
+        assert!(dbm
+            .load_appointment_receipt(tower_id, appointment.locator)
+            .is_none());
+        assert!(dbm
+            .load_appointment_locators(tower_id, AppointmentStatus::Pending)
+            .unwrap()
+            .contains(&appointment.locator));
+        assert_eq!(dbm.load_tower_record(tower_id).unwrap().available_slots, 42);
     }
 
     #[test]
@@ -952,6 +2602,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_store_load_deletion_receipt() {
+        let mut dbm = DBM::in_memory().unwrap();
+        let tower_id = get_random_user_id();
+        let locator = generate_random_appointment(None).locator;
+
+        // Nothing is returned for unknown receipts
+        assert!(dbm.load_deletion_receipt(tower_id, locator).is_none());
+
+        // Receipts cannot be stored for unknown towers
+        let deletion_receipt = DeletionReceipt::with_signature(
+            "user_signature".to_owned(),
+            42,
+            "tower_signature".to_owned(),
+        );
+        assert!(dbm
+            .store_deletion_receipt(tower_id, locator, 1, &deletion_receipt)
+            .is_err());
+
+        // Add the tower and try again
+        let receipt = get_random_registration_receipt();
+        dbm.store_tower_record(tower_id, "talaia.watch", &receipt)
+            .unwrap();
+        dbm.store_deletion_receipt(tower_id, locator, 1, &deletion_receipt)
+            .unwrap();
+
+        assert_eq!(
+            dbm.load_deletion_receipt(tower_id, locator).unwrap(),
+            deletion_receipt
+        );
+        assert_eq!(dbm.load_tower_record(tower_id).unwrap().available_slots, 1);
+    }
+
     #[test]
     fn test_load_appointment_locators() {
         // `load_appointment_locators` is used to load locators from either `appointment_receipts`, `pending_appointments` or `invalid_appointments`
@@ -1005,19 +2688,244 @@ mod tests {
 
         // Pull data from the db and check it matches the expected data
         assert_eq!(
-            dbm.load_appointment_locators(tower_id, AppointmentStatus::Accepted),
+            dbm.load_appointment_locators(tower_id, AppointmentStatus::Accepted)
+                .unwrap(),
             receipts
         );
         assert_eq!(
-            dbm.load_appointment_locators(tower_id, AppointmentStatus::Pending),
+            dbm.load_appointment_locators(tower_id, AppointmentStatus::Pending)
+                .unwrap(),
             pending_appointments
         );
         assert_eq!(
-            dbm.load_appointment_locators(tower_id, AppointmentStatus::Invalid),
+            dbm.load_appointment_locators(tower_id, AppointmentStatus::Invalid)
+                .unwrap(),
             invalid_appointments
         );
     }
 
+    #[test]
+    fn test_count_pending_appointments() {
+        let mut dbm = DBM::in_memory().unwrap();
+        assert!(dbm.count_pending_appointments().unwrap().is_empty());
+
+        // Towers with no pending appointments are not reported, and appointments with other statuses are not counted
+        let mut expected = HashMap::new();
+        for pending in [0, 1, 3] {
+            let tower_id = get_random_user_id();
+            dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+            for _ in 0..pending {
+                dbm.store_pending_appointment(tower_id, &generate_random_appointment(None))
+                    .unwrap();
+            }
+            dbm.store_invalid_appointment(tower_id, &generate_random_appointment(None))
+                .unwrap();
+            if pending > 0 {
+                expected.insert(tower_id, pending);
+            }
+        }
+
+        assert_eq!(dbm.count_pending_appointments().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_count_appointments() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        let other_tower_id = get_random_user_id();
+        for id in [tower_id, other_tower_id] {
+            dbm.store_tower_record(id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
+
+        // Nothing to count for a tower with no appointments
+        for status in [
+            AppointmentStatus::Accepted,
+            AppointmentStatus::Pending,
+            AppointmentStatus::Invalid,
+            AppointmentStatus::Expired,
+        ] {
+            assert_eq!(dbm.count_appointments(tower_id, status).unwrap(), 0);
+        }
+
+        // Store a different number of appointments with every status. The ones of other towers are not counted
+        for id in [tower_id, other_tower_id] {
+            for _ in 0..2 {
+                dbm.store_appointment_receipt(
+                    id,
+                    generate_random_appointment(None).locator,
+                    42,
+                    &get_random_appointment_receipt(get_random_keypair().0),
+                )
+                .unwrap();
+            }
+            for _ in 0..3 {
+                dbm.store_pending_appointment(id, &generate_random_appointment(None))
+                    .unwrap();
+            }
+            for _ in 0..4 {
+                dbm.store_invalid_appointment(id, &generate_random_appointment(None))
+                    .unwrap();
+            }
+            for _ in 0..5 {
+                let appointment = generate_random_appointment(None);
+                dbm.store_pending_appointment(id, &appointment).unwrap();
+                dbm.expire_pending_appointment(id, appointment.locator)
+                    .unwrap();
+            }
+        }
+
+        for status in [
+            AppointmentStatus::Accepted,
+            AppointmentStatus::Pending,
+            AppointmentStatus::Invalid,
+            AppointmentStatus::Expired,
+        ] {
+            assert_eq!(
+                dbm.count_appointments(tower_id, status).unwrap(),
+                dbm.load_appointment_locators(tower_id, status)
+                    .unwrap()
+                    .len()
+            );
+        }
+        assert_eq!(
+            dbm.count_appointments(tower_id, AppointmentStatus::Accepted)
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            dbm.count_appointments(tower_id, AppointmentStatus::Pending)
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            dbm.count_appointments(tower_id, AppointmentStatus::Invalid)
+                .unwrap(),
+            4
+        );
+        assert_eq!(
+            dbm.count_appointments(tower_id, AppointmentStatus::Expired)
+                .unwrap(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_load_pending_locators() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        let other_tower_id = get_random_user_id();
+        for id in [tower_id, other_tower_id] {
+            dbm.store_tower_record(id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
+
+        let mut pending_appointments = HashSet::new();
+        for _ in 0..10 {
+            let appointment = generate_random_appointment(None);
+            dbm.store_pending_appointment(tower_id, &appointment)
+                .unwrap();
+            pending_appointments.insert(appointment.locator);
+        }
+        // Appointments pending for other towers are not loaded
+        dbm.store_pending_appointment(other_tower_id, &generate_random_appointment(None))
+            .unwrap();
+
+        // Page through the backlog
+        let mut loaded = Vec::new();
+        let mut cursor = None;
+        for expected_len in [4, 4, 2, 0] {
+            let page = dbm.load_pending_locators(tower_id, cursor, 4).unwrap();
+            assert_eq!(page.len(), expected_len);
+            cursor = page.last().copied().or(cursor);
+            loaded.extend(page);
+        }
+
+        // Locators are loaded sorted and only once
+        assert!(loaded.windows(2).all(|w| w[0].to_vec() < w[1].to_vec()));
+        assert_eq!(HashSet::from_iter(loaded), pending_appointments);
+    }
+
+    #[test]
+    fn test_load_backfill_appointments() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        let other_tower_id = get_random_user_id();
+        let another_tower_id = get_random_user_id();
+        for id in [tower_id, other_tower_id, another_tower_id] {
+            dbm.store_tower_record(id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
+
+        // Appointments pending for other towers are loaded, with the latest deadline (if all have one)
+        let no_deadline = generate_random_appointment(None);
+        dbm.store_pending_appointment(other_tower_id, &no_deadline)
+            .unwrap();
+        dbm.store_pending_appointment_with_deadline(another_tower_id, &no_deadline, Some(42))
+            .unwrap();
+        let deadline = generate_random_appointment(None);
+        dbm.store_pending_appointment_with_deadline(other_tower_id, &deadline, Some(42))
+            .unwrap();
+        dbm.store_pending_appointment_with_deadline(another_tower_id, &deadline, Some(43))
+            .unwrap();
+
+        // Appointments of channels restricted to the tower are loaded too
+        let restricted = generate_random_appointment(None);
+        dbm.store_pending_appointment(other_tower_id, &restricted)
+            .unwrap();
+        dbm.store_appointment_channel(restricted.locator, "allowed", 0)
+            .unwrap();
+        dbm.store_channel_towers("allowed", &HashSet::from([tower_id, other_tower_id]))
+            .unwrap();
+
+        let mut expected = vec![
+            (no_deadline.clone(), None),
+            (deadline.clone(), Some(43)),
+            (restricted, None),
+        ];
+        expected.sort_by_key(|(a, _)| a.locator.to_vec());
+
+        // Appointments the tower already knows about, deleted ones and the ones of channels restricted to some other
+        // towers are not
+        let known = generate_random_appointment(None);
+        dbm.store_pending_appointment(other_tower_id, &known)
+            .unwrap();
+        dbm.store_invalid_appointment(tower_id, &known).unwrap();
+        let deleted = generate_random_appointment(None);
+        dbm.store_pending_appointment(other_tower_id, &deleted)
+            .unwrap();
+        dbm.store_deletion_receipt(
+            another_tower_id,
+            deleted.locator,
+            1,
+            &DeletionReceipt::with_signature(
+                "user_signature".to_owned(),
+                42,
+                "tower_signature".to_owned(),
+            ),
+        )
+        .unwrap();
+        let excluded = generate_random_appointment(None);
+        dbm.store_pending_appointment(other_tower_id, &excluded)
+            .unwrap();
+        dbm.store_appointment_channel(excluded.locator, "excluded", 0)
+            .unwrap();
+        dbm.store_channel_towers("excluded", &HashSet::from([other_tower_id]))
+            .unwrap();
+
+        assert_eq!(dbm.load_backfill_appointments(tower_id).unwrap(), expected);
+
+        // Neither are the ones already pending for the tower
+        assert!(dbm
+            .load_backfill_appointments(other_tower_id)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_store_load_appointment() {
         let mut dbm = DBM::in_memory().unwrap();
@@ -1027,7 +2935,7 @@ mod tests {
         DBM::store_appointment(&tx, &appointment).unwrap();
         tx.commit().unwrap();
 
-        let loaded_appointment = dbm.load_appointment(appointment.locator);
+        let loaded_appointment = dbm.load_appointment(appointment.locator).unwrap();
         assert_eq!(appointment, loaded_appointment.unwrap());
     }
 
@@ -1036,7 +2944,7 @@ mod tests {
         let dbm = DBM::in_memory().unwrap();
 
         let locator = generate_random_appointment(None).locator;
-        let loaded_appointment = dbm.load_appointment(locator);
+        let loaded_appointment = dbm.load_appointment(locator).unwrap();
         assert!(loaded_appointment.is_none());
     }
 
@@ -1129,6 +3037,7 @@ mod tests {
         // The appointment should be completely gone
         assert!(!dbm
             .load_appointment_locators(tower_id, AppointmentStatus::Pending)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(!dbm.appointment_exists(appointment.locator));
 
@@ -1149,9 +3058,11 @@ mod tests {
         // Check
         assert!(!dbm
             .load_appointment_locators(tower_id, AppointmentStatus::Pending)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(dbm
             .load_appointment_locators(another_tower_id, AppointmentStatus::Pending)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(dbm.appointment_exists(appointment.locator));
 
@@ -1163,13 +3074,82 @@ mod tests {
             .is_ok());
         assert!(!dbm
             .load_appointment_locators(another_tower_id, AppointmentStatus::Pending)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(dbm
             .load_appointment_locators(tower_id, AppointmentStatus::Invalid)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(dbm.appointment_exists(appointment.locator));
     }
 
+    #[test]
+    fn test_store_pending_appointment_with_deadline() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        let net_addr = "talaia.watch";
+        let receipt = get_random_registration_receipt();
+        dbm.store_tower_record(tower_id, net_addr, &receipt)
+            .unwrap();
+
+        // Appointments with no deadline have none
+        let appointment = generate_random_appointment(None);
+        dbm.store_pending_appointment(tower_id, &appointment)
+            .unwrap();
+        assert_eq!(
+            dbm.load_pending_deadline(tower_id, appointment.locator),
+            None
+        );
+
+        let appointment = generate_random_appointment(None);
+        dbm.store_pending_appointment_with_deadline(tower_id, &appointment, Some(42))
+            .unwrap();
+        assert_eq!(
+            dbm.load_pending_deadline(tower_id, appointment.locator),
+            Some(42)
+        );
+
+        // Non-pending appointments have no deadline either
+        assert_eq!(
+            dbm.load_pending_deadline(tower_id, generate_random_appointment(None).locator),
+            None
+        );
+    }
+
+    #[test]
+    fn test_expire_pending_appointment() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        let net_addr = "talaia.watch";
+        let receipt = get_random_registration_receipt();
+        dbm.store_tower_record(tower_id, net_addr, &receipt)
+            .unwrap();
+
+        let appointment = generate_random_appointment(None);
+        dbm.store_pending_appointment_with_deadline(tower_id, &appointment, Some(42))
+            .unwrap();
+        dbm.expire_pending_appointment(tower_id, appointment.locator)
+            .unwrap();
+
+        // The appointment is moved from pending to expired, but the appointment data is kept
+        let tower = dbm.load_tower_record(tower_id).unwrap();
+        assert!(tower.pending_appointments.is_empty());
+        assert_eq!(tower.expired_appointments, vec![appointment.clone()]);
+        assert!(dbm.appointment_exists(appointment.locator));
+        assert_eq!(
+            dbm.load_appointment_locators(tower_id, AppointmentStatus::Expired)
+                .unwrap(),
+            HashSet::from([appointment.locator])
+        );
+
+        // An expired appointment cannot be expired again, since it is not pending anymore
+        assert!(dbm
+            .expire_pending_appointment(tower_id, appointment.locator)
+            .is_err());
+    }
+
     #[test]
     fn test_store_invalid_appointment() {
         let mut dbm = DBM::in_memory().unwrap();
@@ -1233,6 +3213,106 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn test_recover_invalid_appointments() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        dbm.store_tower_record(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+
+        // Reject a pending appointment
+        let appointment = generate_random_appointment(None);
+        dbm.store_pending_appointment(tower_id, &appointment)
+            .unwrap();
+        assert_eq!(
+            dbm.store_invalid_appointment(tower_id, &appointment)
+                .unwrap(),
+            1
+        );
+        dbm.delete_pending_appointment(tower_id, appointment.locator)
+            .unwrap();
+
+        // The appointment is only recoverable once the cooldown is over, and if it has not been retried too many times
+        assert!(dbm
+            .load_recoverable_invalid_appointments(3600, 1)
+            .is_empty());
+        assert!(dbm.load_recoverable_invalid_appointments(0, 0).is_empty());
+        let recoverable = dbm.load_recoverable_invalid_appointments(0, 1);
+        assert_eq!(recoverable, vec![(tower_id, appointment.locator)]);
+
+        // Recovering it moves it back to pending
+        dbm.recover_invalid_appointments(&recoverable).unwrap();
+        assert!(dbm
+            .load_appointments(tower_id, AppointmentStatus::Invalid)
+            .is_empty());
+        assert_eq!(
+            dbm.load_appointments(tower_id, AppointmentStatus::Pending),
+            vec![appointment.clone()]
+        );
+
+        // Rejecting it again accounts for the previous rejection, so it is not recoverable anymore
+        assert_eq!(
+            dbm.store_invalid_appointment(tower_id, &appointment)
+                .unwrap(),
+            2
+        );
+        dbm.delete_pending_appointment(tower_id, appointment.locator)
+            .unwrap();
+        assert!(dbm.load_recoverable_invalid_appointments(0, 1).is_empty());
+        assert_eq!(
+            dbm.load_recoverable_invalid_appointments(0, 2),
+            vec![(tower_id, appointment.locator)]
+        );
+    }
+
+    #[test]
+    fn test_store_load_dead_letters() {
+        let mut dbm = DBM::in_memory().unwrap();
+
+        let tower_id = get_random_user_id();
+        let another_tower_id = get_random_user_id();
+        assert!(dbm.load_dead_letters(tower_id).is_empty());
+
+        // Dead letters are loaded per tower, oldest first
+        let mut dead_letters = Vec::new();
+        for i in 0..3 {
+            let dead_letter = DeadLetter {
+                tower_id,
+                appointment: generate_random_appointment(None),
+                error_code: 1,
+                error: format!("error_msg {i}"),
+                rejected_at: 1_700_000_000 + i,
+            };
+            dbm.store_dead_letter(&dead_letter, 10).unwrap();
+            dead_letters.push(dead_letter);
+        }
+        let another_dead_letter = DeadLetter {
+            tower_id: another_tower_id,
+            appointment: generate_random_appointment(None),
+            error_code: 2,
+            error: "another_error_msg".to_owned(),
+            rejected_at: 1_700_000_000,
+        };
+        dbm.store_dead_letter(&another_dead_letter, 10).unwrap();
+        assert_eq!(dbm.load_dead_letters(tower_id), dead_letters);
+        assert_eq!(
+            dbm.load_dead_letters(another_tower_id),
+            vec![another_dead_letter.clone()]
+        );
+
+        // Only the latest ones are kept once over the limit
+        dbm.store_dead_letter(&dead_letters[0], 3).unwrap();
+        assert_eq!(
+            dbm.load_dead_letters(tower_id),
+            vec![dead_letters[2].clone(), dead_letters[0].clone()]
+        );
+        assert_eq!(
+            dbm.load_dead_letters(another_tower_id),
+            vec![another_dead_letter]
+        );
+    }
+
     #[test]
     fn test_store_load_misbehaving_proof() {
         let mut dbm = DBM::in_memory().unwrap();
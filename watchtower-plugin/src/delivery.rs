@@ -0,0 +1,133 @@
+//! Logic related to exporting the appointments delivered to the towers, alongside their receipts, in CSV format.
+//!
+//! Receipts are already available as JSON through `getappointmentreceipt`, but auditing all the deliveries at once is
+//! way easier in a spreadsheet. Fields are escaped following RFC 4180, so the output can be loaded by any CSV reader.
+
+use teos_common::appointment::Locator;
+use teos_common::TowerId;
+
+/// The header of the exported CSV.
+pub const CSV_HEADER: &str =
+    "tower_id,locator,available_slots,delivered_at,start_block,tower_signature";
+
+/// Record of an appointment delivered to a tower.
+///
+/// The slots and the delivery time are unknown for deliveries recorded before they were tracked, hence optional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryRecord {
+    pub tower_id: TowerId,
+    pub locator: Locator,
+    /// Slots available in the tower subscription right after the delivery.
+    pub available_slots: Option<u32>,
+    /// Unix timestamp of the delivery.
+    pub delivered_at: Option<u64>,
+    pub start_block: u32,
+    pub tower_signature: String,
+}
+
+/// Escapes a CSV field, quoting it if it contains a separator, a quote or a line break. Inner quotes are doubled.
+fn escape_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+impl DeliveryRecord {
+    /// Renders the record as a CSV row (with no line terminator). Unknown values are left empty.
+    fn to_csv_row(&self) -> String {
+        [
+            self.tower_id.to_string(),
+            self.locator.to_string(),
+            self.available_slots
+                .map(|s| s.to_string())
+                .unwrap_or_default(),
+            self.delivered_at.map(|t| t.to_string()).unwrap_or_default(),
+            self.start_block.to_string(),
+            self.tower_signature.clone(),
+        ]
+        .iter()
+        .map(|field| escape_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+}
+
+/// Renders a collection of delivery records as CSV, header included. Rows are terminated by CRLF, as per RFC 4180.
+pub fn to_csv(records: &[DeliveryRecord]) -> String {
+    let mut csv = format!("{CSV_HEADER}\r\n");
+    for record in records {
+        csv.push_str(&record.to_csv_row());
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use teos_common::test_utils::{get_random_locator, get_random_user_id};
+
+    fn get_random_record() -> DeliveryRecord {
+        DeliveryRecord {
+            tower_id: get_random_user_id(),
+            locator: get_random_locator(),
+            available_slots: Some(21),
+            delivered_at: Some(1_700_000_000),
+            start_block: 42,
+            tower_signature: "signature".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_escape_field() {
+        assert_eq!(escape_field("plain"), "plain");
+        assert_eq!(escape_field(""), "");
+        assert_eq!(escape_field("a,b"), "\"a,b\"");
+        assert_eq!(escape_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(escape_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn test_to_csv() {
+        // With no deliveries only the header is exported
+        assert_eq!(to_csv(&[]), format!("{CSV_HEADER}\r\n"));
+
+        let mut records = Vec::new();
+        for _ in 0..5 {
+            records.push(get_random_record());
+        }
+        // Unknown values are left empty and fields that need it are escaped
+        let mut legacy = get_random_record();
+        legacy.available_slots = None;
+        legacy.delivered_at = None;
+        legacy.tower_signature = "odd,\"sig\"".to_owned();
+        records.push(legacy.clone());
+
+        let csv = to_csv(&records);
+        let rows: Vec<&str> = csv.strip_suffix("\r\n").unwrap().split("\r\n").collect();
+        assert_eq!(rows[0], CSV_HEADER);
+        assert_eq!(rows.len(), records.len() + 1);
+
+        for (row, record) in rows[1..].iter().zip(records.iter()) {
+            assert!(row.starts_with(&format!("{},{},", record.tower_id, record.locator)));
+        }
+        assert_eq!(
+            rows[1],
+            format!(
+                "{},{},21,1700000000,42,signature",
+                records[0].tower_id, records[0].locator
+            )
+        );
+        assert_eq!(
+            *rows.last().unwrap(),
+            format!(
+                "{},{},,,42,\"odd,\"\"sig\"\"\"",
+                legacy.tower_id, legacy.locator
+            )
+        );
+    }
+}
@@ -1,40 +1,62 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt;
+use std::str::FromStr;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use teos_common::appointment::{Appointment, Locator};
+use bitcoin::hashes::Hash;
+use bitcoin::OutPoint;
+
+use teos_common::appointment::{Appointment, Locator, SignatureVersion};
 use teos_common::net::NetAddr;
 use teos_common::receipts::AppointmentReceipt;
 use teos_common::TowerId;
 
+use crate::net::TlsPin;
+use crate::retrier::RetrierStatusInfo;
+use crate::wt_client::TowerError;
+
+#[cfg(any(test, feature = "blocking"))]
+pub mod blocking;
 pub mod constants;
 pub mod convert;
+pub mod coverage;
 pub mod dbm;
+pub mod delivery;
 pub mod net;
 pub mod retrier;
+pub mod selftest;
 mod ser;
+pub mod signer;
+pub mod state;
+pub mod submitter;
+pub mod tower_list;
+pub mod webhook;
 pub mod wt_client;
 
 #[cfg(test)]
 mod test_utils;
 
 /// The status the tower can be found at.
-#[derive(Clone, Serialize, PartialEq, Eq, Copy, Debug)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Copy, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum TowerStatus {
     Reachable,
     TemporaryUnreachable,
     Unreachable,
     SubscriptionError,
+    SubscriptionExhausted,
     Misbehaving,
 }
 
 /// The status an appointment can be at.
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum AppointmentStatus {
     Accepted,
     Pending,
     Invalid,
+    Expired,
 }
 
 /// Errors related to updating a subscription
@@ -61,12 +83,30 @@ impl fmt::Display for TowerStatus {
                 TowerStatus::TemporaryUnreachable => "temporary unreachable",
                 TowerStatus::Unreachable => "unreachable",
                 TowerStatus::SubscriptionError => "subscription error",
+                TowerStatus::SubscriptionExhausted => "subscription exhausted",
                 TowerStatus::Misbehaving => "misbehaving",
             }
         )
     }
 }
 
+impl FromStr for TowerStatus {
+    type Err = String;
+
+    /// Parses a [TowerStatus] from its serialized (snake_case) name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reachable" => Ok(TowerStatus::Reachable),
+            "temporary_unreachable" => Ok(TowerStatus::TemporaryUnreachable),
+            "unreachable" => Ok(TowerStatus::Unreachable),
+            "subscription_error" => Ok(TowerStatus::SubscriptionError),
+            "subscription_exhausted" => Ok(TowerStatus::SubscriptionExhausted),
+            "misbehaving" => Ok(TowerStatus::Misbehaving),
+            _ => Err(format!("Unknown tower status: {s}")),
+        }
+    }
+}
+
 impl TowerStatus {
     /// Whether the tower is reachable or not.
     pub fn is_reachable(&self) -> bool {
@@ -93,12 +133,34 @@ impl TowerStatus {
         *self == TowerStatus::SubscriptionError
     }
 
+    /// Whether the subscription with the tower has run out of slots.
+    pub fn is_subscription_exhausted(&self) -> bool {
+        *self == TowerStatus::SubscriptionExhausted
+    }
+
+    /// Whether the subscription needs to be renewed before sending more appointments to the tower.
+    pub fn needs_renewal(&self) -> bool {
+        self.is_subscription_error() || self.is_subscription_exhausted()
+    }
+
     /// Whether the tower can be manually retried
     pub fn is_retryable(&self) -> bool {
-        self.is_unreachable() || self.is_subscription_error()
+        self.is_unreachable() || self.needs_renewal()
     }
 }
 
+/// Computes the id of the channel funded by a given outpoint, as defined in BOLT2 (and used by CoreLN).
+///
+/// The id is the funding txid (in internal byte order) with its last two bytes XORed with the funding output index.
+pub fn channel_id_from_outpoint(outpoint: &OutPoint) -> String {
+    let mut channel_id = outpoint.txid.into_inner();
+    let [vout_hi, vout_lo] = (outpoint.vout as u16).to_be_bytes();
+    channel_id[30] ^= vout_hi;
+    channel_id[31] ^= vout_lo;
+
+    hex::encode(channel_id)
+}
+
 /// Summarized data associated with a given tower.
 #[derive(Clone, Serialize, Debug, PartialEq, Eq)]
 pub struct TowerSummary {
@@ -112,6 +174,18 @@ pub struct TowerSummary {
     pub pending_appointments: HashSet<Locator>,
     #[serde(serialize_with = "teos_common::ser::serialize_locators")]
     pub invalid_appointments: HashSet<Locator>,
+    /// Whether the tower must always be reached through the proxy (if any), regardless of the global proxy policy.
+    pub use_proxy: bool,
+    /// Free-form labels used to organize towers.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub labels: BTreeSet<String>,
+    /// Fingerprint of the TLS certificate the tower is expected to present, if pinned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_pin: Option<TlsPin>,
+    /// Whether the subscription is automatically renewed when it is about to expire.
+    pub auto_renew: bool,
+    /// Version of the serialization appointments sent to the tower are signed over.
+    pub signature_version: SignatureVersion,
 }
 
 impl TowerSummary {
@@ -122,14 +196,20 @@ impl TowerSummary {
         subscription_start: u32,
         subscription_expiry: u32,
     ) -> Self {
+        let net_addr = NetAddr::new(net_addr);
         Self {
-            net_addr: NetAddr::new(net_addr),
+            use_proxy: net_addr.is_onion(),
+            net_addr,
             available_slots,
             subscription_start,
             subscription_expiry,
             status: TowerStatus::Reachable,
             pending_appointments: HashSet::new(),
             invalid_appointments: HashSet::new(),
+            labels: BTreeSet::new(),
+            tls_pin: None,
+            auto_renew: false,
+            signature_version: SignatureVersion::default(),
         }
     }
 
@@ -142,14 +222,20 @@ impl TowerSummary {
         pending_appointments: HashSet<Locator>,
         invalid_appointments: HashSet<Locator>,
     ) -> Self {
+        let net_addr = NetAddr::new(net_addr);
         Self {
-            net_addr: NetAddr::new(net_addr),
+            use_proxy: net_addr.is_onion(),
+            net_addr,
             available_slots,
             subscription_start,
             subscription_expiry,
             status: TowerStatus::Reachable,
             pending_appointments,
             invalid_appointments,
+            labels: BTreeSet::new(),
+            tls_pin: None,
+            auto_renew: false,
+            signature_version: SignatureVersion::default(),
         }
     }
 
@@ -159,6 +245,36 @@ impl TowerSummary {
         self
     }
 
+    /// Creates a new instance using the existing info but updating the proxy override.
+    pub fn with_proxy(mut self, use_proxy: bool) -> Self {
+        self.use_proxy = use_proxy;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the labels.
+    pub fn with_labels(mut self, labels: BTreeSet<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the TLS pin.
+    pub fn with_tls_pin(mut self, tls_pin: Option<TlsPin>) -> Self {
+        self.tls_pin = tls_pin;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating whether the subscription is automatically renewed.
+    pub fn with_auto_renew(mut self, auto_renew: bool) -> Self {
+        self.auto_renew = auto_renew;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the appointment signature version.
+    pub fn with_signature_version(mut self, signature_version: SignatureVersion) -> Self {
+        self.signature_version = signature_version;
+        self
+    }
+
     /// Updates the main information about the summary while preserving the appointment maps.
     pub fn udpate(
         &mut self,
@@ -167,7 +283,12 @@ impl TowerSummary {
         subscription_start: u32,
         subscription_expiry: u32,
     ) {
-        self.net_addr = NetAddr::new(net_addr);
+        let net_addr = NetAddr::new(net_addr);
+        // The proxy flag is only inferred again if the address changes, so the one set by the user is kept otherwise
+        if net_addr != self.net_addr {
+            self.use_proxy = net_addr.is_onion();
+        }
+        self.net_addr = net_addr;
         self.available_slots = available_slots;
         self.subscription_start = subscription_start;
         self.subscription_expiry = subscription_expiry;
@@ -191,6 +312,11 @@ impl From<TowerInfo> for TowerSummary {
                 .collect(),
         )
         .with_status(info.status)
+        .with_proxy(info.use_proxy)
+        .with_labels(info.labels)
+        .with_tls_pin(info.tls_pin)
+        .with_auto_renew(info.auto_renew)
+        .with_signature_version(info.signature_version)
     }
 }
 
@@ -208,8 +334,32 @@ pub struct TowerInfo {
     pub pending_appointments: Vec<Appointment>,
     #[serde(serialize_with = "crate::ser::serialize_appointments")]
     pub invalid_appointments: Vec<Appointment>,
+    /// Appointments that could not be delivered before their deadline.
+    #[serde(
+        serialize_with = "crate::ser::serialize_appointments",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub expired_appointments: Vec<Appointment>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub misbehaving_proof: Option<MisbehaviorProof>,
+    /// The status of the retrier associated to the tower, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrier: Option<RetrierStatusInfo>,
+    /// The last error faced when sending data to the tower, if any. Only kept in memory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<TowerError>,
+    /// Whether the tower must always be reached through the proxy (if any), regardless of the global proxy policy.
+    pub use_proxy: bool,
+    /// Free-form labels used to organize towers.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub labels: BTreeSet<String>,
+    /// Fingerprint of the TLS certificate the tower is expected to present, if pinned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_pin: Option<TlsPin>,
+    /// Whether the subscription is automatically renewed when it is about to expire.
+    pub auto_renew: bool,
+    /// Version of the serialization appointments sent to the tower are signed over.
+    pub signature_version: SignatureVersion,
 }
 
 impl TowerInfo {
@@ -224,6 +374,7 @@ impl TowerInfo {
         invalid_appointments: Vec<Appointment>,
     ) -> Self {
         Self {
+            use_proxy: NetAddr::new(net_addr.clone()).is_onion(),
             net_addr,
             available_slots,
             subscription_start,
@@ -232,7 +383,14 @@ impl TowerInfo {
             appointments,
             pending_appointments,
             invalid_appointments,
+            expired_appointments: Vec::new(),
             misbehaving_proof: None,
+            retrier: None,
+            last_error: None,
+            labels: BTreeSet::new(),
+            tls_pin: None,
+            auto_renew: false,
+            signature_version: SignatureVersion::default(),
         }
     }
 
@@ -242,6 +400,48 @@ impl TowerInfo {
         self
     }
 
+    /// Creates a new instance using the existing info but updating the proxy override.
+    pub fn with_proxy(mut self, use_proxy: bool) -> Self {
+        self.use_proxy = use_proxy;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the labels.
+    pub fn with_labels(mut self, labels: BTreeSet<String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the TLS pin.
+    pub fn with_tls_pin(mut self, tls_pin: Option<TlsPin>) -> Self {
+        self.tls_pin = tls_pin;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating whether the subscription is automatically renewed.
+    pub fn with_auto_renew(mut self, auto_renew: bool) -> Self {
+        self.auto_renew = auto_renew;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the appointment signature version.
+    pub fn with_signature_version(mut self, signature_version: SignatureVersion) -> Self {
+        self.signature_version = signature_version;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the retrier status.
+    pub fn with_retrier(mut self, retrier: Option<RetrierStatusInfo>) -> Self {
+        self.retrier = retrier;
+        self
+    }
+
+    /// Creates a new instance using the existing info but updating the last error.
+    pub fn with_last_error(mut self, last_error: Option<TowerError>) -> Self {
+        self.last_error = last_error;
+        self
+    }
+
     /// Sets the misbehaving proof of a tower.
     pub fn set_misbehaving_proof(&mut self, proof: MisbehaviorProof) {
         self.misbehaving_proof = Some(proof);
@@ -272,15 +472,33 @@ impl MisbehaviorProof {
     }
 }
 
+/// An appointment permanently rejected by a tower, alongside the context of the rejection.
+///
+/// Dead letters are kept as a durable record to investigate systematic rejections, so they are not bound to the rest of
+/// the tower data (e.g. they outlive abandoning the tower).
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct DeadLetter {
+    pub tower_id: TowerId,
+    pub appointment: Appointment,
+    /// The error code returned by the tower (see `teos_common::errors`).
+    pub error_code: u8,
+    /// The error message returned by the tower.
+    pub error: String,
+    /// Unix timestamp of the rejection.
+    pub rejected_at: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::str::FromStr;
 
-    const STATUSES: [TowerStatus; 5] = [
+    const STATUSES: [TowerStatus; 6] = [
         TowerStatus::Reachable,
         TowerStatus::TemporaryUnreachable,
         TowerStatus::Unreachable,
         TowerStatus::SubscriptionError,
+        TowerStatus::SubscriptionExhausted,
         TowerStatus::Misbehaving,
     ];
 
@@ -288,6 +506,25 @@ mod tests {
     const SUBSCRIPTION_START: u32 = 100;
     const SUBSCRIPTION_EXPIRY: u32 = SUBSCRIPTION_START + 42;
 
+    #[test]
+    fn test_channel_id_from_outpoint() {
+        let txid = "7f2c6a8b3e1d4f5a9b0c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f70";
+
+        // The txid is used in internal byte order, so it is reversed with respect to how it is displayed
+        let outpoint = OutPoint::from_str(&format!("{txid}:0")).unwrap();
+        assert_eq!(
+            channel_id_from_outpoint(&outpoint),
+            "708f9e0d1c2b3a4f5e6d7c8b9a0f1e2d3c4b5a6f7e8d0c9b5a4f1d3e8b6a2c7f"
+        );
+
+        // The output index is XORed into the last two bytes
+        let outpoint = OutPoint::from_str(&format!("{txid}:258")).unwrap();
+        assert_eq!(
+            channel_id_from_outpoint(&outpoint),
+            "708f9e0d1c2b3a4f5e6d7c8b9a0f1e2d3c4b5a6f7e8d0c9b5a4f1d3e8b6a2d7d"
+        );
+    }
+
     mod tower_status {
         use super::*;
         use TowerStatus::*;
@@ -347,10 +584,35 @@ mod tests {
             }
         }
 
+        #[test]
+        fn test_is_subscription_exhausted() {
+            for status in STATUSES {
+                if status == SubscriptionExhausted {
+                    assert!(status.is_subscription_exhausted())
+                } else {
+                    assert!(!status.is_subscription_exhausted());
+                }
+            }
+        }
+
+        #[test]
+        fn test_needs_renewal() {
+            for status in STATUSES {
+                if status == SubscriptionError || status == SubscriptionExhausted {
+                    assert!(status.needs_renewal())
+                } else {
+                    assert!(!status.needs_renewal());
+                }
+            }
+        }
+
         #[test]
         fn test_is_retryable() {
             for status in STATUSES {
-                if status == Unreachable || status == SubscriptionError {
+                if status == Unreachable
+                    || status == SubscriptionError
+                    || status == SubscriptionExhausted
+                {
                     assert!(status.is_retryable())
                 } else {
                     assert!(!status.is_retryable());
@@ -392,6 +654,11 @@ mod tests {
                     status: TowerStatus::Reachable,
                     pending_appointments: HashSet::new(),
                     invalid_appointments: HashSet::new(),
+                    use_proxy: false,
+                    labels: BTreeSet::new(),
+                    tls_pin: None,
+                    auto_renew: false,
+                    signature_version: SignatureVersion::V1,
                 },
             );
         }
@@ -423,6 +690,11 @@ mod tests {
                     status: TowerStatus::Reachable,
                     pending_appointments,
                     invalid_appointments,
+                    use_proxy: false,
+                    labels: BTreeSet::new(),
+                    tls_pin: None,
+                    auto_renew: false,
+                    signature_version: SignatureVersion::V1,
                 },
             );
         }
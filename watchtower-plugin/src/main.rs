@@ -1,58 +1,82 @@
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::env;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use home::home_dir;
 use serde_json::json;
 use tokio::io::{stdin, stdout};
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::unbounded_channel;
 
+use bitcoin::secp256k1::PublicKey;
+use cln_plugin::messages::NotificationTopic;
 use cln_plugin::options::{ConfigOption, Value};
 use cln_plugin::{anyhow, Builder, Error, Plugin};
 
 use teos_common::appointment::{Appointment, Locator};
+use teos_common::cryptography;
 use teos_common::net::http::Endpoint;
 use teos_common::net::NetAddr;
 use teos_common::protos as common_msgs;
 use teos_common::TowerId;
-use teos_common::{cryptography, errors};
 
-use watchtower_plugin::convert::{CommitmentRevocation, GetAppointmentParams, RegisterParams};
+use watchtower_plugin::convert::{
+    block_height_from_params, net_addr_from_params, tower_id_from_params, AutoRenewParams,
+    ChannelCoverageParams, ChannelTowersParams, CommitmentRevocation, CoverageEstimateParams,
+    GetAppointmentParams, LabelFilterParams, ListTowersParams, RegisterParams, RetryTowerParams,
+    TowerLabelsParams, TowerOrderParams, TowerPinParams, TowerProxyParams,
+};
+use watchtower_plugin::coverage::{CoverageEstimate, KnownTerms};
+use watchtower_plugin::delivery;
 use watchtower_plugin::net::http::{
-    self, get_request, post_request, process_post_response, AddAppointmentError, ApiResponse,
-    RequestError,
+    self, get_request, post_request, process_post_response, ApiResponse, RequestError,
 };
-use watchtower_plugin::net::ProxyInfo;
+use watchtower_plugin::net::{ProxyInfo, TowerHeaders};
 use watchtower_plugin::retrier::RetryManager;
-use watchtower_plugin::wt_client::{RevocationData, WTClient};
+use watchtower_plugin::selftest;
+use watchtower_plugin::state::ExportedState;
+use watchtower_plugin::submitter;
+use watchtower_plugin::tower_list::TowerList;
+use watchtower_plugin::webhook::{Webhook, WEBHOOK_MAX_RETRY_TIME, WEBHOOK_QUEUE_CAPACITY};
+use watchtower_plugin::wt_client::{
+    InvalidRetryPolicy, StaleFeed, StatusFilter, SubmissionPolicy, WTClient,
+};
 use watchtower_plugin::{constants, TowerStatus};
 
 fn to_cln_error(e: RequestError) -> Error {
     let e = match e {
         RequestError::ConnectionError(e) => anyhow!(e),
+        RequestError::Timeout(e) => anyhow!(e),
         RequestError::DeserializeError(e) => anyhow!(e),
+        RequestError::Rejected(e) => anyhow!(e),
+        RequestError::PaymentRequired(e) => anyhow!(e),
+        RequestError::PinMismatch(e) => anyhow!(e),
         RequestError::Unexpected(e) => anyhow!(e),
     };
     log::info!("{e}");
     e
 }
 
-/// Sends fresh data to a retrier as long as is does not exist, or it does and its running.
-fn send_to_retrier(state: &MutexGuard<WTClient>, tower_id: TowerId, locator: Locator) {
-    if if let Some(status) = state.get_retrier_status(&tower_id) {
-        // A retrier in the retriers map can only be running or idle
-        status.is_running()
-    } else {
-        true
-    } {
-        state
-            .unreachable_towers
-            .send((tower_id, RevocationData::Fresh(locator)))
-            .unwrap();
-    } else {
-        log::debug!("Not sending data to idle retrier ({tower_id}, {locator})")
+/// Builds the address of a tower given its host and, optionally, its port (`watchtower-port` is used otherwise).
+fn build_net_addr(
+    plugin: &Plugin<Arc<Mutex<WTClient>>>,
+    mut host: String,
+    port: Option<u16>,
+) -> Result<NetAddr, Error> {
+    let port = match port {
+        Some(port) => port,
+        None => u16::try_from(plugin.option(constants::WT_PORT).unwrap().as_i64().unwrap())
+            .map_err(|_| anyhow!("{} out of range", constants::WT_PORT))?,
+    };
+
+    if !host.starts_with("http://") {
+        host = format!("http://{host}")
     }
+    Ok(NetAddr::new(format!("{host}:{port}")))
 }
 
 /// Registers the client to a given tower.
@@ -67,7 +91,7 @@ async fn register(
     v: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
     let params = RegisterParams::try_from(v).map_err(|x| anyhow!(x))?;
-    let mut host = params.host.unwrap_or_else(|| "localhost".to_owned());
+    let host = params.host.unwrap_or_else(|| "localhost".to_owned());
     let tower_id = params.tower_id;
     let user_id = plugin.state().lock().unwrap().user_id;
 
@@ -75,29 +99,30 @@ async fn register(
     // Otherwise the tower could just generate a subscription starting far in the future. For this we need to access lightning RPC
     // which is not available in the current version of `cln-plugin` (but already on master). Add it for the next release.
 
-    let port = params.port.unwrap_or(
-        u16::try_from(plugin.option(constants::WT_PORT).unwrap().as_i64().unwrap())
-            .map_err(|_| anyhow!("{} out of range", constants::WT_PORT))?,
-    );
+    let tower_net_addr = build_net_addr(&plugin, host, params.port)?;
 
-    let tower_net_addr = {
-        if !host.starts_with("http://") {
-            host = format!("http://{host}")
-        }
-        NetAddr::new(format!("{host}:{port}"))
+    let options = {
+        let state = plugin.state().lock().unwrap();
+        let use_proxy =
+            tower_net_addr.is_onion() || state.towers.get(&tower_id).is_some_and(|t| t.use_proxy);
+        state.resolve_request_options(tower_id, state.resolve_proxy(use_proxy).is_some())
     };
 
-    let proxy = plugin.state().lock().unwrap().proxy.clone();
-
-    let receipt = http::register(tower_id, user_id, &tower_net_addr, &proxy)
-        .await
-        .map_err(|e| {
-            let mut state = plugin.state().lock().unwrap();
-            if e.is_connection() && state.towers.contains_key(&tower_id) {
-                state.set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
-            }
-            to_cln_error(e)
-        })?;
+    let (receipt, signature_version) = http::register(
+        tower_id,
+        user_id,
+        &tower_net_addr,
+        params.payment_preimage.as_deref(),
+        &options,
+    )
+    .await
+    .map_err(|e| {
+        let mut state = plugin.state().lock().unwrap();
+        if e.is_connection() && state.towers.contains_key(&tower_id) {
+            state.set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
+        }
+        to_cln_error(e)
+    })?;
 
     if !receipt.verify(&tower_id) {
         return Err(anyhow!(
@@ -105,17 +130,17 @@ async fn register(
         ));
     }
 
-    plugin
-        .state()
-        .lock()
-        .unwrap()
-        .add_update_tower(tower_id, tower_net_addr.net_addr(), &receipt).map_err(|e| {
+    {
+        let mut state = plugin.state().lock().unwrap();
+        state.add_update_tower(tower_id, tower_net_addr.net_addr(), &receipt).map_err(|e| {
             if e.is_expiry() {
                 anyhow!("Registration receipt contains a subscription expiry that is not higher than the one we are currently registered for")
             } else {
                 anyhow!("Registration receipt does not contain more slots than the ones we are currently registered for")
             }
         })?;
+        state.set_tower_signature_version(tower_id, signature_version);
+    }
 
     log::info!(
         "Registration succeeded. Available slots: {}. Subscription period (block height range): ({}-{})",
@@ -134,7 +159,7 @@ async fn get_registration_receipt(
     plugin: Plugin<Arc<Mutex<WTClient>>>,
     v: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
-    let tower_id = TowerId::try_from(v).map_err(|x| anyhow!(x))?;
+    let tower_id = tower_id_from_params(v).map_err(|x| anyhow!(x))?;
     let state = plugin.state().lock().unwrap();
 
     if let Some(response) = state.get_registration_receipt(tower_id) {
@@ -151,39 +176,33 @@ async fn get_subscription_info(
     plugin: Plugin<Arc<Mutex<WTClient>>>,
     v: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
-    let tower_id = TowerId::try_from(v).map_err(|x| anyhow!(x))?;
+    let tower_id = tower_id_from_params(v).map_err(|x| anyhow!(x))?;
 
-    let (user_sk, tower_net_addr, proxy) = {
+    let (user_sk, tower_net_addr, options) = {
         let state = plugin.state().lock().unwrap();
         if let Some(info) = state.towers.get(&tower_id) {
-            Ok((state.user_sk, info.net_addr.clone(), state.proxy.clone()))
+            Ok((
+                state.user_sk,
+                info.net_addr.clone(),
+                state.get_request_options(tower_id),
+            ))
         } else {
             Err(anyhow!("Unknown tower id: {tower_id}"))
         }
     }?;
 
-    let signature = cryptography::sign("get subscription info".as_bytes(), &user_sk).unwrap();
-
-    let response: common_msgs::GetSubscriptionInfoResponse = process_post_response(
-        post_request(
-            &tower_net_addr,
-            Endpoint::GetSubscriptionInfo,
-            &common_msgs::GetSubscriptionInfoRequest { signature },
-            &proxy,
-        )
-        .await,
-    )
-    .await
-    .map_err(|e| {
-        if e.is_connection() {
-            plugin
-                .state()
-                .lock()
-                .unwrap()
-                .set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
-        }
-        to_cln_error(e)
-    })?;
+    let response = http::get_subscription_info(&tower_net_addr, &options, &user_sk)
+        .await
+        .map_err(|e| {
+            if e.is_connection() {
+                plugin
+                    .state()
+                    .lock()
+                    .unwrap()
+                    .set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
+            }
+            to_cln_error(e)
+        })?;
 
     Ok(json!(response))
 }
@@ -195,10 +214,14 @@ async fn get_appointment(
 ) -> Result<serde_json::Value, Error> {
     let params = GetAppointmentParams::try_from(v).map_err(|x| anyhow!(x))?;
 
-    let (user_sk, tower_net_addr, proxy) = {
+    let (user_sk, tower_net_addr, options) = {
         let state = plugin.state().lock().unwrap();
         if let Some(info) = state.towers.get(&params.tower_id) {
-            Ok((state.user_sk, info.net_addr.clone(), state.proxy.clone()))
+            Ok((
+                state.user_sk,
+                info.net_addr.clone(),
+                state.get_request_options(params.tower_id),
+            ))
         } else {
             Err(anyhow!("Unknown tower id: {}", params.tower_id))
         }
@@ -218,7 +241,7 @@ async fn get_appointment(
                 locator: params.locator.to_vec(),
                 signature,
             },
-            &proxy,
+            &options,
         )
         .await,
     )
@@ -237,6 +260,72 @@ async fn get_appointment(
     Ok(json!(response))
 }
 
+/// Asks a given tower to delete an appointment, storing the deletion receipt.
+async fn delete_appointment(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = GetAppointmentParams::try_from(v).map_err(|x| anyhow!(x))?;
+
+    let (user_sk, tower_net_addr, options, start_block) = {
+        let state = plugin.state().lock().unwrap();
+        if let Some(info) = state.towers.get(&params.tower_id) {
+            // The request is bound to the block the tower accepted the appointment at, as found in its receipt
+            let start_block = state
+                .get_appointment_receipt(params.tower_id, params.locator)
+                .map(|receipt| receipt.start_block())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Cannot find an appointment receipt for {} from {}",
+                        params.locator,
+                        params.tower_id
+                    )
+                })?;
+            Ok((
+                state.user_sk,
+                info.net_addr.clone(),
+                state.get_request_options(params.tower_id),
+                start_block,
+            ))
+        } else {
+            Err(anyhow!("Unknown tower id: {}", params.tower_id))
+        }
+    }?;
+
+    let (available_slots, receipt) = http::delete_appointment(
+        params.tower_id,
+        &tower_net_addr,
+        &options,
+        params.locator,
+        start_block,
+        &user_sk,
+    )
+    .await
+    .map_err(|e| {
+        if e.is_connection() {
+            plugin
+                .state()
+                .lock()
+                .unwrap()
+                .set_tower_status(params.tower_id, TowerStatus::TemporaryUnreachable);
+        }
+        to_cln_error(e)
+    })?;
+
+    plugin.state().lock().unwrap().add_deletion_receipt(
+        params.tower_id,
+        params.locator,
+        available_slots,
+        &receipt,
+    );
+
+    Ok(json!({
+        "locator": params.locator,
+        "available_slots": available_slots,
+        "receipt": receipt
+    }))
+}
+
 /// Gets an appointment receipt from the client given a tower_id and a locator (if it exists).
 ///
 /// This is pulled from the database
@@ -265,12 +354,328 @@ async fn get_appointment_receipt(
 
 /// Lists all the registered towers.
 ///
-/// The given information comes from memory, so it is summarized.
+/// The given information comes from memory, so it is summarized. If `verbose` is set, the status of every tower is
+/// reported instead, alongside the one of its retrier and its number of pending appointments.
 async fn list_towers(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = ListTowersParams::try_from(v).map_err(|e| anyhow!(e))?;
+    let state = plugin.state().lock().unwrap();
+    let tower_ids = params.label.map(|label| state.get_towers_by_label(&label));
+    let is_listed =
+        |tower_id: &TowerId| tower_ids.as_ref().is_none_or(|ids| ids.contains(tower_id));
+
+    if params.verbose {
+        Ok(json!(state
+            .all_tower_statuses()
+            .into_iter()
+            .filter(|(tower_id, _)| is_listed(tower_id))
+            .collect::<HashMap<_, _>>()))
+    } else {
+        Ok(json!(state
+            .towers
+            .iter()
+            .filter(|(tower_id, _)| is_listed(tower_id))
+            .collect::<HashMap<_, _>>()))
+    }
+}
+
+/// Sets the labels of a given tower, replacing any previous ones. Removes all labels if none is given.
+async fn set_tower_labels(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = TowerLabelsParams::try_from(v).map_err(|e| anyhow!(e))?;
+    plugin
+        .state()
+        .lock()
+        .unwrap()
+        .set_tower_labels(params.tower_id, params.labels.clone())
+        .map_err(|_| anyhow!("Unknown tower {}", params.tower_id))?;
+
+    Ok(json!({
+        "tower_id": params.tower_id,
+        "labels": params.labels,
+    }))
+}
+
+/// Pins the TLS certificate of a given tower, or removes the pin if no fingerprint is given.
+async fn set_tower_pin(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = TowerPinParams::try_from(v).map_err(|e| anyhow!(e))?;
+    plugin
+        .state()
+        .lock()
+        .unwrap()
+        .set_tower_pin(params.tower_id, params.tls_pin)
+        .map_err(|e| anyhow!(e))?;
+
+    Ok(json!({
+        "tower_id": params.tower_id,
+        "tls_pin": params.tls_pin,
+    }))
+}
+
+/// Sets whether a given tower is always reached through the proxy.
+async fn set_tower_proxy(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = TowerProxyParams::try_from(v).map_err(|e| anyhow!(e))?;
+    plugin
+        .state()
+        .lock()
+        .unwrap()
+        .set_tower_proxy(params.tower_id, params.use_proxy)
+        .map_err(|e| anyhow!(e))?;
+
+    Ok(json!({
+        "tower_id": params.tower_id,
+        "use_proxy": params.use_proxy,
+    }))
+}
+
+/// Sets whether the subscription with a given tower is automatically renewed before it expires.
+async fn set_auto_renew(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = AutoRenewParams::try_from(v).map_err(|e| anyhow!(e))?;
+    plugin
+        .state()
+        .lock()
+        .unwrap()
+        .set_tower_auto_renew(params.tower_id, params.enabled)
+        .map_err(|e| anyhow!(e))?;
+
+    Ok(json!({
+        "tower_id": params.tower_id,
+        "auto_renew": params.enabled,
+    }))
+}
+
+/// Sets the tower every pending appointment is also sent to, or removes it if no tower is given.
+async fn set_mirror(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let tower_id = match &v {
+        serde_json::Value::Null => None,
+        serde_json::Value::Array(a) if a.is_empty() => None,
+        serde_json::Value::Object(m) if m.is_empty() => None,
+        _ => Some(tower_id_from_params(v).map_err(|e| anyhow!(e))?),
+    };
+    plugin
+        .state()
+        .lock()
+        .unwrap()
+        .set_mirror(tower_id)
+        .map_err(|_| anyhow!("Unknown tower. Towers need to be registered first"))?;
+
+    Ok(json!({ "mirror": tower_id }))
+}
+
+/// Sets the order in which appointments are delivered to the towers, or removes it if no tower is given.
+async fn set_tower_order(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = TowerOrderParams::try_from(v).map_err(|e| anyhow!(e))?;
+    plugin
+        .state()
+        .lock()
+        .unwrap()
+        .set_tower_order(params.tower_ids.clone())
+        .map_err(|_| anyhow!("Unknown tower(s). Towers need to be registered first"))?;
+
+    Ok(json!({ "tower_order": params.tower_ids }))
+}
+
+/// Registers with all the towers in a signed tower list file.
+///
+/// Towers that cannot be registered with are reported, but do not prevent registering with the rest.
+async fn import_list(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let file = match &v {
+        serde_json::Value::Array(a) if a.len() == 1 => a[0].as_str(),
+        serde_json::Value::Object(m) if m.len() == 1 => m.get("file").and_then(|f| f.as_str()),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("Unexpected request format. Expected: file. Received: '{v}'"))?;
+
+    let maintainer = plugin
+        .option(constants::WT_LIST_MAINTAINER)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_owned();
+    if maintainer.is_empty() {
+        return Err(anyhow!(
+            "No list maintainer set. Set {} to import tower lists",
+            constants::WT_LIST_MAINTAINER
+        ));
+    }
+    let maintainer = PublicKey::from_str(&maintainer)
+        .map_err(|_| anyhow!("Invalid {}", constants::WT_LIST_MAINTAINER))?;
+
+    let data = tokio::fs::read_to_string(file)
+        .await
+        .map_err(|e| anyhow!("Cannot read {file}. Error: {e}"))?;
+    let list = TowerList::from_json(&data).map_err(|e| anyhow!(e))?;
+    let report = WTClient::import_tower_list(plugin.state(), &list, &maintainer)
+        .await
+        .map_err(|e| anyhow!(e))?;
+
+    Ok(json!(report))
+}
+
+/// Exports the client state, so it can be compared against the one of a different plugin instance.
+async fn export_state(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    _: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    Ok(json!(plugin.state().lock().unwrap().export_state()))
+}
+
+/// Compares the client state against the one exported by a different plugin instance, stored in a file.
+async fn diff_state(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let file = match &v {
+        serde_json::Value::Array(a) if a.len() == 1 => a[0].as_str(),
+        serde_json::Value::Object(m) if m.len() == 1 => m.get("file").and_then(|f| f.as_str()),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("Unexpected request format. Expected: file. Received: '{v}'"))?;
+
+    let data = tokio::fs::read_to_string(file)
+        .await
+        .map_err(|e| anyhow!("Cannot read {file}. Error: {e}"))?;
+    let other = ExportedState::from_json(&data).map_err(|e| anyhow!(e))?;
+
+    Ok(json!(plugin.state().lock().unwrap().diff_against(&other)))
+}
+
+/// Exports all the appointments delivered to the towers, alongside their receipts, to a CSV file.
+async fn export_csv(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let file = match &v {
+        serde_json::Value::Array(a) if a.len() == 1 => a[0].as_str(),
+        serde_json::Value::Object(m) if m.len() == 1 => m.get("file").and_then(|f| f.as_str()),
+        _ => None,
+    }
+    .ok_or_else(|| anyhow!("Unexpected request format. Expected: file. Received: '{v}'"))?;
+
+    let records = plugin.state().lock().unwrap().delivery_records();
+    tokio::fs::write(file, delivery::to_csv(&records))
+        .await
+        .map_err(|e| anyhow!("Cannot write {file}. Error: {e}"))?;
+
+    Ok(json!({"file": file, "appointments": records.len()}))
+}
+
+/// Reports the version of the plugin alongside the features it supports.
+async fn capabilities(
+    _: Plugin<Arc<Mutex<WTClient>>>,
+    _: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    Ok(json!(WTClient::capabilities()))
+}
+
+/// Lists the appointments permanently rejected by a given tower, alongside the context of the rejection.
+async fn dead_letters(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    // Dead letters outlive the tower data, so the tower does not need to be known anymore
+    let tower_id = tower_id_from_params(v).map_err(|e| anyhow!(e))?;
+
+    Ok(json!({ "dead_letters": plugin.state().lock().unwrap().dead_letters(tower_id) }))
+}
+
+/// Runs the self-test against a given tower, exercising the full delivery path. Only available on test networks.
+async fn self_test(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    selftest::check_network(&plugin.configuration().network).map_err(|e| anyhow!(e))?;
+
+    let params = RegisterParams::try_from(v).map_err(|x| anyhow!(x))?;
+    let host = params.host.unwrap_or_else(|| "localhost".to_owned());
+    let tower_id = params.tower_id;
+    let tower_net_addr = build_net_addr(&plugin, host, params.port)?;
+
+    let (user_sk, options) = {
+        let state = plugin.state().lock().unwrap();
+        let use_proxy = state.resolve_proxy(tower_net_addr.is_onion()).is_some();
+        (
+            state.user_sk,
+            state.resolve_request_options(tower_id, use_proxy),
+        )
+    };
+
+    let report = selftest::run(
+        tower_id,
+        &tower_net_addr,
+        &user_sk,
+        params.payment_preimage.as_deref(),
+        &options,
+    )
+    .await;
+    log::info!(
+        "Self-test against {tower_id} {}",
+        if report.passed { "passed" } else { "failed" }
+    );
+
+    Ok(json!(report))
+}
+
+/// Gets liveness information about the plugin, namely the last time the retry manager loop ran.
+async fn get_metrics(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    _: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    Ok(json!(plugin.state().lock().unwrap().metrics()))
+}
+
+/// Verifies all the stored receipts against the ids of the towers they belong to.
+async fn verify_receipts(
     plugin: Plugin<Arc<Mutex<WTClient>>>,
     _: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
-    Ok(json!(plugin.state().lock().unwrap().towers))
+    Ok(json!(plugin.state().lock().unwrap().verify_all_receipts()))
+}
+
+async fn get_health(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let block_height = block_height_from_params(v).map_err(|e| anyhow!(e))?;
+    let state = plugin.state().lock().unwrap();
+    let last_tick = state.get_retry_manager_tick();
+    let elapsed = last_tick.map(|tick| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .saturating_sub(tick)
+    });
+
+    Ok(json!({
+        "retry_manager": {
+            "last_tick": last_tick,
+            "secs_since_last_tick": elapsed,
+        },
+        "towers_needing_attention": state.towers_needing_attention(block_height),
+    }))
 }
 
 /// Gets information about a given tower.
@@ -281,14 +686,17 @@ async fn get_tower_info(
     v: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
     let state = plugin.state().lock().unwrap();
-    let tower_id = TowerId::try_from(v).map_err(|e| anyhow!(e))?;
+    let tower_id = tower_id_from_params(v).map_err(|e| anyhow!(e))?;
 
     if let Some(tower_info) = state.load_tower_info(tower_id) {
         // Notice we need to check the status in memory since we cannot distinguish between unreachable and temporary unreachable
         // by just checking the data in the database.
-        Ok(json!(
-            tower_info.with_status(state.get_tower_status(&tower_id).unwrap())
-        ))
+        Ok(json!(tower_info
+            .with_status(state.get_tower_status(&tower_id).unwrap())
+            .with_retrier(state.get_retrier_status(&tower_id).map(Into::into))
+            .with_last_error(
+                state.last_errors.get(&tower_id).cloned()
+            )))
     } else {
         Err(anyhow!(
             "Cannot find {tower_id} within the known towers. Have you registered?",
@@ -296,13 +704,27 @@ async fn get_tower_info(
     }
 }
 
+async fn diagnose(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let tower_id = tower_id_from_params(v).map_err(|e| anyhow!(e))?;
+
+    match plugin.state().lock().unwrap().diagnose_tower(tower_id) {
+        Some(diagnosis) => Ok(json!(diagnosis)),
+        None => Err(anyhow!(
+            "Cannot find {tower_id} within the known towers. Have you registered?",
+        )),
+    }
+}
+
 async fn ping(
     plugin: Plugin<Arc<Mutex<WTClient>>>,
     v: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
-    let (tower_net_addr, proxy) = {
+    let (tower_net_addr, options) = {
         // Check if the tower_id is known to the plugin
-        let tower_id = TowerId::try_from(v).map_err(|e| anyhow!(e))?;
+        let tower_id = tower_id_from_params(v).map_err(|e| anyhow!(e))?;
         let state = plugin.state().lock().unwrap();
         (
             state
@@ -311,10 +733,10 @@ async fn ping(
                 .ok_or(anyhow!("Unknown tower_id"))?
                 .net_addr
                 .clone(),
-            state.proxy.clone(),
+            state.get_request_options(tower_id),
         )
     };
-    let response = get_request(&tower_net_addr, Endpoint::Ping, &proxy)
+    let response = get_request(&tower_net_addr, Endpoint::Ping, &options)
         .await
         .map_err(to_cln_error)?;
 
@@ -328,62 +750,195 @@ async fn ping(
     }
 }
 
-/// Triggers a manual retry of a tower, tries to send all pending appointments to it.
+/// Fetches the terms advertised by a tower (e.g. its price per slot), so they can be checked before registering with it.
+async fn get_tower_terms(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let (host, port) = net_addr_from_params(v).map_err(|e| anyhow!(e))?;
+    let tower_net_addr = build_net_addr(&plugin, host, port)?;
+    let options = plugin
+        .state()
+        .lock()
+        .unwrap()
+        .resolve_discovery_options(&tower_net_addr);
+
+    match http::get_tower_info(&tower_net_addr, &options)
+        .await
+        .map_err(to_cln_error)?
+    {
+        Some(terms) => Ok(json!(terms)),
+        None => Ok(json!("not advertised")),
+    }
+}
+
+/// Estimates the slots and subscription renewals (and their cost) covering a new channel with the current towers
+/// would require, given the number of commitment updates it is expected to go through (and optionally its lifetime).
 ///
-/// Only works if the tower is unreachable or there's been a subscription error (and the tower is not already being retried).
-async fn retry_tower(
+/// The terms of the towers are fetched from them, towers that cannot be reached are reported with unknown cost.
+async fn coverage_estimate(
     plugin: Plugin<Arc<Mutex<WTClient>>>,
     v: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
-    let tower_id = TowerId::try_from(v).map_err(|e| anyhow!(e))?;
-    let state = plugin.state().lock().unwrap();
-    if let Some(tower_status) = state.get_tower_status(&tower_id) {
-        if let Some(retrier_status) = state.retriers.get(&tower_id) {
-            if retrier_status.is_idle() {
-                // We don't send any associated data in this case given the idle retrier already has it all.
-                state
-                    .unreachable_towers
-                    .send((tower_id, RevocationData::None))
-                    .map_err(|e| anyhow!(e))?;
-            } else {
-                // Status can only be running or idle for data in the retriers map.
-                return Err(anyhow!("{tower_id} is already being retried"));
+    let params = CoverageEstimateParams::try_from(v).map_err(|e| anyhow!(e))?;
+    let (towers, block_height) = {
+        let state = plugin.state().lock().unwrap();
+        let towers: Vec<_> = state
+            .towers
+            .iter()
+            .map(|(id, tower)| (*id, tower.clone(), state.get_request_options(*id)))
+            .collect();
+        (towers, state.block_height)
+    };
+
+    let mut known_terms = Vec::with_capacity(towers.len());
+    for (tower_id, tower, options) in towers.iter() {
+        let terms = match http::get_tower_info(&tower.net_addr, options).await {
+            Ok(Some(terms)) => KnownTerms::Advertised(terms),
+            Ok(None) => KnownTerms::NotAdvertised,
+            Err(e) => {
+                log::debug!("Cannot get the terms of {tower_id}. Error: {e:?}");
+                KnownTerms::Unknown
             }
-        } else if tower_status.is_retryable() {
-            // We do send associated data here given there is no retrier associated to this tower.
-            state
-                .unreachable_towers
-                .send((
-                    tower_id,
-                    RevocationData::Stale(
-                        state
-                            .towers
-                            .get(&tower_id)
-                            .unwrap()
-                            .pending_appointments
-                            .iter()
-                            .cloned()
-                            .collect(),
-                    ),
-                ))
-                .map_err(|e| anyhow!(e))?;
-        } else {
-            return Err(anyhow!(
-                "Tower status must be unreachable or have a subscription issue to manually retry",
-            ));
-        }
+        };
+        known_terms.push((*tower_id, tower, terms));
+    }
+
+    Ok(json!(CoverageEstimate::new(
+        known_terms,
+        params.updates,
+        params.lifetime,
+        block_height
+    )))
+}
+
+/// Triggers a manual retry of a tower, tries to send all pending appointments to it.
+///
+/// If `aggressive` is set, the tower is retried using the aggressive backoff instead of the automatic one.
+/// Only works if the tower is unreachable or there's been a subscription error (and the tower is not already being retried).
+async fn retry_tower(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = RetryTowerParams::try_from(v).map_err(|e| anyhow!(e))?;
+    let tower_id = params.tower_id;
+    let mut state = plugin.state().lock().unwrap();
+    if params.aggressive {
+        state.retry_tower_aggressively(tower_id)
     } else {
-        return Err(anyhow!("Unknown tower {tower_id}"));
+        state.retry_tower(tower_id)
     }
+    .map_err(|e| anyhow!(e))?;
     Ok(json!(format!("Retrying {tower_id}")))
 }
 
+/// Retries all the towers that can be retried (optionally only the ones tagged with a given label).
+///
+/// Towers that do not need to be retried (e.g. reachable ones or ones already being retried) are skipped.
+async fn retry_all(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = LabelFilterParams::try_from(v).map_err(|e| anyhow!(e))?;
+    let state = plugin.state().lock().unwrap();
+
+    let tower_ids: Vec<TowerId> = match params.label {
+        Some(label) => state.get_towers_by_label(&label).into_iter().collect(),
+        None => state.towers.keys().cloned().collect(),
+    };
+    let retrying: Vec<TowerId> = tower_ids
+        .into_iter()
+        .filter(|tower_id| match state.retry_tower(*tower_id) {
+            Ok(()) => true,
+            Err(e) => {
+                log::debug!("Not retrying {tower_id}. {e}");
+                false
+            }
+        })
+        .collect();
+
+    Ok(json!({ "retrying": retrying }))
+}
+
+/// Syncs the local state of a tower with the data the tower is holding for the user.
+///
+/// Pending appointments missing in the tower are re-sent, while accepted appointments missing in the tower and
+/// appointments unknown to the client are reported.
+async fn resync_tower(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let tower_id = tower_id_from_params(v).map_err(|e| anyhow!(e))?;
+
+    let (user_sk, tower_net_addr, options) = {
+        let state = plugin.state().lock().unwrap();
+        if let Some(info) = state.towers.get(&tower_id) {
+            Ok((
+                state.user_sk,
+                info.net_addr.clone(),
+                state.get_request_options(tower_id),
+            ))
+        } else {
+            Err(anyhow!("Unknown tower id: {tower_id}"))
+        }
+    }?;
+
+    let response = http::get_subscription_info(&tower_net_addr, &options, &user_sk)
+        .await
+        .map_err(|e| {
+            if e.is_connection() {
+                plugin
+                    .state()
+                    .lock()
+                    .unwrap()
+                    .set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
+            }
+            to_cln_error(e)
+        })?;
+
+    let tower_locators = response
+        .locators
+        .iter()
+        .map(|l| Locator::from_slice(l))
+        .collect::<Result<HashSet<_>, _>>()
+        .map_err(|_| anyhow!("{tower_id} replied with a malformed locator"))?;
+
+    let report = plugin
+        .state()
+        .lock()
+        .unwrap()
+        .resync_tower(tower_id, tower_locators)
+        .map_err(|_| anyhow!("Unknown tower {tower_id}"))?;
+
+    Ok(json!(report))
+}
+
+/// Sends the appointments still being delivered to the rest of towers to a given tower.
+async fn backfill_tower(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let tower_id = tower_id_from_params(v).map_err(|e| anyhow!(e))?;
+    let mut locators = plugin
+        .state()
+        .lock()
+        .unwrap()
+        .backfill_tower(tower_id)
+        .map_err(|_| anyhow!("Unknown tower {tower_id}"))?
+        .into_iter()
+        .map(|locator| locator.to_string())
+        .collect::<Vec<_>>();
+    locators.sort();
+
+    Ok(json!({ "backfilled": locators }))
+}
+
 /// Forgets about a tower wiping out all local data associated to it.
 async fn abandon_tower(
     plugin: Plugin<Arc<Mutex<WTClient>>>,
     v: serde_json::Value,
 ) -> Result<serde_json::Value, Error> {
-    let tower_id = TowerId::try_from(v).map_err(|e| anyhow!(e))?;
+    let tower_id = tower_id_from_params(v).map_err(|e| anyhow!(e))?;
     let mut state = plugin.state().lock().unwrap();
     if state.towers.contains_key(&tower_id) {
         state.remove_tower(tower_id).unwrap();
@@ -393,9 +948,95 @@ async fn abandon_tower(
     }
 }
 
-/// Sends an appointment to all registered towers for every new commitment transaction.
+/// Abandons all towers that failed to be retried or have been unreachable for too long.
+async fn prune_failed(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    _: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let min_age = u64::try_from(
+        plugin
+            .option(constants::WT_PRUNE_AGE)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .map_err(|_| anyhow!("{} out of range", constants::WT_PRUNE_AGE))?;
+
+    let removed = plugin
+        .state()
+        .lock()
+        .unwrap()
+        .remove_failed_towers(min_age)
+        .map_err(|e| anyhow!("Cannot remove failed towers. Error: {e:?}"))?;
+    Ok(json!({ "removed": removed }))
+}
+
+/// Restricts the towers the appointments of a given channel are sent to, or lifts the restriction if no tower is given.
+async fn set_channel_towers(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = ChannelTowersParams::try_from(v).map_err(|e| anyhow!(e))?;
+    let mut state = plugin.state().lock().unwrap();
+
+    state
+        .set_channel_towers(&params.channel_id, params.tower_ids)
+        .map_err(|_| anyhow!("Unknown tower(s). Towers need to be registered first"))?;
+    let tower_ids = state.get_channel_towers(&params.channel_id);
+    Ok(json!({
+        "channel_id": params.channel_id,
+        "towers": tower_ids,
+    }))
+}
+
+/// Shows the appointments of the channel funded by a given outpoint, alongside their status for every tower.
+async fn channel_coverage(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let params = ChannelCoverageParams::try_from(v).map_err(|e| anyhow!(e))?;
+    let state = plugin.state().lock().unwrap();
+
+    Ok(json!({
+        "outpoint": params.outpoint.to_string(),
+        "appointments": state.appointments_for_outpoint(&params.outpoint),
+    }))
+}
+
+/// Stops the plugin when lightningd is shutting down, so the retriers can be wound down before exiting.
 ///
-/// The appointment is built using the data provided by the backend (dispute txid and penalty transaction).
+/// The client is flagged as shutting down right away, so no new retries are started while the plugin stops.
+async fn on_shutdown(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    _: serde_json::Value,
+) -> Result<(), Error> {
+    log::info!("Received shutdown notification");
+    plugin.state().lock().unwrap().begin_shutdown();
+    plugin.shutdown()
+}
+
+/// Keeps track of the block height, so subscriptions can be renewed before they expire.
+async fn on_block_added(
+    plugin: Plugin<Arc<Mutex<WTClient>>>,
+    v: serde_json::Value,
+) -> Result<(), Error> {
+    // Older versions of lightningd wrap the block data in "block" instead of "block_added"
+    let height = v
+        .get("block_added")
+        .or_else(|| v.get("block"))
+        .and_then(|block| block.get("height"))
+        .and_then(|height| height.as_u64())
+        .and_then(|height| u32::try_from(height).ok())
+        .ok_or_else(|| anyhow!("Unexpected block_added notification: {v}"))?;
+    plugin.state().lock().unwrap().set_block_height(height);
+    Ok(())
+}
+
+/// Sends an appointment to all registered towers for every new commitment transaction (or to the towers the channel
+/// has been restricted to, if any).
+///
+/// The appointment is built using the data provided by the backend (dispute txid and penalty transaction). Whether the
+/// hook waits for any tower to confirm it depends on the [SubmissionPolicy].
 async fn on_commitment_revocation(
     plugin: Plugin<Arc<Mutex<WTClient>>>,
     v: serde_json::Value,
@@ -410,6 +1051,11 @@ async fn on_commitment_revocation(
 
     // TODO: For now, to_self_delay is hardcoded to 42. Revisit and define it better / remove it when / if needed
     let locator = Locator::new(commitment_revocation.commitment_txid);
+    plugin.state().lock().unwrap().add_appointment_channel(
+        locator,
+        &commitment_revocation.channel_id,
+        commitment_revocation.commit_num,
+    );
     let appointment = Appointment::new(
         locator,
         cryptography::encrypt(
@@ -419,107 +1065,30 @@ async fn on_commitment_revocation(
         .unwrap(),
         42,
     );
-    let signature = cryptography::sign(
-        &appointment.to_vec(),
-        &plugin.state().lock().unwrap().user_sk,
-    )
-    .unwrap();
 
     // Looks like we cannot iterate through towers given a locked state is not Send (due to the async call),
-    // so we need to clone the bare minimum.
-    let towers = plugin
-        .state()
-        .lock()
-        .unwrap()
-        .towers
-        .iter()
-        .map(|(id, info)| (*id, info.net_addr.clone(), info.status))
-        .collect::<Vec<_>>();
-
-    let proxy = plugin.state().lock().unwrap().proxy.clone();
-
-    for (tower_id, net_addr, status) in towers {
-        if status.is_reachable() {
-            match http::add_appointment(tower_id, &net_addr, &proxy, &appointment, &signature).await
-            {
-                Ok((slots, receipt)) => {
-                    plugin
-                        .state()
-                        .lock()
-                        .unwrap()
-                        .add_appointment_receipt(tower_id, locator, slots, &receipt);
-                    log::debug!("Response verified and data stored in the database");
-                }
-                Err(e) => match e {
-                    AddAppointmentError::RequestError(e) => {
-                        if e.is_connection() {
-                            log::warn!(
-                                "{tower_id} cannot be reached. Adding {} to pending appointments",
-                                appointment.locator
-                            );
-                            let mut state = plugin.state().lock().unwrap();
-                            state.set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
-                            state.add_pending_appointment(tower_id, &appointment);
-                            send_to_retrier(&state, tower_id, appointment.locator);
-                        }
-                    }
-                    AddAppointmentError::ApiError(e) => match e.error_code {
-                        errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR => {
-                            log::warn!(
-                                "There is a subscription issue with {tower_id}. Adding {} to pending",
-                                appointment.locator
-                            );
-                            let mut state = plugin.state().lock().unwrap();
-                            state.set_tower_status(tower_id, TowerStatus::SubscriptionError);
-                            state.add_pending_appointment(tower_id, &appointment);
-                            send_to_retrier(&state, tower_id, appointment.locator);
-                        }
-
-                        _ => {
-                            log::warn!(
-                                "{tower_id} rejected the appointment. Error: {}, error_code: {}",
-                                e.error,
-                                e.error_code
-                            );
-                            plugin
-                                .state()
-                                .lock()
-                                .unwrap()
-                                .add_invalid_appointment(tower_id, &appointment);
-                        }
-                    },
-                    AddAppointmentError::SignatureError(proof) => {
-                        log::warn!("Cannot recover known tower_id from the appointment receipt. Flagging tower as misbehaving");
-                        plugin
-                            .state()
-                            .lock()
-                            .unwrap()
-                            .flag_misbehaving_tower(tower_id, proof)
-                    }
-                },
-            };
-        } else if status.is_misbehaving() {
-            log::warn!("{tower_id} is misbehaving. Not sending any further appointments",);
-        } else {
-            if status.is_subscription_error() {
-                log::warn!(
-                    "There is a subscription issue with {tower_id}. Adding {} to pending",
-                    appointment.locator
-                );
-            } else {
-                log::warn!(
-                    "{tower_id} is {status}. Adding {} to pending",
-                    appointment.locator,
-                );
-            }
-
-            let mut state = plugin.state().lock().unwrap();
-            state.add_pending_appointment(tower_id, &appointment);
+    // so we need to clone the bare minimum. Appointments are signed following the version agreed with each tower.
+    let towers = {
+        let state = plugin.state().lock().unwrap();
+        let mut targets = state.get_channel_towers(&commitment_revocation.channel_id);
+        state.apply_tower_order(&mut targets);
+        state
+            .towers
+            .iter()
+            .filter(|(id, _)| targets.contains(id))
+            .map(|(id, info)| {
+                let signature = cryptography::sign(
+                    &appointment.to_signable_vec(info.signature_version),
+                    &state.user_sk,
+                )
+                .unwrap();
+                (*id, info.net_addr.clone(), info.status, signature)
+            })
+            .collect::<Vec<_>>()
+    };
 
-            if !status.is_unreachable() {
-                send_to_retrier(&state, tower_id, appointment.locator);
-            }
-        }
+    if submitter::submit_appointment(plugin.state().clone(), appointment, towers).await {
+        log::debug!("Appointment confirmed by at least one tower");
     }
 
     // FIXME: Ask cdecker: Do hooks need to return something?
@@ -544,11 +1113,121 @@ async fn main() -> Result<(), Error> {
             Value::Integer(constants::DEFAULT_WT_MAX_RETRY_TIME),
             constants::WT_MAX_RETRY_TIME_DESC,
         ))
+        .option(ConfigOption::new(
+            constants::WT_MAX_RETRIES,
+            Value::Integer(constants::DEFAULT_WT_MAX_RETRIES),
+            constants::WT_MAX_RETRIES_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_AGGRESSIVE_MAX_RETRY_TIME,
+            Value::Integer(constants::DEFAULT_WT_AGGRESSIVE_MAX_RETRY_TIME),
+            constants::WT_AGGRESSIVE_MAX_RETRY_TIME_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_AGGRESSIVE_MAX_RETRY_INTERVAL,
+            Value::Integer(constants::DEFAULT_WT_AGGRESSIVE_MAX_RETRY_INTERVAL),
+            constants::WT_AGGRESSIVE_MAX_RETRY_INTERVAL_DESC,
+        ))
         .option(ConfigOption::new(
             constants::WT_AUTO_RETRY_DELAY,
             Value::Integer(constants::DEFAULT_WT_AUTO_RETRY_DELAY),
             constants::WT_AUTO_RETRY_DELAY_DESC,
         ))
+        .option(ConfigOption::new(
+            constants::WT_RETRY_POLLING_INTERVAL,
+            Value::Integer(constants::DEFAULT_WT_RETRY_POLLING_INTERVAL),
+            constants::WT_RETRY_POLLING_INTERVAL_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_USER_AGENT,
+            Value::String(constants::DEFAULT_WT_USER_AGENT.to_owned()),
+            constants::WT_USER_AGENT_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_EXTRA_HEADERS,
+            Value::String(constants::DEFAULT_WT_EXTRA_HEADERS.to_owned()),
+            constants::WT_EXTRA_HEADERS_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_LIST_MAINTAINER,
+            Value::String(constants::DEFAULT_WT_LIST_MAINTAINER.to_owned()),
+            constants::WT_LIST_MAINTAINER_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_DB_BUSY_TIMEOUT,
+            Value::Integer(constants::DEFAULT_WT_DB_BUSY_TIMEOUT),
+            constants::WT_DB_BUSY_TIMEOUT_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_PRUNE_AGE,
+            Value::Integer(constants::DEFAULT_WT_PRUNE_AGE),
+            constants::WT_PRUNE_AGE_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_STALE_FEED,
+            Value::String(constants::DEFAULT_WT_STALE_FEED.to_owned()),
+            constants::WT_STALE_FEED_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_INVALID_RETRY_DELAY,
+            Value::Integer(constants::DEFAULT_WT_INVALID_RETRY_DELAY),
+            constants::WT_INVALID_RETRY_DELAY_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_INVALID_MAX_RETRIES,
+            Value::Integer(constants::DEFAULT_WT_INVALID_MAX_RETRIES),
+            constants::WT_INVALID_MAX_RETRIES_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_MAX_APPOINTMENT_AGE,
+            Value::Integer(constants::DEFAULT_WT_MAX_APPOINTMENT_AGE),
+            constants::WT_MAX_APPOINTMENT_AGE_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_AUTO_RENEW_BLOCKS,
+            Value::Integer(constants::DEFAULT_WT_AUTO_RENEW_BLOCKS),
+            constants::WT_AUTO_RENEW_BLOCKS_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_SUBMISSION_POLICY,
+            Value::String(constants::DEFAULT_WT_SUBMISSION_POLICY.to_owned()),
+            constants::WT_SUBMISSION_POLICY_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_SUBMISSION_TIMEOUT,
+            Value::Integer(constants::DEFAULT_WT_SUBMISSION_TIMEOUT),
+            constants::WT_SUBMISSION_TIMEOUT_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_MAX_FANOUT,
+            Value::Integer(constants::DEFAULT_WT_MAX_FANOUT),
+            constants::WT_MAX_FANOUT_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_DEAD_LETTER_LIMIT,
+            Value::Integer(constants::DEFAULT_WT_DEAD_LETTER_LIMIT),
+            constants::WT_DEAD_LETTER_LIMIT_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_STATUS_EVENTS,
+            Value::String(constants::DEFAULT_WT_STATUS_EVENTS.to_owned()),
+            constants::WT_STATUS_EVENTS_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_WEBHOOK_URL,
+            Value::String(constants::DEFAULT_WT_WEBHOOK_URL.to_owned()),
+            constants::WT_WEBHOOK_URL_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_WEBHOOK_SECRET,
+            Value::String(constants::DEFAULT_WT_WEBHOOK_SECRET.to_owned()),
+            constants::WT_WEBHOOK_SECRET_DESC,
+        ))
+        .option(ConfigOption::new(
+            constants::WT_COMPACT_APPOINTMENTS,
+            Value::Boolean(constants::DEFAULT_WT_COMPACT_APPOINTMENTS),
+            constants::WT_COMPACT_APPOINTMENTS_DESC,
+        ))
         .option(ConfigOption::new(
             constants::DEV_WT_MAX_RETRY_INTERVAL,
             Value::Integer(constants::DEFAULT_DEV_WT_MAX_RETRY_INTERVAL),
@@ -574,6 +1253,11 @@ async fn main() -> Result<(), Error> {
             constants::RPC_GET_APPOINTMENT_RECEIPT_DESC,
             get_appointment_receipt,
         )
+        .rpcmethod(
+            constants::RPC_DELETE_APPOINTMENT,
+            constants::RPC_DELETE_APPOINTMENT_DESC,
+            delete_appointment,
+        )
         .rpcmethod(
             constants::RPC_GET_SUBSCRIPTION_INFO,
             constants::RPC_GET_SUBSCRIPTION_INFO_DESC,
@@ -589,21 +1273,149 @@ async fn main() -> Result<(), Error> {
             constants::RPC_GET_TOWER_INFO_DESC,
             get_tower_info,
         )
+        .rpcmethod(
+            constants::RPC_DIAGNOSE,
+            constants::RPC_DIAGNOSE_DESC,
+            diagnose,
+        )
+        .rpcmethod(
+            constants::RPC_SET_CHANNEL_TOWERS,
+            constants::RPC_SET_CHANNEL_TOWERS_DESC,
+            set_channel_towers,
+        )
+        .rpcmethod(
+            constants::RPC_CHANNEL_COVERAGE,
+            constants::RPC_CHANNEL_COVERAGE_DESC,
+            channel_coverage,
+        )
+        .rpcmethod(
+            constants::RPC_SET_TOWER_LABELS,
+            constants::RPC_SET_TOWER_LABELS_DESC,
+            set_tower_labels,
+        )
+        .rpcmethod(
+            constants::RPC_SET_TOWER_PIN,
+            constants::RPC_SET_TOWER_PIN_DESC,
+            set_tower_pin,
+        )
+        .rpcmethod(
+            constants::RPC_SET_TOWER_PROXY,
+            constants::RPC_SET_TOWER_PROXY_DESC,
+            set_tower_proxy,
+        )
+        .rpcmethod(
+            constants::RPC_SET_AUTO_RENEW,
+            constants::RPC_SET_AUTO_RENEW_DESC,
+            set_auto_renew,
+        )
+        .rpcmethod(
+            constants::RPC_SET_MIRROR,
+            constants::RPC_SET_MIRROR_DESC,
+            set_mirror,
+        )
+        .rpcmethod(
+            constants::RPC_SET_TOWER_ORDER,
+            constants::RPC_SET_TOWER_ORDER_DESC,
+            set_tower_order,
+        )
+        .rpcmethod(
+            constants::RPC_GET_HEALTH,
+            constants::RPC_GET_HEALTH_DESC,
+            get_health,
+        )
+        .rpcmethod(
+            constants::RPC_GET_METRICS,
+            constants::RPC_GET_METRICS_DESC,
+            get_metrics,
+        )
+        .rpcmethod(
+            constants::RPC_VERIFY_RECEIPTS,
+            constants::RPC_VERIFY_RECEIPTS_DESC,
+            verify_receipts,
+        )
+        .rpcmethod(
+            constants::RPC_IMPORT_LIST,
+            constants::RPC_IMPORT_LIST_DESC,
+            import_list,
+        )
+        .rpcmethod(
+            constants::RPC_EXPORT_STATE,
+            constants::RPC_EXPORT_STATE_DESC,
+            export_state,
+        )
+        .rpcmethod(
+            constants::RPC_DIFF_STATE,
+            constants::RPC_DIFF_STATE_DESC,
+            diff_state,
+        )
+        .rpcmethod(
+            constants::RPC_EXPORT_CSV,
+            constants::RPC_EXPORT_CSV_DESC,
+            export_csv,
+        )
+        .rpcmethod(
+            constants::RPC_CAPABILITIES,
+            constants::RPC_CAPABILITIES_DESC,
+            capabilities,
+        )
+        .rpcmethod(
+            constants::RPC_DEAD_LETTERS,
+            constants::RPC_DEAD_LETTERS_DESC,
+            dead_letters,
+        )
+        .rpcmethod(
+            constants::RPC_SELF_TEST,
+            constants::RPC_SELF_TEST_DESC,
+            self_test,
+        )
         .rpcmethod(constants::RPC_PING, constants::RPC_PING_DESC, ping)
+        .rpcmethod(
+            constants::RPC_TOWER_TERMS,
+            constants::RPC_TOWER_TERMS_DESC,
+            get_tower_terms,
+        )
+        .rpcmethod(
+            constants::RPC_COVERAGE_ESTIMATE,
+            constants::RPC_COVERAGE_ESTIMATE_DESC,
+            coverage_estimate,
+        )
         .rpcmethod(
             constants::RPC_RETRY_TOWER,
             constants::RPC_RETRY_TOWER_DESC,
             retry_tower,
         )
+        .rpcmethod(
+            constants::RPC_RETRY_ALL,
+            constants::RPC_RETRY_ALL_DESC,
+            retry_all,
+        )
+        .rpcmethod(
+            constants::RPC_RESYNC_TOWER,
+            constants::RPC_RESYNC_TOWER_DESC,
+            resync_tower,
+        )
+        .rpcmethod(
+            constants::RPC_BACKFILL_TOWER,
+            constants::RPC_BACKFILL_TOWER_DESC,
+            backfill_tower,
+        )
         .rpcmethod(
             constants::RPC_ABANDON_TOWER,
             constants::RPC_ABANDON_TOWER_DESC,
             abandon_tower,
         )
+        .rpcmethod(
+            constants::RPC_PRUNE_FAILED,
+            constants::RPC_PRUNE_FAILED_DESC,
+            prune_failed,
+        )
         .hook(
             constants::HOOK_COMMITMENT_REVOCATION,
             on_commitment_revocation,
-        );
+        )
+        .subscribe(constants::SUBSCRIPTION_SHUTDOWN, on_shutdown)
+        .subscribe(constants::SUBSCRIPTION_BLOCK_ADDED, on_block_added)
+        .notification(NotificationTopic::new(constants::NOTIFICATION_TOWER_STATUS));
 
     // We're unwrapping here given it does not seem we actually have anything to check at the moment.
     // Change this so the plugin can be disabled soon if this happens not to be the case.
@@ -613,21 +1425,224 @@ async fn main() -> Result<(), Error> {
         return Ok(());
     };
 
-    let (tx, rx) = unbounded_channel();
-    let wt_client = Arc::new(Mutex::new(
-        WTClient::with_proxy(
-            data_dir,
-            tx,
-            midstate.configuration().proxy.map(|proxy| {
-                // We don't need to inform `always-use-proxy` needing `proxy` to work. This is done by CLN already when needed.
-                ProxyInfo::new(
-                    proxy,
-                    midstate.configuration().always_use_proxy.unwrap_or(false),
-                )
-            }),
+    let headers = TowerHeaders::new(
+        midstate
+            .option(constants::WT_USER_AGENT)
+            .unwrap()
+            .as_str()
+            .unwrap(),
+        midstate
+            .option(constants::WT_EXTRA_HEADERS)
+            .unwrap()
+            .as_str()
+            .unwrap(),
+    )
+    .map_err(|e| {
+        log::error!("Invalid request headers: {e}");
+        anyhow!(e)
+    })?;
+
+    let maintainer = midstate
+        .option(constants::WT_LIST_MAINTAINER)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_owned();
+    if !maintainer.is_empty() && PublicKey::from_str(&maintainer).is_err() {
+        log::error!("Invalid {}: {maintainer}", constants::WT_LIST_MAINTAINER);
+        return Err(anyhow!("Invalid {}", constants::WT_LIST_MAINTAINER));
+    }
+
+    let db_busy_timeout = u64::try_from(
+        midstate
+            .option(constants::WT_DB_BUSY_TIMEOUT)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_DB_BUSY_TIMEOUT);
+    })?;
+
+    let stale_feed = StaleFeed::from_str(
+        midstate
+            .option(constants::WT_STALE_FEED)
+            .unwrap()
+            .as_str()
+            .unwrap(),
+    )
+    .map_err(|e| {
+        log::error!("Invalid {}: {e}", constants::WT_STALE_FEED);
+        anyhow!(e)
+    })?;
+
+    let invalid_retry_delay = u64::try_from(
+        midstate
+            .option(constants::WT_INVALID_RETRY_DELAY)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_INVALID_RETRY_DELAY);
+    })?;
+
+    let invalid_max_retries = u32::try_from(
+        midstate
+            .option(constants::WT_INVALID_MAX_RETRIES)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_INVALID_MAX_RETRIES);
+    })?;
+    let invalid_retry_policy =
+        (invalid_retry_delay > 0 && invalid_max_retries > 0).then_some(InvalidRetryPolicy {
+            cooldown: invalid_retry_delay,
+            max_retries: invalid_max_retries,
+        });
+
+    let max_appointment_age = u64::try_from(
+        midstate
+            .option(constants::WT_MAX_APPOINTMENT_AGE)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_MAX_APPOINTMENT_AGE);
+    })?;
+
+    let auto_renew_blocks = u32::try_from(
+        midstate
+            .option(constants::WT_AUTO_RENEW_BLOCKS)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_AUTO_RENEW_BLOCKS);
+    })?;
+
+    let submission_timeout = u64::try_from(
+        midstate
+            .option(constants::WT_SUBMISSION_TIMEOUT)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_SUBMISSION_TIMEOUT);
+    })?;
+    let submission_policy = SubmissionPolicy::new(
+        midstate
+            .option(constants::WT_SUBMISSION_POLICY)
+            .unwrap()
+            .as_str()
+            .unwrap(),
+        Duration::from_secs(submission_timeout),
+    )
+    .map_err(|e| {
+        log::error!("Invalid {}: {e}", constants::WT_SUBMISSION_POLICY);
+        anyhow!(e)
+    })?;
+
+    let max_fanout = usize::try_from(
+        midstate
+            .option(constants::WT_MAX_FANOUT)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .ok()
+    .filter(|max| *max > 0)
+    .ok_or_else(|| {
+        log::error!("{} out of range", constants::WT_MAX_FANOUT);
+        anyhow!("{} out of range", constants::WT_MAX_FANOUT)
+    })?;
+
+    let dead_letter_limit = usize::try_from(
+        midstate
+            .option(constants::WT_DEAD_LETTER_LIMIT)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_DEAD_LETTER_LIMIT);
+    })?;
+
+    let status_filter = StatusFilter::from_str(
+        midstate
+            .option(constants::WT_STATUS_EVENTS)
+            .unwrap()
+            .as_str()
+            .unwrap(),
+    )
+    .map_err(|e| {
+        log::error!("Invalid {}: {e}", constants::WT_STATUS_EVENTS);
+        anyhow!(e)
+    })?;
+
+    let webhook_url = midstate
+        .option(constants::WT_WEBHOOK_URL)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_owned();
+    let webhook_secret = midstate
+        .option(constants::WT_WEBHOOK_SECRET)
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_owned();
+    let webhook = (!webhook_url.is_empty()).then(|| {
+        Webhook::new(
+            webhook_url,
+            (!webhook_secret.is_empty()).then_some(webhook_secret),
+            Duration::from_secs(WEBHOOK_MAX_RETRY_TIME),
         )
-        .await,
-    ));
+    });
+
+    let compact_appointments = midstate
+        .option(constants::WT_COMPACT_APPOINTMENTS)
+        .unwrap()
+        .as_bool()
+        .unwrap();
+
+    let (tx, rx) = unbounded_channel();
+    let (status_tx, mut status_rx) = unbounded_channel();
+    let (webhook_tx, webhook_rx) = broadcast::channel(WEBHOOK_QUEUE_CAPACITY);
+    let mut wt_client = WTClient::with_proxy(
+        data_dir,
+        tx,
+        midstate.configuration().proxy.map(|proxy| {
+            // We don't need to inform `always-use-proxy` needing `proxy` to work. This is done by CLN already when needed.
+            ProxyInfo::new(
+                proxy,
+                midstate.configuration().always_use_proxy.unwrap_or(false),
+            )
+        }),
+    )
+    .await
+    .with_headers(headers)
+    .with_db_busy_timeout(Duration::from_millis(db_busy_timeout))
+    .with_stale_feed(stale_feed)
+    .with_invalid_retry_policy(invalid_retry_policy)
+    .with_max_pending_age((max_appointment_age > 0).then_some(max_appointment_age))
+    .with_auto_renew_blocks(auto_renew_blocks)
+    .with_submission_policy(submission_policy)
+    .with_max_fanout(max_fanout)
+    .with_dead_letter_limit(dead_letter_limit)
+    .with_compact_appointments(compact_appointments)
+    .with_status_sink(status_tx)
+    .with_status_filter(status_filter);
+    if let Some(webhook) = webhook {
+        wt_client = wt_client.with_webhook_sink(webhook_tx);
+        tokio::spawn(webhook.run(webhook_rx));
+    }
+    let wt_client = Arc::new(Mutex::new(wt_client));
 
     let max_elapsed_time = u16::try_from(
         midstate
@@ -640,6 +1655,42 @@ async fn main() -> Result<(), Error> {
         log::error!("{} out of range", constants::WT_MAX_RETRY_TIME);
     })?;
 
+    let max_retries = u32::try_from(
+        midstate
+            .option(constants::WT_MAX_RETRIES)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_MAX_RETRIES);
+    })?;
+
+    let aggressive_max_elapsed_time = u16::try_from(
+        midstate
+            .option(constants::WT_AGGRESSIVE_MAX_RETRY_TIME)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_AGGRESSIVE_MAX_RETRY_TIME);
+    })?;
+
+    let aggressive_max_interval_time = u16::try_from(
+        midstate
+            .option(constants::WT_AGGRESSIVE_MAX_RETRY_INTERVAL)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!(
+            "{} out of range",
+            constants::WT_AGGRESSIVE_MAX_RETRY_INTERVAL
+        );
+    })?;
+
     let auto_retry_delay = u32::try_from(
         midstate
             .option(constants::WT_AUTO_RETRY_DELAY)
@@ -662,17 +1713,68 @@ async fn main() -> Result<(), Error> {
         log::error!("{} out of range", constants::DEV_WT_MAX_RETRY_INTERVAL);
     })?;
 
+    let polling_interval = u64::try_from(
+        midstate
+            .option(constants::WT_RETRY_POLLING_INTERVAL)
+            .unwrap()
+            .as_i64()
+            .unwrap(),
+    )
+    .inspect_err(|_| {
+        log::error!("{} out of range", constants::WT_RETRY_POLLING_INTERVAL);
+    })?;
+
     let plugin = midstate.start(wt_client.clone()).await?;
+
+    // Publish tower status changes so other plugins can react to them.
+    let notifier = plugin.clone();
     tokio::spawn(async move {
-        RetryManager::new(
-            wt_client,
+        while let Some(change) = status_rx.recv().await {
+            if let Err(e) = notifier
+                .send_custom_notification(
+                    constants::NOTIFICATION_TOWER_STATUS.to_owned(),
+                    json!(change),
+                )
+                .await
+            {
+                log::error!(
+                    "Cannot send {} notification: {e}",
+                    constants::NOTIFICATION_TOWER_STATUS
+                );
+            }
+        }
+    });
+
+    let retry_manager_client = wt_client.clone();
+    let retry_manager_task = tokio::spawn(async move {
+        let mut retry_manager = RetryManager::new(
+            retry_manager_client,
             rx,
             max_elapsed_time,
             auto_retry_delay,
             max_interval_time,
+            polling_interval,
         )
-        .manage_retry()
-        .await
+        .with_aggressive_backoff(aggressive_max_elapsed_time, aggressive_max_interval_time);
+        // Zero means no limit
+        if max_retries > 0 {
+            retry_manager = retry_manager.with_max_retries(max_retries);
+        }
+        retry_manager.manage_retry().await
     });
-    plugin.join().await
+    let result = plugin.join().await;
+
+    // Wind down in order: stop feeding the retriers and wait for the running ones to persist what they have delivered.
+    // The database is closed once the client is dropped, after this returns.
+    // The client may have already been flagged if lightningd notified the shutdown
+    {
+        let mut state = wt_client.lock().unwrap();
+        if !state.is_shutting_down() {
+            state.begin_shutdown();
+        }
+    }
+    if let Err(e) = retry_manager_task.await {
+        log::error!("Retry manager did not finish cleanly. Error: {e}");
+    }
+    result
 }
@@ -1,15 +1,22 @@
-use reqwest::{Method, Response};
+use std::fmt;
+
+use prost::Message;
+use reqwest::header::{ACCEPT, CONTENT_TYPE};
+use reqwest::{Method, Response, StatusCode};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use teos_common::appointment::Appointment;
+use bitcoin::secp256k1::SecretKey;
+
+use teos_common::appointment::{Appointment, Locator, SignatureVersion};
 use teos_common::cryptography;
-use teos_common::net::http::Endpoint;
+use teos_common::errors;
+use teos_common::net::http::{Endpoint, PROTOBUF_CONTENT_TYPE};
 use teos_common::net::NetAddr;
 use teos_common::protos as common_msgs;
-use teos_common::receipts::{AppointmentReceipt, RegistrationReceipt};
+use teos_common::receipts::{AppointmentReceipt, DeletionReceipt, RegistrationReceipt};
 use teos_common::{TowerId, UserId};
 
-use crate::net::ProxyInfo;
+use crate::net::{self, RequestOptions};
 use crate::MisbehaviorProof;
 
 /// Represents a generic api response.
@@ -27,20 +34,96 @@ pub struct ApiError {
     pub error_code: u8,
 }
 
+/// The terms a (paid) tower advertises for its service, so users know what they are agreeing to before registering.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TowerTerms {
+    /// The price (in sats) of every appointment slot.
+    pub price_per_slot: u64,
+    /// The payment methods accepted by the tower (e.g. `bolt11`).
+    pub payment_methods: Vec<String>,
+    /// How long (in blocks) subscriptions last.
+    pub subscription_duration: u32,
+}
+
 /// Errors related to requests sent to the tower.
 #[derive(Debug, PartialEq, Eq)]
 pub enum RequestError {
     ConnectionError(String),
+    Timeout(String),
     DeserializeError(String),
+    Rejected(String),
+    /// The tower requires a payment to register. Holds the invoice to be paid.
+    PaymentRequired(String),
+    /// The tower presented a TLS certificate not matching its pin.
+    PinMismatch(String),
     Unexpected(String),
 }
 
 impl RequestError {
+    /// Gets the [ErrorKind] of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            RequestError::ConnectionError(_) => ErrorKind::Connection,
+            RequestError::Unexpected(_) => ErrorKind::Unexpected,
+            RequestError::Timeout(_) => ErrorKind::Timeout,
+            RequestError::DeserializeError(_) => ErrorKind::Unsupported,
+            RequestError::Rejected(_) => ErrorKind::Rejected,
+            RequestError::PinMismatch(_) => ErrorKind::Security,
+            // Only registrations can require a payment
+            RequestError::PaymentRequired(_) => ErrorKind::Subscription,
+        }
+    }
+
+    /// Whether the tower could not be reached. Timeouts are also considered connection errors.
     pub fn is_connection(&self) -> bool {
-        matches!(self, RequestError::ConnectionError(_))
+        matches!(
+            self,
+            RequestError::ConnectionError(_) | RequestError::Timeout(_)
+        )
     }
 }
 
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RequestError::ConnectionError(x) => write!(f, "{x}"),
+            RequestError::Timeout(x) => write!(f, "{x}"),
+            RequestError::DeserializeError(x) => write!(f, "{x}"),
+            RequestError::Rejected(x) => write!(f, "{x}"),
+            RequestError::PaymentRequired(x) => write!(f, "{x}"),
+            RequestError::PinMismatch(x) => write!(f, "{x}"),
+            RequestError::Unexpected(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// Stable categories for [AddAppointmentError], so callers can branch on them without inspecting the inner errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// The tower could not be reached.
+    Connection,
+    /// The request to the tower timed out.
+    Timeout,
+    /// The user signature was invalid or the subscription is not valid anymore (expired or out of slots).
+    Subscription,
+    /// The tower rejected the appointment.
+    Rejected,
+    /// The tower replied with a receipt not signed by the expected key.
+    Misbehaving,
+    /// The tower is temporarily not accepting requests.
+    RateLimited,
+    /// The tower could not understand the request, or the client could not understand the response.
+    Unsupported,
+    /// The tower presented a TLS certificate not matching its pin. Someone may be impersonating it.
+    Security,
+    /// The client could not process the data locally (e.g. the database could not be read or the appointments could
+    /// not be signed). Never returned by [AddAppointmentError::kind].
+    Internal,
+    /// The tower replied with something the client did not expect (e.g. a receipt that does not match the request).
+    Unexpected,
+}
+
 /// Errors related to the `add_appointment` requests to the tower.
 #[derive(Debug)]
 pub enum AddAppointmentError {
@@ -49,6 +132,34 @@ pub enum AddAppointmentError {
     SignatureError(MisbehaviorProof),
 }
 
+impl AddAppointmentError {
+    /// Gets the [ErrorKind] of the error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AddAppointmentError::RequestError(e) => e.kind(),
+            AddAppointmentError::ApiError(e) => match e.error_code {
+                errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR => ErrorKind::Subscription,
+                errors::SERVICE_UNAVAILABLE => ErrorKind::RateLimited,
+                errors::INVALID_REQUEST_FORMAT => ErrorKind::Unsupported,
+                _ => ErrorKind::Rejected,
+            },
+            AddAppointmentError::SignatureError(_) => ErrorKind::Misbehaving,
+        }
+    }
+}
+
+impl fmt::Display for AddAppointmentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddAppointmentError::RequestError(e) => write!(f, "{e}"),
+            AddAppointmentError::ApiError(e) => write!(f, "{}", e.error),
+            AddAppointmentError::SignatureError(_) => {
+                write!(f, "The receipt is not signed by the tower")
+            }
+        }
+    }
+}
+
 impl From<RequestError> for AddAppointmentError {
     fn from(r: RequestError) -> Self {
         AddAppointmentError::RequestError(r)
@@ -56,12 +167,16 @@ impl From<RequestError> for AddAppointmentError {
 }
 
 /// Handles the logic of interacting with the `register` endpoint of the tower.
+///
+/// Alongside the receipt, returns the [SignatureVersion] agreed with the tower. Legacy towers do not report any, in
+/// which case [SignatureVersion::V1] is assumed.
 pub async fn register(
     tower_id: TowerId,
     user_id: UserId,
     tower_net_addr: &NetAddr,
-    proxy: &Option<ProxyInfo>,
-) -> Result<RegistrationReceipt, RequestError> {
+    payment_preimage: Option<&[u8]>,
+    options: &RequestOptions,
+) -> Result<(RegistrationReceipt, SignatureVersion), RequestError> {
     log::info!("Registering in the Eye of Satoshi (tower_id={tower_id})");
     process_post_response(
         post_request(
@@ -69,20 +184,170 @@ pub async fn register(
             Endpoint::Register,
             &common_msgs::RegisterRequest {
                 user_id: user_id.to_vec(),
+                payment_preimage: payment_preimage.map(|p| p.to_vec()).unwrap_or_default(),
+                signature_versions: SignatureVersion::supported(),
             },
-            proxy,
+            options,
         )
         .await,
     )
     .await
-    .map(|r: common_msgs::RegisterResponse| {
-        RegistrationReceipt::with_signature(
-            user_id,
-            r.available_slots,
-            r.subscription_start,
-            r.subscription_expiry,
-            r.subscription_signature,
+    .and_then(|r| match r {
+        ApiResponse::Response::<common_msgs::RegisterResponse>(r) => {
+            let signature_version = match r.signature_version {
+                0 => SignatureVersion::V1,
+                v => SignatureVersion::try_from(v).map_err(|e| {
+                    RequestError::Unexpected(format!(
+                        "{tower_id} agreed on a signature version we do not support. {e}"
+                    ))
+                })?,
+            };
+            Ok((
+                RegistrationReceipt::with_signature(
+                    user_id,
+                    r.available_slots,
+                    r.subscription_start,
+                    r.subscription_expiry,
+                    r.subscription_signature,
+                ),
+                signature_version,
+            ))
+        }
+        ApiResponse::Error(e) if e.error_code == errors::REGISTRATION_PAYMENT_REQUIRED => Err(
+            RequestError::PaymentRequired(format!("{tower_id} requires a payment. {}", e.error)),
+        ),
+        ApiResponse::Error(e) if e.error_code == errors::REGISTRATION_USER_NOT_ALLOWED => {
+            Err(RequestError::Rejected(format!(
+                "{tower_id} does not allow {user_id} to register. Ask the tower operator for access"
+            )))
+        }
+        ApiResponse::Error(e) => Err(RequestError::Rejected(format!(
+            "{tower_id} rejected the registration. Error: {}, error_code: {}",
+            e.error, e.error_code
+        ))),
+    })
+}
+
+/// Handles the logic of interacting with the `get_subscription_info` endpoint of the tower.
+pub async fn get_subscription_info(
+    tower_net_addr: &NetAddr,
+    options: &RequestOptions,
+    user_sk: &SecretKey,
+) -> Result<common_msgs::GetSubscriptionInfoResponse, RequestError> {
+    let signature = cryptography::sign("get subscription info".as_bytes(), user_sk).unwrap();
+
+    process_post_response(
+        post_request(
+            tower_net_addr,
+            Endpoint::GetSubscriptionInfo,
+            &common_msgs::GetSubscriptionInfoRequest { signature },
+            options,
         )
+        .await,
+    )
+    .await
+}
+
+/// Handles the logic of interacting with the `get_tower_info` endpoint of the tower.
+///
+/// Returns [None] if the tower does not advertise any terms (e.g. because it is free to use).
+pub async fn get_tower_info(
+    tower_net_addr: &NetAddr,
+    options: &RequestOptions,
+) -> Result<Option<TowerTerms>, RequestError> {
+    let response = get_request(tower_net_addr, Endpoint::GetTowerInfo, options).await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    process_post_response(Ok(response))
+        .await
+        .and_then(|r| match r {
+            ApiResponse::Response::<TowerTerms>(terms) => Ok(Some(terms)),
+            ApiResponse::Error(e) => Err(RequestError::Rejected(format!(
+                "The tower refused to share its terms. Error: {}, error_code: {}",
+                e.error, e.error_code
+            ))),
+        })
+}
+
+/// Handles the logic of interacting with the `get_fee_estimate` endpoint of the tower.
+///
+/// Returns the feerate (in sat/vB) the tower estimates a transaction needs to confirm within `conf_target` blocks, so
+/// penalties can be pre-signed at an adequate feerate.
+pub async fn get_fee_estimate(
+    tower_net_addr: &NetAddr,
+    options: &RequestOptions,
+    conf_target: u32,
+) -> Result<f64, RequestError> {
+    process_post_response(
+        post_request(
+            tower_net_addr,
+            Endpoint::GetFeeEstimate,
+            &common_msgs::GetFeeEstimateRequest { conf_target },
+            options,
+        )
+        .await,
+    )
+    .await
+    .and_then(|r| match r {
+        ApiResponse::Response::<common_msgs::GetFeeEstimateResponse>(r) => Ok(r.feerate),
+        ApiResponse::Error(e) => Err(RequestError::Rejected(format!(
+            "The tower refused to share its fee estimate. Error: {}, error_code: {}",
+            e.error, e.error_code
+        ))),
+    })
+}
+
+/// Handles the logic of interacting with the `delete_appointment` endpoint of the tower.
+///
+/// The request is bound to the tower and to the block the appointment was accepted at (`start_block`), as found in its receipt.
+/// Returns the slots available after the deletion alongside the deletion receipt, which is checked to be signed by the tower.
+pub async fn delete_appointment(
+    tower_id: TowerId,
+    tower_net_addr: &NetAddr,
+    options: &RequestOptions,
+    locator: Locator,
+    start_block: u32,
+    user_sk: &SecretKey,
+) -> Result<(u32, DeletionReceipt), RequestError> {
+    log::debug!("Deleting appointment {locator} from tower {tower_id}");
+    let user_signature = cryptography::sign(
+        &teos_common::delete_appointment_message(tower_id, locator, start_block),
+        user_sk,
+    )
+    .unwrap();
+
+    process_post_response(
+        post_request(
+            tower_net_addr,
+            Endpoint::DeleteAppointment,
+            &common_msgs::DeleteAppointmentRequest {
+                locator: locator.to_vec(),
+                signature: user_signature.clone(),
+                start_block,
+            },
+            options,
+        )
+        .await,
+    )
+    .await
+    .and_then(|r| match r {
+        ApiResponse::Response::<common_msgs::DeleteAppointmentResponse>(r) => {
+            let receipt =
+                DeletionReceipt::with_signature(user_signature, r.deletion_block, r.signature);
+            if receipt.verify(&tower_id) {
+                Ok((r.available_slots, receipt))
+            } else {
+                Err(RequestError::Unexpected(format!(
+                    "The deletion receipt for {locator} is not properly signed by {tower_id}"
+                )))
+            }
+        }
+        ApiResponse::Error(e) => Err(RequestError::Rejected(format!(
+            "{tower_id} refused to delete {locator}. Error: {}, error_code: {}",
+            e.error, e.error_code
+        ))),
     })
 }
 
@@ -90,7 +355,7 @@ pub async fn register(
 pub async fn add_appointment(
     tower_id: TowerId,
     tower_net_addr: &NetAddr,
-    proxy: &Option<ProxyInfo>,
+    options: &RequestOptions,
     appointment: &Appointment,
     signature: &str,
 ) -> Result<(u32, AppointmentReceipt), AddAppointmentError> {
@@ -99,7 +364,7 @@ pub async fn add_appointment(
         appointment.locator
     );
     let (response, receipt) =
-        send_appointment(tower_id, tower_net_addr, proxy, appointment, signature).await?;
+        send_appointment(tower_id, tower_net_addr, options, appointment, signature).await?;
     log::debug!("Appointment accepted and signed by {tower_id}");
     log::debug!("Remaining slots: {}", response.available_slots);
     log::debug!("Start block: {}", response.start_block);
@@ -111,26 +376,43 @@ pub async fn add_appointment(
 pub async fn send_appointment(
     tower_id: TowerId,
     tower_net_addr: &NetAddr,
-    proxy: &Option<ProxyInfo>,
+    options: &RequestOptions,
     appointment: &Appointment,
     signature: &str,
 ) -> Result<(common_msgs::AddAppointmentResponse, AppointmentReceipt), AddAppointmentError> {
     let request_data = common_msgs::AddAppointmentRequest {
         appointment: Some(appointment.clone().into()),
         signature: signature.to_owned(),
+        ttl: None,
+        priority: None,
+        reward: None,
     };
 
-    match process_post_response(
-        post_request(
-            tower_net_addr,
-            Endpoint::AddAppointment,
-            &request_data,
-            proxy,
+    let response = if options.compact_appointments {
+        process_protobuf_post_response(
+            post_protobuf_request(
+                tower_net_addr,
+                Endpoint::AddAppointment,
+                &request_data,
+                options,
+            )
+            .await,
         )
-        .await,
-    )
-    .await?
-    {
+        .await?
+    } else {
+        process_post_response(
+            post_request(
+                tower_net_addr,
+                Endpoint::AddAppointment,
+                &request_data,
+                options,
+            )
+            .await,
+        )
+        .await?
+    };
+
+    match response {
         ApiResponse::Response::<common_msgs::AddAppointmentResponse>(r) => {
             let receipt = AppointmentReceipt::with_signature(
                 signature.to_owned(),
@@ -154,48 +436,58 @@ pub async fn send_appointment(
     }
 }
 
+/// The body of a request sent to a tower.
+enum Body<S> {
+    Json(S),
+    /// Compact binary (protobuf) encoding. The tower is asked to answer using the same encoding.
+    Protobuf(Vec<u8>),
+}
+
 /// A generic function to send a request to a tower.
 async fn request<S: Serialize>(
     tower_net_addr: &NetAddr,
     endpoint: Endpoint,
-    proxy: &Option<ProxyInfo>,
+    options: &RequestOptions,
     method: Method,
-    data: Option<S>,
+    data: Option<Body<S>>,
 ) -> Result<Response, RequestError> {
-    let client = if let Some(proxy) = proxy {
-        if proxy.always_use || tower_net_addr.is_onion() {
-            reqwest::Client::builder()
-                .proxy(
-                    reqwest::Proxy::http(proxy.get_socks_addr())
-                        .map_err(|e| RequestError::ConnectionError(format!("{e}")))?,
-                )
-                .build()
-                .map_err(|e| RequestError::ConnectionError(format!("{e}")))?
-        } else {
-            reqwest::Client::new()
-        }
-    } else {
-        // If there is no proxy we only build the client as long as the address is not onion
-        if tower_net_addr.is_onion() {
-            return Err(RequestError::ConnectionError(
-                "Cannot connect to an onion address without a proxy".to_owned(),
-            ));
-        }
-        reqwest::Client::new()
-    };
-
-    let mut request_builder = client.request(
-        method,
-        format!("{}{}", tower_net_addr.net_addr(), endpoint.path()),
-    );
+    // If there is no proxy we only send the request as long as the address is not onion
+    if !options.use_proxy && tower_net_addr.is_onion() {
+        return Err(RequestError::ConnectionError(
+            "Cannot connect to an onion address without a proxy".to_owned(),
+        ));
+    }
 
-    if let Some(data) = data {
-        request_builder = request_builder.json(&data);
+    let mut request_builder = options
+        .client
+        .request(
+            method,
+            format!("{}{}", tower_net_addr.net_addr(), endpoint.path()),
+        )
+        .headers(options.headers.clone());
+
+    match data {
+        Some(Body::Json(data)) => request_builder = request_builder.json(&data),
+        Some(Body::Protobuf(data)) => {
+            request_builder = request_builder
+                .header(CONTENT_TYPE, PROTOBUF_CONTENT_TYPE)
+                .header(ACCEPT, PROTOBUF_CONTENT_TYPE)
+                .body(data)
+        }
+        None => (),
     }
 
     request_builder.send().await.map_err(|e| {
         log::debug!("An error ocurred when sending data to the tower: {e}");
-        if e.is_connect() | e.is_timeout() {
+        // Checked first, given a refused handshake also surfaces as a connection error.
+        if net::is_pin_mismatch(&e) {
+            RequestError::PinMismatch(
+                "Cannot connect to the tower. Its TLS certificate does not match the pinned one"
+                    .to_owned(),
+            )
+        } else if e.is_timeout() {
+            RequestError::Timeout("Cannot connect to the tower. Request timed out".to_owned())
+        } else if e.is_connect() {
             RequestError::ConnectionError(
                 "Cannot connect to the tower. Connection refused".to_owned(),
             )
@@ -209,17 +501,41 @@ pub async fn post_request<S: Serialize>(
     tower_net_addr: &NetAddr,
     endpoint: Endpoint,
     data: S,
-    proxy: &Option<ProxyInfo>,
+    options: &RequestOptions,
 ) -> Result<Response, RequestError> {
-    request(tower_net_addr, endpoint, proxy, Method::POST, Some(data)).await
+    request(
+        tower_net_addr,
+        endpoint,
+        options,
+        Method::POST,
+        Some(Body::Json(data)),
+    )
+    .await
+}
+
+/// Sends a post request to a tower using the compact binary (protobuf) encoding instead of JSON.
+pub async fn post_protobuf_request<M: Message>(
+    tower_net_addr: &NetAddr,
+    endpoint: Endpoint,
+    data: &M,
+    options: &RequestOptions,
+) -> Result<Response, RequestError> {
+    request::<()>(
+        tower_net_addr,
+        endpoint,
+        options,
+        Method::POST,
+        Some(Body::Protobuf(data.encode_to_vec())),
+    )
+    .await
 }
 
 pub async fn get_request(
     tower_net_addr: &NetAddr,
     endpoint: Endpoint,
-    proxy: &Option<ProxyInfo>,
+    options: &RequestOptions,
 ) -> Result<Response, RequestError> {
-    request::<()>(tower_net_addr, endpoint, proxy, Method::GET, None).await
+    request::<()>(tower_net_addr, endpoint, options, Method::GET, None).await
 }
 
 /// Generic function to process the response of a given post request.
@@ -235,12 +551,48 @@ pub async fn process_post_response<T: DeserializeOwned>(
     }
 }
 
+/// Processes the response of a post request sent using the compact binary encoding.
+///
+/// Only successful responses are binary encoded, errors (and the responses of towers that do not support the encoding)
+/// are parsed as JSON.
+pub async fn process_protobuf_post_response<T: Message + Default + DeserializeOwned>(
+    post_request: Result<Response, RequestError>,
+) -> Result<ApiResponse<T>, RequestError> {
+    let r = post_request?;
+    let is_protobuf = r
+        .headers()
+        .get(CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == PROTOBUF_CONTENT_TYPE);
+    let body = r.bytes().await.map_err(|e| {
+        RequestError::DeserializeError(format!("Unexpected response body. Error: {e}"))
+    })?;
+
+    if is_protobuf {
+        T::decode(body).map(ApiResponse::Response).map_err(|e| {
+            RequestError::DeserializeError(format!("Unexpected response body. Error: {e}"))
+        })
+    } else {
+        serde_json::from_slice(&body).map_err(|e| {
+            RequestError::DeserializeError(format!("Unexpected response body. Error: {e}"))
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use serde_json::json;
 
-    use crate::test_utils::get_dummy_add_appointment_response;
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use reqwest::header::HeaderMap;
+
+    use crate::constants::DEFAULT_WT_USER_AGENT;
+    use crate::net::{TlsPin, TowerHeaders};
+    use crate::test_utils::{
+        get_dummy_add_appointment_response, run_tls_server, TOWER_CERT_FINGERPRINT,
+    };
     use teos_common::test_utils::{
         generate_random_appointment, get_random_appointment_receipt,
         get_random_registration_receipt, get_random_user_id,
@@ -254,10 +606,16 @@ mod tests {
             let error_message = "error_msg";
             for error in [
                 RequestError::ConnectionError(error_message.to_owned()),
+                RequestError::Timeout(error_message.to_owned()),
                 RequestError::DeserializeError(error_message.to_owned()),
+                RequestError::Rejected(error_message.to_owned()),
+                RequestError::PinMismatch(error_message.to_owned()),
                 RequestError::Unexpected(error_message.to_owned()),
             ] {
-                if error == RequestError::ConnectionError(error_message.to_owned()) {
+                if matches!(
+                    error,
+                    RequestError::ConnectionError(_) | RequestError::Timeout(_)
+                ) {
                     assert!(error.is_connection())
                 } else {
                     assert!(!error.is_connection())
@@ -266,6 +624,80 @@ mod tests {
         }
     }
 
+    mod add_appointment_error {
+        use super::*;
+
+        #[test]
+        fn test_kind() {
+            let error_message = "error_msg";
+            let api_error = |error_code| {
+                AddAppointmentError::ApiError(ApiError {
+                    error: error_message.to_owned(),
+                    error_code,
+                })
+            };
+
+            for (error, kind) in [
+                (
+                    RequestError::ConnectionError(error_message.to_owned()).into(),
+                    ErrorKind::Connection,
+                ),
+                (
+                    RequestError::Unexpected(error_message.to_owned()).into(),
+                    ErrorKind::Unexpected,
+                ),
+                (
+                    RequestError::Timeout(error_message.to_owned()).into(),
+                    ErrorKind::Timeout,
+                ),
+                (
+                    RequestError::DeserializeError(error_message.to_owned()).into(),
+                    ErrorKind::Unsupported,
+                ),
+                (
+                    RequestError::Rejected(error_message.to_owned()).into(),
+                    ErrorKind::Rejected,
+                ),
+                (
+                    RequestError::PinMismatch(error_message.to_owned()).into(),
+                    ErrorKind::Security,
+                ),
+                (
+                    api_error(errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR),
+                    ErrorKind::Subscription,
+                ),
+                (
+                    api_error(errors::SERVICE_UNAVAILABLE),
+                    ErrorKind::RateLimited,
+                ),
+                (
+                    api_error(errors::INVALID_REQUEST_FORMAT),
+                    ErrorKind::Unsupported,
+                ),
+                (
+                    api_error(errors::APPOINTMENT_FIELD_TOO_BIG),
+                    ErrorKind::Rejected,
+                ),
+                (
+                    api_error(errors::APPOINTMENT_ALREADY_TRIGGERED),
+                    ErrorKind::Rejected,
+                ),
+                (api_error(errors::WRONG_FIELD_FORMAT), ErrorKind::Rejected),
+                (api_error(errors::WRONG_FIELD_SIZE), ErrorKind::Rejected),
+                (
+                    AddAppointmentError::SignatureError(MisbehaviorProof::new(
+                        generate_random_appointment(None).locator,
+                        get_random_appointment_receipt(cryptography::get_random_keypair().0),
+                        get_random_user_id(),
+                    )),
+                    ErrorKind::Misbehaving,
+                ),
+            ] {
+                assert_eq!(error.kind(), kind);
+            }
+        }
+    }
+
     #[tokio::test]
     async fn test_register() {
         let (tower_sk, tower_pk) = cryptography::get_random_keypair();
@@ -281,11 +713,104 @@ mod tests {
             .create_async()
             .await;
 
-        let receipt = register(
+        let (receipt, signature_version) = register(
             TowerId(tower_pk),
             registration_receipt.user_id(),
             &NetAddr::new(server.url()),
-            &None,
+            None,
+            &RequestOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        api_mock.assert_async().await;
+        assert_eq!(receipt, registration_receipt);
+        // The mock behaves like a legacy tower, which does not report any signature version
+        assert_eq!(signature_version, SignatureVersion::V1);
+    }
+
+    #[tokio::test]
+    async fn test_register_signature_version() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let mut registration_receipt = get_random_registration_receipt();
+        registration_receipt.sign(&tower_sk);
+
+        // The supported versions are advertised, and the one picked by the tower is returned
+        let mut response = json!(registration_receipt);
+        response["signature_version"] = json!(2);
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .match_body(mockito::Matcher::PartialJson(
+                json!({ "signature_versions": SignatureVersion::supported() }),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(response.to_string())
+            .create_async()
+            .await;
+
+        let (_, signature_version) = register(
+            TowerId(tower_pk),
+            registration_receipt.user_id(),
+            &NetAddr::new(server.url()),
+            None,
+            &RequestOptions::default(),
+        )
+        .await
+        .unwrap();
+        api_mock.assert_async().await;
+        assert_eq!(signature_version, SignatureVersion::V2);
+
+        // Towers agreeing on a version we don't know are not trusted
+        response["signature_version"] = json!(7);
+        let mut server = mockito::Server::new_async().await;
+        let _api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(response.to_string())
+            .create_async()
+            .await;
+
+        let error = register(
+            TowerId(tower_pk),
+            registration_receipt.user_id(),
+            &NetAddr::new(server.url()),
+            None,
+            &RequestOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error, RequestError::Unexpected(..)));
+    }
+
+    #[tokio::test]
+    async fn test_register_with_payment_preimage() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let mut registration_receipt = get_random_registration_receipt();
+        registration_receipt.sign(&tower_sk);
+        let preimage = [7; 32];
+
+        // The preimage is sent along with the user id
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .match_body(mockito::Matcher::PartialJson(
+                json!({ "payment_preimage": hex::encode(preimage) }),
+            ))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(registration_receipt).to_string())
+            .create_async()
+            .await;
+
+        let (receipt, _) = register(
+            TowerId(tower_pk),
+            registration_receipt.user_id(),
+            &NetAddr::new(server.url()),
+            Some(&preimage),
+            &RequestOptions::default(),
         )
         .await
         .unwrap();
@@ -300,7 +825,8 @@ mod tests {
             get_random_user_id(),
             get_random_user_id(),
             &NetAddr::new("http://server_addr".to_owned()),
-            &None,
+            None,
+            &RequestOptions::default(),
         )
         .await
         .unwrap_err();
@@ -308,6 +834,119 @@ mod tests {
         assert!(matches!(error, RequestError::ConnectionError { .. }))
     }
 
+    #[tokio::test]
+    async fn test_register_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(ApiError {
+                    error: "Subscription cannot be renewed yet".to_owned(),
+                    error_code: errors::REGISTRATION_RENEWAL_TOO_EARLY,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let tower_id = get_random_user_id();
+        let error = register(
+            tower_id,
+            get_random_user_id(),
+            &NetAddr::new(server.url()),
+            None,
+            &RequestOptions::default(),
+        )
+        .await
+        .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert_eq!(
+            error,
+            RequestError::Rejected(format!(
+                "{tower_id} rejected the registration. Error: Subscription cannot be renewed yet, error_code: {}",
+                errors::REGISTRATION_RENEWAL_TOO_EARLY
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_payment_required() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(402)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(ApiError {
+                    error: "Pay lnbcrt1invoice".to_owned(),
+                    error_code: errors::REGISTRATION_PAYMENT_REQUIRED,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let tower_id = get_random_user_id();
+        let error = register(
+            tower_id,
+            get_random_user_id(),
+            &NetAddr::new(server.url()),
+            None,
+            &RequestOptions::default(),
+        )
+        .await
+        .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert_eq!(
+            error,
+            RequestError::PaymentRequired(format!(
+                "{tower_id} requires a payment. Pay lnbcrt1invoice"
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_user_not_allowed() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(403)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(ApiError {
+                    error: "This user is not allowed to register with the tower".to_owned(),
+                    error_code: errors::REGISTRATION_USER_NOT_ALLOWED,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let tower_id = get_random_user_id();
+        let user_id = get_random_user_id();
+        let error = register(
+            tower_id,
+            user_id,
+            &NetAddr::new(server.url()),
+            None,
+            &RequestOptions::default(),
+        )
+        .await
+        .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert_eq!(
+            error,
+            RequestError::Rejected(format!(
+                "{tower_id} does not allow {user_id} to register. Ask the tower operator for access"
+            ))
+        );
+    }
+
     #[tokio::test]
     async fn test_register_deserialize_error() {
         let mut server = mockito::Server::new_async().await;
@@ -323,7 +962,8 @@ mod tests {
             get_random_user_id(),
             get_random_user_id(),
             &NetAddr::new(server.url()),
-            &None,
+            None,
+            &RequestOptions::default(),
         )
         .await
         .unwrap_err();
@@ -332,6 +972,242 @@ mod tests {
         assert!(matches!(error, RequestError::DeserializeError { .. }))
     }
 
+    #[tokio::test]
+    async fn test_get_tower_info() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("GET", Endpoint::GetTowerInfo.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"price_per_slot": 10, "payment_methods": ["bolt11", "bolt12"], "subscription_duration": 4320}"#,
+            )
+            .create_async()
+            .await;
+
+        let terms = get_tower_info(&NetAddr::new(server.url()), &RequestOptions::default())
+            .await
+            .unwrap();
+
+        api_mock.assert_async().await;
+        assert_eq!(
+            terms,
+            Some(TowerTerms {
+                price_per_slot: 10,
+                payment_methods: vec!["bolt11".to_owned(), "bolt12".to_owned()],
+                subscription_duration: 4320,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_get_tower_info_not_advertised() {
+        // Towers that do not expose the endpoint simply have no terms
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("GET", Endpoint::GetTowerInfo.path().as_str())
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let terms = get_tower_info(&NetAddr::new(server.url()), &RequestOptions::default())
+            .await
+            .unwrap();
+
+        api_mock.assert_async().await;
+        assert_eq!(terms, None);
+    }
+
+    #[tokio::test]
+    async fn test_get_tower_info_deserialize_error() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("GET", Endpoint::GetTowerInfo.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "price_per_slot": "free" }).to_string())
+            .create_async()
+            .await;
+
+        let error = get_tower_info(&NetAddr::new(server.url()), &RequestOptions::default())
+            .await
+            .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert!(matches!(error, RequestError::DeserializeError { .. }))
+    }
+
+    #[tokio::test]
+    async fn test_get_fee_estimate() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::GetFeeEstimate.path().as_str())
+            .match_body(mockito::Matcher::Json(json!({ "conf_target": 6 })))
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!({ "conf_target": 6, "feerate": 25.5 }).to_string())
+            .create_async()
+            .await;
+
+        let feerate = get_fee_estimate(&NetAddr::new(server.url()), &RequestOptions::default(), 6)
+            .await
+            .unwrap();
+
+        api_mock.assert_async().await;
+        assert_eq!(feerate, 25.5);
+    }
+
+    #[tokio::test]
+    async fn test_get_fee_estimate_unavailable() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::GetFeeEstimate.path().as_str())
+            .with_status(503)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(ApiError {
+                    error: "No fee estimate is currently available".to_owned(),
+                    error_code: errors::SERVICE_UNAVAILABLE,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let error = get_fee_estimate(&NetAddr::new(server.url()), &RequestOptions::default(), 6)
+            .await
+            .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert!(matches!(error, RequestError::Rejected { .. }))
+    }
+
+    #[tokio::test]
+    async fn test_delete_appointment() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let (user_sk, _) = cryptography::get_random_keypair();
+        let locator = generate_random_appointment(None).locator;
+
+        let user_signature = cryptography::sign(
+            &teos_common::delete_appointment_message(TowerId(tower_pk), locator, 21),
+            &user_sk,
+        )
+        .unwrap();
+        let mut deletion_receipt = DeletionReceipt::new(user_signature, 42);
+        deletion_receipt.sign(&tower_sk);
+
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::DeleteAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(common_msgs::DeleteAppointmentResponse {
+                    locator: locator.to_vec(),
+                    deletion_block: deletion_receipt.deletion_block(),
+                    signature: deletion_receipt.signature().unwrap(),
+                    available_slots: 21,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let (slots, receipt) = delete_appointment(
+            TowerId(tower_pk),
+            &NetAddr::new(server.url()),
+            &RequestOptions::default(),
+            locator,
+            21,
+            &user_sk,
+        )
+        .await
+        .unwrap();
+
+        api_mock.assert_async().await;
+        assert_eq!(slots, 21);
+        assert_eq!(receipt, deletion_receipt);
+    }
+
+    #[tokio::test]
+    async fn test_delete_appointment_wrong_signature() {
+        let (user_sk, _) = cryptography::get_random_keypair();
+        let locator = generate_random_appointment(None).locator;
+
+        // The receipt is signed by someone other than the tower
+        let tower_id = get_random_user_id();
+        let user_signature = cryptography::sign(
+            &teos_common::delete_appointment_message(tower_id, locator, 21),
+            &user_sk,
+        )
+        .unwrap();
+        let mut deletion_receipt = DeletionReceipt::new(user_signature, 42);
+        deletion_receipt.sign(&cryptography::get_random_keypair().0);
+
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::DeleteAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(common_msgs::DeleteAppointmentResponse {
+                    locator: locator.to_vec(),
+                    deletion_block: deletion_receipt.deletion_block(),
+                    signature: deletion_receipt.signature().unwrap(),
+                    available_slots: 21,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let error = delete_appointment(
+            tower_id,
+            &NetAddr::new(server.url()),
+            &RequestOptions::default(),
+            locator,
+            21,
+            &user_sk,
+        )
+        .await
+        .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert!(matches!(error, RequestError::Unexpected { .. }))
+    }
+
+    #[tokio::test]
+    async fn test_delete_appointment_rejected() {
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::DeleteAppointment.path().as_str())
+            .with_status(404)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(ApiError {
+                    error: "Appointment not found".to_owned(),
+                    error_code: errors::APPOINTMENT_NOT_FOUND,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let error = delete_appointment(
+            get_random_user_id(),
+            &NetAddr::new(server.url()),
+            &RequestOptions::default(),
+            generate_random_appointment(None).locator,
+            21,
+            &cryptography::get_random_keypair().0,
+        )
+        .await
+        .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert!(matches!(error, RequestError::Rejected { .. }))
+    }
+
     #[tokio::test]
     async fn test_add_appointment() {
         // `add_appointment` is basically a pass trough function for `send_appointment` with some logging and a parse of the outputs
@@ -355,7 +1231,7 @@ mod tests {
         let (response, receipt) = add_appointment(
             TowerId(tower_pk),
             &NetAddr::new(server.url()),
-            &None,
+            &RequestOptions::default(),
             &appointment,
             appointment_receipt.user_signature(),
         )
@@ -367,6 +1243,49 @@ mod tests {
         assert_eq!(receipt, appointment_receipt);
     }
 
+    #[tokio::test]
+    async fn test_add_appointment_headers() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let appointment = generate_random_appointment(None);
+
+        let appointment_receipt = get_random_appointment_receipt(tower_sk);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &appointment_receipt);
+
+        // The User-Agent and the tower extra headers must be sent along with the request
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .match_header("user-agent", DEFAULT_WT_USER_AGENT)
+            .match_header("x-debug", "true")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(add_appointment_response).to_string())
+            .create_async()
+            .await;
+
+        let headers = TowerHeaders::new(
+            DEFAULT_WT_USER_AGENT,
+            &format!(r#"{{"{tower_id}": {{"X-Debug": "true"}}}}"#),
+        )
+        .unwrap();
+        add_appointment(
+            tower_id,
+            &NetAddr::new(server.url()),
+            &RequestOptions {
+                headers: headers.get(tower_id),
+                ..Default::default()
+            },
+            &appointment,
+            appointment_receipt.user_signature(),
+        )
+        .await
+        .unwrap();
+
+        api_mock.assert_async().await;
+    }
+
     #[tokio::test]
     async fn test_send_appointment() {
         let (tower_sk, tower_pk) = cryptography::get_random_keypair();
@@ -388,7 +1307,52 @@ mod tests {
         let (response, receipt) = send_appointment(
             TowerId(tower_pk),
             &NetAddr::new(server.url()),
-            &None,
+            &RequestOptions::default(),
+            &appointment,
+            appointment_receipt.user_signature(),
+        )
+        .await
+        .unwrap();
+
+        api_mock.assert_async().await;
+        assert_eq!(response, add_appointment_response);
+        assert_eq!(receipt, appointment_receipt);
+    }
+
+    #[tokio::test]
+    async fn test_send_appointment_compact() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let appointment = generate_random_appointment(None);
+
+        let appointment_receipt = get_random_appointment_receipt(tower_sk);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &appointment_receipt);
+        let options = RequestOptions::default().with_compact_appointments(true);
+
+        // The appointment is sent binary encoded, and so is the response
+        let mut server = mockito::Server::new_async().await;
+        let expected_appointment: common_msgs::Appointment = appointment.clone().into();
+        let encoded_response = add_appointment_response.encode_to_vec();
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .match_header("content-type", PROTOBUF_CONTENT_TYPE)
+            .match_header("accept", PROTOBUF_CONTENT_TYPE)
+            .with_status(200)
+            .with_header("content-type", PROTOBUF_CONTENT_TYPE)
+            .with_body_from_request(move |request| {
+                let request =
+                    common_msgs::AddAppointmentRequest::decode(request.body().unwrap().as_slice())
+                        .unwrap();
+                assert_eq!(request.appointment, Some(expected_appointment.clone()));
+                encoded_response.clone()
+            })
+            .create_async()
+            .await;
+
+        let (response, receipt) = send_appointment(
+            TowerId(tower_pk),
+            &NetAddr::new(server.url()),
+            &options,
             &appointment,
             appointment_receipt.user_signature(),
         )
@@ -398,6 +1362,33 @@ mod tests {
         api_mock.assert_async().await;
         assert_eq!(response, add_appointment_response);
         assert_eq!(receipt, appointment_receipt);
+
+        // Errors (and the responses of towers not supporting the encoding) are still JSON encoded
+        let api_error = ApiError {
+            error: "error_msg".to_owned(),
+            error_code: 1,
+        };
+        let mut server = mockito::Server::new_async().await;
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(json!(api_error).to_string())
+            .create_async()
+            .await;
+
+        let error = send_appointment(
+            TowerId(tower_pk),
+            &NetAddr::new(server.url()),
+            &options,
+            &appointment,
+            appointment_receipt.user_signature(),
+        )
+        .await
+        .unwrap_err();
+
+        api_mock.assert_async().await;
+        assert!(matches!(error, AddAppointmentError::ApiError(e) if e.error_code == 1));
     }
 
     #[tokio::test]
@@ -422,7 +1413,7 @@ mod tests {
         let error = send_appointment(
             tower_id,
             &NetAddr::new(server.url()),
-            &None,
+            &RequestOptions::default(),
             &appointment,
             appointment_receipt.user_signature(),
         )
@@ -449,7 +1440,7 @@ mod tests {
         let error = send_appointment(
             get_random_user_id(),
             &NetAddr::new("http://server_addr".to_owned()),
-            &None,
+            &RequestOptions::default(),
             &generate_random_appointment(None),
             "user_sig",
         )
@@ -477,7 +1468,7 @@ mod tests {
         let error = send_appointment(
             get_random_user_id(),
             &NetAddr::new(server.url()),
-            &None,
+            &RequestOptions::default(),
             &generate_random_appointment(None),
             "user_sig",
         )
@@ -511,7 +1502,7 @@ mod tests {
         let error = send_appointment(
             get_random_user_id(),
             &NetAddr::new(server.url()),
-            &None,
+            &RequestOptions::default(),
             &generate_random_appointment(None),
             "user_sig",
         )
@@ -537,9 +1528,9 @@ mod tests {
         let response_post = request(
             &NetAddr::new(server.url()),
             Endpoint::Register,
-            &None,
+            &RequestOptions::default(),
             Method::POST,
-            Some(json!("")),
+            Some(Body::Json(json!(""))),
         )
         .await;
 
@@ -557,7 +1548,7 @@ mod tests {
         let response_get = request::<()>(
             &NetAddr::new(server.url()),
             Endpoint::Ping,
-            &None,
+            &RequestOptions::default(),
             Method::GET,
             None,
         )
@@ -567,14 +1558,42 @@ mod tests {
         assert!(matches!(response_get, Ok(Response { .. })));
     }
 
+    #[tokio::test]
+    async fn test_request_tls_pin() {
+        let addr = run_tls_server("{}").await;
+        let net_addr = NetAddr::new(format!("https://localhost:{}", addr.port()));
+        let options = |tls_pin: &TlsPin| {
+            RequestOptions::new(
+                Arc::new(net::build_client(None, Some(tls_pin)).unwrap()),
+                false,
+                HeaderMap::new(),
+            )
+        };
+
+        // Towers presenting the pinned certificate are reached, even if it is self-signed
+        let tls_pin = TlsPin::from_str(TOWER_CERT_FINGERPRINT).unwrap();
+        assert!(get_request(&net_addr, Endpoint::Ping, &options(&tls_pin))
+            .await
+            .is_ok());
+
+        // But the connection is refused if the certificate does not match the pin
+        let tls_pin = TlsPin::from_certificate(b"another certificate");
+        let error = get_request(&net_addr, Endpoint::Ping, &options(&tls_pin))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, RequestError::PinMismatch(_)));
+        assert!(!error.is_connection());
+        assert_eq!(AddAppointmentError::from(error).kind(), ErrorKind::Security);
+    }
+
     #[tokio::test]
     async fn test_request_connection_error() {
         assert!(request(
             &NetAddr::new("http://unreachable_url".to_owned()),
             Endpoint::Register,
-            &None,
+            &RequestOptions::default(),
             Method::POST,
-            Some(json!("")),
+            Some(Body::Json(json!(""))),
         )
         .await
         .unwrap_err()
@@ -583,9 +1602,9 @@ mod tests {
         assert!(request(
             &NetAddr::new("http://unreachable_url".to_owned()),
             Endpoint::Ping,
-            &None,
+            &RequestOptions::default(),
             Method::GET,
-            None::<&str>,
+            None::<Body<()>>,
         )
         .await
         .unwrap_err()
@@ -601,7 +1620,12 @@ mod tests {
             .with_header("content-type", "application/json")
             .create_async()
             .await;
-        let response = get_request(&NetAddr::new(server.url()), Endpoint::Ping, &None).await;
+        let response = get_request(
+            &NetAddr::new(server.url()),
+            Endpoint::Ping,
+            &RequestOptions::default(),
+        )
+        .await;
 
         api_mock.assert_async().await;
 
@@ -613,7 +1637,7 @@ mod tests {
         assert!(get_request(
             &NetAddr::new("http://unreachable_url".to_owned()),
             Endpoint::Ping,
-            &None,
+            &RequestOptions::default(),
         )
         .await
         .unwrap_err()
@@ -634,7 +1658,7 @@ mod tests {
             &NetAddr::new(server.url()),
             Endpoint::Register,
             json!(""),
-            &None,
+            &RequestOptions::default(),
         )
         .await;
 
@@ -648,7 +1672,7 @@ mod tests {
             &NetAddr::new("http://unreachable_url".to_owned()),
             Endpoint::Register,
             json!(""),
-            &None,
+            &RequestOptions::default(),
         )
         .await
         .unwrap_err()
@@ -674,7 +1698,7 @@ mod tests {
                 &NetAddr::new(server.url()),
                 Endpoint::GetAppointment,
                 json!(""),
-                &None,
+                &RequestOptions::default(),
             )
             .await,
         )
@@ -1,7 +1,49 @@
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
 use cln_plugin::messages;
-use serde::Deserialize;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, ServerName};
+use serde::{Deserialize, Serialize};
+
+use bitcoin::hashes::{sha256, Hash};
+
+use teos_common::TowerId;
+
+use crate::constants::DEFAULT_WT_USER_AGENT;
+
 pub mod http;
 
+/// How long idle connections to the towers are kept in the pool.
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// TCP keepalive interval for the connections to the towers.
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// Reason given by the TLS layer when a tower presents a certificate that does not match its pin.
+const TLS_PIN_MISMATCH: &str = "TLS certificate does not match the pinned fingerprint";
+
+/// Headers that cannot be set by the user, given they define how requests are routed, authenticated or parsed.
+const PROTECTED_HEADERS: [HeaderName; 12] = [
+    header::AUTHORIZATION,
+    header::CONNECTION,
+    header::CONTENT_LENGTH,
+    header::CONTENT_TYPE,
+    header::COOKIE,
+    header::HOST,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    header::USER_AGENT,
+];
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct ProxyInfo {
     #[serde(flatten)]
@@ -23,3 +65,299 @@ impl ProxyInfo {
         format!("socks5h://{}:{}", self.inner.address, self.inner.port)
     }
 }
+
+/// The headers sent along with the requests to the towers.
+#[derive(Clone, Debug)]
+pub struct TowerHeaders {
+    /// The User-Agent sent to every tower.
+    user_agent: HeaderValue,
+    /// Additional headers sent to specific towers.
+    extra: HashMap<TowerId, HeaderMap>,
+}
+
+impl Default for TowerHeaders {
+    fn default() -> Self {
+        Self {
+            user_agent: HeaderValue::from_static(DEFAULT_WT_USER_AGENT),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+impl TowerHeaders {
+    /// Creates a new [TowerHeaders] instance.
+    ///
+    /// `extra_headers` is a JSON object mapping tower ids to the headers to be sent to them, e.g.
+    /// `{"<tower_id>": {"<name>": "<value>"}}`. An empty string means no extra headers. Protected headers are rejected.
+    pub fn new(user_agent: &str, extra_headers: &str) -> Result<Self, String> {
+        let user_agent =
+            HeaderValue::from_str(user_agent).map_err(|_| "Invalid User-Agent".to_owned())?;
+
+        let mut extra = HashMap::new();
+        if !extra_headers.is_empty() {
+            let towers: HashMap<String, HashMap<String, String>> =
+                serde_json::from_str(extra_headers)
+                    .map_err(|e| format!("Invalid extra headers: {e}"))?;
+
+            for (tower_id, headers) in towers {
+                let tower_id = TowerId::from_str(&tower_id)?;
+                let mut header_map = HeaderMap::new();
+                for (name, value) in headers {
+                    let name = HeaderName::from_str(&name)
+                        .map_err(|_| format!("Invalid header name: {name}"))?;
+                    if PROTECTED_HEADERS.contains(&name) {
+                        return Err(format!("{name} cannot be overwritten"));
+                    }
+                    let value = HeaderValue::from_str(&value)
+                        .map_err(|_| format!("Invalid value for header {name}"))?;
+                    header_map.insert(name, value);
+                }
+                extra.insert(tower_id, header_map);
+            }
+        }
+
+        Ok(Self { user_agent, extra })
+    }
+
+    /// Gets the headers to be sent to a given tower.
+    pub fn get(&self, tower_id: TowerId) -> HeaderMap {
+        let mut headers = self.extra.get(&tower_id).cloned().unwrap_or_default();
+        headers.insert(header::USER_AGENT, self.user_agent.clone());
+        headers
+    }
+
+    /// Gets the headers sent to every tower, including the ones whose id is not known.
+    pub fn common(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, self.user_agent.clone());
+        headers
+    }
+}
+
+/// SHA-256 fingerprint of the (DER encoded) TLS certificate a tower is expected to present.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct TlsPin(#[serde(with = "hex::serde")] [u8; 32]);
+
+impl TlsPin {
+    /// Computes the pin of a given DER encoded certificate.
+    pub fn from_certificate(der: &[u8]) -> Self {
+        Self(sha256::Hash::hash(der).into_inner())
+    }
+
+    /// Builds a pin from its raw bytes.
+    pub fn from_slice(data: &[u8]) -> Result<Self, String> {
+        <[u8; 32]>::try_from(data)
+            .map(Self)
+            .map_err(|_| "TLS pins must be 32-byte long".to_owned())
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+}
+
+impl FromStr for TlsPin {
+    type Err = String;
+
+    /// Parses a hex encoded pin. Bytes may be separated by colons, as output by `openssl x509 -fingerprint`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let data = hex::decode(s.replace(':', "")).map_err(|_| {
+            format!("Invalid TLS pin: {s}. Expected a hex encoded SHA-256 fingerprint")
+        })?;
+        Self::from_slice(&data).map_err(|_| {
+            format!("Invalid TLS pin: {s}. Expected a hex encoded SHA-256 fingerprint")
+        })
+    }
+}
+
+impl fmt::Display for TlsPin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+/// Verifies the certificate presented by a tower against its [TlsPin].
+///
+/// The pin replaces the CA based validation (so towers can also be pinned to self-signed certificates). The
+/// handshake signatures are still checked against the presented certificate.
+struct PinnedCertVerifier(TlsPin);
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if TlsPin::from_certificate(&end_entity.0) == self.0 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(TLS_PIN_MISMATCH.to_owned()))
+        }
+    }
+}
+
+/// Checks whether a request failed because the tower presented a certificate not matching its pin.
+pub(crate) fn is_pin_mismatch(error: &reqwest::Error) -> bool {
+    let mut source: Option<&(dyn StdError + 'static)> = Some(error);
+    while let Some(e) = source {
+        // The TLS error is wrapped in (possibly nested) io::Errors, which do not expose it as their source.
+        let mut e = e;
+        while let Some(inner) = e.downcast_ref::<std::io::Error>().and_then(|e| e.get_ref()) {
+            e = inner;
+        }
+        if matches!(e.downcast_ref::<rustls::Error>(), Some(rustls::Error::General(reason)) if reason == TLS_PIN_MISMATCH)
+        {
+            return true;
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Builds an HTTP client to reach the towers, either straight or through a given proxy.
+///
+/// If a [TlsPin] is given, the client only accepts connections presenting the pinned certificate, so it is only meant
+/// to reach the tower the pin belongs to.
+///
+/// Clients are meant to be shared across requests (see [RequestOptions]), so connections are pooled and kept alive.
+/// Building a client per request costs ~40ms (mainly TLS backend initialization) on top of a fresh TCP (and SOCKS,
+/// if proxied) handshake, while a request over a pooled connection to a local tower takes ~0.1ms.
+pub fn build_client(
+    proxy: Option<&ProxyInfo>,
+    tls_pin: Option<&TlsPin>,
+) -> Result<Client, reqwest::Error> {
+    let mut builder = Client::builder()
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .tcp_keepalive(TCP_KEEPALIVE);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(reqwest::Proxy::http(proxy.get_socks_addr())?);
+    }
+    if let Some(tls_pin) = tls_pin {
+        let tls = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier(*tls_pin)))
+            .with_no_client_auth();
+        builder = builder.use_preconfigured_tls(tls);
+    }
+    builder.build()
+}
+
+/// Builds the pair of clients used to reach the towers: a straight one, and one going through the proxy (if any).
+///
+/// See [build_client].
+pub fn build_clients(
+    proxy: Option<&ProxyInfo>,
+    tls_pin: Option<&TlsPin>,
+) -> Result<(Arc<Client>, Option<Arc<Client>>), reqwest::Error> {
+    let client = build_client(None, tls_pin)?;
+    let proxied_client = proxy
+        .map(|proxy| build_client(Some(proxy), tls_pin).map(Arc::new))
+        .transpose()?;
+    Ok((Arc::new(client), proxied_client))
+}
+
+/// Options used to build the requests sent to a given tower.
+#[derive(Clone, Debug, Default)]
+pub struct RequestOptions {
+    /// The client the requests are sent with. Shared across requests so connections can be reused.
+    pub client: Arc<Client>,
+    /// Whether `client` reaches the towers through the proxy.
+    pub use_proxy: bool,
+    /// The headers sent along with every request.
+    pub headers: HeaderMap,
+    /// Whether appointments are sent using the compact binary encoding instead of JSON.
+    pub compact_appointments: bool,
+}
+
+impl RequestOptions {
+    pub fn new(client: Arc<Client>, use_proxy: bool, headers: HeaderMap) -> Self {
+        Self {
+            client,
+            use_proxy,
+            headers,
+            compact_appointments: false,
+        }
+    }
+
+    /// Sets whether appointments are sent using the compact binary encoding instead of JSON.
+    pub fn with_compact_appointments(mut self, compact: bool) -> Self {
+        self.compact_appointments = compact;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use teos_common::test_utils::get_random_user_id;
+
+    #[test]
+    fn test_tower_headers() {
+        let tower_id = get_random_user_id();
+        let headers = TowerHeaders::new(
+            "custom-agent/1.0",
+            &format!(r#"{{"{tower_id}": {{"X-Debug": "true"}}}}"#),
+        )
+        .unwrap();
+
+        // The User-Agent is sent to every tower, extra headers only to the ones they were set for
+        let tower_headers = headers.get(tower_id);
+        assert_eq!(tower_headers[header::USER_AGENT], "custom-agent/1.0");
+        assert_eq!(tower_headers["x-debug"], "true");
+
+        let other_headers = headers.get(get_random_user_id());
+        assert_eq!(other_headers[header::USER_AGENT], "custom-agent/1.0");
+        assert_eq!(other_headers.len(), 1);
+
+        // Defaults to the plugin version
+        assert_eq!(
+            TowerHeaders::default().get(tower_id)[header::USER_AGENT],
+            DEFAULT_WT_USER_AGENT
+        );
+    }
+
+    #[test]
+    fn test_tower_headers_protected() {
+        let tower_id = get_random_user_id();
+        for name in ["Host", "content-type", "Authorization", "User-Agent"] {
+            assert!(TowerHeaders::new(
+                DEFAULT_WT_USER_AGENT,
+                &format!(r#"{{"{tower_id}": {{"{name}": "value"}}}}"#),
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_tower_headers_invalid() {
+        assert!(TowerHeaders::new("agent\n", "").is_err());
+        assert!(TowerHeaders::new(DEFAULT_WT_USER_AGENT, "not json").is_err());
+        assert!(TowerHeaders::new(DEFAULT_WT_USER_AGENT, r#"{"not_a_tower_id": {}}"#).is_err());
+    }
+
+    #[test]
+    fn test_tls_pin_from_str() {
+        let tls_pin = TlsPin::from_certificate(b"certificate");
+
+        // Pins can be given both as plain hex or with colon separated bytes (either upper or lower case)
+        let hex_pin = tls_pin.to_string();
+        assert_eq!(TlsPin::from_str(&hex_pin).unwrap(), tls_pin);
+        let colon_pin = hex::encode_upper(tls_pin.to_vec())
+            .as_bytes()
+            .chunks(2)
+            .map(|b| std::str::from_utf8(b).unwrap())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(TlsPin::from_str(&colon_pin).unwrap(), tls_pin);
+
+        // Anything that is not a 32-byte hex string is rejected
+        assert!(TlsPin::from_str(&hex_pin[2..]).is_err());
+        assert!(TlsPin::from_str(&format!("{hex_pin}00")).is_err());
+        assert!(TlsPin::from_str("not a pin").is_err());
+    }
+}
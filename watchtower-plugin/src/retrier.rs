@@ -1,22 +1,59 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::mpsc::{error::TryRecvError, UnboundedReceiver};
+use tokio::task::JoinHandle;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use backoff::future::retry_notify;
 use backoff::{Error, ExponentialBackoff};
 
+use bitcoin::hashes::{sha256, Hash};
+
 use teos_common::appointment::Locator;
-use teos_common::cryptography;
 use teos_common::errors;
+use teos_common::receipts::AppointmentReceipt;
+use teos_common::UserId;
 use teos_common::UserId as TowerId;
 
-use crate::net::http::{self, AddAppointmentError};
-use crate::wt_client::{RevocationData, WTClient};
+use crate::constants;
+use crate::net::http::{self, AddAppointmentError, ErrorKind, RequestError};
+use crate::signer::{Signer, SigningError};
+use crate::wt_client::{RevocationData, TowerError, WTClient};
 use crate::{MisbehaviorProof, TowerStatus};
 
-const POLLING_TIME: u64 = 1;
+/// Minimum time (in milliseconds) the [RetryManager] can be set to wait between polls.
+pub const MIN_POLLING_INTERVAL: u64 = 100;
+/// Number of successful deliveries after which a [Retrier] persists them to the database.
+pub const DELIVERY_BATCH_SIZE: usize = 50;
+/// Maximum number of pending appointments a [Retrier] goes through before yielding to the runtime.
+pub const RETRY_BATCH_SIZE: usize = 100;
+/// Maximum time (in seconds) the [RetryManager] waits for the retriers to stop once the client is shutting down.
+/// lightningd gives plugins 30 seconds to exit after notifying the shutdown, so this is kept well below that.
+pub const SHUTDOWN_GRACE_PERIOD: u64 = 20;
+
+/// Current Unix time, in seconds.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Locks a mutex, recovering its data if it was poisoned (i.e. something panicked while holding it).
+///
+/// Retriers run on tasks of their own, so a panic while holding the lock would otherwise bring all of them (and the
+/// [RetryManager]) down with it. The poison flag is cleared, so it is only reported once.
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| {
+        log::error!("Recovering from a poisoned lock. A previous holder panicked");
+        mutex.clear_poison();
+        e.into_inner()
+    })
+}
 
 #[derive(Eq, PartialEq, Debug)]
 enum RetryError {
@@ -25,6 +62,16 @@ enum RetryError {
     Unreachable,
     Misbehaving(MisbehaviorProof),
     Abandoned,
+    // The tower presented a TLS certificate not matching its pin. Retrying won't help until the pin is reviewed
+    Security(String),
+    // The database could not be queried (e.g. because it is busy). Worth trying again later
+    Database,
+    // The appointments could not be signed. bool marks whether the error is permanent or not
+    Signing(String, bool),
+    // The client is shutting down. Whatever is left pending is retried on the next start
+    ShuttingDown,
+    // The tower replied with something that could not be made sense of (e.g. a malformed response)
+    UnexpectedResponse(String),
 }
 
 impl Display for RetryError {
@@ -34,6 +81,11 @@ impl Display for RetryError {
             RetryError::Unreachable => write!(f, "Tower cannot be reached"),
             RetryError::Misbehaving(_) => write!(f, "Tower misbehaved"),
             RetryError::Abandoned => write!(f, "Tower was abandoned. Skipping retry"),
+            RetryError::Security(r) => write!(f, "{r}"),
+            RetryError::Database => write!(f, "Cannot read from the database"),
+            RetryError::Signing(r, _) => write!(f, "Cannot sign the appointments. {r}"),
+            RetryError::ShuttingDown => write!(f, "The client is shutting down"),
+            RetryError::UnexpectedResponse(r) => write!(f, "Unexpected response from tower. {r}"),
         }
     }
 }
@@ -42,7 +94,11 @@ impl RetryError {
     fn is_permanent(&self) -> bool {
         matches!(
             self,
-            RetryError::Subscription(_, true) | RetryError::Misbehaving(_) | RetryError::Abandoned
+            RetryError::Subscription(_, true)
+                | RetryError::Misbehaving(_)
+                | RetryError::Abandoned
+                | RetryError::Security(_)
+                | RetryError::Signing(_, true)
         )
     }
 }
@@ -53,7 +109,15 @@ pub struct RetryManager {
     max_elapsed_time_secs: u16,
     auto_retry_delay: u32,
     max_interval_time_secs: u16,
+    max_retries: Option<u32>,
+    /// Backoff (`max_elapsed_time_secs`, `max_interval_time_secs`) used by the retriers of aggressive manual retries.
+    aggressive_backoff: (u16, u16),
+    polling_interval: Duration,
     retriers: HashMap<TowerId, Arc<Retrier>>,
+    /// Tasks of the retriers that have been started, so they can be waited for on shutdown.
+    tasks: Vec<JoinHandle<()>>,
+    /// Time of the last loop iteration, shared with the [WTClient] so it can be used as a liveness check.
+    last_tick: Arc<AtomicU64>,
 }
 
 impl RetryManager {
@@ -63,19 +127,58 @@ impl RetryManager {
         max_elapsed_time_secs: u16,
         auto_retry_delay: u32,
         max_interval_time_secs: u16,
+        polling_interval_millis: u64,
     ) -> Self {
+        if polling_interval_millis < MIN_POLLING_INTERVAL {
+            log::warn!(
+                "Retry polling interval ({polling_interval_millis}ms) is below the minimum. Using {MIN_POLLING_INTERVAL}ms instead"
+            );
+        }
+
+        let last_tick = lock(&wt_client).retry_manager_tick.clone();
+
         RetryManager {
             wt_client,
             unreachable_towers,
             max_elapsed_time_secs,
             auto_retry_delay,
             max_interval_time_secs,
+            max_retries: None,
+            aggressive_backoff: (
+                constants::DEFAULT_WT_AGGRESSIVE_MAX_RETRY_TIME as u16,
+                constants::DEFAULT_WT_AGGRESSIVE_MAX_RETRY_INTERVAL as u16,
+            ),
+            polling_interval: Duration::from_millis(
+                polling_interval_millis.max(MIN_POLLING_INTERVAL),
+            ),
             retriers: HashMap::new(),
+            tasks: Vec::new(),
+            last_tick,
         }
     }
 
+    /// Caps the number of attempts a retrier will make to reach a tower before giving up, regardless of the elapsed time.
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Sets the backoff used when a tower is manually retried aggressively (see [WTClient::retry_tower_aggressively]).
+    ///
+    /// Aggressive retries are not capped by `max_retries`, they are meant to try hard for a short time instead.
+    pub fn with_aggressive_backoff(
+        mut self,
+        max_elapsed_time_secs: u16,
+        max_interval_time_secs: u16,
+    ) -> Self {
+        self.aggressive_backoff = (max_elapsed_time_secs, max_interval_time_secs);
+        self
+    }
+
     /// Starts the retry manager's main logic loop.
-    /// This method will keep running until the `unreachable_towers` sender disconnects.
+    /// This method will keep running until the `unreachable_towers` sender disconnects or the [WTClient] is shutting
+    /// down. In the latter case, it waits for the running retriers to stop before returning, so everything they have
+    /// delivered is persisted before the database is closed.
     ///
     /// It will receive a `(tower_id, revocation_data)` pair and try to send all the appointments contained
     /// in `revocation_data` (identified by `locator`) to the tower with `tower_id`. This is done by spawning
@@ -83,22 +186,24 @@ impl RetryManager {
     ///
     /// The content of [RevocationData] will depend on who called `unreachable_towers.send`:
     ///     - If it was called by `on_commitment_revocation`, the data will be fresh and contain a single locator
-    ///     - If it was called by the [WTClient] constructor, or by manually retrying, then the data will the stale
-    ///       and contain a `HashSet<locator>` with, potentially, many locators.
+    ///     - If it was called by manually retrying, then the data will the stale and contain a `HashSet<locator>` with,
+    ///       potentially, many locators.
+    ///
+    /// The stale data found by the [WTClient] on startup is not sent through the channel, but pulled from the client
+    /// whenever the channel is empty, following its [StaleFeed](crate::wt_client::StaleFeed) strategy.
     pub async fn manage_retry(&mut self) {
         log::info!("Starting retry manager");
 
         loop {
+            self.last_tick.store(now(), Ordering::Relaxed);
+            if lock(&self.wt_client).is_shutting_down() {
+                self.wind_down().await;
+                break;
+            }
             match self.unreachable_towers.try_recv() {
                 Ok((tower_id, data)) => {
                     // Not start a retry if the tower is flagged to be abandoned
-                    if !self
-                        .wt_client
-                        .lock()
-                        .unwrap()
-                        .towers
-                        .contains_key(&tower_id)
-                    {
+                    if !lock(&self.wt_client).towers.contains_key(&tower_id) {
                         log::info!("Skipping retrying abandoned tower {tower_id}");
                     } else if let Some(retrier) = self.retriers.get(&tower_id) {
                         if retrier.is_idle() {
@@ -110,20 +215,12 @@ impl RetryManager {
                                 "Manually finished idling. Flagging {} for retry",
                                 retrier.tower_id
                             );
-                            // While a retrier is idle data is not kept in memory.
-                            // Load the pending appointments from the DB and feed them to the retrier
-                            retrier.set_status(RetrierStatus::Stopped);
-                            retrier.pending_appointments.lock().unwrap().extend(
-                                self.wt_client
-                                    .lock()
-                                    .unwrap()
-                                    .dbm
-                                    .load_appointment_locators(
-                                        retrier.tower_id,
-                                        crate::AppointmentStatus::Pending,
-                                    ),
-                            );
+                            self.resume_idle_retrier(retrier);
                         } else {
+                            let fresh = matches!(data, RevocationData::Fresh(_));
+                            if fresh && retrier.failed() {
+                                self.revive_failed_retrier(retrier);
+                            }
                             self.add_pending_appointments(tower_id, data.into());
                         }
                     } else {
@@ -131,20 +228,46 @@ impl RetryManager {
                     }
                 }
                 Err(TryRecvError::Empty) => {
+                    // Feed the stale data found on startup, if there is any left.
+                    let stale_batch = lock(&self.wt_client).next_stale_batch();
+                    for (tower_id, locators) in stale_batch {
+                        self.add_pending_appointments(tower_id, locators);
+                    }
+                    // Retry the invalid appointments that are due, if any.
+                    let recovered = lock(&self.wt_client).recover_invalid_appointments();
+                    for (tower_id, locators) in recovered {
+                        self.add_pending_appointments(tower_id, locators);
+                    }
+                    // Renew the subscriptions that are about to expire, if any. This is done in the background so
+                    // slow towers do not hold the loop.
+                    let renewals = lock(&self.wt_client).towers_to_renew();
+                    for tower_id in renewals {
+                        let wt_client = self.wt_client.clone();
+                        self.tasks.push(tokio::spawn(async move {
+                            log::info!("Renewing the subscription with {tower_id}");
+                            if let Err(e) = WTClient::renew_subscription(&wt_client, tower_id).await
+                            {
+                                log::warn!("Cannot renew the subscription with {tower_id}. {e}");
+                            }
+                        }));
+                    }
                     // Keep only running retriers and retriers ready to be started/re-started.
                     // This will remove failed ones and ones finished successfully and have no pending appointments.
                     //
-                    // Note that a failed retrier could have received some new appointments to retry. In this case, we don't try to send
-                    // them because we know that that tower is unreachable. We most likely received these new appointments while the tower
-                    // was still flagged as temporarily unreachable when cleaning up after giving up retrying.
+                    // Note that a failed retrier could have received some new appointments to retry. Fresh ones have already
+                    // brought it back to stopped (see `revive_failed_retrier`), so it is only removed here if what it received
+                    // is stale, most likely while the tower was still flagged as temporarily unreachable when cleaning up after
+                    // giving up retrying.
                     self.retriers.retain(|_, retrier| {
                         retrier.remove_if_failed();
                         retrier.should_start() || retrier.is_running() || retrier.is_idle()
                     });
                     // Start all the ready retriers.
+                    self.tasks.retain(|task| !task.is_finished());
+                    let mut started = Vec::new();
                     for retrier in self.retriers.values() {
                         if retrier.should_start() {
-                            self.start_retrying(retrier.clone());
+                            started.push(self.start_retrying(retrier.clone()));
                         // Effectively this is the same as `if retrier.is_idle` plus returning for how long is true.
                         } else if let Some(t) = retrier.get_elapsed_time() {
                             if t > self.auto_retry_delay as u64 {
@@ -152,30 +275,68 @@ impl RetryManager {
                                     "Finished idling. Flagging {} for retry",
                                     retrier.tower_id
                                 );
-                                // While a retrier is idle data is not kept in memory.
-                                // Load the pending appointments from the DB and feed them to the retrier
-                                retrier.set_status(RetrierStatus::Stopped);
-                                retrier.pending_appointments.lock().unwrap().extend(
-                                    self.wt_client
-                                        .lock()
-                                        .unwrap()
-                                        .dbm
-                                        .load_appointment_locators(
-                                            retrier.tower_id,
-                                            crate::AppointmentStatus::Pending,
-                                        ),
-                                );
+                                self.resume_idle_retrier(retrier);
                             }
                         }
                     }
+                    self.tasks.extend(started);
                     // Sleep to not waste a lot of CPU cycles.
-                    tokio::time::sleep(Duration::from_secs(POLLING_TIME)).await;
+                    tokio::time::sleep(self.polling_interval).await;
                 }
                 Err(TryRecvError::Disconnected) => break,
             }
         }
     }
 
+    /// Flags an idle retrier for retry.
+    ///
+    /// While a retrier is idle data is not kept in memory, so the pending appointments are loaded from the DB and fed to
+    /// the retrier. If the DB cannot be read (e.g. because it is busy), the retrier is kept idle so it can be resumed later on.
+    /// Appointments that have been pending for too long (see [WTClient::max_pending_age]) are expired instead of reloaded.
+    fn resume_idle_retrier(&self, retrier: &Retrier) {
+        let locators = {
+            let mut wt_client = lock(&self.wt_client);
+            wt_client.expire_stale_pending_appointments(retrier.tower_id);
+            wt_client
+                .dbm
+                .load_appointment_locators(retrier.tower_id, crate::AppointmentStatus::Pending)
+        };
+
+        match locators {
+            Ok(locators) => {
+                retrier.set_status(RetrierStatus::Stopped);
+                lock(&retrier.pending_appointments).extend(locators);
+            }
+            Err(e) => log::warn!(
+                "Cannot load the pending appointments of {}. Keeping it idle. Error: {e:?}",
+                retrier.tower_id
+            ),
+        }
+    }
+
+    /// Gives a failed retrier another go after receiving fresh data for its tower.
+    ///
+    /// Failed retriers are removed on the next tick, which would strand the data they hold in the DB with no retrier to
+    /// send it. Fresh data means the tower is worth sending appointments to again, so the retrier is stopped instead, and
+    /// re-evaluated (and re-started) alongside the rest. Misbehaving towers are never given another go.
+    fn revive_failed_retrier(&self, retrier: &Retrier) {
+        let misbehaving = lock(&self.wt_client)
+            .get_tower_status(&retrier.tower_id)
+            .is_none_or(|status| status.is_misbehaving());
+        if misbehaving {
+            log::debug!(
+                "Not reviving the failed retrier of misbehaving tower {}",
+                retrier.tower_id
+            );
+        } else {
+            log::info!(
+                "Received fresh data for {}. Reviving its failed retrier",
+                retrier.tower_id
+            );
+            retrier.set_status(RetrierStatus::Stopped);
+        }
+    }
+
     /// Adds an appointment to pending for a given tower.
     ///
     /// If the tower is not currently being retried, a new entry for it is created, otherwise, the data is appended to the existing entry.
@@ -188,13 +349,8 @@ impl RetryManager {
                 locators,
             )));
         } else {
-            let mut pending_appointments = self
-                .retriers
-                .get(&tower_id)
-                .unwrap()
-                .pending_appointments
-                .lock()
-                .unwrap();
+            let mut pending_appointments =
+                lock(&self.retriers.get(&tower_id).unwrap().pending_appointments);
             for locator in locators {
                 log::debug!("Adding pending appointment {locator} to existing tower {tower_id}",);
                 pending_appointments.insert(locator);
@@ -202,9 +358,56 @@ impl RetryManager {
         }
     }
 
-    fn start_retrying(&self, retrier: Arc<Retrier>) {
-        log::info!("Retrying tower {}", retrier.tower_id);
-        retrier.start(self.max_elapsed_time_secs, self.max_interval_time_secs);
+    fn start_retrying(&self, retrier: Arc<Retrier>) -> JoinHandle<()> {
+        let aggressive = lock(&self.wt_client)
+            .aggressive_retries
+            .remove(&retrier.tower_id);
+        if aggressive {
+            log::info!("Retrying tower {} aggressively", retrier.tower_id);
+            let (max_elapsed_time_secs, max_interval_time_secs) = self.aggressive_backoff;
+            retrier.start(
+                max_elapsed_time_secs,
+                max_interval_time_secs,
+                None,
+                self.auto_retry_delay,
+            )
+        } else {
+            log::info!("Retrying tower {}", retrier.tower_id);
+            retrier.start(
+                self.max_elapsed_time_secs,
+                self.max_interval_time_secs,
+                self.max_retries,
+                self.auto_retry_delay,
+            )
+        }
+    }
+
+    /// Waits for the tasks of the started retriers to finish.
+    ///
+    /// Retriers stop sending appointments once the [WTClient] is shutting down, but the ones in flight are awaited and
+    /// the deliveries persisted before the tasks finish. Retriers that have not stopped after [SHUTDOWN_GRACE_PERIOD]
+    /// seconds are aborted.
+    async fn wind_down(&mut self) {
+        log::info!(
+            "Shutting down retry manager. Waiting for {} retrier(s) to stop",
+            self.tasks.iter().filter(|task| !task.is_finished()).count()
+        );
+        let tasks = &mut self.tasks;
+        let stopped = tokio::time::timeout(Duration::from_secs(SHUTDOWN_GRACE_PERIOD), async {
+            for task in tasks.iter_mut() {
+                if let Err(e) = task.await {
+                    log::error!("Retrier task did not finish cleanly. Error: {e}");
+                }
+            }
+        })
+        .await;
+        if stopped.is_err() {
+            // Appointments that are not flagged as delivered are still pending in the database, so they are sent again on
+            // the next start
+            log::warn!("Retriers did not stop in time. Aborting them");
+            self.tasks.iter().for_each(|task| task.abort());
+        }
+        self.tasks.clear();
     }
 }
 
@@ -219,7 +422,8 @@ pub enum RetrierStatus {
     ///
     /// If a retrier status is `Running`, then its associated tower is either temporary unreachable or subscription error.
     Running,
-    /// Retrier failed retrying the tower. Should not be re-started.
+    /// Retrier failed retrying the tower. Should not be re-started, unless it receives fresh data for a tower that is not
+    /// misbehaving, in which case it is brought back to `Stopped`.
     ///
     /// If a retrier status is `Failed`, then its associated tower is neither reachable nor temporary unreachable.
     Failed,
@@ -261,11 +465,136 @@ impl RetrierStatus {
     }
 }
 
+/// [RetrierStatus] is (de)serialized through its [RetrierStatusInfo] representation.
+impl Serialize for RetrierStatus {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        RetrierStatusInfo::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RetrierStatus {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        RetrierStatusInfo::deserialize(deserializer).map(RetrierStatus::from)
+    }
+}
+
+/// Serializable representation of a [RetrierStatus], used to expose the state of a retrier through the plugin API.
+///
+/// Idle retriers report since when (Unix time, in seconds) and for how long (in seconds) they have been idling.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RetrierStatusInfo {
+    Stopped,
+    Running,
+    Failed,
+    Idle { idle_since: u64, idle_for: u64 },
+}
+
+impl From<&RetrierStatus> for RetrierStatusInfo {
+    fn from(status: &RetrierStatus) -> Self {
+        match status {
+            RetrierStatus::Stopped => RetrierStatusInfo::Stopped,
+            RetrierStatus::Running => RetrierStatusInfo::Running,
+            RetrierStatus::Failed => RetrierStatusInfo::Failed,
+            RetrierStatus::Idle(_) => {
+                let idle_for = status.get_elapsed_time().unwrap();
+                RetrierStatusInfo::Idle {
+                    idle_since: now().saturating_sub(idle_for),
+                    idle_for,
+                }
+            }
+        }
+    }
+}
+
+impl From<RetrierStatusInfo> for RetrierStatus {
+    /// Idle retriers are rebuilt from `idle_since`, so the time spent between serializing and deserializing is also
+    /// accounted for. `idle_for` is only informative.
+    fn from(info: RetrierStatusInfo) -> Self {
+        match info {
+            RetrierStatusInfo::Stopped => RetrierStatus::Stopped,
+            RetrierStatusInfo::Running => RetrierStatus::Running,
+            RetrierStatusInfo::Failed => RetrierStatus::Failed,
+            RetrierStatusInfo::Idle { idle_since, .. } => {
+                let idle_for = Duration::from_secs(now().saturating_sub(idle_since));
+                // Instants cannot go further back than the system boot time. Cap it if so.
+                let since = Instant::now();
+                RetrierStatus::Idle(since.checked_sub(idle_for).unwrap_or(since))
+            }
+        }
+    }
+}
+
+/// Appointments delivered to a tower within a retry cycle that are yet to be persisted.
+///
+/// Deliveries are stored in batches to reduce the number of database transactions. Until stored, delivered appointments
+/// remain pending (both in memory and in the database), so a crash in between would only lead to them being re-sent.
+#[derive(Default)]
+struct Deliveries {
+    /// The slots reported by the tower on the last delivery.
+    available_slots: u32,
+    /// The delivered appointments alongside their receipts.
+    receipts: Vec<(Locator, AppointmentReceipt)>,
+}
+
+/// Signatures of the appointments being retried, so they are not signed over and over on every retry attempt.
+///
+/// Signatures are bound to the user key they were created with, so the cache is cleared if the key changes. They are
+/// also bound to the signed data, so a signature is not reused if the signature version is renegotiated.
+#[derive(Default)]
+struct SignatureCache {
+    /// The user the cached signatures belong to.
+    user_id: Option<UserId>,
+    /// The signatures by locator, alongside the hash of the data they sign.
+    signatures: HashMap<Locator, (sha256::Hash, String)>,
+}
+
+impl SignatureCache {
+    /// Gets the signature of the given data, signing it (and caching the signature) if not cached yet.
+    fn get_or_sign(
+        &mut self,
+        user_id: UserId,
+        locator: Locator,
+        data: &[u8],
+        signer: &dyn Signer,
+    ) -> Result<String, SigningError> {
+        if self.user_id != Some(user_id) {
+            self.signatures.clear();
+            self.user_id = Some(user_id);
+        }
+
+        let digest = sha256::Hash::hash(data);
+        if let Some((cached_digest, signature)) = self.signatures.get(&locator) {
+            if *cached_digest == digest {
+                return Ok(signature.clone());
+            }
+        }
+        let signature = signer.sign(data)?;
+        self.signatures.insert(locator, (digest, signature.clone()));
+        Ok(signature)
+    }
+
+    /// Drops the signatures of the appointments that are not pending anymore.
+    fn retain(&mut self, pending_appointments: &HashSet<Locator>) {
+        self.signatures
+            .retain(|locator, _| pending_appointments.contains(locator));
+    }
+}
+
 pub struct Retrier {
     wt_client: Arc<Mutex<WTClient>>,
     tower_id: TowerId,
     pending_appointments: Mutex<HashSet<Locator>>,
     status: Mutex<RetrierStatus>,
+    signatures: Mutex<SignatureCache>,
+    /// Whether the retrier is waiting for its next attempt (instead of trying to reach the tower).
+    backing_off: AtomicBool,
 }
 
 impl Retrier {
@@ -279,59 +608,99 @@ impl Retrier {
             tower_id,
             pending_appointments: Mutex::new(locators),
             status: Mutex::new(RetrierStatus::Stopped),
+            signatures: Mutex::new(SignatureCache::default()),
+            backing_off: AtomicBool::new(false),
         }
     }
 
     fn has_pending_appointments(&self) -> bool {
-        !self.pending_appointments.lock().unwrap().is_empty()
+        !lock(&self.pending_appointments).is_empty()
+    }
+
+    /// Gets the next batch (of at most [RETRY_BATCH_SIZE]) of locators to be sent to the tower.
+    ///
+    /// Locators are only removed from the pending set once handled, so the ones left behind when leaving the cycle are
+    /// picked by the next one.
+    fn next_batch(&self) -> Vec<Locator> {
+        lock(&self.pending_appointments)
+            .iter()
+            .take(RETRY_BATCH_SIZE)
+            .cloned()
+            .collect()
+    }
+
+    /// Persists the delivered appointments (if any) and clears the batch.
+    ///
+    /// Appointments that were cancelled after being sent are not stored.
+    fn store_deliveries(&self, deliveries: &mut Deliveries) {
+        if !deliveries.receipts.is_empty() {
+            let mut wt_client = lock(&self.wt_client);
+            deliveries.receipts.retain(|(locator, _)| {
+                let pending = wt_client.is_pending(self.tower_id, *locator);
+                if !pending {
+                    log::info!(
+                        "{locator} was cancelled while being sent to {}. Not storing its receipt",
+                        self.tower_id
+                    );
+                }
+                pending
+            });
+            wt_client.add_appointment_receipts(
+                self.tower_id,
+                deliveries.available_slots,
+                &deliveries.receipts,
+            );
+            deliveries.receipts.clear();
+        }
     }
 
     fn set_status(&self, status: RetrierStatus) {
-        *self.status.lock().unwrap() = status.clone();
+        *lock(&self.status) = status.clone();
 
         // Add or remove retriers from WTClient based on the RetrierStatus
         if self.is_running() || self.is_idle() {
             log::debug!("Adding {} to active retriers", self.tower_id);
-            self.wt_client
-                .lock()
-                .unwrap()
-                .retriers
-                .insert(self.tower_id, status);
+            lock(&self.wt_client).retriers.insert(self.tower_id, status);
         } else if self.is_stopped() {
             // We are not removing failed retriers here to prevent a manual retry until the retrier is removed from
             // the manager
             log::debug!("Removing retrier {} from active retriers", self.tower_id);
-            self.wt_client
-                .lock()
-                .unwrap()
-                .retriers
-                .remove(&self.tower_id);
+            let mut wt_client = lock(&self.wt_client);
+            wt_client.retriers.remove(&self.tower_id);
+            wt_client.retry_schedule.remove(&self.tower_id);
         }
     }
 
+    /// Lets the [WTClient] know when the next attempt to reach the tower is expected (Unix time, in seconds).
+    fn set_next_attempt(&self, at: u64) {
+        lock(&self.wt_client)
+            .retry_schedule
+            .insert(self.tower_id, at);
+    }
+
     /// Maps [RetrierStatus::is_stopped]
     pub fn is_stopped(&self) -> bool {
-        self.status.lock().unwrap().is_stopped()
+        lock(&self.status).is_stopped()
     }
 
     /// Maps [RetrierStatus::is_running]
     pub fn is_running(&self) -> bool {
-        self.status.lock().unwrap().is_running()
+        lock(&self.status).is_running()
     }
 
     /// Maps [RetrierStatus::is_idle]
     pub fn is_idle(&self) -> bool {
-        self.status.lock().unwrap().is_idle()
+        lock(&self.status).is_idle()
     }
 
     /// Maps [RetrierStatus::failed]
     pub fn failed(&self) -> bool {
-        self.status.lock().unwrap().failed()
+        lock(&self.status).failed()
     }
 
     /// Maps [RetrierStatus::get_elapsed_time]
     pub fn get_elapsed_time(&self) -> Option<u64> {
-        self.status.lock().unwrap().get_elapsed_time()
+        lock(&self.status).get_elapsed_time()
     }
 
     pub fn should_start(&self) -> bool {
@@ -340,54 +709,109 @@ impl Retrier {
         self.is_stopped() && self.has_pending_appointments()
     }
 
-    pub fn start(self: Arc<Self>, max_elapsed_time_secs: u16, max_interval_time_secs: u16) {
+    /// Starts retrying the tower in the background.
+    ///
+    /// The retry strategy gives up once `max_elapsed_time_secs` have passed or, if set, after `max_retries` failed attempts.
+    /// If it gives up due to transient errors, the retrier idles and is expected to be resumed after `auto_retry_delay`
+    /// seconds, unless the error was with the local database, in which case it is stopped (keeping its pending
+    /// appointments) so it is started again right away.
+    pub fn start(
+        self: Arc<Self>,
+        max_elapsed_time_secs: u16,
+        max_interval_time_secs: u16,
+        max_retries: Option<u32>,
+        auto_retry_delay: u32,
+    ) -> JoinHandle<()> {
         // We shouldn't be retrying failed and running retriers.
-        debug_assert_eq!(*self.status.lock().unwrap(), RetrierStatus::Stopped);
+        debug_assert_eq!(*lock(&self.status), RetrierStatus::Stopped);
 
         // When manually retrying the tower may be in either SubscriptionError or Unreachable state.
-        // Flag this as TemporaryUnreachable only if there is no SubscriptionError.
+        // Flag this as TemporaryUnreachable only if the subscription does not need to be renewed.
         // Rationale: if there is a subscription error that needs to be handled first, otherwise we'll
         //            waste a retry cycle with a request that will always fail.
         {
-            let mut state = self.wt_client.lock().unwrap();
+            let mut state = lock(&self.wt_client);
             if !state
                 .get_tower_status(&self.tower_id)
                 .unwrap()
-                .is_subscription_error()
+                .needs_renewal()
             {
                 state.set_tower_status(self.tower_id, TowerStatus::TemporaryUnreachable);
             }
         }
         self.set_status(RetrierStatus::Running);
+        self.set_next_attempt(now());
 
         tokio::spawn(async move {
-            let r = retry_notify(
+            let retrier = &self;
+            let mut attempts = 0;
+            let retry = retry_notify(
                 ExponentialBackoff {
                     max_elapsed_time: Some(Duration::from_secs(max_elapsed_time_secs as u64)),
                     max_interval: Duration::from_secs(max_interval_time_secs as u64),
                     ..ExponentialBackoff::default()
                 },
-                || async { self.run().await },
-                |err, _| {
+                || {
+                    attempts += 1;
+                    let out_of_retries = max_retries.is_some_and(|max| attempts >= max);
+                    async move {
+                        // The attempt is built before waiting for it, so it is only flagged as in flight once polled
+                        retrier.backing_off.store(false, Ordering::Relaxed);
+                        // Transient errors become permanent once we run out of retries so the backoff stops
+                        retrier.run().await.map_err(|e| match e {
+                            Error::Transient { err, .. } if out_of_retries => {
+                                log::debug!(
+                                    "Reached the maximum number of retries for {}",
+                                    retrier.tower_id
+                                );
+                                Error::permanent(err)
+                            }
+                            e => e,
+                        })
+                    }
+                },
+                |err: RetryError, delay: Duration| {
                     log::warn!("Retry error happened with {}. {err}", self.tower_id);
+                    self.set_next_attempt(now() + delay.as_secs_f64().ceil() as u64);
+                    self.backing_off.store(true, Ordering::Relaxed);
                 },
-            )
-            .await;
+            );
+            // Waiting for the next attempt can take way longer than lightningd waits for plugins to exit on shutdown,
+            // so the wait is cut short. Attempts in flight are let finish, so their deliveries are persisted.
+            let r = tokio::select! {
+                r = retry => r,
+                _ = retrier.shut_down_while_backing_off() => Err(RetryError::ShuttingDown),
+            };
 
             match r {
                 Ok(_) => {
                     log::info!("Retry strategy succeeded for {}", self.tower_id);
                     // Set the tower status now so new appointment doesn't go to the retry manager.
-                    self.wt_client
-                        .lock()
-                        .unwrap()
-                        .set_tower_status(self.tower_id, TowerStatus::Reachable);
+                    // Exhausted subscriptions are kept as such so they are renewed before sending the next appointment.
+                    {
+                        let mut state = lock(&self.wt_client);
+                        if !state
+                            .get_tower_status(&self.tower_id)
+                            .unwrap()
+                            .is_subscription_exhausted()
+                        {
+                            state.set_tower_status(self.tower_id, TowerStatus::Reachable);
+                        }
+                    }
                     // Retrier succeeded and can be re-used by re-starting it.
                     self.set_status(RetrierStatus::Stopped);
                 }
+                Err(RetryError::ShuttingDown) => {
+                    log::info!(
+                        "Stopped retrying {} given the client is shutting down",
+                        self.tower_id
+                    );
+                    self.set_status(RetrierStatus::Stopped);
+                }
                 Err(e) => {
                     // Notice we'll end up here after a permanent error. That is, either after finishing the backoff strategy
                     // unsuccessfully or by manually raising such an error (like when facing a tower misbehavior).
+                    // The error that made the retrier give up has already been recorded by Retrier::run
                     log::warn!("Retry strategy gave up for {}. {e}", self.tower_id);
                     if e.is_permanent() {
                         self.set_status(RetrierStatus::Failed);
@@ -396,42 +820,70 @@ impl Retrier {
                     match e {
                         RetryError::Subscription(_, true) => {
                             log::info!("Setting {} status as subscription error", self.tower_id);
-                            self.wt_client
-                                .lock()
-                                .unwrap()
+                            lock(&self.wt_client)
                                 .set_tower_status(self.tower_id, TowerStatus::SubscriptionError)
                         }
                         RetryError::Misbehaving(p) => {
                             log::warn!("Cannot recover known tower_id from the appointment receipt. Flagging tower as misbehaving");
-                            self.wt_client
-                                .lock()
-                                .unwrap()
-                                .flag_misbehaving_tower(self.tower_id, p);
+                            lock(&self.wt_client).flag_misbehaving_tower(self.tower_id, p);
                         }
                         RetryError::Abandoned => {
                             log::info!("Skipping retrying abandoned tower {}", self.tower_id)
                         }
-                        // This covers `RetryError::Unreachable` and `RetryError::Subscription(_, false)`
+                        RetryError::Security(r) | RetryError::Signing(r, true) => {
+                            log::error!(
+                                "{r}. Not retrying {} until manually requested",
+                                self.tower_id
+                            );
+                            let mut state = lock(&self.wt_client);
+                            state.set_tower_status(self.tower_id, TowerStatus::Unreachable);
+                            state.fall_back_pending_appointments(self.tower_id);
+                        }
+                        // The tower is not to blame for the database being busy, so its status is left as is. The pending
+                        // appointments are kept, so the retrier is started again (with a fresh backoff) on the next tick
+                        RetryError::Database => {
+                            log::info!(
+                                "Cannot read from the database. Retrying {} on the next tick",
+                                self.tower_id
+                            );
+                            self.set_status(RetrierStatus::Stopped);
+                        }
+                        // This covers `RetryError::Unreachable`, `RetryError::Subscription(_, false)`,
+                        // `RetryError::Signing(_, false)` and `RetryError::UnexpectedResponse`
                         _ => {
                             log::debug!("Starting to idle");
                             self.set_status(RetrierStatus::Idle(Instant::now()));
+                            self.set_next_attempt(now() + auto_retry_delay as u64);
                             // Clear all pending appointments so they do not waste any memory while idling
-                            self.pending_appointments.lock().unwrap().clear();
-                            self.wt_client
-                                .lock()
-                                .unwrap()
-                                .set_tower_status(self.tower_id, TowerStatus::Unreachable);
+                            lock(&self.pending_appointments).clear();
+                            let mut state = lock(&self.wt_client);
+                            state.set_tower_status(self.tower_id, TowerStatus::Unreachable);
+                            state.fall_back_pending_appointments(self.tower_id);
                         }
                     }
                 }
             }
-        });
+        })
+    }
+
+    /// Resolves once the client is shutting down while the retrier is waiting for its next attempt.
+    async fn shut_down_while_backing_off(&self) {
+        loop {
+            tokio::time::sleep(Duration::from_millis(MIN_POLLING_INTERVAL)).await;
+            if self.backing_off.load(Ordering::Relaxed) && lock(&self.wt_client).is_shutting_down()
+            {
+                return;
+            }
+        }
     }
 
     async fn run(&self) -> Result<(), Error<RetryError>> {
         // Create a new scope so we can get all the data only locking the WTClient once.
-        let (tower_id, status, net_addr, user_id, user_sk, proxy) = {
-            let wt_client = self.wt_client.lock().unwrap();
+        let (tower_id, status, net_addr, user_id, signer, options) = {
+            let wt_client = lock(&self.wt_client);
+            if wt_client.is_shutting_down() {
+                return Err(Error::permanent(RetryError::ShuttingDown));
+            }
             if !wt_client.towers.contains_key(&self.tower_id) {
                 return Err(Error::permanent(RetryError::Abandoned));
             }
@@ -442,118 +894,231 @@ impl Retrier {
                 tower.status,
                 tower.net_addr.clone(),
                 wt_client.user_id,
-                wt_client.user_sk,
-                wt_client.proxy.clone(),
+                wt_client.signer.clone(),
+                wt_client.get_request_options(self.tower_id),
             )
         };
 
-        // If the tower state is subscription_error we need to re-register first. If we cannot, then the retry is aborted.
-        if status.is_subscription_error() {
-            let receipt = http::register(tower_id, user_id, &net_addr, &proxy)
-                .await
-                .map_err(|e| {
-                    log::debug!("Cannot renew registration with tower. Error: {e:?}");
-                    Error::transient(RetryError::Subscription(
-                        "Cannot renew registration with tower".to_owned(),
-                        false,
-                    ))
-                })?;
+        // If the subscription needs to be renewed we need to re-register first. If we cannot, then the retry is aborted.
+        if status.needs_renewal() {
+            let (receipt, signature_version) =
+                http::register(tower_id, user_id, &net_addr, None, &options)
+                    .await
+                    .map_err(|e| {
+                        log::debug!("Cannot renew registration with tower. Error: {e:?}");
+                        self.record_error(TowerError::from(&e));
+                        Error::transient(RetryError::Subscription(
+                            "Cannot renew registration with tower".to_owned(),
+                            false,
+                        ))
+                    })?;
             if !receipt.verify(&tower_id) {
-                return Err(Error::permanent(RetryError::Subscription("Registration receipt contains bad signature. Are you using the right tower_id?".to_owned(), true)));
+                let reason = "Registration receipt contains bad signature. Are you using the right tower_id?";
+                self.record_error(TowerError::new(ErrorKind::Misbehaving, reason.to_owned()));
+                return Err(Error::permanent(RetryError::Subscription(
+                    reason.to_owned(),
+                    true,
+                )));
             }
-            self.wt_client
-                .lock()
-                .unwrap()
-                .add_update_tower(tower_id, net_addr.net_addr(), &receipt)
-                .map_err(|e| {
-                    let reason = if e.is_expiry() {
-                        "Registration receipt contains a subscription expiry that is not higher than the one we are currently registered for"
-                    } else {
-                        "Registration receipt does not contain more slots than the ones we are currently registered for"
-                    };
-                    Error::permanent(RetryError::Subscription(reason.to_owned(), true))
-                })?;
+            let mut wt_client = lock(&self.wt_client);
+            // The tower may have been abandoned while waiting for the tower response. Adding it back would bring it to life.
+            if !wt_client.towers.contains_key(&tower_id) {
+                return Err(Error::permanent(RetryError::Abandoned));
+            }
+            if let Err(e) = wt_client.add_update_tower(tower_id, net_addr.net_addr(), &receipt) {
+                let reason = if e.is_expiry() {
+                    "Registration receipt contains a subscription expiry that is not higher than the one we are currently registered for"
+                } else {
+                    "Registration receipt does not contain more slots than the ones we are currently registered for"
+                };
+                wt_client.record_error(
+                    tower_id,
+                    TowerError::new(ErrorKind::Subscription, reason.to_owned()),
+                );
+                return Err(Error::permanent(RetryError::Subscription(
+                    reason.to_owned(),
+                    true,
+                )));
+            }
+            wt_client.set_tower_signature_version(tower_id, signature_version);
         }
 
-        while self.has_pending_appointments() {
-            let locators = self.pending_appointments.lock().unwrap().clone();
-            for locator in locators.into_iter() {
-                let appointment = self
-                    .wt_client
-                    .lock()
-                    .unwrap()
-                    .dbm
-                    .load_appointment(locator)
-                    .unwrap();
+        // Successful deliveries are persisted in batches, and always before leaving the cycle (no matter the outcome).
+        let mut deliveries = Deliveries::default();
+        let result = async {
+            loop {
+                let locators = self.next_batch();
+                if locators.is_empty() {
+                    break;
+                }
+                for locator in locators.into_iter() {
+                    let (appointment, signature_version) = {
+                        let mut wt_client = lock(&self.wt_client);
+                        // Stop in between appointments, so the ones already delivered can be persisted
+                        if wt_client.is_shutting_down() {
+                            return Err(Error::permanent(RetryError::ShuttingDown));
+                        }
+                        if !wt_client.is_pending(tower_id, locator) {
+                            log::info!("Delivery of {locator} to {tower_id} was cancelled");
+                            lock(&self.pending_appointments).remove(&locator);
+                            continue;
+                        }
+                        // Appointments that were not delivered on time are not worth sending anymore
+                        if wt_client
+                            .dbm
+                            .load_pending_deadline(tower_id, locator)
+                            .is_some_and(|deadline| deadline <= now())
+                        {
+                            lock(&self.pending_appointments).remove(&locator);
+                            wt_client.expire_pending_appointment(tower_id, locator);
+                            continue;
+                        }
+                        // The version may have been renegotiated on renewal, so it is read right before signing
+                        let signature_version = wt_client
+                            .towers
+                            .get(&tower_id)
+                            .map(|tower| tower.signature_version)
+                            .unwrap_or_default();
+                        match wt_client.dbm.load_appointment(locator) {
+                            Ok(Some(appointment)) => (appointment, signature_version),
+                            Ok(None) => {
+                                log::error!("Cannot find appointment {locator} in the database. Skipping");
+                                lock(&self.pending_appointments).remove(&locator);
+                                continue;
+                            }
+                            // Most likely the database is busy. Back off and try the locator again later
+                            Err(e) => {
+                                log::warn!("Cannot load appointment {locator} from the database. Error: {e:?}");
+                                let e = RetryError::Database;
+                                wt_client.record_error(tower_id, TowerError::new(ErrorKind::Internal, e.to_string()));
+                                return Err(Error::transient(e));
+                            }
+                        }
+                    };
 
-                match http::add_appointment(
-                    tower_id,
-                    &net_addr,
-                    &proxy,
-                    &appointment,
-                    &cryptography::sign(&appointment.to_vec(), &user_sk).unwrap(),
-                )
-                .await
-                {
-                    Ok((slots, receipt)) => {
-                        self.pending_appointments.lock().unwrap().remove(&locator);
-                        let mut wt_client = self.wt_client.lock().unwrap();
-                        wt_client.add_appointment_receipt(
-                            tower_id,
-                            appointment.locator,
-                            slots,
-                            &receipt,
-                        );
-                        wt_client.remove_pending_appointment(tower_id, appointment.locator);
-                        log::debug!("Response verified and data stored in the database");
-                    }
-                    Err(e) => {
-                        match e {
-                            AddAppointmentError::RequestError(e) => {
-                                if e.is_connection() {
-                                    log::warn!(
-                                        "{tower_id} cannot be reached. Tower will be retried later"
-                                    );
-                                    return Err(Error::transient(RetryError::Unreachable));
-                                }
+                    // The appointment is kept pending if it cannot be signed. Signers may recover, but a misconfigured
+                    // one needs to be fixed before retrying. Signatures are reused across retries.
+                    let signature = lock(&self.signatures).get_or_sign(
+                        user_id,
+                        locator,
+                        &appointment.to_signable_vec(signature_version),
+                        signer.as_ref(),
+                    ).map_err(|e| {
+                        log::error!("Cannot sign appointment {locator}. {e}");
+                        let permanent = !e.is_transient();
+                        let e = RetryError::Signing(e.to_string(), permanent);
+                        self.record_error(TowerError::new(ErrorKind::Internal, e.to_string()));
+                        if permanent {
+                            Error::permanent(e)
+                        } else {
+                            Error::transient(e)
+                        }
+                    })?;
+                    match http::add_appointment(tower_id, &net_addr, &options, &appointment, &signature)
+                    .await
+                    {
+                        Ok((slots, receipt)) => {
+                            lock(&self.pending_appointments).remove(&locator);
+                            deliveries.available_slots = slots;
+                            deliveries.receipts.push((locator, receipt));
+                            if deliveries.receipts.len() >= DELIVERY_BATCH_SIZE {
+                                self.store_deliveries(&mut deliveries);
                             }
-                            AddAppointmentError::ApiError(e) => match e.error_code {
-                                errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR => {
-                                    log::warn!("There is a subscription issue with {tower_id}");
-                                    self.wt_client
-                                        .lock()
-                                        .unwrap()
-                                        .set_tower_status(tower_id, TowerStatus::SubscriptionError);
-                                    return Err(Error::transient(RetryError::Subscription(
-                                        "Subscription error".to_owned(),
-                                        false,
-                                    )));
-                                }
-                                _ => {
-                                    log::warn!(
-                                        "{tower_id} rejected the appointment. Error: {}, error_code: {}",
-                                        e.error,
-                                        e.error_code
-                                    );
-                                    // We need to move the appointment from pending to invalid
-                                    // Add it first to invalid and remove it from pending later so a cascade delete is not triggered
-                                    self.pending_appointments.lock().unwrap().remove(&locator);
-                                    let mut wt_client = self.wt_client.lock().unwrap();
-                                    wt_client.add_invalid_appointment(tower_id, &appointment);
-                                    wt_client
-                                        .remove_pending_appointment(tower_id, appointment.locator);
+                            log::debug!("Response verified");
+
+                            // Sending more appointments would fail, so renew the subscription first.
+                            if slots == 0 && self.has_pending_appointments() {
+                                let reason = "Subscription exhausted".to_owned();
+                                self.record_error(TowerError::new(ErrorKind::Subscription, reason.clone()));
+                                return Err(Error::transient(RetryError::Subscription(reason, false)));
+                            }
+                        }
+                        Err(e) => {
+                            self.record_error(TowerError::from(&e));
+                            match e {
+                                AddAppointmentError::RequestError(e) => match e {
+                                    e if e.is_connection() => {
+                                        log::warn!(
+                                            "{tower_id} cannot be reached. Tower will be retried later"
+                                        );
+                                        return Err(Error::transient(RetryError::Unreachable));
+                                    }
+                                    RequestError::PinMismatch(r) => {
+                                        return Err(Error::permanent(RetryError::Security(r)));
+                                    }
+                                    // Sending the appointment again right away would most likely get the same reply, so
+                                    // back off instead
+                                    e => {
+                                        log::warn!(
+                                            "Unexpected response from {tower_id}. Tower will be retried later. Error: {e}"
+                                        );
+                                        return Err(Error::transient(RetryError::UnexpectedResponse(
+                                            e.to_string(),
+                                        )));
+                                    }
+                                },
+                                AddAppointmentError::ApiError(e) => match e.error_code {
+                                    errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR => {
+                                        log::warn!("There is a subscription issue with {tower_id}");
+                                        lock(&self.wt_client)
+                                            .set_tower_status(tower_id, TowerStatus::SubscriptionError);
+                                        return Err(Error::transient(RetryError::Subscription(
+                                            "Subscription error".to_owned(),
+                                            false,
+                                        )));
+                                    }
+                                    _ => {
+                                        log::warn!(
+                                            "{tower_id} rejected the appointment. Error: {}, error_code: {}",
+                                            e.error,
+                                            e.error_code
+                                        );
+                                        // We need to move the appointment from pending to invalid
+                                        // Add it first to invalid and remove it from pending later so a cascade delete is not triggered
+                                        lock(&self.pending_appointments).remove(&locator);
+                                        let mut wt_client = lock(&self.wt_client);
+                                        // Cancelled appointments are not flagged as invalid
+                                        if !wt_client.is_pending(tower_id, locator) {
+                                            continue;
+                                        }
+                                        // Some rejections are due to temporary issues with the tower, so the appointment
+                                        // may be moved back to pending later on (see WTClient::recover_invalid_appointments)
+                                        if wt_client.add_rejected_appointment(tower_id, &appointment, &e) {
+                                            log::info!("Appointment {locator} will be retried later on");
+                                        } else {
+                                            log::info!(
+                                                "Appointment {locator} flagged as permanently invalid"
+                                            );
+                                        }
+                                        wt_client
+                                            .remove_pending_appointment(tower_id, appointment.locator);
+                                    }
+                                },
+                                AddAppointmentError::SignatureError(proof) => {
+                                    return Err(Error::permanent(RetryError::Misbehaving(proof)));
                                 }
-                            },
-                            AddAppointmentError::SignatureError(proof) => {
-                                return Err(Error::permanent(RetryError::Misbehaving(proof)));
                             }
                         }
                     }
                 }
+                // Skipped appointments do not hit any await point, so yield in between batches to keep big backlogs from
+                // hogging the runtime.
+                tokio::task::yield_now().await;
             }
+
+            Ok(())
         }
+        .await;
+        self.store_deliveries(&mut deliveries);
+        lock(&self.signatures).retain(&lock(&self.pending_appointments));
+
+        result
+    }
 
-        Ok(())
+    /// Records an error faced while retrying the tower, so it can be queried later on (see
+    /// [WTClient::diagnose_tower](crate::wt_client::WTClient::diagnose_tower)).
+    fn record_error(&self, error: TowerError) {
+        lock(&self.wt_client).record_error(self.tower_id, error);
     }
 
     /// Removed our retrier identifier from the WTClient if the retrier has failed
@@ -563,11 +1128,9 @@ impl Retrier {
                 "Removing failed retrier {} from active retriers",
                 self.tower_id
             );
-            self.wt_client
-                .lock()
-                .unwrap()
-                .retriers
-                .remove(&self.tower_id);
+            let mut wt_client = lock(&self.wt_client);
+            wt_client.retriers.remove(&self.tower_id);
+            wt_client.retry_schedule.remove(&self.tower_id);
         }
     }
 }
@@ -578,8 +1141,11 @@ mod tests {
 
     use serde_json::json;
     use tempdir::TempDir;
+    use tokio::sync::broadcast;
     use tokio::sync::mpsc::unbounded_channel;
 
+    use teos_common::appointment::SignatureVersion;
+    use teos_common::cryptography;
     use teos_common::errors;
     use teos_common::net::http::Endpoint;
     use teos_common::protos::AddAppointmentRequest;
@@ -590,7 +1156,10 @@ mod tests {
     };
 
     use crate::net::http::ApiError;
+    use crate::signer::{LocalSigner, Signer, SigningError};
     use crate::test_utils::get_dummy_add_appointment_response;
+    use crate::webhook::{WebhookEvent, WEBHOOK_QUEUE_CAPACITY};
+    use crate::wt_client::{InvalidRetryPolicy, StaleFeed, STALE_FEED_CHUNK_SIZE};
 
     const LONG_AUTO_RETRY_DELAY: u32 = 60;
     const SHORT_AUTO_RETRY_DELAY: u32 = 3;
@@ -598,6 +1167,7 @@ mod tests {
     const HALF_API_DELAY: f64 = API_DELAY / 2.0;
     const MAX_ELAPSED_TIME: u16 = 2;
     const MAX_INTERVAL_TIME: u16 = 1;
+    const POLLING_INTERVAL: u64 = 1000;
     const MAX_RUN_TIME: f64 = 0.2;
 
     macro_rules! wait_until {
@@ -619,10 +1189,72 @@ mod tests {
                 tower_id,
                 pending_appointments: Mutex::new(HashSet::new()),
                 status: Mutex::new(RetrierStatus::Stopped),
+                signatures: Mutex::new(SignatureCache::default()),
+                backing_off: AtomicBool::new(false),
             }
         }
     }
 
+    #[test]
+    fn test_retrier_status_info() {
+        for (status, expected) in [
+            (RetrierStatus::Stopped, json!({"status": "stopped"})),
+            (RetrierStatus::Running, json!({"status": "running"})),
+            (RetrierStatus::Failed, json!({"status": "failed"})),
+        ] {
+            assert_eq!(
+                serde_json::to_value(RetrierStatusInfo::from(&status)).unwrap(),
+                expected
+            );
+        }
+
+        // Idle retriers report for how long they have been idling, and since when
+        let idle_for = 720;
+        let status = RetrierStatus::Idle(Instant::now() - Duration::from_secs(idle_for));
+        let info = serde_json::to_value(RetrierStatusInfo::from(&status)).unwrap();
+        assert_eq!(info["status"], "idle");
+        assert_eq!(info["idle_for"], idle_for);
+        // Leave some margin in case the clock ticks in between
+        let idle_since = info["idle_since"].as_u64().unwrap();
+        assert!((now() - idle_for - 1..=now() - idle_for).contains(&idle_since));
+    }
+
+    #[test]
+    fn test_retrier_status_serde_roundtrip() {
+        for status in [
+            RetrierStatus::Stopped,
+            RetrierStatus::Running,
+            RetrierStatus::Failed,
+        ] {
+            let value = serde_json::to_value(&status).unwrap();
+            assert_eq!(
+                value,
+                serde_json::to_value(RetrierStatusInfo::from(&status)).unwrap()
+            );
+            assert_eq!(
+                serde_json::from_value::<RetrierStatus>(value).unwrap(),
+                status
+            );
+        }
+
+        // Idle retriers keep (roughly) for how long they have been idling
+        let idle_for = 720;
+        let status = RetrierStatus::Idle(Instant::now() - Duration::from_secs(idle_for));
+        let value = serde_json::to_value(&status).unwrap();
+        assert_eq!(value["status"], "idle");
+        assert_eq!(value["idle_for"], idle_for);
+
+        let deserialized = serde_json::from_value::<RetrierStatus>(value).unwrap();
+        assert!(deserialized.is_idle());
+        // Leave some margin in case the clock ticks in between
+        let elapsed = deserialized.get_elapsed_time().unwrap();
+        assert!((idle_for..=idle_for + 1).contains(&elapsed));
+
+        // Unknown tags are rejected
+        assert!(serde_json::from_value::<RetrierStatus>(json!({"status": "sleeping"})).is_err());
+        assert!(serde_json::from_value::<RetrierStatus>(json!({"status": "idle"})).is_err());
+    }
+
     #[tokio::test]
     async fn test_manage_retry_reachable() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
@@ -682,6 +1314,7 @@ mod tests {
                 MAX_ELAPSED_TIME,
                 LONG_AUTO_RETRY_DELAY,
                 MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
             )
             .manage_retry()
             .await
@@ -718,133 +1351,96 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_manage_retry_unreachable() {
+    async fn test_manage_retry_poisoned_lock() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let (tx, rx) = unbounded_channel();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
         ));
 
-        // Add a tower with pending appointments
+        let mut server = mockito::Server::new_async().await;
+
+        // Add a tower with a pending appointment
         let (tower_sk, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
         let receipt = get_random_registration_receipt();
-        wt_client
-            .lock()
-            .unwrap()
-            .add_update_tower(tower_id, "http://unreachable.tower", &receipt)
-            .unwrap();
-
-        // Add appointment to pending
         let appointment = generate_random_appointment(None);
-        wt_client
-            .lock()
-            .unwrap()
-            .add_pending_appointment(tower_id, &appointment);
+        let user_sk = {
+            let mut state = wt_client.lock().unwrap();
+            state
+                .add_update_tower(tower_id, &server.url(), &receipt)
+                .unwrap();
+            state.add_pending_appointment(tower_id, &appointment);
+            state.user_sk
+        };
 
-        // Start the task and send the tower to the channel for retry
+        let mut add_appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &user_sk).unwrap(),
+            42,
+        );
+        add_appointment_receipt.sign(&tower_sk);
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(get_dummy_add_appointment_response(
+                    appointment.locator,
+                    &add_appointment_receipt
+                ))
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        // Poison the lock by panicking while holding it
+        let poisoner = wt_client.clone();
+        std::thread::spawn(move || {
+            let _state = poisoner.lock().unwrap();
+            panic!("Poisoning the lock");
+        })
+        .join()
+        .unwrap_err();
+        assert!(wt_client.is_poisoned());
+
+        // The retry manager (and its retriers) carry on regardless
         tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
             .unwrap();
-
         let wt_client_clone = wt_client.clone();
         let task = tokio::spawn(async move {
             RetryManager::new(
                 wt_client_clone,
                 rx,
                 MAX_ELAPSED_TIME,
-                SHORT_AUTO_RETRY_DELAY,
+                LONG_AUTO_RETRY_DELAY,
                 MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
             )
             .manage_retry()
             .await
         });
 
-        // Wait for one retry round and check to tower status
-        tokio::time::sleep(Duration::from_secs_f64(MAX_RUN_TIME)).await;
-        assert!(wt_client
-            .lock()
+        wait_until!(!lock(&wt_client)
+            .towers
+            .get(&tower_id)
             .unwrap()
+            .pending_appointments
+            .contains(&appointment.locator));
+        wait_until!(lock(&wt_client).get_retrier_status(&tower_id).is_none());
+        assert!(lock(&wt_client)
             .get_tower_status(&tower_id)
             .unwrap()
-            .is_temporary_unreachable());
-        assert!(wt_client
-            .lock()
-            .unwrap()
-            .get_retrier_status(&tower_id)
-            .unwrap()
-            .is_running());
-
-        // Wait until the task gives up and check again (this gives up due to accumulation of transient errors, so the retriers will be idle).
-        wait_until!(wt_client
-            .lock()
-            .unwrap()
-            .get_retrier_status(&tower_id)
-            .unwrap()
-            .is_idle());
-
-        assert!(wt_client
-            .lock()
-            .unwrap()
-            .get_tower_status(&tower_id)
-            .unwrap()
-            .is_unreachable());
-
-        // Add a proper server and check that the auto-retry works
-        // Prepare the mock response
-        let mut server = mockito::Server::new_async().await;
-        let mut add_appointment_receipt = AppointmentReceipt::new(
-            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
-            42,
-        );
-        add_appointment_receipt.sign(&tower_sk);
-        let add_appointment_response =
-            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
-        let api_mock = server
-            .mock("POST", Endpoint::AddAppointment.path().as_str())
-            .with_status(200)
-            .with_header("content-type", "application/json")
-            .with_body(json!(add_appointment_response).to_string())
-            .create_async()
-            .await;
-
-        // Update the tower details
-        wt_client
-            .lock()
-            .unwrap()
-            .add_update_tower(
-                tower_id,
-                &server.url(),
-                &get_registration_receipt_from_previous(&receipt),
-            )
-            .unwrap();
-
-        // Wait and check. We wait twice the short retry delay because it can be the case that the first auto retry
-        // is performed while we are patching the mock.
-        tokio::time::sleep(Duration::from_secs((SHORT_AUTO_RETRY_DELAY * 2) as u64)).await;
-        assert_eq!(
-            wt_client
-                .lock()
-                .unwrap()
-                .get_tower_status(&tower_id)
-                .unwrap(),
-            TowerStatus::Reachable
-        );
-        assert!(!wt_client
-            .lock()
-            .unwrap()
-            .towers
-            .get(&tower_id)
-            .unwrap()
-            .pending_appointments
-            .contains(&appointment.locator));
-        assert!(!wt_client.lock().unwrap().retriers.contains_key(&tower_id));
-        api_mock.assert_async().await;
+            .is_reachable());
+        api_mock.assert_async().await;
 
+        // The lock is not reported as poisoned anymore
+        assert!(!wt_client.is_poisoned());
+        assert!(!task.is_finished());
         task.abort();
     }
 
     #[tokio::test]
-    async fn test_manage_retry_rejected() {
+    async fn test_manage_retry_shutdown() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let (tx, rx) = unbounded_channel();
         let wt_client = Arc::new(Mutex::new(
@@ -852,8 +1448,8 @@ mod tests {
         ));
         let mut server = mockito::Server::new_async().await;
 
-        // Add a tower with pending appointments
-        let (_, tower_pk) = cryptography::get_random_keypair();
+        // Add a tower with a couple of pending appointments
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
         let receipt = get_random_registration_receipt();
         wt_client
@@ -862,32 +1458,46 @@ mod tests {
             .add_update_tower(tower_id, &server.url(), &receipt)
             .unwrap();
 
-        // Add appointment to pending
-        let appointment = generate_random_appointment(None);
-        wt_client
-            .lock()
-            .unwrap()
-            .add_pending_appointment(tower_id, &appointment);
+        let mut responses = HashMap::new();
+        for _ in 0..2 {
+            let appointment = generate_random_appointment(None);
+            wt_client
+                .lock()
+                .unwrap()
+                .add_pending_appointment(tower_id, &appointment);
+            let mut receipt = AppointmentReceipt::new(
+                cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk)
+                    .unwrap(),
+                42,
+            );
+            receipt.sign(&tower_sk);
+            responses.insert(
+                appointment.locator.to_vec(),
+                get_dummy_add_appointment_response(appointment.locator, &receipt),
+            );
+        }
+        let locators: HashSet<Locator> = wt_client.lock().unwrap().towers[&tower_id]
+            .pending_appointments
+            .clone();
 
-        // Prepare the mock response
+        // The tower takes a while to answer, so the first delivery is still in flight when shutting down
         let api_mock = server
             .mock("POST", Endpoint::AddAppointment.path().as_str())
-            .with_status(400)
+            .with_status(200)
             .with_header("content-type", "application/json")
-            .with_body_from_request(|_| {
+            .with_body_from_request(move |request| {
                 std::thread::sleep(Duration::from_secs_f64(API_DELAY));
-                json!(ApiError {
-                    error: "error_msg".to_owned(),
-                    error_code: 1,
-                })
-                .to_string()
-                .into()
+                let body = serde_json::from_slice::<AddAppointmentRequest>(request.body().unwrap())
+                    .unwrap();
+                json!(responses[&body.appointment.unwrap().locator])
+                    .to_string()
+                    .into()
             })
+            .expect(1)
             .create_async()
             .await;
 
-        // Start the task and send the tower to the channel for retry
-        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+        tx.send((tower_id, RevocationData::Stale(locators.clone())))
             .unwrap();
 
         let wt_client_clone = wt_client.clone();
@@ -898,12 +1508,12 @@ mod tests {
                 MAX_ELAPSED_TIME,
                 LONG_AUTO_RETRY_DELAY,
                 MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
             )
             .manage_retry()
             .await
         });
 
-        // Wait for a fraction of the API delay and check how the tower status changed
         tokio::time::sleep(Duration::from_secs_f64(HALF_API_DELAY)).await;
         assert!(wt_client
             .lock()
@@ -911,156 +1521,156 @@ mod tests {
             .get_retrier_status(&tower_id)
             .unwrap()
             .is_running());
+        wt_client.lock().unwrap().begin_shutdown();
 
-        // Wait for the remaining time and re-check
-        wait_until!(wt_client
-            .lock()
-            .unwrap()
-            .get_retrier_status(&tower_id)
-            .is_none());
-
-        assert!(wt_client
-            .lock()
-            .unwrap()
-            .get_tower_status(&tower_id)
-            .unwrap()
-            .is_reachable());
-        assert!(!wt_client
-            .lock()
-            .unwrap()
-            .towers
-            .get(&tower_id)
-            .unwrap()
-            .pending_appointments
-            .contains(&appointment.locator));
-        assert!(wt_client
-            .lock()
-            .unwrap()
-            .towers
-            .get(&tower_id)
-            .unwrap()
-            .invalid_appointments
-            .contains(&appointment.locator));
+        // The retry manager does not return until the in-flight delivery has been persisted
+        task.await.unwrap();
         api_mock.assert_async().await;
 
-        task.abort();
+        let state = wt_client.lock().unwrap();
+        let delivered: HashSet<Locator> = locators
+            .iter()
+            .filter(|locator| {
+                state
+                    .dbm
+                    .load_appointment_receipt(tower_id, **locator)
+                    .is_some()
+            })
+            .cloned()
+            .collect();
+        assert_eq!(delivered.len(), 1);
+        // The appointment that was not sent is kept pending for the next start
+        assert_eq!(
+            state
+                .dbm
+                .load_appointment_locators(tower_id, crate::AppointmentStatus::Pending)
+                .unwrap(),
+            locators.difference(&delivered).cloned().collect()
+        );
+
+        // No more data is sent to the retriers from now on
+        state.send_to_retrier(tower_id, *delivered.iter().next().unwrap());
+        assert!(state.retry_tower(tower_id).is_err());
     }
 
     #[tokio::test]
-    async fn test_manage_retry_misbehaving() {
+    async fn test_manage_retry_shutdown_while_backing_off() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let (tx, rx) = unbounded_channel();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
         ));
-        let mut server = mockito::Server::new_async().await;
 
-        // Add a tower with pending appointments
-        let (_, tower_pk) = cryptography::get_random_keypair();
-        let tower_id = TowerId(tower_pk);
+        // Add an unreachable tower with a pending appointment
+        let tower_id = get_random_user_id();
         let receipt = get_random_registration_receipt();
         wt_client
             .lock()
             .unwrap()
-            .add_update_tower(tower_id, &server.url(), &receipt)
+            .add_update_tower(tower_id, "http://unreachable.tower", &receipt)
             .unwrap();
-
-        // Add appointment to pending
         let appointment = generate_random_appointment(None);
         wt_client
             .lock()
             .unwrap()
             .add_pending_appointment(tower_id, &appointment);
-
-        // Prepare the mock response
-        let mut add_appointment_receipt = AppointmentReceipt::new(
-            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
-            42,
-        );
-        // Sign with a random key so it counts as misbehaving
-        add_appointment_receipt.sign(&cryptography::get_random_keypair().0);
-        let add_appointment_response =
-            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
-        let api_mock = server
-            .mock("POST", Endpoint::AddAppointment.path().as_str())
-            .with_status(200)
-            .with_header("content-type", "application/json")
-            .with_body_from_request(move |_| {
-                std::thread::sleep(Duration::from_secs_f64(API_DELAY));
-                json!(add_appointment_response).to_string().into()
-            })
-            .create_async()
-            .await;
-
-        // Start the task and send the tower to the channel for retry
         tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
             .unwrap();
 
+        // The retry strategy would keep on trying the tower for an hour
         let wt_client_clone = wt_client.clone();
         let task = tokio::spawn(async move {
             RetryManager::new(
                 wt_client_clone,
                 rx,
-                MAX_ELAPSED_TIME,
+                3600,
                 LONG_AUTO_RETRY_DELAY,
-                MAX_INTERVAL_TIME,
+                60,
+                POLLING_INTERVAL,
             )
             .manage_retry()
             .await
         });
 
-        // Wait for a fraction of the API delay and check how the tower status changed
-        tokio::time::sleep(Duration::from_secs_f64(HALF_API_DELAY)).await;
-        assert!(wt_client
-            .lock()
-            .unwrap()
-            .get_retrier_status(&tower_id)
-            .unwrap()
-            .is_running());
-
-        // Wait until the tower is no longer being retried.
+        // Wait until the retrier is waiting a few seconds for the next attempt
         wait_until!(wt_client
             .lock()
             .unwrap()
-            .get_retrier_status(&tower_id)
-            .is_none());
+            .retry_schedule
+            .get(&tower_id)
+            .is_some_and(|at| *at >= now() + 3));
+        wt_client.lock().unwrap().begin_shutdown();
 
-        // The tower should have a misbehaving status.
-        assert!(wt_client
-            .lock()
-            .unwrap()
-            .get_tower_status(&tower_id)
-            .unwrap()
-            .is_misbehaving());
-        api_mock.assert_async().await;
+        // The retry manager quiesces before the next attempt is due
+        tokio::time::timeout(Duration::from_secs(2), task)
+            .await
+            .expect("the retry manager did not stop in time")
+            .unwrap();
 
-        task.abort();
+        // The appointment is kept pending for the next start
+        let state = wt_client.lock().unwrap();
+        assert!(state
+            .dbm
+            .load_appointment_locators(tower_id, crate::AppointmentStatus::Pending)
+            .unwrap()
+            .contains(&appointment.locator));
+        assert!(!state.retriers.contains_key(&tower_id));
     }
 
     #[tokio::test]
-    async fn test_manage_retry_abandoned() {
+    async fn test_manage_retry_mirror() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let (tx, rx) = unbounded_channel();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
         ));
-        let server = mockito::Server::new_async().await;
 
-        // Add a tower with pending appointments
-        let (_, tower_pk) = cryptography::get_random_keypair();
-        let tower_id = TowerId(tower_pk);
-        let receipt = get_random_registration_receipt();
+        // Add a tower and its mirror, each one behind its own server
+        let appointment = generate_random_appointment(None);
+        let user_signature =
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap();
+        let mut towers = Vec::new();
+        for _ in 0..2 {
+            let mut server = mockito::Server::new_async().await;
+            let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+            let tower_id = TowerId(tower_pk);
+            wt_client
+                .lock()
+                .unwrap()
+                .add_update_tower(tower_id, &server.url(), &get_random_registration_receipt())
+                .unwrap();
+
+            let mut add_appointment_receipt = AppointmentReceipt::new(user_signature.clone(), 42);
+            add_appointment_receipt.sign(&tower_sk);
+            let api_mock = server
+                .mock("POST", Endpoint::AddAppointment.path().as_str())
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(
+                    json!(get_dummy_add_appointment_response(
+                        appointment.locator,
+                        &add_appointment_receipt
+                    ))
+                    .to_string(),
+                )
+                .create_async()
+                .await;
+            towers.push((tower_id, server, api_mock));
+        }
+        let (tower_id, mirror_id) = (towers[0].0, towers[1].0);
         wt_client
             .lock()
             .unwrap()
-            .add_update_tower(tower_id, &server.url(), &receipt)
+            .set_mirror(Some(mirror_id))
             .unwrap();
 
-        // Remove the tower (to simulate it has been abandoned)
-        wt_client.lock().unwrap().remove_tower(tower_id).unwrap();
-
-        // Start the task and send the tower to the channel for retry
-        tx.send((tower_id, RevocationData::None)).unwrap();
+        // Add the appointment to the pending appointments of the tower, which also enqueues it for the mirror
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+            .unwrap();
 
         let wt_client_clone = wt_client.clone();
         let task = tokio::spawn(async move {
@@ -1070,82 +1680,97 @@ mod tests {
                 MAX_ELAPSED_TIME,
                 LONG_AUTO_RETRY_DELAY,
                 MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
             )
             .manage_retry()
             .await
         });
-        assert!(!wt_client.lock().unwrap().towers.contains_key(&tower_id));
+
+        // Both towers are retried independently, and both end up holding the appointment
+        wait_until!({
+            let state = wt_client.lock().unwrap();
+            state.retriers.is_empty()
+                && state
+                    .towers
+                    .values()
+                    .all(|tower| tower.pending_appointments.is_empty())
+        });
+        for (tower_id, _, api_mock) in towers.iter() {
+            {
+                let state = wt_client.lock().unwrap();
+                assert!(state.get_tower_status(tower_id).unwrap().is_reachable());
+                assert!(state
+                    .get_appointment_receipt(*tower_id, appointment.locator)
+                    .is_some());
+            }
+            api_mock.assert_async().await;
+        }
 
         task.abort();
     }
 
     #[tokio::test]
-    async fn test_manage_retry_subscription_error() {
+    async fn test_manage_retry_tower_order() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let (tx, rx) = unbounded_channel();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
         ));
-        let mut server = mockito::Server::new_async().await;
 
-        // Add a tower with pending appointments
-        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
-        let tower_id = TowerId(tower_pk);
-        let mut registration_receipt =
-            RegistrationReceipt::new(wt_client.lock().unwrap().user_id, 21, 42, 420);
-        registration_receipt.sign(&tower_sk);
+        // Add an unreachable tower, preferred over one behind a server
+        let appointment = generate_random_appointment(None);
+        let tower_id = get_random_user_id();
         wt_client
             .lock()
             .unwrap()
-            .add_update_tower(tower_id, &server.url(), &registration_receipt)
+            .add_update_tower(
+                tower_id,
+                "http://unreachable.tower",
+                &get_random_registration_receipt(),
+            )
             .unwrap();
 
-        // Add appointment to pending
-        let appointment = generate_random_appointment(None);
+        let mut server = mockito::Server::new_async().await;
+        let (fallback_sk, fallback_pk) = cryptography::get_random_keypair();
+        let fallback_id = TowerId(fallback_pk);
         wt_client
             .lock()
             .unwrap()
-            .add_pending_appointment(tower_id, &appointment);
-
-        // Mock the registration and add_appointment response (this is right, so after the re-registration the appointments are accepted)
-        let mut re_registration_receipt =
-            get_registration_receipt_from_previous(&registration_receipt);
-        re_registration_receipt.sign(&tower_sk);
-
+            .add_update_tower(
+                fallback_id,
+                &server.url(),
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
         let mut add_appointment_receipt = AppointmentReceipt::new(
             cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
             42,
         );
-        add_appointment_receipt.sign(&tower_sk);
-        let add_appointment_response =
-            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
-
+        add_appointment_receipt.sign(&fallback_sk);
         let api_mock = server
-            .mock("POST", mockito::Matcher::Any)
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
             .with_status(200)
             .with_header("content-type", "application/json")
-            .with_body_from_request(move |request| {
-                let response = if request.path() == Endpoint::Register.path().as_str() {
-                    std::thread::sleep(Duration::from_secs_f64(API_DELAY));
-                    json!(re_registration_receipt).to_string()
-                } else if request.path() == Endpoint::AddAppointment.path().as_str() {
-                    json!(add_appointment_response).to_string()
-                } else {
-                    panic!("Wrong endpoint hit")
-                };
-                response.into()
-            })
+            .with_body(
+                json!(get_dummy_add_appointment_response(
+                    appointment.locator,
+                    &add_appointment_receipt
+                ))
+                .to_string(),
+            )
+            .expect(1)
             .create_async()
-            .await
-            .expect(2);
-
-        // Set the status as SubscriptionError so we simulate the retrier faced this in a previous round
-        wt_client
-            .lock()
-            .unwrap()
-            .set_tower_status(tower_id, TowerStatus::SubscriptionError);
+            .await;
 
-        // Start the task and send the tower to the channel for retry
+        // Only the preferred tower is targeted, so the appointment is only added to its pending appointments
+        {
+            let mut state = wt_client.lock().unwrap();
+            state.set_tower_order(vec![tower_id, fallback_id]).unwrap();
+            let mut targets = HashSet::from([tower_id, fallback_id]);
+            state.apply_tower_order(&mut targets);
+            assert_eq!(targets, HashSet::from([tower_id]));
+            state.add_pending_appointment(tower_id, &appointment);
+        }
         tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
             .unwrap();
 
@@ -1157,32 +1782,40 @@ mod tests {
                 MAX_ELAPSED_TIME,
                 LONG_AUTO_RETRY_DELAY,
                 MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
             )
             .manage_retry()
             .await
         });
 
-        // Wait for a fraction of the API delay and check how the tower status changed
-        tokio::time::sleep(Duration::from_secs_f64(HALF_API_DELAY)).await;
-        assert!(wt_client
+        // The fallback tower is not tried while the preferred one is being retried
+        tokio::time::sleep(Duration::from_secs_f64(MAX_RUN_TIME)).await;
+        {
+            let state = wt_client.lock().unwrap();
+            assert!(state.get_retrier_status(&tower_id).unwrap().is_running());
+            assert!(state.towers[&fallback_id].pending_appointments.is_empty());
+            assert!(!state.retriers.contains_key(&fallback_id));
+        }
+
+        // Once the preferred tower gives up, the appointment falls back to the next one
+        wait_until!(wt_client
             .lock()
             .unwrap()
             .get_retrier_status(&tower_id)
             .unwrap()
-            .is_running());
-
-        // Wait for the remaining time and re-check
+            .is_idle());
         wait_until!(wt_client
             .lock()
             .unwrap()
-            .get_retrier_status(&tower_id)
-            .is_none());
-
+            .get_appointment_receipt(fallback_id, appointment.locator)
+            .is_some());
         {
             let state = wt_client.lock().unwrap();
-            let tower = state.towers.get(&tower_id).unwrap();
-            assert!(tower.status.is_reachable());
-            assert!(tower.pending_appointments.is_empty());
+            assert!(state.get_tower_status(&tower_id).unwrap().is_unreachable());
+            assert!(state.towers[&tower_id]
+                .pending_appointments
+                .contains(&appointment.locator));
+            assert!(state.towers[&fallback_id].pending_appointments.is_empty());
         }
         api_mock.assert_async().await;
 
@@ -1190,165 +1823,2154 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_manage_retry_while_idle() {
-        use crate::dbm::DBM;
-        // Let's try adding a tower, setting it to idle and send revocation data in all its forms
-        // This replicates the three types of data the retrier can receive:
-        // - Initialization (from db) with stale data
-        // - Regular (fresh) data from `on_commitment_revocation`
-        // - A wake up call with no data
-
+    async fn test_manage_retry_unreachable() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
 
-        // Stale data is sent on WTClient initialization if found in the database. We'll force that to happen by populating the DB before initializing the WTClient
+        // Add a tower with pending appointments
         let (tower_sk, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
-
-        let mut dbm = DBM::new(&tmp_path.path().to_path_buf().join("watchtowers_db.sql3")).unwrap();
         let receipt = get_random_registration_receipt();
-        dbm.store_tower_record(tower_id, "http://unreachable.tower", &receipt)
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, "http://unreachable.tower", &receipt)
             .unwrap();
 
+        // Add appointment to pending
         let appointment = generate_random_appointment(None);
-        dbm.store_pending_appointment(tower_id, &appointment)
-            .unwrap();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
 
-        // Now we can create the WTClient and check that the data is pending
-        let wt_client = Arc::new(Mutex::new(
-            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
-        ));
+        // Start the task and send the tower to the channel for retry
+        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+            .unwrap();
 
-        // Also create the retrier thread so retries can be managed
         let wt_client_clone = wt_client.clone();
         let task = tokio::spawn(async move {
             RetryManager::new(
                 wt_client_clone,
                 rx,
                 MAX_ELAPSED_TIME,
-                LONG_AUTO_RETRY_DELAY,
+                SHORT_AUTO_RETRY_DELAY,
                 MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
             )
             .manage_retry()
             .await
         });
 
-        {
-            // After the retriers gives up, it should go idling and flag the tower as unreachable
-            tokio::time::sleep(Duration::from_secs_f64(
-                MAX_ELAPSED_TIME as f64 + MAX_RUN_TIME,
-            ))
-            .await;
-            let state = wt_client.lock().unwrap();
-            assert!(state.get_retrier_status(&tower_id).unwrap().is_idle());
-
-            let tower = state.towers.get(&tower_id).unwrap();
-            assert!(tower.pending_appointments.contains(&appointment.locator));
-            assert_eq!(tower.status, TowerStatus::Unreachable);
-        }
+        // Wait for one retry round and check to tower status
+        tokio::time::sleep(Duration::from_secs_f64(MAX_RUN_TIME)).await;
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_tower_status(&tower_id)
+            .unwrap()
+            .is_temporary_unreachable());
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .unwrap()
+            .is_running());
 
-        // With the retrier idling all fresh data sent to it will be stored but it won't trigger a retry.
-        // (we can check the data was stored later on)
-        let appointment2 = generate_random_appointment(None);
-        wt_client
+        // Wait until the task gives up and check again (this gives up due to accumulation of transient errors, so the retriers will be idle).
+        wait_until!(wt_client
             .lock()
             .unwrap()
-            .add_pending_appointment(tower_id, &appointment2);
-        tx.send((tower_id, RevocationData::Fresh(appointment2.locator)))
-            .unwrap();
+            .get_retrier_status(&tower_id)
+            .unwrap()
+            .is_idle());
 
-        {
-            tokio::time::sleep(Duration::from_secs_f64(POLLING_TIME as f64 + MAX_RUN_TIME)).await;
-            let state = wt_client.lock().unwrap();
-            assert!(state.get_retrier_status(&tower_id).unwrap().is_idle());
-            let tower = state.towers.get(&tower_id).unwrap();
-            assert_eq!(tower.status, TowerStatus::Unreachable);
-        }
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_tower_status(&tower_id)
+            .unwrap()
+            .is_unreachable());
 
-        // Create the receipts, the responses and set the mocks
-        let mut appointment_receipt = AppointmentReceipt::new(
+        // Add a proper server and check that the auto-retry works
+        // Prepare the mock response
+        let mut server = mockito::Server::new_async().await;
+        let mut add_appointment_receipt = AppointmentReceipt::new(
             cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
             42,
         );
-        let mut appointment2_receipt = AppointmentReceipt::new(
-            cryptography::sign(&appointment2.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
-            42,
+        add_appointment_receipt.sign(&tower_sk);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(add_appointment_response).to_string())
+            .create_async()
+            .await;
+
+        // Update the tower details
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(
+                tower_id,
+                &server.url(),
+                &get_registration_receipt_from_previous(&receipt),
+            )
+            .unwrap();
+
+        // Wait and check. We wait twice the short retry delay because it can be the case that the first auto retry
+        // is performed while we are patching the mock.
+        tokio::time::sleep(Duration::from_secs((SHORT_AUTO_RETRY_DELAY * 2) as u64)).await;
+        assert_eq!(
+            wt_client
+                .lock()
+                .unwrap()
+                .get_tower_status(&tower_id)
+                .unwrap(),
+            TowerStatus::Reachable
         );
-        appointment_receipt.sign(&tower_sk);
-        appointment2_receipt.sign(&tower_sk);
+        assert!(!wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .pending_appointments
+            .contains(&appointment.locator));
+        assert!(!wt_client.lock().unwrap().retriers.contains_key(&tower_id));
+        api_mock.assert_async().await;
 
-        // Mock a proper response
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_rejected() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
         let mut server = mockito::Server::new_async().await;
 
+        // Add a tower with pending appointments
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // Prepare the mock response
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(|_| {
+                std::thread::sleep(Duration::from_secs_f64(API_DELAY));
+                json!(ApiError {
+                    error: "error_msg".to_owned(),
+                    error_code: 1,
+                })
+                .to_string()
+                .into()
+            })
+            .create_async()
+            .await;
+
+        // Start the task and send the tower to the channel for retry
+        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+            .unwrap();
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        // Wait for a fraction of the API delay and check how the tower status changed
+        tokio::time::sleep(Duration::from_secs_f64(HALF_API_DELAY)).await;
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .unwrap()
+            .is_running());
+
+        // Wait for the remaining time and re-check
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .is_none());
+
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_tower_status(&tower_id)
+            .unwrap()
+            .is_reachable());
+        assert!(!wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .pending_appointments
+            .contains(&appointment.locator));
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .invalid_appointments
+            .contains(&appointment.locator));
+        api_mock.assert_async().await;
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_resume_idle_retrier_max_pending_age() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx)
+                .await
+                .with_max_pending_age(Some(1)),
+        ));
+
+        let tower_id = TowerId(cryptography::get_random_keypair().1);
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, "http://unreachable.tower", &receipt)
+            .unwrap();
+
+        // One appointment gets over-age while the tower is idle, the other one is added right before resuming it
+        let stale = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &stale);
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let fresh = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &fresh);
+
+        let manager = RetryManager::new(
+            wt_client.clone(),
+            rx,
+            MAX_ELAPSED_TIME,
+            LONG_AUTO_RETRY_DELAY,
+            MAX_INTERVAL_TIME,
+            POLLING_INTERVAL,
+        );
+        let retrier = Retrier::empty(wt_client.clone(), tower_id);
+        retrier.set_status(RetrierStatus::Idle(Instant::now()));
+        manager.resume_idle_retrier(&retrier);
+
+        // Only the fresh appointment is reloaded, the over-age one is flagged as expired
+        assert_eq!(
+            *retrier.pending_appointments.lock().unwrap(),
+            HashSet::from([fresh.locator])
+        );
+        let state = wt_client.lock().unwrap();
+        assert_eq!(
+            state.towers[&tower_id].pending_appointments,
+            HashSet::from([fresh.locator])
+        );
+        assert_eq!(
+            state
+                .dbm
+                .load_appointment_locators(tower_id, crate::AppointmentStatus::Pending)
+                .unwrap(),
+            HashSet::from([fresh.locator])
+        );
+        assert_eq!(
+            state
+                .dbm
+                .load_appointment_locators(tower_id, crate::AppointmentStatus::Expired)
+                .unwrap(),
+            HashSet::from([stale.locator])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_rejected_recovered() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone())
+                .await
+                .with_invalid_retry_policy(Some(InvalidRetryPolicy {
+                    cooldown: 1,
+                    max_retries: 1,
+                })),
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // Add a tower with pending appointments
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // The tower rejects the appointment first
+        let reject_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(ApiError {
+                    error: "error_msg".to_owned(),
+                    error_code: 1,
+                })
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+            .unwrap();
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        wait_until!(wt_client.lock().unwrap().towers[&tower_id]
+            .invalid_appointments
+            .contains(&appointment.locator));
+        reject_mock.assert_async().await;
+        drop(reject_mock);
+
+        // Once the tower is fixed, the appointment is accepted when retried after the cooldown
+        let mut appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        appointment_receipt.sign(&tower_sk);
+        let accept_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(get_dummy_add_appointment_response(
+                    appointment.locator,
+                    &appointment_receipt
+                ))
+                .to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_appointment_receipt(tower_id, appointment.locator)
+            .is_some());
+        accept_mock.assert_async().await;
+        {
+            let state = wt_client.lock().unwrap();
+            let tower = &state.towers[&tower_id];
+            assert!(tower.invalid_appointments.is_empty());
+            assert!(tower.pending_appointments.is_empty());
+        }
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_misbehaving() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // Add a tower with pending appointments
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // Prepare the mock response
+        let mut add_appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        // Sign with a random key so it counts as misbehaving
+        add_appointment_receipt.sign(&cryptography::get_random_keypair().0);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |_| {
+                std::thread::sleep(Duration::from_secs_f64(API_DELAY));
+                json!(add_appointment_response).to_string().into()
+            })
+            .create_async()
+            .await;
+
+        // Start the task and send the tower to the channel for retry
+        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+            .unwrap();
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        // Wait for a fraction of the API delay and check how the tower status changed
+        tokio::time::sleep(Duration::from_secs_f64(HALF_API_DELAY)).await;
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .unwrap()
+            .is_running());
+
+        // Wait until the tower is no longer being retried.
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .is_none());
+
+        // The tower should have a misbehaving status.
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_tower_status(&tower_id)
+            .unwrap()
+            .is_misbehaving());
+        api_mock.assert_async().await;
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_failed_fed() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // Add two towers with a pending appointment each. Both have been given up on
+        let mut manager = RetryManager::new(
+            wt_client.clone(),
+            rx,
+            MAX_ELAPSED_TIME,
+            LONG_AUTO_RETRY_DELAY,
+            MAX_INTERVAL_TIME,
+            POLLING_INTERVAL,
+        );
+        let mut towers = Vec::new();
+        for _ in 0..2 {
+            let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+            let tower_id = TowerId(tower_pk);
+            let appointment = generate_random_appointment(None);
+            {
+                let mut state = wt_client.lock().unwrap();
+                state
+                    .add_update_tower(tower_id, &server.url(), &get_random_registration_receipt())
+                    .unwrap();
+                state.add_pending_appointment(tower_id, &appointment);
+                state.set_tower_status(tower_id, TowerStatus::Unreachable);
+            }
+            let retrier = Retrier::new(
+                wt_client.clone(),
+                tower_id,
+                HashSet::from_iter([appointment.locator]),
+            );
+            retrier.set_status(RetrierStatus::Running);
+            retrier.set_status(RetrierStatus::Failed);
+            manager.retriers.insert(tower_id, Arc::new(retrier));
+            towers.push((tower_sk, tower_id, appointment));
+        }
+
+        // Prepare the mock response for the first tower
+        let (tower_sk, tower_id, _) = towers[0];
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&tower_sk);
+                let locator = Locator::from_slice(&request.appointment.unwrap().locator).unwrap();
+                json!(get_dummy_add_appointment_response(locator, &receipt))
+                    .to_string()
+                    .into()
+            })
+            .expect(2)
+            .create_async()
+            .await;
+
+        // The first tower receives fresh data, whereas the second receives stale data
+        let fresh_appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &fresh_appointment);
+        tx.send((tower_id, RevocationData::Fresh(fresh_appointment.locator)))
+            .unwrap();
+        let (_, stale_tower_id, stale_appointment) = &towers[1];
+        tx.send((
+            *stale_tower_id,
+            RevocationData::Stale(HashSet::from_iter([stale_appointment.locator])),
+        ))
+        .unwrap();
+
+        let task = tokio::spawn(async move { manager.manage_retry().await });
+
+        // The retrier fed with fresh data resumes and delivers all its pending appointments
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .pending_appointments
+            .is_empty());
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .is_none());
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_tower_status(&tower_id)
+            .unwrap()
+            .is_reachable());
+        api_mock.assert_async().await;
+
+        // The one fed with stale data is removed, keeping its data pending
+        {
+            let state = wt_client.lock().unwrap();
+            assert!(state.get_retrier_status(stale_tower_id).is_none());
+            assert!(state
+                .get_tower_status(stale_tower_id)
+                .unwrap()
+                .is_unreachable());
+            assert!(state
+                .towers
+                .get(stale_tower_id)
+                .unwrap()
+                .pending_appointments
+                .contains(&stale_appointment.locator));
+        }
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_abandoned() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+        let server = mockito::Server::new_async().await;
+
+        // Add a tower with pending appointments
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Remove the tower (to simulate it has been abandoned)
+        wt_client.lock().unwrap().remove_tower(tower_id).unwrap();
+
+        // Start the task and send the tower to the channel for retry
+        tx.send((tower_id, RevocationData::None)).unwrap();
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+        assert!(!wt_client.lock().unwrap().towers.contains_key(&tower_id));
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_subscription_error() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // Add a tower with pending appointments
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let mut registration_receipt =
+            RegistrationReceipt::new(wt_client.lock().unwrap().user_id, 21, 42, 420);
+        registration_receipt.sign(&tower_sk);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &registration_receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // Mock the registration and add_appointment response (this is right, so after the re-registration the appointments are accepted)
+        let mut re_registration_receipt =
+            get_registration_receipt_from_previous(&registration_receipt);
+        re_registration_receipt.sign(&tower_sk);
+
+        let mut add_appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        add_appointment_receipt.sign(&tower_sk);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
+
+        let api_mock = server
+            .mock("POST", mockito::Matcher::Any)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let response = if request.path() == Endpoint::Register.path().as_str() {
+                    std::thread::sleep(Duration::from_secs_f64(API_DELAY));
+                    json!(re_registration_receipt).to_string()
+                } else if request.path() == Endpoint::AddAppointment.path().as_str() {
+                    json!(add_appointment_response).to_string()
+                } else {
+                    panic!("Wrong endpoint hit")
+                };
+                response.into()
+            })
+            .create_async()
+            .await
+            .expect(2);
+
+        // Set the status as SubscriptionError so we simulate the retrier faced this in a previous round
+        wt_client
+            .lock()
+            .unwrap()
+            .set_tower_status(tower_id, TowerStatus::SubscriptionError);
+
+        // Start the task and send the tower to the channel for retry
+        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+            .unwrap();
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        // Wait for a fraction of the API delay and check how the tower status changed
+        tokio::time::sleep(Duration::from_secs_f64(HALF_API_DELAY)).await;
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .unwrap()
+            .is_running());
+
+        // Wait for the remaining time and re-check
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .is_none());
+
+        {
+            let state = wt_client.lock().unwrap();
+            let tower = state.towers.get(&tower_id).unwrap();
+            assert!(tower.status.is_reachable());
+            assert!(tower.pending_appointments.is_empty());
+        }
+        api_mock.assert_async().await;
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_max_retries() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // Add a tower with pending appointments
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // The tower needs its subscription renewed, but it always fails to do so
+        wt_client
+            .lock()
+            .unwrap()
+            .set_tower_status(tower_id, TowerStatus::SubscriptionError);
+        let max_retries = 3;
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(500)
+            .expect(max_retries as usize)
+            .create_async()
+            .await;
+
+        tx.send((tower_id, RevocationData::Fresh(appointment.locator)))
+            .unwrap();
+
+        // The elapsed time cap is high enough to not be hit before running out of retries
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                60,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .with_max_retries(max_retries)
+            .manage_retry()
+            .await
+        });
+
+        // The retrier gives up after exactly `max_retries` attempts
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .is_some_and(|status| status.is_idle()));
+        api_mock.assert_async().await;
+        assert_eq!(
+            wt_client.lock().unwrap().get_tower_status(&tower_id),
+            Some(TowerStatus::Unreachable)
+        );
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_aggressive() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+
+        // Add an unreachable tower with pending appointments
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, "http://unreachable.tower", &receipt)
+            .unwrap();
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+        wt_client
+            .lock()
+            .unwrap()
+            .set_tower_status(tower_id, TowerStatus::Unreachable);
+
+        // The automatic backoff takes way longer to give up than the aggressive one
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                60,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .with_aggressive_backoff(MAX_ELAPSED_TIME, MAX_INTERVAL_TIME)
+            .manage_retry()
+            .await
+        });
+
+        // Retrying aggressively makes the retrier give up as soon as the aggressive backoff does
+        wt_client
+            .lock()
+            .unwrap()
+            .retry_tower_aggressively(tower_id)
+            .unwrap();
+        tokio::time::timeout(
+            Duration::from_secs_f64(
+                POLLING_INTERVAL as f64 / 1000.0 + MAX_ELAPSED_TIME as f64 + 1.0,
+            ),
+            async {
+                wait_until!(wt_client
+                    .lock()
+                    .unwrap()
+                    .get_retrier_status(&tower_id)
+                    .is_some_and(|status| status.is_idle()));
+            },
+        )
+        .await
+        .expect("the aggressive backoff was not used");
+        assert!(wt_client.lock().unwrap().aggressive_retries.is_empty());
+
+        // The override only applies to that retry, plain manual retries go back to the automatic backoff
+        wt_client.lock().unwrap().retry_tower(tower_id).unwrap();
+        tokio::time::sleep(Duration::from_secs_f64(
+            POLLING_INTERVAL as f64 / 1000.0 + MAX_ELAPSED_TIME as f64 + 1.0,
+        ))
+        .await;
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_id)
+            .is_some_and(|status| status.is_running()));
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_tick() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+
+        // The manager has not run yet
+        assert_eq!(wt_client.lock().unwrap().get_retry_manager_tick(), None);
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        // The tick keeps advancing while the manager runs, even if there is nothing to retry
+        wait_until!(wt_client.lock().unwrap().get_retry_manager_tick().is_some());
+        let first_tick = wt_client.lock().unwrap().get_retry_manager_tick().unwrap();
+        wait_until!(wt_client.lock().unwrap().get_retry_manager_tick().unwrap() > first_tick);
+
+        // And stops once it is done
+        task.abort();
+        let _ = task.await;
+        let last_tick = wt_client.lock().unwrap().get_retry_manager_tick();
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            wt_client.lock().unwrap().get_retry_manager_tick(),
+            last_tick
+        );
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_stale_backlog() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let mut server = mockito::Server::new_async().await;
+
+        // Leave a big backlog pending from a previous run
+        let backlog = 2 * STALE_FEED_CHUNK_SIZE + 100;
+        let mut locators = HashSet::new();
+        {
+            let mut wt_client =
+                WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+            wt_client
+                .add_update_tower(tower_id, &server.url(), &get_random_registration_receipt())
+                .unwrap();
+            for _ in 0..backlog {
+                let appointment = generate_random_appointment(None);
+                wt_client.add_pending_appointment(tower_id, &appointment);
+                locators.insert(appointment.locator);
+            }
+        }
+
+        // The tower signs a receipt for every appointment it is sent
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&tower_sk);
+                let locator = Locator::from_slice(&request.appointment.unwrap().locator).unwrap();
+                json!(get_dummy_add_appointment_response(locator, &receipt))
+                    .to_string()
+                    .into()
+            })
+            .expect(backlog)
+            .create_async()
+            .await;
+
+        // Restart with the backlog being fed lazily
+        let (tx, mut rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx)
+                .await
+                .with_stale_feed(StaleFeed::Lazy),
+        ));
+        // Nothing floods the channel on startup
+        assert!(rx.try_recv().is_err());
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        // Only a chunk of the backlog is fed on the first iteration
+        tokio::time::sleep(Duration::from_secs_f64(MAX_RUN_TIME)).await;
+        assert!(matches!(
+            wt_client.lock().unwrap().stale_towers.front(),
+            Some((id, Some(_))) if *id == tower_id
+        ));
+
+        // But all of it is eventually retried
+        wait_until!(wt_client.lock().unwrap().towers[&tower_id]
+            .pending_appointments
+            .is_empty());
+        api_mock.assert_async().await;
+        {
+            let state = wt_client.lock().unwrap();
+            assert!(state.stale_towers.is_empty());
+            for locator in locators {
+                assert!(state.get_appointment_receipt(tower_id, locator).is_some());
+            }
+        }
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_while_idle() {
+        use crate::dbm::DBM;
+        // Let's try adding a tower, setting it to idle and send revocation data in all its forms
+        // This replicates the three types of data the retrier can receive:
+        // - Initialization (from db) with stale data
+        // - Regular (fresh) data from `on_commitment_revocation`
+        // - A wake up call with no data
+
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+
+        // Stale data is sent on WTClient initialization if found in the database. We'll force that to happen by populating the DB before initializing the WTClient
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+
+        let mut dbm = DBM::new(&tmp_path.path().to_path_buf().join("watchtowers_db.sql3")).unwrap();
+        let receipt = get_random_registration_receipt();
+        dbm.store_tower_record(tower_id, "http://unreachable.tower", &receipt)
+            .unwrap();
+
+        let appointment = generate_random_appointment(None);
+        dbm.store_pending_appointment(tower_id, &appointment)
+            .unwrap();
+
+        // Now we can create the WTClient and check that the data is pending
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+
+        // Also create the retrier thread so retries can be managed
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        {
+            // After the retriers gives up, it should go idling and flag the tower as unreachable
+            tokio::time::sleep(Duration::from_secs_f64(
+                MAX_ELAPSED_TIME as f64 + MAX_RUN_TIME,
+            ))
+            .await;
+            let state = wt_client.lock().unwrap();
+            assert!(state.get_retrier_status(&tower_id).unwrap().is_idle());
+
+            let tower = state.towers.get(&tower_id).unwrap();
+            assert!(tower.pending_appointments.contains(&appointment.locator));
+            assert_eq!(tower.status, TowerStatus::Unreachable);
+        }
+
+        // With the retrier idling all fresh data sent to it will be stored but it won't trigger a retry.
+        // (we can check the data was stored later on)
+        let appointment2 = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment2);
+        tx.send((tower_id, RevocationData::Fresh(appointment2.locator)))
+            .unwrap();
+
+        {
+            tokio::time::sleep(Duration::from_secs_f64(
+                POLLING_INTERVAL as f64 / 1000.0 + MAX_RUN_TIME,
+            ))
+            .await;
+            let state = wt_client.lock().unwrap();
+            assert!(state.get_retrier_status(&tower_id).unwrap().is_idle());
+            let tower = state.towers.get(&tower_id).unwrap();
+            assert_eq!(tower.status, TowerStatus::Unreachable);
+        }
+
+        // Create the receipts, the responses and set the mocks
+        let mut appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        let mut appointment2_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment2.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        appointment_receipt.sign(&tower_sk);
+        appointment2_receipt.sign(&tower_sk);
+
+        // Mock a proper response
+        let mut server = mockito::Server::new_async().await;
+
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let body = serde_json::from_slice::<AddAppointmentRequest>(request.body().unwrap())
+                    .unwrap();
+
+                let response = if body.appointment.unwrap().locator == appointment.locator.to_vec()
+                {
+                    get_dummy_add_appointment_response(appointment.locator, &appointment_receipt)
+                } else {
+                    get_dummy_add_appointment_response(appointment2.locator, &appointment2_receipt)
+                };
+                json!(response).to_string().into()
+            })
+            .expect(2)
+            .create_async()
+            .await;
+
+        // Patch the tower address
+        wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .get_mut(&tower_id)
+            .unwrap()
+            .set_net_addr(server.url());
+
+        // Check pending data is still there now, and is it not once the retrier succeeds
+        assert_eq!(
+            wt_client
+                .lock()
+                .unwrap()
+                .towers
+                .get(&tower_id)
+                .unwrap()
+                .pending_appointments
+                .len(),
+            2,
+        );
+
+        // Send a retry flag to the retrier to force a retry.
+        tx.send((tower_id, RevocationData::None)).unwrap();
+
+        // After retrying the pending pool has been emptied, meaning that both appointments went trough
+        tokio::time::sleep(Duration::from_secs_f64(
+            POLLING_INTERVAL as f64 / 1000.0 + MAX_RUN_TIME,
+        ))
+        .await;
+        assert!(!wt_client.lock().unwrap().retriers.contains_key(&tower_id));
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .pending_appointments
+            .is_empty());
+        api_mock.assert_async().await;
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_upcoming_retries() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+
+        // Add two unreachable towers with pending appointments
+        let mut towers = Vec::new();
+        for _ in 0..2 {
+            let tower_id = get_random_user_id();
+            let appointment = generate_random_appointment(None);
+            let mut state = wt_client.lock().unwrap();
+            state
+                .add_update_tower(
+                    tower_id,
+                    "http://unreachable.tower",
+                    &get_random_registration_receipt(),
+                )
+                .unwrap();
+            state.add_pending_appointment(tower_id, &appointment);
+            towers.push((tower_id, appointment.locator));
+        }
+        let (idle_tower, idle_locator) = towers[0];
+        let (running_tower, running_locator) = towers[1];
+
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+        assert!(wt_client.lock().unwrap().upcoming_retries().is_empty());
+
+        // Let the first retrier give up so it idles
+        tx.send((idle_tower, RevocationData::Fresh(idle_locator)))
+            .unwrap();
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&idle_tower)
+            .is_some_and(|status| status.is_idle()));
+        let idle_since = now();
+
+        // And get the second one running
+        tx.send((running_tower, RevocationData::Fresh(running_locator)))
+            .unwrap();
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&running_tower)
+            .is_some_and(|status| status.is_running()));
+        tokio::time::sleep(Duration::from_secs_f64(MAX_RUN_TIME)).await;
+
+        // The running retrier is backing off, so its next attempt is way closer than the idle one
+        let upcoming = wt_client.lock().unwrap().upcoming_retries();
+        assert_eq!(
+            upcoming.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![running_tower, idle_tower]
+        );
+        let (running_next, idle_next) = (upcoming[0].1, upcoming[1].1);
+        assert!(running_next <= now() + MAX_INTERVAL_TIME as u64);
+        assert!(idle_next >= idle_since + LONG_AUTO_RETRY_DELAY as u64 - 1);
+        assert!(idle_next <= idle_since + LONG_AUTO_RETRY_DELAY as u64);
+
+        // Once the running retrier idles as well, it is expected to be resumed after the other one
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&running_tower)
+            .unwrap()
+            .is_idle());
+        let upcoming = wt_client.lock().unwrap().upcoming_retries();
+        assert_eq!(upcoming[0], (idle_tower, idle_next));
+        assert_eq!(upcoming[1].0, running_tower);
+        assert!(upcoming[1].1 >= idle_next);
+
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_manage_retry_polling_interval() {
+        // Data sent to the manager while it is sleeping is only picked up on the next poll, so a shorter
+        // polling interval should get the retrier running earlier than the default one.
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, rx) = unbounded_channel();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx.clone()).await,
+        ));
+
+        let mut tower_ids = Vec::new();
+        let mut locators = Vec::new();
+        for _ in 0..2 {
+            let tower_id = get_random_user_id();
+            let appointment = generate_random_appointment(None);
+            let mut state = wt_client.lock().unwrap();
+            state
+                .add_update_tower(
+                    tower_id,
+                    "http://unreachable.tower",
+                    &get_random_registration_receipt(),
+                )
+                .unwrap();
+            state.add_pending_appointment(tower_id, &appointment);
+            tower_ids.push(tower_id);
+            locators.push(appointment.locator);
+        }
+
+        // Start a manager using the default interval and another one using the minimum interval. The first one won't
+        // receive any data, but it is kept alive so the second can be compared against it.
+        let (default_tx, default_rx) = unbounded_channel();
+        let wt_client_clone = wt_client.clone();
+        let default_task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                default_rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+        let wt_client_clone = wt_client.clone();
+        let task = tokio::spawn(async move {
+            RetryManager::new(
+                wt_client_clone,
+                rx,
+                MAX_ELAPSED_TIME,
+                LONG_AUTO_RETRY_DELAY,
+                MAX_INTERVAL_TIME,
+                MIN_POLLING_INTERVAL,
+            )
+            .manage_retry()
+            .await
+        });
+
+        // Let both managers go to sleep and feed them some data afterwards.
+        tokio::time::sleep(Duration::from_secs_f64(MAX_RUN_TIME)).await;
+        default_tx
+            .send((tower_ids[0], RevocationData::Fresh(locators[0])))
+            .unwrap();
+        tx.send((tower_ids[1], RevocationData::Fresh(locators[1])))
+            .unwrap();
+
+        // Wait for a few short polls. Only the retrier of the short interval manager should have been started.
+        tokio::time::sleep(Duration::from_millis(3 * MIN_POLLING_INTERVAL)).await;
+        {
+            let state = wt_client.lock().unwrap();
+            assert!(state.get_retrier_status(&tower_ids[0]).is_none());
+            assert!(state
+                .get_retrier_status(&tower_ids[1])
+                .unwrap()
+                .is_running());
+        }
+
+        // Eventually, the default one gets there too.
+        wait_until!(wt_client
+            .lock()
+            .unwrap()
+            .get_retrier_status(&tower_ids[0])
+            .is_some());
+
+        default_task.abort();
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // The tower we'd like to retry sending appointments to has to exist within the plugin
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // Prepare the mock response
+        let mut add_appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        add_appointment_receipt.sign(&tower_sk);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(add_appointment_response).to_string())
+            .create_async()
+            .await;
+
+        // Since we are retrying manually, we need to add the data to pending appointments manually too
+        let retrier = Retrier::new(wt_client, tower_id, HashSet::from([appointment.locator]));
+        let r = retrier.run().await;
+        assert_eq!(r, Ok(()));
+        api_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_signature_version() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let (user_sk, user_id) = {
+            let state = wt_client.lock().unwrap();
+            (state.user_sk, state.user_id)
+        };
+        let appointment = generate_random_appointment(None);
+
+        // The same appointment is sent to two towers that agreed on different signature versions. Each of them must
+        // get a signature over its own version: they differ, but both are valid for the user.
+        let mut signatures = Vec::new();
+        for version in [SignatureVersion::V1, SignatureVersion::V2] {
+            let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+            let tower_id = TowerId(tower_pk);
+            let mut server = mockito::Server::new_async().await;
+            {
+                let mut state = wt_client.lock().unwrap();
+                state
+                    .add_update_tower(tower_id, &server.url(), &get_random_registration_receipt())
+                    .unwrap();
+                state.set_tower_signature_version(tower_id, version);
+                state.add_pending_appointment(tower_id, &appointment);
+            }
+
+            let signature =
+                cryptography::sign(&appointment.to_signable_vec(version), &user_sk).unwrap();
+            assert_eq!(
+                cryptography::recover_pk(&appointment.to_signable_vec(version), &signature)
+                    .unwrap(),
+                user_id.0
+            );
+
+            let mut add_appointment_receipt = AppointmentReceipt::new(signature.clone(), 42);
+            add_appointment_receipt.sign(&tower_sk);
+            let add_appointment_response =
+                get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
+            let api_mock = server
+                .mock("POST", Endpoint::AddAppointment.path().as_str())
+                .match_body(mockito::Matcher::PartialJson(
+                    json!({ "signature": signature }),
+                ))
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(json!(add_appointment_response).to_string())
+                .create_async()
+                .await;
+
+            let retrier = Retrier::new(
+                wt_client.clone(),
+                tower_id,
+                HashSet::from([appointment.locator]),
+            );
+            assert_eq!(retrier.run().await, Ok(()));
+            api_mock.assert_async().await;
+            signatures.push(signature);
+        }
+        assert_ne!(signatures[0], signatures[1]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_batched_writes() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Build a 500 appointment backlog
+        let backlog = 500;
+        let mut locators = HashSet::new();
+        for _ in 0..backlog {
+            let appointment = generate_random_appointment(None);
+            wt_client
+                .lock()
+                .unwrap()
+                .add_pending_appointment(tower_id, &appointment);
+            locators.insert(appointment.locator);
+        }
+
+        // The tower signs a receipt for every appointment it is sent
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&tower_sk);
+                let locator = Locator::from_slice(&request.appointment.unwrap().locator).unwrap();
+                json!(get_dummy_add_appointment_response(locator, &receipt))
+                    .to_string()
+                    .into()
+            })
+            .expect(backlog)
+            .create_async()
+            .await;
+
+        let commits = wt_client.lock().unwrap().dbm.commit_counter();
+        let retrier = Retrier::new(wt_client.clone(), tower_id, locators.clone());
+        assert_eq!(retrier.run().await, Ok(()));
+        api_mock.assert_async().await;
+
+        // Deliveries were stored in batches instead of one by one (which used two transactions per appointment)
+        let commits = commits.load(std::sync::atomic::Ordering::Relaxed);
+        assert_eq!(commits, backlog / DELIVERY_BATCH_SIZE);
+        assert!(commits < 2 * backlog);
+
+        // And all the data has been properly stored
+        let state = wt_client.lock().unwrap();
+        assert!(state.towers[&tower_id].pending_appointments.is_empty());
+        assert!(state
+            .dbm
+            .load_appointment_locators(tower_id, crate::AppointmentStatus::Pending)
+            .unwrap()
+            .is_empty());
+        for locator in locators {
+            assert!(state.get_appointment_receipt(tower_id, locator).is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_cancelled() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add two appointments to pending and cancel one of them
+        let appointment = generate_random_appointment(None);
+        let cancelled = generate_random_appointment(None);
+        {
+            let mut state = wt_client.lock().unwrap();
+            state.add_pending_appointment(tower_id, &appointment);
+            state.add_pending_appointment(tower_id, &cancelled);
+            state
+                .cancel_appointment(tower_id, cancelled.locator)
+                .unwrap();
+        }
+
+        // Only the appointment that was not cancelled reaches the tower
+        let expected_locator = appointment.locator.to_vec();
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&tower_sk);
+                let locator = request.appointment.unwrap().locator;
+                assert_eq!(locator, expected_locator);
+                json!(get_dummy_add_appointment_response(
+                    Locator::from_slice(&locator).unwrap(),
+                    &receipt
+                ))
+                .to_string()
+                .into()
+            })
+            .expect(1)
+            .create_async()
+            .await;
+
+        let retrier = Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator, cancelled.locator]),
+        );
+        assert_eq!(retrier.run().await, Ok(()));
+        api_mock.assert_async().await;
+
+        assert!(!retrier.has_pending_appointments());
+        let state = wt_client.lock().unwrap();
+        assert!(state.towers[&tower_id].pending_appointments.is_empty());
+        assert!(state
+            .get_appointment_receipt(tower_id, appointment.locator)
+            .is_some());
+        assert!(state
+            .get_appointment_receipt(tower_id, cancelled.locator)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_large_backlog() {
+        let tower_id = get_random_user_id();
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(
+                tower_id,
+                "http://unreachable.tower",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+
+        // A big backlog of appointments that are not pending anymore, so none of them hits an await point
+        let backlog = 10 * RETRY_BATCH_SIZE;
+        let retrier = Arc::new(Retrier::new(
+            wt_client,
+            tower_id,
+            (0..backlog)
+                .map(|_| generate_random_appointment(None).locator)
+                .collect(),
+        ));
+        assert_eq!(retrier.next_batch().len(), RETRY_BATCH_SIZE);
+
+        // Other tasks get to run in between batches. By the time this one does, only a single batch has been handled
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let r = retrier.clone();
+        tokio::spawn(async move {
+            tx.send(r.pending_appointments.lock().unwrap().len())
+                .unwrap();
+        });
+
+        assert_eq!(retrier.run().await, Ok(()));
+        assert_eq!(rx.await.unwrap(), backlog - RETRY_BATCH_SIZE);
+        assert!(!retrier.has_pending_appointments());
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_cancelled_in_flight() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // The appointment is cancelled while the tower is processing it
+        let state = wt_client.clone();
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&tower_sk);
+                let locator = Locator::from_slice(&request.appointment.unwrap().locator).unwrap();
+                state
+                    .lock()
+                    .unwrap()
+                    .cancel_appointment(tower_id, locator)
+                    .unwrap();
+                json!(get_dummy_add_appointment_response(locator, &receipt))
+                    .to_string()
+                    .into()
+            })
+            .create_async()
+            .await;
+
+        let retrier = Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator]),
+        );
+        assert_eq!(retrier.run().await, Ok(()));
+        api_mock.assert_async().await;
+
+        // The receipt is not stored and the appointment is no longer pending
+        assert!(!retrier.has_pending_appointments());
+        let state = wt_client.lock().unwrap();
+        assert!(state.towers[&tower_id].pending_appointments.is_empty());
+        assert!(state
+            .get_appointment_receipt(tower_id, appointment.locator)
+            .is_none());
+        assert!(state
+            .dbm
+            .load_appointment_locators(tower_id, crate::AppointmentStatus::Pending)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_deadline() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (sink, mut events) = broadcast::channel(WEBHOOK_QUEUE_CAPACITY);
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+                .await
+                .with_webhook_sink(sink),
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add an appointment whose deadline has already passed and one that can still be delivered
+        let expired = generate_random_appointment(None);
+        let appointment = generate_random_appointment(None);
+        {
+            let mut state = wt_client.lock().unwrap();
+            state.add_pending_appointment_with_deadline(tower_id, &expired, now() - 1);
+            state.add_pending_appointment_with_deadline(tower_id, &appointment, now() + 3600);
+        }
+
+        // Only the appointment within its deadline reaches the tower
+        let expected_locator = appointment.locator.to_vec();
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&tower_sk);
+                let locator = request.appointment.unwrap().locator;
+                assert_eq!(locator, expected_locator);
+                json!(get_dummy_add_appointment_response(
+                    Locator::from_slice(&locator).unwrap(),
+                    &receipt
+                ))
+                .to_string()
+                .into()
+            })
+            .expect(1)
+            .create_async()
+            .await;
+
+        let retrier = Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator, expired.locator]),
+        );
+        assert_eq!(retrier.run().await, Ok(()));
+        api_mock.assert_async().await;
+
+        // The expired appointment is no longer pending and has been flagged as expired
+        assert!(!retrier.has_pending_appointments());
+        let state = wt_client.lock().unwrap();
+        assert!(state.towers[&tower_id].pending_appointments.is_empty());
+        assert!(state
+            .get_appointment_receipt(tower_id, appointment.locator)
+            .is_some());
+        assert!(state
+            .get_appointment_receipt(tower_id, expired.locator)
+            .is_none());
+        assert_eq!(
+            state
+                .dbm
+                .load_appointment_locators(tower_id, crate::AppointmentStatus::Expired)
+                .unwrap(),
+            HashSet::from([expired.locator])
+        );
+
+        // The expiry has been reported
+        let mut expiries = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if let WebhookEvent::AppointmentExpired {
+                tower_id: id,
+                locator,
+                ..
+            } = event
+            {
+                expiries.push((id, locator));
+            }
+        }
+        assert_eq!(expiries, vec![(tower_id, expired.locator)]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_no_pending() {
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let server = mockito::Server::new_async().await;
+
+        // The tower we'd like to retry sending appointments to has to exist within the plugin
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // If there are no pending appointments the method will simply return
+        let r = Retrier::empty(wt_client, tower_id).run().await;
+        assert_eq!(r, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_misbehaving() {
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // The tower we'd like to retry sending appointments to has to exist within the plugin
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // Prepare the mock response
+        let mut add_appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        add_appointment_receipt.sign(&cryptography::get_random_keypair().0);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(add_appointment_response).to_string())
+            .create_async()
+            .await;
+
+        // Since we are retrying manually, we need to add the data to pending appointments manually too
+        let retrier = Retrier::new(wt_client, tower_id, HashSet::from([appointment.locator]));
+        let r = retrier.run().await;
+        assert!(matches!(
+            r,
+            Err(Error::Permanent(RetryError::Misbehaving { .. },))
+        ));
+        api_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_unreachable() {
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+
+        // The tower we'd like to retry sending appointments to has to exist within the plugin
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, "http://unreachable.tower", &receipt)
+            .unwrap();
+
+        // Add some pending appointments and try again (with an unreachable tower).
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // Since we are retrying manually, we need to add the data to pending appointments manually too
+        let retrier = Retrier::new(wt_client, tower_id, HashSet::from([appointment.locator]));
+        let r = retrier.run().await;
+
+        assert_eq!(r, Err(Error::transient(RetryError::Unreachable)));
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_malformed_response() {
+        let (_, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // The tower we'd like to retry sending appointments to has to exist within the plugin
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // The tower replies with something that cannot be decoded
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body("not a json")
+            .expect(1)
+            .create_async()
+            .await;
+
+        // The attempt is given up as a transient error instead of sending the appointment over and over
+        let retrier = Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator]),
+        );
+        let r = retrier.run().await;
+        assert!(matches!(
+            r,
+            Err(Error::Transient {
+                err: RetryError::UnexpectedResponse(_),
+                ..
+            })
+        ));
+        api_mock.assert_async().await;
+
+        // The appointment is kept for later
+        assert!(retrier.has_pending_appointments());
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .pending_appointments
+            .contains(&appointment.locator));
+    }
+
+    #[tokio::test]
+    async fn test_retry_tower_db_busy() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+                .await
+                .with_db_busy_timeout(Duration::from_millis(10)),
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // The tower we'd like to retry sending appointments to has to exist within the plugin
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        // Add appointment to pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // Prepare the mock response
+        let mut add_appointment_receipt = AppointmentReceipt::new(
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            42,
+        );
+        add_appointment_receipt.sign(&tower_sk);
+        let add_appointment_response =
+            get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
         let api_mock = server
             .mock("POST", Endpoint::AddAppointment.path().as_str())
             .with_status(200)
             .with_header("content-type", "application/json")
-            .with_body_from_request(move |request| {
-                let body = serde_json::from_slice::<AddAppointmentRequest>(request.body().unwrap())
-                    .unwrap();
-
-                let response = if body.appointment.unwrap().locator == appointment.locator.to_vec()
-                {
-                    get_dummy_add_appointment_response(appointment.locator, &appointment_receipt)
-                } else {
-                    get_dummy_add_appointment_response(appointment2.locator, &appointment2_receipt)
-                };
-                json!(response).to_string().into()
-            })
-            .expect(2)
+            .with_body(json!(add_appointment_response).to_string())
             .create_async()
             .await;
 
-        // Patch the tower address
-        wt_client
+        // Simulate contention by having someone else hold an exclusive lock on the database
+        let other_conn =
+            rusqlite::Connection::open(tmp_path.path().join("watchtowers_db.sql3")).unwrap();
+        other_conn.execute_batch("BEGIN EXCLUSIVE").unwrap();
+
+        // A busy database is a transient error, and the appointment is kept for later
+        let retrier = Arc::new(Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator]),
+        ));
+        let r = retrier.run().await;
+        assert_eq!(r, Err(Error::transient(RetryError::Database)));
+        assert!(retrier.has_pending_appointments());
+
+        // Giving up on a busy database does not flag the tower as unreachable, the retrier is ready to be started again
+        retrier
+            .clone()
+            .start(
+                MAX_ELAPSED_TIME,
+                MAX_INTERVAL_TIME,
+                None,
+                LONG_AUTO_RETRY_DELAY,
+            )
+            .await
+            .unwrap();
+        assert!(retrier.should_start());
+        assert!(wt_client
             .lock()
             .unwrap()
-            .towers
-            .get_mut(&tower_id)
+            .get_tower_status(&tower_id)
             .unwrap()
-            .set_net_addr(server.url());
+            .is_temporary_unreachable());
+        assert!(!wt_client.lock().unwrap().towers[&tower_id]
+            .pending_appointments
+            .is_empty());
 
-        // Check pending data is still there now, and is it not once the retrier succeeds
-        assert_eq!(
-            wt_client
-                .lock()
-                .unwrap()
-                .towers
-                .get(&tower_id)
-                .unwrap()
-                .pending_appointments
-                .len(),
-            2,
+        // The retrier keeps backing off while the database is busy, and delivers the appointment once it is released
+        retrier.clone().start(
+            MAX_ELAPSED_TIME * 5,
+            MAX_INTERVAL_TIME,
+            None,
+            LONG_AUTO_RETRY_DELAY,
         );
+        tokio::time::sleep(Duration::from_secs_f64(API_DELAY)).await;
+        assert!(retrier.is_running());
+        other_conn.execute_batch("COMMIT").unwrap();
 
-        // Send a retry flag to the retrier to force a retry.
-        tx.send((tower_id, RevocationData::None)).unwrap();
-
-        // After retrying the pending pool has been emptied, meaning that both appointments went trough
-        tokio::time::sleep(Duration::from_secs_f64(POLLING_TIME as f64 + MAX_RUN_TIME)).await;
-        assert!(!wt_client.lock().unwrap().retriers.contains_key(&tower_id));
+        wait_until!(!retrier.is_running());
+        assert!(retrier.is_stopped());
+        assert!(!retrier.has_pending_appointments());
         assert!(wt_client
             .lock()
             .unwrap()
-            .towers
-            .get(&tower_id)
+            .get_tower_status(&tower_id)
             .unwrap()
-            .pending_appointments
-            .is_empty());
+            .is_reachable());
         api_mock.assert_async().await;
+    }
 
-        task.abort();
+    /// Signer that fails a given number of times before signing with the given key.
+    struct FlakySigner {
+        signer: LocalSigner,
+        failures: std::sync::atomic::AtomicUsize,
+        error: SigningError,
+    }
+
+    impl Signer for FlakySigner {
+        fn sign(&self, msg: &[u8]) -> Result<String, SigningError> {
+            if self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |f| f.checked_sub(1))
+                .is_ok()
+            {
+                Err(self.error.clone())
+            } else {
+                self.signer.sign(msg)
+            }
+        }
     }
 
     #[tokio::test]
-    async fn test_retry_tower() {
+    async fn test_retry_tower_signing_error() {
         let (tower_sk, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
         ));
+        let user_sk = wt_client.lock().unwrap().user_sk;
         let mut server = mockito::Server::new_async().await;
 
         // The tower we'd like to retry sending appointments to has to exist within the plugin
@@ -1368,7 +3990,7 @@ mod tests {
 
         // Prepare the mock response
         let mut add_appointment_receipt = AppointmentReceipt::new(
-            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
+            cryptography::sign(&appointment.to_vec(), &user_sk).unwrap(),
             42,
         );
         add_appointment_receipt.sign(&tower_sk);
@@ -1379,70 +4001,182 @@ mod tests {
             .with_status(200)
             .with_header("content-type", "application/json")
             .with_body(json!(add_appointment_response).to_string())
+            .expect(1)
             .create_async()
             .await;
 
-        // Since we are retrying manually, we need to add the data to pending appointments manually too
-        let retrier = Retrier::new(wt_client, tower_id, HashSet::from([appointment.locator]));
+        // A signer that cannot be reached is a transient error, and the appointment is kept for later
+        let error = SigningError::Unavailable("Cannot reach the signer".to_owned());
+        wt_client.lock().unwrap().signer = Arc::new(FlakySigner {
+            signer: LocalSigner::new(user_sk),
+            failures: std::sync::atomic::AtomicUsize::new(2),
+            error: error.clone(),
+        });
+        let retrier = Arc::new(Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator]),
+        ));
         let r = retrier.run().await;
-        assert_eq!(r, Ok(()));
+        assert_eq!(
+            r,
+            Err(Error::transient(RetryError::Signing(
+                error.to_string(),
+                false
+            )))
+        );
+        assert!(retrier.has_pending_appointments());
+
+        // The retrier keeps backing off until the signer is back, and delivers the appointment then
+        retrier.clone().start(
+            MAX_ELAPSED_TIME * 5,
+            MAX_INTERVAL_TIME,
+            None,
+            LONG_AUTO_RETRY_DELAY,
+        );
+        wait_until!(!retrier.is_running());
+        assert!(retrier.is_stopped());
+        assert!(!retrier.has_pending_appointments());
         api_mock.assert_async().await;
+
+        // A misconfigured signer is a permanent error. The retrier gives up, but the appointment is kept pending
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+        let error = SigningError::Misconfigured("Wrong key".to_owned());
+        wt_client.lock().unwrap().signer = Arc::new(FlakySigner {
+            signer: LocalSigner::new(user_sk),
+            failures: std::sync::atomic::AtomicUsize::new(usize::MAX),
+            error,
+        });
+        retrier
+            .pending_appointments
+            .lock()
+            .unwrap()
+            .insert(appointment.locator);
+        retrier.clone().start(
+            MAX_ELAPSED_TIME,
+            MAX_INTERVAL_TIME,
+            None,
+            LONG_AUTO_RETRY_DELAY,
+        );
+        wait_until!(!retrier.is_running());
+        assert!(retrier.failed());
+        {
+            let state = wt_client.lock().unwrap();
+            assert_eq!(
+                state.get_tower_status(&tower_id),
+                Some(TowerStatus::Unreachable)
+            );
+            assert!(state.is_pending(tower_id, appointment.locator));
+            assert!(state.last_errors[&tower_id].error.contains("Wrong key"));
+            assert_eq!(state.last_errors[&tower_id].kind, ErrorKind::Internal);
+        }
+    }
+
+    /// Signer that counts how many times it has been asked to sign.
+    struct CountingSigner {
+        signer: LocalSigner,
+        count: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Signer for CountingSigner {
+        fn sign(&self, msg: &[u8]) -> Result<String, SigningError> {
+            self.count.fetch_add(1, Ordering::Relaxed);
+            self.signer.sign(msg)
+        }
     }
 
     #[tokio::test]
-    async fn test_retry_tower_no_pending() {
+    async fn test_retry_tower_signature_cache() {
         let (_, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
         ));
-        let server = mockito::Server::new_async().await;
+        let count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        {
+            let mut state = wt_client.lock().unwrap();
+            state.signer = Arc::new(CountingSigner {
+                signer: LocalSigner::new(state.user_sk),
+                count: count.clone(),
+            });
+            state
+                .add_update_tower(
+                    tower_id,
+                    "http://unreachable.tower",
+                    &get_random_registration_receipt(),
+                )
+                .unwrap();
+        }
 
-        // The tower we'd like to retry sending appointments to has to exist within the plugin
-        let receipt = get_random_registration_receipt();
+        let appointment = generate_random_appointment(None);
         wt_client
             .lock()
             .unwrap()
-            .add_update_tower(tower_id, &server.url(), &receipt)
-            .unwrap();
+            .add_pending_appointment(tower_id, &appointment);
+        let retrier = Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator]),
+        );
 
-        // If there are no pending appointments the method will simply return
-        let r = Retrier::empty(wt_client, tower_id).run().await;
-        assert_eq!(r, Ok(()));
+        // The appointment is only signed once, no matter how many times it is retried
+        for _ in 0..3 {
+            assert_eq!(
+                retrier.run().await,
+                Err(Error::transient(RetryError::Unreachable))
+            );
+        }
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+
+        // Changing the user key invalidates the cached signatures
+        {
+            let mut state = wt_client.lock().unwrap();
+            let (user_sk, user_pk) = cryptography::get_random_keypair();
+            state.user_sk = user_sk;
+            state.user_id = UserId(user_pk);
+            state.signer = Arc::new(CountingSigner {
+                signer: LocalSigner::new(user_sk),
+                count: count.clone(),
+            });
+        }
+        retrier.run().await.unwrap_err();
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        let signatures = retrier.signatures.lock().unwrap();
+        assert_eq!(signatures.user_id, Some(wt_client.lock().unwrap().user_id));
+        assert_eq!(signatures.signatures.len(), 1);
     }
 
     #[tokio::test]
-    async fn test_retry_tower_misbehaving() {
-        let (_, tower_pk) = cryptography::get_random_keypair();
+    async fn test_retry_tower_signature_cache_pruned() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
         ));
         let mut server = mockito::Server::new_async().await;
-
-        // The tower we'd like to retry sending appointments to has to exist within the plugin
-        let receipt = get_random_registration_receipt();
         wt_client
             .lock()
             .unwrap()
-            .add_update_tower(tower_id, &server.url(), &receipt)
+            .add_update_tower(tower_id, &server.url(), &get_random_registration_receipt())
             .unwrap();
 
-        // Add appointment to pending
         let appointment = generate_random_appointment(None);
         wt_client
             .lock()
             .unwrap()
             .add_pending_appointment(tower_id, &appointment);
 
-        // Prepare the mock response
         let mut add_appointment_receipt = AppointmentReceipt::new(
             cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap(),
             42,
         );
-        add_appointment_receipt.sign(&cryptography::get_random_keypair().0);
+        add_appointment_receipt.sign(&tower_sk);
         let add_appointment_response =
             get_dummy_add_appointment_response(appointment.locator, &add_appointment_receipt);
         let api_mock = server
@@ -1453,33 +4187,45 @@ mod tests {
             .create_async()
             .await;
 
-        // Since we are retrying manually, we need to add the data to pending appointments manually too
+        // Signatures are dropped once the appointments are delivered
         let retrier = Retrier::new(wt_client, tower_id, HashSet::from([appointment.locator]));
-        let r = retrier.run().await;
-        assert!(matches!(
-            r,
-            Err(Error::Permanent(RetryError::Misbehaving { .. },))
-        ));
+        retrier.run().await.unwrap();
         api_mock.assert_async().await;
+        assert!(retrier.signatures.lock().unwrap().signatures.is_empty());
     }
 
     #[tokio::test]
-    async fn test_retry_tower_unreachable() {
+    async fn test_retry_tower_subscription_error() {
         let (_, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let wt_client = Arc::new(Mutex::new(
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
         ));
+        let mut server = mockito::Server::new_async().await;
 
         // The tower we'd like to retry sending appointments to has to exist within the plugin
         let receipt = get_random_registration_receipt();
         wt_client
             .lock()
             .unwrap()
-            .add_update_tower(tower_id, "http://unreachable.tower", &receipt)
+            .add_update_tower(tower_id, &server.url(), &receipt)
             .unwrap();
 
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(400)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!(ApiError {
+                    error: "error_msg".to_owned(),
+                    error_code: errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR,
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
         // Add some pending appointments and try again (with an unreachable tower).
         let appointment = generate_random_appointment(None);
         wt_client
@@ -1491,12 +4237,19 @@ mod tests {
         let retrier = Retrier::new(wt_client, tower_id, HashSet::from([appointment.locator]));
         let r = retrier.run().await;
 
-        assert_eq!(r, Err(Error::transient(RetryError::Unreachable)));
+        assert!(matches!(
+            r,
+            Err(Error::Transient {
+                err: RetryError::Subscription { .. },
+                ..
+            })
+        ));
+        api_mock.assert_async().await;
     }
 
     #[tokio::test]
-    async fn test_retry_tower_subscription_error() {
-        let (_, tower_pk) = cryptography::get_random_keypair();
+    async fn test_retry_tower_subscription_exhausted() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
         let tower_id = TowerId(tower_pk);
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let wt_client = Arc::new(Mutex::new(
@@ -1512,39 +4265,57 @@ mod tests {
             .add_update_tower(tower_id, &server.url(), &receipt)
             .unwrap();
 
+        // Add two appointments to pending
+        let mut locators = HashSet::new();
+        for _ in 0..2 {
+            let appointment = generate_random_appointment(None);
+            wt_client
+                .lock()
+                .unwrap()
+                .add_pending_appointment(tower_id, &appointment);
+            locators.insert(appointment.locator);
+        }
+
+        // The tower replies with no slots left to whatever appointment is sent first
         let api_mock = server
             .mock("POST", Endpoint::AddAppointment.path().as_str())
-            .with_status(400)
+            .with_status(200)
             .with_header("content-type", "application/json")
-            .with_body(
-                json!(ApiError {
-                    error: "error_msg".to_owned(),
-                    error_code: errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR,
-                })
-                .to_string(),
-            )
+            .with_body_from_request(move |request| {
+                let request: AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&tower_sk);
+                let locator = Locator::from_slice(&request.appointment.unwrap().locator).unwrap();
+                let mut response = get_dummy_add_appointment_response(locator, &receipt);
+                response.available_slots = 0;
+                json!(response).to_string().into()
+            })
+            .expect(1)
             .create_async()
             .await;
 
-        // Add some pending appointments and try again (with an unreachable tower).
-        let appointment = generate_random_appointment(None);
-        wt_client
-            .lock()
-            .unwrap()
-            .add_pending_appointment(tower_id, &appointment);
-
-        // Since we are retrying manually, we need to add the data to pending appointments manually too
-        let retrier = Retrier::new(wt_client, tower_id, HashSet::from([appointment.locator]));
+        // The retrier stops after the first delivery, since the following one would fail
+        let retrier = Retrier::new(wt_client.clone(), tower_id, locators);
         let r = retrier.run().await;
 
         assert!(matches!(
             r,
             Err(Error::Transient {
-                err: RetryError::Subscription { .. },
+                err: RetryError::Subscription(_, false),
                 ..
             })
         ));
         api_mock.assert_async().await;
+
+        // The tower is flagged as exhausted so the subscription is renewed before sending more data
+        let state = wt_client.lock().unwrap();
+        assert_eq!(
+            state.get_tower_status(&tower_id),
+            Some(TowerStatus::SubscriptionExhausted)
+        );
+        assert_eq!(state.towers[&tower_id].available_slots, 0);
+        assert_eq!(state.towers[&tower_id].pending_appointments.len(), 1);
     }
 
     #[tokio::test]
@@ -1602,6 +4373,12 @@ mod tests {
             .unwrap()
             .invalid_appointments
             .contains(&appointment.locator));
+        // The rejection is recorded in the dead-letter queue, given the appointment won't be retried
+        let dead_letters = wt_client.lock().unwrap().dead_letters(tower_id);
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].appointment, appointment);
+        assert_eq!(dead_letters[0].error_code, 1);
+        assert_eq!(dead_letters[0].error, "error_msg");
         assert!(r.is_ok());
         api_mock.assert_async().await;
     }
@@ -1631,4 +4408,68 @@ mod tests {
 
         assert_eq!(r, Err(Error::permanent(RetryError::Abandoned)));
     }
+
+    #[tokio::test]
+    async fn test_retry_tower_abandoned_while_registering() {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let mut server = mockito::Server::new_async().await;
+
+        // The tower has a subscription issue, so the retrier will try to re-register first
+        let mut receipt = RegistrationReceipt::new(wt_client.lock().unwrap().user_id, 21, 42, 420);
+        receipt.sign(&tower_sk);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+        wt_client
+            .lock()
+            .unwrap()
+            .set_tower_status(tower_id, TowerStatus::SubscriptionError);
+        let appointment = generate_random_appointment(None);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_pending_appointment(tower_id, &appointment);
+
+        // The tower is abandoned while the registration request is in flight
+        let mut re_registration_receipt = get_registration_receipt_from_previous(&receipt);
+        re_registration_receipt.sign(&tower_sk);
+        let wt_client_clone = wt_client.clone();
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |_| {
+                wt_client_clone
+                    .lock()
+                    .unwrap()
+                    .remove_tower(tower_id)
+                    .unwrap();
+                json!(re_registration_receipt).to_string().into()
+            })
+            .create_async()
+            .await;
+
+        let retrier = Retrier::new(
+            wt_client.clone(),
+            tower_id,
+            HashSet::from([appointment.locator]),
+        );
+        let r = retrier.run().await;
+
+        assert_eq!(r, Err(Error::permanent(RetryError::Abandoned)));
+        api_mock.assert_async().await;
+
+        // No stale data has been written back
+        let state = wt_client.lock().unwrap();
+        assert!(!state.towers.contains_key(&tower_id));
+        assert!(state.load_tower_info(tower_id).is_none());
+        assert!(state.get_registration_receipt(tower_id).is_none());
+    }
 }
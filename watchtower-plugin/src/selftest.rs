@@ -0,0 +1,488 @@
+//! Logic related to the self-test, a guided diagnostic that exercises the full delivery path against a test tower.
+//!
+//! The self-test registers with the tower, sends it a synthetic appointment, checks the receipt it answers with and
+//! asks the tower for the appointment back, reporting the outcome of every step. Nothing is stored by the client, so
+//! it is meant to be run against a tower dedicated to testing, and only on test networks.
+
+use serde::Serialize;
+
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+
+use teos_common::appointment::{Appointment, Locator};
+use teos_common::cryptography;
+use teos_common::net::http::Endpoint;
+use teos_common::net::NetAddr;
+use teos_common::protos as common_msgs;
+use teos_common::{TowerId, UserId};
+
+use crate::net::http::{self, AddAppointmentError, ApiResponse};
+use crate::net::RequestOptions;
+
+/// Networks (as named by CoreLN) the self-test is allowed to run on.
+pub const SELFTEST_NETWORKS: [&str; 2] = ["regtest", "signet"];
+
+/// `to_self_delay` of the synthetic appointment. It is never triggered, so any value accepted by the tower works.
+const SYNTHETIC_TO_SELF_DELAY: u32 = 42;
+
+/// Checks whether the self-test can be run on a given network.
+pub fn check_network(network: &str) -> Result<(), String> {
+    if SELFTEST_NETWORKS.contains(&network) {
+        Ok(())
+    } else {
+        Err(format!(
+            "The self-test can only be run on {}. Current network: {network}",
+            SELFTEST_NETWORKS.join(" or ")
+        ))
+    }
+}
+
+/// The steps of the self-test, in the order they are run.
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTestStep {
+    /// Registering with the tower and checking the registration receipt is signed by it.
+    Register,
+    /// Sending the synthetic appointment to the tower.
+    AddAppointment,
+    /// Checking the appointment receipt is signed by the tower.
+    VerifyReceipt,
+    /// Asking the tower for the appointment, which must be being watched.
+    GetAppointment,
+}
+
+/// The outcome of a self-test step.
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Passed,
+    Failed,
+    /// The step was not run given a previous one failed.
+    Skipped,
+}
+
+/// The outcome of a self-test step, alongside the reason if it failed.
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct StepReport {
+    pub step: SelfTestStep,
+    pub status: StepStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Outcome of the self-test (see [run]).
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub tower_id: TowerId,
+    /// Whether every step passed.
+    pub passed: bool,
+    pub steps: Vec<StepReport>,
+}
+
+impl SelfTestReport {
+    fn new(tower_id: TowerId) -> Self {
+        SelfTestReport {
+            tower_id,
+            passed: true,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Records the outcome of a step. Returns whether it passed, so the test can go on.
+    fn record(&mut self, step: SelfTestStep, outcome: Result<(), String>) -> bool {
+        let (status, error) = match outcome {
+            Ok(()) => (StepStatus::Passed, None),
+            Err(e) => {
+                self.passed = false;
+                (StepStatus::Failed, Some(e))
+            }
+        };
+        self.steps.push(StepReport {
+            step,
+            status,
+            error,
+        });
+
+        status == StepStatus::Passed
+    }
+
+    /// Records the steps that could not be run given a previous one failed.
+    fn skip(mut self, steps: &[SelfTestStep]) -> Self {
+        for step in steps {
+            self.steps.push(StepReport {
+                step: *step,
+                status: StepStatus::Skipped,
+                error: None,
+            });
+        }
+        self
+    }
+}
+
+/// Builds an appointment that can never be triggered, given it is not bound to any actual transaction.
+fn synthetic_appointment() -> Appointment {
+    let locator = Locator::from_slice(&cryptography::get_random_bytes(16)).unwrap();
+    Appointment::new(
+        locator,
+        cryptography::get_random_bytes(100),
+        SYNTHETIC_TO_SELF_DELAY,
+    )
+}
+
+/// Asks the tower for a given appointment, checking it is being watched.
+async fn check_appointment(
+    tower_net_addr: &NetAddr,
+    options: &RequestOptions,
+    locator: Locator,
+    user_sk: &SecretKey,
+) -> Result<(), String> {
+    let signature =
+        cryptography::sign(format!("get appointment {locator}").as_bytes(), user_sk).unwrap();
+
+    let response = http::process_post_response(
+        http::post_request(
+            tower_net_addr,
+            Endpoint::GetAppointment,
+            &common_msgs::GetAppointmentRequest {
+                locator: locator.to_vec(),
+                signature,
+            },
+            options,
+        )
+        .await,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+
+    match response {
+        ApiResponse::Response::<common_msgs::GetAppointmentResponse>(r) => {
+            let watched = r.status
+                == common_msgs::get_appointment_response::AppointmentStatus::BeingWatched as i32;
+            let matches = matches!(
+                r.appointment_data.and_then(|data| data.appointment_data),
+                Some(common_msgs::appointment_data::AppointmentData::Appointment(a))
+                    if a.locator == locator.to_vec()
+            );
+            if watched && matches {
+                Ok(())
+            } else {
+                Err(format!("The tower is not watching appointment {locator}"))
+            }
+        }
+        ApiResponse::Error(e) => Err(format!(
+            "The tower refused to share appointment {locator}. Error: {}, error_code: {}",
+            e.error, e.error_code
+        )),
+    }
+}
+
+/// Runs the self-test against a given tower, reporting the outcome of every step.
+///
+/// Steps are run in order, and the ones following a failed step are skipped. The tower is not added to the client.
+pub async fn run(
+    tower_id: TowerId,
+    tower_net_addr: &NetAddr,
+    user_sk: &SecretKey,
+    payment_preimage: Option<&[u8]>,
+    options: &RequestOptions,
+) -> SelfTestReport {
+    let mut report = SelfTestReport::new(tower_id);
+    let user_id = UserId(PublicKey::from_secret_key(&Secp256k1::new(), user_sk));
+
+    // Register
+    let registration = http::register(tower_id, user_id, tower_net_addr, payment_preimage, options)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|(receipt, signature_version)| {
+            if receipt.verify(&tower_id) {
+                Ok(signature_version)
+            } else {
+                Err("Registration receipt contains bad signature. Are you using the right tower_id?"
+                .to_owned())
+            }
+        });
+    let signature_version = match registration {
+        Ok(signature_version) => {
+            report.record(SelfTestStep::Register, Ok(()));
+            signature_version
+        }
+        Err(e) => {
+            report.record(SelfTestStep::Register, Err(e));
+            return report.skip(&[
+                SelfTestStep::AddAppointment,
+                SelfTestStep::VerifyReceipt,
+                SelfTestStep::GetAppointment,
+            ]);
+        }
+    };
+
+    // Send the synthetic appointment and check the receipt. A receipt not signed by the tower still means the
+    // appointment was delivered.
+    let appointment = synthetic_appointment();
+    let signature =
+        cryptography::sign(&appointment.to_signable_vec(signature_version), user_sk).unwrap();
+    let delivery =
+        http::add_appointment(tower_id, tower_net_addr, options, &appointment, &signature).await;
+    let receipt_check = match delivery {
+        Ok(_) => Ok(()),
+        Err(AddAppointmentError::SignatureError(proof)) => Err(format!(
+            "The appointment receipt is signed by {} instead",
+            proof.recovered_id
+        )),
+        Err(e) => {
+            report.record(SelfTestStep::AddAppointment, Err(e.to_string()));
+            return report.skip(&[SelfTestStep::VerifyReceipt, SelfTestStep::GetAppointment]);
+        }
+    };
+    report.record(SelfTestStep::AddAppointment, Ok(()));
+    if !report.record(SelfTestStep::VerifyReceipt, receipt_check) {
+        return report.skip(&[SelfTestStep::GetAppointment]);
+    }
+
+    // Check the tower is watching the appointment
+    let outcome = check_appointment(tower_net_addr, options, appointment.locator, user_sk).await;
+    report.record(SelfTestStep::GetAppointment, outcome);
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use teos_common::receipts::{AppointmentReceipt, RegistrationReceipt};
+
+    /// Mocks a tower that registers users, signs the appointments it receives with `signing_sk`, and reports them as
+    /// being watched if `watching` is set. Returns the tower id, alongside the server and its mocks (which are removed
+    /// from the server once dropped).
+    async fn run_mock_tower(
+        user_id: UserId,
+        signing_sk: Option<SecretKey>,
+        watching: bool,
+    ) -> (TowerId, mockito::ServerGuard, Vec<mockito::Mock>) {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let signing_sk = signing_sk.unwrap_or(tower_sk);
+        let mut registration_receipt = RegistrationReceipt::new(user_id, 21, 42, 420);
+        registration_receipt.sign(&tower_sk);
+
+        let mut server = mockito::Server::new_async().await;
+        let register_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(registration_receipt).to_string())
+            .create_async()
+            .await;
+        let add_appointment_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: common_msgs::AddAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let mut receipt = AppointmentReceipt::new(request.signature, 42);
+                receipt.sign(&signing_sk);
+                json!(common_msgs::AddAppointmentResponse {
+                    locator: request.appointment.unwrap().locator,
+                    start_block: receipt.start_block(),
+                    signature: receipt.signature().unwrap(),
+                    available_slots: 20,
+                    subscription_expiry: 420,
+                })
+                .to_string()
+                .into()
+            })
+            .create_async()
+            .await;
+        let get_appointment_mock = server
+            .mock("POST", Endpoint::GetAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |request| {
+                let request: common_msgs::GetAppointmentRequest =
+                    serde_json::from_slice(request.body().unwrap()).unwrap();
+                let response = if watching {
+                    common_msgs::GetAppointmentResponse {
+                        appointment_data: Some(common_msgs::AppointmentData {
+                            appointment_data: Some(
+                                common_msgs::appointment_data::AppointmentData::Appointment(
+                                    common_msgs::Appointment {
+                                        locator: request.locator,
+                                        encrypted_blob: Vec::new(),
+                                        to_self_delay: SYNTHETIC_TO_SELF_DELAY,
+                                    },
+                                ),
+                            ),
+                        }),
+                        status:
+                            common_msgs::get_appointment_response::AppointmentStatus::BeingWatched
+                                as i32,
+                    }
+                } else {
+                    common_msgs::GetAppointmentResponse {
+                        appointment_data: None,
+                        status: common_msgs::get_appointment_response::AppointmentStatus::NotFound
+                            as i32,
+                    }
+                };
+                json!(response).to_string().into()
+            })
+            .create_async()
+            .await;
+
+        (
+            TowerId(tower_pk),
+            server,
+            vec![register_mock, add_appointment_mock, get_appointment_mock],
+        )
+    }
+
+    fn statuses(report: &SelfTestReport) -> Vec<(SelfTestStep, StepStatus)> {
+        report.steps.iter().map(|s| (s.step, s.status)).collect()
+    }
+
+    #[test]
+    fn test_check_network() {
+        for network in SELFTEST_NETWORKS {
+            assert!(check_network(network).is_ok());
+        }
+        // CoreLN names mainnet "bitcoin"
+        assert!(check_network("bitcoin").is_err());
+        assert!(check_network("testnet").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let (user_sk, user_pk) = cryptography::get_random_keypair();
+        let (tower_id, server, _mocks) = run_mock_tower(UserId(user_pk), None, true).await;
+
+        let report = run(
+            tower_id,
+            &NetAddr::new(server.url()),
+            &user_sk,
+            None,
+            &RequestOptions::default(),
+        )
+        .await;
+
+        assert!(report.passed);
+        assert_eq!(report.tower_id, tower_id);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                (SelfTestStep::Register, StepStatus::Passed),
+                (SelfTestStep::AddAppointment, StepStatus::Passed),
+                (SelfTestStep::VerifyReceipt, StepStatus::Passed),
+                (SelfTestStep::GetAppointment, StepStatus::Passed),
+            ]
+        );
+        assert!(report.steps.iter().all(|s| s.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_run_wrong_tower_id() {
+        // A registration receipt not signed by the given tower fails the test right away
+        let (user_sk, user_pk) = cryptography::get_random_keypair();
+        let (_, server, _mocks) = run_mock_tower(UserId(user_pk), None, true).await;
+        let tower_id = TowerId(cryptography::get_random_keypair().1);
+
+        let report = run(
+            tower_id,
+            &NetAddr::new(server.url()),
+            &user_sk,
+            None,
+            &RequestOptions::default(),
+        )
+        .await;
+
+        assert!(!report.passed);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                (SelfTestStep::Register, StepStatus::Failed),
+                (SelfTestStep::AddAppointment, StepStatus::Skipped),
+                (SelfTestStep::VerifyReceipt, StepStatus::Skipped),
+                (SelfTestStep::GetAppointment, StepStatus::Skipped),
+            ]
+        );
+        assert!(report.steps[0].error.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_run_unreachable_tower() {
+        let (user_sk, _) = cryptography::get_random_keypair();
+        let tower_id = TowerId(cryptography::get_random_keypair().1);
+
+        let report = run(
+            tower_id,
+            &NetAddr::new("http://unreachable.tower".to_owned()),
+            &user_sk,
+            None,
+            &RequestOptions::default(),
+        )
+        .await;
+
+        assert!(!report.passed);
+        assert_eq!(report.steps[0].status, StepStatus::Failed);
+        assert_eq!(report.steps.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_run_bad_receipt() {
+        // The appointment is delivered, but the receipt is signed by someone else
+        let (user_sk, user_pk) = cryptography::get_random_keypair();
+        let (tower_id, server, _mocks) = run_mock_tower(
+            UserId(user_pk),
+            Some(cryptography::get_random_keypair().0),
+            true,
+        )
+        .await;
+
+        let report = run(
+            tower_id,
+            &NetAddr::new(server.url()),
+            &user_sk,
+            None,
+            &RequestOptions::default(),
+        )
+        .await;
+
+        assert!(!report.passed);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                (SelfTestStep::Register, StepStatus::Passed),
+                (SelfTestStep::AddAppointment, StepStatus::Passed),
+                (SelfTestStep::VerifyReceipt, StepStatus::Failed),
+                (SelfTestStep::GetAppointment, StepStatus::Skipped),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_not_watching() {
+        let (user_sk, user_pk) = cryptography::get_random_keypair();
+        let (tower_id, server, _mocks) = run_mock_tower(UserId(user_pk), None, false).await;
+
+        let report = run(
+            tower_id,
+            &NetAddr::new(server.url()),
+            &user_sk,
+            None,
+            &RequestOptions::default(),
+        )
+        .await;
+
+        assert!(!report.passed);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                (SelfTestStep::Register, StepStatus::Passed),
+                (SelfTestStep::AddAppointment, StepStatus::Passed),
+                (SelfTestStep::VerifyReceipt, StepStatus::Passed),
+                (SelfTestStep::GetAppointment, StepStatus::Failed),
+            ]
+        );
+    }
+}
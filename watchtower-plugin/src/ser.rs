@@ -5,7 +5,7 @@ use teos_common::appointment::{Appointment, Locator};
 
 use hex::FromHex;
 use serde::{de, ser::SerializeMap, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub fn deserialize_tx<'de, D>(deserializer: D) -> Result<Transaction, D::Error>
 where
@@ -35,6 +35,17 @@ where
     deserializer.deserialize_any(TransactionVisitor)
 }
 
+pub fn deserialize_locators<'de, D>(deserializer: D) -> Result<HashSet<Locator>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let locators: Vec<String> = de::Deserialize::deserialize(deserializer)?;
+    locators
+        .iter()
+        .map(|l| Locator::from_hex(l).map_err(|_| de::Error::custom("locator is not hex encoded")))
+        .collect()
+}
+
 pub fn serialize_receipts<S>(hm: &HashMap<Locator, String>, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
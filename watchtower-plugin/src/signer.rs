@@ -0,0 +1,71 @@
+//! Logic related to signing data on behalf of the user.
+
+use std::fmt;
+
+use bitcoin::secp256k1::SecretKey;
+
+use teos_common::cryptography;
+
+/// Errors faced when signing data on behalf of the user.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SigningError {
+    /// The signer cannot sign right now, but may be able to later on (e.g. a remote signer that cannot be reached).
+    Unavailable(String),
+    /// The signer will never be able to sign (e.g. it is set with the wrong key).
+    Misconfigured(String),
+}
+
+impl fmt::Display for SigningError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SigningError::Unavailable(e) => write!(f, "Signer unavailable: {e}"),
+            SigningError::Misconfigured(e) => write!(f, "Signer misconfigured: {e}"),
+        }
+    }
+}
+
+impl SigningError {
+    /// Whether the signer may be able to sign if asked again later on.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SigningError::Unavailable(_))
+    }
+}
+
+/// Signs data on behalf of the user, using the message signing scheme towers expect (see [cryptography::sign]).
+pub trait Signer: Send + Sync {
+    fn sign(&self, msg: &[u8]) -> Result<String, SigningError>;
+}
+
+/// [Signer] backed by a secret key held in memory. This is the one used by default.
+pub struct LocalSigner {
+    sk: SecretKey,
+}
+
+impl LocalSigner {
+    /// Creates a new [LocalSigner] instance.
+    pub fn new(sk: SecretKey) -> Self {
+        Self { sk }
+    }
+}
+
+impl Signer for LocalSigner {
+    fn sign(&self, msg: &[u8]) -> Result<String, SigningError> {
+        cryptography::sign(msg, &self.sk).map_err(|e| SigningError::Misconfigured(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_signer() {
+        let (sk, pk) = cryptography::get_random_keypair();
+        let signer = LocalSigner::new(sk);
+
+        let msg = "Test message".as_bytes();
+        let signature = signer.sign(msg).unwrap();
+        assert_eq!(signature, cryptography::sign(msg, &sk).unwrap());
+        assert!(cryptography::verify(msg, &signature, &pk));
+    }
+}
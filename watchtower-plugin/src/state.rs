@@ -0,0 +1,250 @@
+//! Logic related to exporting the client state, so it can be compared against the one of other plugin instances.
+//!
+//! Running redundant instances of the plugin (e.g. a primary and a standby) is only safe as long as they agree on the
+//! towers they are registered with and the appointments they still have to deliver. An [ExportedState] is a
+//! read-only snapshot of that data that can be diffed against the state of a different instance.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use teos_common::appointment::Locator;
+use teos_common::TowerId;
+
+use crate::{TowerStatus, TowerSummary};
+
+/// Snapshot of the data the client holds about a given tower.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedTower {
+    pub net_addr: String,
+    pub status: TowerStatus,
+    pub available_slots: u32,
+    pub subscription_expiry: u32,
+    #[serde(
+        serialize_with = "teos_common::ser::serialize_locators",
+        deserialize_with = "crate::ser::deserialize_locators"
+    )]
+    pub pending_appointments: HashSet<Locator>,
+    #[serde(
+        serialize_with = "teos_common::ser::serialize_locators",
+        deserialize_with = "crate::ser::deserialize_locators"
+    )]
+    pub invalid_appointments: HashSet<Locator>,
+}
+
+impl From<&TowerSummary> for ExportedTower {
+    fn from(summary: &TowerSummary) -> Self {
+        ExportedTower {
+            net_addr: summary.net_addr.net_addr().to_owned(),
+            status: summary.status,
+            available_slots: summary.available_slots,
+            subscription_expiry: summary.subscription_expiry,
+            pending_appointments: summary.pending_appointments.clone(),
+            invalid_appointments: summary.invalid_appointments.clone(),
+        }
+    }
+}
+
+/// Snapshot of the client state, as shared between plugin instances.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportedState {
+    pub towers: HashMap<TowerId, ExportedTower>,
+}
+
+impl ExportedState {
+    /// Builds an [ExportedState] from its JSON representation.
+    pub fn from_json(data: &str) -> Result<Self, String> {
+        serde_json::from_str(data).map_err(|e| format!("Cannot decode exported state: {e}"))
+    }
+
+    /// Compares this (local) state against a `remote` one.
+    pub fn diff(&self, remote: &ExportedState) -> StateDiff {
+        let mut diff = StateDiff::default();
+
+        for (tower_id, local_tower) in self.towers.iter() {
+            match remote.towers.get(tower_id) {
+                Some(remote_tower) => {
+                    let tower_diff = TowerDiff::new(local_tower, remote_tower);
+                    if !tower_diff.is_empty() {
+                        diff.differing.insert(*tower_id, tower_diff);
+                    }
+                }
+                None => diff.local_only.push(*tower_id),
+            }
+        }
+        diff.remote_only = remote
+            .towers
+            .keys()
+            .filter(|tower_id| !self.towers.contains_key(tower_id))
+            .cloned()
+            .collect();
+
+        diff
+    }
+}
+
+/// Differences between the data two instances hold about the same tower. Pairs are given as `(local, remote)`.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TowerDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub net_addr: Option<(String, String)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<(TowerStatus, TowerStatus)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub available_slots: Option<(u32, u32)>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_expiry: Option<(u32, u32)>,
+    /// Pending appointments only known by the local instance.
+    #[serde(
+        serialize_with = "teos_common::ser::serialize_locators",
+        skip_serializing_if = "HashSet::is_empty"
+    )]
+    pub local_pending: HashSet<Locator>,
+    /// Pending appointments only known by the remote instance.
+    #[serde(
+        serialize_with = "teos_common::ser::serialize_locators",
+        skip_serializing_if = "HashSet::is_empty"
+    )]
+    pub remote_pending: HashSet<Locator>,
+    /// Invalid appointments only known by the local instance.
+    #[serde(
+        serialize_with = "teos_common::ser::serialize_locators",
+        skip_serializing_if = "HashSet::is_empty"
+    )]
+    pub local_invalid: HashSet<Locator>,
+    /// Invalid appointments only known by the remote instance.
+    #[serde(
+        serialize_with = "teos_common::ser::serialize_locators",
+        skip_serializing_if = "HashSet::is_empty"
+    )]
+    pub remote_invalid: HashSet<Locator>,
+}
+
+/// Returns the pair if both values differ.
+fn differing<T: PartialEq + Clone>(local: &T, remote: &T) -> Option<(T, T)> {
+    (local != remote).then(|| (local.clone(), remote.clone()))
+}
+
+impl TowerDiff {
+    /// Creates a new [TowerDiff] comparing the `local` and `remote` data of a tower.
+    pub fn new(local: &ExportedTower, remote: &ExportedTower) -> Self {
+        TowerDiff {
+            net_addr: differing(&local.net_addr, &remote.net_addr),
+            status: differing(&local.status, &remote.status),
+            available_slots: differing(&local.available_slots, &remote.available_slots),
+            subscription_expiry: differing(&local.subscription_expiry, &remote.subscription_expiry),
+            local_pending: &local.pending_appointments - &remote.pending_appointments,
+            remote_pending: &remote.pending_appointments - &local.pending_appointments,
+            local_invalid: &local.invalid_appointments - &remote.invalid_appointments,
+            remote_invalid: &remote.invalid_appointments - &local.invalid_appointments,
+        }
+    }
+
+    /// Whether both instances agree on the tower data.
+    pub fn is_empty(&self) -> bool {
+        self == &TowerDiff::default()
+    }
+}
+
+/// Outcome of comparing two [ExportedState]s.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StateDiff {
+    /// Towers only known by the local instance.
+    pub local_only: Vec<TowerId>,
+    /// Towers only known by the remote instance.
+    pub remote_only: Vec<TowerId>,
+    /// Towers known by both instances whose data differs.
+    pub differing: HashMap<TowerId, TowerDiff>,
+}
+
+impl StateDiff {
+    /// Whether both instances agree on their whole state.
+    pub fn is_empty(&self) -> bool {
+        self.local_only.is_empty() && self.remote_only.is_empty() && self.differing.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use teos_common::test_utils::{generate_random_appointment, get_random_user_id};
+
+    fn get_random_exported_tower() -> ExportedTower {
+        ExportedTower {
+            net_addr: "http://talaia.watch:9814".to_owned(),
+            status: TowerStatus::Reachable,
+            available_slots: 21,
+            subscription_expiry: 420,
+            pending_appointments: HashSet::from_iter(
+                (0..3).map(|_| generate_random_appointment(None).locator),
+            ),
+            invalid_appointments: HashSet::from_iter(
+                (0..2).map(|_| generate_random_appointment(None).locator),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_json_roundtrip() {
+        let state = ExportedState {
+            towers: HashMap::from_iter(
+                (0..3).map(|_| (get_random_user_id(), get_random_exported_tower())),
+            ),
+        };
+
+        let json = serde_json::to_string(&state).unwrap();
+        assert_eq!(ExportedState::from_json(&json).unwrap(), state);
+        assert!(ExportedState::from_json("{\"towers\": 42}").is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let shared_tower_id = get_random_user_id();
+        let local_tower_id = get_random_user_id();
+        let remote_tower_id = get_random_user_id();
+        let shared_tower = get_random_exported_tower();
+
+        let local = ExportedState {
+            towers: HashMap::from([
+                (shared_tower_id, shared_tower.clone()),
+                (local_tower_id, get_random_exported_tower()),
+            ]),
+        };
+        let mut remote = ExportedState {
+            towers: HashMap::from([
+                (shared_tower_id, shared_tower.clone()),
+                (remote_tower_id, get_random_exported_tower()),
+            ]),
+        };
+
+        // Towers known by a single instance are reported, while the ones the instances agree on are not
+        let diff = local.diff(&remote);
+        assert_eq!(diff.local_only, vec![local_tower_id]);
+        assert_eq!(diff.remote_only, vec![remote_tower_id]);
+        assert!(diff.differing.is_empty());
+        assert!(!diff.is_empty());
+
+        // Diverge on the shared tower
+        let remote_tower = remote.towers.get_mut(&shared_tower_id).unwrap();
+        remote_tower.status = TowerStatus::Unreachable;
+        let delivered = *shared_tower.pending_appointments.iter().next().unwrap();
+        remote_tower.pending_appointments.remove(&delivered);
+        let new_pending = generate_random_appointment(None).locator;
+        remote_tower.pending_appointments.insert(new_pending);
+
+        let diff = local.diff(&remote);
+        assert_eq!(
+            diff.differing.get(&shared_tower_id).unwrap(),
+            &TowerDiff {
+                status: Some((TowerStatus::Reachable, TowerStatus::Unreachable)),
+                local_pending: HashSet::from([delivered]),
+                remote_pending: HashSet::from([new_pending]),
+                ..Default::default()
+            }
+        );
+
+        // A state does not diverge from itself
+        assert!(local.diff(&local).is_empty());
+    }
+}
@@ -0,0 +1,506 @@
+//! Logic related to submitting the appointments built on every commitment revocation to the towers.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::time::timeout;
+
+use teos_common::appointment::Appointment;
+use teos_common::errors;
+use teos_common::net::NetAddr;
+use teos_common::TowerId;
+
+use crate::net::http::{self, AddAppointmentError, RequestError};
+use crate::wt_client::{SubmissionPolicy, TowerError, WTClient};
+use crate::TowerStatus;
+
+/// A tower an appointment is submitted to, alongside the data needed to do so: its address, its status and the user
+/// signature of the appointment (following the signature version agreed with the tower).
+pub type SubmissionTarget = (TowerId, NetAddr, TowerStatus, String);
+
+/// Submits an appointment to a collection of towers following the client [SubmissionPolicy].
+///
+/// Appointments are sent to every reachable tower concurrently, in the background, bounded by the client max fan-out
+/// (see [WTClient::with_max_fanout]). Towers that cannot be sent the appointment at the moment get it added to their
+/// pending appointments (and retried if applicable).
+///
+/// With [SubmissionPolicy::Async] this returns straightaway. With [SubmissionPolicy::SyncAtLeastOne] this returns as
+/// soon as a tower accepts the appointment, once none can, or when the time runs out (whatever happens first). Returns
+/// whether the appointment was confirmed by any tower before returning.
+pub async fn submit_appointment(
+    wt_client: Arc<Mutex<WTClient>>,
+    appointment: Appointment,
+    targets: Vec<SubmissionTarget>,
+) -> bool {
+    let policy = wt_client.lock().unwrap().submission_policy;
+    let (tx, mut rx) = unbounded_channel();
+
+    for (tower_id, net_addr, status, signature) in targets {
+        if status.is_reachable() {
+            let wt_client = wt_client.clone();
+            let appointment = appointment.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let accepted =
+                    deliver(wt_client, tower_id, net_addr, &appointment, &signature).await;
+                // The receiver is gone if nobody is waiting for confirmation
+                tx.send(accepted).ok();
+            });
+        } else {
+            let mut state = wt_client.lock().unwrap();
+            if status.is_misbehaving() {
+                log::warn!("{tower_id} is misbehaving. Not sending any further appointments",);
+                continue;
+            } else if status.needs_renewal() {
+                log::warn!(
+                    "There is a subscription issue with {tower_id}. Adding {} to pending",
+                    appointment.locator
+                );
+            } else {
+                log::warn!(
+                    "{tower_id} is {status}. Adding {} to pending",
+                    appointment.locator,
+                );
+            }
+
+            state.add_pending_appointment(tower_id, &appointment);
+            if !status.is_unreachable() {
+                state.send_to_retrier(tower_id, appointment.locator);
+            }
+        }
+    }
+    // The channel is closed once every delivery is over
+    drop(tx);
+
+    match policy {
+        SubmissionPolicy::Async => false,
+        SubmissionPolicy::SyncAtLeastOne(max_wait) => {
+            let confirmed = timeout(max_wait, async {
+                while let Some(accepted) = rx.recv().await {
+                    if accepted {
+                        return true;
+                    }
+                }
+                false
+            })
+            .await;
+
+            match confirmed {
+                Ok(true) => true,
+                Ok(false) => {
+                    log::warn!("No tower accepted {}", appointment.locator);
+                    false
+                }
+                Err(_) => {
+                    log::warn!(
+                        "No tower confirmed {} within {}s. Carrying on in the background",
+                        appointment.locator,
+                        max_wait.as_secs_f64()
+                    );
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Sends an appointment to a reachable tower once there is room for it within the client max fan-out, so no more than
+/// the configured number of deliveries are in flight at once (see [WTClient::with_max_fanout]).
+///
+/// Returns whether the tower accepted the appointment.
+pub(crate) async fn deliver(
+    wt_client: Arc<Mutex<WTClient>>,
+    tower_id: TowerId,
+    net_addr: NetAddr,
+    appointment: &Appointment,
+    signature: &str,
+) -> bool {
+    let fanout = wt_client.lock().unwrap().fanout.clone();
+    // The semaphore is never closed, so a permit is eventually granted
+    let _permit = fanout.acquire_owned().await.unwrap();
+    send_appointment(wt_client, tower_id, net_addr, appointment, signature).await
+}
+
+/// Sends an appointment to a reachable tower, updating the client state according to the outcome.
+///
+/// Returns whether the tower accepted the appointment.
+async fn send_appointment(
+    wt_client: Arc<Mutex<WTClient>>,
+    tower_id: TowerId,
+    net_addr: NetAddr,
+    appointment: &Appointment,
+    signature: &str,
+) -> bool {
+    let options = wt_client.lock().unwrap().get_request_options(tower_id);
+    match http::add_appointment(tower_id, &net_addr, &options, appointment, signature).await {
+        Ok((slots, receipt)) => {
+            wt_client.lock().unwrap().add_appointment_receipt(
+                tower_id,
+                appointment.locator,
+                slots,
+                &receipt,
+            );
+            log::debug!("Response verified and data stored in the database");
+            true
+        }
+        Err(e) => {
+            let mut state = wt_client.lock().unwrap();
+            state.record_error(tower_id, TowerError::from(&e));
+            match e {
+                AddAppointmentError::RequestError(e) => {
+                    if e.is_connection() {
+                        log::warn!(
+                            "{tower_id} cannot be reached. Adding {} to pending appointments",
+                            appointment.locator
+                        );
+                        state.set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
+                        state.add_pending_appointment(tower_id, appointment);
+                        state.send_to_retrier(tower_id, appointment.locator);
+                    } else if let RequestError::PinMismatch(r) = e {
+                        // Not retried automatically, the tower may be being impersonated
+                        log::error!(
+                            "{r}. Adding {} to pending appointments of {tower_id}",
+                            appointment.locator
+                        );
+                        state.set_tower_status(tower_id, TowerStatus::Unreachable);
+                        state.add_pending_appointment(tower_id, appointment);
+                        state.fall_back_appointment(tower_id, appointment);
+                    }
+                }
+                AddAppointmentError::ApiError(e) => match e.error_code {
+                    errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR => {
+                        log::warn!(
+                            "There is a subscription issue with {tower_id}. Adding {} to pending",
+                            appointment.locator
+                        );
+                        state.set_tower_status(tower_id, TowerStatus::SubscriptionError);
+                        state.add_pending_appointment(tower_id, appointment);
+                        state.send_to_retrier(tower_id, appointment.locator);
+                    }
+
+                    _ => {
+                        log::warn!(
+                            "{tower_id} rejected the appointment. Error: {}, error_code: {}",
+                            e.error,
+                            e.error_code
+                        );
+                        state.add_rejected_appointment(tower_id, appointment, &e);
+                    }
+                },
+                AddAppointmentError::SignatureError(proof) => {
+                    log::warn!("Cannot recover known tower_id from the appointment receipt. Flagging tower as misbehaving");
+                    state.flag_misbehaving_tower(tower_id, proof);
+                    state.fall_back_appointment(tower_id, appointment);
+                }
+            }
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+    use tempdir::TempDir;
+
+    use teos_common::cryptography;
+    use teos_common::net::http::Endpoint;
+    use teos_common::receipts::AppointmentReceipt;
+    use teos_common::test_utils::{
+        generate_random_appointment, get_random_registration_receipt, get_random_user_id,
+    };
+
+    use crate::test_utils::get_dummy_add_appointment_response;
+    use crate::AppointmentStatus;
+
+    const API_DELAY: f64 = 1.0;
+
+    /// Registers a tower with the client that takes [API_DELAY] seconds to accept any appointment. Returns the
+    /// submission target for a given appointment.
+    async fn add_slow_tower(
+        wt_client: &Arc<Mutex<WTClient>>,
+        server: &mut mockito::Server,
+        appointment: &Appointment,
+    ) -> (SubmissionTarget, mockito::Mock) {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &get_random_registration_receipt())
+            .unwrap();
+
+        let signature =
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap();
+        let mut receipt = AppointmentReceipt::new(signature.clone(), 42);
+        receipt.sign(&tower_sk);
+        let response = get_dummy_add_appointment_response(appointment.locator, &receipt);
+
+        let api_mock = server
+            .mock("POST", Endpoint::AddAppointment.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body_from_request(move |_| {
+                std::thread::sleep(Duration::from_secs_f64(API_DELAY));
+                json!(response).to_string().into()
+            })
+            .create_async()
+            .await;
+
+        (
+            (
+                tower_id,
+                NetAddr::new(server.url()),
+                TowerStatus::Reachable,
+                signature,
+            ),
+            api_mock,
+        )
+    }
+
+    async fn new_client(tmp_path: &TempDir, policy: SubmissionPolicy) -> Arc<Mutex<WTClient>> {
+        let (tx, _) = unbounded_channel();
+        Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), tx)
+                .await
+                .with_submission_policy(policy),
+        ))
+    }
+
+    fn is_accepted(
+        wt_client: &Arc<Mutex<WTClient>>,
+        tower_id: TowerId,
+        appointment: &Appointment,
+    ) -> bool {
+        wt_client
+            .lock()
+            .unwrap()
+            .dbm
+            .load_appointment_locators(tower_id, AppointmentStatus::Accepted)
+            .unwrap()
+            .contains(&appointment.locator)
+    }
+
+    #[tokio::test]
+    async fn test_submit_appointment_async() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = new_client(&tmp_path, SubmissionPolicy::Async).await;
+        let mut server = mockito::Server::new_async().await;
+        let appointment = generate_random_appointment(None);
+        let (target, _api_mock) = add_slow_tower(&wt_client, &mut server, &appointment).await;
+        let tower_id = target.0;
+
+        // The appointment is sent in the background, so this returns without waiting for the tower
+        let start = Instant::now();
+        assert!(!submit_appointment(wt_client.clone(), appointment.clone(), vec![target]).await);
+        assert!(start.elapsed() < Duration::from_secs_f64(API_DELAY));
+        assert!(!is_accepted(&wt_client, tower_id, &appointment));
+
+        // The tower gets it eventually
+        tokio::time::sleep(Duration::from_secs_f64(API_DELAY * 2.0)).await;
+        assert!(is_accepted(&wt_client, tower_id, &appointment));
+    }
+
+    #[tokio::test]
+    async fn test_submit_appointment_sync_at_least_one() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = new_client(
+            &tmp_path,
+            SubmissionPolicy::SyncAtLeastOne(Duration::from_secs_f64(API_DELAY * 5.0)),
+        )
+        .await;
+        let mut server = mockito::Server::new_async().await;
+        let appointment = generate_random_appointment(None);
+        let (target, _api_mock) = add_slow_tower(&wt_client, &mut server, &appointment).await;
+        let tower_id = target.0;
+
+        // This waits for the tower to accept the appointment
+        let start = Instant::now();
+        assert!(submit_appointment(wt_client.clone(), appointment.clone(), vec![target]).await);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs_f64(API_DELAY));
+        assert!(elapsed < Duration::from_secs_f64(API_DELAY * 5.0));
+        assert!(is_accepted(&wt_client, tower_id, &appointment));
+    }
+
+    #[tokio::test]
+    async fn test_submit_appointment_sync_at_least_one_timeout() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let max_wait = Duration::from_secs_f64(API_DELAY / 2.0);
+        let wt_client = new_client(&tmp_path, SubmissionPolicy::SyncAtLeastOne(max_wait)).await;
+        let mut server = mockito::Server::new_async().await;
+        let appointment = generate_random_appointment(None);
+        let (target, _api_mock) = add_slow_tower(&wt_client, &mut server, &appointment).await;
+        let tower_id = target.0;
+
+        // The tower is too slow, so this gives up waiting once the time runs out
+        let start = Instant::now();
+        assert!(!submit_appointment(wt_client.clone(), appointment.clone(), vec![target]).await);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= max_wait);
+        assert!(elapsed < Duration::from_secs_f64(API_DELAY));
+        assert!(!is_accepted(&wt_client, tower_id, &appointment));
+
+        // But the appointment keeps being sent in the background
+        tokio::time::sleep(Duration::from_secs_f64(API_DELAY * 2.0)).await;
+        assert!(is_accepted(&wt_client, tower_id, &appointment));
+    }
+
+    #[tokio::test]
+    async fn test_submit_appointment_sync_at_least_one_no_reachable_tower() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = new_client(
+            &tmp_path,
+            SubmissionPolicy::SyncAtLeastOne(Duration::from_secs_f64(API_DELAY * 5.0)),
+        )
+        .await;
+        let mut server = mockito::Server::new_async().await;
+        let appointment = generate_random_appointment(None);
+        let ((tower_id, net_addr, _, signature), _api_mock) =
+            add_slow_tower(&wt_client, &mut server, &appointment).await;
+
+        // There is nothing to wait for if no tower can be sent the appointment, so it is simply added to pending
+        let start = Instant::now();
+        let target = (tower_id, net_addr, TowerStatus::Unreachable, signature);
+        assert!(!submit_appointment(wt_client.clone(), appointment.clone(), vec![target]).await);
+        assert!(start.elapsed() < Duration::from_secs_f64(API_DELAY));
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .dbm
+            .load_appointment_locators(tower_id, AppointmentStatus::Pending)
+            .unwrap()
+            .contains(&appointment.locator));
+    }
+
+    /// Registers a tower with the client that sits behind a bare HTTP server handling every request in its own thread,
+    /// taking [API_DELAY] seconds to accept any appointment. Unlike the mock servers (which all share a single thread),
+    /// this one can serve several requests at once. Returns the submission target for a given appointment.
+    fn add_concurrent_slow_tower(
+        wt_client: &Arc<Mutex<WTClient>>,
+        appointment: &Appointment,
+    ) -> SubmissionTarget {
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &url, &get_random_registration_receipt())
+            .unwrap();
+
+        let signature =
+            cryptography::sign(&appointment.to_vec(), &wt_client.lock().unwrap().user_sk).unwrap();
+        let mut receipt = AppointmentReceipt::new(signature.clone(), 42);
+        receipt.sign(&tower_sk);
+        let body = json!(get_dummy_add_appointment_response(
+            appointment.locator,
+            &receipt
+        ))
+        .to_string();
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let body = body.clone();
+                std::thread::spawn(move || {
+                    // Read the whole request (headers and body) before answering
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+                    loop {
+                        let n = stream.read(&mut buf).unwrap();
+                        request.extend_from_slice(&buf[..n]);
+                        let text = String::from_utf8_lossy(&request).to_lowercase();
+                        if let Some(headers_end) = text.find("\r\n\r\n") {
+                            let content_length = text
+                                .lines()
+                                .find_map(|l| l.strip_prefix("content-length:"))
+                                .map_or(0, |l| l.trim().parse::<usize>().unwrap());
+                            if n == 0 || request.len() >= headers_end + 4 + content_length {
+                                break;
+                            }
+                        }
+                    }
+                    std::thread::sleep(Duration::from_secs_f64(API_DELAY));
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    stream.write_all(response.as_bytes()).unwrap();
+                });
+            }
+        });
+
+        (
+            tower_id,
+            NetAddr::new(url),
+            TowerStatus::Reachable,
+            signature,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_deliver_to_all() {
+        const TOWERS: usize = 4;
+
+        for (max_fanout, min_rounds) in [(TOWERS, 1.0), (TOWERS / 2, 2.0)] {
+            let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+            let wt_client = Arc::new(Mutex::new(
+                WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+                    .await
+                    .with_max_fanout(max_fanout),
+            ));
+            let appointment = generate_random_appointment(None);
+            let mut targets = (0..TOWERS)
+                .map(|_| add_concurrent_slow_tower(&wt_client, &appointment))
+                .collect::<Vec<_>>();
+            // Unreachable towers are left to the retriers
+            let (unreachable_id, net_addr, _, signature) = targets.pop().unwrap();
+            targets.push((
+                unreachable_id,
+                net_addr,
+                TowerStatus::Unreachable,
+                signature,
+            ));
+
+            // The rest are sent the appointment concurrently, but to no more than max_fanout at a time
+            let start = Instant::now();
+            let report = WTClient::deliver_to_all(&wt_client, &appointment, targets.clone()).await;
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_secs_f64(API_DELAY * min_rounds));
+            assert!(elapsed < Duration::from_secs_f64(API_DELAY * (min_rounds + 1.0)));
+
+            assert!(report.any_accepted());
+            assert_eq!(report.accepted.len(), TOWERS - 1);
+            assert!(report.failed.is_empty());
+            assert_eq!(report.skipped, HashSet::from([unreachable_id]));
+            for (tower_id, ..) in targets {
+                assert_eq!(
+                    is_accepted(&wt_client, tower_id, &appointment),
+                    tower_id != unreachable_id
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_submission_policy_new() {
+        let max_wait = Duration::from_secs(3);
+        assert_eq!(
+            SubmissionPolicy::new("async", max_wait),
+            Ok(SubmissionPolicy::Async)
+        );
+        assert_eq!(
+            SubmissionPolicy::new("sync-at-least-one", max_wait),
+            Ok(SubmissionPolicy::SyncAtLeastOne(max_wait))
+        );
+        assert!(SubmissionPolicy::new("sync", max_wait).is_err());
+    }
+}
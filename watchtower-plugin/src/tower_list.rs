@@ -0,0 +1,139 @@
+//! Logic related to community-maintained tower lists.
+//!
+//! A tower list is a JSON document listing towers (id and network address) alongside the signature of its maintainer:
+//!
+//! ```json
+//! {
+//!     "towers": [{"tower_id": "02...", "net_addr": "http://host:port"}],
+//!     "signature": "..."
+//! }
+//! ```
+//!
+//! The signature covers one `tower_id@net_addr` line per tower (in the given order, each followed by a newline), so it
+//! does not depend on how the JSON document is formatted.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use bitcoin::secp256k1::PublicKey;
+
+use teos_common::cryptography;
+use teos_common::TowerId;
+
+/// Errors related to tower lists.
+#[derive(Debug)]
+pub enum TowerListError {
+    InvalidFormat(String),
+    InvalidSignature,
+}
+
+impl fmt::Display for TowerListError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TowerListError::InvalidFormat(x) => write!(f, "{x}"),
+            TowerListError::InvalidSignature => {
+                write!(f, "The list is not signed by the configured maintainer")
+            }
+        }
+    }
+}
+
+/// An entry of a [TowerList].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TowerListEntry {
+    pub tower_id: TowerId,
+    pub net_addr: String,
+}
+
+/// A list of towers signed by its maintainer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TowerList {
+    pub towers: Vec<TowerListEntry>,
+    pub signature: String,
+}
+
+impl TowerList {
+    /// Builds a [TowerList] from its JSON representation.
+    pub fn from_json(data: &str) -> Result<Self, TowerListError> {
+        serde_json::from_str(data)
+            .map_err(|e| TowerListError::InvalidFormat(format!("Cannot decode tower list: {e}")))
+    }
+
+    /// Serializes the list data that is covered by the maintainer signature.
+    pub fn to_signable(towers: &[TowerListEntry]) -> Vec<u8> {
+        towers
+            .iter()
+            .map(|t| format!("{}@{}\n", t.tower_id, t.net_addr))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    /// Checks whether the list is signed by `maintainer`.
+    pub fn verify(&self, maintainer: &PublicKey) -> bool {
+        cryptography::verify(
+            &TowerList::to_signable(&self.towers),
+            &self.signature,
+            maintainer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use teos_common::cryptography::get_random_keypair;
+    use teos_common::test_utils::get_random_user_id;
+
+    use crate::test_utils::get_signed_list;
+
+    #[test]
+    fn test_verify() {
+        let (sk, pk) = get_random_keypair();
+        let towers = vec![
+            TowerListEntry {
+                tower_id: get_random_user_id(),
+                net_addr: "http://talaia.watch:9814".to_owned(),
+            },
+            TowerListEntry {
+                tower_id: get_random_user_id(),
+                net_addr: "http://localhost:9814".to_owned(),
+            },
+        ];
+        let list = get_signed_list(towers, &sk);
+        assert!(list.verify(&pk));
+
+        // Signed by someone else
+        assert!(!list.verify(&get_random_keypair().1));
+
+        // Tampered with
+        let mut tampered = list.clone();
+        tampered.towers[0].net_addr = "http://evil.watch:9814".to_owned();
+        assert!(!tampered.verify(&pk));
+        let mut tampered = list;
+        tampered.towers.pop();
+        assert!(!tampered.verify(&pk));
+    }
+
+    #[test]
+    fn test_from_json() {
+        let (sk, _) = get_random_keypair();
+        let list = get_signed_list(
+            vec![TowerListEntry {
+                tower_id: get_random_user_id(),
+                net_addr: "http://talaia.watch:9814".to_owned(),
+            }],
+            &sk,
+        );
+
+        assert_eq!(
+            TowerList::from_json(&serde_json::to_string(&list).unwrap()).unwrap(),
+            list
+        );
+        assert!(matches!(
+            TowerList::from_json("{\"towers\": []}"),
+            Err(TowerListError::InvalidFormat(..))
+        ));
+    }
+}
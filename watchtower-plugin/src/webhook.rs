@@ -0,0 +1,388 @@
+//! Logic related to reporting client events (deliveries, expiries and tower status changes) to an external webhook.
+//!
+//! Events are POSTed as JSON, one at a time, from a task of their own, so a slow or unreachable webhook never holds the
+//! retriers back. Events wait to be sent in a bounded queue (see [WEBHOOK_QUEUE_CAPACITY]), so the oldest ones are
+//! dropped if the webhook cannot keep up. If a secret is set, requests carry the HMAC-SHA256 of their body so the receiver can verify them.
+//! Bodies include when they were sent, so receivers can also reject replayed requests.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use backoff::future::retry_notify;
+use backoff::{Error, ExponentialBackoff};
+
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+
+use teos_common::appointment::Locator;
+use teos_common::TowerId;
+
+use crate::retrier::now;
+use crate::wt_client::TowerStatusChange;
+
+/// For how long (in seconds) an event is retried before being dropped if the webhook cannot be reached.
+pub const WEBHOOK_MAX_RETRY_TIME: u64 = 600;
+
+/// For how long (in seconds) a request waits for the webhook to answer before being given up (and retried). Capped by
+/// the time events are retried for.
+pub const WEBHOOK_REQUEST_TIMEOUT: u64 = 30;
+
+/// Maximum number of events waiting to be sent to the webhook. Once full, the oldest events are dropped to make room.
+pub const WEBHOOK_QUEUE_CAPACITY: usize = 1024;
+
+/// Header carrying the (hex encoded) HMAC-SHA256 of the request body, keyed with the webhook secret.
+pub const SIGNATURE_HEADER: &str = "X-Watchtower-Signature";
+
+/// An event reported to the webhook.
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// An appointment has been accepted by a tower.
+    AppointmentDelivered {
+        tower_id: TowerId,
+        #[serde(with = "hex::serde")]
+        locator: Locator,
+        /// When the appointment was delivered (Unix time, in seconds).
+        timestamp: u64,
+    },
+    /// A pending appointment was not delivered to a tower in time and will not be retried anymore.
+    AppointmentExpired {
+        tower_id: TowerId,
+        #[serde(with = "hex::serde")]
+        locator: Locator,
+        /// When the appointment was expired (Unix time, in seconds).
+        timestamp: u64,
+    },
+    /// A tower has changed its status.
+    TowerStatusChanged(TowerStatusChange),
+}
+
+/// The body of a webhook request: the event, alongside when the request was sent.
+#[derive(Serialize)]
+struct WebhookRequest<'a> {
+    #[serde(flatten)]
+    event: &'a WebhookEvent,
+    /// When the request was sent (Unix time, in seconds). Covered by the signature, so receivers can reject replays.
+    sent_at: u64,
+}
+
+/// Computes the signature of a webhook request body given the webhook secret.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut engine = HmacEngine::<sha256::Hash>::new(secret.as_bytes());
+    engine.input(body);
+    hex::encode(Hmac::<sha256::Hash>::from_engine(engine).into_inner())
+}
+
+/// An external endpoint client events are POSTed to.
+#[derive(Clone, Debug)]
+pub struct Webhook {
+    url: String,
+    /// The secret requests are signed with, if any.
+    secret: Option<String>,
+    client: reqwest::Client,
+    /// For how long a request is retried before the event is dropped.
+    max_elapsed_time: Duration,
+}
+
+impl Webhook {
+    /// Creates a new [Webhook] instance. Requests are only signed if a secret is given.
+    pub fn new(url: String, secret: Option<String>, max_elapsed_time: Duration) -> Self {
+        // A webhook that never answers would otherwise hold every event behind it forever
+        let timeout = max_elapsed_time.min(Duration::from_secs(WEBHOOK_REQUEST_TIMEOUT));
+        Webhook {
+            url,
+            secret,
+            client: reqwest::Client::builder().timeout(timeout).build().unwrap(),
+            max_elapsed_time,
+        }
+    }
+
+    /// POSTs an event to the webhook. Any non-success response counts as a failure.
+    async fn post(&self, body: &str) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_owned());
+        if let Some(secret) = &self.secret {
+            request = request.header(SIGNATURE_HEADER, sign(secret, body.as_bytes()));
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("Unexpected response status: {}", response.status()))
+        }
+    }
+
+    /// Reports an event to the webhook, retrying with exponential backoff on failure. Returns whether the event was
+    /// delivered before running out of time.
+    pub async fn send(&self, event: &WebhookEvent) -> bool {
+        retry_notify(
+            ExponentialBackoff {
+                max_elapsed_time: Some(self.max_elapsed_time),
+                ..ExponentialBackoff::default()
+            },
+            || async {
+                // Every attempt is a request of its own, so it is stamped (and signed) anew
+                let body = serde_json::to_string(&WebhookRequest {
+                    event,
+                    sent_at: now(),
+                })
+                .unwrap();
+                self.post(&body).await.map_err(Error::transient)
+            },
+            |e, _| log::debug!("Cannot reach webhook. Retrying. Error: {e}"),
+        )
+        .await
+        .inspect_err(|e| log::warn!("Giving up on reporting {event:?} to the webhook. Error: {e}"))
+        .is_ok()
+    }
+
+    /// Reports the events received through the given channel, one at a time, until the channel is closed.
+    ///
+    /// The channel is expected to be bounded (see [WEBHOOK_QUEUE_CAPACITY]). Events that were dropped from it while the
+    /// webhook was lagging behind are logged.
+    pub async fn run(self, mut events: Receiver<WebhookEvent>) {
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    self.send(&event).await;
+                }
+                Err(RecvError::Lagged(dropped)) => {
+                    log::warn!("The webhook cannot keep up. Dropped the {dropped} oldest event(s)")
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::{Arc, Mutex};
+
+    use serde_json::json;
+    use tokio::sync::broadcast;
+
+    use teos_common::test_utils::{get_random_locator, get_random_user_id};
+
+    use crate::TowerStatus;
+
+    const SECRET: &str = "shh";
+    const MAX_ELAPSED_TIME: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = Webhook::new(
+            format!("{}/hook", server.url()),
+            Some(SECRET.to_owned()),
+            MAX_ELAPSED_TIME,
+        );
+
+        let tower_id = get_random_user_id();
+        let locator = get_random_locator();
+        let event = WebhookEvent::AppointmentDelivered {
+            tower_id,
+            locator,
+            timestamp: 42,
+        };
+        let payload = json!({
+            "event": "appointment_delivered",
+            "tower_id": tower_id.to_string(),
+            "locator": locator.to_string(),
+            "timestamp": 42,
+        });
+
+        // Failures are retried, so the event makes it through once the webhook is back
+        let failure_mock = server
+            .mock("POST", "/hook")
+            .with_status(500)
+            .expect(1)
+            .create_async()
+            .await;
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+        let api_mock = server
+            .mock("POST", "/hook")
+            .match_header("content-type", "application/json")
+            .match_body(mockito::Matcher::PartialJson(payload))
+            .with_status(200)
+            .with_body_from_request(move |request| {
+                requests_clone.lock().unwrap().push((
+                    request.body().unwrap().clone(),
+                    request.header(SIGNATURE_HEADER)[0].to_owned(),
+                ));
+                Vec::new()
+            })
+            .create_async()
+            .await;
+
+        let sent_after = now();
+        assert!(webhook.send(&event).await);
+        failure_mock.assert_async().await;
+        api_mock.assert_async().await;
+
+        // The body carries when it was sent, and the signature covers it
+        let (body, signature) = requests.lock().unwrap().pop().unwrap();
+        let sent_at = serde_json::from_slice::<serde_json::Value>(&body).unwrap()["sent_at"]
+            .as_u64()
+            .unwrap();
+        assert!((sent_after..=now()).contains(&sent_at));
+        assert_eq!(signature, sign(SECRET, &body));
+    }
+
+    #[tokio::test]
+    async fn test_send_timeout() {
+        // The webhook accepts connections, but never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        // Requests are given up on instead of hanging forever, so the event is eventually dropped
+        let webhook = Webhook::new(url, None, Duration::from_secs(1));
+        let event = WebhookEvent::AppointmentDelivered {
+            tower_id: get_random_user_id(),
+            locator: get_random_locator(),
+            timestamp: 42,
+        };
+        assert!(
+            !tokio::time::timeout(Duration::from_secs(10), webhook.send(&event))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_unsigned() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = Webhook::new(server.url(), None, MAX_ELAPSED_TIME);
+
+        let change = TowerStatusChange {
+            tower_id: get_random_user_id(),
+            old_status: TowerStatus::Reachable,
+            new_status: TowerStatus::Unreachable,
+            timestamp: 42,
+        };
+        let api_mock = server
+            .mock("POST", "/")
+            .match_header(SIGNATURE_HEADER, mockito::Matcher::Missing)
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "event": "tower_status_changed",
+                "tower_id": change.tower_id.to_string(),
+                "old_status": "reachable",
+                "new_status": "unreachable",
+                "timestamp": 42,
+            })))
+            .with_status(200)
+            .create_async()
+            .await;
+
+        assert!(
+            webhook
+                .send(&WebhookEvent::TowerStatusChanged(change))
+                .await
+        );
+        api_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_send_give_up() {
+        // Events are dropped once the webhook has been unreachable for too long
+        let webhook = Webhook::new(
+            "http://unreachable.webhook".to_owned(),
+            None,
+            Duration::from_secs(1),
+        );
+        assert!(
+            !webhook
+                .send(&WebhookEvent::AppointmentDelivered {
+                    tower_id: get_random_user_id(),
+                    locator: get_random_locator(),
+                    timestamp: 42,
+                })
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = Webhook::new(server.url(), None, MAX_ELAPSED_TIME);
+        let api_mock = server
+            .mock("POST", "/")
+            .with_status(200)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let (tx, rx) = broadcast::channel(WEBHOOK_QUEUE_CAPACITY);
+        for _ in 0..3 {
+            tx.send(WebhookEvent::AppointmentDelivered {
+                tower_id: get_random_user_id(),
+                locator: get_random_locator(),
+                timestamp: 42,
+            })
+            .unwrap();
+        }
+        // The task is over once the channel is closed and all the events have been reported
+        drop(tx);
+        webhook.run(rx).await;
+        api_mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_run_full_queue() {
+        let mut server = mockito::Server::new_async().await;
+        let webhook = Webhook::new(server.url(), None, MAX_ELAPSED_TIME);
+
+        // The queue only fits two events, so the oldest ones are dropped and only the two most recent are reported
+        let (tx, rx) = broadcast::channel(2);
+        for timestamp in 0..5 {
+            tx.send(WebhookEvent::AppointmentDelivered {
+                tower_id: get_random_user_id(),
+                locator: get_random_locator(),
+                timestamp,
+            })
+            .unwrap();
+        }
+        let dropped_mock = server
+            .mock("POST", "/")
+            .match_body(mockito::Matcher::Regex(r#""timestamp":[0-2]\b"#.to_owned()))
+            .expect(0)
+            .create_async()
+            .await;
+        let api_mock = server
+            .mock("POST", "/")
+            .match_body(mockito::Matcher::Regex(r#""timestamp":[3-4]\b"#.to_owned()))
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+
+        drop(tx);
+        webhook.run(rx).await;
+        dropped_mock.assert_async().await;
+        api_mock.assert_async().await;
+    }
+}
@@ -1,21 +1,42 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::iter::FromIterator;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::fs;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Semaphore;
+
+use serde::Serialize;
 
 use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::OutPoint;
 
-use teos_common::appointment::{Appointment, Locator};
+use teos_common::appointment::{Appointment, Locator, SignatureVersion};
 use teos_common::cryptography;
 use teos_common::dbm::Error as DBError;
-use teos_common::receipts::{AppointmentReceipt, RegistrationReceipt};
+use teos_common::net::NetAddr;
+use teos_common::receipts::{AppointmentReceipt, DeletionReceipt, RegistrationReceipt};
 use teos_common::{TowerId, UserId};
 
+use crate::constants;
 use crate::dbm::DBM;
-use crate::net::ProxyInfo;
-use crate::retrier::RetrierStatus;
-use crate::{MisbehaviorProof, SubscriptionError, TowerInfo, TowerStatus, TowerSummary};
+use crate::delivery::DeliveryRecord;
+use crate::net::http::{self, AddAppointmentError, ApiError, ErrorKind, RequestError};
+use crate::net::{self, ProxyInfo, RequestOptions, TlsPin, TowerHeaders};
+use crate::retrier::{self, RetrierStatus, RetrierStatusInfo};
+use crate::signer::{LocalSigner, Signer};
+use crate::state::{ExportedState, ExportedTower, StateDiff};
+use crate::submitter::{self, SubmissionTarget};
+use crate::tower_list::{TowerList, TowerListEntry, TowerListError};
+use crate::webhook::WebhookEvent;
+use crate::{
+    channel_id_from_outpoint, AppointmentStatus, DeadLetter, MisbehaviorProof, SubscriptionError,
+    TowerInfo, TowerStatus, TowerSummary,
+};
 
 #[derive(Eq, PartialEq)]
 pub enum RevocationData {
@@ -57,6 +78,368 @@ impl std::fmt::Debug for RevocationData {
     }
 }
 
+/// Outcome of syncing the local view of a tower with the data the tower holds for the user.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ResyncReport {
+    /// Pending appointments the tower was not holding. These are queued to be re-sent.
+    #[serde(serialize_with = "teos_common::ser::serialize_locators")]
+    pub resent: HashSet<Locator>,
+    /// Appointments accepted by the tower that the tower is not holding anymore. These cannot be re-sent, given
+    /// the appointment data is not kept once accepted (they may have been legitimately triggered).
+    #[serde(serialize_with = "teos_common::ser::serialize_locators")]
+    pub missing: HashSet<Locator>,
+    /// Appointments held by the tower that are not known by the client.
+    #[serde(serialize_with = "teos_common::ser::serialize_locators")]
+    pub unknown: HashSet<Locator>,
+}
+
+/// Outcome of importing a [TowerList].
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Towers the client registered with.
+    pub registered: Vec<TowerId>,
+    /// Towers the client was already registered with. These are not registered with again.
+    pub skipped: Vec<TowerId>,
+    /// Towers the client could not register with, alongside the reason.
+    pub failed: HashMap<TowerId, String>,
+}
+
+/// Outcome of delivering an appointment to several towers at once (see [WTClient::deliver_to_all]).
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryReport {
+    /// Towers that accepted the appointment.
+    pub accepted: HashSet<TowerId>,
+    /// Towers that were sent the appointment but did not accept it.
+    pub failed: HashSet<TowerId>,
+    /// Towers that were not sent the appointment given they were not reachable.
+    pub skipped: HashSet<TowerId>,
+}
+
+impl DeliveryReport {
+    /// Whether any of the towers accepted the appointment.
+    pub fn any_accepted(&self) -> bool {
+        !self.accepted.is_empty()
+    }
+}
+
+/// Receipts of a given tower that do not validate against its id.
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct InvalidReceipts {
+    /// Subscription expiry of the registration receipts that do not validate.
+    pub registration_receipts: Vec<u32>,
+    /// Locators of the appointment receipts that do not validate.
+    #[serde(serialize_with = "teos_common::ser::serialize_locators")]
+    pub appointment_receipts: HashSet<Locator>,
+}
+
+/// Outcome of verifying all the stored receipts (see [WTClient::verify_all_receipts]).
+#[derive(Serialize, Debug, Default, PartialEq, Eq)]
+pub struct ReceiptsReport {
+    /// Number of receipts checked, both registration and appointment receipts.
+    pub checked: usize,
+    /// Towers with receipts that do not validate, alongside the receipts.
+    pub invalid: HashMap<TowerId, InvalidReceipts>,
+}
+
+/// An error faced when sending data to a tower.
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct TowerError {
+    /// When the error happened (Unix time, in seconds).
+    pub timestamp: u64,
+    /// The category of the error.
+    pub kind: ErrorKind,
+    pub error: String,
+    /// The error code returned by the tower API, if the tower replied with an error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<u8>,
+}
+
+impl TowerError {
+    /// Creates a new [TowerError] happening now.
+    pub fn new(kind: ErrorKind, error: String) -> Self {
+        TowerError {
+            timestamp: retrier::now(),
+            kind,
+            error,
+            code: None,
+        }
+    }
+}
+
+impl From<&RequestError> for TowerError {
+    fn from(e: &RequestError) -> Self {
+        TowerError::new(e.kind(), e.to_string())
+    }
+}
+
+impl From<&AddAppointmentError> for TowerError {
+    fn from(e: &AddAppointmentError) -> Self {
+        TowerError {
+            code: match e {
+                AddAppointmentError::ApiError(e) => Some(e.error_code),
+                _ => None,
+            },
+            ..TowerError::new(e.kind(), e.to_string())
+        }
+    }
+}
+
+/// Summary of why a tower is at its current status.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct TowerDiagnosis {
+    pub status: TowerStatus,
+    /// The status of the retrier associated to the tower, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrier: Option<RetrierStatusInfo>,
+    /// The last error faced when sending data to the tower, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<TowerError>,
+    pub accepted_appointments: usize,
+    pub pending_appointments: usize,
+    pub invalid_appointments: usize,
+    pub expired_appointments: usize,
+    /// When an appointment was last delivered to the tower (Unix time, in seconds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_delivery: Option<u64>,
+    /// Human-readable explanation of the above.
+    pub explanation: String,
+}
+
+/// A feature supported by the running plugin, so front-ends can tell which functionality they can rely on.
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Pending appointments are delivered to the towers in batches.
+    BatchDelivery,
+    /// Fresh appointments are delivered to several towers concurrently (see `watchtower-max-fanout`).
+    ConcurrentDelivery,
+    /// Towers can be reached through the proxy on a per-tower basis.
+    PerTowerProxy,
+    /// Tower TLS certificates can be pinned (`settowerpin`).
+    TlsPinning,
+    /// Appointments can be mirrored to a backup tower (`setmirror`).
+    MirrorTowers,
+    /// Appointments can fall back through an ordered list of towers (`settowerorder`).
+    TowerOrder,
+    /// Channels can be restricted to a subset of the towers (`setchanneltowers`).
+    ChannelTowers,
+    /// Towers can be labeled and filtered by label (`settowerlabels`).
+    TowerLabels,
+    /// Subscriptions can be automatically renewed (`setautorenew`).
+    AutoRenew,
+    /// Signed tower lists can be imported (`importlist`).
+    TowerLists,
+    /// The client state can be exported and compared against other instances (`exportstate`, `diffstate`).
+    StateExport,
+    /// Delivered appointments can be exported as CSV (`exportcsv`).
+    CsvExport,
+    /// Stored receipts can be verified against the towers they belong to (`verifyreceipts`).
+    ReceiptVerification,
+    /// The full delivery path can be exercised against a test tower (`selftest`).
+    SelfTest,
+    /// Permanently rejected appointments are kept in a dead-letter queue (`deadletters`).
+    DeadLetters,
+    /// Towers can be manually retried using a tighter backoff (`retrytower` with `aggressive` set).
+    AggressiveRetry,
+    /// Deliveries, expiries and tower status changes can be POSTed to a webhook (`watchtower-webhook-url`).
+    Webhooks,
+    /// The client can be driven synchronously, without an async runtime (the `blocking` Cargo feature).
+    Blocking,
+}
+
+/// The version of the running plugin alongside the features it supports (see [WTClient::capabilities]).
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub features: Vec<Capability>,
+}
+
+/// Number of blocks before the subscription expiry from which a subscription is reported as expiring.
+pub const EXPIRY_WARNING_BLOCKS: u32 = 1008;
+
+/// How long (in seconds) to wait before trying to automatically renew a subscription again after a failed attempt.
+pub const AUTO_RENEW_RETRY_DELAY: u64 = 600;
+
+/// Why a tower requires action from the user (see [WTClient::towers_needing_attention]).
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AttentionReason {
+    /// The subscription expires within the next [EXPIRY_WARNING_BLOCKS] blocks.
+    SubscriptionExpiring,
+    /// The subscription has already expired.
+    SubscriptionExpired,
+    /// The subscription is not valid and could not be renewed.
+    SubscriptionError,
+    /// The subscription has run out of slots.
+    SlotsExhausted,
+    /// The tower could not be reached after retrying for a while.
+    Failed,
+    /// The tower has misbehaved.
+    Misbehaving,
+    /// The subscription could not be automatically renewed given the tower requires a payment.
+    PaymentRequired,
+}
+
+/// Counters of the appointments delivered to the towers since the client was started.
+///
+/// Counters never go down, so they can be periodically sampled to compute delivery rates.
+#[derive(Clone, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct DeliveryMetrics {
+    /// Appointments delivered to any tower.
+    pub delivered: u64,
+    /// Appointments delivered to each tower.
+    pub delivered_per_tower: HashMap<TowerId, u64>,
+}
+
+impl DeliveryMetrics {
+    /// Accounts for a number of appointments delivered to a given tower.
+    fn record(&mut self, tower_id: TowerId, count: u64) {
+        self.delivered += count;
+        *self.delivered_per_tower.entry(tower_id).or_default() += count;
+    }
+}
+
+/// The appointments pending delivery, in total and per tower (see [WTClient::total_pending]).
+#[derive(Clone, Serialize, Debug, Default, PartialEq, Eq)]
+pub struct PendingWorkload {
+    /// Appointments pending delivery to any tower. An appointment pending for several towers is counted once per tower.
+    pub total: usize,
+    /// Appointments pending delivery to each tower. Every registered tower is included, even if it has none pending.
+    pub per_tower: HashMap<TowerId, usize>,
+}
+
+/// An appointment created for a given channel, alongside its status for every tower it was sent to
+/// (see [WTClient::appointments_for_outpoint]).
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct ChannelAppointment {
+    #[serde(with = "hex::serde")]
+    pub locator: Locator,
+    pub commit_num: u32,
+    /// The status of the appointment for each tower. Towers that have been abandoned are not included.
+    pub towers: HashMap<TowerId, AppointmentStatus>,
+}
+
+/// A change in the status of a tower, as reported through the status sink (see [WTClient::with_status_sink]).
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct TowerStatusChange {
+    pub tower_id: TowerId,
+    pub old_status: TowerStatus,
+    pub new_status: TowerStatus,
+    /// When the change happened (Unix time, in seconds).
+    pub timestamp: u64,
+}
+
+/// Status of a tower alongside the one of its retrier, as reported by [WTClient::all_tower_statuses].
+#[derive(Clone, Serialize, Debug, PartialEq, Eq)]
+pub struct TowerStatusSummary {
+    pub status: TowerStatus,
+    /// The status of the retrier of the tower, if it is being retried.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retrier: Option<RetrierStatusInfo>,
+    pub pending_count: usize,
+}
+
+/// Which tower status changes are reported through the status sink, based on the status the tower changes to.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum StatusFilter {
+    /// Every change is reported.
+    #[default]
+    All,
+    /// Only changes to any of the given statuses are reported.
+    Only(HashSet<TowerStatus>),
+}
+
+impl StatusFilter {
+    /// Whether a change to the given status is reported.
+    pub fn allows(&self, status: TowerStatus) -> bool {
+        match self {
+            StatusFilter::All => true,
+            StatusFilter::Only(statuses) => statuses.contains(&status),
+        }
+    }
+}
+
+impl FromStr for StatusFilter {
+    type Err = String;
+
+    /// Parses either `all` or a comma separated list of statuses, e.g. `unreachable,misbehaving`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "all" {
+            return Ok(StatusFilter::All);
+        }
+        s.split(',')
+            .map(|status| TowerStatus::from_str(status.trim()))
+            .collect::<Result<HashSet<_>, _>>()
+            .map(StatusFilter::Only)
+    }
+}
+
+/// Number of stale locators fed to the retriers per [RetryManager](crate::retrier::RetryManager) iteration when
+/// feeding them lazily.
+pub const STALE_FEED_CHUNK_SIZE: usize = 500;
+/// Stale backlog (in appointments) above which [StaleFeed::Auto] feeds the retriers lazily.
+pub const LAZY_FEED_THRESHOLD: usize = 5000;
+
+/// How the stale data found in the database on startup is fed to the retriers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StaleFeed {
+    /// The whole backlog is fed at once.
+    Eager,
+    /// The backlog is loaded from the database and fed in chunks of [STALE_FEED_CHUNK_SIZE] appointments, one chunk per
+    /// [RetryManager](crate::retrier::RetryManager) iteration.
+    Lazy,
+    /// [StaleFeed::Lazy] if the backlog is bigger than [LAZY_FEED_THRESHOLD] appointments, [StaleFeed::Eager] otherwise.
+    Auto,
+}
+
+impl FromStr for StaleFeed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eager" => Ok(StaleFeed::Eager),
+            "lazy" => Ok(StaleFeed::Lazy),
+            "auto" => Ok(StaleFeed::Auto),
+            _ => Err(format!(
+                "Unknown stale feed strategy: {s}. Expected eager, lazy or auto"
+            )),
+        }
+    }
+}
+
+/// How the appointments built on every commitment revocation are submitted to the towers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SubmissionPolicy {
+    /// Appointments are sent in the background, so the revocation is let through straightaway.
+    Async,
+    /// The revocation is held until at least one tower confirms the appointment, for up to the given time. Appointments
+    /// keep being sent in the background (as with [SubmissionPolicy::Async]) if the time runs out.
+    SyncAtLeastOne(Duration),
+}
+
+impl SubmissionPolicy {
+    /// Builds a [SubmissionPolicy] given its name. `max_wait` is only used by [SubmissionPolicy::SyncAtLeastOne].
+    pub fn new(policy: &str, max_wait: Duration) -> Result<Self, String> {
+        match policy {
+            "async" => Ok(SubmissionPolicy::Async),
+            "sync-at-least-one" => Ok(SubmissionPolicy::SyncAtLeastOne(max_wait)),
+            _ => Err(format!(
+                "Unknown submission policy: {policy}. Expected async or sync-at-least-one"
+            )),
+        }
+    }
+}
+
+/// Policy to automatically retry the appointments rejected by the towers, given some rejections may be due to a temporary
+/// misconfiguration of the tower.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidRetryPolicy {
+    /// For how long (in seconds) an appointment is kept as invalid before being retried.
+    pub cooldown: u64,
+    /// How many times a rejected appointment is retried before being considered permanently invalid.
+    pub max_retries: u32,
+}
+
 /// Represents the watchtower client that is being used as the CoreLN plugin state.
 pub struct WTClient {
     /// A [DBM] instance.
@@ -67,12 +450,75 @@ pub struct WTClient {
     pub unreachable_towers: UnboundedSender<(TowerId, RevocationData)>,
     // Map of existing retriers and its state.
     pub retriers: HashMap<TowerId, RetrierStatus>,
+    /// Estimated time (Unix seconds) of the next attempt of each active retrier. Kept up to date by the retriers.
+    pub retry_schedule: HashMap<TowerId, u64>,
+    /// Towers manually flagged to be retried aggressively whose retrier has not been started yet.
+    pub aggressive_retries: HashSet<TowerId>,
+    /// The last error faced when sending data to each tower. Only kept in memory.
+    pub last_errors: HashMap<TowerId, TowerError>,
+    /// Time (Unix seconds) of the last appointment delivered to each tower. Only kept in memory.
+    pub last_deliveries: HashMap<TowerId, u64>,
+    /// Time (Unix seconds) since when each unreachable tower has not been reached. Only kept in memory, so towers
+    /// loaded as unreachable count from the moment the client is started.
+    pub unreachable_since: HashMap<TowerId, u64>,
+    /// Counters of the appointments delivered to the towers. Only kept in memory.
+    pub delivery_metrics: DeliveryMetrics,
     /// The user secret key.
     pub user_sk: SecretKey,
+    /// Signs the appointments sent by the retriers. Backed by the user secret key by default.
+    pub signer: Arc<dyn Signer>,
     /// The user identifier.
     pub user_id: UserId,
-    /// Optional proxy
+    /// Optional proxy. Used by all towers flagged with `use_proxy`, or by every tower if `always_use` is set.
     pub proxy: Option<ProxyInfo>,
+    /// The headers sent along with the requests to the towers.
+    pub headers: TowerHeaders,
+    /// Whether appointments are sent to the towers using the compact binary encoding instead of JSON.
+    pub compact_appointments: bool,
+    /// HTTP client used to reach the towers straight. Shared by all requests so connections are reused.
+    pub client: Arc<reqwest::Client>,
+    /// HTTP client used to reach the towers through the proxy, if any.
+    pub proxied_client: Option<Arc<reqwest::Client>>,
+    /// Clients (straight and proxied) used to reach the towers with a pinned TLS certificate.
+    pub pinned_clients: HashMap<TowerId, (Arc<reqwest::Client>, Option<Arc<reqwest::Client>>)>,
+    /// Time (Unix seconds) of the last iteration of the [RetryManager](crate::retrier::RetryManager) loop. Zero if
+    /// it has not run yet.
+    pub retry_manager_tick: Arc<AtomicU64>,
+    /// Where tower status changes are reported to, if anywhere.
+    pub status_sink: Option<UnboundedSender<TowerStatusChange>>,
+    /// Which status changes are reported to the status sink (and the webhook sink).
+    pub status_filter: StatusFilter,
+    /// Where deliveries, expiries and tower status changes are reported to be POSTed to a webhook, if anywhere (see
+    /// [Webhook](crate::webhook::Webhook)).
+    pub webhook_sink: Option<broadcast::Sender<WebhookEvent>>,
+    /// Whether the plugin is shutting down. No data is sent to the retriers from then on.
+    shutting_down: bool,
+    /// Tower every pending appointment is also enqueued for, if any.
+    pub mirror: Option<TowerId>,
+    /// Towers appointments are delivered to one at a time, most preferred first (see [Self::set_tower_order]).
+    pub tower_order: Vec<TowerId>,
+    /// How the stale data found on startup is fed to the retriers.
+    pub stale_feed: StaleFeed,
+    /// Towers with stale data still to be fed to the retriers, alongside the last locator fed to them (if any).
+    pub stale_towers: VecDeque<(TowerId, Option<Locator>)>,
+    /// How invalid appointments are retried, if at all.
+    pub invalid_retry: Option<InvalidRetryPolicy>,
+    /// For how long (in seconds) an appointment is worth keeping as pending, if there is a limit at all.
+    pub max_pending_age: Option<u64>,
+    /// The best known block height, if any. Only kept in memory, and updated as blocks are connected.
+    pub block_height: Option<u32>,
+    /// How many blocks ahead of their expiry the subscriptions flagged for auto-renewal are renewed. Zero disables it.
+    pub auto_renew_blocks: u32,
+    /// Time (Unix seconds) of the last automatic renewal attempt of each tower. Only kept in memory.
+    pub renewal_attempts: HashMap<TowerId, u64>,
+    /// Towers that could not be automatically renewed given they require a payment. Only kept in memory.
+    pub payment_required: HashSet<TowerId>,
+    /// How the appointments are submitted to the towers on every commitment revocation.
+    pub submission_policy: SubmissionPolicy,
+    /// Permits bounding how many towers fresh appointments are sent to concurrently.
+    pub fanout: Arc<Semaphore>,
+    /// How many permanently rejected appointments are kept in the dead-letter queue. Zero disables it.
+    pub dead_letter_limit: usize,
 }
 
 impl WTClient {
@@ -96,6 +542,12 @@ impl WTClient {
 
         let dbm = DBM::new(&data_dir.join("watchtowers_db.sql3")).unwrap();
 
+        let (client, proxied_client) =
+            net::build_clients(proxy.as_ref(), None).unwrap_or_else(|e| {
+                log::error!("Cannot build HTTP client: {e:?}");
+                std::process::exit(1);
+            });
+
         let (user_sk, user_id) = if let Some(sk) = dbm.load_client_key() {
             (
                 sk,
@@ -109,16 +561,31 @@ impl WTClient {
         };
 
         let towers = dbm.load_towers();
-        for (tower_id, tower) in towers.iter() {
-            if tower.status.is_temporary_unreachable() {
-                unreachable_towers
-                    .send((
-                        *tower_id,
-                        RevocationData::Stale(tower.pending_appointments.iter().cloned().collect()),
-                    ))
-                    .unwrap();
-            }
-        }
+        let mirror = dbm.load_mirror_tower();
+        let tower_order = dbm.load_tower_order();
+        let pinned_clients = towers
+            .iter()
+            .filter_map(|(tower_id, tower)| Some((*tower_id, tower.tls_pin?)))
+            .map(|(tower_id, tls_pin)| {
+                let clients =
+                    net::build_clients(proxy.as_ref(), Some(&tls_pin)).unwrap_or_else(|e| {
+                        log::error!("Cannot build HTTP client for {tower_id}: {e:?}");
+                        std::process::exit(1);
+                    });
+                (tower_id, clients)
+            })
+            .collect();
+        let unreachable_since = towers
+            .iter()
+            .filter(|(_, tower)| tower.status == TowerStatus::Unreachable)
+            .map(|(tower_id, _)| (*tower_id, retrier::now()))
+            .collect();
+        // Stale data is not sent straightaway, but fed to the retriers by the retry manager (see Self::next_stale_batch)
+        let stale_towers = towers
+            .iter()
+            .filter(|(_, tower)| tower.status.is_temporary_unreachable())
+            .map(|(tower_id, _)| (*tower_id, None))
+            .collect();
 
         log::info!("Plugin watchtower client initialized. User id = {user_id}");
 
@@ -126,11 +593,261 @@ impl WTClient {
             towers,
             unreachable_towers,
             retriers: HashMap::new(),
+            retry_schedule: HashMap::new(),
+            aggressive_retries: HashSet::new(),
+            last_errors: HashMap::new(),
+            last_deliveries: HashMap::new(),
+            unreachable_since,
+            delivery_metrics: DeliveryMetrics::default(),
             dbm,
             user_sk,
+            signer: Arc::new(LocalSigner::new(user_sk)),
             user_id,
             proxy,
+            headers: TowerHeaders::default(),
+            compact_appointments: false,
+            client,
+            proxied_client,
+            pinned_clients,
+            retry_manager_tick: Arc::new(AtomicU64::new(0)),
+            status_sink: None,
+            status_filter: StatusFilter::All,
+            webhook_sink: None,
+            shutting_down: false,
+            mirror,
+            tower_order,
+            stale_feed: StaleFeed::Auto,
+            invalid_retry: None,
+            max_pending_age: None,
+            stale_towers,
+            block_height: None,
+            auto_renew_blocks: 0,
+            renewal_attempts: HashMap::new(),
+            payment_required: HashSet::new(),
+            submission_policy: SubmissionPolicy::Async,
+            fanout: Arc::new(Semaphore::new(constants::DEFAULT_WT_MAX_FANOUT as usize)),
+            dead_letter_limit: constants::DEFAULT_WT_DEAD_LETTER_LIMIT as usize,
+        }
+    }
+
+    /// Gets the time (Unix seconds) of the last iteration of the retry manager loop, if it has run at all.
+    ///
+    /// Meant to be used as a liveness check: a tick that stops advancing means the retry manager has stalled.
+    pub fn get_retry_manager_tick(&self) -> Option<u64> {
+        match self.retry_manager_tick.load(Ordering::Relaxed) {
+            0 => None,
+            tick => Some(tick),
+        }
+    }
+
+    /// Sets the headers to be sent along with the requests to the towers.
+    pub fn with_headers(mut self, headers: TowerHeaders) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Sets where tower status changes are reported to.
+    pub fn with_status_sink(mut self, sink: UnboundedSender<TowerStatusChange>) -> Self {
+        self.status_sink = Some(sink);
+        self
+    }
+
+    /// Sets where deliveries, expiries and tower status changes are reported to be POSTed to a webhook (see
+    /// [Webhook::run](crate::webhook::Webhook::run)).
+    pub fn with_webhook_sink(mut self, sink: broadcast::Sender<WebhookEvent>) -> Self {
+        self.webhook_sink = Some(sink);
+        self
+    }
+
+    /// Sets which status changes are reported to the status sink (all of them by default).
+    pub fn with_status_filter(mut self, filter: StatusFilter) -> Self {
+        self.status_filter = filter;
+        self
+    }
+
+    /// Sets the signer used to sign the appointments sent by the retriers.
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Sets how the stale data found on startup is fed to the retriers.
+    pub fn with_stale_feed(mut self, stale_feed: StaleFeed) -> Self {
+        self.stale_feed = stale_feed;
+        self
+    }
+
+    /// Sets how invalid appointments are retried. They are never retried if no policy is set.
+    pub fn with_invalid_retry_policy(mut self, policy: Option<InvalidRetryPolicy>) -> Self {
+        self.invalid_retry = policy;
+        self
+    }
+
+    /// Sets for how long (in seconds) appointments are worth keeping as pending. There is no limit if no age is set.
+    pub fn with_max_pending_age(mut self, max_age: Option<u64>) -> Self {
+        self.max_pending_age = max_age;
+        self
+    }
+
+    /// Sets how many blocks ahead of their expiry the subscriptions flagged for auto-renewal are renewed.
+    pub fn with_auto_renew_blocks(mut self, blocks: u32) -> Self {
+        self.auto_renew_blocks = blocks;
+        self
+    }
+
+    /// Sets how the appointments are submitted to the towers on every commitment revocation.
+    pub fn with_submission_policy(mut self, policy: SubmissionPolicy) -> Self {
+        self.submission_policy = policy;
+        self
+    }
+
+    /// Sets how many towers a fresh appointment is sent to concurrently. Deliveries over the limit wait for an ongoing
+    /// one to be over. Must be greater than zero.
+    pub fn with_max_fanout(mut self, max_fanout: usize) -> Self {
+        self.fanout = Arc::new(Semaphore::new(max_fanout));
+        self
+    }
+
+    /// Sets how many permanently rejected appointments are kept in the dead-letter queue. Zero disables it.
+    pub fn with_dead_letter_limit(mut self, limit: usize) -> Self {
+        self.dead_letter_limit = limit;
+        self
+    }
+
+    /// Sets whether appointments are sent to the towers using the compact binary encoding instead of JSON.
+    pub fn with_compact_appointments(mut self, compact: bool) -> Self {
+        self.compact_appointments = compact;
+        self
+    }
+
+    /// Moves the invalid appointments that are due to be retried (according to the [InvalidRetryPolicy]) back to pending.
+    ///
+    /// Only appointments of towers that are either reachable or already being retried are recovered. The rest are
+    /// recovered once their tower is reachable again. Returns the recovered locators grouped by tower.
+    pub fn recover_invalid_appointments(&mut self) -> Vec<(TowerId, HashSet<Locator>)> {
+        let policy = match self.invalid_retry {
+            Some(policy) => policy,
+            None => return Vec::new(),
+        };
+
+        let recoverable: Vec<(TowerId, Locator)> = self
+            .dbm
+            .load_recoverable_invalid_appointments(policy.cooldown, policy.max_retries)
+            .into_iter()
+            .filter(|(tower_id, _)| {
+                self.towers.get(tower_id).is_some_and(|tower| {
+                    tower.status.is_reachable() || tower.status.is_temporary_unreachable()
+                })
+            })
+            .collect();
+        if recoverable.is_empty() {
+            return Vec::new();
+        }
+
+        if let Err(e) = self.dbm.recover_invalid_appointments(&recoverable) {
+            log::error!("Cannot recover invalid appointments. Error: {e}");
+            return Vec::new();
+        }
+
+        let mut recovered: HashMap<TowerId, HashSet<Locator>> = HashMap::new();
+        for (tower_id, locator) in recoverable {
+            let tower = self.towers.get_mut(&tower_id).unwrap();
+            tower.invalid_appointments.remove(&locator);
+            tower.pending_appointments.insert(locator);
+            recovered.entry(tower_id).or_default().insert(locator);
+        }
+        for (tower_id, locators) in recovered.iter() {
+            log::info!(
+                "Retrying {} appointment(s) previously rejected by {tower_id}",
+                locators.len()
+            );
+        }
+
+        recovered.into_iter().collect()
+    }
+
+    /// Gets the next batch of stale data to be fed to the retriers, following the [StaleFeed] strategy.
+    ///
+    /// Towers that are not being retried anymore (because they were abandoned, or their retrier gave up) are dropped
+    /// from the feed. Their pending appointments are loaded from the database if they are ever retried again.
+    pub fn next_stale_batch(&mut self) -> Vec<(TowerId, HashSet<Locator>)> {
+        if self.stale_towers.is_empty() {
+            return Vec::new();
+        }
+
+        if self.stale_feed == StaleFeed::Auto {
+            let backlog: usize = self
+                .stale_towers
+                .iter()
+                .filter_map(|(tower_id, _)| self.towers.get(tower_id))
+                .map(|tower| tower.pending_appointments.len())
+                .sum();
+            self.stale_feed = if backlog > LAZY_FEED_THRESHOLD {
+                log::info!("Found a stale backlog of {backlog} appointments. Feeding it lazily");
+                StaleFeed::Lazy
+            } else {
+                StaleFeed::Eager
+            };
+        }
+
+        let mut batch = Vec::new();
+        if self.stale_feed == StaleFeed::Eager {
+            for (tower_id, _) in self.stale_towers.drain(..) {
+                if let Some(tower) = self.towers.get(&tower_id) {
+                    batch.push((tower_id, tower.pending_appointments.clone()));
+                }
+            }
+            return batch;
+        }
+
+        let mut budget = STALE_FEED_CHUNK_SIZE;
+        while budget > 0 {
+            let (tower_id, cursor) = match self.stale_towers.front() {
+                Some(entry) => *entry,
+                None => break,
+            };
+            let retried = self.towers.contains_key(&tower_id)
+                && !self
+                    .retriers
+                    .get(&tower_id)
+                    .is_some_and(|status| status.is_idle() || status.failed());
+            if !retried {
+                log::debug!(
+                    "{tower_id} is not being retried anymore. Dropping it from the stale feed"
+                );
+                self.stale_towers.pop_front();
+                continue;
+            }
+
+            match self.dbm.load_pending_locators(tower_id, cursor, budget) {
+                Ok(locators) => {
+                    if locators.len() < budget {
+                        self.stale_towers.pop_front();
+                    } else {
+                        self.stale_towers.front_mut().unwrap().1 = locators.last().copied();
+                    }
+                    budget -= locators.len();
+                    if !locators.is_empty() {
+                        batch.push((tower_id, HashSet::from_iter(locators)));
+                    }
+                }
+                // Most likely the database is busy. Try again on the next iteration
+                Err(e) => {
+                    log::warn!("Cannot load the stale appointments of {tower_id}. Error: {e:?}");
+                    break;
+                }
+            }
+        }
+
+        batch
+    }
+
+    /// Sets for how long database queries wait for the database to be unlocked before failing as busy.
+    pub fn with_db_busy_timeout(self, timeout: Duration) -> Self {
+        if let Err(e) = self.dbm.set_busy_timeout(timeout) {
+            log::error!("Cannot set the database busy timeout: {e:?}");
         }
+        self
     }
 
     /// Adds or updates a tower entry.
@@ -156,6 +873,8 @@ impl WTClient {
         self.dbm
             .store_tower_record(tower_id, tower_net_addr, receipt)
             .unwrap();
+        self.renewal_attempts.remove(&tower_id);
+        self.payment_required.remove(&tower_id);
 
         if let Some(summary) = self.towers.get_mut(&tower_id) {
             summary.udpate(
@@ -179,6 +898,16 @@ impl WTClient {
         Ok(())
     }
 
+    /// Sets the appointment signature version agreed with a given tower. Unknown towers are ignored.
+    pub fn set_tower_signature_version(&mut self, tower_id: TowerId, version: SignatureVersion) {
+        if let Some(tower) = self.towers.get_mut(&tower_id) {
+            self.dbm
+                .store_tower_signature_version(tower_id, version)
+                .unwrap();
+            tower.signature_version = version;
+        }
+    }
+
     /// Gets the latest registration receipt of a given tower.
     pub fn get_registration_receipt(&self, tower_id: TowerId) -> Option<RegistrationReceipt> {
         self.dbm.load_registration_receipt(tower_id, self.user_id)
@@ -189,19 +918,126 @@ impl WTClient {
         self.dbm.load_tower_record(tower_id)
     }
 
+    /// Gets the proxy that must be used to reach a given tower, if any.
+    ///
+    /// Towers flagged with `use_proxy` (e.g. onion towers) are always reached through the proxy, while the rest
+    /// fall back to the global policy (they only use it if `always_use` is set). Unknown towers get no proxy.
+    pub fn get_tower_proxy(&self, tower_id: TowerId) -> Option<ProxyInfo> {
+        self.resolve_proxy(self.towers.get(&tower_id)?.use_proxy)
+    }
+
+    /// Resolves the proxy to be used given whether a tower is flagged to use it or not.
+    pub fn resolve_proxy(&self, use_proxy: bool) -> Option<ProxyInfo> {
+        self.proxy
+            .clone()
+            .filter(|proxy| use_proxy || proxy.always_use)
+            .map(|mut proxy| {
+                proxy.always_use = true;
+                proxy
+            })
+    }
+
+    /// Gets the options to build the requests sent to a given tower (client, headers and appointment encoding).
+    pub fn get_request_options(&self, tower_id: TowerId) -> RequestOptions {
+        self.resolve_request_options(tower_id, self.get_tower_proxy(tower_id).is_some())
+    }
+
+    /// Resolves the options to build the requests sent to a given tower given whether it must be reached through the proxy.
+    ///
+    /// The returned client is shared, so a backlog of requests to the same tower reuses connections.
+    /// Towers with a pinned TLS certificate are reached using their own clients.
+    pub fn resolve_request_options(&self, tower_id: TowerId, use_proxy: bool) -> RequestOptions {
+        let headers = self.headers.get(tower_id);
+        let (client, proxied_client) = self
+            .pinned_clients
+            .get(&tower_id)
+            .map(|(client, proxied_client)| (client, proxied_client))
+            .unwrap_or((&self.client, &self.proxied_client));
+        match proxied_client.as_ref().filter(|_| use_proxy) {
+            Some(client) => RequestOptions::new(client.clone(), true, headers),
+            None => RequestOptions::new(client.clone(), false, headers),
+        }
+        .with_compact_appointments(self.compact_appointments)
+    }
+
+    /// Resolves the options to build the requests sent to a tower only known by its address (e.g. before registering with it).
+    ///
+    /// Onion addresses are reached through the proxy. Neither tower specific headers nor TLS pins apply.
+    pub fn resolve_discovery_options(&self, net_addr: &NetAddr) -> RequestOptions {
+        let headers = self.headers.common();
+        match self
+            .proxied_client
+            .as_ref()
+            .filter(|_| self.resolve_proxy(net_addr.is_onion()).is_some())
+        {
+            Some(client) => RequestOptions::new(client.clone(), true, headers),
+            None => RequestOptions::new(self.client.clone(), false, headers),
+        }
+    }
+
     /// Gets the given tower status (identified by tower_id), if found.
     pub fn get_tower_status(&self, tower_id: &TowerId) -> Option<TowerStatus> {
         Some(self.towers.get(tower_id)?.status)
     }
 
+    /// Gets the status of every tower, alongside the one of its retrier and its number of pending appointments.
+    ///
+    /// This is meant for callers that need all of them, so they can be fetched at once instead of tower by tower.
+    pub fn all_tower_statuses(&self) -> HashMap<TowerId, TowerStatusSummary> {
+        self.towers
+            .iter()
+            .map(|(tower_id, tower)| {
+                (
+                    *tower_id,
+                    TowerStatusSummary {
+                        status: tower.status,
+                        retrier: self.retriers.get(tower_id).map(Into::into),
+                        pending_count: tower.pending_appointments.len(),
+                    },
+                )
+            })
+            .collect()
+    }
+
     /// Sets the tower status to any of the `TowerStatus` variants.
     pub fn set_tower_status(&mut self, tower_id: TowerId, status: TowerStatus) {
         if let Some(tower) = self.towers.get_mut(&tower_id) {
             if tower.status != status {
-                tower.status = status
+                let old_status = tower.status;
+                tower.status = status;
+                if self.status_filter.allows(status) {
+                    let change = TowerStatusChange {
+                        tower_id,
+                        old_status,
+                        new_status: status,
+                        timestamp: retrier::now(),
+                    };
+                    // The receiving ends may already be gone if the plugin is shutting down.
+                    if let Some(sink) = &self.webhook_sink {
+                        sink.send(WebhookEvent::TowerStatusChanged(change.clone()))
+                            .ok();
+                    }
+                    if let Some(sink) = &self.status_sink {
+                        sink.send(change).ok();
+                    }
+                }
             } else {
                 log::debug!("{tower_id} status is already {status}")
             }
+
+            // Towers going back and forth between temporary unreachable and unreachable keep the time they were
+            // first flagged as unreachable, so only reaching them resets it.
+            match status {
+                TowerStatus::Unreachable => {
+                    self.unreachable_since
+                        .entry(tower_id)
+                        .or_insert_with(retrier::now);
+                }
+                TowerStatus::Reachable => {
+                    self.unreachable_since.remove(&tower_id);
+                }
+                _ => (),
+            }
         } else {
             log::error!("Cannot change tower status to {status}. Unknown tower_id: {tower_id}");
         }
@@ -212,379 +1048,3129 @@ impl WTClient {
         self.retriers.get(tower_id)
     }
 
-    /// Adds an appointment receipt to the tower record.
-    pub fn add_appointment_receipt(
-        &mut self,
-        tower_id: TowerId,
-        locator: Locator,
-        available_slots: u32,
-        receipt: &AppointmentReceipt,
-    ) {
-        if let Some(tower) = self.towers.get_mut(&tower_id) {
-            // DISCUSS: It may be nice to independently compute the slots and compare
-            tower.available_slots = available_slots;
-
-            self.dbm
-                .store_appointment_receipt(tower_id, locator, available_slots, receipt)
-                .unwrap();
-        } else {
-            log::error!("Cannot add appointment receipt to tower. Unknown tower_id: {tower_id}");
-        }
+    /// Flags the client as shutting down.
+    ///
+    /// From this point on no data is sent to the retriers, and the running ones stop as soon as they have persisted what
+    /// they have delivered (see [RetryManager::manage_retry](crate::retrier::RetryManager::manage_retry)). Appointments
+    /// are still stored as pending, so they are retried on the next start.
+    pub fn begin_shutdown(&mut self) {
+        log::info!("Shutting down. Not retrying any more towers");
+        self.shutting_down = true;
     }
 
-    /// Gets an appointment receipt from the database (if found).
-    pub fn get_appointment_receipt(
-        &self,
-        tower_id: TowerId,
-        locator: Locator,
-    ) -> Option<AppointmentReceipt> {
-        self.dbm.load_appointment_receipt(tower_id, locator)
+    /// Whether the client is shutting down (see [Self::begin_shutdown]).
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down
     }
 
-    /// Adds a pending appointment to the tower record.
-    pub fn add_pending_appointment(&mut self, tower_id: TowerId, appointment: &Appointment) {
-        if let Some(tower) = self.towers.get_mut(&tower_id) {
-            tower.pending_appointments.insert(appointment.locator);
-
-            self.dbm
-                .store_pending_appointment(tower_id, appointment)
+    /// Sends fresh data to the retrier of a given tower, as long as it does not exist, or it does and it is running.
+    ///
+    /// Idle retriers load all their pending appointments from the database once resumed, so no data is sent to them.
+    pub fn send_to_retrier(&self, tower_id: TowerId, locator: Locator) {
+        if self.shutting_down {
+            log::debug!("Shutting down. Not sending data to the retrier ({tower_id}, {locator})");
+            return;
+        }
+        // A retrier in the retriers map can only be running or idle
+        if self
+            .get_retrier_status(&tower_id)
+            .is_none_or(|status| status.is_running())
+        {
+            self.unreachable_towers
+                .send((tower_id, RevocationData::Fresh(locator)))
                 .unwrap();
         } else {
-            log::error!("Cannot add pending appointment to tower. Unknown tower_id: {tower_id}");
+            log::debug!("Not sending data to idle retrier ({tower_id}, {locator})")
         }
     }
 
-    /// Removes a pending appointment from the tower record.
-    pub fn remove_pending_appointment(&mut self, tower_id: TowerId, locator: Locator) {
-        if let Some(tower) = self.towers.get_mut(&tower_id) {
-            tower.pending_appointments.remove(&locator);
+    /// Flags a tower for retry, as long as it is unreachable or has a subscription issue.
+    ///
+    /// Idle retriers are resumed, whereas towers that are already being retried are rejected.
+    pub fn retry_tower(&self, tower_id: TowerId) -> Result<(), String> {
+        if self.shutting_down {
+            return Err("The client is shutting down".to_owned());
+        }
+        let tower_status = self
+            .get_tower_status(&tower_id)
+            .ok_or_else(|| format!("Unknown tower {tower_id}"))?;
 
-            self.dbm
-                .delete_pending_appointment(tower_id, locator)
-                .unwrap();
+        let data = if let Some(retrier_status) = self.retriers.get(&tower_id) {
+            if retrier_status.is_idle() {
+                // We don't send any associated data in this case given the idle retrier already has it all.
+                RevocationData::None
+            } else {
+                // Status can only be running or idle for data in the retriers map.
+                return Err(format!("{tower_id} is already being retried"));
+            }
+        } else if tower_status.is_retryable() {
+            // We do send associated data here given there is no retrier associated to this tower.
+            RevocationData::Stale(
+                self.towers
+                    .get(&tower_id)
+                    .unwrap()
+                    .pending_appointments
+                    .iter()
+                    .cloned()
+                    .collect(),
+            )
         } else {
-            log::error!("Cannot remove pending appointment to tower. Unknown tower_id: {tower_id}");
-        }
+            return Err(
+                "Tower status must be unreachable or have a subscription issue to manually retry"
+                    .to_owned(),
+            );
+        };
+
+        self.unreachable_towers
+            .send((tower_id, data))
+            .map_err(|e| e.to_string())
     }
 
-    /// Adds an invalid appointment to the tower record.
-    pub fn add_invalid_appointment(&mut self, tower_id: TowerId, appointment: &Appointment) {
-        if let Some(tower) = self.towers.get_mut(&tower_id) {
-            tower.invalid_appointments.insert(appointment.locator);
+    /// Flags a tower for retry (see [WTClient::retry_tower]) using the aggressive backoff instead of the automatic one.
+    ///
+    /// The override only applies to the next time the tower's retrier is started.
+    pub fn retry_tower_aggressively(&mut self, tower_id: TowerId) -> Result<(), String> {
+        self.retry_tower(tower_id)?;
+        self.aggressive_retries.insert(tower_id);
+        Ok(())
+    }
 
-            self.dbm
-                .store_invalid_appointment(tower_id, appointment)
-                .unwrap();
-        } else {
-            log::error!("Cannot add invalid appointment to tower. Unknown tower_id: {tower_id}");
-        }
+    /// Gets the estimated time (Unix seconds) of the next attempt of every running or idle retrier, soonest first.
+    ///
+    /// Running retriers are expected to try again once their current backoff expires, whereas idle ones are
+    /// expected to do so once they are automatically resumed.
+    pub fn upcoming_retries(&self) -> Vec<(TowerId, u64)> {
+        let mut upcoming = self
+            .retriers
+            .iter()
+            .filter(|(_, status)| status.is_running() || status.is_idle())
+            .filter_map(|(tower_id, _)| {
+                self.retry_schedule
+                    .get(tower_id)
+                    .map(|next_attempt| (*tower_id, *next_attempt))
+            })
+            .collect::<Vec<_>>();
+        upcoming.sort_by_key(|(tower_id, next_attempt)| (*next_attempt, tower_id.to_vec()));
+
+        upcoming
     }
 
-    /// Flags a given tower as misbehaving, storing the misbehaving proof in the database.
-    pub fn flag_misbehaving_tower(&mut self, tower_id: TowerId, proof: MisbehaviorProof) {
-        if let Some(tower) = self.towers.get_mut(&tower_id) {
-            self.dbm.store_misbehaving_proof(tower_id, &proof).unwrap();
-            tower.status = TowerStatus::Misbehaving;
-        } else {
-            log::error!("Cannot flag tower. Unknown tower_id: {tower_id}");
+    /// Gets the delivery counters of the client.
+    pub fn metrics(&self) -> DeliveryMetrics {
+        self.delivery_metrics.clone()
+    }
+
+    /// Records the last error faced when sending data to a given tower.
+    pub fn record_error(&mut self, tower_id: TowerId, error: TowerError) {
+        if self.towers.contains_key(&tower_id) {
+            self.last_errors.insert(tower_id, error);
         }
     }
 
-    /// Removes a tower from the client (both memory and database).
+    /// Gets the towers that require action from the user, alongside the reasons why.
     ///
-    /// Any data associated to the tower will be deleted (i.e. links to appointments)
-    pub fn remove_tower(&mut self, tower_id: TowerId) -> Result<(), DBError> {
+    /// Subscriptions are checked against their expiry using the given `block_height`, or the height tracked from block
+    /// notifications if none is given. If neither is known, expiries are not checked.
+    pub fn towers_needing_attention(
+        &self,
+        block_height: Option<u32>,
+    ) -> HashMap<TowerId, Vec<AttentionReason>> {
+        let block_height = block_height.or(self.block_height);
+        self.towers
+            .iter()
+            .filter_map(|(tower_id, tower)| {
+                let mut reasons = Vec::new();
+                if let Some(height) = block_height {
+                    if height >= tower.subscription_expiry {
+                        reasons.push(AttentionReason::SubscriptionExpired);
+                    } else if tower.subscription_expiry - height <= EXPIRY_WARNING_BLOCKS {
+                        reasons.push(AttentionReason::SubscriptionExpiring);
+                    }
+                }
+                match tower.status {
+                    TowerStatus::SubscriptionError => {
+                        reasons.push(AttentionReason::SubscriptionError)
+                    }
+                    TowerStatus::Unreachable => reasons.push(AttentionReason::Failed),
+                    TowerStatus::Misbehaving => reasons.push(AttentionReason::Misbehaving),
+                    _ => (),
+                }
+                if self.payment_required.contains(tower_id) {
+                    reasons.push(AttentionReason::PaymentRequired);
+                }
+                if tower.status.is_subscription_exhausted() || tower.available_slots == 0 {
+                    reasons.push(AttentionReason::SlotsExhausted);
+                }
+
+                (!reasons.is_empty()).then_some((*tower_id, reasons))
+            })
+            .collect()
+    }
+
+    /// Counts the appointments of a given tower with a given `status`.
+    ///
+    /// Accepted and expired appointments are not kept in memory, so they are counted straight from the database
+    /// instead of being loaded. Zero if the database cannot be queried.
+    pub fn count_appointments(&self, tower_id: TowerId, status: AppointmentStatus) -> usize {
+        match status {
+            AppointmentStatus::Pending => self
+                .towers
+                .get(&tower_id)
+                .map_or(0, |tower| tower.pending_appointments.len()),
+            AppointmentStatus::Invalid => self
+                .towers
+                .get(&tower_id)
+                .map_or(0, |tower| tower.invalid_appointments.len()),
+            AppointmentStatus::Accepted | AppointmentStatus::Expired => self
+                .dbm
+                .count_appointments(tower_id, status)
+                .unwrap_or_else(|e| {
+                    log::error!(
+                        "Cannot count the {status:?} appointments of {tower_id}. Error: {e:?}"
+                    );
+                    0
+                }),
+        }
+    }
+
+    /// Gets how many appointments are pending delivery, in total and per tower.
+    ///
+    /// The pending appointments are counted by the database, without loading them. If the database cannot be queried,
+    /// the pending appointments tracked in memory are counted instead.
+    pub fn total_pending(&self) -> PendingWorkload {
+        let counts = self.dbm.count_pending_appointments().unwrap_or_else(|e| {
+            log::error!(
+                "Cannot count the pending appointments. Using the in-memory ones. Error: {e:?}"
+            );
+            self.towers
+                .iter()
+                .map(|(tower_id, tower)| (*tower_id, tower.pending_appointments.len()))
+                .collect()
+        });
+
+        let per_tower: HashMap<TowerId, usize> = self
+            .towers
+            .keys()
+            .map(|tower_id| (*tower_id, counts.get(tower_id).copied().unwrap_or(0)))
+            .collect();
+        PendingWorkload {
+            total: per_tower.values().sum(),
+            per_tower,
+        }
+    }
+
+    /// Gathers the data explaining why a given tower is at its current status, if the tower is known.
+    pub fn diagnose_tower(&self, tower_id: TowerId) -> Option<TowerDiagnosis> {
+        let tower = self.towers.get(&tower_id)?;
+        let retrier = self.get_retrier_status(&tower_id);
+        let last_error = self.last_errors.get(&tower_id).cloned();
+
+        let mut explanation = vec![match tower.status {
+            TowerStatus::Reachable => "The tower is reachable and appointments are sent to it as they are generated.",
+            TowerStatus::TemporaryUnreachable => "The last attempt to reach the tower failed. Pending appointments are being retried in the background.",
+            TowerStatus::Unreachable => "The tower could not be reached after retrying for a while. It will be automatically retried later on, or it can be retried manually (retrytower).",
+            TowerStatus::SubscriptionError => "The subscription with the tower is not valid (it may have expired) and could not be renewed. Register again (registertower) or retry (retrytower) once fixed.",
+            TowerStatus::SubscriptionExhausted => "The subscription with the tower has run out of slots. It will be renewed before sending the next appointment.",
+            TowerStatus::Misbehaving => "The tower replied with a receipt that is not signed by it. No more appointments will be sent to it (check the proof with gettowerinfo).",
+        }
+        .to_owned()];
+
+        match retrier {
+            Some(RetrierStatus::Running) => {
+                let next_attempt = self
+                    .retry_schedule
+                    .get(&tower_id)
+                    .map_or(String::new(), |at| format!(" Next attempt at {at}."));
+                explanation.push(format!("The tower is being retried.{next_attempt}"));
+            }
+            Some(status @ RetrierStatus::Idle(_)) => explanation.push(format!(
+                "The retrier has been idle for {} seconds.",
+                status.get_elapsed_time().unwrap()
+            )),
+            Some(RetrierStatus::Failed) => {
+                explanation.push("The retrier gave up on the tower.".to_owned())
+            }
+            _ => (),
+        }
+        if let Some(e) = &last_error {
+            let code = e
+                .code
+                .map_or(String::new(), |code| format!(", code {code}"));
+            explanation.push(format!(
+                "Last error: {} ({:?}{code}, at {}).",
+                e.error, e.kind, e.timestamp
+            ));
+        }
+        if !tower.pending_appointments.is_empty() {
+            explanation.push(format!(
+                "{} appointment(s) are pending to be delivered.",
+                tower.pending_appointments.len()
+            ));
+        }
+        if !tower.invalid_appointments.is_empty() {
+            explanation.push(format!(
+                "{} appointment(s) were rejected by the tower.",
+                tower.invalid_appointments.len()
+            ));
+        }
+
+        Some(TowerDiagnosis {
+            status: tower.status,
+            retrier: retrier.map(Into::into),
+            last_error,
+            accepted_appointments: self.count_appointments(tower_id, AppointmentStatus::Accepted),
+            pending_appointments: tower.pending_appointments.len(),
+            invalid_appointments: tower.invalid_appointments.len(),
+            expired_appointments: self.count_appointments(tower_id, AppointmentStatus::Expired),
+            last_delivery: self.last_deliveries.get(&tower_id).cloned(),
+            explanation: explanation.join(" "),
+        })
+    }
+
+    /// Updates the available slots of a given tower.
+    ///
+    /// If the tower reports no slots left, it is flagged as [TowerStatus::SubscriptionExhausted] so the subscription
+    /// gets renewed before the next appointment is sent.
+    fn update_available_slots(&mut self, tower_id: TowerId, available_slots: u32) {
+        if let Some(tower) = self.towers.get_mut(&tower_id) {
+            tower.available_slots = available_slots;
+            if available_slots == 0 {
+                log::warn!("Subscription with {tower_id} has run out of slots. Top it up by registering again (registertower)");
+                self.set_tower_status(tower_id, TowerStatus::SubscriptionExhausted);
+            }
+        }
+    }
+
+    /// Adds an appointment receipt to the tower record.
+    ///
+    /// If the tower reports no slots left, the tower is flagged as [TowerStatus::SubscriptionExhausted].
+    pub fn add_appointment_receipt(
+        &mut self,
+        tower_id: TowerId,
+        locator: Locator,
+        available_slots: u32,
+        receipt: &AppointmentReceipt,
+    ) {
         if self.towers.contains_key(&tower_id) {
-            self.towers.remove(&tower_id);
-            self.dbm.remove_tower_record(tower_id)
+            // DISCUSS: It may be nice to independently compute the slots and compare
+            self.update_available_slots(tower_id, available_slots);
+            self.last_deliveries.insert(tower_id, retrier::now());
+            self.delivery_metrics.record(tower_id, 1);
+            self.report_deliveries(tower_id, [locator]);
+
+            self.dbm
+                .store_appointment_receipt(tower_id, locator, available_slots, receipt)
+                .unwrap();
         } else {
-            Err(DBError::NotFound)
+            log::error!("Cannot add appointment receipt to tower. Unknown tower_id: {tower_id}");
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Adds a batch of appointment receipts to the tower record, removing the corresponding appointments from pending.
+    ///
+    /// This is equivalent to calling [Self::add_appointment_receipt] and [Self::remove_pending_appointment] for every
+    /// delivered appointment, but all the data is persisted within a single database transaction.
+    pub fn add_appointment_receipts(
+        &mut self,
+        tower_id: TowerId,
+        available_slots: u32,
+        receipts: &[(Locator, AppointmentReceipt)],
+    ) {
+        if self.towers.contains_key(&tower_id) {
+            self.update_available_slots(tower_id, available_slots);
+            let tower = self.towers.get_mut(&tower_id).unwrap();
+            for (locator, _) in receipts {
+                tower.pending_appointments.remove(locator);
+            }
+            if !receipts.is_empty() {
+                self.last_deliveries.insert(tower_id, retrier::now());
+                self.delivery_metrics
+                    .record(tower_id, receipts.len() as u64);
+            }
+            self.report_deliveries(tower_id, receipts.iter().map(|(locator, _)| *locator));
 
-    use tempdir::TempDir;
-    use tokio::sync::mpsc::unbounded_channel;
+            self.dbm
+                .store_appointment_receipts(tower_id, available_slots, receipts)
+                .unwrap();
+        } else {
+            log::error!("Cannot add appointment receipts to tower. Unknown tower_id: {tower_id}");
+        }
+    }
+
+    /// Reports the appointments delivered to a tower to the webhook sink, if any.
+    fn report_deliveries(&self, tower_id: TowerId, locators: impl IntoIterator<Item = Locator>) {
+        if let Some(sink) = &self.webhook_sink {
+            let timestamp = retrier::now();
+            for locator in locators {
+                sink.send(WebhookEvent::AppointmentDelivered {
+                    tower_id,
+                    locator,
+                    timestamp,
+                })
+                .ok();
+            }
+        }
+    }
+
+    /// Reports the pending appointments of a tower that have been expired to the webhook sink, if any.
+    fn report_expiries(&self, tower_id: TowerId, locators: impl IntoIterator<Item = Locator>) {
+        if let Some(sink) = &self.webhook_sink {
+            let timestamp = retrier::now();
+            for locator in locators {
+                sink.send(WebhookEvent::AppointmentExpired {
+                    tower_id,
+                    locator,
+                    timestamp,
+                })
+                .ok();
+            }
+        }
+    }
+
+    /// Adds a deletion receipt to the tower record, updating the tower available slots.
+    pub fn add_deletion_receipt(
+        &mut self,
+        tower_id: TowerId,
+        locator: Locator,
+        available_slots: u32,
+        receipt: &DeletionReceipt,
+    ) {
+        if self.towers.contains_key(&tower_id) {
+            self.update_available_slots(tower_id, available_slots);
+            self.dbm
+                .store_deletion_receipt(tower_id, locator, available_slots, receipt)
+                .unwrap();
+        } else {
+            log::error!("Cannot add deletion receipt to tower. Unknown tower_id: {tower_id}");
+        }
+    }
+
+    /// Gets an appointment receipt from the database (if found).
+    pub fn get_appointment_receipt(
+        &self,
+        tower_id: TowerId,
+        locator: Locator,
+    ) -> Option<AppointmentReceipt> {
+        self.dbm.load_appointment_receipt(tower_id, locator)
+    }
+
+    /// Checks that all the stored receipts are signed by the tower they are stored for, reporting the ones that are
+    /// not (e.g. due to database corruption).
+    ///
+    /// This is read-only. Every kind of receipt is loaded in a single query, so it is cheap even for large databases.
+    pub fn verify_all_receipts(&self) -> ReceiptsReport {
+        let mut report = ReceiptsReport::default();
+
+        for (tower_id, receipt) in self.dbm.load_all_registration_receipts(self.user_id) {
+            report.checked += 1;
+            if !receipt.verify(&tower_id) {
+                report
+                    .invalid
+                    .entry(tower_id)
+                    .or_default()
+                    .registration_receipts
+                    .push(receipt.subscription_expiry());
+            }
+        }
+        for (tower_id, locator, receipt) in self.dbm.load_all_appointment_receipts() {
+            report.checked += 1;
+            if !receipt.verify(&tower_id) {
+                report
+                    .invalid
+                    .entry(tower_id)
+                    .or_default()
+                    .appointment_receipts
+                    .insert(locator);
+            }
+        }
+
+        if !report.invalid.is_empty() {
+            log::warn!(
+                "Found invalid receipts for {} tower(s)",
+                report.invalid.len()
+            );
+        }
+        report
+    }
+
+    /// Adds a pending appointment to the tower record.
+    ///
+    /// The appointment is also enqueued for the mirror tower, if any (see [Self::set_mirror]).
+    pub fn add_pending_appointment(&mut self, tower_id: TowerId, appointment: &Appointment) {
+        self.store_pending_appointment(tower_id, appointment, None)
+    }
+
+    /// Adds a pending appointment to the tower record that is only worth delivering until `deadline` (Unix time, in seconds).
+    ///
+    /// Once the deadline has passed, the appointment is not retried anymore and it is moved to expired.
+    pub fn add_pending_appointment_with_deadline(
+        &mut self,
+        tower_id: TowerId,
+        appointment: &Appointment,
+        deadline: u64,
+    ) {
+        self.store_pending_appointment(tower_id, appointment, Some(deadline))
+    }
+
+    fn store_pending_appointment(
+        &mut self,
+        tower_id: TowerId,
+        appointment: &Appointment,
+        deadline: Option<u64>,
+    ) {
+        if let Some(tower) = self.towers.get_mut(&tower_id) {
+            tower.pending_appointments.insert(appointment.locator);
+
+            self.dbm
+                .store_pending_appointment_with_deadline(tower_id, appointment, deadline)
+                .unwrap();
+
+            if let Some(mirror_id) = self.mirror.filter(|id| *id != tower_id) {
+                log::info!("Mirroring {} to {mirror_id}", appointment.locator);
+                self.enqueue_pending_appointment(mirror_id, appointment, deadline);
+            }
+        } else {
+            log::error!("Cannot add pending appointment to tower. Unknown tower_id: {tower_id}");
+        }
+    }
+
+    /// Enqueues a pending appointment of some other tower for `target_id` (e.g. the mirror tower), so it is retried
+    /// independently.
+    ///
+    /// Appointments the target already holds (or has pending) are skipped, and so is a misbehaving target.
+    fn enqueue_pending_appointment(
+        &mut self,
+        target_id: TowerId,
+        appointment: &Appointment,
+        deadline: Option<u64>,
+    ) {
+        let status = match self.towers.get(&target_id) {
+            Some(target) if !target.pending_appointments.contains(&appointment.locator) => {
+                target.status
+            }
+            _ => return,
+        };
+        if status.is_misbehaving()
+            || self
+                .dbm
+                .load_appointment_receipt(target_id, appointment.locator)
+                .is_some()
+        {
+            return;
+        }
+
+        self.towers
+            .get_mut(&target_id)
+            .unwrap()
+            .pending_appointments
+            .insert(appointment.locator);
+        self.dbm
+            .store_pending_appointment_with_deadline(target_id, appointment, deadline)
+            .unwrap();
+
+        // Unreachable towers are only retried on demand, as any other pending appointment of theirs
+        if !status.is_unreachable() {
+            self.send_to_retrier(target_id, appointment.locator);
+        }
+    }
+
+    /// Hands an appointment a tower has permanently failed to get over to the next tower in the [tower order](Self::set_tower_order).
+    ///
+    /// Towers that have already failed (unreachable or misbehaving) are skipped. Towers out of the order do not fall
+    /// back to any other.
+    pub fn fall_back_appointment(&mut self, tower_id: TowerId, appointment: &Appointment) {
+        let Some(position) = self.tower_order.iter().position(|id| *id == tower_id) else {
+            return;
+        };
+        let next = self.tower_order[position + 1..].iter().find(|id| {
+            self.towers.get(id).is_some_and(|tower| {
+                !tower.status.is_unreachable() && !tower.status.is_misbehaving()
+            })
+        });
+
+        match next.cloned() {
+            Some(next_id) => {
+                log::info!(
+                    "{tower_id} failed to get {}. Falling back to {next_id}",
+                    appointment.locator
+                );
+                let deadline = self
+                    .dbm
+                    .load_pending_deadline(tower_id, appointment.locator);
+                self.enqueue_pending_appointment(next_id, appointment, deadline);
+            }
+            None => log::warn!(
+                "{tower_id} failed to get {} and there are no towers left to fall back to",
+                appointment.locator
+            ),
+        }
+    }
+
+    /// Hands all the pending appointments of a tower that has permanently failed over to the next tower in the
+    /// [tower order](Self::set_tower_order). See [Self::fall_back_appointment].
+    pub fn fall_back_pending_appointments(&mut self, tower_id: TowerId) {
+        if !self.tower_order.contains(&tower_id) {
+            return;
+        }
+        let locators = match self.towers.get(&tower_id) {
+            Some(tower) => tower.pending_appointments.clone(),
+            None => return,
+        };
+        for locator in locators {
+            if let Ok(Some(appointment)) = self.dbm.load_appointment(locator) {
+                self.fall_back_appointment(tower_id, &appointment);
+            }
+        }
+    }
+
+    /// Moves a pending appointment whose deadline has passed to expired.
+    pub fn expire_pending_appointment(&mut self, tower_id: TowerId, locator: Locator) {
+        if let Some(tower) = self.towers.get_mut(&tower_id) {
+            log::warn!("{locator} could not be delivered to {tower_id} before its deadline. Flagging it as expired");
+            tower.pending_appointments.remove(&locator);
+
+            self.dbm
+                .expire_pending_appointment(tower_id, locator)
+                .unwrap();
+            self.report_expiries(tower_id, [locator]);
+        } else {
+            log::error!("Cannot expire pending appointment. Unknown tower_id: {tower_id}");
+        }
+    }
+
+    /// Moves the pending appointments of a given tower that are older than the maximum pending age (if any) to expired.
+    ///
+    /// Returns the expired locators.
+    pub fn expire_stale_pending_appointments(&mut self, tower_id: TowerId) -> HashSet<Locator> {
+        let max_age = match self.max_pending_age {
+            Some(max_age) => max_age,
+            None => return HashSet::new(),
+        };
+        let stale = match self.dbm.load_stale_pending_locators(tower_id, max_age) {
+            Ok(stale) => stale,
+            Err(e) => {
+                log::warn!(
+                    "Cannot load the stale pending appointments of {tower_id}. Error: {e:?}"
+                );
+                return HashSet::new();
+            }
+        };
+
+        if !stale.is_empty() {
+            log::warn!(
+                "{} pending appointment(s) of {tower_id} are older than {max_age} seconds. Flagging them as expired",
+                stale.len()
+            );
+        }
+        for locator in stale.iter() {
+            if let Some(tower) = self.towers.get_mut(&tower_id) {
+                tower.pending_appointments.remove(locator);
+            }
+            if let Err(e) = self.dbm.expire_pending_appointment(tower_id, *locator) {
+                log::error!("Cannot expire pending appointment {locator}. Error: {e}");
+            }
+        }
+        self.report_expiries(tower_id, stale.iter().copied());
+
+        stale
+    }
+
+    /// Removes a pending appointment from the tower record.
+    pub fn remove_pending_appointment(&mut self, tower_id: TowerId, locator: Locator) {
+        if let Some(tower) = self.towers.get_mut(&tower_id) {
+            tower.pending_appointments.remove(&locator);
+
+            self.dbm
+                .delete_pending_appointment(tower_id, locator)
+                .unwrap();
+        } else {
+            log::error!("Cannot remove pending appointment to tower. Unknown tower_id: {tower_id}");
+        }
+    }
+
+    /// Checks whether a given appointment is pending for a given tower.
+    pub fn is_pending(&self, tower_id: TowerId, locator: Locator) -> bool {
+        self.towers
+            .get(&tower_id)
+            .is_some_and(|tower| tower.pending_appointments.contains(&locator))
+    }
+
+    /// Cancels the delivery of a pending appointment to a given tower.
+    ///
+    /// The appointment is removed from pending (both memory and database). A [Retrier](crate::retrier::Retrier) checks
+    /// whether an appointment is still pending before sending it and before storing its receipt, so a cancelled
+    /// appointment won't be sent, and if it was already in flight, it won't be recorded as accepted.
+    pub fn cancel_appointment(
+        &mut self,
+        tower_id: TowerId,
+        locator: Locator,
+    ) -> Result<(), DBError> {
+        if !self.is_pending(tower_id, locator) {
+            return Err(DBError::NotFound);
+        }
+
+        log::info!("Cancelling the delivery of {locator} to {tower_id}");
+        self.remove_pending_appointment(tower_id, locator);
+        Ok(())
+    }
+
+    /// Restricts the towers the appointments of a given channel are sent to. [None] lifts the restriction, so the
+    /// appointments of the channel are sent to all towers.
+    ///
+    /// Fails with [DBError::NotFound] if any of the towers is unknown.
+    pub fn set_channel_towers(
+        &mut self,
+        channel_id: &str,
+        tower_ids: Option<HashSet<TowerId>>,
+    ) -> Result<(), DBError> {
+        let tower_ids = tower_ids.unwrap_or_default();
+        if tower_ids.iter().any(|id| !self.towers.contains_key(id)) {
+            return Err(DBError::NotFound);
+        }
+
+        self.dbm.store_channel_towers(channel_id, &tower_ids)
+    }
+
+    /// Records the channel (and commitment number) a given appointment was created for.
+    pub fn add_appointment_channel(&self, locator: Locator, channel_id: &str, commit_num: u32) {
+        if let Err(e) = self
+            .dbm
+            .store_appointment_channel(locator, channel_id, commit_num)
+        {
+            log::error!("Cannot record the channel of {locator}. Error: {e:?}");
+        }
+    }
+
+    /// Gets all the appointments created for the channel funded by a given outpoint, alongside their status for every
+    /// tower they were sent to.
+    ///
+    /// Appointments are sorted by commitment number. Only appointments created since the mapping is recorded are known.
+    pub fn appointments_for_outpoint(&self, outpoint: &OutPoint) -> Vec<ChannelAppointment> {
+        self.dbm
+            .load_channel_appointments(&channel_id_from_outpoint(outpoint))
+            .into_iter()
+            .map(|(locator, commit_num)| ChannelAppointment {
+                locator,
+                commit_num,
+                towers: self.dbm.load_appointment_statuses(locator),
+            })
+            .collect()
+    }
+
+    /// Gets the towers the appointments of a given channel are sent to.
+    ///
+    /// Channels with no restriction are sent to all towers. Towers that have been abandoned are not included, but are
+    /// not replaced by any other either.
+    pub fn get_channel_towers(&self, channel_id: &str) -> HashSet<TowerId> {
+        match self.dbm.load_channel_towers(channel_id) {
+            Some(tower_ids) => tower_ids
+                .into_iter()
+                .filter(|id| self.towers.contains_key(id))
+                .collect(),
+            None => self.towers.keys().cloned().collect(),
+        }
+    }
+
+    /// Sets the labels of a given tower, replacing any previous ones.
+    ///
+    /// Fails with [DBError::NotFound] if the tower is unknown.
+    pub fn set_tower_labels(
+        &mut self,
+        tower_id: TowerId,
+        labels: BTreeSet<String>,
+    ) -> Result<(), DBError> {
+        let tower = self.towers.get_mut(&tower_id).ok_or(DBError::NotFound)?;
+        self.dbm.store_tower_labels(tower_id, &labels)?;
+        tower.labels = labels;
+
+        Ok(())
+    }
+
+    /// Sets the tower every pending appointment is also enqueued for, so appointments that cannot be delivered to a
+    /// tower still reach the mirror. [None] removes the mirror.
+    ///
+    /// Fails with [DBError::NotFound] if the tower is unknown.
+    pub fn set_mirror(&mut self, tower_id: Option<TowerId>) -> Result<(), DBError> {
+        if tower_id.is_some_and(|id| !self.towers.contains_key(&id)) {
+            return Err(DBError::NotFound);
+        }
+
+        self.dbm.store_mirror_tower(tower_id)?;
+        self.mirror = tower_id;
+        Ok(())
+    }
+
+    /// Sets the order in which appointments are delivered to the towers, replacing any previous one. An empty order
+    /// removes it.
+    ///
+    /// Appointments are only sent to the first tower of the order. The next one only gets them once the previous one
+    /// permanently fails to: its retrier gives up, it misbehaves, or it rejects the appointment for good. Towers out of
+    /// the order are sent appointments as usual. Fails with [DBError::NotFound] if any of the towers is unknown.
+    pub fn set_tower_order(&mut self, tower_ids: Vec<TowerId>) -> Result<(), DBError> {
+        if tower_ids.iter().any(|id| !self.towers.contains_key(id)) {
+            return Err(DBError::NotFound);
+        }
+
+        self.dbm.store_tower_order(&tower_ids)?;
+        self.tower_order = tower_ids;
+        Ok(())
+    }
+
+    /// Filters out the towers an appointment should not be sent to yet given the [tower order](Self::set_tower_order).
+    ///
+    /// Only the first ordered tower within `targets` that has not already failed (unreachable or misbehaving) is kept,
+    /// or the last one if all have. Towers out of the order are not affected.
+    pub fn apply_tower_order(&self, targets: &mut HashSet<TowerId>) {
+        let ordered = self
+            .tower_order
+            .iter()
+            .filter(|id| targets.contains(id))
+            .cloned()
+            .collect::<Vec<_>>();
+        let first = ordered
+            .iter()
+            .find(|id| {
+                self.towers.get(id).is_some_and(|tower| {
+                    !tower.status.is_unreachable() && !tower.status.is_misbehaving()
+                })
+            })
+            .or(ordered.last());
+
+        if let Some(first) = first.cloned() {
+            for id in ordered.into_iter().filter(|id| *id != first) {
+                targets.remove(&id);
+            }
+        }
+    }
+
+    /// Pins the TLS certificate of a given tower, so connections presenting any other certificate are refused. Removes
+    /// the pin if [None] is given.
+    pub fn set_tower_pin(
+        &mut self,
+        tower_id: TowerId,
+        tls_pin: Option<TlsPin>,
+    ) -> Result<(), String> {
+        if !self.towers.contains_key(&tower_id) {
+            return Err(format!("Unknown tower {tower_id}"));
+        }
+
+        let clients = tls_pin
+            .map(|tls_pin| net::build_clients(self.proxy.as_ref(), Some(&tls_pin)))
+            .transpose()
+            .map_err(|e| format!("Cannot build HTTP client: {e:?}"))?;
+        self.dbm
+            .store_tower_pin(tower_id, tls_pin.as_ref())
+            .map_err(|e| format!("Cannot store the TLS pin: {e:?}"))?;
+
+        self.towers.get_mut(&tower_id).unwrap().tls_pin = tls_pin;
+        match clients {
+            Some(clients) => self.pinned_clients.insert(tower_id, clients),
+            None => self.pinned_clients.remove(&tower_id),
+        };
+
+        Ok(())
+    }
+
+    /// Gets the towers tagged with a given label.
+    pub fn get_towers_by_label(&self, label: &str) -> HashSet<TowerId> {
+        self.towers
+            .iter()
+            .filter(|(_, tower)| tower.labels.contains(label))
+            .map(|(tower_id, _)| *tower_id)
+            .collect()
+    }
+
+    /// Adds an invalid appointment to the tower record.
+    ///
+    /// Returns whether the appointment will be retried later on, according to the [InvalidRetryPolicy] (if any).
+    /// Appointments that won't are handed over to the next tower in the [tower order](Self::set_tower_order), if any.
+    pub fn add_invalid_appointment(
+        &mut self,
+        tower_id: TowerId,
+        appointment: &Appointment,
+    ) -> bool {
+        if let Some(tower) = self.towers.get_mut(&tower_id) {
+            tower.invalid_appointments.insert(appointment.locator);
+
+            let rejections = self
+                .dbm
+                .store_invalid_appointment(tower_id, appointment)
+                .unwrap();
+            let retriable = self
+                .invalid_retry
+                .is_some_and(|policy| rejections <= policy.max_retries);
+            if !retriable {
+                self.fall_back_appointment(tower_id, appointment);
+            }
+            retriable
+        } else {
+            log::error!("Cannot add invalid appointment to tower. Unknown tower_id: {tower_id}");
+            false
+        }
+    }
+
+    /// Adds an appointment rejected by a tower to the tower record (see [Self::add_invalid_appointment]).
+    ///
+    /// Appointments that won't be retried are also recorded in the dead-letter queue alongside the rejection, unless it is
+    /// disabled. Returns whether the appointment will be retried later on.
+    pub fn add_rejected_appointment(
+        &mut self,
+        tower_id: TowerId,
+        appointment: &Appointment,
+        error: &ApiError,
+    ) -> bool {
+        let retriable = self.add_invalid_appointment(tower_id, appointment);
+        if !retriable && self.dead_letter_limit > 0 && self.towers.contains_key(&tower_id) {
+            let dead_letter = DeadLetter {
+                tower_id,
+                appointment: appointment.clone(),
+                error_code: error.error_code,
+                error: error.error.clone(),
+                rejected_at: retrier::now(),
+            };
+            if let Err(e) = self
+                .dbm
+                .store_dead_letter(&dead_letter, self.dead_letter_limit)
+            {
+                log::error!(
+                    "Cannot add {} to the dead-letter queue. Error: {e}",
+                    appointment.locator
+                );
+            }
+        }
+        retriable
+    }
+
+    /// Gets the appointments permanently rejected by a given tower, alongside the context of the rejection, oldest first.
+    pub fn dead_letters(&self, tower_id: TowerId) -> Vec<DeadLetter> {
+        self.dbm.load_dead_letters(tower_id)
+    }
+
+    /// Flags a given tower as misbehaving, storing the misbehaving proof in the database.
+    ///
+    /// Its pending appointments are handed over to the next tower in the [tower order](Self::set_tower_order), if any.
+    pub fn flag_misbehaving_tower(&mut self, tower_id: TowerId, proof: MisbehaviorProof) {
+        if let Some(tower) = self.towers.get_mut(&tower_id) {
+            self.dbm.store_misbehaving_proof(tower_id, &proof).unwrap();
+            tower.status = TowerStatus::Misbehaving;
+            self.fall_back_pending_appointments(tower_id);
+        } else {
+            log::error!("Cannot flag tower. Unknown tower_id: {tower_id}");
+        }
+    }
+
+    /// Compares the appointments the tower reports to be holding for the user against the local ones.
+    ///
+    /// Pending appointments that are not held by the tower are sent to the retrier so they are re-sent. Missing and unknown
+    /// appointments are only reported, given there is nothing the client can do about them.
+    pub fn resync_tower(
+        &mut self,
+        tower_id: TowerId,
+        tower_locators: HashSet<Locator>,
+    ) -> Result<ResyncReport, DBError> {
+        let tower = self.towers.get(&tower_id).ok_or(DBError::NotFound)?;
+        let accepted = self
+            .dbm
+            .load_appointment_locators(tower_id, AppointmentStatus::Accepted)?;
+
+        let report = ResyncReport {
+            resent: tower
+                .pending_appointments
+                .difference(&tower_locators)
+                .cloned()
+                .collect(),
+            missing: accepted.difference(&tower_locators).cloned().collect(),
+            unknown: tower_locators
+                .iter()
+                .filter(|l| {
+                    !accepted.contains(l)
+                        && !tower.pending_appointments.contains(l)
+                        && !tower.invalid_appointments.contains(l)
+                })
+                .cloned()
+                .collect(),
+        };
+
+        if !report.missing.is_empty() || !report.unknown.is_empty() {
+            log::warn!(
+                "Local state does not match {tower_id}'s. Tower holds {} appointments ({} missing, {} unknown)",
+                tower_locators.len(),
+                report.missing.len(),
+                report.unknown.len()
+            );
+        }
+
+        if !report.resent.is_empty() {
+            if tower.status.is_misbehaving() {
+                log::warn!("{tower_id} is misbehaving. Not re-sending any appointment");
+            } else if self.shutting_down {
+                log::info!(
+                    "Shutting down. The appointments will be re-sent to {tower_id} on restart"
+                );
+            } else if self
+                .get_retrier_status(&tower_id)
+                .is_some_and(|status| status.is_idle())
+            {
+                // Idle retriers load all pending appointments from the database when woken up.
+                self.unreachable_towers
+                    .send((tower_id, RevocationData::None))
+                    .unwrap();
+            } else {
+                self.unreachable_towers
+                    .send((tower_id, RevocationData::Stale(report.resent.clone())))
+                    .unwrap();
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Enqueues the appointments that are still being delivered to other towers for a given (e.g. freshly-added) tower,
+    /// so it covers the existing channels straight away. Returns the locators of the appointments enqueued.
+    ///
+    /// The appointment data is not kept once accepted, so appointments that have already been delivered to every other
+    /// tower cannot be replayed. Appointments past their delivery deadline, deleted ones and the ones of channels
+    /// restricted to some other towers are skipped (see [DBM::load_backfill_appointments]).
+    pub fn backfill_tower(&mut self, tower_id: TowerId) -> Result<HashSet<Locator>, DBError> {
+        let status = self.get_tower_status(&tower_id).ok_or(DBError::NotFound)?;
+        if status.is_misbehaving() {
+            log::warn!("{tower_id} is misbehaving. Not backfilling any appointment");
+            return Ok(HashSet::new());
+        }
+
+        let now = retrier::now();
+        let mut locators = HashSet::new();
+        for (appointment, deadline) in self.dbm.load_backfill_appointments(tower_id)? {
+            if deadline.is_some_and(|deadline| deadline <= now) {
+                continue;
+            }
+            self.dbm
+                .store_pending_appointment_with_deadline(tower_id, &appointment, deadline)
+                .unwrap();
+            locators.insert(appointment.locator);
+        }
+        if locators.is_empty() {
+            return Ok(locators);
+        }
+
+        log::info!("Backfilling {} appointments to {tower_id}", locators.len());
+        self.towers
+            .get_mut(&tower_id)
+            .unwrap()
+            .pending_appointments
+            .extend(locators.iter().cloned());
+
+        if self.shutting_down {
+            log::info!("Shutting down. The appointments will be sent to {tower_id} on restart");
+        } else if status.is_unreachable() {
+            // Unreachable towers are only retried on demand, as any other pending appointment of theirs
+        } else if self
+            .get_retrier_status(&tower_id)
+            .is_some_and(|status| status.is_idle())
+        {
+            // Idle retriers load all pending appointments from the database when woken up.
+            self.unreachable_towers
+                .send((tower_id, RevocationData::None))
+                .unwrap();
+        } else {
+            self.unreachable_towers
+                .send((tower_id, RevocationData::Stale(locators.clone())))
+                .unwrap();
+        }
+
+        Ok(locators)
+    }
+
+    /// Registers with every tower in a community-maintained [TowerList], as long as it is signed by `maintainer`.
+    ///
+    /// Failing to register with a given tower does not abort the import. The failure is reported and the rest of the
+    /// towers are tried. Towers the client is already registered with are skipped.
+    pub async fn import_tower_list(
+        wt_client: &Arc<Mutex<WTClient>>,
+        list: &TowerList,
+        maintainer: &PublicKey,
+    ) -> Result<ImportReport, TowerListError> {
+        if !list.verify(maintainer) {
+            return Err(TowerListError::InvalidSignature);
+        }
+
+        let mut report = ImportReport::default();
+        for entry in list.towers.iter() {
+            if wt_client
+                .lock()
+                .unwrap()
+                .towers
+                .contains_key(&entry.tower_id)
+            {
+                report.skipped.push(entry.tower_id);
+                continue;
+            }
+
+            match WTClient::register_listed_tower(wt_client, entry).await {
+                Ok(()) => report.registered.push(entry.tower_id),
+                Err(e) => {
+                    log::warn!("Cannot register with listed tower {}. {e}", entry.tower_id);
+                    report.failed.insert(entry.tower_id, e);
+                }
+            }
+        }
+
+        log::info!(
+            "Tower list imported. {} registered, {} skipped, {} failed",
+            report.registered.len(),
+            report.skipped.len(),
+            report.failed.len()
+        );
+        Ok(report)
+    }
+
+    /// Sends a fresh appointment to every reachable target concurrently, bounded by the client max fan-out (see
+    /// [Self::with_max_fanout]), and reports the aggregate outcome once every delivery is over.
+    ///
+    /// Targets that are not reachable are skipped and left to the retriers. Towers failing to accept the appointment
+    /// are handled as in any other submission (e.g. the appointment is added to their pending appointments if they
+    /// cannot be reached).
+    pub async fn deliver_to_all(
+        wt_client: &Arc<Mutex<WTClient>>,
+        appointment: &Appointment,
+        targets: Vec<SubmissionTarget>,
+    ) -> DeliveryReport {
+        let mut report = DeliveryReport::default();
+        let mut deliveries = Vec::new();
+        for (tower_id, net_addr, status, signature) in targets {
+            if !status.is_reachable() {
+                report.skipped.insert(tower_id);
+                continue;
+            }
+
+            let wt_client = wt_client.clone();
+            let appointment = appointment.clone();
+            let delivery = tokio::spawn(async move {
+                submitter::deliver(wt_client, tower_id, net_addr, &appointment, &signature).await
+            });
+            deliveries.push((tower_id, delivery));
+        }
+
+        for (tower_id, delivery) in deliveries {
+            if delivery.await.unwrap_or(false) {
+                report.accepted.insert(tower_id);
+            } else {
+                report.failed.insert(tower_id);
+            }
+        }
+
+        log::info!(
+            "{} delivered to {} tower(s). {} failed, {} skipped",
+            appointment.locator,
+            report.accepted.len(),
+            report.failed.len(),
+            report.skipped.len()
+        );
+        report
+    }
+
+    /// Exports the client view of its towers, so it can be compared against the one of other plugin instances.
+    pub fn export_state(&self) -> ExportedState {
+        ExportedState {
+            towers: self
+                .towers
+                .iter()
+                .map(|(tower_id, summary)| (*tower_id, ExportedTower::from(summary)))
+                .collect(),
+        }
+    }
+
+    /// Reports the version of the plugin and the features it has been compiled with.
+    ///
+    /// New features are appended as they land, so front-ends can adapt to the running version.
+    pub fn capabilities() -> Capabilities {
+        let mut features = vec![
+            Capability::BatchDelivery,
+            Capability::ConcurrentDelivery,
+            Capability::PerTowerProxy,
+            Capability::TlsPinning,
+            Capability::MirrorTowers,
+            Capability::TowerOrder,
+            Capability::ChannelTowers,
+            Capability::TowerLabels,
+            Capability::AutoRenew,
+            Capability::TowerLists,
+            Capability::StateExport,
+            Capability::CsvExport,
+            Capability::ReceiptVerification,
+            Capability::SelfTest,
+            Capability::DeadLetters,
+            Capability::AggressiveRetry,
+            Capability::Webhooks,
+        ];
+        if cfg!(feature = "blocking") {
+            features.push(Capability::Blocking);
+        }
+
+        Capabilities {
+            version: env!("CARGO_PKG_VERSION"),
+            features,
+        }
+    }
+
+    /// Loads the records of all the appointments delivered to the towers, alongside their receipts.
+    pub fn delivery_records(&self) -> Vec<DeliveryRecord> {
+        self.dbm.load_delivery_records()
+    }
+
+    /// Compares the client state against the one exported by a different plugin instance. This is read-only.
+    pub fn diff_against(&self, other: &ExportedState) -> StateDiff {
+        self.export_state().diff(other)
+    }
+
+    /// Registers with a tower from a [TowerList].
+    async fn register_listed_tower(
+        wt_client: &Arc<Mutex<WTClient>>,
+        entry: &TowerListEntry,
+    ) -> Result<(), String> {
+        let net_addr = if entry.net_addr.starts_with("http://") {
+            NetAddr::new(entry.net_addr.clone())
+        } else {
+            NetAddr::new(format!("http://{}", entry.net_addr))
+        };
+        let (user_id, options) = {
+            let state = wt_client.lock().unwrap();
+            let use_proxy = net_addr.is_onion();
+            (
+                state.user_id,
+                state.resolve_request_options(
+                    entry.tower_id,
+                    state.resolve_proxy(use_proxy).is_some(),
+                ),
+            )
+        };
+
+        let (receipt, signature_version) =
+            http::register(entry.tower_id, user_id, &net_addr, None, &options)
+                .await
+                .map_err(|e| e.to_string())?;
+        if !receipt.verify(&entry.tower_id) {
+            return Err("Registration receipt contains bad signature".to_owned());
+        }
+
+        let mut state = wt_client.lock().unwrap();
+        state
+            .add_update_tower(entry.tower_id, net_addr.net_addr(), &receipt)
+            .map_err(|_| "Registration receipt is not valid".to_owned())?;
+        state.set_tower_signature_version(entry.tower_id, signature_version);
+        Ok(())
+    }
+
+    /// Updates the best known block height.
+    pub fn set_block_height(&mut self, block_height: u32) {
+        self.block_height = Some(block_height);
+    }
+
+    /// Sets whether a given tower is always reached through the proxy (if any), overriding the flag inferred from its
+    /// address.
+    pub fn set_tower_proxy(&mut self, tower_id: TowerId, use_proxy: bool) -> Result<(), String> {
+        if !self.towers.contains_key(&tower_id) {
+            return Err(format!("Unknown tower {tower_id}"));
+        }
+
+        self.dbm
+            .store_tower_proxy(tower_id, use_proxy)
+            .map_err(|e| format!("Cannot store the proxy flag: {e:?}"))?;
+        self.towers.get_mut(&tower_id).unwrap().use_proxy = use_proxy;
+
+        Ok(())
+    }
+
+    /// Sets whether the subscription with a given tower is automatically renewed before it expires.
+    pub fn set_tower_auto_renew(
+        &mut self,
+        tower_id: TowerId,
+        auto_renew: bool,
+    ) -> Result<(), String> {
+        if !self.towers.contains_key(&tower_id) {
+            return Err(format!("Unknown tower {tower_id}"));
+        }
+
+        self.dbm
+            .store_tower_auto_renew(tower_id, auto_renew)
+            .map_err(|e| format!("Cannot store the auto-renew flag: {e:?}"))?;
+        self.towers.get_mut(&tower_id).unwrap().auto_renew = auto_renew;
+
+        Ok(())
+    }
+
+    /// Gets the towers flagged for auto-renewal whose subscription expires within the next [Self::auto_renew_blocks]
+    /// blocks, flagging them as attempted.
+    ///
+    /// Nothing is renewed if the current block height is unknown. Towers that require a payment are left for the user
+    /// to renew, and towers that failed to be renewed are not tried again until [AUTO_RENEW_RETRY_DELAY] has elapsed.
+    pub fn towers_to_renew(&mut self) -> Vec<TowerId> {
+        let height = match self.block_height {
+            Some(height) if self.auto_renew_blocks > 0 && !self.shutting_down => height,
+            _ => return Vec::new(),
+        };
+
+        let now = retrier::now();
+        let due: Vec<TowerId> = self
+            .towers
+            .iter()
+            .filter(|(tower_id, tower)| {
+                tower.auto_renew
+                    && tower.status != TowerStatus::Misbehaving
+                    && tower.subscription_expiry <= height.saturating_add(self.auto_renew_blocks)
+                    && !self.payment_required.contains(tower_id)
+                    && self
+                        .renewal_attempts
+                        .get(tower_id)
+                        .is_none_or(|t| now.saturating_sub(*t) >= AUTO_RENEW_RETRY_DELAY)
+            })
+            .map(|(tower_id, _)| *tower_id)
+            .collect();
+
+        for tower_id in due.iter() {
+            self.renewal_attempts.insert(*tower_id, now);
+        }
+        due
+    }
+
+    /// Renews the subscription with a given tower by registering with it again.
+    ///
+    /// If the tower requires a payment to do so it is flagged as needing attention, so the user can renew it manually.
+    pub async fn renew_subscription(
+        wt_client: &Arc<Mutex<WTClient>>,
+        tower_id: TowerId,
+    ) -> Result<(), String> {
+        let (user_id, net_addr, options) = {
+            let state = wt_client.lock().unwrap();
+            let tower = state
+                .towers
+                .get(&tower_id)
+                .ok_or_else(|| format!("Unknown tower {tower_id}"))?;
+            let use_proxy = tower.net_addr.is_onion() || tower.use_proxy;
+            (
+                state.user_id,
+                tower.net_addr.clone(),
+                state.resolve_request_options(tower_id, state.resolve_proxy(use_proxy).is_some()),
+            )
+        };
+
+        let (receipt, signature_version) =
+            match http::register(tower_id, user_id, &net_addr, None, &options).await {
+                Ok(r) => r,
+                Err(e) => {
+                    if let RequestError::PaymentRequired(_) = e {
+                        wt_client.lock().unwrap().payment_required.insert(tower_id);
+                    }
+                    return Err(e.to_string());
+                }
+            };
+        if !receipt.verify(&tower_id) {
+            return Err("Registration receipt contains bad signature".to_owned());
+        }
+
+        let mut state = wt_client.lock().unwrap();
+        state
+            .add_update_tower(tower_id, net_addr.net_addr(), &receipt)
+            .map_err(|_| "Registration receipt is not valid".to_owned())?;
+        state.set_tower_signature_version(tower_id, signature_version);
+        Ok(())
+    }
+
+    /// Removes a tower from the client (both memory and database).
+    ///
+    /// Any data associated to the tower will be deleted (i.e. links to appointments)
+    pub fn remove_tower(&mut self, tower_id: TowerId) -> Result<(), DBError> {
+        if self.towers.contains_key(&tower_id) {
+            self.towers.remove(&tower_id);
+            self.last_errors.remove(&tower_id);
+            self.last_deliveries.remove(&tower_id);
+            self.unreachable_since.remove(&tower_id);
+            self.pinned_clients.remove(&tower_id);
+            self.renewal_attempts.remove(&tower_id);
+            self.payment_required.remove(&tower_id);
+            if self.mirror == Some(tower_id) {
+                self.mirror = None;
+            }
+            self.tower_order.retain(|id| *id != tower_id);
+            self.dbm.remove_tower_record(tower_id)
+        } else {
+            Err(DBError::NotFound)
+        }
+    }
+
+    /// Removes all the towers that have failed to be retried, or that have been unreachable for at least `min_age`
+    /// seconds, returning the ones removed.
+    ///
+    /// The towers are removed from the database in a single transaction. Their retriers, if any, are dropped by the
+    /// [RetryManager](crate::retrier::RetryManager) once it notices the towers are gone.
+    pub fn remove_failed_towers(&mut self, min_age: u64) -> Result<Vec<TowerId>, DBError> {
+        let now = retrier::now();
+        let failed: Vec<TowerId> = self
+            .towers
+            .iter()
+            .filter(|(tower_id, tower)| {
+                self.retriers
+                    .get(tower_id)
+                    .is_some_and(|status| status.failed())
+                    || (tower.status == TowerStatus::Unreachable
+                        && self
+                            .unreachable_since
+                            .get(tower_id)
+                            .is_some_and(|since| now.saturating_sub(*since) >= min_age))
+            })
+            .map(|(tower_id, _)| *tower_id)
+            .collect();
+
+        if !failed.is_empty() {
+            self.dbm.remove_tower_records(&failed)?;
+            for tower_id in failed.iter() {
+                self.towers.remove(tower_id);
+                self.last_errors.remove(tower_id);
+                self.last_deliveries.remove(tower_id);
+                self.unreachable_since.remove(tower_id);
+                self.pinned_clients.remove(tower_id);
+            }
+        }
+
+        Ok(failed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+    use tempdir::TempDir;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use teos_common::errors;
+    use teos_common::net::http::Endpoint;
+    use teos_common::test_utils::{
+        generate_random_appointment, get_random_appointment_receipt, get_random_locator,
+        get_random_registration_receipt, get_random_user_id,
+        get_registration_receipt_from_previous,
+    };
+
+    use crate::test_utils::get_signed_list;
+    use crate::webhook::WEBHOOK_QUEUE_CAPACITY;
+
+    #[tokio::test]
+    async fn test_add_update_load_tower() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        // Adding a new tower will add a summary to towers and the full data to the
+        let mut receipt = get_random_registration_receipt();
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let tower_info = TowerInfo::empty(
+            "talaia.watch".to_owned(),
+            receipt.available_slots(),
+            receipt.subscription_start(),
+            receipt.subscription_expiry(),
+        );
+
+        wt_client
+            .add_update_tower(tower_id, &tower_info.net_addr, &receipt)
+            .unwrap();
+        assert_eq!(
+            wt_client.towers.get(&tower_id),
+            Some(&TowerSummary::from(tower_info.clone()))
+        );
+        assert_eq!(wt_client.load_tower_info(tower_id).unwrap(), tower_info);
+
+        // Calling the method again with updated information should also updated the records in memory and the database
+        receipt = get_registration_receipt_from_previous(&receipt);
+
+        let updated_tower_info = TowerInfo::empty(
+            "talaia.watch".to_owned(),
+            receipt.available_slots(),
+            receipt.subscription_start(),
+            receipt.subscription_expiry(),
+        );
+        wt_client
+            .add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt)
+            .unwrap();
+
+        assert_eq!(
+            wt_client.towers.get(&tower_id),
+            Some(&TowerSummary::from(updated_tower_info.clone()))
+        );
+        assert_eq!(
+            wt_client.load_tower_info(tower_id).unwrap(),
+            updated_tower_info
+        );
+
+        // If we try to update without increasing both the end_time and the slots, this will fail
+        let mut receipt_same_slots = RegistrationReceipt::new(
+            receipt.user_id(),
+            receipt.available_slots(),
+            receipt.subscription_start(),
+            receipt.subscription_expiry() + 1,
+        );
+        receipt_same_slots.sign(&tower_sk);
+        let mut receipt_same_expiry = RegistrationReceipt::new(
+            receipt.user_id(),
+            receipt.available_slots() + 1,
+            receipt.subscription_start(),
+            receipt.subscription_expiry(),
+        );
+        receipt_same_expiry.sign(&tower_sk);
+
+        assert!(matches!(
+            wt_client.add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt),
+            Err(SubscriptionError::Expiry)
+        ));
+        assert!(matches!(
+            wt_client.add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt_same_slots),
+            Err(SubscriptionError::Slots)
+        ));
+        assert!(matches!(
+            wt_client.add_update_tower(
+                tower_id,
+                &updated_tower_info.net_addr,
+                &receipt_same_expiry
+            ),
+            Err(SubscriptionError::Expiry)
+        ));
+
+        // Decrease the slots count (simulate exhaustion) and update with more than the current count it should work
+        let locator = generate_random_appointment(None).locator;
+        wt_client.add_appointment_receipt(
+            tower_id,
+            locator,
+            0,
+            &get_random_appointment_receipt(tower_sk),
+        );
+        wt_client
+            .add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt_same_slots)
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_tower_proxy() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let proxy = ProxyInfo::new(
+            cln_plugin::messages::ProxyInfo {
+                typ: "ipv4".to_owned(),
+                address: "127.0.0.1".to_owned(),
+                port: 9050,
+            },
+            false,
+        );
+        let mut wt_client = WTClient::with_proxy(
+            tmp_path.path().to_path_buf(),
+            unbounded_channel().0,
+            Some(proxy),
+        )
+        .await;
+
+        // Unknown towers get no proxy
+        let clearnet_id = get_random_user_id();
+        assert!(wt_client.get_tower_proxy(clearnet_id).is_none());
+
+        // Clearnet towers bypass the proxy while onion towers are flagged to use it
+        let onion_id = get_random_user_id();
+        wt_client
+            .add_update_tower(
+                clearnet_id,
+                "http://talaia.watch:9814",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+        wt_client
+            .add_update_tower(
+                onion_id,
+                "http://recnedb7xfhzjdrcgxongzli3a6qyrv5jwgowoho3v5g3rwk7kkglrid.onion:9814",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+
+        assert!(!wt_client.towers[&clearnet_id].use_proxy);
+        assert!(wt_client.towers[&onion_id].use_proxy);
+        assert!(wt_client.get_tower_proxy(clearnet_id).is_none());
+        assert!(wt_client.get_tower_proxy(onion_id).unwrap().always_use);
+
+        // The flag is persisted alongside the tower record
+        assert!(!wt_client.load_tower_info(clearnet_id).unwrap().use_proxy);
+        assert!(wt_client.load_tower_info(onion_id).unwrap().use_proxy);
+        assert_eq!(wt_client.dbm.load_towers(), wt_client.towers);
+
+        // If the proxy is set to be always used, clearnet towers fall back to it
+        wt_client.proxy.as_mut().unwrap().always_use = true;
+        assert!(wt_client.get_tower_proxy(clearnet_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_set_tower_proxy() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        assert!(wt_client
+            .set_tower_proxy(get_random_user_id(), true)
+            .is_err());
+
+        let tower_id = get_random_user_id();
+        let onion_addr =
+            "http://recnedb7xfhzjdrcgxongzli3a6qyrv5jwgowoho3v5g3rwk7kkglrid.onion:9814";
+        wt_client
+            .add_update_tower(tower_id, onion_addr, &get_random_registration_receipt())
+            .unwrap();
+        assert!(wt_client.towers[&tower_id].use_proxy);
+
+        // The inferred flag can be cleared, and stays so when the subscription is renewed
+        wt_client.set_tower_proxy(tower_id, false).unwrap();
+        let mut receipt = RegistrationReceipt::new(wt_client.user_id, u32::MAX, 0, u32::MAX);
+        receipt.sign(&cryptography::get_random_keypair().0);
+        wt_client
+            .add_update_tower(tower_id, onion_addr, &receipt)
+            .unwrap();
+        assert!(!wt_client.towers[&tower_id].use_proxy);
+        assert!(!wt_client.load_tower_info(tower_id).unwrap().use_proxy);
+
+        // Clearnet towers can be set to use it too
+        let clearnet_id = get_random_user_id();
+        wt_client
+            .add_update_tower(
+                clearnet_id,
+                "http://talaia.watch:9814",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+        wt_client.set_tower_proxy(clearnet_id, true).unwrap();
+        assert!(wt_client.towers[&clearnet_id].use_proxy);
+        assert_eq!(wt_client.dbm.load_towers(), wt_client.towers);
+    }
+
+    #[tokio::test]
+    async fn test_get_request_options() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let proxy = ProxyInfo::new(
+            cln_plugin::messages::ProxyInfo {
+                typ: "ipv4".to_owned(),
+                address: "127.0.0.1".to_owned(),
+                port: 9050,
+            },
+            false,
+        );
+        let mut wt_client = WTClient::with_proxy(
+            tmp_path.path().to_path_buf(),
+            unbounded_channel().0,
+            Some(proxy),
+        )
+        .await;
+
+        let clearnet_id = get_random_user_id();
+        let onion_id = get_random_user_id();
+        wt_client
+            .add_update_tower(
+                clearnet_id,
+                "http://talaia.watch:9814",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+        wt_client
+            .add_update_tower(
+                onion_id,
+                "http://recnedb7xfhzjdrcgxongzli3a6qyrv5jwgowoho3v5g3rwk7kkglrid.onion:9814",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+
+        // The same client instance is handed over across calls, so connections can be reused
+        let options = wt_client.get_request_options(clearnet_id);
+        assert!(!options.use_proxy);
+        assert!(Arc::ptr_eq(&options.client, &wt_client.client));
+        assert!(Arc::ptr_eq(
+            &options.client,
+            &wt_client.get_request_options(clearnet_id).client
+        ));
+
+        // Towers reached through the proxy share the proxied client instead
+        let options = wt_client.get_request_options(onion_id);
+        assert!(options.use_proxy);
+        assert!(Arc::ptr_eq(
+            &options.client,
+            wt_client.proxied_client.as_ref().unwrap()
+        ));
+        assert!(Arc::ptr_eq(
+            &options.client,
+            &wt_client.get_request_options(onion_id).client
+        ));
+
+        // Towers only known by their address are reached the same way, depending on the kind of address
+        let options =
+            wt_client.resolve_discovery_options(&NetAddr::new("http://talaia.watch:9814".into()));
+        assert!(!options.use_proxy);
+        assert!(Arc::ptr_eq(&options.client, &wt_client.client));
+        let options = wt_client.resolve_discovery_options(&NetAddr::new(
+            "http://recnedb7xfhzjdrcgxongzli3a6qyrv5jwgowoho3v5g3rwk7kkglrid.onion:9814".into(),
+        ));
+        assert!(options.use_proxy);
+        assert!(Arc::ptr_eq(
+            &options.client,
+            wt_client.proxied_client.as_ref().unwrap()
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_tower_status() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        // If the tower is unknown, get_tower_status returns None
+        let tower_id = get_random_user_id();
+        assert!(wt_client.get_tower_status(&tower_id).is_none());
+
+        // Add a tower
+        let receipt = get_random_registration_receipt();
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &receipt)
+            .unwrap();
+
+        // If the tower is known, get_tower_status matches getting the same data from the towers collection
+        assert_eq!(
+            wt_client.towers.get(&tower_id).unwrap().status,
+            wt_client.get_tower_status(&tower_id).unwrap()
+        )
+    }
+
+    #[tokio::test]
+    async fn test_all_tower_statuses() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert!(wt_client.all_tower_statuses().is_empty());
+
+        // Add towers in varied states, some of them with pending appointments and retriers
+        let states = [
+            (TowerStatus::Reachable, None, 0),
+            (
+                TowerStatus::TemporaryUnreachable,
+                Some(RetrierStatus::Running),
+                2,
+            ),
+            (TowerStatus::Unreachable, Some(RetrierStatus::Failed), 3),
+            (
+                TowerStatus::SubscriptionError,
+                Some(RetrierStatus::Stopped),
+                1,
+            ),
+            (TowerStatus::Misbehaving, None, 0),
+        ];
+        for (status, retrier, pending) in states {
+            let tower_id = get_random_user_id();
+            wt_client
+                .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+            wt_client.set_tower_status(tower_id, status);
+            if let Some(retrier) = retrier {
+                wt_client.retriers.insert(tower_id, retrier);
+            }
+            for _ in 0..pending {
+                wt_client.add_pending_appointment(tower_id, &generate_random_appointment(None));
+            }
+        }
+
+        // The bulk result matches querying the towers one by one
+        let statuses = wt_client.all_tower_statuses();
+        assert_eq!(statuses.len(), 5);
+        for (tower_id, summary) in statuses {
+            assert_eq!(
+                summary,
+                TowerStatusSummary {
+                    status: wt_client.get_tower_status(&tower_id).unwrap(),
+                    retrier: wt_client.get_retrier_status(&tower_id).map(Into::into),
+                    pending_count: wt_client.towers[&tower_id].pending_appointments.len(),
+                }
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_tower_status() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        // If the tower is unknown nothing will happen
+        let unknown_tower = get_random_user_id();
+        wt_client.set_tower_status(unknown_tower, TowerStatus::Reachable);
+        assert!(!wt_client.towers.contains_key(&unknown_tower));
+
+        // If the tower is known, the status will be updated.
+        let receipt = get_random_registration_receipt();
+        let tower_id = get_random_user_id();
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &receipt)
+            .unwrap();
+
+        for status in [
+            TowerStatus::Reachable,
+            TowerStatus::TemporaryUnreachable,
+            TowerStatus::Unreachable,
+            TowerStatus::SubscriptionError,
+            TowerStatus::Misbehaving,
+        ] {
+            wt_client.set_tower_status(tower_id, status);
+            assert_eq!(status, wt_client.get_tower_status(&tower_id).unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_tower_status_sink_filtered() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (sink, mut status_changes) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+            .await
+            .with_status_sink(sink)
+            .with_status_filter(
+                StatusFilter::from_str("unreachable, misbehaving,subscription_error").unwrap(),
+            );
+
+        let receipt = get_random_registration_receipt();
+        let tower_id = get_random_user_id();
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &receipt)
+            .unwrap();
+
+        // Routine churn is filtered out, whereas changes to the subscribed statuses are reported
+        for status in [
+            TowerStatus::TemporaryUnreachable,
+            TowerStatus::Reachable,
+            TowerStatus::TemporaryUnreachable,
+            TowerStatus::Unreachable,
+            TowerStatus::Reachable,
+            TowerStatus::SubscriptionError,
+            TowerStatus::SubscriptionExhausted,
+            TowerStatus::Misbehaving,
+        ] {
+            wt_client.set_tower_status(tower_id, status);
+            // The status is updated no matter if the change is reported
+            assert_eq!(wt_client.get_tower_status(&tower_id), Some(status));
+        }
+
+        let mut reported = Vec::new();
+        while let Ok(change) = status_changes.try_recv() {
+            reported.push((change.old_status, change.new_status));
+        }
+        assert_eq!(
+            reported,
+            vec![
+                (TowerStatus::TemporaryUnreachable, TowerStatus::Unreachable),
+                (TowerStatus::Reachable, TowerStatus::SubscriptionError),
+                (TowerStatus::SubscriptionExhausted, TowerStatus::Misbehaving),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_sink() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (sink, mut events) = broadcast::channel(WEBHOOK_QUEUE_CAPACITY);
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+            .await
+            .with_webhook_sink(sink)
+            .with_status_filter(StatusFilter::from_str("unreachable").unwrap());
+
+        let receipt = get_random_registration_receipt();
+        let tower_id = get_random_user_id();
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &receipt)
+            .unwrap();
+
+        // Deliveries are reported, one event per appointment
+        let locator = get_random_locator();
+        wt_client.add_appointment_receipt(
+            tower_id,
+            locator,
+            21,
+            &get_random_appointment_receipt(cryptography::get_random_keypair().0),
+        );
+        let locators = (0..2).map(|_| get_random_locator()).collect::<Vec<_>>();
+        wt_client.add_appointment_receipts(
+            tower_id,
+            20,
+            &locators
+                .iter()
+                .map(|locator| {
+                    (
+                        *locator,
+                        get_random_appointment_receipt(cryptography::get_random_keypair().0),
+                    )
+                })
+                .collect::<Vec<_>>(),
+        );
+        for locator in [locator].iter().chain(locators.iter()) {
+            match events.try_recv().unwrap() {
+                WebhookEvent::AppointmentDelivered {
+                    tower_id: id,
+                    locator: l,
+                    timestamp,
+                } => {
+                    assert_eq!((id, l), (tower_id, *locator));
+                    assert!(timestamp > 0);
+                }
+                e => panic!("Unexpected event: {e:?}"),
+            }
+        }
+
+        // Status changes are reported too, as long as they are not filtered out
+        wt_client.set_tower_status(tower_id, TowerStatus::TemporaryUnreachable);
+        wt_client.set_tower_status(tower_id, TowerStatus::Unreachable);
+        match events.try_recv().unwrap() {
+            WebhookEvent::TowerStatusChanged(change) => {
+                assert_eq!(change.old_status, TowerStatus::TemporaryUnreachable);
+                assert_eq!(change.new_status, TowerStatus::Unreachable);
+            }
+            e => panic!("Unexpected event: {e:?}"),
+        }
+        assert!(events.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_status_filter_from_str() {
+        assert_eq!(StatusFilter::from_str("all"), Ok(StatusFilter::All));
+        assert_eq!(StatusFilter::default(), StatusFilter::All);
+        assert_eq!(
+            StatusFilter::from_str("temporary_unreachable,subscription_exhausted"),
+            Ok(StatusFilter::Only(HashSet::from_iter([
+                TowerStatus::TemporaryUnreachable,
+                TowerStatus::SubscriptionExhausted
+            ])))
+        );
+        for filter in ["", "reachable,", "failed", "temporary unreachable"] {
+            assert!(StatusFilter::from_str(filter).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_tower_status_sink() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (sink, mut status_changes) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+            .await
+            .with_status_sink(sink);
+
+        let receipt = get_random_registration_receipt();
+        let tower_id = get_random_user_id();
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &receipt)
+            .unwrap();
+
+        // Only actual changes are reported
+        wt_client.set_tower_status(tower_id, TowerStatus::Reachable);
+        wt_client.set_tower_status(tower_id, TowerStatus::Unreachable);
+        wt_client.set_tower_status(tower_id, TowerStatus::Unreachable);
+        wt_client.set_tower_status(get_random_user_id(), TowerStatus::Unreachable);
+
+        let change = status_changes.try_recv().unwrap();
+        assert!(status_changes.try_recv().is_err());
+        assert_eq!(
+            change,
+            TowerStatusChange {
+                tower_id,
+                old_status: TowerStatus::Reachable,
+                new_status: TowerStatus::Unreachable,
+                timestamp: change.timestamp,
+            }
+        );
+        assert!(change.timestamp > 0);
+
+        // This is the payload of the notification sent to CLN
+        assert_eq!(
+            serde_json::to_value(&change).unwrap(),
+            serde_json::json!({
+                "tower_id": tower_id.to_string(),
+                "old_status": "reachable",
+                "new_status": "unreachable",
+                "timestamp": change.timestamp,
+            })
+        );
+
+        // Reports are dropped if no one is listening anymore
+        drop(status_changes);
+        wt_client.set_tower_status(tower_id, TowerStatus::Reachable);
+        assert_eq!(
+            wt_client.get_tower_status(&tower_id),
+            Some(TowerStatus::Reachable)
+        );
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = WTClient::capabilities();
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+
+        // Features are reported once each, and the optional ones only if compiled in
+        let features: HashSet<Capability> = capabilities.features.iter().cloned().collect();
+        assert_eq!(features.len(), capabilities.features.len());
+        assert_eq!(
+            features.contains(&Capability::Blocking),
+            cfg!(feature = "blocking")
+        );
+        for feature in [
+            Capability::BatchDelivery,
+            Capability::MirrorTowers,
+            Capability::PerTowerProxy,
+            Capability::CsvExport,
+        ] {
+            assert!(features.contains(&feature));
+        }
+
+        // Features are serialized as snake case strings
+        assert_eq!(
+            serde_json::to_value(&capabilities).unwrap()["features"][0],
+            json!("batch_delivery")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_tower() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        // Unknown towers cannot be diagnosed
+        assert_eq!(wt_client.diagnose_tower(get_random_user_id()), None);
+
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+
+        // A healthy tower
+        let appointment = generate_random_appointment(None);
+        wt_client.add_appointment_receipt(
+            tower_id,
+            appointment.locator,
+            10,
+            &get_random_appointment_receipt(tower_sk),
+        );
+        let diagnosis = wt_client.diagnose_tower(tower_id).unwrap();
+        assert_eq!(diagnosis.status, TowerStatus::Reachable);
+        assert_eq!(diagnosis.retrier, None);
+        assert_eq!(diagnosis.last_error, None);
+        assert_eq!(diagnosis.accepted_appointments, 1);
+        assert_eq!(diagnosis.pending_appointments, 0);
+        assert_eq!(diagnosis.invalid_appointments, 0);
+        assert_eq!(diagnosis.expired_appointments, 0);
+        let last_delivery = diagnosis.last_delivery.unwrap();
+
+        // A tower whose subscription could not be renewed
+        let error = "Registration receipt contains bad signature".to_owned();
+        wt_client.set_tower_status(tower_id, TowerStatus::SubscriptionError);
+        wt_client.add_pending_appointment(tower_id, &generate_random_appointment(None));
+        wt_client.add_invalid_appointment(tower_id, &generate_random_appointment(None));
+        wt_client.record_error(
+            tower_id,
+            TowerError::new(ErrorKind::Subscription, error.clone()),
+        );
+
+        let diagnosis = wt_client.diagnose_tower(tower_id).unwrap();
+        assert_eq!(diagnosis.status, TowerStatus::SubscriptionError);
+        assert_eq!(diagnosis.last_error.as_ref().unwrap().error, error);
+        assert_eq!(diagnosis.accepted_appointments, 1);
+        assert_eq!(diagnosis.pending_appointments, 1);
+        assert_eq!(diagnosis.invalid_appointments, 1);
+        assert_eq!(diagnosis.expired_appointments, 0);
+        assert_eq!(diagnosis.last_delivery, Some(last_delivery));
+        assert!(diagnosis
+            .explanation
+            .starts_with("The subscription with the tower is not valid"));
+        assert!(diagnosis.explanation.contains(&error));
+
+        // Running retriers are reported alongside their next attempt
+        wt_client.retriers.insert(tower_id, RetrierStatus::Running);
+        wt_client.retry_schedule.insert(tower_id, 42);
+        let diagnosis = wt_client.diagnose_tower(tower_id).unwrap();
+        assert_eq!(diagnosis.retrier, Some(RetrierStatusInfo::Running));
+        assert!(diagnosis.explanation.contains("Next attempt at 42"));
+
+        // The data is gone with the tower
+        wt_client.remove_tower(tower_id).unwrap();
+        assert_eq!(wt_client.diagnose_tower(tower_id), None);
+        assert!(!wt_client.last_errors.contains_key(&tower_id));
+        assert!(!wt_client.last_deliveries.contains_key(&tower_id));
+    }
+
+    #[tokio::test]
+    async fn test_record_error() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        let tower_id = get_random_user_id();
+
+        // Errors of unknown towers are not recorded
+        let error = TowerError::new(ErrorKind::Internal, "error_msg".to_owned());
+        wt_client.record_error(tower_id, error.clone());
+        assert!(!wt_client.last_errors.contains_key(&tower_id));
+
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        wt_client.record_error(tower_id, error.clone());
+        assert_eq!(wt_client.last_errors[&tower_id], error);
+
+        // Every failure category is captured alongside its message (and code, if the tower replied with an error)
+        let error_message = "error_msg".to_owned();
+        for (error, kind, code) in [
+            (
+                RequestError::ConnectionError(error_message.clone()).into(),
+                ErrorKind::Connection,
+                None,
+            ),
+            (
+                RequestError::Unexpected(error_message.clone()).into(),
+                ErrorKind::Unexpected,
+                None,
+            ),
+            (
+                RequestError::Timeout(error_message.clone()).into(),
+                ErrorKind::Timeout,
+                None,
+            ),
+            (
+                RequestError::DeserializeError(error_message.clone()).into(),
+                ErrorKind::Unsupported,
+                None,
+            ),
+            (
+                RequestError::Rejected(error_message.clone()).into(),
+                ErrorKind::Rejected,
+                None,
+            ),
+            (
+                RequestError::PinMismatch(error_message.clone()).into(),
+                ErrorKind::Security,
+                None,
+            ),
+            (
+                AddAppointmentError::ApiError(http::ApiError {
+                    error: error_message.clone(),
+                    error_code: errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR,
+                }),
+                ErrorKind::Subscription,
+                Some(errors::INVALID_SIGNATURE_OR_SUBSCRIPTION_ERROR),
+            ),
+            (
+                AddAppointmentError::ApiError(http::ApiError {
+                    error: error_message.clone(),
+                    error_code: errors::SERVICE_UNAVAILABLE,
+                }),
+                ErrorKind::RateLimited,
+                Some(errors::SERVICE_UNAVAILABLE),
+            ),
+            (
+                AddAppointmentError::ApiError(http::ApiError {
+                    error: error_message.clone(),
+                    error_code: errors::APPOINTMENT_FIELD_TOO_BIG,
+                }),
+                ErrorKind::Rejected,
+                Some(errors::APPOINTMENT_FIELD_TOO_BIG),
+            ),
+        ] {
+            wt_client.record_error(tower_id, TowerError::from(&error));
+            let last_error = &wt_client.last_errors[&tower_id];
+            assert_eq!(last_error.kind, kind);
+            assert_eq!(last_error.error, error_message);
+            assert_eq!(last_error.code, code);
+        }
+
+        let proof = MisbehaviorProof::new(
+            generate_random_appointment(None).locator,
+            get_random_appointment_receipt(cryptography::get_random_keypair().0),
+            get_random_user_id(),
+        );
+        wt_client.record_error(
+            tower_id,
+            TowerError::from(&AddAppointmentError::SignatureError(proof)),
+        );
+        assert_eq!(
+            wt_client.last_errors[&tower_id].kind,
+            ErrorKind::Misbehaving
+        );
+        assert!(wt_client
+            .diagnose_tower(tower_id)
+            .unwrap()
+            .explanation
+            .contains("(Misbehaving, at"));
+    }
+
+    #[tokio::test]
+    async fn test_towers_needing_attention() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        // All subscriptions expire at height 5000
+        let height = 5000 - EXPIRY_WARNING_BLOCKS - 1;
+        let mut add_tower = |status: TowerStatus, available_slots: u32| {
+            let tower_id = get_random_user_id();
+            let mut receipt =
+                RegistrationReceipt::new(wt_client.user_id, available_slots, 42, 5000);
+            receipt.sign(&cryptography::get_random_keypair().0);
+            wt_client
+                .add_update_tower(tower_id, "talaia.watch", &receipt)
+                .unwrap();
+            wt_client.set_tower_status(tower_id, status);
+            tower_id
+        };
+
+        let healthy = add_tower(TowerStatus::Reachable, 21);
+        let retrying = add_tower(TowerStatus::TemporaryUnreachable, 21);
+        let failed = add_tower(TowerStatus::Unreachable, 21);
+        let misbehaving = add_tower(TowerStatus::Misbehaving, 21);
+        let subscription_error = add_tower(TowerStatus::SubscriptionError, 21);
+        let exhausted = add_tower(TowerStatus::SubscriptionExhausted, 0);
+        let out_of_slots = add_tower(TowerStatus::Unreachable, 0);
+
+        // Towers that are working (or being retried) do not need attention
+        let expected = HashMap::from([
+            (failed, vec![AttentionReason::Failed]),
+            (misbehaving, vec![AttentionReason::Misbehaving]),
+            (subscription_error, vec![AttentionReason::SubscriptionError]),
+            (exhausted, vec![AttentionReason::SlotsExhausted]),
+            (
+                out_of_slots,
+                vec![AttentionReason::Failed, AttentionReason::SlotsExhausted],
+            ),
+        ]);
+        assert_eq!(wt_client.towers_needing_attention(None), expected);
+        assert_eq!(wt_client.towers_needing_attention(Some(height)), expected);
+
+        // Once the expiry gets close all subscriptions are reported as expiring, and as expired after that
+        for (height, reason) in [
+            (height + 1, AttentionReason::SubscriptionExpiring),
+            (4999, AttentionReason::SubscriptionExpiring),
+            (5000, AttentionReason::SubscriptionExpired),
+        ] {
+            let attention = wt_client.towers_needing_attention(Some(height));
+            assert_eq!(attention.len(), 7);
+            assert_eq!(attention[&healthy], vec![reason]);
+            assert_eq!(attention[&retrying], vec![reason]);
+            assert_eq!(
+                attention[&out_of_slots],
+                vec![
+                    reason,
+                    AttentionReason::Failed,
+                    AttentionReason::SlotsExhausted
+                ]
+            );
+        }
+
+        // If no height is given, the one tracked from block notifications is used
+        wt_client.set_block_height(4999);
+        assert_eq!(
+            wt_client.towers_needing_attention(None)[&healthy],
+            vec![AttentionReason::SubscriptionExpiring]
+        );
+        wt_client.set_block_height(5000);
+        assert_eq!(
+            wt_client.towers_needing_attention(None)[&healthy],
+            vec![AttentionReason::SubscriptionExpired]
+        );
+        // A given height still takes precedence
+        assert_eq!(wt_client.towers_needing_attention(Some(height)), expected);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client.metrics(), DeliveryMetrics::default());
+
+        let towers = [
+            cryptography::get_random_keypair(),
+            cryptography::get_random_keypair(),
+        ];
+        for (_, tower_pk) in towers.iter() {
+            wt_client
+                .add_update_tower(
+                    TowerId(*tower_pk),
+                    "talaia.watch",
+                    &get_random_registration_receipt(),
+                )
+                .unwrap();
+        }
+        let (sk1, tower1) = (towers[0].0, TowerId(towers[0].1));
+        let (sk2, tower2) = (towers[1].0, TowerId(towers[1].1));
+
+        // Single deliveries
+        for _ in 0..3 {
+            let appointment = generate_random_appointment(None);
+            wt_client.add_appointment_receipt(
+                tower1,
+                appointment.locator,
+                10,
+                &get_random_appointment_receipt(sk1),
+            );
+        }
+
+        // Batched deliveries
+        for batch_size in [5, 2] {
+            let receipts = (0..batch_size)
+                .map(|_| {
+                    let appointment = generate_random_appointment(None);
+                    wt_client.add_pending_appointment(tower2, &appointment);
+                    (appointment.locator, get_random_appointment_receipt(sk2))
+                })
+                .collect::<Vec<_>>();
+            wt_client.add_appointment_receipts(tower2, 10, &receipts);
+        }
+
+        let metrics = wt_client.metrics();
+        assert_eq!(metrics.delivered, 10);
+        assert_eq!(
+            metrics.delivered_per_tower,
+            HashMap::from([(tower1, 3), (tower2, 7)])
+        );
+
+        // Deliveries to unknown towers are not accounted for
+        wt_client.add_appointment_receipt(
+            get_random_user_id(),
+            generate_random_appointment(None).locator,
+            10,
+            &get_random_appointment_receipt(sk1),
+        );
+        assert_eq!(wt_client.metrics(), metrics);
+    }
+
+    #[tokio::test]
+    async fn test_total_pending() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client.total_pending(), PendingWorkload::default());
+
+        // Add some towers with a known set of pending appointments
+        let tower_ids: Vec<TowerId> = (0..3).map(|_| get_random_user_id()).collect();
+        for tower_id in tower_ids.iter() {
+            wt_client
+                .add_update_tower(
+                    *tower_id,
+                    "talaia.watch",
+                    &get_random_registration_receipt(),
+                )
+                .unwrap();
+        }
+        for (tower_id, pending) in tower_ids.iter().zip([0, 2, 5]) {
+            for _ in 0..pending {
+                wt_client.add_pending_appointment(*tower_id, &generate_random_appointment(None));
+            }
+        }
+        // Appointments pending for several towers are counted once per tower
+        let shared = generate_random_appointment(None);
+        wt_client.add_pending_appointment(tower_ids[0], &shared);
+        wt_client.add_pending_appointment(tower_ids[1], &shared);
+
+        let expected = PendingWorkload {
+            total: 9,
+            per_tower: HashMap::from([(tower_ids[0], 1), (tower_ids[1], 3), (tower_ids[2], 5)]),
+        };
+        assert_eq!(wt_client.total_pending(), expected);
+
+        // Delivered appointments are not pending anymore
+        let (tower_sk, _) = cryptography::get_random_keypair();
+        wt_client.add_appointment_receipts(
+            tower_ids[0],
+            10,
+            &[(shared.locator, get_random_appointment_receipt(tower_sk))],
+        );
+        let workload = wt_client.total_pending();
+        assert_eq!(workload.total, 8);
+        assert_eq!(workload.per_tower[&tower_ids[0]], 0);
+    }
+
+    #[tokio::test]
+    async fn test_next_stale_batch() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let tower_id = get_random_user_id();
+        let reachable_tower_id = get_random_user_id();
+
+        // Build a backlog bigger than a single chunk. Towers with nothing pending are not stale
+        let backlog = STALE_FEED_CHUNK_SIZE + 10;
+        let mut locators = HashSet::new();
+        {
+            let mut wt_client =
+                WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+            for id in [tower_id, reachable_tower_id] {
+                wt_client
+                    .add_update_tower(id, "talaia.watch", &get_random_registration_receipt())
+                    .unwrap();
+            }
+            for _ in 0..backlog {
+                let appointment = generate_random_appointment(None);
+                wt_client.add_pending_appointment(tower_id, &appointment);
+                locators.insert(appointment.locator);
+            }
+        }
+
+        // Nothing is sent to the retriers on startup, the stale towers are only queued
+        let (tx, mut rx) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), tx)
+            .await
+            .with_stale_feed(StaleFeed::Lazy);
+        assert!(rx.try_recv().is_err());
+        assert_eq!(wt_client.stale_towers, VecDeque::from([(tower_id, None)]));
+
+        // Feeding lazily, the backlog is fed in chunks
+        let mut fed = HashSet::new();
+        for expected_len in [STALE_FEED_CHUNK_SIZE, backlog - STALE_FEED_CHUNK_SIZE] {
+            let batch = wt_client.next_stale_batch();
+            assert_eq!(batch.len(), 1);
+            assert_eq!(batch[0].0, tower_id);
+            assert_eq!(batch[0].1.len(), expected_len);
+            fed.extend(batch[0].1.iter());
+        }
+        assert_eq!(fed, locators);
+        assert!(wt_client.next_stale_batch().is_empty());
+        assert!(wt_client.stale_towers.is_empty());
+
+        // Towers that are not being retried anymore are dropped from the feed
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+            .await
+            .with_stale_feed(StaleFeed::Lazy);
+        wt_client
+            .retriers
+            .insert(tower_id, RetrierStatus::Idle(std::time::Instant::now()));
+        assert!(wt_client.next_stale_batch().is_empty());
+        assert!(wt_client.stale_towers.is_empty());
+
+        // Feeding eagerly, the backlog is fed at once
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+            .await
+            .with_stale_feed(StaleFeed::Eager);
+        assert_eq!(wt_client.next_stale_batch(), vec![(tower_id, locators)]);
+        assert!(wt_client.stale_towers.is_empty());
+
+        // The backlog is not big enough to be fed lazily by default
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client.next_stale_batch()[0].1.len(), backlog);
+        assert_eq!(wt_client.stale_feed, StaleFeed::Eager);
+    }
+
+    #[tokio::test]
+    async fn test_add_appointment_receipt_exhausted() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (sink, mut status_changes) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+            .await
+            .with_status_sink(sink);
+
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+
+        // Running out of slots is reported like any other status change
+        wt_client.add_appointment_receipt(
+            tower_id,
+            generate_random_appointment(None).locator,
+            0,
+            &get_random_appointment_receipt(tower_sk),
+        );
+        assert_eq!(
+            wt_client.get_tower_status(&tower_id),
+            Some(TowerStatus::SubscriptionExhausted)
+        );
+        let change = status_changes.try_recv().unwrap();
+        assert_eq!(
+            (change.old_status, change.new_status),
+            (TowerStatus::Reachable, TowerStatus::SubscriptionExhausted)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_appointment_receipt() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+
+        let locator = generate_random_appointment(None).locator;
+        let registration_receipt = get_random_registration_receipt();
+        let appointment_receipt = get_random_appointment_receipt(tower_sk);
+
+        // If we call this on an unknown tower it will simply do nothing
+        wt_client.add_appointment_receipt(
+            tower_id,
+            locator,
+            registration_receipt.available_slots(),
+            &appointment_receipt,
+        );
+        assert!(!wt_client.towers.contains_key(&tower_id));
+
+        // Add the tower to the state and try again
+        let tower_info = TowerInfo::new(
+            "talaia.watch".to_owned(),
+            registration_receipt.available_slots(),
+            registration_receipt.subscription_start(),
+            registration_receipt.subscription_expiry(),
+            HashMap::from([(locator, appointment_receipt.signature().unwrap())]),
+            Vec::new(),
+            Vec::new(),
+        );
+        wt_client
+            .add_update_tower(tower_id, &tower_info.net_addr, &registration_receipt)
+            .unwrap();
+        wt_client.add_appointment_receipt(
+            tower_id,
+            locator,
+            registration_receipt.available_slots(),
+            &appointment_receipt,
+        );
+
+        assert!(wt_client.towers.contains_key(&tower_id));
+        assert_eq!(
+            wt_client.towers.get(&tower_id).unwrap(),
+            &TowerSummary::from(tower_info.clone())
+        );
+        assert_eq!(wt_client.load_tower_info(tower_id).unwrap(), tower_info);
+    }
+
+    #[tokio::test]
+    async fn test_add_pending_appointment() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        let tower_id = get_random_user_id();
+
+        let registration_receipt = get_random_registration_receipt();
+        let appointment = generate_random_appointment(None);
+
+        // If we call this on an unknown tower it will simply do nothing
+        wt_client.add_pending_appointment(tower_id, &appointment);
+        assert!(!wt_client.towers.contains_key(&tower_id));
+
+        // Add the tower to the state and try again
+        let tower_info = TowerInfo::new(
+            "talaia.watch".to_owned(),
+            registration_receipt.available_slots(),
+            registration_receipt.subscription_start(),
+            registration_receipt.subscription_expiry(),
+            HashMap::new(),
+            vec![appointment.clone()],
+            Vec::new(),
+        );
+
+        wt_client
+            .add_update_tower(tower_id, &tower_info.net_addr, &registration_receipt)
+            .unwrap();
+        wt_client.add_pending_appointment(tower_id, &appointment);
+
+        assert!(wt_client.towers.contains_key(&tower_id));
+        assert_eq!(
+            wt_client.towers.get(&tower_id).unwrap(),
+            &TowerSummary::from(tower_info.clone())
+        );
+        // When towers data is loaded from the database, it is assumed to be reachable.
+        assert_eq!(
+            wt_client.load_tower_info(tower_id).unwrap(),
+            tower_info.with_status(TowerStatus::TemporaryUnreachable)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_pending_appointment() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        let tower_id = get_random_user_id();
+
+        let registration_receipt = get_random_registration_receipt();
+        let appointment = generate_random_appointment(None);
+
+        // If we call this on an unknown tower it will simply do nothing
+        wt_client.remove_pending_appointment(tower_id, appointment.locator);
+
+        // Add the tower to the state and try again
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &registration_receipt)
+            .unwrap();
+        wt_client.add_pending_appointment(tower_id, &appointment);
+
+        wt_client.remove_pending_appointment(tower_id, appointment.locator);
+        assert!(!wt_client
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .pending_appointments
+            .contains(&appointment.locator));
+        // This bit is tested exhaustively in the DBM.
+        assert!(!wt_client.dbm.appointment_exists(appointment.locator));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_appointment() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        let tower_id = get_random_user_id();
+        let appointment = generate_random_appointment(None);
+
+        // Appointments can only be cancelled for known towers
+        assert!(matches!(
+            wt_client.cancel_appointment(tower_id, appointment.locator),
+            Err(DBError::NotFound)
+        ));
+
+        // And as long as they are pending
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        assert!(matches!(
+            wt_client.cancel_appointment(tower_id, appointment.locator),
+            Err(DBError::NotFound)
+        ));
 
-    use teos_common::test_utils::{
-        generate_random_appointment, get_random_appointment_receipt,
-        get_random_registration_receipt, get_random_user_id,
-        get_registration_receipt_from_previous,
-    };
+        wt_client.add_pending_appointment(tower_id, &appointment);
+        assert!(wt_client.is_pending(tower_id, appointment.locator));
+        wt_client
+            .cancel_appointment(tower_id, appointment.locator)
+            .unwrap();
+        assert!(!wt_client.is_pending(tower_id, appointment.locator));
+        assert!(wt_client
+            .dbm
+            .load_appointment_locators(tower_id, AppointmentStatus::Pending)
+            .unwrap()
+            .is_empty());
+    }
 
     #[tokio::test]
-    async fn test_add_update_load_tower() {
+    async fn test_set_get_channel_towers() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let mut wt_client =
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
 
-        // Adding a new tower will add a summary to towers and the full data to the
-        let mut receipt = get_random_registration_receipt();
-        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
-        let tower_id = TowerId(tower_pk);
-        let tower_info = TowerInfo::empty(
-            "talaia.watch".to_owned(),
-            receipt.available_slots(),
-            receipt.subscription_start(),
-            receipt.subscription_expiry(),
-        );
+        let channel_id = "4c9a3f5e0d4d4cbe6ff4c9bd4a6e08e2b1d0b4f1e2a7e5d3b5c6a0f8e9d7c1b2";
+        let paid_tower = get_random_user_id();
+        let free_tower = get_random_user_id();
+        for tower_id in [paid_tower, free_tower] {
+            wt_client
+                .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
 
-        wt_client
-            .add_update_tower(tower_id, &tower_info.net_addr, &receipt)
-            .unwrap();
+        // By default appointments go to all towers
         assert_eq!(
-            wt_client.towers.get(&tower_id),
-            Some(&TowerSummary::from(tower_info.clone()))
+            wt_client.get_channel_towers(channel_id),
+            HashSet::from([paid_tower, free_tower])
         );
-        assert_eq!(wt_client.load_tower_info(tower_id).unwrap(), tower_info);
 
-        // Calling the method again with updated information should also updated the records in memory and the database
-        receipt = get_registration_receipt_from_previous(&receipt);
+        // Unknown towers cannot be targeted
+        assert!(matches!(
+            wt_client.set_channel_towers(channel_id, Some(HashSet::from([get_random_user_id()]))),
+            Err(DBError::NotFound)
+        ));
 
-        let updated_tower_info = TowerInfo::empty(
-            "talaia.watch".to_owned(),
-            receipt.available_slots(),
-            receipt.subscription_start(),
-            receipt.subscription_expiry(),
-        );
+        // Once restricted, the appointments of the channel only go to the given towers, but others are not affected
         wt_client
-            .add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt)
+            .set_channel_towers(channel_id, Some(HashSet::from([paid_tower])))
             .unwrap();
-
         assert_eq!(
-            wt_client.towers.get(&tower_id),
-            Some(&TowerSummary::from(updated_tower_info.clone()))
+            wt_client.get_channel_towers(channel_id),
+            HashSet::from([paid_tower])
         );
         assert_eq!(
-            wt_client.load_tower_info(tower_id).unwrap(),
-            updated_tower_info
-        );
-
-        // If we try to update without increasing both the end_time and the slots, this will fail
-        let mut receipt_same_slots = RegistrationReceipt::new(
-            receipt.user_id(),
-            receipt.available_slots(),
-            receipt.subscription_start(),
-            receipt.subscription_expiry() + 1,
-        );
-        receipt_same_slots.sign(&tower_sk);
-        let mut receipt_same_expiry = RegistrationReceipt::new(
-            receipt.user_id(),
-            receipt.available_slots() + 1,
-            receipt.subscription_start(),
-            receipt.subscription_expiry(),
+            wt_client.get_channel_towers("another_channel"),
+            HashSet::from([paid_tower, free_tower])
         );
-        receipt_same_expiry.sign(&tower_sk);
 
-        assert!(matches!(
-            wt_client.add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt),
-            Err(SubscriptionError::Expiry)
-        ));
-        assert!(matches!(
-            wt_client.add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt_same_slots),
-            Err(SubscriptionError::Slots)
-        ));
-        assert!(matches!(
-            wt_client.add_update_tower(
-                tower_id,
-                &updated_tower_info.net_addr,
-                &receipt_same_expiry
-            ),
-            Err(SubscriptionError::Expiry)
-        ));
+        // Abandoning the only target tower does not make the appointments go to the rest
+        wt_client.remove_tower(paid_tower).unwrap();
+        assert!(wt_client.get_channel_towers(channel_id).is_empty());
 
-        // Decrease the slots count (simulate exhaustion) and update with more than the current count it should work
-        let locator = generate_random_appointment(None).locator;
-        wt_client.add_appointment_receipt(
-            tower_id,
-            locator,
-            0,
-            &get_random_appointment_receipt(tower_sk),
+        // Until the restriction is lifted
+        wt_client.set_channel_towers(channel_id, None).unwrap();
+        assert_eq!(
+            wt_client.get_channel_towers(channel_id),
+            HashSet::from([free_tower])
         );
-        wt_client
-            .add_update_tower(tower_id, &updated_tower_info.net_addr, &receipt_same_slots)
-            .unwrap();
     }
 
     #[tokio::test]
-    async fn test_get_tower_status() {
+    async fn test_appointments_for_outpoint() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let mut wt_client =
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
 
-        // If the tower is unknown, get_tower_status returns None
-        let tower_id = get_random_user_id();
-        assert!(wt_client.get_tower_status(&tower_id).is_none());
-
-        // Add a tower
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
         let receipt = get_random_registration_receipt();
         wt_client
             .add_update_tower(tower_id, "talaia.watch", &receipt)
             .unwrap();
 
-        // If the tower is known, get_tower_status matches getting the same data from the towers collection
-        assert_eq!(
-            wt_client.towers.get(&tower_id).unwrap().status,
-            wt_client.get_tower_status(&tower_id).unwrap()
+        let outpoint = OutPoint::from_str(
+            "7f2c6a8b3e1d4f5a9b0c8d7e6f5a4b3c2d1e0f9a8b7c6d5e4f3a2b1c0d9e8f70:1",
         )
+        .unwrap();
+        let channel_id = channel_id_from_outpoint(&outpoint);
+        assert!(wt_client.appointments_for_outpoint(&outpoint).is_empty());
+
+        // One appointment of the channel is accepted by the tower and the other one is pending
+        let accepted = generate_random_appointment(None);
+        let pending = generate_random_appointment(None);
+        wt_client.add_appointment_channel(accepted.locator, &channel_id, 0);
+        wt_client.add_appointment_channel(pending.locator, &channel_id, 1);
+        wt_client.add_appointment_receipt(
+            tower_id,
+            accepted.locator,
+            receipt.available_slots(),
+            &get_random_appointment_receipt(tower_sk),
+        );
+        wt_client.add_pending_appointment(tower_id, &pending);
+
+        // Appointments of other channels (including the one funded by another output of the same transaction) are excluded
+        let other = generate_random_appointment(None);
+        let other_outpoint = OutPoint::new(outpoint.txid, 0);
+        wt_client.add_appointment_channel(
+            other.locator,
+            &channel_id_from_outpoint(&other_outpoint),
+            0,
+        );
+        wt_client.add_pending_appointment(tower_id, &other);
+
+        assert_eq!(
+            wt_client.appointments_for_outpoint(&outpoint),
+            vec![
+                ChannelAppointment {
+                    locator: accepted.locator,
+                    commit_num: 0,
+                    towers: HashMap::from([(tower_id, AppointmentStatus::Accepted)]),
+                },
+                ChannelAppointment {
+                    locator: pending.locator,
+                    commit_num: 1,
+                    towers: HashMap::from([(tower_id, AppointmentStatus::Pending)]),
+                },
+            ]
+        );
+
+        // Abandoned towers are not reported
+        wt_client.remove_tower(tower_id).unwrap();
+        assert!(wt_client
+            .appointments_for_outpoint(&outpoint)
+            .iter()
+            .all(|a| a.towers.is_empty()));
     }
 
     #[tokio::test]
-    async fn test_set_tower_status() {
+    async fn test_verify_all_receipts() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let mut wt_client =
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client.verify_all_receipts(), ReceiptsReport::default());
 
-        // If the tower is unknown nothing will happen
-        let unknown_tower = get_random_user_id();
-        wt_client.set_tower_status(unknown_tower, TowerStatus::Reachable);
-        assert!(!wt_client.towers.contains_key(&unknown_tower));
-
-        // If the tower is known, the status will be updated.
-        let receipt = get_random_registration_receipt();
-        let tower_id = get_random_user_id();
-        wt_client
-            .add_update_tower(tower_id, "talaia.watch", &receipt)
-            .unwrap();
+        // Add a couple of towers with properly signed receipts
+        let mut towers = Vec::new();
+        for _ in 0..2 {
+            let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+            let tower_id = TowerId(tower_pk);
+            let mut receipt = RegistrationReceipt::new(wt_client.user_id, 21, 42, 420);
+            receipt.sign(&tower_sk);
+            wt_client
+                .add_update_tower(tower_id, "talaia.watch", &receipt)
+                .unwrap();
 
-        for status in [
-            TowerStatus::Reachable,
-            TowerStatus::TemporaryUnreachable,
-            TowerStatus::Unreachable,
-            TowerStatus::SubscriptionError,
-            TowerStatus::Misbehaving,
-        ] {
-            wt_client.set_tower_status(tower_id, status);
-            assert_eq!(status, wt_client.get_tower_status(&tower_id).unwrap());
+            for _ in 0..5 {
+                let locator = generate_random_appointment(None).locator;
+                wt_client.add_appointment_receipt(
+                    tower_id,
+                    locator,
+                    20,
+                    &get_random_appointment_receipt(tower_sk),
+                );
+            }
+            towers.push((tower_id, tower_sk));
         }
+        let report = wt_client.verify_all_receipts();
+        assert_eq!(report.checked, 12);
+        assert!(report.invalid.is_empty());
+
+        // Corrupt one of the receipts, so its signature does not match its content anymore
+        let (tower_id, tower_sk) = towers[0];
+        let locator = generate_random_appointment(None).locator;
+        let receipt = get_random_appointment_receipt(tower_sk);
+        let corrupted = AppointmentReceipt::with_signature(
+            receipt.user_signature().to_owned(),
+            receipt.start_block() + 1,
+            receipt.signature().unwrap(),
+        );
+        wt_client.add_appointment_receipt(tower_id, locator, 19, &corrupted);
+
+        let report = wt_client.verify_all_receipts();
+        assert_eq!(report.checked, 13);
+        assert_eq!(
+            report.invalid,
+            HashMap::from([(
+                tower_id,
+                InvalidReceipts {
+                    registration_receipts: Vec::new(),
+                    appointment_receipts: HashSet::from([locator]),
+                }
+            )])
+        );
     }
 
     #[tokio::test]
-    async fn test_add_appointment_receipt() {
+    async fn test_add_pending_appointment_mirror() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), tx).await;
+
+        let tower_id = get_random_user_id();
+        let mirror_id = get_random_user_id();
+        for id in [tower_id, mirror_id] {
+            wt_client
+                .add_update_tower(id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
+
+        // Unknown towers cannot be set as mirror
+        assert!(matches!(
+            wt_client.set_mirror(Some(get_random_user_id())),
+            Err(DBError::NotFound)
+        ));
+        wt_client.set_mirror(Some(mirror_id)).unwrap();
+
+        // Pending appointments of any tower are also enqueued for the mirror
+        let appointment = generate_random_appointment(None);
+        wt_client.add_pending_appointment(tower_id, &appointment);
+        for id in [tower_id, mirror_id] {
+            assert!(wt_client.towers[&id]
+                .pending_appointments
+                .contains(&appointment.locator));
+            assert!(wt_client
+                .dbm
+                .load_appointment_locators(id, AppointmentStatus::Pending)
+                .unwrap()
+                .contains(&appointment.locator));
+        }
+        assert!(
+            rx.recv().await.unwrap() == (mirror_id, RevocationData::Fresh(appointment.locator))
+        );
+
+        // But not the other way around
+        let appointment = generate_random_appointment(None);
+        wt_client.add_pending_appointment(mirror_id, &appointment);
+        assert!(!wt_client.towers[&tower_id]
+            .pending_appointments
+            .contains(&appointment.locator));
+        assert!(rx.try_recv().is_err());
+
+        // Nor if the mirror already holds the appointment
+        let appointment = generate_random_appointment(None);
+        wt_client.add_appointment_receipt(
+            mirror_id,
+            appointment.locator,
+            42,
+            &get_random_appointment_receipt(cryptography::get_random_keypair().0),
+        );
+        wt_client.add_pending_appointment(tower_id, &appointment);
+        assert!(!wt_client.towers[&mirror_id]
+            .pending_appointments
+            .contains(&appointment.locator));
+        assert!(rx.try_recv().is_err());
+
+        // The mirror is persisted across restarts
+        drop(wt_client);
         let mut wt_client =
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client.mirror, Some(mirror_id));
 
-        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
-        let tower_id = TowerId(tower_pk);
+        // And dropped alongside the tower
+        wt_client.remove_tower(mirror_id).unwrap();
+        assert!(wt_client.mirror.is_none());
+        let appointment = generate_random_appointment(None);
+        wt_client.add_pending_appointment(tower_id, &appointment);
+        assert!(wt_client.towers[&tower_id]
+            .pending_appointments
+            .contains(&appointment.locator));
+    }
 
-        let locator = generate_random_appointment(None).locator;
-        let registration_receipt = get_random_registration_receipt();
-        let appointment_receipt = get_random_appointment_receipt(tower_sk);
+    #[tokio::test]
+    async fn test_tower_order() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), tx).await;
 
-        // If we call this on an unknown tower it will simply do nothing
-        wt_client.add_appointment_receipt(
-            tower_id,
-            locator,
-            registration_receipt.available_slots(),
-            &appointment_receipt,
+        let ordered = (0..3).map(|_| get_random_user_id()).collect::<Vec<_>>();
+        let unordered = get_random_user_id();
+        for id in ordered.iter().chain([&unordered]) {
+            wt_client
+                .add_update_tower(*id, "talaia.watch", &get_random_registration_receipt())
+                .unwrap();
+        }
+
+        // Unknown towers cannot be ordered
+        assert!(matches!(
+            wt_client.set_tower_order(vec![ordered[0], get_random_user_id()]),
+            Err(DBError::NotFound)
+        ));
+        assert!(wt_client.tower_order.is_empty());
+        wt_client.set_tower_order(ordered.clone()).unwrap();
+
+        // Only the first ordered tower that has not failed is targeted, alongside the unordered ones
+        let all = ordered
+            .iter()
+            .chain([&unordered])
+            .cloned()
+            .collect::<HashSet<_>>();
+        let mut targets = all.clone();
+        wt_client.apply_tower_order(&mut targets);
+        assert_eq!(targets, HashSet::from([ordered[0], unordered]));
+
+        wt_client.set_tower_status(ordered[0], TowerStatus::Unreachable);
+        let mut targets = all.clone();
+        wt_client.apply_tower_order(&mut targets);
+        assert_eq!(targets, HashSet::from([ordered[1], unordered]));
+
+        // If all of them have failed, the last one is kept
+        wt_client.set_tower_status(ordered[1], TowerStatus::Misbehaving);
+        wt_client.set_tower_status(ordered[2], TowerStatus::Unreachable);
+        let mut targets = all.clone();
+        wt_client.apply_tower_order(&mut targets);
+        assert_eq!(targets, HashSet::from([ordered[2], unordered]));
+
+        // Appointments fall back to the next tower once a tower fails for good
+        for id in ordered.iter() {
+            wt_client.set_tower_status(*id, TowerStatus::Reachable);
+        }
+        let appointment = generate_random_appointment(None);
+        wt_client.add_pending_appointment_with_deadline(ordered[0], &appointment, 42);
+        wt_client.fall_back_appointment(ordered[0], &appointment);
+        assert!(wt_client.towers[&ordered[1]]
+            .pending_appointments
+            .contains(&appointment.locator));
+        assert_eq!(
+            wt_client
+                .dbm
+                .load_pending_deadline(ordered[1], appointment.locator),
+            Some(42)
+        );
+        assert!(
+            rx.recv().await.unwrap() == (ordered[1], RevocationData::Fresh(appointment.locator))
         );
-        assert!(!wt_client.towers.contains_key(&tower_id));
 
-        // Add the tower to the state and try again
-        let tower_info = TowerInfo::new(
-            "talaia.watch".to_owned(),
-            registration_receipt.available_slots(),
-            registration_receipt.subscription_start(),
-            registration_receipt.subscription_expiry(),
-            HashMap::from([(locator, appointment_receipt.signature().unwrap())]),
-            Vec::new(),
-            Vec::new(),
+        // Failed towers are skipped
+        wt_client.set_tower_status(ordered[1], TowerStatus::Misbehaving);
+        let appointment = generate_random_appointment(None);
+        wt_client.add_invalid_appointment(ordered[0], &appointment);
+        assert!(!wt_client.towers[&ordered[1]]
+            .pending_appointments
+            .contains(&appointment.locator));
+        assert!(wt_client.towers[&ordered[2]]
+            .pending_appointments
+            .contains(&appointment.locator));
+        assert!(
+            rx.recv().await.unwrap() == (ordered[2], RevocationData::Fresh(appointment.locator))
         );
+
+        // There is nothing to fall back to after the last tower, nor from an unordered one
+        wt_client.add_invalid_appointment(ordered[2], &generate_random_appointment(None));
+        wt_client.add_invalid_appointment(unordered, &generate_random_appointment(None));
+        assert!(rx.try_recv().is_err());
+
+        // The order is persisted across restarts, and towers are dropped from it alongside their records
+        drop(wt_client);
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client.tower_order, ordered);
+        wt_client.remove_tower(ordered[1]).unwrap();
+        assert_eq!(wt_client.tower_order, vec![ordered[0], ordered[2]]);
+        drop(wt_client);
+        let wt_client = WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client.tower_order, vec![ordered[0], ordered[2]]);
+    }
+
+    #[tokio::test]
+    async fn test_set_tower_pin() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        let tower_id = get_random_user_id();
         wt_client
-            .add_update_tower(tower_id, &tower_info.net_addr, &registration_receipt)
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
             .unwrap();
-        wt_client.add_appointment_receipt(
-            tower_id,
-            locator,
-            registration_receipt.available_slots(),
-            &appointment_receipt,
-        );
+        let tls_pin = TlsPin::from_certificate(b"certificate");
 
-        assert!(wt_client.towers.contains_key(&tower_id));
+        // Unknown towers cannot be pinned
+        assert!(wt_client
+            .set_tower_pin(get_random_user_id(), Some(tls_pin))
+            .is_err());
+
+        // Pinned towers are reached using their own client
+        wt_client.set_tower_pin(tower_id, Some(tls_pin)).unwrap();
+        assert_eq!(wt_client.towers[&tower_id].tls_pin, Some(tls_pin));
         assert_eq!(
-            wt_client.towers.get(&tower_id).unwrap(),
-            &TowerSummary::from(tower_info.clone())
+            wt_client.load_tower_info(tower_id).unwrap().tls_pin,
+            Some(tls_pin)
         );
-        assert_eq!(wt_client.load_tower_info(tower_id).unwrap(), tower_info);
+        assert!(!Arc::ptr_eq(
+            &wt_client.get_request_options(tower_id).client,
+            &wt_client.client
+        ));
+
+        // Pins survive restarts
+        let wt_client_restarted =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+        assert_eq!(wt_client_restarted.towers[&tower_id].tls_pin, Some(tls_pin));
+        assert!(wt_client_restarted.pinned_clients.contains_key(&tower_id));
+
+        // And the shared client is used again once the pin is removed
+        wt_client.set_tower_pin(tower_id, None).unwrap();
+        assert!(wt_client.towers[&tower_id].tls_pin.is_none());
+        assert!(Arc::ptr_eq(
+            &wt_client.get_request_options(tower_id).client,
+            &wt_client.client
+        ));
     }
 
     #[tokio::test]
-    async fn test_add_pending_appointment() {
+    async fn test_set_tower_labels() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let mut wt_client =
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
 
-        let tower_id = get_random_user_id();
+        let paid_tower = get_random_user_id();
+        let backup_tower = get_random_user_id();
+        let untagged_tower = get_random_user_id();
+        let receipt = get_random_registration_receipt();
+        for tower_id in [paid_tower, backup_tower, untagged_tower] {
+            wt_client
+                .add_update_tower(tower_id, "talaia.watch", &receipt)
+                .unwrap();
+        }
+        let labels = |l: &[&str]| l.iter().map(|x| x.to_string()).collect::<BTreeSet<_>>();
 
-        let registration_receipt = get_random_registration_receipt();
-        let appointment = generate_random_appointment(None);
+        // Unknown towers cannot be tagged
+        assert!(matches!(
+            wt_client.set_tower_labels(get_random_user_id(), labels(&["paid"])),
+            Err(DBError::NotFound)
+        ));
 
-        // If we call this on an unknown tower it will simply do nothing
-        wt_client.add_pending_appointment(tower_id, &appointment);
-        assert!(!wt_client.towers.contains_key(&tower_id));
+        wt_client
+            .set_tower_labels(paid_tower, labels(&["paid", "tor"]))
+            .unwrap();
+        wt_client
+            .set_tower_labels(backup_tower, labels(&["backup", "tor"]))
+            .unwrap();
+        assert_eq!(
+            wt_client.get_towers_by_label("paid"),
+            HashSet::from([paid_tower])
+        );
+        assert_eq!(
+            wt_client.get_towers_by_label("tor"),
+            HashSet::from([paid_tower, backup_tower])
+        );
+        assert!(wt_client.get_towers_by_label("free").is_empty());
 
-        // Add the tower to the state and try again
-        let tower_info = TowerInfo::new(
-            "talaia.watch".to_owned(),
-            registration_receipt.available_slots(),
-            registration_receipt.subscription_start(),
-            registration_receipt.subscription_expiry(),
-            HashMap::new(),
-            vec![appointment.clone()],
-            Vec::new(),
+        // Updating the tower does not drop the labels
+        wt_client
+            .add_update_tower(
+                paid_tower,
+                "talaia.watch",
+                &get_registration_receipt_from_previous(&receipt),
+            )
+            .unwrap();
+        assert_eq!(
+            wt_client.towers[&paid_tower].labels,
+            labels(&["paid", "tor"])
         );
 
+        // Setting new labels replaces the old ones
         wt_client
-            .add_update_tower(tower_id, &tower_info.net_addr, &registration_receipt)
+            .set_tower_labels(paid_tower, labels(&["backup"]))
             .unwrap();
-        wt_client.add_pending_appointment(tower_id, &appointment);
+        assert_eq!(
+            wt_client.get_towers_by_label("backup"),
+            HashSet::from([paid_tower, backup_tower])
+        );
+        assert_eq!(
+            wt_client.get_towers_by_label("tor"),
+            HashSet::from([backup_tower])
+        );
 
-        assert!(wt_client.towers.contains_key(&tower_id));
+        // Labels are persisted
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
         assert_eq!(
-            wt_client.towers.get(&tower_id).unwrap(),
-            &TowerSummary::from(tower_info.clone())
+            wt_client.get_towers_by_label("backup"),
+            HashSet::from([paid_tower, backup_tower])
         );
-        // When towers data is loaded from the database, it is assumed to be reachable.
         assert_eq!(
-            wt_client.load_tower_info(tower_id).unwrap(),
-            tower_info.with_status(TowerStatus::TemporaryUnreachable)
+            wt_client.load_tower_info(backup_tower).unwrap().labels,
+            labels(&["backup", "tor"])
         );
+
+        // And can be removed
+        wt_client
+            .set_tower_labels(backup_tower, BTreeSet::new())
+            .unwrap();
+        assert_eq!(
+            wt_client.get_towers_by_label("backup"),
+            HashSet::from([paid_tower])
+        );
+        assert!(wt_client.towers[&untagged_tower].labels.is_empty());
     }
 
     #[tokio::test]
-    async fn test_remove_pending_appointment() {
+    async fn test_diff_against() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
         let mut wt_client =
             WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
 
         let tower_id = get_random_user_id();
-
-        let registration_receipt = get_random_registration_receipt();
-        let appointment = generate_random_appointment(None);
-
-        // If we call this on an unknown tower it will simply do nothing
-        wt_client.remove_pending_appointment(tower_id, appointment.locator);
-
-        // Add the tower to the state and try again
+        let receipt = get_random_registration_receipt();
         wt_client
-            .add_update_tower(tower_id, "talaia.watch", &registration_receipt)
+            .add_update_tower(tower_id, "talaia.watch", &receipt)
             .unwrap();
+        let appointment = generate_random_appointment(None);
         wt_client.add_pending_appointment(tower_id, &appointment);
 
-        wt_client.remove_pending_appointment(tower_id, appointment.locator);
-        assert!(!wt_client
+        // An instance agrees with its own export
+        let exported = wt_client.export_state();
+        assert!(wt_client.diff_against(&exported).is_empty());
+
+        // Make the remote state diverge: the pending appointment was delivered and there is an extra tower
+        let mut remote = serde_json::from_value::<ExportedState>(json!(exported)).unwrap();
+        remote
             .towers
-            .get(&tower_id)
+            .get_mut(&tower_id)
             .unwrap()
             .pending_appointments
-            .contains(&appointment.locator));
-        // This bit is tested exhaustively in the DBM.
-        assert!(!wt_client.dbm.appointment_exists(appointment.locator));
+            .clear();
+        let remote_tower_id = get_random_user_id();
+        remote
+            .towers
+            .insert(remote_tower_id, remote.towers[&tower_id].clone());
+
+        let diff = wt_client.diff_against(&remote);
+        assert!(diff.local_only.is_empty());
+        assert_eq!(diff.remote_only, vec![remote_tower_id]);
+        let tower_diff = &diff.differing[&tower_id];
+        assert_eq!(
+            tower_diff.local_pending,
+            HashSet::from([appointment.locator])
+        );
+        assert!(tower_diff.remote_pending.is_empty());
+        assert!(tower_diff.status.is_none());
     }
 
     #[tokio::test]
@@ -626,6 +4212,60 @@ mod tests {
         assert_eq!(wt_client.load_tower_info(tower_id).unwrap(), tower_info);
     }
 
+    #[tokio::test]
+    async fn test_add_rejected_appointment() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        let tower_id = get_random_user_id();
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        let error = ApiError {
+            error: "error_msg".to_owned(),
+            error_code: 1,
+        };
+
+        // Appointments that are not going to be retried end up in the dead-letter queue with the rejection context
+        let appointment = generate_random_appointment(None);
+        assert!(!wt_client.add_rejected_appointment(tower_id, &appointment, &error));
+        let dead_letters = wt_client.dead_letters(tower_id);
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].tower_id, tower_id);
+        assert_eq!(dead_letters[0].appointment, appointment);
+        assert_eq!(dead_letters[0].error_code, error.error_code);
+        assert_eq!(dead_letters[0].error, error.error);
+        assert!(wt_client
+            .towers
+            .get(&tower_id)
+            .unwrap()
+            .invalid_appointments
+            .contains(&appointment.locator));
+
+        // The ones that are going to be retried do not
+        wt_client.invalid_retry = Some(InvalidRetryPolicy {
+            cooldown: 0,
+            max_retries: 1,
+        });
+        assert!(wt_client.add_rejected_appointment(
+            tower_id,
+            &generate_random_appointment(None),
+            &error
+        ));
+        assert_eq!(wt_client.dead_letters(tower_id).len(), 1);
+
+        // Nothing is recorded if the dead-letter queue is disabled
+        wt_client.invalid_retry = None;
+        wt_client.dead_letter_limit = 0;
+        assert!(!wt_client.add_rejected_appointment(
+            tower_id,
+            &generate_random_appointment(None),
+            &error
+        ));
+        assert_eq!(wt_client.dead_letters(tower_id).len(), 1);
+    }
+
     #[tokio::test]
     async fn test_move_pending_appointment_to_invalid() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
@@ -661,10 +4301,12 @@ mod tests {
         assert!(!wt_client
             .dbm
             .load_appointment_locators(tower_id, crate::AppointmentStatus::Pending)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(wt_client
             .dbm
             .load_appointment_locators(tower_id, crate::AppointmentStatus::Invalid)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(wt_client.dbm.appointment_exists(appointment.locator));
     }
@@ -712,10 +4354,12 @@ mod tests {
         assert!(!wt_client
             .dbm
             .load_appointment_locators(tower_id, crate::AppointmentStatus::Pending)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(wt_client
             .dbm
             .load_appointment_locators(tower_id, crate::AppointmentStatus::Invalid)
+            .unwrap()
             .contains(&appointment.locator));
 
         // ANOTHER_TOWER_ID CHECKS
@@ -734,10 +4378,12 @@ mod tests {
         assert!(wt_client
             .dbm
             .load_appointment_locators(another_tower_id, crate::AppointmentStatus::Pending)
+            .unwrap()
             .contains(&appointment.locator));
         assert!(!wt_client
             .dbm
             .load_appointment_locators(another_tower_id, crate::AppointmentStatus::Invalid)
+            .unwrap()
             .contains(&appointment.locator));
 
         // GENERAL
@@ -835,6 +4481,64 @@ mod tests {
         assert!(!wt_client.dbm.appointment_receipt_exists(locator, tower_id));
     }
 
+    #[tokio::test]
+    async fn test_remove_failed_towers() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let mut wt_client =
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await;
+
+        let receipt = get_random_registration_receipt();
+        let mut tower_ids = Vec::new();
+        for _ in 0..4 {
+            let tower_id = get_random_user_id();
+            wt_client
+                .add_update_tower(tower_id, "talaia.watch", &receipt)
+                .unwrap();
+            tower_ids.push(tower_id);
+        }
+        let (failed, old_unreachable, new_unreachable, reachable) =
+            (tower_ids[0], tower_ids[1], tower_ids[2], tower_ids[3]);
+
+        // Towers remember since when they are unreachable until they are reached again
+        wt_client.set_tower_status(old_unreachable, TowerStatus::Unreachable);
+        let since = wt_client.unreachable_since[&old_unreachable];
+        wt_client.set_tower_status(old_unreachable, TowerStatus::TemporaryUnreachable);
+        wt_client.set_tower_status(old_unreachable, TowerStatus::Unreachable);
+        assert_eq!(wt_client.unreachable_since[&old_unreachable], since);
+        wt_client.set_tower_status(old_unreachable, TowerStatus::Reachable);
+        assert!(!wt_client.unreachable_since.contains_key(&old_unreachable));
+
+        wt_client.set_tower_status(old_unreachable, TowerStatus::Unreachable);
+        wt_client.set_tower_status(new_unreachable, TowerStatus::Unreachable);
+        wt_client
+            .unreachable_since
+            .insert(old_unreachable, retrier::now() - 100);
+        wt_client.retriers.insert(failed, RetrierStatus::Failed);
+
+        let removed = wt_client.remove_failed_towers(50).unwrap();
+        assert_eq!(
+            HashSet::<TowerId>::from_iter(removed),
+            HashSet::from_iter([failed, old_unreachable])
+        );
+
+        for tower_id in [failed, old_unreachable] {
+            assert!(!wt_client.towers.contains_key(&tower_id));
+            assert!(!wt_client.unreachable_since.contains_key(&tower_id));
+            assert!(wt_client.load_tower_info(tower_id).is_none());
+        }
+        for tower_id in [new_unreachable, reachable] {
+            assert!(wt_client.towers.contains_key(&tower_id));
+            assert!(wt_client.load_tower_info(tower_id).is_some());
+        }
+
+        // Nothing else is removed until the tower has been unreachable for long enough
+        assert!(wt_client.remove_failed_towers(50).unwrap().is_empty());
+        assert_eq!(
+            wt_client.remove_failed_towers(0).unwrap(),
+            vec![new_unreachable]
+        );
+    }
+
     #[tokio::test]
     async fn test_remove_tower_shared_appointment() {
         // Lets test removing a tower that has associated data shared with another tower.
@@ -887,6 +4591,384 @@ mod tests {
         assert!(wt_client.dbm.appointment_receipt_exists(locator, tower2_id));
     }
 
+    #[tokio::test]
+    async fn test_resync_tower() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), tx).await;
+
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+
+        // Resyncing an unknown tower fails
+        assert!(matches!(
+            wt_client.resync_tower(tower_id, HashSet::new()),
+            Err(DBError::NotFound)
+        ));
+
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+
+        // Add two accepted and two pending appointments
+        let accepted: Vec<Locator> = (0..2)
+            .map(|_| {
+                let locator = generate_random_appointment(None).locator;
+                wt_client.add_appointment_receipt(
+                    tower_id,
+                    locator,
+                    21,
+                    &get_random_appointment_receipt(tower_sk),
+                );
+                locator
+            })
+            .collect();
+        let pending: Vec<Locator> = (0..2)
+            .map(|_| {
+                let appointment = generate_random_appointment(None);
+                wt_client.add_pending_appointment(tower_id, &appointment);
+                appointment.locator
+            })
+            .collect();
+
+        // The tower is holding one of each, plus one we know nothing about
+        let unknown = generate_random_appointment(None).locator;
+        let report = wt_client
+            .resync_tower(tower_id, HashSet::from([accepted[0], pending[0], unknown]))
+            .unwrap();
+
+        assert_eq!(
+            report,
+            ResyncReport {
+                resent: HashSet::from([pending[1]]),
+                missing: HashSet::from([accepted[1]]),
+                unknown: HashSet::from([unknown]),
+            }
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            (tower_id, RevocationData::Stale(HashSet::from([pending[1]])))
+        );
+
+        // If the tower is holding less than we expected, all of our data is reported and nothing is unknown
+        let report = wt_client.resync_tower(tower_id, HashSet::new()).unwrap();
+        assert_eq!(report.resent, HashSet::from_iter(pending.clone()));
+        assert_eq!(report.missing, HashSet::from_iter(accepted.clone()));
+        assert!(report.unknown.is_empty());
+        rx.recv().await.unwrap();
+
+        // If the tower matches our view, nothing is reported nor re-sent
+        let report = wt_client
+            .resync_tower(
+                tower_id,
+                HashSet::from_iter(accepted.into_iter().chain(pending)),
+            )
+            .unwrap();
+        assert_eq!(report, ResyncReport::default());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_tower() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let (tx, mut rx) = unbounded_channel();
+        let mut wt_client = WTClient::new(tmp_path.path().to_path_buf(), tx).await;
+
+        // Backfilling an unknown tower fails
+        let new_tower_id = get_random_user_id();
+        assert!(matches!(
+            wt_client.backfill_tower(new_tower_id),
+            Err(DBError::NotFound)
+        ));
+
+        // The existing tower has an accepted appointment and some pending ones, one of them past its deadline
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        wt_client
+            .add_update_tower(tower_id, "talaia.watch", &get_random_registration_receipt())
+            .unwrap();
+        wt_client.add_appointment_receipt(
+            tower_id,
+            generate_random_appointment(None).locator,
+            21,
+            &get_random_appointment_receipt(tower_sk),
+        );
+        let pending = generate_random_appointment(None);
+        wt_client.add_pending_appointment(tower_id, &pending);
+        let with_deadline = generate_random_appointment(None);
+        let deadline = retrier::now() + 3600;
+        wt_client.add_pending_appointment_with_deadline(tower_id, &with_deadline, deadline);
+        wt_client.add_pending_appointment_with_deadline(
+            tower_id,
+            &generate_random_appointment(None),
+            1,
+        );
+
+        // The new tower gets the appointments still being delivered (accepted ones are not kept, so cannot be replayed)
+        wt_client
+            .add_update_tower(
+                new_tower_id,
+                "new.tower",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+        let expected = HashSet::from([pending.locator, with_deadline.locator]);
+        assert_eq!(wt_client.backfill_tower(new_tower_id).unwrap(), expected);
+        assert_eq!(
+            wt_client.towers[&new_tower_id].pending_appointments,
+            expected
+        );
+        assert_eq!(
+            wt_client
+                .dbm
+                .load_pending_deadline(new_tower_id, with_deadline.locator),
+            Some(deadline)
+        );
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            (new_tower_id, RevocationData::Stale(expected.clone()))
+        );
+
+        // Backfilling again does nothing, since the tower already has them all
+        assert!(wt_client.backfill_tower(new_tower_id).unwrap().is_empty());
+        assert!(rx.try_recv().is_err());
+
+        // Unreachable towers get the appointments, but are not retried
+        let unreachable_tower_id = get_random_user_id();
+        wt_client
+            .add_update_tower(
+                unreachable_tower_id,
+                "unreachable.tower",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+        wt_client.set_tower_status(unreachable_tower_id, TowerStatus::Unreachable);
+        assert_eq!(
+            wt_client.backfill_tower(unreachable_tower_id).unwrap(),
+            expected
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_tower_list() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0).await,
+        ));
+        let user_id = wt_client.lock().unwrap().user_id;
+        let (maintainer_sk, maintainer_pk) = cryptography::get_random_keypair();
+
+        // A reachable tower
+        let mut server = mockito::Server::new_async().await;
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let reachable_tower = TowerId(tower_pk);
+        let mut receipt = RegistrationReceipt::new(user_id, 100, 42, 420);
+        receipt.sign(&tower_sk);
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(receipt).to_string())
+            .create_async()
+            .await;
+
+        // An unreachable one
+        let unreachable_tower = get_random_user_id();
+
+        // And one we are already registered with
+        let known_tower = get_random_user_id();
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(
+                known_tower,
+                "talaia.watch",
+                &get_random_registration_receipt(),
+            )
+            .unwrap();
+
+        let list = get_signed_list(
+            vec![
+                TowerListEntry {
+                    tower_id: unreachable_tower,
+                    net_addr: "http://127.0.0.1:1".to_owned(),
+                },
+                TowerListEntry {
+                    tower_id: reachable_tower,
+                    net_addr: server.url(),
+                },
+                TowerListEntry {
+                    tower_id: known_tower,
+                    net_addr: "talaia.watch".to_owned(),
+                },
+            ],
+            &maintainer_sk,
+        );
+
+        // Lists not signed by the maintainer are rejected altogether
+        assert!(matches!(
+            WTClient::import_tower_list(&wt_client, &list, &cryptography::get_random_keypair().1)
+                .await,
+            Err(TowerListError::InvalidSignature)
+        ));
+        assert!(!wt_client
+            .lock()
+            .unwrap()
+            .towers
+            .contains_key(&reachable_tower));
+
+        // The unreachable tower does not prevent registering with the rest
+        let report = WTClient::import_tower_list(&wt_client, &list, &maintainer_pk)
+            .await
+            .unwrap();
+        api_mock.assert_async().await;
+        assert_eq!(report.registered, vec![reachable_tower]);
+        assert_eq!(report.skipped, vec![known_tower]);
+        assert_eq!(
+            report.failed.keys().collect::<Vec<_>>(),
+            vec![&unreachable_tower]
+        );
+
+        let state = wt_client.lock().unwrap();
+        assert!(state.towers.contains_key(&reachable_tower));
+        assert!(!state.towers.contains_key(&unreachable_tower));
+        assert_eq!(
+            state.get_registration_receipt(reachable_tower),
+            Some(receipt)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auto_renew() {
+        let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
+        let wt_client = Arc::new(Mutex::new(
+            WTClient::new(tmp_path.path().to_path_buf(), unbounded_channel().0)
+                .await
+                .with_auto_renew_blocks(144),
+        ));
+        let user_id = wt_client.lock().unwrap().user_id;
+
+        // Unknown towers cannot be flagged for auto-renewal
+        assert!(wt_client
+            .lock()
+            .unwrap()
+            .set_tower_auto_renew(get_random_user_id(), true)
+            .is_err());
+
+        // Register with a tower whose subscription expires at height 1000
+        let mut server = mockito::Server::new_async().await;
+        let (tower_sk, tower_pk) = cryptography::get_random_keypair();
+        let tower_id = TowerId(tower_pk);
+        let mut receipt = RegistrationReceipt::new(user_id, 100, 0, 1000);
+        receipt.sign(&tower_sk);
+        wt_client
+            .lock()
+            .unwrap()
+            .add_update_tower(tower_id, &server.url(), &receipt)
+            .unwrap();
+
+        {
+            let mut state = wt_client.lock().unwrap();
+            // Nothing is renewed while the height is unknown, or the subscription is not about to expire
+            assert!(state.towers_to_renew().is_empty());
+            state.set_block_height(800);
+            assert!(state.towers_to_renew().is_empty());
+
+            // Nor if the tower is not flagged for auto-renewal
+            state.set_block_height(900);
+            assert!(state.towers_to_renew().is_empty());
+            state.set_tower_auto_renew(tower_id, true).unwrap();
+            assert!(state.towers[&tower_id].auto_renew);
+            assert!(state.load_tower_info(tower_id).unwrap().auto_renew);
+            assert_eq!(state.towers_to_renew(), vec![tower_id]);
+
+            // Towers are not tried again straightaway
+            assert!(state.towers_to_renew().is_empty());
+        }
+
+        let mut renewed_receipt = RegistrationReceipt::new(user_id, 200, 900, 2000);
+        renewed_receipt.sign(&tower_sk);
+        let api_mock = server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(json!(renewed_receipt).to_string())
+            .create_async()
+            .await;
+        WTClient::renew_subscription(&wt_client, tower_id)
+            .await
+            .unwrap();
+        api_mock.assert_async().await;
+
+        {
+            let mut state = wt_client.lock().unwrap();
+            assert_eq!(state.towers[&tower_id].subscription_expiry, 2000);
+            assert_eq!(
+                state.get_registration_receipt(tower_id),
+                Some(renewed_receipt)
+            );
+            assert!(state.towers_to_renew().is_empty());
+        }
+
+        // Towers requiring a payment are flagged for the user to renew them instead
+        let mut paid_server = mockito::Server::new_async().await;
+        let (paid_tower_sk, paid_tower_pk) = cryptography::get_random_keypair();
+        let paid_tower_id = TowerId(paid_tower_pk);
+        let mut receipt = RegistrationReceipt::new(user_id, 100, 0, 1000);
+        receipt.sign(&paid_tower_sk);
+        {
+            let mut state = wt_client.lock().unwrap();
+            state
+                .add_update_tower(paid_tower_id, &paid_server.url(), &receipt)
+                .unwrap();
+            state.set_tower_auto_renew(paid_tower_id, true).unwrap();
+            assert_eq!(state.towers_to_renew(), vec![paid_tower_id]);
+        }
+
+        let api_mock = paid_server
+            .mock("POST", Endpoint::Register.path().as_str())
+            .with_status(402)
+            .with_header("content-type", "application/json")
+            .with_body(
+                json!({
+                    "error": "Pay lnbcrt1invoice",
+                    "error_code": teos_common::errors::REGISTRATION_PAYMENT_REQUIRED
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        assert!(WTClient::renew_subscription(&wt_client, paid_tower_id)
+            .await
+            .is_err());
+        api_mock.assert_async().await;
+
+        let mut state = wt_client.lock().unwrap();
+        assert_eq!(state.towers[&paid_tower_id].subscription_expiry, 1000);
+        assert_eq!(
+            state.towers_needing_attention(None),
+            HashMap::from([(
+                paid_tower_id,
+                vec![
+                    AttentionReason::SubscriptionExpiring,
+                    AttentionReason::PaymentRequired
+                ]
+            )])
+        );
+        // Not even once the retry delay is over
+        state.renewal_attempts.clear();
+        assert!(state.towers_to_renew().is_empty());
+
+        // Registering manually clears the flag
+        let mut receipt = RegistrationReceipt::new(user_id, 200, 900, 2000);
+        receipt.sign(&paid_tower_sk);
+        state
+            .add_update_tower(paid_tower_id, &paid_server.url(), &receipt)
+            .unwrap();
+        assert!(state.towers_needing_attention(None).is_empty());
+    }
+
     #[tokio::test]
     async fn test_remove_inexistent_tower() {
         let tmp_path = TempDir::new(&format!("watchtower_{}", get_random_user_id())).unwrap();
@@ -241,3 +241,34 @@ def test_get_appointment(node_factory, bitcoind, teosd, directory):
 
     # Manually stop l2, otherwise the tower may be stopped before the tower client and we may get some BROKEN logs.
     l2.stop()
+
+
+def test_trigger_penalty(node_factory, bitcoind, teosd):
+    l1, l2 = node_factory.line_graph(2, opts=[{}, {"plugin": WT_PLUGIN}])
+
+    # We need to register l2 with the tower
+    tower_id = teosd.cli.gettowerinfo()["tower_id"]
+    l2.rpc.registertower(tower_id)
+    user_id = teosd.cli.getusers()["user_ids"][0]
+
+    # Force a new commitment
+    l1.rpc.pay(l2.rpc.invoice(25000000, "lbl1", "desc1")["bolt11"])
+    tx = l1.rpc.dev_sign_last_tx(l2.info["id"])["tx"]
+
+    # Now make sure it is out of date
+    l1.rpc.pay(l2.rpc.invoice(25000000, "lbl2", "desc2")["bolt11"])
+
+    # Now l1 cheats, but the dispute is not mined, so the tower would not react on its own yet
+    dispute_txid = bitcoind.rpc.sendrawtransaction(tx)
+    locator = change_endianness(dispute_txid[32:])
+    assert l2.rpc.getappointment(tower_id, locator)["status"] == "being_watched"
+
+    # Trigger the penalty manually. It is decrypted, broadcast and tracked as if the dispute had been seen on chain
+    tracker = teosd.cli.triggerpenalty(locator, user_id, tx)["tracker"]
+    assert tracker["dispute_txid"] == dispute_txid
+    assert tracker["penalty_txid"] in bitcoind.rpc.getrawmempool()
+    teosd.wait_for_log("New tracker added")
+    assert l2.rpc.getappointment(tower_id, locator)["status"] == "dispute_responded"
+
+    # Manually stop l2, otherwise the tower may be stopped before the tower client and we may get some BROKEN logs.
+    l2.stop()